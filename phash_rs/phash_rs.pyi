@@ -0,0 +1,832 @@
+# Type stubs for the phash_rs extension module.
+#
+# maturin ships this file (and a py.typed marker) with the module. Keep it
+# in step with src/python.rs: the stub tests in src/lib.rs fail when a
+# function, class or parameter is missing here. Full descriptions are in
+# the docstrings (help(phash_rs.find_duplicate_images)).
+
+from collections.abc import Callable, Iterable, Iterator, Mapping, Sequence
+from typing import Any, Literal, TypedDict, overload
+
+import os
+
+import numpy
+
+THRESHOLD_IDENTICAL: int
+THRESHOLD_VERY_SIMILAR: int
+THRESHOLD_SIMILAR: int
+THRESHOLD_SOMEWHAT_SIMILAR: int
+
+# Paths are accepted as str or os.PathLike (e.g. pathlib.Path). Names that
+# are not valid UTF-8 come back as str with surrogate escapes, as from
+# os.fsdecode, and round-trip unchanged.
+StrPath = str | os.PathLike[str]
+ProgressCallback = Callable[[int, int, str], object]
+Region = tuple[int, int, int, int]
+Color = tuple[int, int, int]
+
+class HashErrorInfo(TypedDict):
+    kind: Literal["io", "unsupported_format", "decode", "too_large", "invalid_input"]
+    message: str
+
+Errors = dict[str, HashErrorInfo]
+
+class Review(TypedDict):
+    state: str
+    note: str
+
+class FileInfo(TypedDict):
+    size: int
+    mtime: float | None
+    dimensions: tuple[int, int] | None
+    format: str | None
+
+class HashedFile(TypedDict):
+    hash: str
+    size: int | None
+    mtime: float | None
+    dimensions: tuple[int, int] | None
+    format: str | None
+
+class MappingEntry(TypedDict):
+    group_id: int
+    distance_to_best: int | None
+    is_best: bool
+
+class Comparison(TypedDict):
+    distance: int
+    agreement: list[bool]
+    frequency_bands: list[tuple[int, int]] | None
+    quadrants: dict[str, int]
+    heatmap: numpy.ndarray | None
+
+class BlocklistMatch(TypedDict):
+    id: str
+    hash: str
+    distance: int
+    transform: str
+
+class QualityScore(TypedDict):
+    score: float
+    sharpness: float
+    upscale: int
+    jpeg_quality: int | None
+    blockiness: float
+    dimensions: tuple[int, int]
+
+class RobustnessReport(TypedDict):
+    hash: str
+    distances: dict[str, int]
+    max_distance: int
+
+class BurstShot(TypedDict):
+    path: str
+    capture_time: str | None
+    sharpness: float
+    dimensions: tuple[int, int]
+
+class BurstCluster(TypedDict):
+    paths: list[str]
+    keeper: str
+    shots: list[BurstShot]
+
+class DocumentMatch(TypedDict):
+    photo: str
+    document: str
+    page: int
+    distance: int
+
+class TiledRegion(TypedDict):
+    region: Region
+    hash: str
+
+class RegionMatch(TypedDict):
+    region: Region
+    other: Region
+    distance: int
+    contains: bool
+
+class ScanMetadata(TypedDict):
+    algorithm: str | None
+    threshold: int | None
+    hash_size: int | None
+    root: str | None
+    created: int | None
+
+class ImportedResults(TypedDict):
+    metadata: ScanMetadata
+    groups: list[DuplicateGroup]
+
+class BenchReport(TypedDict):
+    corpus_size: int
+    threads: int
+    build_secs: float
+    inserts_per_sec: float
+    queries: int
+    query_secs: float
+    queries_per_sec: float
+    mean_matches: float
+    group_secs: float
+    group_secs_single: float
+    group_speedup: float
+    duplicate_groups: int
+    index_bytes: int
+    peak_rss_bytes: int | None
+
+class ActionRecord(TypedDict):
+    path: str
+    keep: str
+    action: str
+    target: str | None
+    sidecars: list[str]
+    bytes: int
+
+class DedupeReport(TypedDict):
+    scanned: int
+    groups: list[DuplicateGroup]
+    plan: list[ActionRecord]
+    unverified: dict[str, str]
+    applied: bool
+    apply_errors: dict[str, str]
+    manifest: str | None
+    bytes_recoverable: int
+    errors: Errors
+
+class ApplyReport(TypedDict):
+    dry_run: bool
+    actions: list[ActionRecord]
+    skipped: dict[str, str]
+    errors: dict[str, str]
+    bytes_freed: int
+    manifest: str | None
+
+class RestoreReport(TypedDict):
+    restored: list[str]
+    skipped: dict[str, str]
+    errors: dict[str, str]
+
+class FormatEstimate(TypedDict):
+    files: int
+    bytes: int
+    seconds: float
+
+class ScanEstimate(TypedDict):
+    files: int
+    total_bytes: int
+    cached_files: int
+    unsupported_files: int
+    unreadable_files: int
+    decode_bytes: int
+    seconds: float
+    peak_memory_bytes: int
+    formats: dict[str, FormatEstimate]
+
+class CalibrationPoint(TypedDict):
+    threshold: int
+    precision: float
+    recall: float
+    f1: float
+
+class Calibration(CalibrationPoint):
+    curve: list[CalibrationPoint]
+    duplicate_distances: list[int]
+    distinct_distances: list[int]
+    errors: Errors
+
+class DuplicateGroup:
+    """A group of duplicate images, as returned by the duplicate finders."""
+
+    paths: list[str]
+    hash: str
+    best: str
+    key: str
+    sidecars: dict[str, list[str]]
+    content_type: str | None
+    hashes: dict[str, str] | None
+    dimensions: dict[str, tuple[int, int]] | None
+    distances: dict[str, int] | None
+    max_distance: int | None
+    mean_distance: float | None
+    pairwise: list[list[int]] | None
+    metadata: dict[str, FileInfo] | None
+    review: dict[str, str] | None
+    new: list[str] | None
+    def __getitem__(self, key: str) -> Any: ...
+    def __contains__(self, key: str) -> bool: ...
+    def get(self, key: str, default: Any | None = None) -> Any: ...
+    def keys(self) -> list[str]: ...
+    def to_dict(self) -> dict[str, Any]: ...
+
+GroupLike = DuplicateGroup | Mapping[str, Any]
+
+class DuplicateStream(Iterator[DuplicateGroup]):
+    def __iter__(self) -> DuplicateStream: ...
+    def __next__(self) -> DuplicateGroup: ...
+    @property
+    def errors(self) -> Errors: ...
+
+class ImageHash:
+    @staticmethod
+    def from_hex(hex: str, bit_order: str = "native") -> ImageHash: ...
+    @staticmethod
+    def from_tagged(text: str) -> ImageHash: ...
+    @staticmethod
+    def from_bytes(data: bytes, hash_size: int | None = None) -> ImageHash: ...
+    @staticmethod
+    def from_base64(text: str, hash_size: int | None = None) -> ImageHash: ...
+    @staticmethod
+    def from_u64(value: int, signed: bool = False) -> ImageHash: ...
+    @staticmethod
+    def from_path(
+        path: StrPath,
+        hash_size: int = 8,
+        algorithm: str = "phash",
+        frames: str = "first",
+        trim_borders: bool = False,
+        center_crop: float | None = None,
+        normalize_gamma: bool = False,
+        mask_regions: list[Region] | None = None,
+        alpha_background: Color | None = None,
+        dct_size: int = ...,
+        filter: str = "lanczos3",
+        bit_order: str = "native",
+    ) -> ImageHash: ...
+    def to_hex(self, bit_order: str = "native") -> str: ...
+    def to_tagged(self) -> str: ...
+    def to_int(self) -> int: ...
+    def to_bytes(self) -> bytes: ...
+    def to_base64(self) -> str: ...
+    def to_u64(self, signed: bool = False) -> int: ...
+    def index_keys(self, bands: int, signed: bool = False) -> list[int]: ...
+    def index_probes(self, bands: int, threshold: int, signed: bool = False) -> list[list[int]]: ...
+    def bits(self) -> list[bool]: ...
+    def bit_matrix(self) -> numpy.ndarray: ...
+    @property
+    def hash_size(self) -> int: ...
+    @property
+    def algorithm(self) -> str | None: ...
+    def distance(self, other: ImageHash) -> int: ...
+    def __sub__(self, other: ImageHash) -> int: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+    def __len__(self) -> int: ...
+
+class HashIndex:
+    def __init__(self, algorithm: str | None = None, hash_size: int | None = None) -> None: ...
+    @property
+    def version(self) -> int: ...
+    @property
+    def algorithm(self) -> str | None: ...
+    @property
+    def hash_size(self) -> int | None: ...
+    def sync(self, base_url: str, public_key: str) -> int: ...
+    def add(self, path: StrPath, hash: str, dimensions: tuple[int, int] | None = None) -> None: ...
+    def query(self, hash: str, threshold: int = 10) -> list[tuple[str, int]]: ...
+    def save(self, path: StrPath) -> None: ...
+    @staticmethod
+    def load(path: StrPath) -> HashIndex: ...
+    def stale(self) -> list[str]: ...
+    def __len__(self) -> int: ...
+
+class Blocklist:
+    def __init__(
+        self,
+        hashes: Sequence[str] | Mapping[str, str] | None = None,
+        algorithm: str = "phash",
+        hash_size: int = 8,
+    ) -> None: ...
+    @staticmethod
+    def load(path: StrPath, algorithm: str = "phash", hash_size: int = 8) -> Blocklist: ...
+    @staticmethod
+    def from_index(index: HashIndex) -> Blocklist: ...
+    def add(self, hash: str | ImageHash, id: str | None = None) -> None: ...
+    def check(
+        self,
+        path_or_hash: StrPath | ImageHash,
+        threshold: int = 10,
+        check_rotations: bool = True,
+    ) -> BlocklistMatch | None: ...
+    @property
+    def algorithm(self) -> str: ...
+    @property
+    def hash_size(self) -> int: ...
+    def __len__(self) -> int: ...
+
+class DuplicateWatcher:
+    def __init__(
+        self,
+        directory: StrPath,
+        callback: Callable[[str, list[tuple[str, int]]], object],
+        threshold: int = 10,
+        algorithm: str = "phash",
+        hash_size: int = 8,
+        recursive: bool = True,
+        extensions: list[str] | None = None,
+        exclude: Sequence[StrPath] | None = None,
+    ) -> None: ...
+    def stop(self) -> None: ...
+    @property
+    def running(self) -> bool: ...
+    @property
+    def errors(self) -> Errors: ...
+    def __enter__(self) -> DuplicateWatcher: ...
+    def __exit__(self, *_args: object) -> None: ...
+
+def compute_hash(
+    path: StrPath,
+    hash_size: int = 8,
+    algorithm: str = "phash",
+    frames: str = "first",
+    check_rotations: bool = False,
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    bit_order: str = "native",
+    apply_exif_orientation: bool | None = None,
+) -> str: ...
+def compute_hash_bytes(
+    data: bytes,
+    hash_size: int = 8,
+    algorithm: str = "phash",
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+) -> str: ...
+def compute_hash_image(
+    image: Any,
+    hash_size: int = 8,
+    algorithm: str = "phash",
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+) -> str: ...
+def dct_coefficients(path: StrPath) -> numpy.ndarray: ...
+def hamming_distance(hash1: str, hash2: str, check_rotations: bool = False) -> int: ...
+def cross_correlation(hash1: str, hash2: str) -> float: ...
+def weighted_hamming_distance(
+    hash1: str, hash2: str, weights: Sequence[float] | numpy.ndarray | None = None
+) -> float: ...
+@overload
+def distance_matrix(hashes: list[str], query: None = None) -> list[list[int]]: ...
+@overload
+def distance_matrix(hashes: list[str], query: str) -> list[int]: ...
+def distances(query_hex: str, candidate_hexes: list[str]) -> list[int]: ...
+def filter_within(query_hex: str, candidates: list[str], threshold: int) -> list[tuple[int, int]]: ...
+@overload
+def pairwise_distances(
+    hashes: list[str],
+    max_memory_mb: int = 256,
+    threshold: int | None = None,
+    above: bool = False,
+    callback: None = None,
+    output_path: None = None,
+) -> list[tuple[int, int, int]]: ...
+@overload
+def pairwise_distances(
+    hashes: list[str],
+    max_memory_mb: int = 256,
+    threshold: int | None = None,
+    above: bool = False,
+    *,
+    callback: Callable[[list[tuple[int, int, int]]], object],
+    output_path: None = None,
+) -> int: ...
+@overload
+def pairwise_distances(
+    hashes: list[str],
+    max_memory_mb: int = 256,
+    threshold: int | None = None,
+    above: bool = False,
+    callback: None = None,
+    *,
+    output_path: StrPath,
+) -> int: ...
+def are_similar(
+    path1: StrPath,
+    path2: StrPath,
+    threshold: int = 10,
+    algorithm: str = "phash",
+    index: HashIndex | None = None,
+    hash_size: int = 8,
+) -> bool: ...
+def compare_detailed(
+    path1: StrPath,
+    path2: StrPath,
+    algorithm: str = "phash",
+    hash_size: int = 8,
+    heatmap: bool = False,
+) -> Comparison: ...
+def has_duplicate(
+    path: StrPath,
+    index: HashIndex,
+    threshold: int = 10,
+    algorithm: str = "phash",
+    hash_size: int = 8,
+) -> bool: ...
+def find_similar(
+    query: StrPath,
+    candidates: Sequence[StrPath] | Mapping[StrPath, str],
+    threshold: int = 10,
+    max_results: int | None = None,
+    algorithm: str = "phash",
+    hash_size: int = 8,
+    cache_path: StrPath | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: bool = False,
+) -> list[tuple[str, int]] | tuple[list[tuple[str, int]], Errors]: ...
+@overload
+def find_duplicate_images(
+    paths: Sequence[StrPath] | Mapping[StrPath, str],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    review_path: StrPath | None = None,
+    relative_to: StrPath | None = None,
+    cache_path: StrPath | None = None,
+    progress_socket: str | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: Literal[False] = False,
+    check_rotations: bool = False,
+    content_aware: bool = False,
+    match_rule: str | None = None,
+    exact_prepass: bool = False,
+    best_by: str | Callable[[str], Any] | None = None,
+    pairwise: bool = False,
+    hash_size: int = 8,
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    verify: bool = False,
+    min_similarity: float = ...,
+    weights: Sequence[float] | numpy.ndarray | None = None,
+    grouping: str = "connected",
+    coarse_threshold: int | None = None,
+    num_threads: int | None = None,
+    as_mapping: Literal[False] = False,
+    metadata: bool = False,
+    quality: bool = False,
+    apply_exif_orientation: bool | None = None,
+) -> list[DuplicateGroup]: ...
+@overload
+def find_duplicate_images(
+    paths: Sequence[StrPath] | Mapping[StrPath, str],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    review_path: StrPath | None = None,
+    relative_to: StrPath | None = None,
+    cache_path: StrPath | None = None,
+    progress_socket: str | None = None,
+    progress_callback: ProgressCallback | None = None,
+    *,
+    return_errors: Literal[True],
+    check_rotations: bool = False,
+    content_aware: bool = False,
+    match_rule: str | None = None,
+    exact_prepass: bool = False,
+    best_by: str | Callable[[str], Any] | None = None,
+    pairwise: bool = False,
+    hash_size: int = 8,
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    verify: bool = False,
+    min_similarity: float = ...,
+    weights: Sequence[float] | numpy.ndarray | None = None,
+    grouping: str = "connected",
+    coarse_threshold: int | None = None,
+    num_threads: int | None = None,
+    as_mapping: Literal[False] = False,
+    metadata: bool = False,
+    quality: bool = False,
+    apply_exif_orientation: bool | None = None,
+) -> tuple[list[DuplicateGroup], Errors]: ...
+@overload
+def find_duplicate_images(
+    paths: Sequence[StrPath] | Mapping[StrPath, str],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    review_path: StrPath | None = None,
+    relative_to: StrPath | None = None,
+    cache_path: StrPath | None = None,
+    progress_socket: str | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: bool = False,
+    check_rotations: bool = False,
+    content_aware: bool = False,
+    match_rule: str | None = None,
+    exact_prepass: bool = False,
+    best_by: str | Callable[[str], Any] | None = None,
+    pairwise: bool = False,
+    hash_size: int = 8,
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    verify: bool = False,
+    min_similarity: float = ...,
+    weights: Sequence[float] | numpy.ndarray | None = None,
+    grouping: str = "connected",
+    coarse_threshold: int | None = None,
+    num_threads: int | None = None,
+    *,
+    as_mapping: Literal[True],
+    metadata: bool = False,
+    quality: bool = False,
+    apply_exif_orientation: bool | None = None,
+) -> dict[str, MappingEntry] | tuple[dict[str, MappingEntry], Errors]: ...
+def find_duplicates_streaming(
+    path_chunks: Iterable[Sequence[StrPath]],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    window: int | None = None,
+    cache_path: StrPath | None = None,
+    relative_to: StrPath | None = None,
+    hash_size: int = 8,
+) -> DuplicateStream: ...
+def find_duplicates_resumable(
+    paths: Sequence[StrPath],
+    checkpoint_path: StrPath,
+    threshold: int = 10,
+    algorithm: str = "phash",
+    hash_size: int = 8,
+    checkpoint_every: int = 1000,
+    checkpoint_interval: float = 30.0,
+    cache_path: StrPath | None = None,
+    relative_to: StrPath | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: bool = False,
+    num_threads: int | None = None,
+) -> list[DuplicateGroup] | tuple[list[DuplicateGroup], Errors]: ...
+def resume(
+    checkpoint_path: StrPath,
+    cache_path: StrPath | None = None,
+    relative_to: StrPath | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: bool = False,
+    num_threads: int | None = None,
+) -> list[DuplicateGroup] | tuple[list[DuplicateGroup], Errors]: ...
+def classify_images(
+    paths: Sequence[StrPath], return_errors: bool = False
+) -> dict[str, str] | tuple[dict[str, str], Errors]: ...
+def cluster_similar(
+    paths: Sequence[StrPath],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    hash_size: int = 8,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: bool = False,
+    num_threads: int | None = None,
+) -> list[BurstCluster] | tuple[list[BurstCluster], Errors]: ...
+def score_image(path: StrPath) -> QualityScore: ...
+def robustness_report(path: StrPath, algorithm: str = "phash", hash_size: int = 8) -> RobustnessReport: ...
+def find_duplicate_videos(
+    paths: Sequence[StrPath],
+    threshold: float = 10.0,
+    frames: int = ...,
+    algorithm: str = "phash",
+    frame_provider: Callable[[str, int], Any] | None = None,
+    relative_to: StrPath | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: bool = False,
+) -> list[DuplicateGroup] | tuple[list[DuplicateGroup], Errors]: ...
+def export_report(
+    groups: Sequence[GroupLike],
+    path: StrPath,
+    format: str = "json",
+    thumbnails: bool = False,
+    thumbnail_size: int = ...,
+    root: StrPath | None = None,
+) -> None: ...
+def generate_report(
+    groups: Sequence[GroupLike], output_path: StrPath, root: StrPath | None = None
+) -> None: ...
+def export_results(
+    groups: Sequence[GroupLike],
+    path: StrPath,
+    format: str = "json",
+    algorithm: str | None = None,
+    threshold: int | None = None,
+    hash_size: int | None = None,
+    root: StrPath | None = None,
+) -> None: ...
+def import_results(path: StrPath) -> ImportedResults: ...
+def benchmark_index(
+    size: int = 100_000,
+    hash_size: int = 8,
+    distribution: str = "clustered",
+    threshold: int = 10,
+    queries: int = 10_000,
+    seed: int | None = None,
+) -> BenchReport: ...
+def find_photos_in_documents(
+    photos: Sequence[StrPath],
+    documents: Mapping[str, list[str]] | Sequence[StrPath],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    return_errors: bool = False,
+) -> list[DocumentMatch] | tuple[list[DocumentMatch], Errors]: ...
+def match_collections(
+    paths_a: Sequence[StrPath],
+    paths_b: Sequence[StrPath],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    hash_size: int = 8,
+    return_errors: bool = False,
+) -> list[tuple[str, str, int]] | tuple[list[tuple[str, str, int]], Errors]: ...
+def find_duplicates_incremental(
+    new_paths: Sequence[StrPath],
+    existing: Mapping[StrPath, str],
+    threshold: int = 10,
+    algorithm: str = "phash",
+    relative_to: StrPath | None = None,
+    hash_size: int = 8,
+) -> list[DuplicateGroup]: ...
+def annotate_group(review_path: StrPath, key: str, state: str, note: str = "") -> None: ...
+@overload
+def compute_hashes_parallel(
+    paths: Sequence[StrPath],
+    algorithm: str = "phash",
+    relative_to: StrPath | None = None,
+    cache_path: StrPath | None = None,
+    progress_socket: str | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: Literal[False] = False,
+    hash_size: int = 8,
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    num_threads: int | None = None,
+    return_partial: bool = False,
+    metadata: Literal[False] = False,
+    apply_exif_orientation: bool | None = None,
+) -> dict[str, str]: ...
+@overload
+def compute_hashes_parallel(
+    paths: Sequence[StrPath],
+    algorithm: str = "phash",
+    relative_to: StrPath | None = None,
+    cache_path: StrPath | None = None,
+    progress_socket: str | None = None,
+    progress_callback: ProgressCallback | None = None,
+    *,
+    return_errors: Literal[True],
+    hash_size: int = 8,
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    num_threads: int | None = None,
+    return_partial: bool = False,
+    metadata: Literal[False] = False,
+    apply_exif_orientation: bool | None = None,
+) -> tuple[dict[str, str], Errors]: ...
+@overload
+def compute_hashes_parallel(
+    paths: Sequence[StrPath],
+    algorithm: str = "phash",
+    relative_to: StrPath | None = None,
+    cache_path: StrPath | None = None,
+    progress_socket: str | None = None,
+    progress_callback: ProgressCallback | None = None,
+    return_errors: bool = False,
+    hash_size: int = 8,
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    num_threads: int | None = None,
+    return_partial: bool = False,
+    *,
+    metadata: Literal[True],
+    apply_exif_orientation: bool | None = None,
+) -> dict[str, HashedFile] | tuple[dict[str, HashedFile], Errors]: ...
+def scan_directory(
+    path: StrPath,
+    recursive: bool = True,
+    extensions: list[str] | None = None,
+    follow_symlinks: bool = False,
+    exclude: Sequence[StrPath] | None = None,
+    output: Literal["paths", "hashes", "duplicates"] = "paths",
+    threshold: int = 10,
+    algorithm: str = "phash",
+) -> list[str] | dict[str, str] | list[DuplicateGroup]: ...
+def dedupe(
+    root: StrPath,
+    profile: str = "balanced",
+    apply: bool = False,
+    threshold: int | None = None,
+    algorithm: str | None = None,
+    best_by: str | None = None,
+    exact_prepass: bool = False,
+    action: str | None = None,
+    quarantine_dir: StrPath | None = None,
+    cache_path: StrPath | None = None,
+    progress_callback: ProgressCallback | None = None,
+    hash_size: int | None = None,
+    num_threads: int | None = None,
+) -> DedupeReport: ...
+def apply_dedup(
+    groups: Sequence[GroupLike],
+    action: str = "delete",
+    keep: str | Callable[[str], Any] | None = None,
+    dry_run: bool = True,
+    move_to: StrPath | None = None,
+    quarantine_dir: StrPath | None = None,
+    relative_to: StrPath | None = None,
+    scanned_at: float | None = None,
+    algorithm: str = "phash",
+    trim_borders: bool = False,
+    center_crop: float | None = None,
+    normalize_gamma: bool = False,
+    mask_regions: list[Region] | None = None,
+    alpha_background: Color | None = None,
+    dct_size: int = ...,
+    filter: str = "lanczos3",
+    apply_exif_orientation: bool | None = None,
+) -> ApplyReport: ...
+def restore(manifest: StrPath) -> RestoreReport: ...
+def estimate_scan(
+    root_or_paths: StrPath | Sequence[StrPath],
+    algorithm: str = "phash",
+    threads: int | None = None,
+    cache_path: StrPath | None = None,
+    calibrate: int = 0,
+    hash_size: int = 8,
+) -> ScanEstimate: ...
+def calibrate_threshold(
+    duplicate_pairs: Sequence[tuple[StrPath, StrPath]],
+    distinct_pairs: Sequence[tuple[StrPath, StrPath]],
+    algorithm: str = "phash",
+    hash_size: int = 8,
+    cache_path: StrPath | None = None,
+) -> Calibration: ...
+def compute_crop_resistant_hash(path: StrPath, hash_size: int = 8, algorithm: str = "phash") -> str: ...
+def crop_resistant_distance(hash1: str, hash2: str) -> float: ...
+def crop_resistant_matches(
+    hash1: str, hash2: str, region_cutoff: int = 1, bit_error_rate: float = ...
+) -> bool: ...
+def compute_tiled_hash(
+    path: StrPath,
+    hash_size: int = 8,
+    algorithm: str = "phash",
+    scales: list[float] | None = None,
+    overlap: float = 0.5,
+) -> list[TiledRegion]: ...
+def match_regions(
+    path_a: StrPath,
+    path_b: StrPath,
+    threshold: int = 10,
+    hash_size: int = 8,
+    algorithm: str = "phash",
+    scales: list[float] | None = None,
+    overlap: float = 0.5,
+) -> list[RegionMatch]: ...
+def set_decode_backends(extension: str, backends: list[str]) -> None: ...
+def set_external_decoder(command: list[str] | None = None) -> None: ...
+def register_decoder(extension: str, decoder: Callable[[bytes], Any] | None = None) -> None: ...
+def set_exif_orientation(apply_exif_orientation: bool = True) -> None: ...
+def set_decode_limits(max_pixels: int | None = None, max_file_size: int | None = None) -> None: ...
+def set_read_order(order: str = "parallel") -> None: ...
+def set_hash_backend(backend: str = "auto") -> None: ...
+def hash_backend() -> str: ...
+def configure_logging(
+    level: Literal["off", "error", "warn", "info", "debug", "trace"] = "info",
+    forward: bool = True,
+) -> None: ...
+def register_hash_function(name: str, func: Callable[[Any], str] | None) -> None: ...
+def set_num_threads(num_threads: int | None = None) -> None: ...
+def supported_formats() -> dict[str, list[str]]: ...
+def features() -> dict[str, bool]: ...
+def get_capabilities() -> dict[str, Any]: ...
@@ -0,0 +1,575 @@
+//! Dedupe Actions Module
+//!
+//! Carries out a decision on duplicate groups found earlier: delete the
+//! copies, replace them with hard or symbolic links to the kept file, or
+//! move them away, into a quarantine folder or to the OS trash. Every file
+//! is checked right before it is touched, and a copy that changed since the
+//! scan is left alone.
+
+use crate::best::BestBy;
+use crate::cache::FileStamp;
+use crate::duplicate::DuplicateGroup;
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::paths::relative_path;
+use crate::pipeline::{free_target, move_file, sidecar_name};
+use crate::preprocess::HashOptions;
+use crate::quarantine::{move_to_trash, utc_stamp, ManifestEntry, ManifestWriter, MANIFEST_NAME};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What `apply_dedup` does with each copy that is not kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupAction {
+    /// Delete, sidecars included
+    Delete,
+    /// Replace with a hard link to the kept file; sidecars stay
+    Hardlink,
+    /// Replace with a symbolic link to the kept file; sidecars stay
+    Symlink,
+    /// Move into `DedupOptions::move_to`, sidecars included
+    Move,
+    /// Move into a timestamped folder below `DedupOptions::quarantine_dir`,
+    /// sidecars included, logging each file so `restore` can undo it
+    Quarantine,
+    /// Move to the OS trash (feature "trash"), sidecars included, logging
+    /// each file in a manifest below `DedupOptions::quarantine_dir`
+    Trash,
+}
+
+impl FromStr for DedupAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "delete" => Ok(DedupAction::Delete),
+            "hardlink" => Ok(DedupAction::Hardlink),
+            "symlink" => Ok(DedupAction::Symlink),
+            "move" => Ok(DedupAction::Move),
+            "quarantine" => Ok(DedupAction::Quarantine),
+            "trash" => Ok(DedupAction::Trash),
+            _ => Err(format!(
+                "Unknown action: {} (expected delete, hardlink, symlink, move, quarantine or trash)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DedupAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DedupAction::Delete => write!(f, "delete"),
+            DedupAction::Hardlink => write!(f, "hardlink"),
+            DedupAction::Symlink => write!(f, "symlink"),
+            DedupAction::Move => write!(f, "move"),
+            DedupAction::Quarantine => write!(f, "quarantine"),
+            DedupAction::Trash => write!(f, "trash"),
+        }
+    }
+}
+
+/// Settings for `apply_dedup`
+#[derive(Debug, Clone)]
+pub struct DedupOptions {
+    pub action: DedupAction,
+    /// Re-pick the kept file of each group; `None` keeps `best_path`
+    pub keep: Option<BestBy>,
+    /// Only report what would be done (the default)
+    pub dry_run: bool,
+    /// Destination directory for `DedupAction::Move`
+    pub move_to: Option<PathBuf>,
+    /// Folder holding one timestamped run folder per quarantine or trash
+    /// run, with the files (quarantine only) and the manifest
+    pub quarantine_dir: Option<PathBuf>,
+    /// Root the group paths are relative to, as with `relative_to` when
+    /// finding them. Moved and quarantined files keep their path below it; without it they
+    /// keep only their file name.
+    pub relative_to: Option<PathBuf>,
+    /// When the groups were scanned; files modified later are left alone
+    pub scanned_at: Option<SystemTime>,
+    /// Algorithm the groups' member hashes were computed with, for hashes
+    /// that don't record their own. Files with a recorded hash are hashed
+    /// again and left alone if it differs.
+    pub algorithm: HashAlgorithm,
+    /// Preprocessing and decoding the member hashes were computed with
+    pub hash_options: HashOptions,
+}
+
+impl Default for DedupOptions {
+    fn default() -> Self {
+        Self {
+            action: DedupAction::Delete,
+            keep: None,
+            dry_run: true,
+            move_to: None,
+            quarantine_dir: None,
+            relative_to: None,
+            scanned_at: None,
+            algorithm: HashAlgorithm::PHash,
+            hash_options: HashOptions::default(),
+        }
+    }
+}
+
+/// One copy acted on, or to be acted on in a dry run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionRecord {
+    pub path: PathBuf,
+    /// The file that stays and that links point to
+    pub keep: PathBuf,
+    pub action: DedupAction,
+    /// Destination when moving or quarantining
+    pub target: Option<PathBuf>,
+    /// Sidecars deleted or moved along with `path`
+    pub sidecars: Vec<PathBuf>,
+    /// Bytes freed, sidecars included
+    pub bytes: u64,
+}
+
+/// What `apply_dedup` did
+#[derive(Debug, Clone, Default)]
+pub struct ActionReport {
+    pub dry_run: bool,
+    /// Copies acted on, or that would be in a dry run
+    pub actions: Vec<ActionRecord>,
+    /// Copies left alone, with why (changed since the scan, already a
+    /// link, ...)
+    pub skipped: Vec<(PathBuf, String)>,
+    /// Copies whose action failed, with the error
+    pub errors: Vec<(PathBuf, String)>,
+    /// Manifest of a quarantine or trash run, for `restore`; `None` in dry
+    /// runs and when nothing was taken away
+    pub manifest: Option<PathBuf>,
+}
+
+impl ActionReport {
+    /// Bytes freed by `actions` (or that would be in a dry run)
+    pub fn bytes_freed(&self) -> u64 {
+        self.actions.iter().map(|a| a.bytes).sum()
+    }
+}
+
+/// Delete, link or move every copy in `groups` that is not kept
+///
+/// Before a copy is touched, it and the kept file must be unchanged since
+/// the scan: not modified after `scanned_at`, and hashing to their
+/// recorded member hash. Copies that can't be checked either way are
+/// skipped, as are copies that already are the kept file or a link to it.
+/// Dry runs do the same checks and change nothing. Quarantine and trash
+/// runs write their manifest into a new `<quarantine_dir>/<UTC time>`
+/// folder. Fails only for invalid options or an unwritable manifest.
+pub fn apply_dedup(
+    groups: &[DuplicateGroup],
+    options: &DedupOptions,
+) -> Result<ActionReport, String> {
+    if options.action == DedupAction::Move && options.move_to.is_none() {
+        return Err("The move action needs a destination directory".to_string());
+    }
+    let run_dir = match options.action {
+        DedupAction::Quarantine | DedupAction::Trash => {
+            let Some(dir) = &options.quarantine_dir else {
+                return Err(format!(
+                    "The {} action needs a quarantine directory",
+                    options.action
+                ));
+            };
+            let dir = std::path::absolute(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+            Some(free_target(
+                &dir.join(utc_stamp(SystemTime::now())),
+                &HashSet::new(),
+            ))
+        }
+        _ => None,
+    };
+    let dest = match options.action {
+        DedupAction::Move => options.move_to.as_deref(),
+        DedupAction::Quarantine => run_dir.as_deref(),
+        _ => None,
+    };
+    let mut manifest = None;
+    let mut report = ActionReport {
+        dry_run: options.dry_run,
+        ..ActionReport::default()
+    };
+    let mut taken = HashSet::new();
+    for g in groups.iter().filter(|g| g.paths.len() > 1) {
+        let resolved;
+        let g = match &options.relative_to {
+            Some(root) => {
+                resolved = g.resolved_under(root);
+                &resolved
+            }
+            None => g,
+        };
+        let keep = match &options.keep {
+            Some(by) => by.pick(&g.paths, &g.best_path).to_path_buf(),
+            None => g.best_path.clone(),
+        };
+        if let Err(reason) = unchanged(&keep, g, options) {
+            for path in g.paths.iter().filter(|p| **p != keep) {
+                report
+                    .skipped
+                    .push((path.clone(), format!("{}: {}", keep.display(), reason)));
+            }
+            continue;
+        }
+        for path in g.paths.iter().filter(|p| **p != keep) {
+            let record = match plan(path, &keep, g, options, dest, &mut taken) {
+                Ok(record) => record,
+                Err(reason) => {
+                    report.skipped.push((path.clone(), reason));
+                    continue;
+                }
+            };
+            if !options.dry_run {
+                if let (Some(dir), None) = (&run_dir, &manifest) {
+                    manifest = Some(ManifestWriter::create(dir, &options.action.to_string())?);
+                    report.manifest = Some(dir.join(MANIFEST_NAME));
+                }
+                if let Err(e) = execute(&record, manifest.as_mut()) {
+                    report.errors.push((path.clone(), e));
+                    continue;
+                }
+            }
+            report.actions.push(record);
+        }
+    }
+    Ok(report)
+}
+
+/// Check `path` against the scan time and its recorded hash
+fn unchanged(path: &Path, group: &DuplicateGroup, options: &DedupOptions) -> Result<(), String> {
+    let meta = fs::metadata(path).map_err(|e| e.to_string())?;
+    let recorded = group.member_hashes.get(path);
+    if recorded.is_none() && options.scanned_at.is_none() {
+        return Err("no recorded hash or scan time to check it against".to_string());
+    }
+    if let Some(scanned_at) = options.scanned_at {
+        if meta.modified().map_err(|e| e.to_string())? > scanned_at {
+            return Err("modified since the scan".to_string());
+        }
+    }
+    if let Some(recorded) = recorded {
+        let algorithm = recorded.algorithm().unwrap_or(options.algorithm);
+        let current =
+            ImageHash::from_path_with(path, algorithm, recorded.hash_size(), &options.hash_options)
+                .map_err(|e| e.to_string())?;
+        if current != *recorded {
+            return Err("changed since the scan".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Check one copy and work out what acting on it involves
+fn plan(
+    path: &Path,
+    keep: &Path,
+    group: &DuplicateGroup,
+    options: &DedupOptions,
+    dest: Option<&Path>,
+    taken: &mut HashSet<PathBuf>,
+) -> Result<ActionRecord, String> {
+    if same_file(path, keep) {
+        return Err(format!("already {} or a link to it", keep.display()));
+    }
+    if options.action == DedupAction::Symlink && is_symlink(path) {
+        return Err("already a symbolic link".to_string());
+    }
+    unchanged(path, group, options)?;
+
+    let size = FileStamp::of(path).map_or(0, |s| s.size());
+    let moves_sidecars = !matches!(options.action, DedupAction::Hardlink | DedupAction::Symlink);
+    let sidecars = match group.sidecars.get(path) {
+        Some(sidecars) if moves_sidecars => sidecars.clone(),
+        _ => Vec::new(),
+    };
+    let bytes = size
+        + sidecars
+            .iter()
+            .filter_map(FileStamp::of)
+            .map(|s| s.size())
+            .sum::<u64>();
+    let target = dest.map(|dir| {
+        let name = match &options.relative_to {
+            Some(root) => relative_path(path, root),
+            None => PathBuf::from(path.file_name().unwrap_or_default()),
+        };
+        let target = free_target(&dir.join(name), taken);
+        taken.insert(target.clone());
+        target
+    });
+    Ok(ActionRecord {
+        path: path.to_path_buf(),
+        keep: keep.to_path_buf(),
+        action: options.action,
+        target,
+        sidecars,
+        bytes,
+    })
+}
+
+/// Whether both paths lead to the same file (symlinks, hard links)
+fn same_file(a: &Path, b: &Path) -> bool {
+    if let (Ok(a), Ok(b)) = (fs::canonicalize(a), fs::canonicalize(b)) {
+        if a == b {
+            return true;
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let (Ok(a), Ok(b)) = (fs::metadata(a), fs::metadata(b)) {
+            return a.dev() == b.dev() && a.ino() == b.ino();
+        }
+    }
+    false
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Carry out `record`, logging quarantined and trashed files in `manifest`
+fn execute(record: &ActionRecord, mut manifest: Option<&mut ManifestWriter>) -> Result<(), String> {
+    let path = record.path.as_path();
+    let mut log = |original: &Path, stored: Option<&Path>| match manifest.as_deref_mut() {
+        Some(manifest) => manifest.record(&ManifestEntry {
+            original: absolute(original),
+            stored: stored.map(absolute),
+            keep: absolute(&record.keep),
+        }),
+        None => Ok(()),
+    };
+    match record.action {
+        DedupAction::Delete => {
+            fs::remove_file(path).map_err(|e| e.to_string())?;
+            for sidecar in &record.sidecars {
+                fs::remove_file(sidecar).map_err(|e| format!("{}: {}", sidecar.display(), e))?;
+            }
+        }
+        DedupAction::Move | DedupAction::Quarantine => {
+            let target = record.target.as_deref().unwrap_or(Path::new(""));
+            // Logged before moving: an entry whose file never left is
+            // skipped by `restore`, a moved file without one is lost
+            log(path, Some(target))?;
+            move_file(path, target)?;
+            for sidecar in &record.sidecars {
+                let name = sidecar_name(sidecar, &record.path, target);
+                let sidecar_target = target.with_file_name(name);
+                log(sidecar, Some(&sidecar_target))?;
+                move_file(sidecar, &sidecar_target)?;
+            }
+        }
+        DedupAction::Trash => {
+            for file in std::iter::once(&record.path).chain(&record.sidecars) {
+                log(file, None)?;
+                move_to_trash(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            }
+        }
+        DedupAction::Hardlink => replace_with_link(path, |link| fs::hard_link(&record.keep, link))?,
+        DedupAction::Symlink => {
+            let keep = fs::canonicalize(&record.keep).map_err(|e| e.to_string())?;
+            replace_with_link(path, |link| symlink(&keep, link))?
+        }
+    }
+    Ok(())
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Create a link next to `path` and rename it over `path`, so `path` is
+/// never missing, even if linking fails
+fn replace_with_link<F>(path: &Path, link: F) -> Result<(), String>
+where
+    F: FnOnce(&Path) -> std::io::Result<()>,
+{
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.phash-link", name));
+    link(&temp).map_err(|e| format!("linking {}: {}", temp.display(), e))?;
+    fs::rename(&temp, path).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        e.to_string()
+    })
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_original: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symbolic links are not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_links_unchanged_copies_only() {
+        let root = std::env::temp_dir().join(format!("phash_rs_actions_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = |name: &str| root.join(name);
+        for name in ["a.jpg", "b.jpg", "c.jpg"] {
+            fs::write(path(name), b"pixels").unwrap();
+        }
+        let group = DuplicateGroup {
+            paths: vec![path("a.jpg"), path("b.jpg"), path("c.jpg")],
+            hash: "0000000000000000".to_string(),
+            best_path: path("a.jpg"),
+            sidecars: BTreeMap::new(),
+            content_type: None,
+            member_hashes: BTreeMap::new(),
+            member_dimensions: BTreeMap::new(),
+        };
+        let scanned_at = SystemTime::now() + Duration::from_secs(3600);
+        let options = DedupOptions {
+            action: DedupAction::Hardlink,
+            scanned_at: Some(scanned_at),
+            ..DedupOptions::default()
+        };
+
+        // Dry run: planned, nothing touched
+        let report = apply_dedup(std::slice::from_ref(&group), &options).unwrap();
+        assert_eq!(report.actions.len(), 2);
+        assert_eq!(report.bytes_freed(), 12);
+        assert!(!same_file(&path("a.jpg"), &path("b.jpg")));
+
+        // A copy edited after the scan is left alone
+        let edited = SystemTime::now() + Duration::from_secs(7200);
+        let file = fs::File::options().write(true).open(path("c.jpg")).unwrap();
+        file.set_modified(edited).unwrap();
+        drop(file);
+        let report = apply_dedup(
+            std::slice::from_ref(&group),
+            &DedupOptions {
+                dry_run: false,
+                ..options.clone()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.actions.len(), 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].0, path("c.jpg"));
+        assert!(report.errors.is_empty());
+        assert!(same_file(&path("a.jpg"), &path("b.jpg")));
+
+        // Linked copies aren't linked again; without a scan time or
+        // hashes nothing can be checked
+        let again = apply_dedup(std::slice::from_ref(&group), &options).unwrap();
+        assert!(again.skipped.iter().any(|(p, _)| *p == path("b.jpg")));
+        let unchecked = apply_dedup(&[group], &DedupOptions::default()).unwrap();
+        assert!(unchecked.actions.is_empty());
+        assert!(apply_dedup(
+            &[],
+            &DedupOptions {
+                action: DedupAction::Move,
+                ..DedupOptions::default()
+            }
+        )
+        .is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rehashes_with_the_scans_algorithm_and_options() {
+        let root =
+            std::env::temp_dir().join(format!("phash_rs_actions_whash_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let paths = vec![root.join("a.png"), root.join("b.png")];
+        // Gradient inside a white scan margin
+        let img = image::RgbImage::from_fn(64, 48, |x, y| {
+            if !(8..56).contains(&x) || !(8..40).contains(&y) {
+                image::Rgb([255, 255, 255])
+            } else {
+                image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8])
+            }
+        });
+        for path in &paths {
+            img.save(path).unwrap();
+        }
+        let hash_options = HashOptions {
+            trim_borders: true,
+            ..HashOptions::default()
+        };
+        let (groups, _) = crate::duplicate::find_duplicates_with(
+            &paths,
+            HashAlgorithm::WHash { level: None },
+            8,
+            0,
+            false,
+            &hash_options,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(groups.len(), 1);
+
+        // The recorded hashes know their algorithm; the options must match
+        let options = DedupOptions {
+            hash_options,
+            ..DedupOptions::default()
+        };
+        let report = apply_dedup(&groups, &options).unwrap();
+        assert_eq!(report.actions.len(), 1);
+        assert!(report.skipped.is_empty());
+        let untrimmed = apply_dedup(&groups, &DedupOptions::default()).unwrap();
+        assert!(untrimmed.actions.is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_quarantine_and_restore() {
+        let root =
+            std::env::temp_dir().join(format!("phash_rs_quarantine_run_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = |name: &str| root.join(name);
+        for name in ["a.jpg", "b.jpg", "b.xmp"] {
+            fs::write(path(name), name).unwrap();
+        }
+        let group = DuplicateGroup {
+            paths: vec![path("a.jpg"), path("b.jpg")],
+            hash: "0000000000000000".to_string(),
+            best_path: path("a.jpg"),
+            sidecars: BTreeMap::from([(path("b.jpg"), vec![path("b.xmp")])]),
+            content_type: None,
+            member_hashes: BTreeMap::new(),
+            member_dimensions: BTreeMap::new(),
+        };
+        let options = DedupOptions {
+            action: DedupAction::Quarantine,
+            dry_run: false,
+            quarantine_dir: Some(root.join("quarantine")),
+            scanned_at: Some(SystemTime::now() + Duration::from_secs(3600)),
+            ..DedupOptions::default()
+        };
+        let report = apply_dedup(std::slice::from_ref(&group), &options).unwrap();
+        assert_eq!(report.actions.len(), 1);
+        assert!(!Path::new(&path("b.jpg")).exists());
+        assert!(!Path::new(&path("b.xmp")).exists());
+
+        let manifest = report.manifest.unwrap();
+        let restored = crate::quarantine::restore(Path::new(&manifest)).unwrap();
+        assert_eq!(restored.restored, vec![path("b.jpg"), path("b.xmp")]);
+        assert_eq!(fs::read(path("b.xmp")).unwrap(), b"b.xmp");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
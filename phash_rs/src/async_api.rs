@@ -0,0 +1,124 @@
+//! Async Module
+//!
+//! Variants of the hashing entry points for tokio services (`tokio`
+//! feature). They never block the calling executor thread: files are read
+//! with `tokio::fs`, single images are decoded and hashed on tokio's
+//! blocking pool, and duplicate grouping runs on the rayon pool like its
+//! synchronous counterpart, its result handed back through a channel.
+//!
+//! Dropping one of these futures doesn't stop work already handed off;
+//! it runs to completion and its result is discarded.
+
+use crate::decode::decoder_config;
+use crate::duplicate::{find_duplicates_parallel, DuplicateGroup};
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, ImageHash};
+use std::path::{Path, PathBuf};
+use tokio::sync::oneshot;
+
+impl ImageHash {
+    /// `from_path` for async callers
+    ///
+    /// The file is read with `tokio::fs`; decoding and hashing run on
+    /// tokio's blocking pool. Files the `image` backend decodes are
+    /// decoded from the bytes read, others by their configured backends.
+    pub async fn from_path_async<P: AsRef<Path>>(
+        path: P,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<Self, HashError> {
+        let path = path.as_ref().to_path_buf();
+        let data = tokio::fs::read(&path)
+            .await
+            .map_err(|e| HashError::Io(format!("{}: {}", path.display(), e)))?;
+        tokio::task::spawn_blocking(move || {
+            let img = decoder_config().open_read(&path, &data)?;
+            Self::from_image(&img, algorithm, hash_size)
+        })
+        .await
+        .map_err(|e| HashError::Io(format!("Hashing task failed: {}", e)))?
+    }
+}
+
+/// `find_duplicates_parallel` for async callers
+///
+/// Hashing and grouping run on the rayon pool, so the whole batch is
+/// spread over all cores while the calling task waits without blocking.
+pub async fn find_duplicates_async(
+    paths: Vec<PathBuf>,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+) -> Result<Vec<DuplicateGroup>, String> {
+    on_rayon(move || find_duplicates_parallel(&paths, algorithm, hash_size, threshold)).await?
+}
+
+/// Run `f` on the rayon pool and wait for its result without blocking
+async fn on_rayon<T, F>(f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    rayon::spawn(move || {
+        // The receiver is gone if the caller stopped waiting
+        let _ = tx.send(f());
+    });
+    rx.await
+        .map_err(|_| "Worker stopped before finishing".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GrayImage, ImageFormat, Luma};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_async_matches_sync() {
+        let root = std::env::temp_dir().join(format!("phash_rs_async_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(64, 48, |x, y| {
+            Luma([(x * 3 + y * 2) as u8])
+        }));
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageFormat::Png).unwrap();
+        let paths: Vec<PathBuf> = ["a.png", "b.png"]
+            .iter()
+            .map(|name| {
+                let path = root.join(name);
+                std::fs::write(&path, png.get_ref()).unwrap();
+                path
+            })
+            .collect();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let hash = runtime
+            .block_on(ImageHash::from_path_async(
+                &paths[0],
+                HashAlgorithm::PHash,
+                8,
+            ))
+            .unwrap();
+        assert_eq!(
+            hash,
+            ImageHash::from_path(&paths[0], HashAlgorithm::PHash, 8).unwrap()
+        );
+        let missing = root.join("missing.png");
+        assert!(runtime
+            .block_on(ImageHash::from_path_async(
+                &missing,
+                HashAlgorithm::PHash,
+                8
+            ))
+            .is_err());
+
+        let groups = runtime
+            .block_on(find_duplicates_async(paths, HashAlgorithm::PHash, 8, 0))
+            .unwrap();
+        assert_eq!(groups.len(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,959 @@
+//! Hash Backend Module
+//!
+//! Chooses where the resize and DCT stages of pHash run. The CPU backend is
+//! the `image` crate's Lanczos3 resize followed by an f64 DCT; the GPU
+//! backend (`gpu` feature) runs both stages as wgpu compute shaders over
+//! batches of images.
+//!
+//! GPU hashes are identical to CPU hashes. The shaders resample with the
+//! same weights, in the same order, as the `image` crate and also return a
+//! bound on how far each f32 result may be from the CPU's. The few pixels
+//! whose rounding the bound leaves open are recomputed on the CPU, and the
+//! DCT is redone there when the GPU's coefficients are too close to their
+//! median to be sure of every bit.
+
+use crate::error::HashError;
+#[cfg(feature = "gpu")]
+use crate::hash::PHashConfig;
+#[cfg(any(test, feature = "gpu"))]
+use crate::hash::DCT_SIZE;
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::preprocess::HashOptions;
+use image::DynamicImage;
+#[cfg(any(test, feature = "gpu"))]
+use image::{GenericImageView, GrayImage};
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{OnceLock, RwLock};
+
+#[cfg(feature = "gpu")]
+use std::sync::mpsc;
+
+/// Images sent to the GPU in one dispatch at most
+pub const GPU_BATCH_SIZE: usize = 256;
+
+/// Bound on the f32 error of the GPU DCT, for pixels in 0..=255
+///
+/// Each pass sums 32 products whose magnitudes add up to at most 1443
+/// (rows) and 8165 (columns), so the rounding error stays below 0.035.
+#[cfg(any(test, feature = "gpu"))]
+const DCT_TOLERANCE: f64 = 0.05;
+
+/// Where pHash's resize and DCT stages run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashBackend {
+    /// The GPU when one is usable, the CPU otherwise
+    #[default]
+    Auto,
+    /// Always the CPU
+    Cpu,
+    /// The GPU (`gpu` feature)
+    Gpu,
+}
+
+impl FromStr for HashBackend {
+    type Err = String;
+
+    /// Parse "auto", "cpu" or "gpu"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(HashBackend::Auto),
+            "cpu" => Ok(HashBackend::Cpu),
+            "gpu" => Ok(HashBackend::Gpu),
+            _ => Err(format!("Unknown hash backend: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for HashBackend {
+    /// Canonical name, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashBackend::Auto => "auto",
+            HashBackend::Cpu => "cpu",
+            HashBackend::Gpu => "gpu",
+        })
+    }
+}
+
+fn global() -> &'static RwLock<HashBackend> {
+    static BACKEND: OnceLock<RwLock<HashBackend>> = OnceLock::new();
+    BACKEND.get_or_init(|| RwLock::new(HashBackend::default()))
+}
+
+/// Process-wide backend setting (`Auto` unless changed)
+pub fn hash_backend() -> HashBackend {
+    *global().read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Set the process-wide backend
+///
+/// Fails for `Gpu` when no GPU is usable, see `gpu_available`.
+pub fn set_hash_backend(backend: HashBackend) -> Result<(), String> {
+    if backend == HashBackend::Gpu && !gpu_available() {
+        return Err(if cfg!(feature = "gpu") {
+            "No usable GPU adapter found".to_string()
+        } else {
+            "The GPU backend requires the `gpu` feature".to_string()
+        });
+    }
+    *global().write().unwrap_or_else(|e| e.into_inner()) = backend;
+    Ok(())
+}
+
+/// The backend batches run on: `Cpu` or `Gpu`, with `Auto` resolved
+pub fn active_backend() -> HashBackend {
+    match hash_backend() {
+        HashBackend::Auto if gpu_available() => HashBackend::Gpu,
+        HashBackend::Auto => HashBackend::Cpu,
+        backend => backend,
+    }
+}
+
+/// Whether the library was built with the `gpu` feature and found a GPU
+///
+/// The first call initializes the device, which can take a moment.
+pub fn gpu_available() -> bool {
+    #[cfg(feature = "gpu")]
+    return gpu::Gpu::get().is_some();
+    #[cfg(not(feature = "gpu"))]
+    false
+}
+
+/// pHashes of `images` on the active backend, in input order
+///
+/// Images the GPU can't take (16-bit or float pixels, already
+/// `DCT_SIZE` square, too large for its buffers) are hashed on the CPU.
+pub fn phash_batch(images: &[DynamicImage], hash_size: usize) -> Result<Vec<ImageHash>, HashError> {
+    HashAlgorithm::PHash.check_hash_size(hash_size)?;
+    #[cfg(feature = "gpu")]
+    if let (HashBackend::Gpu, Some(gpu)) = (active_backend(), gpu::Gpu::get()) {
+        let lumas: Vec<Option<GrayImage>> = images.par_iter().map(gpu_luma).collect();
+        let mut hashes: Vec<Option<ImageHash>> = vec![None; images.len()];
+        let queued: Vec<usize> = (0..images.len()).filter(|&i| lumas[i].is_some()).collect();
+        for chunk in gpu.chunks(&queued, |i| lumas[i].as_ref().unwrap()) {
+            let batch: Vec<&GrayImage> =
+                chunk.iter().map(|&i| lumas[i].as_ref().unwrap()).collect();
+            for (&i, output) in chunk.iter().zip(gpu.run(&batch)) {
+                hashes[i] = Some(output.phash(lumas[i].as_ref().unwrap(), hash_size));
+            }
+        }
+        return images
+            .par_iter()
+            .zip(hashes)
+            .map(|(img, hash)| match hash {
+                Some(hash) => Ok(hash),
+                None => ImageHash::from_image(img, HashAlgorithm::PHash, hash_size),
+            })
+            .collect();
+    }
+    images
+        .par_iter()
+        .map(|img| ImageHash::from_image(img, HashAlgorithm::PHash, hash_size))
+        .collect()
+}
+
+/// pHash of one image of a batch hashed across worker threads
+///
+/// With the GPU backend the image joins the images other threads are
+/// hashing at the same time in one dispatch; this thread waits for it.
+pub(crate) fn phash_shared(
+    img: &DynamicImage,
+    hash_size: usize,
+    options: &HashOptions,
+) -> Result<ImageHash, HashError> {
+    options.validate()?;
+    // The shaders resample to the default config only
+    #[cfg(feature = "gpu")]
+    if let (HashBackend::Gpu, true, Some(queue)) = (
+        active_backend(),
+        options.phash == PHashConfig::default(),
+        gpu::Gpu::get().and_then(|gpu| gpu.queue()),
+    ) {
+        HashAlgorithm::PHash.check_hash_size(hash_size)?;
+        let applied;
+        let img = if options.is_identity() {
+            img
+        } else {
+            applied = options.apply(img);
+            &applied
+        };
+        if let Some(luma) = gpu_luma(img) {
+            let (reply, result) = mpsc::channel();
+            let sent = queue
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .send((luma, reply))
+                .is_ok();
+            if let Some(Some((luma, output))) = sent.then(|| result.recv().ok()).flatten() {
+                return Ok(output.phash(&luma, hash_size));
+            }
+        }
+        return ImageHash::from_image(img, HashAlgorithm::PHash, hash_size);
+    }
+    ImageHash::from_image_with(img, HashAlgorithm::PHash, hash_size, options)
+}
+
+/// The 8-bit luma plane the CPU path resizes, if the GPU can take it
+///
+/// Alpha is dropped: the `image` crate resamples each channel separately.
+#[cfg(any(test, feature = "gpu"))]
+fn gpu_luma(img: &DynamicImage) -> Option<GrayImage> {
+    let (width, height) = img.dimensions();
+    // Same-size "resizes" are plain copies in the image crate
+    if width == 0 || height == 0 || (width, height) == (DCT_SIZE as u32, DCT_SIZE as u32) {
+        return None;
+    }
+    match img.grayscale() {
+        DynamicImage::ImageLuma8(luma) => Some(luma),
+        DynamicImage::ImageLumaA8(luma_alpha) => Some(GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([luma_alpha.get_pixel(x, y).0[0]])
+        })),
+        _ => None,
+    }
+}
+
+/// Input pixels and weights of each output pixel of a 1D Lanczos3
+/// resample from `src` to `DCT_SIZE` pixels, as the `image` crate
+/// computes them
+#[cfg(any(test, feature = "gpu"))]
+fn lanczos3_taps(src: u32) -> Vec<(u32, Vec<f32>)> {
+    fn sinc(t: f32) -> f32 {
+        let a = t * std::f32::consts::PI;
+        if t == 0.0 {
+            1.0
+        } else {
+            a.sin() / a
+        }
+    }
+    let kernel = |x: f32| {
+        if x.abs() < 3.0 {
+            sinc(x) * sinc(x / 3.0)
+        } else {
+            0.0
+        }
+    };
+
+    let ratio = src as f32 / DCT_SIZE as f32;
+    let sratio = if ratio < 1.0 { 1.0 } else { ratio };
+    let support = 3.0 * sratio;
+    (0..DCT_SIZE as u32)
+        .map(|out| {
+            let center = (out as f32 + 0.5) * ratio;
+            let left = ((center - support).floor() as i64).clamp(0, src as i64 - 1) as u32;
+            let right =
+                ((center + support).ceil() as i64).clamp(left as i64 + 1, src as i64) as u32;
+            let center = center - 0.5;
+
+            let mut weights: Vec<f32> = (left..right)
+                .map(|i| kernel((i as f32 - center) / sratio))
+                .collect();
+            let mut sum = 0.0;
+            for w in &weights {
+                sum += w;
+            }
+            for w in weights.iter_mut() {
+                *w /= sum;
+            }
+            (left, weights)
+        })
+        .collect()
+}
+
+/// The orthonormal `DCT_SIZE`-point DCT-II as a row-major matrix
+#[cfg(any(test, feature = "gpu"))]
+fn dct_matrix() -> Vec<f32> {
+    let n = DCT_SIZE as f64;
+    (0..DCT_SIZE)
+        .flat_map(|k| {
+            let scale = if k == 0 {
+                (1.0 / n).sqrt()
+            } else {
+                (2.0 / n).sqrt()
+            };
+            (0..DCT_SIZE).map(move |x| {
+                let angle = std::f64::consts::PI * (2 * x + 1) as f64 * k as f64 / (2.0 * n);
+                (scale * angle.cos()) as f32
+            })
+        })
+        .collect()
+}
+
+/// What the GPU computed for one image
+#[cfg(any(test, feature = "gpu"))]
+struct GpuOutput {
+    /// Resized pixels before rounding, each with a bound on its distance
+    /// from the CPU's value
+    resized: Vec<[f32; 2]>,
+    /// DCT coefficients of the rounded pixels, row-major
+    coeffs: Vec<f32>,
+}
+
+#[cfg(any(test, feature = "gpu"))]
+impl GpuOutput {
+    /// The pHash of `luma`, which this is the output for, as the CPU
+    /// computes it
+    ///
+    /// Pixels whose bound reaches a rounding boundary are recomputed the
+    /// way the `image` crate computes them. If none of them changes and
+    /// the median coefficient is more than twice `DCT_TOLERANCE` from the
+    /// next one, the GPU's coefficients set the same bits as the CPU's;
+    /// otherwise the DCT is redone on the pixels.
+    fn phash(&self, luma: &GrayImage, hash_size: usize) -> ImageHash {
+        let mut pixels: Vec<f64> = self
+            .resized
+            .iter()
+            .map(|&[value, _]| value.clamp(0.0, 255.0).round_ties_even() as f64)
+            .collect();
+        let mut unchanged = true;
+        let mut taps = None;
+        for (i, &[value, bound]) in self.resized.iter().enumerate() {
+            let (value, bound) = (value as f64, bound as f64);
+            let low = (value - bound).clamp(0.0, 255.0);
+            let high = (value + bound).clamp(0.0, 255.0);
+            // The shader rounds ties to even, the image crate away from zero
+            let settled = low.is_finite()
+                && high.is_finite()
+                && low.round() == high.round()
+                && value.fract() != 0.5;
+            if settled {
+                continue;
+            }
+            let (rows, columns) = taps.get_or_insert_with(|| {
+                let (width, height) = luma.dimensions();
+                (lanczos3_taps(height), lanczos3_taps(width))
+            });
+            let pixel = resample_pixel(luma, &rows[i / DCT_SIZE], &columns[i % DCT_SIZE]);
+            if pixel != pixels[i] {
+                pixels[i] = pixel;
+                unchanged = false;
+            }
+        }
+
+        let hash = |dct: &[f64]| {
+            ImageHash::phash_from_dct(dct, DCT_SIZE, hash_size).with_algorithm(HashAlgorithm::PHash)
+        };
+        if unchanged {
+            let dct: Vec<f64> = self.coeffs.iter().map(|&c| c as f64).collect();
+            let mut window: Vec<f64> = (0..hash_size)
+                .flat_map(|y| dct[y * DCT_SIZE..y * DCT_SIZE + hash_size].iter().copied())
+                .skip(1)
+                .collect();
+            window.sort_by(|a, b| a.total_cmp(b));
+            let mid = window.len() / 2;
+            if window
+                .get(mid + 1)
+                .is_some_and(|next| next - window[mid] > 2.0 * DCT_TOLERANCE)
+            {
+                return hash(&dct);
+            }
+        }
+        hash(&ImageHash::dct_2d(&pixels, DCT_SIZE))
+    }
+}
+
+/// One pixel of the `image` crate's Lanczos3 resize of `luma`, summed in
+/// the same order: the source columns resampled vertically by `rows`,
+/// then those values horizontally by `columns`
+#[cfg(any(test, feature = "gpu"))]
+fn resample_pixel(luma: &GrayImage, rows: &(u32, Vec<f32>), columns: &(u32, Vec<f32>)) -> f64 {
+    let ((top, vertical), (left, horizontal)) = (rows, columns);
+    let mut t = 0.0f32;
+    for (i, w) in horizontal.iter().enumerate() {
+        let mut column = 0.0f32;
+        for (j, v) in vertical.iter().enumerate() {
+            column += luma.get_pixel(left + i as u32, top + j as u32).0[0] as f32 * v;
+        }
+        t += column * w;
+    }
+    t.clamp(0.0, 255.0).round() as f64
+}
+
+#[cfg(feature = "gpu")]
+mod gpu {
+    use super::{dct_matrix, lanczos3_taps, GpuOutput, GPU_BATCH_SIZE};
+    use crate::hash::DCT_SIZE;
+    use image::GrayImage;
+    use std::collections::HashMap;
+    use std::sync::{mpsc, Mutex, OnceLock};
+    use std::time::Duration;
+    use wgpu::util::DeviceExt;
+
+    /// How long the shared queue waits for more images after the first
+    const BATCH_WAIT: Duration = Duration::from_millis(2);
+
+    /// u32 values describing each image at the start of the plan buffer:
+    /// pixel offset, width, height, column offset, vertical taps,
+    /// horizontal taps
+    const INFO_LEN: usize = 6;
+
+    /// Storage buffers bound by the shader
+    const BINDINGS: u32 = 7;
+
+    const SHADER: &str = r#"
+const SIZE: u32 = 32u;
+const EPS: f32 = 1.1920929e-7;
+
+@group(0) @binding(0) var<storage, read> plan: array<u32>;
+@group(0) @binding(1) var<storage, read> pixels: array<u32>;
+@group(0) @binding(2) var<storage, read> weights: array<f32>;
+@group(0) @binding(3) var<storage, read_write> columns: array<vec2<f32>>;
+@group(0) @binding(4) var<storage, read_write> resized: array<vec2<f32>>;
+@group(0) @binding(5) var<storage, read_write> rows: array<f32>;
+@group(0) @binding(6) var<storage, read_write> coeffs: array<f32>;
+
+fn pixel(base: u32, index: u32) -> f32 {
+    let word = pixels[base + index / 4u];
+    return f32((word >> ((index % 4u) * 8u)) & 0xffu);
+}
+
+// Each source column resampled to SIZE rows, as the image crate's first pass.
+// Next to each value goes twice the worst-case f32 rounding error of the
+// sum, bounding how far it can be from the CPU's.
+@compute @workgroup_size(64)
+fn vertical(@builtin(global_invocation_id) id: vec3<u32>) {
+    let info = id.z * 6u;
+    let width = plan[info + 1u];
+    let x = id.x;
+    if (x >= width) {
+        return;
+    }
+    let tap = plan[info + 4u] + id.y * 3u;
+    let left = plan[tap];
+    let count = plan[tap + 1u];
+    let offset = plan[tap + 2u];
+    var t = 0.0;
+    var m = 0.0;
+    for (var i = 0u; i < count; i++) {
+        let p = pixel(plan[info], (left + i) * width + x) * weights[offset + i];
+        t += p;
+        m += abs(p);
+    }
+    columns[plan[info + 3u] + id.y * width + x] = vec2<f32>(t, 2.0 * f32(count) * EPS * m);
+}
+
+// Each of those rows resampled to SIZE pixels
+@compute @workgroup_size(32, 8)
+fn horizontal(@builtin(global_invocation_id) id: vec3<u32>) {
+    let info = id.z * 6u;
+    let width = plan[info + 1u];
+    let tap = plan[info + 5u] + id.x * 3u;
+    let left = plan[tap];
+    let count = plan[tap + 1u];
+    let offset = plan[tap + 2u];
+    let row = plan[info + 3u] + id.y * width + left;
+    var t = 0.0;
+    var m = 0.0;
+    var e = 0.0;
+    for (var i = 0u; i < count; i++) {
+        let c = columns[row + i];
+        let w = weights[offset + i];
+        t += c.x * w;
+        m += abs(c.x * w);
+        e += abs(w) * c.y;
+    }
+    resized[id.z * SIZE * SIZE + id.y * SIZE + id.x] =
+        vec2<f32>(t, 2.0 * f32(count) * EPS * m + 2.0 * e);
+}
+
+// DCT of each row of rounded pixels; the DCT matrix leads the weights
+@compute @workgroup_size(32, 8)
+fn dct_rows(@builtin(global_invocation_id) id: vec3<u32>) {
+    let base = id.z * SIZE * SIZE + id.y * SIZE;
+    var t = 0.0;
+    for (var x = 0u; x < SIZE; x++) {
+        t += weights[id.x * SIZE + x] * round(clamp(resized[base + x].x, 0.0, 255.0));
+    }
+    rows[base + id.x] = t;
+}
+
+// DCT of each column of the row transforms
+@compute @workgroup_size(32, 8)
+fn dct_columns(@builtin(global_invocation_id) id: vec3<u32>) {
+    let base = id.z * SIZE * SIZE;
+    var t = 0.0;
+    for (var y = 0u; y < SIZE; y++) {
+        t += weights[id.y * SIZE + y] * rows[base + y * SIZE + id.x];
+    }
+    coeffs[base + id.y * SIZE + id.x] = t;
+}
+"#;
+
+    /// An image waiting in the shared queue and where to send it back with
+    /// its output (`None` if it could not be dispatched)
+    type Queued = (GrayImage, mpsc::Sender<Option<(GrayImage, GpuOutput)>>);
+
+    pub(super) struct Gpu {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        layout: wgpu::BindGroupLayout,
+        passes: [wgpu::ComputePipeline; 4],
+        /// Largest buffer a binding may use
+        max_binding: u64,
+        shared: OnceLock<Option<Mutex<mpsc::Sender<Queued>>>>,
+    }
+
+    impl Gpu {
+        /// The process-wide device, set up on first use
+        pub(super) fn get() -> Option<&'static Gpu> {
+            static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+            GPU.get_or_init(|| pollster::block_on(Gpu::new())).as_ref()
+        }
+
+        async fn new() -> Option<Gpu> {
+            let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    ..Default::default()
+                })
+                .await?;
+            let limits = adapter.limits();
+            if limits.max_storage_buffers_per_shader_stage < BINDINGS
+                || limits.max_compute_invocations_per_workgroup < 256
+                || limits.max_compute_workgroups_per_dimension < GPU_BATCH_SIZE as u32
+            {
+                return None;
+            }
+            let (device, queue) = adapter
+                .request_device(
+                    &wgpu::DeviceDescriptor {
+                        label: Some("phash"),
+                        required_limits: limits.clone(),
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .await
+                .ok()?;
+
+            let entries: Vec<wgpu::BindGroupLayoutEntry> = (0..BINDINGS)
+                .map(|binding| wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: binding < 3,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                })
+                .collect();
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("phash"),
+                entries: &entries,
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("phash"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("phash"),
+                source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+            });
+            let passes = ["vertical", "horizontal", "dct_rows", "dct_columns"].map(|entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&pipeline_layout),
+                    module: &module,
+                    entry_point,
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+            });
+            if device.pop_error_scope().await.is_some() {
+                return None;
+            }
+
+            Some(Gpu {
+                device,
+                queue,
+                layout,
+                passes,
+                max_binding: (limits.max_storage_buffer_binding_size as u64)
+                    .min(limits.max_buffer_size),
+                shared: OnceLock::new(),
+            })
+        }
+
+        /// The queue that `phash_shared` batches through, started on first
+        /// use
+        pub(super) fn queue(&'static self) -> Option<&'static Mutex<mpsc::Sender<Queued>>> {
+            self.shared
+                .get_or_init(|| {
+                    let (sender, receiver) = mpsc::channel::<Queued>();
+                    std::thread::Builder::new()
+                        .name("phash-gpu".to_string())
+                        .spawn(move || {
+                            while let Ok(first) = receiver.recv() {
+                                let mut queued = vec![first];
+                                while queued.len() < GPU_BATCH_SIZE {
+                                    match receiver.recv_timeout(BATCH_WAIT) {
+                                        Ok(next) => queued.push(next),
+                                        Err(_) => break,
+                                    }
+                                }
+                                self.serve(queued);
+                            }
+                        })
+                        .ok()
+                        .map(|_| Mutex::new(sender))
+                })
+                .as_ref()
+        }
+
+        /// Run the queued images and answer each sender
+        fn serve(&self, queued: Vec<Queued>) {
+            let indices: Vec<usize> = (0..queued.len()).collect();
+            let mut outputs: Vec<Option<GpuOutput>> = queued.iter().map(|_| None).collect();
+            for chunk in self.chunks(&indices, |i| &queued[i].0) {
+                let batch: Vec<&GrayImage> = chunk.iter().map(|&i| &queued[i].0).collect();
+                for (&i, output) in chunk.iter().zip(self.run(&batch)) {
+                    outputs[i] = Some(output);
+                }
+            }
+            for ((luma, reply), output) in queued.into_iter().zip(outputs) {
+                let _ = reply.send(output.map(|output| (luma, output)));
+            }
+        }
+
+        /// Split `indices` into dispatches that fit the device's buffers
+        ///
+        /// Images too large for any dispatch are left out.
+        pub(super) fn chunks<'a, F>(&self, indices: &[usize], image: F) -> Vec<Vec<usize>>
+        where
+            F: Fn(usize) -> &'a GrayImage,
+        {
+            let (mut chunks, mut chunk): (Vec<Vec<usize>>, Vec<usize>) = (Vec::new(), Vec::new());
+            let (mut pixel_bytes, mut column_bytes) = (0u64, 0u64);
+            for &i in indices {
+                let (width, height) = image(i).dimensions();
+                let pixels = (width as u64 * height as u64).div_ceil(4) * 4;
+                let columns = width as u64 * DCT_SIZE as u64 * 8;
+                if pixels > self.max_binding || columns > self.max_binding {
+                    continue;
+                }
+                if chunk.len() == GPU_BATCH_SIZE
+                    || pixel_bytes + pixels > self.max_binding
+                    || column_bytes + columns > self.max_binding
+                {
+                    chunks.push(std::mem::take(&mut chunk));
+                    (pixel_bytes, column_bytes) = (0, 0);
+                }
+                chunk.push(i);
+                pixel_bytes += pixels;
+                column_bytes += columns;
+            }
+            if !chunk.is_empty() {
+                chunks.push(chunk);
+            }
+            chunks
+        }
+
+        /// Resize and transform one dispatch of images
+        pub(super) fn run(&self, images: &[&GrayImage]) -> Vec<GpuOutput> {
+            let n = images.len();
+            let plane = DCT_SIZE * DCT_SIZE;
+            let mut plan = vec![0u32; n * INFO_LEN];
+            let mut pixels: Vec<u32> = Vec::new();
+            let mut weights = dct_matrix();
+            let mut taps: HashMap<u32, u32> = HashMap::new();
+            let mut columns = 0u32;
+            let mut max_width = 0u32;
+
+            for (i, img) in images.iter().enumerate() {
+                let (width, height) = img.dimensions();
+                let mut taps_at = |size: u32| {
+                    *taps.entry(size).or_insert_with(|| {
+                        let start = plan.len() as u32;
+                        for (left, w) in lanczos3_taps(size) {
+                            plan.extend([left, w.len() as u32, weights.len() as u32]);
+                            weights.extend(w);
+                        }
+                        start
+                    })
+                };
+                let info = [
+                    pixels.len() as u32,
+                    width,
+                    height,
+                    columns,
+                    taps_at(height),
+                    taps_at(width),
+                ];
+                plan[i * INFO_LEN..(i + 1) * INFO_LEN].copy_from_slice(&info);
+                pixels.extend(img.as_raw().chunks(4).map(|bytes| {
+                    let mut word = [0u8; 4];
+                    word[..bytes.len()].copy_from_slice(bytes);
+                    u32::from_le_bytes(word)
+                }));
+                columns += width * DCT_SIZE as u32;
+                max_width = max_width.max(width);
+            }
+
+            let input = |label: &str, contents: Vec<u8>| {
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(label),
+                        contents: &contents,
+                        usage: wgpu::BufferUsages::STORAGE,
+                    })
+            };
+            let output = |label: &str, size: usize, usage: wgpu::BufferUsages| {
+                self.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size: size as u64,
+                    usage,
+                    mapped_at_creation: false,
+                })
+            };
+            let storage = wgpu::BufferUsages::STORAGE;
+            let copied = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC;
+            let resized_size = n * plane * 8;
+            let coeffs_size = n * plane * 4;
+            let buffers = [
+                input("plan", plan.iter().flat_map(|v| v.to_le_bytes()).collect()),
+                input(
+                    "pixels",
+                    pixels.iter().flat_map(|v| v.to_le_bytes()).collect(),
+                ),
+                input(
+                    "weights",
+                    weights.iter().flat_map(|v| v.to_le_bytes()).collect(),
+                ),
+                output("columns", columns as usize * 8, storage),
+                output("resized", resized_size, copied),
+                output("rows", coeffs_size, storage),
+                output("coeffs", coeffs_size, copied),
+            ];
+            let readback = output(
+                "readback",
+                resized_size + coeffs_size,
+                wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            );
+            let entries: Vec<wgpu::BindGroupEntry> = buffers
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect();
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("phash"),
+                layout: &self.layout,
+                entries: &entries,
+            });
+
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_bind_group(0, &bind_group, &[]);
+                let groups = [
+                    (max_width.div_ceil(64), DCT_SIZE as u32),
+                    (1, DCT_SIZE as u32 / 8),
+                    (1, DCT_SIZE as u32 / 8),
+                    (1, DCT_SIZE as u32 / 8),
+                ];
+                for (pipeline, (x, y)) in self.passes.iter().zip(groups) {
+                    pass.set_pipeline(pipeline);
+                    pass.dispatch_workgroups(x, y, n as u32);
+                }
+            }
+            encoder.copy_buffer_to_buffer(&buffers[4], 0, &readback, 0, resized_size as u64);
+            encoder.copy_buffer_to_buffer(
+                &buffers[6],
+                0,
+                &readback,
+                resized_size as u64,
+                coeffs_size as u64,
+            );
+            self.queue.submit([encoder.finish()]);
+
+            let (sender, receiver) = mpsc::channel();
+            let slice = readback.slice(..);
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            let _ = self.device.poll(wgpu::Maintain::Wait);
+            if !matches!(receiver.recv(), Ok(Ok(()))) {
+                return Vec::new();
+            }
+
+            let data = slice.get_mapped_range();
+            let floats: Vec<f32> = data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            let (resized, coeffs) = floats.split_at(n * plane * 2);
+            (0..n)
+                .map(|i| GpuOutput {
+                    resized: resized[i * plane * 2..(i + 1) * plane * 2]
+                        .chunks_exact(2)
+                        .map(|v| [v[0], v[1]])
+                        .collect(),
+                    coeffs: coeffs[i * plane..(i + 1) * plane].to_vec(),
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+
+    fn textured(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(ImageBuffer::from_fn(width, height, |x, y| {
+            let (u, v) = (x as f64 / width as f64, y as f64 / height as f64);
+            let smooth = 50.0 * (7.0 * u + 3.0 * v).sin() + 40.0 * (9.0 * v - 4.0 * u).cos();
+            Luma([(120.0 + smooth + ((x * 7 + y * 13) % 16) as f64) as u8])
+        }))
+    }
+
+    /// The GPU pipeline evaluated on the CPU in f32, with the same taps
+    /// and summation order as the shaders
+    fn emulate(luma: &GrayImage) -> GpuOutput {
+        let (width, height) = luma.dimensions();
+        let size = DCT_SIZE;
+        let mut columns = vec![0f32; width as usize * size];
+        for (y, (left, weights)) in lanczos3_taps(height).into_iter().enumerate() {
+            for x in 0..width {
+                let mut t = 0.0;
+                for (i, w) in weights.iter().enumerate() {
+                    t += luma.get_pixel(x, left + i as u32).0[0] as f32 * w;
+                }
+                columns[y * width as usize + x as usize] = t;
+            }
+        }
+        let mut resized = vec![[0f32; 2]; size * size];
+        for (x, (left, weights)) in lanczos3_taps(width).into_iter().enumerate() {
+            for y in 0..size {
+                let mut t = 0.0;
+                for (i, w) in weights.iter().enumerate() {
+                    t += columns[y * width as usize + left as usize + i] * w;
+                }
+                resized[y * size + x] = [t, 0.0];
+            }
+        }
+        let matrix = dct_matrix();
+        let pixels: Vec<f32> = resized
+            .iter()
+            .map(|p| p[0].clamp(0.0, 255.0).round_ties_even())
+            .collect();
+        let mut rows = vec![0f32; size * size];
+        let mut coeffs = vec![0f32; size * size];
+        for y in 0..size {
+            for u in 0..size {
+                rows[y * size + u] = (0..size)
+                    .map(|x| matrix[u * size + x] * pixels[y * size + x])
+                    .sum();
+            }
+        }
+        for v in 0..size {
+            for u in 0..size {
+                coeffs[v * size + u] = (0..size)
+                    .map(|y| matrix[v * size + y] * rows[y * size + u])
+                    .sum();
+            }
+        }
+        GpuOutput { resized, coeffs }
+    }
+
+    #[test]
+    fn test_taps_match_image_resize() {
+        for (width, height) in [(64, 48), (37, 200), (20, 20), (640, 31)] {
+            let img = textured(width, height);
+            let expected = img
+                .resize_exact(32, 32, image::imageops::FilterType::Lanczos3)
+                .to_luma8()
+                .into_raw();
+            let luma = gpu_luma(&img).unwrap();
+            let output = emulate(&luma);
+            let rounded: Vec<u8> = output
+                .resized
+                .iter()
+                .map(|p| p[0].clamp(0.0, 255.0).round() as u8)
+                .collect();
+            assert_eq!(rounded, expected, "{}x{}", width, height);
+
+            let (rows, columns) = (lanczos3_taps(height), lanczos3_taps(width));
+            for (y, x) in [(0, 0), (5, 17), (31, 31)] {
+                let pixel = resample_pixel(&luma, &rows[y], &columns[x]);
+                assert_eq!(pixel, expected[y * DCT_SIZE + x] as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gpu_output_hashes_equal_cpu() {
+        for (width, height) in [(64, 48), (37, 200), (300, 90), (640, 31)] {
+            let img = textured(width, height);
+            let cpu = ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+            let luma = gpu_luma(&img).unwrap();
+            let mut output = emulate(&luma);
+            let hash = output.phash(&luma, 8);
+            assert_eq!(hash, cpu);
+            assert_eq!(hash.algorithm(), Some(HashAlgorithm::PHash));
+
+            // Pixels off by more than a level, within their bounds: all are
+            // recomputed and the DCT redone
+            for pixel in output.resized.iter_mut() {
+                *pixel = [pixel[0] + 1.2, 1.5];
+            }
+            assert_eq!(output.phash(&luma, 8), cpu);
+        }
+    }
+
+    #[test]
+    fn test_batch_matches_single_hashes() {
+        let images = vec![textured(64, 48), textured(32, 32), textured(90, 300)];
+        let hashes = phash_batch(&images, 8).unwrap();
+        for (img, hash) in images.iter().zip(&hashes) {
+            assert_eq!(
+                hash,
+                &ImageHash::from_image(img, HashAlgorithm::PHash, 8).unwrap()
+            );
+        }
+        assert!(phash_batch(&images, 64).is_err());
+        assert_eq!("GPU".parse::<HashBackend>().unwrap(), HashBackend::Gpu);
+        assert_eq!(HashBackend::Auto.to_string(), "auto");
+    }
+
+    /// The tests above check the pipeline through `emulate`; this is the
+    /// only test of the WGSL shaders themselves, and it is skipped on
+    /// machines without a usable GPU, where they go untested
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_shaders_match_cpu() {
+        if !gpu_available() {
+            eprintln!("test_shaders_match_cpu skipped: no usable GPU adapter");
+            return;
+        }
+        let gpu = gpu::Gpu::get().unwrap();
+        let images = [textured(64, 48), textured(37, 200), textured(640, 31)];
+        let lumas: Vec<GrayImage> = images.iter().map(|img| gpu_luma(img).unwrap()).collect();
+        let batch: Vec<&GrayImage> = lumas.iter().collect();
+        for ((img, luma), output) in images.iter().zip(&lumas).zip(gpu.run(&batch)) {
+            let expected = emulate(luma);
+            for (gpu, cpu) in output.resized.iter().zip(&expected.resized) {
+                assert!(
+                    (gpu[0] - cpu[0]).abs() <= gpu[1] + 1e-3,
+                    "{:?} {:?}",
+                    gpu,
+                    cpu
+                );
+            }
+            assert_eq!(
+                output.phash(luma, 8),
+                ImageHash::from_image(img, HashAlgorithm::PHash, 8).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Benchmark Module
+//!
+//! Synthetic hash corpora and timings for the hash index and duplicate
+//! grouping, to get capacity numbers before a deployment has real
+//! collections. Corpora are drawn from `SeededRng`, so a spec always
+//! produces the same hashes and runs can be compared across machines.
+
+use crate::duplicate::group_hashes;
+use crate::hash::ImageHash;
+use crate::index::BkTree;
+use crate::rng::{SeededRng, DEFAULT_SEED};
+use crate::threads::with_threads;
+use rayon::prelude::*;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// How synthetic hashes are spread over the hash space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
+    /// Independent random hashes; almost none are near each other
+    Uniform,
+    /// Clusters of `cluster_size` near-duplicates, each at most `max_flips`
+    /// bits away from its cluster's first hash
+    Clustered { cluster_size: usize, max_flips: u32 },
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    /// Parse `"uniform"`, `"clustered"` or `"clustered:<size>:<flips>"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        let mut parts = name.split(':');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("uniform"), None, None, None) => Ok(Distribution::Uniform),
+            (Some("clustered"), None, None, None) => Ok(CorpusSpec::default().distribution),
+            (Some("clustered"), Some(size), Some(flips), None) => {
+                let cluster_size = size
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid cluster size: {}", size))?;
+                let max_flips = flips
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid bit flip count: {}", flips))?;
+                Ok(Distribution::Clustered {
+                    cluster_size,
+                    max_flips,
+                })
+            }
+            _ => Err(format!("Unknown distribution: {}", s)),
+        }
+    }
+}
+
+/// Shape of a synthetic corpus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusSpec {
+    /// Number of hashes
+    pub size: usize,
+    /// Hash size as for `ImageHash` (8 = 64-bit hashes)
+    pub hash_size: usize,
+    pub distribution: Distribution,
+    pub seed: u64,
+}
+
+impl Default for CorpusSpec {
+    fn default() -> Self {
+        Self {
+            size: 100_000,
+            hash_size: 8,
+            distribution: Distribution::Clustered {
+                cluster_size: 4,
+                max_flips: 4,
+            },
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+/// Generate the hashes described by `spec`, in random order
+pub fn synthetic_corpus(spec: &CorpusSpec) -> Vec<ImageHash> {
+    let mut rng = SeededRng::new(spec.seed);
+    let bits = spec.hash_size * spec.hash_size;
+    let bytes = bits.div_ceil(8);
+    let random =
+        |rng: &mut SeededRng| -> Vec<u8> { (0..bytes).map(|_| rng.next_u64() as u8).collect() };
+
+    let mut corpus: Vec<Vec<u8>> = Vec::with_capacity(spec.size);
+    match spec.distribution {
+        Distribution::Uniform => {
+            while corpus.len() < spec.size {
+                corpus.push(random(&mut rng));
+            }
+        }
+        Distribution::Clustered {
+            cluster_size,
+            max_flips,
+        } => {
+            while corpus.len() < spec.size {
+                let base = random(&mut rng);
+                for _ in 1..cluster_size.min(spec.size - corpus.len()) {
+                    let mut variant = base.clone();
+                    let flips = rng.below(max_flips as u64 + 1) as usize;
+                    for bit in rng.sample_indices(bits, flips) {
+                        variant[bit / 8] ^= 0x80 >> (bit % 8);
+                    }
+                    corpus.push(variant);
+                }
+                corpus.push(base);
+            }
+        }
+    }
+
+    // Insertion order shapes the BK-tree, so don't insert clusters together
+    rng.shuffle(&mut corpus);
+    corpus
+        .into_iter()
+        .map(|bits| ImageHash::from_raw(bits, spec.hash_size))
+        .collect()
+}
+
+/// Timings and sizes from one benchmark run
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// Hashes in the corpus
+    pub corpus_size: usize,
+    /// Worker threads used for queries and grouping
+    pub threads: usize,
+    /// Time to insert the corpus into a BK-tree
+    pub build_secs: f64,
+    pub inserts_per_sec: f64,
+    /// Threshold queries run against the built index
+    pub queries: usize,
+    pub query_secs: f64,
+    pub queries_per_sec: f64,
+    /// Average number of hashes returned per query
+    pub mean_matches: f64,
+    /// Time to group the whole corpus, as `find_duplicates` does
+    pub group_secs: f64,
+    /// Time to group it again on a single thread
+    pub group_secs_single: f64,
+    /// `group_secs_single / group_secs`: how much the worker threads speed
+    /// up grouping
+    pub group_speedup: f64,
+    /// Groups with more than one member
+    pub duplicate_groups: usize,
+    /// Approximate heap memory of the built index
+    pub index_bytes: usize,
+    /// Peak resident memory of the process, where the OS reports it
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Benchmark index construction, `queries` threshold queries and grouping
+///
+/// Queries are corpus members picked at random and run in parallel, so
+/// `queries_per_sec` is the throughput of all worker threads together.
+/// Grouping runs on all worker threads and then on one, for the speedup.
+pub fn run_benchmark(spec: &CorpusSpec, threshold: u32, queries: usize) -> BenchReport {
+    let corpus = synthetic_corpus(spec);
+    let query_hashes: Vec<&ImageHash> = SeededRng::new(spec.seed ^ 0x51)
+        .sample_indices(corpus.len(), queries)
+        .into_iter()
+        .map(|i| &corpus[i])
+        .collect();
+
+    let start = Instant::now();
+    let mut index = BkTree::new();
+    for (i, hash) in corpus.iter().enumerate() {
+        index.add(hash.clone(), i);
+    }
+    let build_secs = start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    let matches: usize = query_hashes
+        .par_iter()
+        .map(|hash| index.query(hash, threshold).len())
+        .sum();
+    let query_secs = start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    let groups = group_hashes(&corpus, threshold);
+    let group_secs = start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    with_threads(Some(1), || group_hashes(&corpus, threshold))
+        .expect("one thread is a valid pool size");
+    let group_secs_single = start.elapsed().as_secs_f64();
+
+    let rate = |n: usize, secs: f64| if secs > 0.0 { n as f64 / secs } else { 0.0 };
+    BenchReport {
+        corpus_size: corpus.len(),
+        threads: rayon::current_num_threads(),
+        build_secs,
+        inserts_per_sec: rate(corpus.len(), build_secs),
+        queries: query_hashes.len(),
+        query_secs,
+        queries_per_sec: rate(query_hashes.len(), query_secs),
+        mean_matches: if query_hashes.is_empty() {
+            0.0
+        } else {
+            matches as f64 / query_hashes.len() as f64
+        },
+        group_secs,
+        group_secs_single,
+        group_speedup: if group_secs > 0.0 {
+            group_secs_single / group_secs
+        } else {
+            0.0
+        },
+        duplicate_groups: groups.iter().filter(|g| g.paths.len() > 1).count(),
+        index_bytes: index.heap_bytes(),
+        peak_rss_bytes: peak_rss_bytes(),
+    }
+}
+
+/// Peak resident set size from /proc (Linux only)
+fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clustered_corpus_groups() {
+        let spec = CorpusSpec {
+            size: 200,
+            hash_size: 8,
+            distribution: "clustered:4:3".parse().unwrap(),
+            seed: 7,
+        };
+        let corpus = synthetic_corpus(&spec);
+        assert_eq!(corpus.len(), 200);
+        assert_eq!(corpus, synthetic_corpus(&spec));
+
+        // Variants are within 3 bits of their base, so within 6 of each other;
+        // random 64-bit bases are far apart
+        let report = run_benchmark(&spec, 6, 50);
+        assert_eq!(report.duplicate_groups, 50);
+        assert_eq!(report.queries, 50);
+        assert!(report.mean_matches >= 1.0);
+        assert!(report.index_bytes > 0);
+        assert!(report.group_secs_single > 0.0 && report.group_speedup > 0.0);
+    }
+}
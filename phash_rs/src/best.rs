@@ -0,0 +1,177 @@
+//! Best Image Module
+//!
+//! Strategies for choosing which member of a duplicate group to keep.
+//! Grouping picks the highest resolution; archives of originals often want
+//! the largest file, the oldest copy, a lossless format or the copy that
+//! looks best instead.
+
+use crate::decode::normalize_ext;
+use crate::quality::score_image;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Formats ranked by `BestBy::FormatPriority` without an explicit list:
+/// lossless before lossy
+pub const DEFAULT_FORMAT_PRIORITY: [&str; 9] = [
+    "png", "tiff", "tif", "bmp", "webp", "heic", "heif", "jpg", "jpeg",
+];
+
+/// How the best member of a duplicate group is chosen
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum BestBy {
+    /// Highest width x height
+    #[default]
+    Resolution,
+    /// Largest file on disk
+    FileSize,
+    /// Most recently modified
+    Newest,
+    /// Least recently modified
+    Oldest,
+    /// First extension in this list (lowercase, no dot); unlisted
+    /// extensions rank last
+    FormatPriority(Vec<String>),
+    /// Highest `QualityScore::score`: sharp, not upscaled, lightly
+    /// compressed. Decodes every member again.
+    Quality,
+}
+
+impl FromStr for BestBy {
+    type Err = String;
+
+    /// Parse "resolution", "filesize", "newest", "oldest", "quality",
+    /// "format_priority" or "format_priority:png,jpg,..."
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.split_once(':') {
+            None => match s.as_str() {
+                "resolution" => Ok(BestBy::Resolution),
+                "filesize" => Ok(BestBy::FileSize),
+                "newest" => Ok(BestBy::Newest),
+                "oldest" => Ok(BestBy::Oldest),
+                "quality" => Ok(BestBy::Quality),
+                "format_priority" => Ok(BestBy::FormatPriority(
+                    DEFAULT_FORMAT_PRIORITY
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect(),
+                )),
+                _ => Err(format!("Unknown best_by strategy: {}", s)),
+            },
+            Some(("format_priority", list)) => {
+                let formats: Vec<String> = list
+                    .split(',')
+                    .map(normalize_ext)
+                    .filter(|e| !e.is_empty())
+                    .collect();
+                if formats.is_empty() {
+                    return Err("format_priority needs at least one extension".to_string());
+                }
+                Ok(BestBy::FormatPriority(formats))
+            }
+            _ => Err(format!("Unknown best_by strategy: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for BestBy {
+    /// Canonical name, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BestBy::Resolution => write!(f, "resolution"),
+            BestBy::FileSize => write!(f, "filesize"),
+            BestBy::Newest => write!(f, "newest"),
+            BestBy::Oldest => write!(f, "oldest"),
+            BestBy::Quality => write!(f, "quality"),
+            BestBy::FormatPriority(formats) => {
+                write!(f, "format_priority:{}", formats.join(","))
+            }
+        }
+    }
+}
+
+impl BestBy {
+    /// Pick the best of `paths` by this strategy
+    ///
+    /// `current` is the highest-resolution member, chosen during grouping;
+    /// it is returned for `Resolution` and wins ties otherwise, so the
+    /// choice is deterministic. Files that cannot be stat'ed rank last.
+    pub fn pick<'a>(&self, paths: &'a [PathBuf], current: &'a Path) -> &'a Path {
+        if *self == BestBy::Resolution {
+            return current;
+        }
+        paths
+            .iter()
+            .map(|p| (self.score(p), p == current, p.as_path()))
+            // Reversed so the first path wins remaining ties
+            .rev()
+            .max_by_key(|&(score, is_current, _)| (score, is_current))
+            .map_or(current, |(_, _, path)| path)
+    }
+
+    /// Higher is better; `None` ranks below every value
+    fn score(&self, path: &Path) -> Option<i128> {
+        let mtime = || {
+            let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+            let nanos = match modified.duration_since(SystemTime::UNIX_EPOCH) {
+                Ok(d) => d.as_nanos() as i128,
+                Err(e) => -(e.duration().as_nanos() as i128),
+            };
+            Some(nanos)
+        };
+        match self {
+            BestBy::Resolution => Some(0),
+            BestBy::FileSize => std::fs::metadata(path).ok().map(|m| m.len() as i128),
+            BestBy::Newest => mtime(),
+            BestBy::Oldest => mtime().map(|t| -t),
+            BestBy::FormatPriority(formats) => {
+                let ext = normalize_ext(&path.extension()?.to_string_lossy());
+                let rank = formats.iter().position(|f| *f == ext)?;
+                Some(-(rank as i128))
+            }
+            // Scores are compared to a millionth
+            BestBy::Quality => score_image(path)
+                .ok()
+                .map(|q| (q.score() * 1e6).round() as i128),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_pick() {
+        for s in ["resolution", "filesize", "newest", "oldest", "quality"] {
+            assert_eq!(s.parse::<BestBy>().unwrap().to_string(), s);
+        }
+        let by: BestBy = "format_priority:PNG, .webp".parse().unwrap();
+        assert_eq!(
+            by,
+            BestBy::FormatPriority(vec!["png".into(), "webp".into()])
+        );
+        assert_eq!(by.to_string().parse::<BestBy>(), Ok(by.clone()));
+        assert!("largest".parse::<BestBy>().is_err());
+        assert!("format_priority:".parse::<BestBy>().is_err());
+
+        // Format ranking needs no files on disk
+        let paths: Vec<PathBuf> = ["a.jpg", "b.webp", "c.png", "d.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(by.pick(&paths, Path::new("a.jpg")), Path::new("c.png"));
+        assert_eq!(by.pick(&paths, Path::new("d.png")), Path::new("d.png"));
+        assert_eq!(
+            BestBy::Resolution.pick(&paths, Path::new("a.jpg")),
+            Path::new("a.jpg")
+        );
+        // Nothing can be stat'ed, so the resolution pick stands
+        assert_eq!(
+            BestBy::FileSize.pick(&paths, Path::new("b.webp")),
+            Path::new("b.webp")
+        );
+    }
+}
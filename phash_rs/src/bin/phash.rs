@@ -0,0 +1,277 @@
+//! `phash` command line tool
+//!
+//! Hashes images, compares two images and lists duplicate groups in a
+//! directory without Python. Build with the `cli` feature.
+//!
+//! Exit status is 0 on success, 2 on errors; `compare` exits with 1 when
+//! the images are not similar, like `cmp`.
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use phash_rs::{
+    find_duplicates_with, init_logging, regroup, render_results, scan_directory, with_threads,
+    Grouping, HashAlgorithm, HashOptions, ImageHash, LogLevel, ResultsFormat, ScanMetadata,
+    ScanOptions, ScanResults, DEFAULT_HASH_SIZE,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn cli() -> Command {
+    let algorithm = Arg::new("algorithm")
+        .long("algorithm")
+        .short('a')
+        .value_name("NAME")
+        .help("phash, whash, whash:<level>, colorhash, blockhash, marrhildreth or radialvariance")
+        .default_value("phash")
+        .value_parser(|s: &str| s.parse::<HashAlgorithm>());
+    let hash_size = Arg::new("hash-size")
+        .long("hash-size")
+        .value_name("N")
+        .help("Hash size; 8 gives 64-bit hashes")
+        .default_value("8")
+        .value_parser(value_parser!(usize));
+    let threshold = Arg::new("threshold")
+        .long("threshold")
+        .short('t')
+        .value_name("BITS")
+        .help("Maximum Hamming distance for a match")
+        .default_value("10")
+        .value_parser(value_parser!(u32));
+
+    Command::new("phash")
+        .about("Perceptual image hashing and duplicate detection")
+        .version(env!("CARGO_PKG_VERSION"))
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .global(true)
+                .value_name("LEVEL")
+                .help("Log scan progress and timings to stderr: error, warn, info, debug or trace")
+                .value_parser(|s: &str| s.parse::<LogLevel>()),
+        )
+        .subcommand(
+            Command::new("hash")
+                .about("Print the hash of each image")
+                .arg(
+                    Arg::new("files")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .args([algorithm.clone(), hash_size.clone()]),
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Print the distance between two images; exit 1 if not similar")
+                .arg(
+                    Arg::new("a")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("b")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .args([algorithm.clone(), hash_size.clone(), threshold.clone()]),
+        )
+        .subcommand(
+            Command::new("dedupe")
+                .about("List groups of duplicate images in a directory (nothing is changed)")
+                .arg(
+                    Arg::new("dir")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .args([algorithm, hash_size, threshold])
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Print groups as JSON, as export_results writes them"),
+                )
+                .arg(
+                    Arg::new("grouping")
+                        .long("grouping")
+                        .value_name("MODE")
+                        .help(
+                            "connected (chains of matches), clique (all members match) \
+                             or medoid (all members match a center)",
+                        )
+                        .default_value("connected")
+                        .value_parser(|s: &str| s.parse::<Grouping>()),
+                )
+                .arg(
+                    Arg::new("threads")
+                        .long("threads")
+                        .short('j')
+                        .value_name("N")
+                        .help("Hash with N threads (default: one per core)")
+                        .value_parser(value_parser!(usize)),
+                ),
+        )
+}
+
+/// Hashing settings shared by all subcommands
+fn settings(args: &ArgMatches) -> (HashAlgorithm, usize) {
+    (
+        *args.get_one("algorithm").unwrap_or(&HashAlgorithm::PHash),
+        *args.get_one("hash-size").unwrap_or(&DEFAULT_HASH_SIZE),
+    )
+}
+
+fn hash(args: &ArgMatches) -> Result<ExitCode, String> {
+    let (algorithm, hash_size) = settings(args);
+    algorithm
+        .check_hash_size(hash_size)
+        .map_err(|e| e.to_string())?;
+    let mut status = ExitCode::SUCCESS;
+    for path in args.get_many::<PathBuf>("files").into_iter().flatten() {
+        match ImageHash::from_path(path, algorithm, hash_size) {
+            Ok(hash) => println!("{}  {}", hash.to_hex(), path.display()),
+            Err(e) => {
+                eprintln!("phash: {}: {}", path.display(), e);
+                status = ExitCode::from(2);
+            }
+        }
+    }
+    Ok(status)
+}
+
+fn compare(args: &ArgMatches) -> Result<ExitCode, String> {
+    let (algorithm, hash_size) = settings(args);
+    let threshold = *args.get_one::<u32>("threshold").unwrap_or(&10);
+    let hash = |name: &str| {
+        let path = args.get_one::<PathBuf>(name).ok_or("Missing path")?;
+        ImageHash::from_path(path, algorithm, hash_size)
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    let distance = hash("a")?.distance(&hash("b")?);
+    let similar = distance <= threshold;
+    println!(
+        "{} ({})",
+        distance,
+        if similar { "similar" } else { "different" }
+    );
+    Ok(if similar {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+fn dedupe(args: &ArgMatches) -> Result<ExitCode, String> {
+    let (algorithm, hash_size) = settings(args);
+    let threshold = *args.get_one::<u32>("threshold").unwrap_or(&10);
+    let dir = args.get_one::<PathBuf>("dir").ok_or("Missing directory")?;
+
+    let threads = args.get_one::<usize>("threads").copied();
+    let grouping = *args
+        .get_one::<Grouping>("grouping")
+        .unwrap_or(&Grouping::Connected);
+
+    let paths = scan_directory(dir, &ScanOptions::default())?;
+    let (mut groups, failures) = with_threads(threads, || {
+        find_duplicates_with(
+            &paths,
+            algorithm,
+            hash_size,
+            threshold,
+            false,
+            &HashOptions::default(),
+            None,
+            None,
+        )
+    })??;
+    groups = regroup(groups, grouping, threshold);
+    groups.retain(|g| g.paths.len() > 1);
+    for (path, e) in &failures {
+        eprintln!("phash: {}: {}", path.display(), e);
+    }
+
+    if args.get_flag("json") {
+        let results = ScanResults {
+            metadata: ScanMetadata {
+                algorithm: Some(algorithm),
+                hash_size: Some(hash_size),
+                threshold: Some(threshold),
+                root: None,
+                created: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .ok(),
+            },
+            groups,
+        };
+        print!("{}", render_results(&results, ResultsFormat::Json));
+    } else {
+        // Best copy first and starred, one blank line between groups
+        for g in &groups {
+            println!("* {}", g.best_path.display());
+            for path in g.paths.iter().filter(|p| **p != g.best_path) {
+                println!("  {}", path.display());
+            }
+            println!();
+        }
+        eprintln!(
+            "{} images, {} duplicate groups, {} unreadable",
+            paths.len(),
+            groups.len(),
+            failures.len()
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let matches = cli().get_matches();
+    if let Some(&level) = matches.get_one::<LogLevel>("log-level") {
+        if let Err(e) = init_logging(level, None) {
+            eprintln!("phash: {}", e);
+        }
+    }
+    let result = match matches.subcommand() {
+        Some(("hash", args)) => hash(args),
+        Some(("compare", args)) => compare(args),
+        Some(("dedupe", args)) => dedupe(args),
+        _ => unreachable!("a subcommand is required"),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("phash: {}", e);
+        ExitCode::from(2)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_definition() {
+        cli().debug_assert();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dedupe_non_utf8_dir() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+        let mut name = format!("phash_cli_{}_", std::process::id()).into_bytes();
+        name.push(0xff);
+        let dir = std::env::temp_dir().join(OsString::from_vec(name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let img = image::DynamicImage::new_rgb8(16, 16);
+        img.save(dir.join("a.png")).unwrap();
+        img.save(dir.join("b.png")).unwrap();
+
+        let matches = cli()
+            .try_get_matches_from([OsString::from("phash"), "dedupe".into(), dir.clone().into()])
+            .unwrap();
+        let (_, args) = matches.subcommand().unwrap();
+        assert_eq!(args.get_one::<PathBuf>("dir"), Some(&dir));
+        assert_eq!(dedupe(args).unwrap(), ExitCode::SUCCESS);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+//! Blocklist Module
+//!
+//! Known-image filtering for moderation pipelines: a set of banned image
+//! hashes, each with an identifier, that uploads are checked against. A
+//! missed match lets a banned image through, so checks lean towards
+//! recall: lookups in the BK-tree are exact rather than approximate,
+//! images are also tried in every rotation and mirror image, and a hash
+//! that cannot be compared with the list is an error rather than a miss.
+
+use crate::decode::open_image;
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, ImageHash, DIHEDRAL_TRANSFORMS};
+use crate::index::BkTree;
+use image::DynamicImage;
+use std::path::Path;
+
+/// Names of the transforms of `ImageHash::dihedral_from_image`, in order
+///
+/// Each is applied to the checked image: a match under "rotate90" means
+/// the image turned 90 degrees clockwise looks like the banned one.
+pub const TRANSFORMS: [&str; DIHEDRAL_TRANSFORMS] = [
+    "none",
+    "rotate90",
+    "rotate180",
+    "rotate270",
+    "mirror",
+    "mirror_rotate90",
+    "mirror_rotate180",
+    "mirror_rotate270",
+];
+
+/// One banned image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlocklistEntry {
+    /// Identifier reported on a match, e.g. a case number
+    pub id: String,
+    pub hash: ImageHash,
+}
+
+/// The blocklist entry closest to a checked image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlocklistMatch {
+    pub id: String,
+    /// Hash of the entry
+    pub hash: ImageHash,
+    pub distance: u32,
+    /// Transform of the checked image that matched, one of `TRANSFORMS`;
+    /// always "none" for hashes
+    pub transform: &'static str,
+}
+
+/// Banned image hashes of one algorithm and hash size
+#[derive(Debug, Clone)]
+pub struct Blocklist {
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    entries: Vec<BlocklistEntry>,
+    /// Index into `entries` by hash
+    tree: BkTree<usize>,
+}
+
+impl Blocklist {
+    /// An empty blocklist for hashes computed with `algorithm` at
+    /// `hash_size`
+    pub fn new(algorithm: HashAlgorithm, hash_size: usize) -> Result<Self, HashError> {
+        algorithm.check_hash_size(hash_size)?;
+        Ok(Self {
+            algorithm,
+            hash_size,
+            entries: Vec::new(),
+            tree: BkTree::new(),
+        })
+    }
+
+    /// Read a blocklist from a text file of one entry per line
+    ///
+    /// Each line holds a hash, as hex or tagged as by
+    /// `ImageHash::to_tagged`, optionally followed by whitespace and the
+    /// entry's identifier; without one the hash text is the identifier.
+    /// Blank lines and lines starting with `#` are skipped. Fails on the
+    /// first line that does not parse, naming it.
+    pub fn load(path: &Path, algorithm: HashAlgorithm, hash_size: usize) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text, algorithm, hash_size).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str, algorithm: HashAlgorithm, hash_size: usize) -> Result<Self, HashError> {
+        let mut blocklist = Self::new(algorithm, hash_size)?;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (hash_text, id) = match line.split_once(char::is_whitespace) {
+                Some((hash, id)) => (hash, id.trim()),
+                None => (line, line),
+            };
+            parse_hash(hash_text)
+                .and_then(|hash| blocklist.add(id, hash))
+                .map_err(|e| HashError::InvalidInput(format!("line {}: {}", number + 1, e)))?;
+        }
+        Ok(blocklist)
+    }
+
+    /// Algorithm the entries are computed with
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Hash size the entries are computed with
+    pub fn hash_size(&self) -> usize {
+        self.hash_size
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the blocklist has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries in the order they were added
+    pub fn entries(&self) -> &[BlocklistEntry] {
+        &self.entries
+    }
+
+    /// Add a banned hash under `id`
+    ///
+    /// Fails if the hash cannot be compared with the entries already
+    /// present or comes from another algorithm.
+    pub fn add(&mut self, id: impl Into<String>, hash: ImageHash) -> Result<(), HashError> {
+        self.comparable(&hash)?;
+        self.tree.add(hash.clone(), self.entries.len());
+        self.entries.push(BlocklistEntry {
+            id: id.into(),
+            hash,
+        });
+        Ok(())
+    }
+
+    /// The entry closest to `hash`, if one is within `threshold`
+    ///
+    /// Ties go to the entry added first. Fails if `hash` cannot be
+    /// compared with the entries, since a silent miss would let the image
+    /// through.
+    pub fn check_hash(
+        &self,
+        hash: &ImageHash,
+        threshold: u32,
+    ) -> Result<Option<BlocklistMatch>, HashError> {
+        self.comparable(hash)?;
+        Ok(self.closest(hash, threshold, TRANSFORMS[0]))
+    }
+
+    /// The entry closest to `img`, if one is within `threshold`
+    ///
+    /// With `rotations`, the image is hashed in all eight rotations and
+    /// mirror images and the closest match of any counts, at about eight
+    /// times the cost of one hash.
+    pub fn check_image(
+        &self,
+        img: &DynamicImage,
+        threshold: u32,
+        rotations: bool,
+    ) -> Result<Option<BlocklistMatch>, HashError> {
+        let variants = if rotations {
+            ImageHash::dihedral_from_image(img, self.algorithm, self.hash_size)?.dihedral_variants()
+        } else {
+            vec![ImageHash::from_image(img, self.algorithm, self.hash_size)?]
+        };
+        self.comparable(&variants[0])?;
+        Ok(variants
+            .iter()
+            .zip(TRANSFORMS)
+            .filter_map(|(hash, transform)| self.closest(hash, threshold, transform))
+            .min_by_key(|m| m.distance))
+    }
+
+    /// Decode the image at `path` and run `check_image`
+    pub fn check_path(
+        &self,
+        path: &Path,
+        threshold: u32,
+        rotations: bool,
+    ) -> Result<Option<BlocklistMatch>, HashError> {
+        self.check_image(&open_image(path)?, threshold, rotations)
+    }
+
+    /// Closest entry within `threshold` of `hash`, lowest index on ties
+    fn closest(
+        &self,
+        hash: &ImageHash,
+        threshold: u32,
+        transform: &'static str,
+    ) -> Option<BlocklistMatch> {
+        let (&index, distance) = self
+            .tree
+            .query(hash, threshold)
+            .into_iter()
+            .min_by_key(|&(&index, distance)| (distance, index))?;
+        let entry = &self.entries[index];
+        Some(BlocklistMatch {
+            id: entry.id.clone(),
+            hash: entry.hash.clone(),
+            distance,
+            transform,
+        })
+    }
+
+    /// Fail unless `hash` can be compared with the entries
+    fn comparable(&self, hash: &ImageHash) -> Result<(), HashError> {
+        if let Some(algorithm) = hash.algorithm().filter(|a| *a != self.algorithm) {
+            return Err(HashError::InvalidInput(format!(
+                "Blocklist holds {} hashes, got a {} hash",
+                self.algorithm, algorithm
+            )));
+        }
+        match self.entries.first() {
+            Some(first) if first.hash.bit_len() != hash.bit_len() => {
+                Err(HashError::InvalidInput(format!(
+                    "Blocklist holds {}-bit hashes, got a {}-bit hash",
+                    first.hash.bit_len(),
+                    hash.bit_len()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parse hex or tagged hash text
+fn parse_hash(text: &str) -> Result<ImageHash, HashError> {
+    if text.contains(':') {
+        ImageHash::from_tagged(text)
+    } else {
+        ImageHash::from_hex(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, y| {
+            Rgb([(x * 4) as u8, (y * 5) as u8, ((x * y) % 256) as u8])
+        }))
+    }
+
+    #[test]
+    fn test_check_finds_closest_entry() {
+        let text = "# banned\n\
+                    00000000000000ff case-1\n\
+                    \n\
+                    00000000000000f0\tcase 2\n\
+                    ffffffffffffffff\n";
+        let blocklist = Blocklist::parse(text, HashAlgorithm::PHash, 8).unwrap();
+        assert_eq!(blocklist.len(), 3);
+        assert_eq!(blocklist.entries()[1].id, "case 2");
+        assert_eq!(blocklist.entries()[2].id, "ffffffffffffffff");
+
+        let query = ImageHash::from_hex("00000000000000f1").unwrap();
+        let hit = blocklist.check_hash(&query, 4).unwrap().unwrap();
+        assert_eq!((hit.id.as_str(), hit.distance), ("case 2", 1));
+        assert_eq!(hit.transform, "none");
+        assert_eq!(blocklist.check_hash(&query, 0).unwrap(), None);
+
+        // Incomparable hashes are errors, not misses
+        assert!(blocklist
+            .check_hash(&ImageHash::from_hex("00ff").unwrap(), 4)
+            .is_err());
+        let err = Blocklist::parse("00ff a\nzz b\n", HashAlgorithm::PHash, 8).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_check_image_finds_rotated_copy() {
+        let img = gradient();
+        let hash = ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+        let mut blocklist = Blocklist::new(HashAlgorithm::PHash, 8).unwrap();
+        blocklist.add("original", hash).unwrap();
+
+        let exact = blocklist.check_image(&img, 0, false).unwrap().unwrap();
+        assert_eq!((exact.distance, exact.transform), (0, "none"));
+
+        let rotated = img.rotate270();
+        let hit = blocklist.check_image(&rotated, 4, true).unwrap().unwrap();
+        assert_eq!(hit.id, "original");
+        assert_eq!(hit.transform, "rotate90");
+
+        let colorhash = ImageHash::from_image(&img, HashAlgorithm::ColorHash, 8).unwrap();
+        assert!(blocklist.add("color", colorhash).is_err());
+    }
+}
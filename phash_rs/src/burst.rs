@@ -0,0 +1,195 @@
+//! Burst Clustering Module
+//!
+//! Burst mode leaves runs of near-identical shots. They are not copies to
+//! clean up but series to pick from, so they are clustered apart from the
+//! duplicate finders: every shot is kept, shots are put in capture order
+//! and each is scored for sharpness to suggest the one to keep.
+
+use crate::decode::open_image;
+use crate::duplicate::{find_duplicates_in_hashes, primaries_only, HashEntry};
+use crate::error::{HashError, HashFailures};
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::orientation::exif_capture_time;
+use crate::progress::{Progress, ProgressCounter};
+use crate::quality::sharpness;
+use image::GenericImageView;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// One shot of a burst
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurstShot {
+    pub path: PathBuf,
+    /// EXIF capture time (see `exif_capture_time`), if recorded
+    pub capture_time: Option<String>,
+    /// Variance of the Laplacian (see `sharpness`); higher is sharper
+    pub sharpness: f64,
+    /// Width and height as decoded
+    pub dimensions: (u32, u32),
+}
+
+/// Near-identical shots, all of which are kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurstCluster {
+    /// Shots by capture time; shots without one follow, by path
+    pub shots: Vec<BurstShot>,
+    /// Suggested shot to keep: the sharpest, then the largest, then the
+    /// earliest
+    pub keeper: PathBuf,
+}
+
+/// Cluster near-identical shots, such as camera bursts, for review
+///
+/// Unlike the duplicate finders this never suggests removing anything:
+/// images whose hashes are linked within `threshold` form a cluster, its
+/// shots ordered by EXIF capture time and scored by `sharpness`, with the
+/// sharpest suggested as the keeper. Each image is decoded once. Images
+/// matching nothing are left out; clusters come in order of their first
+/// shot. Failed files are returned alongside.
+pub fn cluster_similar(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<BurstCluster>, HashFailures), String> {
+    algorithm
+        .check_hash_size(hash_size)
+        .map_err(|e| e.to_string())?;
+    let paths = primaries_only(paths);
+    let counter = ProgressCounter::new(progress, paths.len());
+    type Scored = Result<(ImageHash, BurstShot), HashError>;
+    let results: Vec<(PathBuf, Scored)> = paths
+        .par_iter()
+        .filter_map(|path| {
+            if counter.cancelled() {
+                return None;
+            }
+            let result = open_image(path).and_then(|img| {
+                let shot = BurstShot {
+                    path: path.clone(),
+                    capture_time: exif_capture_time(path),
+                    sharpness: sharpness(&img),
+                    dimensions: img.dimensions(),
+                };
+                Ok((ImageHash::from_image(&img, algorithm, hash_size)?, shot))
+            });
+            counter.tick(path);
+            Some((path.clone(), result))
+        })
+        .collect();
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    let mut entries: Vec<HashEntry> = Vec::new();
+    let mut shots = BTreeMap::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in results {
+        match result {
+            Ok((hash, shot)) => {
+                entries.push((path.clone(), hash, Some(shot.dimensions)));
+                shots.insert(path, shot);
+            }
+            Err(e) => failures.push((path, e)),
+        }
+    }
+
+    let mut clusters: Vec<BurstCluster> = find_duplicates_in_hashes(&entries, threshold)?
+        .into_iter()
+        .filter(|group| group.paths.len() > 1)
+        .map(|group| {
+            let mut members: Vec<BurstShot> =
+                group.paths.iter().map(|path| shots[path].clone()).collect();
+            members.sort_by(|a, b| {
+                (a.capture_time.is_none(), &a.capture_time, &a.path).cmp(&(
+                    b.capture_time.is_none(),
+                    &b.capture_time,
+                    &b.path,
+                ))
+            });
+            let keeper = keeper(&members).to_path_buf();
+            BurstCluster {
+                shots: members,
+                keeper,
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        let first = |c: &BurstCluster| {
+            let shot = &c.shots[0];
+            (
+                shot.capture_time.is_none(),
+                shot.capture_time.clone(),
+                shot.path.clone(),
+            )
+        };
+        first(a).cmp(&first(b))
+    });
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((clusters, failures))
+}
+
+/// Sharpest shot, then the one with the most pixels, then the earliest
+fn keeper(shots: &[BurstShot]) -> &Path {
+    let pixels = |s: &BurstShot| s.dimensions.0 as u64 * s.dimensions.1 as u64;
+    let mut best = &shots[0];
+    for shot in &shots[1..] {
+        let order = shot
+            .sharpness
+            .total_cmp(&best.sharpness)
+            .then(pixels(shot).cmp(&pixels(best)));
+        if order.is_gt() {
+            best = shot;
+        }
+    }
+    &best.path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    /// A gradient with a bright square at (`x`, `y`)
+    fn scene(x: u32, y: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(96, 96, |px, py| {
+            let inside = (x..x + 32).contains(&px) && (y..y + 32).contains(&py);
+            Luma([if inside { 240 } else { (px + py) as u8 }])
+        }))
+    }
+
+    #[test]
+    fn test_cluster_similar() {
+        let root = std::env::temp_dir().join(format!("phash_rs_burst_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let sharp = scene(8, 8);
+        let images = [
+            ("1.png", sharp.blur(1.5)),
+            ("2.png", sharp.clone()),
+            ("3.png", sharp.blur(3.0)),
+            ("other.png", scene(56, 56).fliph()),
+        ];
+        let mut paths: Vec<PathBuf> = images
+            .iter()
+            .map(|(name, img)| {
+                let path = root.join(name);
+                img.save(&path).unwrap();
+                path
+            })
+            .collect();
+        paths.push(root.join("missing.png"));
+
+        let (clusters, failures) =
+            cluster_similar(&paths, HashAlgorithm::PHash, 8, 10, None).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(clusters.len(), 1);
+        let cluster = &clusters[0];
+        let names: Vec<&PathBuf> = cluster.shots.iter().map(|s| &s.path).collect();
+        assert_eq!(names, vec![&paths[0], &paths[1], &paths[2]]);
+        assert_eq!(cluster.keeper, paths[1]);
+        assert!(cluster.shots.iter().all(|s| s.capture_time.is_none()));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
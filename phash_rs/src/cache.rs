@@ -0,0 +1,843 @@
+//! Hash Cache Module
+//!
+//! Opt-in on-disk cache of computed hashes so repeated scans of a large,
+//! mostly unchanged library only decode files that were added or modified.
+//! An entry is reused when the file's size and modification time still
+//! match what was recorded when it was hashed.
+
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, Hasher, ImageHash};
+use crate::locality::{locality_order, prefetch, read_order, ReadOrder};
+use crate::paths::{path_bytes, path_from_bytes};
+use crate::preprocess::HashOptions;
+use crate::progress::{Progress, ProgressCounter};
+use crate::warnings::{Warning, WarningKind};
+use image::{DynamicImage, GenericImageView};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Instant, UNIX_EPOCH};
+
+/// First bytes of a cache file
+const CACHE_FILE_MAGIC: &[u8] = b"phash_rs cache v5\n";
+
+/// Read size used when prefetching files in sequential read order
+const PREFETCH_BUFFER: usize = 1 << 20;
+
+/// Caches written before EXIF orientation was applied (v1), before pHash
+/// kept the top-left DCT block (v2), with only width * height instead of
+/// the image size (v3) or before 16-bit images were hashed at full
+/// precision (v4); loaded as empty
+const OUTDATED_CACHE_FILE_MAGICS: [&[u8]; 4] = [
+    b"phash_rs cache v1\n",
+    b"phash_rs cache v2\n",
+    b"phash_rs cache v3\n",
+    b"phash_rs cache v4\n",
+];
+
+/// Size and modification time used to detect a changed file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    mtime_secs: i64,
+    mtime_nanos: u32,
+    size: u64,
+}
+
+impl FileStamp {
+    /// Read the stamp of a file, or `None` if it cannot be stat'ed
+    pub fn of<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let (mtime_secs, mtime_nanos) = match meta.modified().ok()?.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => {
+                // Before the epoch: store as negative seconds plus nanos
+                let d = e.duration();
+                (-(d.as_secs() as i64), d.subsec_nanos())
+            }
+        };
+        Some(Self {
+            mtime_secs,
+            mtime_nanos,
+            size: meta.len(),
+        })
+    }
+
+    /// File size in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A cached hash together with the size of the image it was computed from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedHash {
+    pub hash: ImageHash,
+    /// Width and height of the decoded (upright) image
+    pub dimensions: (u32, u32),
+}
+
+impl CachedHash {
+    /// Width * height
+    pub fn resolution(&self) -> u64 {
+        self.dimensions.0 as u64 * self.dimensions.1 as u64
+    }
+}
+
+/// Cached hashes keyed by path and algorithm
+#[derive(Debug, Clone, Default)]
+pub struct HashCache {
+    /// (path, algorithm tag) -> (stamp at hashing time, result)
+    entries: HashMap<(PathBuf, String), (FileStamp, CachedHash)>,
+}
+
+/// Tag identifying the algorithm and hash size an entry was computed with
+fn algorithm_tag(
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    rotations: bool,
+    options: &HashOptions,
+) -> String {
+    let mut tag = format!("{}/{}", algorithm, hash_size);
+    if rotations {
+        tag.push_str("/dihedral");
+    }
+    tag.push_str(&options.tag());
+    if !options.applies_exif_orientation() {
+        tag.push_str("/unoriented");
+    }
+    tag
+}
+
+impl HashCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a cache file, or return an empty cache if it does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let data = fs::read(path)
+            .map_err(|e| format!("Failed to read cache file {}: {}", path.display(), e))?;
+        if OUTDATED_CACHE_FILE_MAGICS
+            .iter()
+            .any(|magic| data.starts_with(magic))
+        {
+            return Ok(Self::new());
+        }
+        let mut reader = Reader {
+            data: data
+                .strip_prefix(CACHE_FILE_MAGIC)
+                .ok_or_else(|| format!("Not a hash cache file: {}", path.display()))?,
+        };
+
+        let mut entries = HashMap::new();
+        while !reader.data.is_empty() {
+            let entry = reader
+                .entry()
+                .ok_or_else(|| format!("Truncated hash cache file: {}", path.display()))?;
+            let (path, tag, stamp, hex, dimensions) = entry;
+            let hash = ImageHash::from_hex(&hex)?;
+            entries.insert((path, tag), (stamp, CachedHash { hash, dimensions }));
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Write the cache, replacing the file atomically
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut data = CACHE_FILE_MAGIC.to_vec();
+
+        // Sorted so identical caches produce identical files
+        let sorted: BTreeMap<_, _> = self.entries.iter().collect();
+        for ((file, tag), (stamp, cached)) in sorted {
+            write_bytes(&mut data, &path_bytes(file));
+            write_str(&mut data, tag);
+            write_stamp(&mut data, stamp);
+            data.extend_from_slice(&cached.dimensions.0.to_le_bytes());
+            data.extend_from_slice(&cached.dimensions.1.to_le_bytes());
+            write_str(&mut data, &cached.hash.to_hex());
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("Failed to write cache file {}: {}", path.display(), e))
+    }
+
+    /// Cached result for `path` if it was hashed with the same settings
+    /// and the file still has the given stamp
+    pub fn get(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        stamp: &FileStamp,
+    ) -> Option<&CachedHash> {
+        self.get_tagged(
+            path,
+            &algorithm_tag(algorithm, hash_size, false, &HashOptions::default()),
+            stamp,
+        )
+    }
+
+    fn get_tagged(&self, path: &Path, tag: &str, stamp: &FileStamp) -> Option<&CachedHash> {
+        self.entries
+            .get(&(path.to_path_buf(), tag.to_string()))
+            .filter(|(cached_stamp, _)| cached_stamp == stamp)
+            .map(|(_, cached)| cached)
+    }
+
+    /// Record the result for `path` as of `stamp`
+    pub fn insert(
+        &mut self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        stamp: FileStamp,
+        cached: CachedHash,
+    ) {
+        self.entries.insert(
+            (
+                path.to_path_buf(),
+                algorithm_tag(algorithm, hash_size, false, &HashOptions::default()),
+            ),
+            (stamp, cached),
+        );
+    }
+
+    /// Drop entries whose file has changed or no longer exists
+    ///
+    /// Returns the number of entries removed.
+    pub fn prune(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|(path, _), (stamp, _)| FileStamp::of(path).as_ref() == Some(stamp));
+        before - self.entries.len()
+    }
+
+    /// Number of cached entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Hash `paths` in parallel, decoding only files missing from `cache`
+///
+/// Returns the result for each processed path, failures included. Fresh
+/// results are added to the cache; files that cannot be stat'ed are hashed
+/// but not cached. Every file is reported to `progress`, as are fallback
+/// decoders and low-confidence hashes. Once `progress` is cancelled the
+/// remaining files are skipped and neither reported nor returned.
+pub fn hash_paths(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Vec<(PathBuf, Result<CachedHash, HashError>)> {
+    hash_paths_with(
+        paths,
+        algorithm,
+        hash_size,
+        false,
+        &HashOptions::default(),
+        cache,
+        progress,
+    )
+}
+
+/// `hash_paths`, optionally hashing all rotations and mirror images
+///
+/// With `rotations`, each result holds the hash from
+/// `ImageHash::dihedral_from_image`; these are cached separately from
+/// plain hashes. Images are preprocessed with `options` first; results
+/// are cached per set of options.
+pub fn hash_paths_with(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    rotations: bool,
+    options: &HashOptions,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Vec<(PathBuf, Result<CachedHash, HashError>)> {
+    hash_paths_multi_with(
+        paths,
+        &[algorithm],
+        hash_size,
+        rotations,
+        options,
+        cache,
+        progress,
+    )
+    .into_iter()
+    .map(|(path, result)| (path, result.map(|mut hashes| hashes.swap_remove(0))))
+    .collect()
+}
+
+/// `hash_paths` with several algorithms, decoding each file once
+///
+/// Each result holds one hash per algorithm, in the order given, or the
+/// first error; a file is decoded only if some algorithm is missing from
+/// `cache`, and each hash is cached as `hash_paths` would cache it.
+pub fn hash_paths_multi(
+    paths: &[PathBuf],
+    algorithms: &[HashAlgorithm],
+    hash_size: usize,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Vec<(PathBuf, Result<Vec<CachedHash>, HashError>)> {
+    hash_paths_multi_with(
+        paths,
+        algorithms,
+        hash_size,
+        false,
+        &HashOptions::default(),
+        cache,
+        progress,
+    )
+}
+
+fn hash_paths_multi_with(
+    paths: &[PathBuf],
+    algorithms: &[HashAlgorithm],
+    hash_size: usize,
+    rotations: bool,
+    options: &HashOptions,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Vec<(PathBuf, Result<Vec<CachedHash>, HashError>)> {
+    let hashers: Vec<AlgorithmHasher> = algorithms
+        .iter()
+        .map(|&algorithm| AlgorithmHasher {
+            algorithm,
+            hash_size,
+            rotations,
+            options,
+        })
+        .collect();
+    let hashers: Vec<&dyn Hasher> = hashers.iter().map(|h| h as &dyn Hasher).collect();
+    let algorithm = algorithms
+        .iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join("+");
+    let span = tracing::info_span!("hash_paths", files = paths.len(), %algorithm, hash_size);
+    hash_paths_by(
+        paths,
+        &hashers,
+        options.apply_exif_orientation,
+        &span,
+        cache,
+        progress,
+    )
+}
+
+/// A built-in algorithm as a `Hasher`, cached under its `algorithm_tag`
+pub(crate) struct AlgorithmHasher<'a> {
+    pub(crate) algorithm: HashAlgorithm,
+    pub(crate) hash_size: usize,
+    /// Hash all rotations and mirror images, as `dihedral_from_image`
+    pub(crate) rotations: bool,
+    pub(crate) options: &'a HashOptions,
+}
+
+impl Hasher for AlgorithmHasher<'_> {
+    fn hash_image(&self, img: &DynamicImage) -> Result<ImageHash, HashError> {
+        let (algorithm, hash_size, options) = (self.algorithm, self.hash_size, self.options);
+        if self.rotations {
+            ImageHash::dihedral_from_image_with(img, algorithm, hash_size, options)
+        } else if algorithm == HashAlgorithm::PHash {
+            crate::backend::phash_shared(img, hash_size, options)
+        } else {
+            ImageHash::from_image_with(img, algorithm, hash_size, options)
+        }
+    }
+
+    fn cache_tag(&self) -> Option<String> {
+        Some(algorithm_tag(
+            self.algorithm,
+            self.hash_size,
+            self.rotations,
+            self.options,
+        ))
+    }
+}
+
+/// Hash `paths` with each of `hashers` in parallel, decoding each file
+/// once and only if some hash is missing from `cache`
+///
+/// Hashes are looked up and cached under `Hasher::cache_tag`; hashers
+/// without a tag always hash. Files are decoded with EXIF orientation as
+/// `apply_exif_orientation` says, or as configured if it is `None`.
+/// Workers log inside `span`.
+pub(crate) fn hash_paths_by(
+    paths: &[PathBuf],
+    hashers: &[&dyn Hasher],
+    apply_exif_orientation: Option<bool>,
+    span: &tracing::Span,
+    mut cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Vec<(PathBuf, Result<Vec<CachedHash>, HashError>)> {
+    let _entered = span.enter();
+    let tags: Vec<Option<String>> = hashers.iter().map(|h| h.cache_tag()).collect();
+    let started = Instant::now();
+    let counter = ProgressCounter::new(progress, paths.len());
+    let lookup = cache.as_deref();
+    let hash = |path: &PathBuf| {
+        // Worker threads log inside the batch's span too
+        let _entered = span.enter();
+        if counter.cancelled() {
+            return None;
+        }
+        let (result, stamp) = hash_one(
+            path,
+            hashers,
+            &tags,
+            apply_exif_orientation,
+            lookup,
+            &counter,
+        );
+        if let Err(e) = &result {
+            counter.warn(Warning::new(WarningKind::HashFailed, path, e.to_string()));
+        }
+        counter.tick(path);
+        Some((path.clone(), result, stamp))
+    };
+    let results: Vec<HashedFile> = match read_order() {
+        ReadOrder::Parallel => paths.par_iter().filter_map(hash).collect(),
+        ReadOrder::Sequential { readahead } => {
+            let is_cached = |path: &Path| {
+                lookup.is_some_and(|cache| {
+                    FileStamp::of(path).is_some_and(|stamp| {
+                        tags.iter().all(|tag| {
+                            tag.as_ref()
+                                .is_some_and(|tag| cache.get_tagged(path, tag, &stamp).is_some())
+                        })
+                    })
+                })
+            };
+            hash_sequentially(paths, readahead, &counter, is_cached, hash)
+        }
+    };
+    tracing::info!(
+        hashed = results.len(),
+        failed = results.iter().filter(|(_, r, _)| r.is_err()).count(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "done"
+    );
+
+    results
+        .into_iter()
+        .map(|(path, result, stamp)| {
+            if let (Some(cache), Ok(hashes), Some(stamp)) = (cache.as_deref_mut(), &result, stamp) {
+                for (tag, cached) in tags.iter().zip(hashes) {
+                    if let Some(tag) = tag {
+                        cache
+                            .entries
+                            .insert((path.clone(), tag.clone()), (stamp, cached.clone()));
+                    }
+                }
+            }
+            (path, result)
+        })
+        .collect()
+}
+
+/// Run `hash` over `paths` while one thread reads the files in locality
+/// order, at most `readahead` files ahead
+///
+/// Cached files are not read. Results come back in input order.
+fn hash_sequentially<R, C, H>(
+    paths: &[PathBuf],
+    readahead: usize,
+    counter: &ProgressCounter,
+    is_cached: C,
+    hash: H,
+) -> Vec<R>
+where
+    R: Send,
+    C: Fn(&Path) -> bool + Sync,
+    H: Fn(&PathBuf) -> Option<R> + Sync,
+{
+    let (sender, receiver) = mpsc::sync_channel::<usize>(readahead);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut buf = vec![0u8; PREFETCH_BUFFER];
+            for i in locality_order(paths) {
+                if counter.cancelled() {
+                    break;
+                }
+                if !is_cached(&paths[i]) {
+                    prefetch(&paths[i], &mut buf);
+                }
+                if sender.send(i).is_err() {
+                    break;
+                }
+            }
+            drop(sender);
+        });
+        receiver
+            .into_iter()
+            .par_bridge()
+            .filter_map(|i| hash(&paths[i]).map(|r| (i, r)))
+            .collect()
+    });
+    results.sort_unstable_by_key(|&(i, _)| i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// A file's hashes, and the stamp to cache them under if any was computed
+type HashedFile = (
+    PathBuf,
+    Result<Vec<CachedHash>, HashError>,
+    Option<FileStamp>,
+);
+
+/// Hash one file with each of `hashers`, cached under `tags`,
+/// returning the stamp to cache the hashes under if any was computed
+fn hash_one(
+    path: &Path,
+    hashers: &[&dyn Hasher],
+    tags: &[Option<String>],
+    apply_exif_orientation: Option<bool>,
+    cache: Option<&HashCache>,
+    counter: &ProgressCounter,
+) -> (Result<Vec<CachedHash>, HashError>, Option<FileStamp>) {
+    let stamp = cache.and_then(|_| FileStamp::of(path));
+    let cached: Vec<Option<CachedHash>> = tags
+        .iter()
+        .map(|tag| {
+            let (cache, stamp) = cache.zip(stamp.as_ref())?;
+            cache.get_tagged(path, tag.as_ref()?, stamp).cloned()
+        })
+        .collect();
+    if cached.iter().all(Option::is_some) {
+        tracing::trace!(path = %path.display(), "cache hit");
+        return (Ok(cached.into_iter().flatten().collect()), None);
+    }
+
+    let started = Instant::now();
+    // One decode yields both the hash and the resolution
+    let decoded = crate::decode::open_image_oriented(path, apply_exif_orientation);
+    let result = decoded.and_then(|(img, fallbacks)| {
+        if !fallbacks.is_empty() {
+            counter.warn(Warning::new(
+                WarningKind::FallbackDecoder,
+                path,
+                format!("decoded after {}", fallbacks.join("; ")),
+            ));
+        }
+        let (w, h) = img.dimensions();
+        let hashes = hashers
+            .iter()
+            .zip(cached)
+            .map(|(hasher, cached)| match cached {
+                Some(cached) => Ok(cached),
+                None => Ok(CachedHash {
+                    hash: hasher.hash_image(&img)?,
+                    dimensions: (w, h),
+                }),
+            })
+            .collect::<Result<Vec<_>, HashError>>()?;
+        // One warning per file, however many algorithms found it flat
+        if let Some(reason) = hashes
+            .iter()
+            .find_map(|cached| low_confidence(w, h, &cached.hash))
+        {
+            counter.warn(Warning::new(WarningKind::LowConfidence, path, reason));
+        }
+        tracing::debug!(
+            path = %path.display(),
+            width = w,
+            height = h,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "hashed"
+        );
+        Ok(hashes)
+    });
+    (result, stamp)
+}
+
+/// Why a freshly computed hash is unlikely to discriminate, if it is
+fn low_confidence(width: u32, height: u32, hash: &ImageHash) -> Option<String> {
+    let hash_size = hash.hash_size();
+    if (width.min(height) as usize) < hash_size {
+        Some(format!(
+            "{}x{} image is smaller than the {}x{} hash grid",
+            width, height, hash_size, hash_size
+        ))
+    } else if hash.is_uniform() {
+        Some("all hash bits are equal; the image is nearly flat".to_string())
+    } else {
+        None
+    }
+}
+
+pub(crate) fn write_str(data: &mut Vec<u8>, s: &str) {
+    write_bytes(data, s.as_bytes());
+}
+
+pub(crate) fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    data.extend_from_slice(bytes);
+}
+
+pub(crate) fn write_stamp(data: &mut Vec<u8>, stamp: &FileStamp) {
+    data.extend_from_slice(&stamp.mtime_secs.to_le_bytes());
+    data.extend_from_slice(&stamp.mtime_nanos.to_le_bytes());
+    data.extend_from_slice(&stamp.size.to_le_bytes());
+}
+
+/// Path, tag, stamp, hex hash and dimensions of one stored entry
+type RawEntry = (PathBuf, String, FileStamp, String, (u32, u32));
+
+/// Cursor over the little-endian record stream of a cache or index file
+pub(crate) struct Reader<'a> {
+    pub(crate) data: &'a [u8],
+}
+
+impl Reader<'_> {
+    pub(crate) fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*head)
+    }
+
+    pub(crate) fn bytes(&mut self) -> Option<Vec<u8>> {
+        let len = u32::from_le_bytes(self.take()?) as usize;
+        if len > self.data.len() {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(head.to_vec())
+    }
+
+    pub(crate) fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?).ok()
+    }
+
+    /// A path written with `write_bytes(path_bytes(..))`
+    pub(crate) fn path(&mut self) -> Option<PathBuf> {
+        self.bytes().map(path_from_bytes)
+    }
+
+    pub(crate) fn stamp(&mut self) -> Option<FileStamp> {
+        Some(FileStamp {
+            mtime_secs: i64::from_le_bytes(self.take()?),
+            mtime_nanos: u32::from_le_bytes(self.take()?),
+            size: u64::from_le_bytes(self.take()?),
+        })
+    }
+
+    fn entry(&mut self) -> Option<RawEntry> {
+        let path = self.path()?;
+        let tag = self.string()?;
+        let stamp = self.stamp()?;
+        let dimensions = (
+            u32::from_le_bytes(self.take()?),
+            u32::from_le_bytes(self.take()?),
+        );
+        let hex = self.string()?;
+        Some((path, tag, stamp, hex, dimensions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_requires_same_stamp_and_settings() {
+        let stamp = FileStamp {
+            mtime_secs: 1_700_000_000,
+            mtime_nanos: 5,
+            size: 1234,
+        };
+        let cached = CachedHash {
+            hash: ImageHash::from_hex("00000000000000ff").unwrap(),
+            dimensions: (640, 480),
+        };
+        let mut cache = HashCache::new();
+        cache.insert(
+            Path::new("a.jpg"),
+            HashAlgorithm::PHash,
+            8,
+            stamp,
+            cached.clone(),
+        );
+
+        assert_eq!(
+            cache.get(Path::new("a.jpg"), HashAlgorithm::PHash, 8, &stamp),
+            Some(&cached)
+        );
+        assert!(cache
+            .get(Path::new("a.jpg"), HashAlgorithm::ColorHash, 8, &stamp)
+            .is_none());
+        assert!(cache
+            .get(Path::new("a.jpg"), HashAlgorithm::PHash, 16, &stamp)
+            .is_none());
+        let touched = FileStamp {
+            mtime_nanos: 6,
+            ..stamp
+        };
+        assert!(cache
+            .get(Path::new("a.jpg"), HashAlgorithm::PHash, 8, &touched)
+            .is_none());
+    }
+
+    #[test]
+    fn test_sequential_results_keep_input_order() {
+        let paths: Vec<PathBuf> = ["/missing/b/1.jpg", "/missing/a/2.jpg", "/missing/a/1.jpg"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let counter = ProgressCounter::new(None, paths.len());
+        let results = hash_sequentially(&paths, 1, &counter, |_| false, |p| Some(p.clone()));
+        assert_eq!(results, paths);
+    }
+
+    #[test]
+    fn test_failures_are_reported_as_warnings() {
+        let warnings = crate::warnings::WarningCollector::new();
+        let paths = vec![PathBuf::from("/missing/a.jpg")];
+        let results = hash_paths(&paths, HashAlgorithm::PHash, 8, None, Some(&warnings));
+        assert!(results[0].1.is_err());
+
+        let warnings = warnings.into_sorted();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, WarningKind::HashFailed);
+        assert_eq!(warnings[0].path, Path::new("/missing/a.jpg"));
+    }
+
+    #[test]
+    fn test_multi_matches_single_and_fills_cache() {
+        let root = std::env::temp_dir().join(format!("phash_rs_multi_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("a.png");
+        image::RgbImage::from_fn(40, 30, |x, y| image::Rgb([(x * 6) as u8, (y * 8) as u8, 0]))
+            .save(&path)
+            .unwrap();
+        let paths = vec![path.clone()];
+        let algorithms = [HashAlgorithm::PHash, HashAlgorithm::BlockHash];
+
+        let mut cache = HashCache::new();
+        let multi = hash_paths_multi(&paths, &algorithms, 8, Some(&mut cache), None);
+        let hashes = multi[0].1.as_ref().unwrap();
+        for (algorithm, cached) in algorithms.iter().zip(hashes) {
+            let single = hash_paths(&paths, *algorithm, 8, None, None);
+            assert_eq!(single[0].1.as_ref().unwrap(), cached);
+        }
+        assert_eq!(cache.len(), 2);
+
+        // With both hashes cached, the file is not decoded at all
+        fs::write(&path, b"not an image").unwrap();
+        let stamp = FileStamp::of(&path).unwrap();
+        for (cached_stamp, _) in cache.entries.values_mut() {
+            *cached_stamp = stamp;
+        }
+        let again = hash_paths_multi(&paths, &algorithms, 8, Some(&mut cache), None);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(again[0].1.as_ref().unwrap(), hashes);
+    }
+
+    #[test]
+    fn test_exif_orientation_per_call() {
+        let root = std::env::temp_dir().join(format!("phash_rs_oriented_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("a.jpg");
+        let img = image::RgbImage::from_fn(48, 32, |x, y| {
+            image::Rgb([(x * 5) as u8, (y * 8) as u8, if x < 16 { 255 } else { 0 }])
+        });
+        fs::write(&path, crate::orientation::tagged_jpeg(&img, 6)).unwrap();
+        let paths = vec![path];
+
+        let mut cache = HashCache::new();
+        let mut hash = |apply| {
+            let options = HashOptions {
+                apply_exif_orientation: Some(apply),
+                ..Default::default()
+            };
+            let hashed = hash_paths_with(
+                &paths,
+                HashAlgorithm::PHash,
+                8,
+                false,
+                &options,
+                Some(&mut cache),
+                None,
+            );
+            hashed[0].1.clone().unwrap()
+        };
+        let upright = hash(true);
+        let stored = hash(false);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(upright.dimensions, (32, 48));
+        assert_eq!(stored.dimensions, (48, 32));
+        assert_ne!(upright.hash, stored.hash);
+        // Each setting is cached under its own tag
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_low_confidence() {
+        let varied = ImageHash::from_hex("0123456789abcdef").unwrap();
+        let flat = ImageHash::from_hex("0000000000000000").unwrap();
+        assert!(low_confidence(640, 480, &varied).is_none());
+        assert!(low_confidence(640, 4, &varied).is_some());
+        assert!(low_confidence(640, 480, &flat).is_some());
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let path = std::env::temp_dir().join(format!("phash_rs_cache_{}.bin", std::process::id()));
+        let stamp = FileStamp {
+            mtime_secs: -3,
+            mtime_nanos: 999,
+            size: 42,
+        };
+        let cached = CachedHash {
+            hash: ImageHash::from_hex("0123456789abcdef").unwrap(),
+            dimensions: (7, 1),
+        };
+        let mut cache = HashCache::new();
+        cache.insert(
+            Path::new("dir/ä.png"),
+            HashAlgorithm::WHash { level: Some(2) },
+            8,
+            stamp,
+            cached.clone(),
+        );
+        cache.save(&path).unwrap();
+
+        let loaded = HashCache::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(
+            loaded.get(
+                Path::new("dir/ä.png"),
+                HashAlgorithm::WHash { level: Some(2) },
+                8,
+                &stamp
+            ),
+            Some(&cached)
+        );
+        assert!(HashCache::load(path.with_extension("missing"))
+            .unwrap()
+            .is_empty());
+    }
+}
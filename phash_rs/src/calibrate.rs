@@ -0,0 +1,171 @@
+//! Calibration Module
+//!
+//! Picks a threshold from labelled examples instead of trial and error:
+//! pairs known to be copies and pairs known to be different pictures are
+//! hashed, and every possible threshold is scored by how well it separates
+//! the two distance distributions.
+
+use crate::cache::{hash_paths, HashCache};
+use crate::error::HashFailures;
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::progress::Progress;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+/// Scores of one threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationPoint {
+    pub threshold: u32,
+    /// Share of pairs within the threshold that are duplicates; 1.0 when
+    /// no pair is
+    pub precision: f64,
+    /// Share of duplicate pairs within the threshold
+    pub recall: f64,
+    /// Harmonic mean of precision and recall
+    pub f1: f64,
+}
+
+/// Result of `calibrate_threshold`
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    /// The threshold with the best F1 score, the smallest on ties
+    pub best: CalibrationPoint,
+    /// Scores of every threshold from 0 to the hash's bit count
+    pub curve: Vec<CalibrationPoint>,
+    /// Distance of each duplicate pair that could be hashed
+    pub duplicate_distances: Vec<u32>,
+    /// Distance of each distinct pair that could be hashed
+    pub distinct_distances: Vec<u32>,
+    /// Files that could not be hashed; their pairs are left out
+    pub failures: HashFailures,
+}
+
+/// Find the threshold that best separates labelled pairs
+///
+/// Every file in `duplicates` (pairs of copies) and `distinct` (pairs of
+/// different pictures) is hashed once with `algorithm`, reusing `cache`.
+/// Fails if either list has no pair left after leaving out files that
+/// could not be hashed.
+pub fn calibrate_threshold(
+    duplicates: &[(PathBuf, PathBuf)],
+    distinct: &[(PathBuf, PathBuf)],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<Calibration, String> {
+    algorithm
+        .check_hash_size(hash_size)
+        .map_err(|e| e.to_string())?;
+    let paths: Vec<PathBuf> = duplicates
+        .iter()
+        .chain(distinct)
+        .flat_map(|(a, b)| [a.clone(), b.clone()])
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut hashes: HashMap<PathBuf, ImageHash> = HashMap::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in hash_paths(&paths, algorithm, hash_size, cache, progress) {
+        match result {
+            Ok(cached) => {
+                hashes.insert(path, cached.hash);
+            }
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    let distances = |pairs: &[(PathBuf, PathBuf)]| -> Vec<u32> {
+        pairs
+            .iter()
+            .filter_map(|(a, b)| Some(hashes.get(a)?.distance(hashes.get(b)?)))
+            .collect()
+    };
+    let duplicate_distances = distances(duplicates);
+    let distinct_distances = distances(distinct);
+    let bit_len = hashes.values().next().map_or(0, |h| h.bit_len()) as u32;
+    let curve = score_thresholds(&duplicate_distances, &distinct_distances, bit_len)?;
+    let best = best_point(&curve);
+    Ok(Calibration {
+        best,
+        curve,
+        duplicate_distances,
+        distinct_distances,
+        failures,
+    })
+}
+
+/// Score every threshold from 0 to `max_threshold`
+fn score_thresholds(
+    duplicates: &[u32],
+    distinct: &[u32],
+    max_threshold: u32,
+) -> Result<Vec<CalibrationPoint>, String> {
+    if duplicates.is_empty() || distinct.is_empty() {
+        return Err("Calibration needs at least one duplicate and one distinct pair".to_string());
+    }
+    let within = |distances: &[u32], t: u32| distances.iter().filter(|&&d| d <= t).count() as f64;
+    Ok((0..=max_threshold)
+        .map(|threshold| {
+            let true_pos = within(duplicates, threshold);
+            let false_pos = within(distinct, threshold);
+            let precision = if true_pos + false_pos > 0.0 {
+                true_pos / (true_pos + false_pos)
+            } else {
+                1.0
+            };
+            let recall = true_pos / duplicates.len() as f64;
+            let f1 = if precision + recall > 0.0 {
+                2.0 * precision * recall / (precision + recall)
+            } else {
+                0.0
+            };
+            CalibrationPoint {
+                threshold,
+                precision,
+                recall,
+                f1,
+            }
+        })
+        .collect())
+}
+
+/// The point with the highest F1, the lowest threshold on ties
+fn best_point(curve: &[CalibrationPoint]) -> CalibrationPoint {
+    curve
+        .iter()
+        .fold(None::<&CalibrationPoint>, |best, p| match best {
+            Some(b) if b.f1 >= p.f1 => Some(b),
+            _ => Some(p),
+        })
+        .cloned()
+        .unwrap_or(CalibrationPoint {
+            threshold: 0,
+            precision: 1.0,
+            recall: 0.0,
+            f1: 0.0,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best_threshold_separates_distributions() {
+        let duplicates = [0, 2, 3, 5, 12];
+        let distinct = [9, 10, 11, 20];
+        let curve = score_thresholds(&duplicates, &distinct, 64).unwrap();
+        assert_eq!(curve.len(), 65);
+        let best = best_point(&curve);
+        // Threshold 5 catches 4 of 5 copies with no false match; 12 catches
+        // all five but three distinct pairs too
+        assert_eq!(best.threshold, 5);
+        assert_eq!((best.precision, best.recall), (1.0, 0.8));
+        assert_eq!(curve[64].recall, 1.0);
+        assert!(score_thresholds(&duplicates, &[], 64).is_err());
+    }
+}
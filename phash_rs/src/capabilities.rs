@@ -0,0 +1,157 @@
+//! Capability Introspection Module
+//!
+//! Reports what this build of the library can do on the current machine:
+//! compiled features, decodable formats, SIMD support and default limits.
+
+use crate::decode::decoder_config;
+use crate::hash::{
+    DCT_SIZE, MAX_DCT_SIZE, MIN_SEGMENT_SIZE, SEGMENTATION_IMAGE_SIZE, WHASH_MAX_IMAGE_SCALE,
+};
+use crate::threads::num_threads;
+use std::collections::BTreeMap;
+
+/// Snapshot of the library's build and runtime capabilities
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// Crate version
+    pub version: &'static str,
+    /// Cargo features compiled into this build
+    pub features: Vec<&'static str>,
+    /// Hash algorithm names accepted by the `algorithm` argument
+    pub algorithms: Vec<&'static str>,
+    /// Extension -> usable decode backends, in the order they are tried
+    pub formats: BTreeMap<String, Vec<&'static str>>,
+    /// CPU SIMD extensions detected at runtime
+    pub simd_detected: Vec<&'static str>,
+    /// Implementation used for Hamming distance
+    pub hamming_path: &'static str,
+    /// Default sizes and limits
+    pub limits: BTreeMap<&'static str, usize>,
+}
+
+/// Every optional Cargo feature and whether this build includes it
+///
+/// `full` and `default` only select others and are not listed.
+pub fn features() -> Vec<(&'static str, bool)> {
+    vec![
+        ("gif", cfg!(feature = "gif")),
+        ("webp", cfg!(feature = "webp")),
+        ("tiff", cfg!(feature = "tiff")),
+        ("bmp", cfg!(feature = "bmp")),
+        ("extra-formats", cfg!(feature = "extra-formats")),
+        ("exact", cfg!(feature = "exact")),
+        ("turbojpeg", cfg!(feature = "turbojpeg")),
+        ("heif", cfg!(feature = "heif")),
+        ("avif", cfg!(feature = "avif")),
+        ("raw", cfg!(feature = "raw")),
+        ("pdf", cfg!(feature = "pdf")),
+        ("sync", cfg!(feature = "sync")),
+        ("video", cfg!(feature = "video")),
+        ("trash", cfg!(feature = "trash")),
+        ("tokio", cfg!(feature = "tokio")),
+        ("watch", cfg!(feature = "watch")),
+        ("gpu", cfg!(feature = "gpu")),
+        ("logging", cfg!(feature = "logging")),
+    ]
+}
+
+/// Collect the current capabilities
+pub fn capabilities() -> Capabilities {
+    let features = features()
+        .into_iter()
+        .filter(|&(_, enabled)| enabled)
+        .map(|(name, _)| name)
+        .collect();
+
+    let limits = BTreeMap::from([
+        ("default_hash_size", 8),
+        ("dct_size", DCT_SIZE),
+        ("max_dct_size", MAX_DCT_SIZE),
+        ("whash_max_image_scale", WHASH_MAX_IMAGE_SCALE),
+        ("segmentation_image_size", SEGMENTATION_IMAGE_SIZE as usize),
+        ("min_segment_size", MIN_SEGMENT_SIZE),
+        (
+            "threads",
+            num_threads().unwrap_or_else(rayon::current_num_threads),
+        ),
+    ]);
+
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        features,
+        algorithms: vec![
+            "phash",
+            "whash",
+            "colorhash",
+            "blockhash",
+            "marrhildreth",
+            "radialvariance",
+        ],
+        formats: decoder_config().supported_formats(),
+        simd_detected: detect_simd(),
+        hamming_path: hamming_path(),
+        limits,
+    }
+}
+
+/// Which popcount implementation `ImageHash::distance` compiles to
+fn hamming_path() -> &'static str {
+    if cfg!(any(target_feature = "popcnt", target_feature = "neon")) {
+        "hardware-popcount"
+    } else {
+        "scalar"
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn detect_simd() -> Vec<&'static str> {
+    let mut found = Vec::new();
+    if is_x86_feature_detected!("sse2") {
+        found.push("sse2");
+    }
+    if is_x86_feature_detected!("sse4.2") {
+        found.push("sse4.2");
+    }
+    if is_x86_feature_detected!("popcnt") {
+        found.push("popcnt");
+    }
+    if is_x86_feature_detected!("avx2") {
+        found.push("avx2");
+    }
+    if is_x86_feature_detected!("avx512f") {
+        found.push("avx512f");
+    }
+    found
+}
+
+#[cfg(target_arch = "aarch64")]
+fn detect_simd() -> Vec<&'static str> {
+    let mut found = Vec::new();
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        found.push("neon");
+    }
+    found
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn detect_simd() -> Vec<&'static str> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_report() {
+        let caps = capabilities();
+        assert!(!caps.version.is_empty());
+        assert!(caps.algorithms.contains(&"phash"));
+        assert!(caps.formats.contains_key("png"));
+        assert_eq!(caps.limits["dct_size"], DCT_SIZE);
+        assert_eq!(
+            caps.features.contains(&"exact"),
+            features().contains(&("exact", true))
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Checkpoint Module
+//!
+//! A duplicate scan of a large archive can run for hours, and a crash,
+//! kill or Ctrl+C used to throw all of it away. `find_duplicates_checkpointed`
+//! feeds the paths to a `DuplicateFinder` in chunks and, between chunks,
+//! writes the hashes and grouping state so far and the paths still to do to
+//! a checkpoint file. `resume_duplicates` reads it back and carries on, so
+//! at most the chunks since the last checkpoint are hashed again.
+
+use crate::cache::{write_bytes, HashCache, Reader};
+use crate::duplicate::DuplicateGroup;
+use crate::error::HashFailures;
+use crate::paths::path_bytes;
+use crate::progress::Progress;
+use crate::stream::DuplicateFinder;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const CHECKPOINT_FILE_MAGIC: &[u8] = b"phash_rs checkpoint v1\n";
+
+/// When a checkpointed scan writes its checkpoint
+///
+/// Paths are hashed in chunks of `files`; after a chunk the checkpoint is
+/// written if `interval` has passed since the last write. Each write
+/// holds every hash so far, so on large scans an interval keeps the time
+/// spent writing small next to the time spent hashing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointSchedule {
+    pub files: usize,
+    pub interval: Duration,
+}
+
+impl Default for CheckpointSchedule {
+    /// Chunks of 1000 files, written at most every 30 seconds
+    fn default() -> Self {
+        Self {
+            files: 1000,
+            interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Group `paths` with `finder`, checkpointing to `checkpoint` as it goes
+///
+/// The checkpoint is written once before hashing starts and then on
+/// `schedule`, and removed when the scan completes. If `progress` cancels
+/// or the process dies, the file is left behind for `resume_duplicates`.
+/// Fails without starting if `checkpoint` already exists, so an earlier
+/// interrupted scan is not overwritten by accident.
+pub fn find_duplicates_checkpointed(
+    paths: &[PathBuf],
+    finder: DuplicateFinder,
+    checkpoint: &Path,
+    schedule: CheckpointSchedule,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    if checkpoint.exists() {
+        return Err(format!(
+            "Checkpoint {} already exists; resume it or delete it first",
+            checkpoint.display()
+        ));
+    }
+    run(finder, paths, checkpoint, schedule, cache, progress)
+}
+
+/// Continue the scan saved in `checkpoint` by `find_duplicates_checkpointed`
+///
+/// Uses the settings, schedule and remaining paths stored in the file and
+/// returns the groups and failures of the whole scan, as the uninterrupted
+/// scan would have.
+pub fn resume_duplicates(
+    checkpoint: &Path,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    let (finder, schedule, remaining) = load_checkpoint(checkpoint)?;
+    tracing::info!(
+        checkpoint = %checkpoint.display(),
+        open_images = finder.open_images(),
+        remaining = remaining.len(),
+        "resuming"
+    );
+    run(finder, &remaining, checkpoint, schedule, cache, progress)
+}
+
+fn run(
+    mut finder: DuplicateFinder,
+    paths: &[PathBuf],
+    checkpoint: &Path,
+    schedule: CheckpointSchedule,
+    mut cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    save_checkpoint(checkpoint, &finder, schedule, paths)?;
+    let mut last_save = Instant::now();
+    let mut done = 0;
+    for chunk in paths.chunks(schedule.files.max(1)) {
+        finder.add_paths(chunk, cache.as_deref_mut(), progress)?;
+        done += chunk.len();
+        if done < paths.len() && last_save.elapsed() >= schedule.interval {
+            save_checkpoint(checkpoint, &finder, schedule, &paths[done..])?;
+            last_save = Instant::now();
+        }
+    }
+    let result = finder.finish();
+    match fs::remove_file(checkpoint) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!(
+            "Failed to remove checkpoint file {}: {}",
+            checkpoint.display(),
+            e
+        )),
+        _ => Ok(result),
+    }
+}
+
+/// Write the finder state, schedule and remaining paths, replacing the
+/// file atomically so a kill mid-write leaves the previous checkpoint
+fn save_checkpoint(
+    path: &Path,
+    finder: &DuplicateFinder,
+    schedule: CheckpointSchedule,
+    remaining: &[PathBuf],
+) -> Result<(), String> {
+    let mut data = CHECKPOINT_FILE_MAGIC.to_vec();
+    data.extend_from_slice(&(schedule.files as u64).to_le_bytes());
+    data.extend_from_slice(&(schedule.interval.as_millis() as u64).to_le_bytes());
+    finder.write_state(&mut data);
+    data.extend_from_slice(&(remaining.len() as u64).to_le_bytes());
+    for file in remaining {
+        write_bytes(&mut data, &path_bytes(file));
+    }
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write checkpoint file {}: {}", path.display(), e))?;
+    tracing::debug!(
+        checkpoint = %path.display(),
+        open_images = finder.open_images(),
+        remaining = remaining.len(),
+        "checkpoint written"
+    );
+    Ok(())
+}
+
+fn load_checkpoint(
+    path: &Path,
+) -> Result<(DuplicateFinder, CheckpointSchedule, Vec<PathBuf>), String> {
+    let data = fs::read(path)
+        .map_err(|e| format!("Failed to read checkpoint file {}: {}", path.display(), e))?;
+    let mut reader = Reader {
+        data: data
+            .strip_prefix(CHECKPOINT_FILE_MAGIC)
+            .ok_or_else(|| format!("Not a checkpoint file: {}", path.display()))?,
+    };
+    read_checkpoint(&mut reader)
+        .filter(|_| reader.data.is_empty())
+        .ok_or_else(|| format!("Corrupt checkpoint file: {}", path.display()))
+}
+
+fn read_checkpoint(
+    reader: &mut Reader,
+) -> Option<(DuplicateFinder, CheckpointSchedule, Vec<PathBuf>)> {
+    let schedule = CheckpointSchedule {
+        files: u64::from_le_bytes(reader.take()?) as usize,
+        interval: Duration::from_millis(u64::from_le_bytes(reader.take()?)),
+    };
+    let finder = DuplicateFinder::read_state(reader)?;
+    let remaining = (0..u64::from_le_bytes(reader.take()?))
+        .map(|_| reader.path())
+        .collect::<Option<Vec<PathBuf>>>()?;
+    Some((finder, schedule, remaining))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::HashAlgorithm;
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    #[test]
+    fn test_resume_matches_uninterrupted_scan() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_checkpoint_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut paths = Vec::new();
+        for i in 0..6u32 {
+            // Pairs of near-identical stripe patterns
+            let (sx, sy) = [(4, 64), (64, 4), (8, 8)][i as usize / 2];
+            let img = RgbImage::from_fn(64, 64, |x, y| {
+                let v = if (x / sx + y / sy) % 2 == 0 { 200 } else { 50 };
+                Rgb([(v + i % 2) as u8; 3])
+            });
+            let path = dir.join(format!("{}.png", i));
+            DynamicImage::ImageRgb8(img).save(&path).unwrap();
+            paths.push(path);
+        }
+        paths.push(dir.join("missing.png"));
+
+        let finder = || DuplicateFinder::new(HashAlgorithm::PHash, 4);
+        let mut one_shot = finder();
+        one_shot.add_paths(&paths, None, None).unwrap();
+        let expected = one_shot.finish();
+
+        // Stop after the first chunk, as a kill would
+        let checkpoint = dir.join("scan.checkpoint");
+        let schedule = CheckpointSchedule {
+            files: 3,
+            interval: Duration::ZERO,
+        };
+        let mut partial = finder();
+        partial.add_paths(&paths[..3], None, None).unwrap();
+        save_checkpoint(&checkpoint, &partial, schedule, &paths[3..]).unwrap();
+        assert!(
+            find_duplicates_checkpointed(&paths, finder(), &checkpoint, schedule, None, None)
+                .is_err()
+        );
+
+        let (groups, failures) = resume_duplicates(&checkpoint, None, None).unwrap();
+        assert!(!checkpoint.exists());
+        let summary = |groups: &[DuplicateGroup]| {
+            groups
+                .iter()
+                .map(|g| (g.paths.clone(), g.hash.clone(), g.best_path.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(groups.len(), 3);
+        assert_eq!(summary(&groups), summary(&expected.0));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, expected.1[0].0);
+        assert_eq!(failures[0].1.to_string(), expected.1[0].1.to_string());
+
+        fs::write(&checkpoint, b"phash_rs checkpoint v1\n\x01").unwrap();
+        assert!(resume_duplicates(&checkpoint, None, None)
+            .unwrap_err()
+            .contains("Corrupt"));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
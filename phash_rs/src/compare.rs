@@ -0,0 +1,216 @@
+//! Detailed Comparison Module
+//!
+//! A distance says how far apart two hashes are, not why. For borderline
+//! pairs this breaks the comparison down: which bits agree, which DCT
+//! frequencies the differing bits come from, which quadrants of the picture
+//! changed, and optionally a small map of where the pixels differ.
+
+use crate::decode::open_image;
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, ImageHash};
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, Luma};
+use std::path::Path;
+
+/// Side length of the difference heatmap
+pub const HEATMAP_SIZE: u32 = 32;
+
+/// Why two images are, or are not, within a threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// Hamming distance of the two hashes
+    pub distance: u32,
+    /// Whether each bit agrees, in the order of `ImageHash::to_bits`
+    /// (padding bits included, which always agree)
+    pub agreement: Vec<bool>,
+    /// pHash and colorHash only: `(differing, total)` bits per frequency
+    /// band, band `k` holding the coefficients whose larger of row and
+    /// column is `k`, lowest frequencies first
+    pub frequency_bands: Option<Vec<(u32, u32)>>,
+    /// Distance between the hashes of each quadrant on its own: top-left,
+    /// top-right, bottom-left, bottom-right
+    pub quadrant_distances: [u32; 4],
+    /// Absolute grayscale difference of the two images reduced to
+    /// `HEATMAP_SIZE` x `HEATMAP_SIZE`, if asked for
+    pub heatmap: Option<GrayImage>,
+}
+
+impl Comparison {
+    /// Quadrant with the largest distance, as an index into
+    /// `quadrant_distances`; the first on ties
+    pub fn most_changed_quadrant(&self) -> usize {
+        let max = self.quadrant_distances.iter().copied().max().unwrap_or(0);
+        self.quadrant_distances
+            .iter()
+            .position(|&d| d == max)
+            .unwrap_or(0)
+    }
+}
+
+/// Compare two image files in detail (see `compare_images`)
+pub fn compare_paths<P: AsRef<Path>, Q: AsRef<Path>>(
+    path1: P,
+    path2: Q,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    heatmap: bool,
+) -> Result<Comparison, HashError> {
+    let a = open_image(path1.as_ref())?;
+    let b = open_image(path2.as_ref())?;
+    compare_images(&a, &b, algorithm, hash_size, heatmap)
+}
+
+/// Compare two loaded images in detail
+///
+/// Both are hashed with `algorithm` and `hash_size` as a whole and per
+/// quadrant. Quadrant hashes are independent of the whole-image hash: DCT
+/// coefficients describe the whole picture, so a differing bit cannot be
+/// pinned to one place, but hashing each quarter shows where the change is.
+pub fn compare_images(
+    a: &DynamicImage,
+    b: &DynamicImage,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    heatmap: bool,
+) -> Result<Comparison, HashError> {
+    let hash_a = ImageHash::from_image(a, algorithm, hash_size)?;
+    let hash_b = ImageHash::from_image(b, algorithm, hash_size)?;
+    let agreement: Vec<bool> = hash_a
+        .to_bits()
+        .into_iter()
+        .zip(hash_b.to_bits())
+        .map(|(x, y)| x == y)
+        .collect();
+
+    let frequency_bands = match algorithm {
+        HashAlgorithm::PHash | HashAlgorithm::ColorHash => {
+            Some(frequency_bands(&agreement, hash_size))
+        }
+        _ => None,
+    };
+
+    let mut quadrant_distances = [0; 4];
+    for (i, distance) in quadrant_distances.iter_mut().enumerate() {
+        let qa = ImageHash::from_image(&quadrant(a, i), algorithm, hash_size)?;
+        let qb = ImageHash::from_image(&quadrant(b, i), algorithm, hash_size)?;
+        *distance = qa.distance(&qb);
+    }
+
+    Ok(Comparison {
+        distance: hash_a.checked_distance(&hash_b)?,
+        agreement,
+        frequency_bands,
+        quadrant_distances,
+        heatmap: heatmap.then(|| difference_map(a, b)),
+    })
+}
+
+/// Differing and total bits per band of each `hash_size` x `hash_size`
+/// bit plane, summed over the planes
+fn frequency_bands(agreement: &[bool], hash_size: usize) -> Vec<(u32, u32)> {
+    let plane = hash_size * hash_size;
+    let stride = plane.div_ceil(8) * 8;
+    let mut bands = vec![(0, 0); hash_size];
+    for start in (0..agreement.len()).step_by(stride) {
+        for (i, &agrees) in agreement[start..].iter().take(plane).enumerate() {
+            let band = &mut bands[(i / hash_size).max(i % hash_size)];
+            band.0 += u32::from(!agrees);
+            band.1 += 1;
+        }
+    }
+    bands
+}
+
+/// Quadrant `index` of `img` (see `Comparison::quadrant_distances`)
+///
+/// Images narrower or lower than 2 pixels share their one column or row
+/// between quadrants.
+fn quadrant(img: &DynamicImage, index: usize) -> DynamicImage {
+    let (width, height) = (img.width(), img.height());
+    let (w, h) = ((width / 2).max(1), (height / 2).max(1));
+    let x = if index % 2 == 1 { width - w } else { 0 };
+    let y = if index >= 2 { height - h } else { 0 };
+    img.crop_imm(x, y, w, h)
+}
+
+fn difference_map(a: &DynamicImage, b: &DynamicImage) -> GrayImage {
+    let small = |img: &DynamicImage| {
+        img.resize_exact(HEATMAP_SIZE, HEATMAP_SIZE, FilterType::Triangle)
+            .to_luma8()
+    };
+    let (a, b) = (small(a), small(b));
+    GrayImage::from_fn(HEATMAP_SIZE, HEATMAP_SIZE, |x, y| {
+        Luma([a.get_pixel(x, y).0[0].abs_diff(b.get_pixel(x, y).0[0])])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn gradient() -> RgbImage {
+        RgbImage::from_fn(128, 128, |x, y| {
+            Rgb([(x * 2) as u8, (y * 2) as u8, ((x * y) % 256) as u8])
+        })
+    }
+
+    #[test]
+    fn test_identical_images_agree() {
+        let img = DynamicImage::ImageRgb8(gradient());
+        let comparison = compare_images(&img, &img, HashAlgorithm::PHash, 8, true).unwrap();
+        assert_eq!(comparison.distance, 0);
+        assert_eq!(comparison.agreement.len(), 64);
+        assert!(comparison.agreement.iter().all(|&a| a));
+        assert_eq!(comparison.quadrant_distances, [0; 4]);
+        let bands = comparison.frequency_bands.unwrap();
+        assert_eq!(bands.len(), 8);
+        assert_eq!(bands.iter().map(|b| b.1).sum::<u32>(), 64);
+        assert_eq!(bands[0], (0, 1));
+        let heatmap = comparison.heatmap.unwrap();
+        assert_eq!(heatmap.dimensions(), (HEATMAP_SIZE, HEATMAP_SIZE));
+        assert!(heatmap.pixels().all(|p| p.0[0] == 0));
+    }
+
+    #[test]
+    fn test_change_is_located() {
+        let original = gradient();
+        let mut edited = original.clone();
+        for y in 64..128 {
+            for x in 64..128 {
+                edited.put_pixel(x, y, Rgb([255, 255, 255]));
+            }
+        }
+        let comparison = compare_images(
+            &DynamicImage::ImageRgb8(original),
+            &DynamicImage::ImageRgb8(edited),
+            HashAlgorithm::PHash,
+            8,
+            true,
+        )
+        .unwrap();
+        assert_eq!(&comparison.quadrant_distances[..3], &[0, 0, 0]);
+        assert!(comparison.quadrant_distances[3] > 0);
+        assert_eq!(comparison.most_changed_quadrant(), 3);
+        assert_eq!(
+            comparison.distance,
+            comparison.agreement.iter().filter(|&&a| !a).count() as u32
+        );
+
+        let heatmap = comparison.heatmap.unwrap();
+        assert_eq!(heatmap.get_pixel(4, 4).0[0], 0);
+        assert!(heatmap.get_pixel(28, 28).0[0] > 0);
+    }
+
+    #[test]
+    fn test_bands_only_for_dct_hashes() {
+        let img = DynamicImage::ImageRgb8(gradient());
+        let colorhash = compare_images(&img, &img, HashAlgorithm::ColorHash, 5, false).unwrap();
+        let bands = colorhash.frequency_bands.unwrap();
+        assert_eq!(bands.iter().map(|b| b.1).sum::<u32>(), 3 * 25);
+        assert!(colorhash.heatmap.is_none());
+
+        let blockhash = compare_images(&img, &img, HashAlgorithm::BlockHash, 8, false).unwrap();
+        assert!(blockhash.frequency_bands.is_none());
+    }
+}
@@ -0,0 +1,267 @@
+//! Content Type Module
+//!
+//! Labels images as photos, screenshots, graphics or scanned documents from
+//! a few cheap statistics, so each kind can be hashed with settings that
+//! suit it. A threshold that merges burst photos also merges unrelated
+//! screenshots of the same app, and one that separates screenshots misses
+//! re-encoded photos.
+
+use crate::decode::open_image;
+use crate::error::HashError;
+use crate::hash::HashAlgorithm;
+use crate::orientation::has_exif;
+use crate::progress::Progress;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Edge length of the thumbnail the statistics are computed on
+const ANALYSIS_SIZE: u32 = 128;
+
+/// Luma difference to a neighbour that counts as an edge
+const EDGE_STEP: i32 = 48;
+
+/// What an image shows, as far as hashing is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ContentType {
+    /// Camera or phone photo
+    Photo,
+    /// Screen capture: flat background with sharp text and UI edges
+    Screenshot,
+    /// Illustration, logo, chart: few flat colors
+    Graphic,
+    /// Scanned or rendered page: mostly white, nearly grayscale
+    Document,
+}
+
+impl ContentType {
+    /// All content types
+    pub const ALL: [ContentType; 4] = [
+        ContentType::Photo,
+        ContentType::Screenshot,
+        ContentType::Graphic,
+        ContentType::Document,
+    ];
+
+    /// Hashing settings suited to this kind of image
+    pub fn preset(self) -> HashPreset {
+        match self {
+            ContentType::Photo => HashPreset {
+                algorithm: HashAlgorithm::PHash,
+                threshold: 10,
+            },
+            // Different screens of one app differ in small details only
+            ContentType::Screenshot => HashPreset {
+                algorithm: HashAlgorithm::PHash,
+                threshold: 4,
+            },
+            // Recolored variants are distinct graphics; 3x the bits of pHash
+            ContentType::Graphic => HashPreset {
+                algorithm: HashAlgorithm::ColorHash,
+                threshold: 12,
+            },
+            // Pages share layout, so only the text block structure tells
+            // them apart
+            ContentType::Document => HashPreset {
+                algorithm: HashAlgorithm::WHash { level: None },
+                threshold: 4,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentType::Photo => write!(f, "photo"),
+            ContentType::Screenshot => write!(f, "screenshot"),
+            ContentType::Graphic => write!(f, "graphic"),
+            ContentType::Document => write!(f, "document"),
+        }
+    }
+}
+
+impl FromStr for ContentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ContentType::ALL
+            .into_iter()
+            .find(|t| t.to_string() == s.trim().to_ascii_lowercase())
+            .ok_or_else(|| format!("Unknown content type: {}", s))
+    }
+}
+
+/// Algorithm and threshold used for one content type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashPreset {
+    pub algorithm: HashAlgorithm,
+    pub threshold: u32,
+}
+
+/// Statistics the classifier decides on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentFeatures {
+    /// Fraction of pixels differing sharply from a neighbour
+    pub edge_density: f64,
+    /// Distinct colors (5 bits per channel) per pixel
+    pub color_ratio: f64,
+    /// Fraction of pixels with the most common color
+    pub dominant_fraction: f64,
+    /// Fraction of near-white pixels
+    pub light_fraction: f64,
+    /// Mean channel spread (max - min) per pixel, 0-1
+    pub saturation: f64,
+    /// The file carries EXIF data
+    pub has_exif: bool,
+}
+
+impl ContentFeatures {
+    /// Compute the statistics on a small nearest-neighbour thumbnail
+    ///
+    /// Nearest-neighbour sampling keeps flat colors flat, which a smoothing
+    /// filter would turn into gradients.
+    pub fn from_image(img: &DynamicImage, has_exif: bool) -> Self {
+        let rgb = img
+            .resize_exact(ANALYSIS_SIZE, ANALYSIS_SIZE, FilterType::Nearest)
+            .to_rgb8();
+        let (width, height) = rgb.dimensions();
+        let pixels = (width * height) as f64;
+
+        let luma = |x: u32, y: u32| {
+            let [r, g, b] = rgb.get_pixel(x, y).0;
+            (299 * r as i32 + 587 * g as i32 + 114 * b as i32) / 1000
+        };
+
+        let mut edges = 0usize;
+        let mut light = 0usize;
+        let mut spread = 0u64;
+        let mut counts = HashMap::new();
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b] = rgb.get_pixel(x, y).0;
+                let l = luma(x, y);
+                let right = (x + 1 < width).then(|| luma(x + 1, y));
+                let below = (y + 1 < height).then(|| luma(x, y + 1));
+                if [right, below]
+                    .into_iter()
+                    .flatten()
+                    .any(|n| (n - l).abs() >= EDGE_STEP)
+                {
+                    edges += 1;
+                }
+                if l >= 224 {
+                    light += 1;
+                }
+                spread += (r.max(g).max(b) - r.min(g).min(b)) as u64;
+                *counts.entry((r >> 3, g >> 3, b >> 3)).or_insert(0usize) += 1;
+            }
+        }
+        let dominant = counts.values().copied().max().unwrap_or(0);
+
+        ContentFeatures {
+            edge_density: edges as f64 / pixels,
+            color_ratio: counts.len() as f64 / pixels,
+            dominant_fraction: dominant as f64 / pixels,
+            light_fraction: light as f64 / pixels,
+            saturation: spread as f64 / pixels / 255.0,
+            has_exif,
+        }
+    }
+
+    /// Label the image these statistics were computed on
+    pub fn classify(&self) -> ContentType {
+        if self.light_fraction >= 0.6 && self.saturation < 0.08 && self.edge_density >= 0.02 {
+            ContentType::Document
+        } else if self.has_exif {
+            // Only cameras (and photo editors) write EXIF
+            ContentType::Photo
+        } else if self.dominant_fraction >= 0.25 && self.edge_density >= 0.05 {
+            ContentType::Screenshot
+        } else if self.color_ratio < 0.05 {
+            ContentType::Graphic
+        } else {
+            ContentType::Photo
+        }
+    }
+}
+
+/// Classify a decoded image
+pub fn classify_image(img: &DynamicImage, has_exif: bool) -> ContentType {
+    ContentFeatures::from_image(img, has_exif).classify()
+}
+
+/// Decode and classify the image at `path`
+pub fn classify_path(path: &Path) -> Result<ContentType, HashError> {
+    let img = open_image(path)?;
+    Ok(classify_image(&img, has_exif(path)))
+}
+
+/// Classify `paths` in parallel, in input order
+///
+/// Stops early, returning only the paths done so far, once `progress`
+/// cancels.
+pub fn classify_paths(
+    paths: &[PathBuf],
+    progress: Option<&dyn Progress>,
+) -> Vec<(PathBuf, Result<ContentType, HashError>)> {
+    paths
+        .par_iter()
+        .filter(|_| !progress.is_some_and(|p| p.is_cancelled()))
+        .map(|path| (path.clone(), classify_path(path)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_classify_synthetic_images() {
+        // White page with rows of black "text"
+        let page = RgbImage::from_fn(256, 256, |x, y| {
+            if y % 16 < 4 && x % 12 < 8 && (16..240).contains(&x) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let page = DynamicImage::ImageRgb8(page);
+        assert_eq!(classify_image(&page, false), ContentType::Document);
+
+        // Two flat colored shapes
+        let logo = RgbImage::from_fn(256, 256, |x, y| {
+            if (x as i32 - 128).pow(2) + (y as i32 - 128).pow(2) < 80 * 80 {
+                Rgb([200, 30, 30])
+            } else {
+                Rgb([30, 30, 200])
+            }
+        });
+        let logo = DynamicImage::ImageRgb8(logo);
+        assert_eq!(classify_image(&logo, false), ContentType::Graphic);
+
+        // Noisy, colorful content reads as a photo
+        let mut rng = SeededRng::new(7);
+        let noise = RgbImage::from_fn(256, 256, |_, _| {
+            let v = rng.next_u64();
+            Rgb([v as u8, (v >> 8) as u8, (v >> 16) as u8])
+        });
+        let noise = DynamicImage::ImageRgb8(noise);
+        assert_eq!(classify_image(&noise, false), ContentType::Photo);
+        assert_eq!(classify_image(&logo, true), ContentType::Photo);
+    }
+
+    #[test]
+    fn test_content_type_roundtrip() {
+        for t in ContentType::ALL {
+            assert_eq!(t.to_string().parse::<ContentType>(), Ok(t));
+        }
+        assert!("video".parse::<ContentType>().is_err());
+    }
+}
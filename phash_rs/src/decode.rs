@@ -0,0 +1,839 @@
+//! Image Decoding Module
+//!
+//! Routes each input format to an ordered list of decoder backends so that
+//! deployments can use native libraries where they are available and fall
+//! back gracefully where they are not.
+
+use crate::error::HashError;
+use crate::orientation::{apply_orientation, exif_orientation};
+#[cfg(feature = "gif")]
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+#[cfg(feature = "webp")]
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageError, ImageFormat, ImageReader, Limits};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Placeholder replaced with the input path in external decoder commands
+pub const EXTERNAL_PATH_PLACEHOLDER: &str = "{path}";
+
+/// Camera RAW extensions handled by `DecodeBackend::Raw`
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "arw", "cr2", "cr3", "dcr", "dng", "erf", "iiq", "kdc", "mef", "mos", "nef", "nrw",
+    "orf", "pef", "raf", "rw2", "sr2", "srf", "srw",
+];
+
+/// A decoder implementation that can turn a file into a `DynamicImage`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DecodeBackend {
+    /// The pure-Rust `image` crate
+    Image,
+    /// libjpeg-turbo via the `turbojpeg` crate (feature `turbojpeg`)
+    TurboJpeg,
+    /// libheif via the `libheif-rs` crate (feature `heif`)
+    LibHeif,
+    /// The embedded JPEG preview of a camera RAW file, or the sensor data
+    /// via the `rawloader` crate if there is none (feature `raw`)
+    Raw,
+    /// The largest JPEG image embedded in a PDF (feature `pdf`)
+    Pdf,
+    /// A user-configured command that writes an encoded image to stdout
+    External,
+    /// A decode function registered for the extension with
+    /// `DecoderConfig::set_custom_decoder`
+    Custom,
+}
+
+/// A user-supplied decoder: reads the file at the path into an image
+pub type DecodeHook = Arc<dyn Fn(&Path) -> Result<DynamicImage, String> + Send + Sync>;
+
+impl DecodeBackend {
+    /// Stable lowercase name used by the Python bindings
+    pub fn name(&self) -> &'static str {
+        match self {
+            DecodeBackend::Image => "image",
+            DecodeBackend::TurboJpeg => "turbojpeg",
+            DecodeBackend::LibHeif => "libheif",
+            DecodeBackend::Raw => "raw",
+            DecodeBackend::Pdf => "pdf",
+            DecodeBackend::External => "external",
+            DecodeBackend::Custom => "custom",
+        }
+    }
+
+    /// Whether this backend was compiled into the library
+    pub fn is_compiled(&self) -> bool {
+        match self {
+            DecodeBackend::Image | DecodeBackend::External | DecodeBackend::Custom => true,
+            DecodeBackend::TurboJpeg => cfg!(feature = "turbojpeg"),
+            DecodeBackend::LibHeif => cfg!(feature = "heif"),
+            DecodeBackend::Raw => cfg!(feature = "raw"),
+            DecodeBackend::Pdf => cfg!(feature = "pdf"),
+        }
+    }
+
+    /// Whether this backend can decode files with the given extension
+    fn handles(&self, ext: &str, config: &DecoderConfig) -> bool {
+        match self {
+            DecodeBackend::Image => ImageFormat::from_extension(ext)
+                .map(|f| f.reading_enabled())
+                .unwrap_or(false),
+            DecodeBackend::TurboJpeg => matches!(ext, "jpg" | "jpeg" | "jpe" | "jfif"),
+            DecodeBackend::LibHeif => matches!(ext, "heic" | "heif" | "hif" | "avif"),
+            DecodeBackend::Raw => RAW_EXTENSIONS.contains(&ext),
+            DecodeBackend::Pdf => ext == "pdf",
+            DecodeBackend::External => config.external_command.is_some(),
+            DecodeBackend::Custom => config.custom.0.contains_key(ext),
+        }
+    }
+}
+
+impl std::str::FromStr for DecodeBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "image" => Ok(DecodeBackend::Image),
+            "turbojpeg" => Ok(DecodeBackend::TurboJpeg),
+            "libheif" | "heif" => Ok(DecodeBackend::LibHeif),
+            "raw" => Ok(DecodeBackend::Raw),
+            "pdf" => Ok(DecodeBackend::Pdf),
+            "external" => Ok(DecodeBackend::External),
+            "custom" => Ok(DecodeBackend::Custom),
+            _ => Err(format!("Unknown decode backend: {}", s)),
+        }
+    }
+}
+
+/// Caps on what is decoded, so one huge image can't exhaust memory
+///
+/// Files over a limit fail with `HashError::TooLarge` before they are
+/// decoded; batch functions report them as failures and carry on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Largest width x height decoded. Read from the file header for the
+    /// formats the `image` crate knows (TIFF, PNG, JPEG, ...); other
+    /// formats are only held to `max_file_size`.
+    pub max_pixels: Option<u64>,
+    /// Largest file size in bytes
+    pub max_file_size: Option<u64>,
+}
+
+/// Bytes per pixel allowed for the decoder's buffers under `max_pixels`,
+/// enough for 32-bit float RGBA
+const MAX_BYTES_PER_PIXEL: u64 = 16;
+
+/// Decode hooks by extension; only the extensions are shown by `Debug`
+#[derive(Clone, Default)]
+struct CustomDecoders(HashMap<String, DecodeHook>);
+
+impl std::fmt::Debug for CustomDecoders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// Which backends are tried, in order, for each file extension
+#[derive(Debug, Clone, Default)]
+pub struct DecoderConfig {
+    /// Per-extension overrides (lowercase, without the dot)
+    overrides: HashMap<String, Vec<DecodeBackend>>,
+    /// Command used by `DecodeBackend::External`, e.g.
+    /// `["magick", "{path}", "png:-"]`
+    external_command: Option<Vec<String>>,
+    custom: CustomDecoders,
+    /// Keep pixels in stored order instead of applying EXIF orientation
+    ignore_exif_orientation: bool,
+    limits: DecodeLimits,
+}
+
+impl DecoderConfig {
+    /// Set the backend chain for one extension
+    pub fn set_backends(&mut self, ext: &str, backends: Vec<DecodeBackend>) {
+        self.overrides.insert(normalize_ext(ext), backends);
+    }
+
+    /// Set (or clear) the external decoder command
+    pub fn set_external_command(&mut self, command: Option<Vec<String>>) {
+        self.external_command = command.filter(|c| !c.is_empty());
+    }
+
+    /// Register (or remove) a decode function for one extension
+    ///
+    /// Unless the extension's chain is overridden with `set_backends`, the
+    /// function is tried before the built-in backends, and directory scans
+    /// pick the extension up. Its images are used as returned, without
+    /// EXIF orientation applied.
+    pub fn set_custom_decoder(&mut self, ext: &str, decoder: Option<DecodeHook>) {
+        let ext = normalize_ext(ext);
+        match decoder {
+            Some(decoder) => self.custom.0.insert(ext, decoder),
+            None => self.custom.0.remove(&ext),
+        };
+    }
+
+    /// Turn decoded images upright according to their EXIF orientation
+    /// (enabled by default)
+    pub fn set_apply_exif_orientation(&mut self, apply: bool) {
+        self.ignore_exif_orientation = !apply;
+    }
+
+    /// Whether decoded images are turned upright by their EXIF orientation
+    pub fn applies_exif_orientation(&self) -> bool {
+        !self.ignore_exif_orientation
+    }
+
+    /// Set the size caps applied before decoding (none by default)
+    pub fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    /// The size caps applied before decoding
+    pub fn limits(&self) -> DecodeLimits {
+        self.limits
+    }
+
+    /// Fail with `HashError::TooLarge` if `path` exceeds the limits
+    fn check_limits(&self, path: &Path, file_size: u64) -> Result<(), HashError> {
+        if let Some(max) = self.limits.max_file_size {
+            if file_size > max {
+                return Err(HashError::TooLarge(format!(
+                    "{}: {} bytes, limit {}",
+                    path.display(),
+                    file_size,
+                    max
+                )));
+            }
+        }
+        if let Some(max) = self.limits.max_pixels {
+            let size = ImageReader::open(path)
+                .and_then(|r| r.with_guessed_format())
+                .ok()
+                .and_then(|r| r.into_dimensions().ok());
+            if let Some((width, height)) = size {
+                if width as u64 * height as u64 > max {
+                    return Err(HashError::TooLarge(format!(
+                        "{}: {}x{} pixels, limit {}",
+                        path.display(),
+                        width,
+                        height,
+                        max
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Allocation limits for the `image` crate's decoders
+    fn image_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        if let Some(max) = self.limits.max_pixels {
+            limits.max_alloc = Some(max.saturating_mul(MAX_BYTES_PER_PIXEL));
+        }
+        limits
+    }
+
+    /// Backend chain for an extension, falling back to the built-in defaults
+    pub fn backends_for(&self, ext: &str) -> Vec<DecodeBackend> {
+        let ext = normalize_ext(ext);
+        if let Some(backends) = self.overrides.get(&ext) {
+            return backends.clone();
+        }
+        let mut chain = if self.custom.0.contains_key(&ext) {
+            vec![DecodeBackend::Custom]
+        } else {
+            Vec::new()
+        };
+        chain.extend(match ext.as_str() {
+            "jpg" | "jpeg" | "jpe" | "jfif" => vec![
+                DecodeBackend::TurboJpeg,
+                DecodeBackend::Image,
+                DecodeBackend::External,
+            ],
+            "heic" | "heif" | "hif" => vec![DecodeBackend::LibHeif, DecodeBackend::External],
+            // libheif decodes AVIF when built with an AV1 decoder, the
+            // image crate with the `avif` feature
+            "avif" => vec![
+                DecodeBackend::LibHeif,
+                DecodeBackend::Image,
+                DecodeBackend::External,
+            ],
+            "pdf" => vec![DecodeBackend::Pdf, DecodeBackend::External],
+            ext if RAW_EXTENSIONS.contains(&ext) => {
+                vec![DecodeBackend::Raw, DecodeBackend::External]
+            }
+            _ => vec![DecodeBackend::Image, DecodeBackend::External],
+        });
+        chain
+    }
+
+    /// Backends that are compiled in, configured and able to handle `ext`
+    pub fn available_backends_for(&self, ext: &str) -> Vec<DecodeBackend> {
+        let ext = normalize_ext(ext);
+        self.backends_for(&ext)
+            .into_iter()
+            .filter(|b| b.is_compiled() && b.handles(&ext, self))
+            .collect()
+    }
+
+    /// Map of every known extension to the backends that can decode it
+    ///
+    /// Extensions with no usable backend are included with an empty list so
+    /// callers can tell "unsupported here" apart from "unknown format".
+    pub fn supported_formats(&self) -> BTreeMap<String, Vec<&'static str>> {
+        let mut exts: Vec<String> = ImageFormat::all()
+            .flat_map(|f| f.extensions_str().iter().map(|e| e.to_string()))
+            .collect();
+        exts.extend(["heic", "heif", "hif", "pdf"].iter().map(|e| e.to_string()));
+        exts.extend(RAW_EXTENSIONS.iter().map(|e| e.to_string()));
+        exts.extend(self.overrides.keys().cloned());
+        exts.extend(self.custom.0.keys().cloned());
+
+        exts.into_iter()
+            .map(|ext| {
+                let names = self
+                    .available_backends_for(&ext)
+                    .iter()
+                    .map(|b| b.name())
+                    .collect();
+                (ext, names)
+            })
+            .collect()
+    }
+
+    /// Decode an image file using the configured backend chain
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<DynamicImage, HashError> {
+        self.open_reporting(path).map(|(img, _)| img)
+    }
+
+    /// Like `open`, also returning the `"backend: error"` failures of the
+    /// backends tried before the one that decoded the file
+    pub fn open_reporting<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(DynamicImage, Vec<String>), HashError> {
+        let path = path.as_ref();
+        let meta = std::fs::metadata(path)
+            .map_err(|e| HashError::Io(format!("{}: {}", path.display(), e)))?;
+        self.check_limits(path, meta.len())?;
+        let ext = path
+            .extension()
+            .map(|e| normalize_ext(&e.to_string_lossy()))
+            .unwrap_or_default();
+
+        let mut errors = Vec::new();
+        for backend in self.backends_for(&ext) {
+            if !backend.is_compiled() || !backend.handles(&ext, self) {
+                continue;
+            }
+            match self.decode_with(backend, path) {
+                Ok(img) => return Ok((self.orient(backend, path, img), errors)),
+                Err(e) => errors.push(format!("{}: {}", backend.name(), e)),
+            }
+        }
+
+        if errors.is_empty() {
+            Err(HashError::UnsupportedFormat(ext))
+        } else {
+            Err(HashError::Decode(errors.join("; ")))
+        }
+    }
+
+    /// Decode `data`, the contents of `path`, without reading the file again
+    ///
+    /// Only the `image` backend decodes from memory: if it isn't first in
+    /// the chain for the extension, or fails, the chain runs on `path` as
+    /// with `open`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn open_read(&self, path: &Path, data: &[u8]) -> Result<DynamicImage, HashError> {
+        let ext = path
+            .extension()
+            .map(|e| normalize_ext(&e.to_string_lossy()))
+            .unwrap_or_default();
+        self.check_limits(path, data.len() as u64)?;
+        if self.available_backends_for(&ext).first() == Some(&DecodeBackend::Image) {
+            let mut reader = ImageReader::new(std::io::Cursor::new(data))
+                .with_guessed_format()
+                .map_err(|e| HashError::Io(e.to_string()))?;
+            reader.limits(self.image_limits());
+            if let Ok(img) = reader.decode() {
+                return Ok(match crate::orientation::orientation_from_bytes(data) {
+                    Some(orientation) if !self.ignore_exif_orientation => {
+                        apply_orientation(img, orientation)
+                    }
+                    _ => img,
+                });
+            }
+        }
+        self.open(path)
+    }
+
+    fn orient(&self, backend: DecodeBackend, path: &Path, img: DynamicImage) -> DynamicImage {
+        // libheif applies the container's transformations itself, PDFs
+        // carry no EXIF orientation and custom decoders return final pixels
+        if self.ignore_exif_orientation
+            || matches!(
+                backend,
+                DecodeBackend::LibHeif | DecodeBackend::Pdf | DecodeBackend::Custom
+            )
+        {
+            return img;
+        }
+        match exif_orientation(path) {
+            Some(orientation) => apply_orientation(img, orientation),
+            None => img,
+        }
+    }
+
+    fn decode_with(&self, backend: DecodeBackend, path: &Path) -> Result<DynamicImage, String> {
+        match backend {
+            DecodeBackend::Image => {
+                let mut reader = ImageReader::open(path).map_err(|e| e.to_string())?;
+                reader.limits(self.image_limits());
+                reader.decode().map_err(|e| e.to_string())
+            }
+            DecodeBackend::TurboJpeg => decode_turbojpeg(path),
+            DecodeBackend::LibHeif => decode_libheif(path),
+            DecodeBackend::Raw => decode_raw(path),
+            DecodeBackend::Pdf => decode_pdf(path),
+            DecodeBackend::External => {
+                let command = self
+                    .external_command
+                    .as_ref()
+                    .ok_or_else(|| "no external command configured".to_string())?;
+                decode_external(command, path)
+            }
+            DecodeBackend::Custom => {
+                let ext = path
+                    .extension()
+                    .map(|e| normalize_ext(&e.to_string_lossy()))
+                    .unwrap_or_default();
+                let decoder = self
+                    .custom
+                    .0
+                    .get(&ext)
+                    .ok_or_else(|| "no custom decoder registered".to_string())?;
+                decoder(path)
+            }
+        }
+    }
+}
+
+pub(crate) fn normalize_ext(ext: &str) -> String {
+    ext.trim().trim_start_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(feature = "turbojpeg")]
+fn decode_turbojpeg(path: &Path) -> Result<DynamicImage, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let img: image::RgbImage = turbojpeg::decompress_image(&data).map_err(|e| e.to_string())?;
+    Ok(DynamicImage::ImageRgb8(img))
+}
+
+#[cfg(not(feature = "turbojpeg"))]
+fn decode_turbojpeg(_path: &Path) -> Result<DynamicImage, String> {
+    Err("not compiled in".to_string())
+}
+
+#[cfg(feature = "heif")]
+fn decode_libheif(path: &Path) -> Result<DynamicImage, String> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let path_str = path.to_str().ok_or("non UTF-8 path")?;
+    let ctx = HeifContext::read_from_file(path_str).map_err(|e| e.to_string())?;
+    let handle = ctx.primary_image_handle().map_err(|e| e.to_string())?;
+    let decoded = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .map_err(|e| e.to_string())?;
+
+    let planes = decoded.planes();
+    let plane = planes.interleaved.ok_or("missing interleaved plane")?;
+    let (width, height) = (plane.width, plane.height);
+    let row_len = width as usize * 3;
+    let mut raw = Vec::with_capacity(row_len * height as usize);
+    for row in plane.data.chunks(plane.stride).take(height as usize) {
+        raw.extend_from_slice(&row[..row_len]);
+    }
+
+    image::RgbImage::from_raw(width, height, raw)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| "invalid HEIF plane size".to_string())
+}
+
+#[cfg(not(feature = "heif"))]
+fn decode_libheif(_path: &Path) -> Result<DynamicImage, String> {
+    Err("not compiled in".to_string())
+}
+
+#[cfg(feature = "raw")]
+fn decode_raw(path: &Path) -> Result<DynamicImage, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    // The camera's own rendering, as in the JPEG it saves alongside
+    let preview_error = match crate::embedded::decode_largest_jpeg(&data) {
+        Ok(img) => return Ok(img),
+        Err(e) => e,
+    };
+    decode_raw_sensor(path).map_err(|e| format!("{}; sensor data: {}", preview_error, e))
+}
+
+/// Half-resolution RGB from the sensor data: each 2x2 cell of the color
+/// filter array becomes one pixel, white balanced and gamma encoded
+#[cfg(feature = "raw")]
+fn decode_raw_sensor(path: &Path) -> Result<DynamicImage, String> {
+    let raw = rawloader::decode_file(path).map_err(|e| e.to_string())?;
+    let rawloader::RawImageData::Integer(data) = &raw.data else {
+        return Err("floating-point sensor data is not supported".to_string());
+    };
+    if raw.cpp != 1 || raw.width < 2 || raw.height < 2 {
+        return Err("unsupported sensor layout".to_string());
+    }
+
+    let black = raw.blacklevels[0] as f32;
+    let range = (raw.whitelevels[0] as f32 - black).max(1.0);
+    // Color indices 0-3 are red, green, blue and a second green
+    let mut balance = [1.0f32; 4];
+    if raw.wb_coeffs[..3].iter().all(|c| c.is_finite() && *c > 0.0) {
+        let green = raw.wb_coeffs[1];
+        balance = [raw.wb_coeffs[0] / green, 1.0, raw.wb_coeffs[2] / green, 1.0];
+    }
+    let (width, height) = (raw.width / 2, raw.height / 2);
+    let img = image::RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        let mut sums = [0.0f32; 3];
+        let mut counts = [0u32; 3];
+        for (row, col) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            let (row, col) = (y as usize * 2 + row, x as usize * 2 + col);
+            let color = raw.cfa.color_at(row, col);
+            let value = (data[row * raw.width + col] as f32 - black).max(0.0) / range;
+            let channel = if color == 3 { 1 } else { color.min(2) };
+            sums[channel] += value * balance[color.min(3)];
+            counts[channel] += 1;
+        }
+        image::Rgb(std::array::from_fn(|c| {
+            let linear = (sums[c] / counts[c].max(1) as f32).min(1.0);
+            (linear.powf(1.0 / 2.2) * 255.0).round() as u8
+        }))
+    });
+    Ok(DynamicImage::ImageRgb8(img))
+}
+
+#[cfg(not(feature = "raw"))]
+fn decode_raw(_path: &Path) -> Result<DynamicImage, String> {
+    Err("not compiled in".to_string())
+}
+
+/// The largest image of the PDF that is stored as a plain JPEG stream
+/// (DCTDecode); pages are not rendered
+#[cfg(feature = "pdf")]
+fn decode_pdf(path: &Path) -> Result<DynamicImage, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    crate::embedded::decode_largest_jpeg(&data)
+}
+
+#[cfg(not(feature = "pdf"))]
+fn decode_pdf(_path: &Path) -> Result<DynamicImage, String> {
+    Err("not compiled in".to_string())
+}
+
+/// Every image of the PDF stored as a plain JPEG stream, in file order
+#[cfg(feature = "pdf")]
+pub(crate) fn pdf_images(path: &Path) -> Result<Vec<Result<DynamicImage, String>>, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    Ok(crate::embedded::decode_jpegs(&data))
+}
+
+#[cfg(not(feature = "pdf"))]
+pub(crate) fn pdf_images(_path: &Path) -> Result<Vec<Result<DynamicImage, String>>, String> {
+    Err("PDF support not compiled in (feature \"pdf\")".to_string())
+}
+
+/// Run the external command and decode whatever it writes to stdout
+fn decode_external(command: &[String], path: &Path) -> Result<DynamicImage, String> {
+    let path_str = path.to_string_lossy();
+    let args: Vec<String> = command[1..]
+        .iter()
+        .map(|a| a.replace(EXTERNAL_PATH_PLACEHOLDER, &path_str))
+        .collect();
+
+    let output = Command::new(&command[0])
+        .args(&args)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", command[0], e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "{} exited with {}: {}",
+            command[0],
+            output.status,
+            stderr.trim()
+        ));
+    }
+
+    image::load_from_memory(&output.stdout).map_err(|e| e.to_string())
+}
+
+fn global() -> &'static RwLock<DecoderConfig> {
+    static CONFIG: OnceLock<RwLock<DecoderConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| RwLock::new(DecoderConfig::default()))
+}
+
+/// Snapshot of the process-wide decoder configuration
+pub fn decoder_config() -> DecoderConfig {
+    global().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Modify the process-wide decoder configuration
+pub fn configure_decoder<F: FnOnce(&mut DecoderConfig)>(f: F) {
+    let mut config = global().write().unwrap_or_else(|e| e.into_inner());
+    f(&mut config);
+}
+
+/// Whether the process-wide configuration applies EXIF orientation
+pub(crate) fn exif_orientation_enabled() -> bool {
+    global()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .applies_exif_orientation()
+}
+
+/// Decode an image file using the process-wide configuration
+pub fn open_image<P: AsRef<Path>>(path: P) -> Result<DynamicImage, HashError> {
+    global()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .open(path)
+}
+
+/// Width and height of an image file, as decoding would give them
+///
+/// Read from the file header for the formats the `image` crate knows,
+/// swapped for EXIF orientations that rotate by 90 degrees; other formats
+/// are decoded with the global configuration.
+pub fn image_dimensions<P: AsRef<Path>>(path: P) -> Result<(u32, u32), HashError> {
+    let path = path.as_ref();
+    let header = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .ok()
+        .and_then(|r| r.into_dimensions().ok());
+    let Some((width, height)) = header else {
+        return open_image(path).map(|img| (img.width(), img.height()));
+    };
+    let rotated = exif_orientation_enabled() && exif_orientation(path).is_some_and(|o| o >= 5);
+    Ok(if rotated {
+        (height, width)
+    } else {
+        (width, height)
+    })
+}
+
+/// Decode with the global configuration, also returning fallback
+/// failures; EXIF orientation is applied as `apply_exif_orientation` says
+/// instead of as configured when it is set
+pub fn open_image_oriented<P: AsRef<Path>>(
+    path: P,
+    apply_exif_orientation: Option<bool>,
+) -> Result<(DynamicImage, Vec<String>), HashError> {
+    let config = global().read().unwrap_or_else(|e| e.into_inner());
+    match apply_exif_orientation {
+        Some(apply) if apply != config.applies_exif_orientation() => {
+            let mut config = config.clone();
+            config.set_apply_exif_orientation(apply);
+            config.open_reporting(path)
+        }
+        _ => config.open_reporting(path),
+    }
+}
+
+/// Decode the frames of an animated GIF, WebP or PNG one at a time
+///
+/// Calls `f` with each composited frame in order and stops after `limit`
+/// frames, so only one frame is held in memory. Still images, including
+/// every other format, yield a single frame decoded with the configured
+/// backends. Returns the number of frames passed to `f`.
+pub(crate) fn for_each_frame<P, F>(
+    path: P,
+    limit: Option<usize>,
+    mut f: F,
+) -> Result<usize, HashError>
+where
+    P: AsRef<Path>,
+    F: FnMut(DynamicImage) -> Result<(), HashError>,
+{
+    let path = path.as_ref();
+    let meta =
+        std::fs::metadata(path).map_err(|e| HashError::Io(format!("{}: {}", path.display(), e)))?;
+    global()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .check_limits(path, meta.len())?;
+    let ext = path
+        .extension()
+        .map(|e| normalize_ext(&e.to_string_lossy()))
+        .unwrap_or_default();
+    let reader = || {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| HashError::Io(e.to_string()))
+    };
+    let decode_err = |e: ImageError| HashError::Decode(e.to_string());
+
+    // Animation decoders only exist in the image crate, so other backends
+    // are not consulted for animated files
+    let frames = match ext.as_str() {
+        #[cfg(feature = "gif")]
+        "gif" => Some(
+            GifDecoder::new(reader()?)
+                .map_err(decode_err)?
+                .into_frames(),
+        ),
+        #[cfg(feature = "webp")]
+        "webp" => {
+            let decoder = WebPDecoder::new(reader()?).map_err(decode_err)?;
+            decoder.has_animation().then(|| decoder.into_frames())
+        }
+        "png" | "apng" => {
+            let decoder = PngDecoder::new(reader()?).map_err(decode_err)?;
+            if decoder.is_apng().map_err(decode_err)? {
+                Some(decoder.apng().map_err(decode_err)?.into_frames())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    let Some(frames) = frames else {
+        f(open_image(path)?)?;
+        return Ok(1);
+    };
+
+    let mut count = 0;
+    for frame in frames.take(limit.unwrap_or(usize::MAX)) {
+        f(DynamicImage::ImageRgba8(
+            frame.map_err(decode_err)?.into_buffer(),
+        ))?;
+        count += 1;
+    }
+    if count == 0 {
+        return Err(HashError::Decode("Animation has no frames".to_string()));
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_chains() {
+        let config = DecoderConfig::default();
+        assert_eq!(config.backends_for("JPG")[0], DecodeBackend::TurboJpeg);
+        assert!(config
+            .available_backends_for("png")
+            .contains(&DecodeBackend::Image));
+        // Nothing configured: HEIC is only decodable with libheif compiled in
+        assert_eq!(
+            config.available_backends_for("heic").is_empty(),
+            !cfg!(feature = "heif")
+        );
+        assert_eq!(config.backends_for("NEF")[0], DecodeBackend::Raw);
+        assert_eq!(
+            config.available_backends_for("pdf").is_empty(),
+            !cfg!(feature = "pdf")
+        );
+    }
+
+    #[test]
+    fn test_overrides_and_external() {
+        let mut config = DecoderConfig::default();
+        config.set_backends(".heic", vec![DecodeBackend::External]);
+        assert!(config.available_backends_for("heic").is_empty());
+
+        config.set_external_command(Some(vec!["magick".into(), "{path}".into(), "png:-".into()]));
+        assert_eq!(
+            config.available_backends_for("heic"),
+            vec![DecodeBackend::External]
+        );
+        assert_eq!(config.supported_formats()["heic"], vec!["external"]);
+    }
+
+    #[test]
+    fn test_custom_decoder() {
+        let path = std::env::temp_dir().join(format!("phash_rs_custom_{}.xyz", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        let mut config = DecoderConfig::default();
+        assert_eq!(
+            config.available_backends_for("avif").first() == Some(&DecodeBackend::LibHeif),
+            cfg!(feature = "heif")
+        );
+        assert!(!config.supported_formats().contains_key("xyz"));
+
+        config.set_custom_decoder(
+            ".XYZ",
+            Some(Arc::new(|_: &Path| Ok(DynamicImage::new_rgb8(5, 3)))),
+        );
+        assert_eq!(config.supported_formats()["xyz"], vec!["custom"]);
+        assert_eq!(config.open(&path).unwrap().width(), 5);
+        // Tried before the built-in chain
+        config.set_custom_decoder("png", Some(Arc::new(|_: &Path| Err("no".to_string()))));
+        assert_eq!(config.backends_for("png")[0], DecodeBackend::Custom);
+
+        config.set_custom_decoder("xyz", None);
+        let unsupported = config.open(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(unsupported, HashError::UnsupportedFormat("xyz".into()));
+    }
+
+    #[test]
+    fn test_open_errors_are_typed() {
+        let config = DecoderConfig::default();
+        let missing = config.open("/nonexistent/file.png").unwrap_err();
+        assert_eq!(missing.kind(), "io");
+
+        let dir = std::env::temp_dir();
+        let corrupt = dir.join(format!("phash_rs_corrupt_{}.png", std::process::id()));
+        let unknown = dir.join(format!("phash_rs_unknown_{}.xyz", std::process::id()));
+        std::fs::write(&corrupt, b"not a png").unwrap();
+        std::fs::write(&unknown, b"").unwrap();
+        let decode = config.open(&corrupt).unwrap_err();
+        let unsupported = config.open(&unknown).unwrap_err();
+        std::fs::remove_file(&corrupt).unwrap();
+        std::fs::remove_file(&unknown).unwrap();
+
+        // Each failing backend is named in the message
+        assert!(matches!(&decode, HashError::Decode(msg) if msg.contains("image:")));
+        assert_eq!(unsupported, HashError::UnsupportedFormat("xyz".into()));
+    }
+
+    #[test]
+    fn test_limits_reject_before_decoding() {
+        let path = std::env::temp_dir().join(format!("phash_rs_large_{}.png", std::process::id()));
+        let img = DynamicImage::new_rgb8(64, 48);
+        let mut png = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageFormat::Png).unwrap();
+        std::fs::write(&path, png.get_ref()).unwrap();
+        assert_eq!(image_dimensions(&path).unwrap(), (64, 48));
+
+        let mut config = DecoderConfig::default();
+        config.set_limits(DecodeLimits {
+            max_pixels: Some(64 * 48),
+            ..Default::default()
+        });
+        assert!(config.open(&path).is_ok());
+        config.set_limits(DecodeLimits {
+            max_pixels: Some(1000),
+            ..Default::default()
+        });
+        assert_eq!(config.open(&path).unwrap_err().kind(), "too_large");
+        config.set_limits(DecodeLimits {
+            max_file_size: Some(10),
+            ..Default::default()
+        });
+        assert_eq!(config.open(&path).unwrap_err().kind(), "too_large");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
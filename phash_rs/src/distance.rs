@@ -0,0 +1,541 @@
+//! Hamming Distance Module
+//!
+//! Hashes are stored as 64-bit words, so a 64-bit pHash is compared with
+//! one XOR and one popcount. Batch comparisons pick the fastest kernel the
+//! CPU supports at runtime: AVX2 for single-word hashes, four at a time,
+//! and hardware POPCNT otherwise, with portable code as the fallback.
+//!
+//! `BitWeights` make some bit positions count more than others, e.g. the
+//! low-frequency DCT coefficients of a pHash, which carry most of what the
+//! eye sees.
+//!
+//! `pairwise_distances` compares every two hashes of a large set in tiles
+//! sized to a memory budget, passing on only the pairs a caller asks for,
+//! where a full `distance_matrix` would not fit in memory.
+
+use crate::error::HashError;
+use crate::hash::ImageHash;
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// Weight of each bit position for `ImageHash::weighted_distance`
+///
+/// Positions follow `ImageHash::to_bits`; positions without a weight count
+/// 1.0, so the default weighs every bit equally and gives the plain
+/// Hamming distance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BitWeights {
+    weights: Vec<f64>,
+}
+
+impl BitWeights {
+    /// Weights for the first `weights.len()` bit positions
+    ///
+    /// Fails unless every weight is positive and finite.
+    pub fn new(weights: Vec<f64>) -> Result<Self, HashError> {
+        if weights.iter().any(|w| !(w.is_finite() && *w > 0.0)) {
+            return Err(HashError::InvalidInput(
+                "Bit weights must be positive and finite".to_string(),
+            ));
+        }
+        Ok(Self { weights })
+    }
+
+    /// Every bit counts 1.0
+    pub fn uniform() -> Self {
+        Self::default()
+    }
+
+    /// Weights favouring the low frequencies of a pHash of `hash_size`
+    ///
+    /// pHash bits are the top-left `hash_size` x `hash_size` DCT
+    /// coefficients row by row; the weight falls from 1 to 1/3 with the sum
+    /// of row and column, scaled so the mean weight is 1 and thresholds
+    /// stay comparable to the plain distance. Other algorithms lay their
+    /// bits out differently.
+    pub fn low_frequency(hash_size: usize) -> Self {
+        let size = hash_size.max(1);
+        let raw: Vec<f64> = (0..size * size)
+            .map(|i| {
+                let band = (i / size + i % size) as f64 / (size - 1).max(1) as f64;
+                1.0 / (1.0 + band)
+            })
+            .collect();
+        let mean = raw.iter().sum::<f64>() / raw.len() as f64;
+        Self {
+            weights: raw.iter().map(|w| w / mean).collect(),
+        }
+    }
+
+    /// Weight of bit position `bit`
+    pub fn weight(&self, bit: usize) -> f64 {
+        self.weights.get(bit).copied().unwrap_or(1.0)
+    }
+
+    /// Smallest weight among the first `bit_len` positions
+    pub(crate) fn min_weight(&self, bit_len: usize) -> f64 {
+        let tail = if bit_len > self.weights.len() {
+            1.0
+        } else {
+            f64::INFINITY
+        };
+        self.weights
+            .iter()
+            .take(bit_len)
+            .copied()
+            .fold(tail, f64::min)
+    }
+
+    /// Largest plain distance a pair within weighted `threshold` can have
+    pub(crate) fn hamming_bound(&self, threshold: f64, bit_len: usize) -> u32 {
+        let bound = threshold / self.min_weight(bit_len);
+        if bound.is_finite() {
+            (bound.floor() as usize).min(bit_len) as u32
+        } else {
+            bit_len as u32
+        }
+    }
+}
+
+/// Distance between two equally long word slices
+#[inline]
+pub(crate) fn hamming(a: &[u64], b: &[u64]) -> u32 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// Distance from `query` to each `query.len()`-word row of `rows`
+///
+/// Returns nothing for an empty query.
+pub(crate) fn hamming_rows(query: &[u64], rows: &[u64]) -> Vec<u32> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if query.len() == 1 && is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2, checked just above
+            return unsafe { x86::hamming_rows1_avx2(query[0], rows) };
+        }
+        if is_x86_feature_detected!("popcnt") {
+            // SAFETY: the CPU supports POPCNT, checked just above
+            return unsafe { x86::hamming_rows_popcnt(query, rows) };
+        }
+    }
+    hamming_rows_portable(query, rows)
+}
+
+#[inline(always)]
+fn hamming_rows_portable(query: &[u64], rows: &[u64]) -> Vec<u32> {
+    rows.chunks_exact(query.len())
+        .map(|row| hamming(query, row))
+        .collect()
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    /// `hamming_rows_portable`, compiled to use the POPCNT instruction
+    #[target_feature(enable = "popcnt")]
+    pub(super) unsafe fn hamming_rows_popcnt(query: &[u64], rows: &[u64]) -> Vec<u32> {
+        super::hamming_rows_portable(query, rows)
+    }
+
+    /// Distances from one-word `query` to each word of `rows`
+    ///
+    /// Four rows per step: XOR with the query, count the bits of each
+    /// nibble with a byte shuffle, then sum the bytes of each 64-bit lane.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn hamming_rows1_avx2(query: u64, rows: &[u64]) -> Vec<u32> {
+        let nibble_counts = _mm256_setr_epi8(
+            0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2,
+            3, 3, 4,
+        );
+        let low_nibbles = _mm256_set1_epi8(0x0f);
+        let q = _mm256_set1_epi64x(query as i64);
+
+        let mut out = Vec::with_capacity(rows.len());
+        let chunks = rows.chunks_exact(4);
+        let rest = chunks.remainder();
+        let mut lanes = [0u64; 4];
+        for chunk in chunks {
+            let x = _mm256_xor_si256(_mm256_loadu_si256(chunk.as_ptr() as *const __m256i), q);
+            let lo = _mm256_and_si256(x, low_nibbles);
+            let hi = _mm256_and_si256(_mm256_srli_epi16(x, 4), low_nibbles);
+            let counts = _mm256_add_epi8(
+                _mm256_shuffle_epi8(nibble_counts, lo),
+                _mm256_shuffle_epi8(nibble_counts, hi),
+            );
+            let sums = _mm256_sad_epu8(counts, _mm256_setzero_si256());
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sums);
+            out.extend(lanes.iter().map(|&s| s as u32));
+        }
+        out.extend(rest.iter().map(|r| (r ^ query).count_ones()));
+        out
+    }
+}
+
+/// Distance between every two of `hashes`, rows and columns in input order
+///
+/// Hashes are packed into one buffer and each row is computed with the
+/// batch kernel, rows in parallel.
+pub fn distance_matrix(hashes: &[ImageHash]) -> Vec<Vec<u32>> {
+    let bit_len = hashes.first().map_or(0, |h| h.bit_len());
+    if bit_len == 0 || hashes.iter().any(|h| h.bit_len() != bit_len) {
+        return hashes
+            .par_iter()
+            .map(|a| hashes.iter().map(|b| a.prefix_distance(b)).collect())
+            .collect();
+    }
+    let packed: Vec<u64> = hashes.iter().flat_map(|h| h.words()).copied().collect();
+    hashes
+        .par_iter()
+        .map(|h| hamming_rows(h.words(), &packed))
+        .collect()
+}
+
+/// Total distance from each of `hashes` to all of `references`, in input
+/// order
+///
+/// The row sums of a `distance_matrix` between the two, computed with the
+/// same batch kernel but without holding the matrix.
+pub(crate) fn distance_sums(hashes: &[ImageHash], references: &[ImageHash]) -> Vec<u64> {
+    let bit_len = hashes.first().map_or(0, |h| h.bit_len());
+    if bit_len == 0
+        || hashes
+            .iter()
+            .chain(references)
+            .any(|h| h.bit_len() != bit_len)
+    {
+        return hashes
+            .par_iter()
+            .map(|a| references.iter().map(|b| a.prefix_distance(b) as u64).sum())
+            .collect();
+    }
+    let packed: Vec<u64> = references.iter().flat_map(|h| h.words()).copied().collect();
+    hashes
+        .par_iter()
+        .map(|h| {
+            hamming_rows(h.words(), &packed)
+                .into_iter()
+                .map(u64::from)
+                .sum()
+        })
+        .collect()
+}
+
+/// Two hashes, by their index in the input, and the distance between them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairDistance {
+    /// Index of the first hash; always below `b`
+    pub a: usize,
+    /// Index of the second hash
+    pub b: usize,
+    pub distance: u32,
+}
+
+/// Which pairs `pairwise_distances` passes on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairFilter {
+    /// Every pair
+    All,
+    /// Pairs at most this far apart, i.e. likely duplicates
+    Within(u32),
+    /// Pairs more than this far apart
+    Beyond(u32),
+}
+
+impl PairFilter {
+    /// Whether a pair at `distance` passes
+    pub fn accepts(&self, distance: u32) -> bool {
+        match *self {
+            PairFilter::All => true,
+            PairFilter::Within(threshold) => distance <= threshold,
+            PairFilter::Beyond(threshold) => distance > threshold,
+        }
+    }
+}
+
+/// Rows (and columns) per tile, so a tile's distances and accepted pairs
+/// fit in `max_memory` bytes
+fn tile_side(max_memory: usize) -> usize {
+    let per_pair = std::mem::size_of::<PairDistance>() + std::mem::size_of::<u32>();
+    ((max_memory / per_pair) as f64).sqrt().max(1.0) as usize
+}
+
+/// Distance between every two of `hashes` that `filter` accepts, computed
+/// in tiles that fit in about `max_memory` bytes
+///
+/// Each unordered pair is visited once, with `a < b`. The upper triangle
+/// of the matrix is cut into square tiles; the rows of a tile are computed
+/// in parallel, and its accepted pairs, ordered by `a` then `b`, go to
+/// `sink` before the next tile starts. Tiles without accepted pairs are
+/// not passed on. Besides the tile, only the packed hashes are held in
+/// memory, so 50,000 hashes fit where their 2.5-billion-entry matrix does
+/// not. Stops at the first error `sink` returns.
+pub fn pairwise_distances<F, E>(
+    hashes: &[ImageHash],
+    max_memory: usize,
+    filter: PairFilter,
+    mut sink: F,
+) -> Result<(), E>
+where
+    F: FnMut(&[PairDistance]) -> Result<(), E>,
+{
+    let started = Instant::now();
+    let n = hashes.len();
+    let side = tile_side(max_memory);
+    let bit_len = hashes.first().map_or(0, |h| h.bit_len());
+    // Mixed lengths fall back to `ImageHash::distance`, like `distance_matrix`
+    let packed: Option<Vec<u64>> = (bit_len > 0 && hashes.iter().all(|h| h.bit_len() == bit_len))
+        .then(|| hashes.iter().flat_map(|h| h.words()).copied().collect());
+    let words = hashes.first().map_or(0, |h| h.words().len());
+
+    let mut accepted = 0u64;
+    for rows in (0..n).step_by(side) {
+        let row_end = (rows + side).min(n);
+        for cols in (rows..n).step_by(side) {
+            let col_end = (cols + side).min(n);
+            let tile: Vec<PairDistance> = (rows..row_end)
+                .into_par_iter()
+                .flat_map_iter(|a| {
+                    let first = cols.max(a + 1).min(col_end);
+                    let distances = match &packed {
+                        Some(packed) => {
+                            hamming_rows(hashes[a].words(), &packed[first * words..col_end * words])
+                        }
+                        None => (first..col_end)
+                            .map(|b| hashes[a].prefix_distance(&hashes[b]))
+                            .collect(),
+                    };
+                    distances
+                        .into_iter()
+                        .zip(first..)
+                        .filter(|&(distance, _)| filter.accepts(distance))
+                        .map(move |(distance, b)| PairDistance { a, b, distance })
+                })
+                .collect();
+            if !tile.is_empty() {
+                accepted += tile.len() as u64;
+                sink(&tile)?;
+            }
+        }
+    }
+    tracing::info!(
+        hashes = n,
+        tile = side,
+        pairs = accepted,
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "pairwise distances"
+    );
+    Ok(())
+}
+
+/// Write the pairs of `hashes` that `filter` accepts to a CSV file at
+/// `path`, with columns `a,b,distance`, and return how many were written
+///
+/// Runs `pairwise_distances` with a budget of `max_memory` bytes, so the
+/// file may be far larger than memory.
+pub fn write_pairwise_distances(
+    hashes: &[ImageHash],
+    max_memory: usize,
+    filter: PairFilter,
+    path: &Path,
+) -> Result<u64, String> {
+    let io_err = |e: std::io::Error| format!("Failed to write {}: {}", path.display(), e);
+    let mut out = BufWriter::new(File::create(path).map_err(io_err)?);
+    writeln!(out, "a,b,distance").map_err(io_err)?;
+    let mut written = 0u64;
+    pairwise_distances(hashes, max_memory, filter, |pairs| {
+        for pair in pairs {
+            writeln!(out, "{},{},{}", pair.a, pair.b, pair.distance)?;
+        }
+        written += pairs.len() as u64;
+        Ok(())
+    })
+    .map_err(io_err)?;
+    out.flush().map_err(io_err)?;
+    Ok(written)
+}
+
+/// Candidates parsed and compared per rayon task
+const HEX_CHUNK: usize = 4096;
+
+/// Distance from `query` to each hex-encoded hash in `candidates`, in order
+///
+/// Meant for hashes kept as text elsewhere (e.g. a database column):
+/// candidates are parsed and compared in parallel chunks with the batch
+/// kernel, so no `ImageHash` outlives its chunk. Fails on the first
+/// candidate that is not valid hex.
+pub fn hex_distances<S: AsRef<str> + Sync>(
+    query: &ImageHash,
+    candidates: &[S],
+) -> Result<Vec<u32>, HashError> {
+    let chunks: Vec<Vec<u32>> = candidates
+        .par_chunks(HEX_CHUNK)
+        .enumerate()
+        .map(|(chunk, hexes)| {
+            let hashes = hexes
+                .iter()
+                .enumerate()
+                .map(|(i, hex)| {
+                    ImageHash::from_hex(hex.as_ref()).map_err(|e| {
+                        HashError::InvalidInput(format!(
+                            "Candidate {}: {}",
+                            chunk * HEX_CHUNK + i,
+                            e
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(query.distances_to_many(&hashes))
+        })
+        .collect::<Result<_, HashError>>()?;
+    Ok(chunks.concat())
+}
+
+/// Index and distance of each hex-encoded candidate within `threshold` of
+/// `query`, in input order
+pub fn hex_within<S: AsRef<str> + Sync>(
+    query: &ImageHash,
+    candidates: &[S],
+    threshold: u32,
+) -> Result<Vec<(usize, u32)>, HashError> {
+    Ok(hex_distances(query, candidates)?
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, d)| d <= threshold)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    #[test]
+    fn test_weighted_distance() {
+        let a = ImageHash::from_hex("8001").unwrap();
+        let b = ImageHash::from_hex("0000").unwrap();
+        // Bits 0 and 15 differ
+        assert_eq!(a.weighted_distance(&b, &BitWeights::uniform()), 2.0);
+        let weights = BitWeights::new(vec![3.0, 1.0, 1.0]).unwrap();
+        assert_eq!(a.weighted_distance(&b, &weights), 4.0);
+        assert_eq!(weights.hamming_bound(4.0, 16), 4);
+        assert!(BitWeights::new(vec![0.0]).is_err());
+
+        let low = BitWeights::low_frequency(8);
+        assert!(low.weight(0) > 1.0 && low.weight(63) < 1.0);
+        assert!(((0..64).map(|i| low.weight(i)).sum::<f64>() - 64.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_kernels_match_portable() {
+        let mut rng = SeededRng::new(7);
+        for stride in [1, 2, 3] {
+            let query: Vec<u64> = (0..stride).map(|_| rng.next_u64()).collect();
+            // Not a multiple of four rows, so the AVX2 tail runs too
+            let rows: Vec<u64> = (0..stride * 11).map(|_| rng.next_u64()).collect();
+            let expected: Vec<u32> = rows
+                .chunks(stride)
+                .map(|row| {
+                    row.iter()
+                        .zip(&query)
+                        .map(|(a, b)| (a ^ b).count_ones())
+                        .sum()
+                })
+                .collect();
+            assert_eq!(hamming_rows(&query, &rows), expected);
+        }
+
+        let hashes: Vec<ImageHash> = ["00ff", "0f0f", "ffff", "00"]
+            .iter()
+            .map(|hex| ImageHash::from_hex(hex).unwrap())
+            .collect();
+        let matrix = distance_matrix(&hashes[..3]);
+        assert_eq!(matrix, vec![vec![0, 8, 8], vec![8, 0, 8], vec![8, 8, 0]]);
+        // Mixed lengths compare over the shorter hash
+        assert_eq!(distance_matrix(&hashes)[2][3], 8);
+
+        // Row sums without the matrix, against all or some references
+        assert_eq!(distance_sums(&hashes[..3], &hashes[..3]), vec![16, 16, 16]);
+        assert_eq!(distance_sums(&hashes[..3], &hashes[2..3]), vec![8, 8, 0]);
+        assert_eq!(distance_sums(&hashes, &hashes[3..]), vec![0, 4, 8, 0]);
+    }
+
+    #[test]
+    fn test_hex_distances_span_chunks() {
+        let query = ImageHash::from_hex("00000000000000ff").unwrap();
+        let candidates: Vec<String> = (0..HEX_CHUNK + 3)
+            .map(|i| format!("{:016x}", (1u64 << (i % 9)) - 1))
+            .collect();
+        let distances = hex_distances(&query, &candidates).unwrap();
+        assert_eq!(distances.len(), candidates.len());
+        assert_eq!(distances[HEX_CHUNK + 2], 8 - ((HEX_CHUNK + 2) % 9) as u32);
+        let within = hex_within(&query, &candidates[..9], 1).unwrap();
+        assert_eq!(within, vec![(7, 1), (8, 0)]);
+
+        let mut bad = candidates.clone();
+        bad[HEX_CHUNK + 1] = "zz".to_string();
+        let err = hex_distances(&query, &bad).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(&format!("Candidate {}", HEX_CHUNK + 1)));
+    }
+
+    #[test]
+    fn test_pairwise_distances_match_matrix() {
+        let mut rng = SeededRng::new(11);
+        let hashes: Vec<ImageHash> = (0..37)
+            .map(|_| ImageHash::from_hex(&format!("{:016x}", rng.next_u64() >> 40)).unwrap())
+            .collect();
+        let matrix = distance_matrix(&hashes);
+        // A few hundred bytes, so many tiles, ragged at the edges
+        for max_memory in [0, 300, 1 << 20] {
+            for filter in [
+                PairFilter::All,
+                PairFilter::Within(6),
+                PairFilter::Beyond(6),
+            ] {
+                let mut pairs = Vec::new();
+                pairwise_distances(&hashes, max_memory, filter, |tile| {
+                    assert!(!tile.is_empty());
+                    pairs.extend_from_slice(tile);
+                    Ok::<_, ()>(())
+                })
+                .unwrap();
+                pairs.sort_by_key(|p| (p.a, p.b));
+                let expected: Vec<PairDistance> = (0..hashes.len())
+                    .flat_map(|a| (a + 1..hashes.len()).map(move |b| (a, b)))
+                    .map(|(a, b)| PairDistance {
+                        a,
+                        b,
+                        distance: matrix[a][b],
+                    })
+                    .filter(|p| filter.accepts(p.distance))
+                    .collect();
+                assert_eq!(pairs, expected, "{} bytes, {:?}", max_memory, filter);
+            }
+        }
+
+        let stopped = pairwise_distances(&hashes, 300, PairFilter::All, |_| Err("stop"));
+        assert_eq!(stopped, Err("stop"));
+    }
+
+    #[test]
+    fn test_write_pairwise_distances() {
+        let hashes: Vec<ImageHash> = ["00ff", "00fe", "ff00", "0f0f"]
+            .iter()
+            .map(|hex| ImageHash::from_hex(hex).unwrap())
+            .collect();
+        let path = std::env::temp_dir().join(format!("phash_pairs_{}.csv", std::process::id()));
+        let written = write_pairwise_distances(&hashes, 64, PairFilter::Within(8), &path).unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, 3);
+        assert_eq!(csv, "a,b,distance\n0,1,1\n0,3,8\n2,3,8\n");
+    }
+}
@@ -0,0 +1,276 @@
+//! Document Matching Module
+//!
+//! Finds photos that already exist inside documents (compiled PDFs, scanned
+//! booklets) by matching them against rendered page images. Rendering is up
+//! to the caller, e.g. `pdftoppm -r 100 -png doc.pdf pages/doc`; with the
+//! "pdf" feature a PDF can instead be matched through the JPEG images
+//! embedded in it, as the pdf decode backend reads them.
+
+use crate::cache::hash_paths;
+use crate::decode::pdf_images;
+use crate::error::{HashError, HashFailures};
+use crate::hash::{HashAlgorithm, ImageHash, DEFAULT_HASH_SIZE};
+use crate::index::BkTree;
+use crate::progress::Progress;
+use crate::sidecar::is_sidecar;
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+/// A rendered page image of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentPage {
+    /// The document the page belongs to
+    pub document: PathBuf,
+    /// 1-based page number
+    pub page: usize,
+    /// Rendered page image, or `document` itself for `DocumentPage::pdf`
+    pub path: PathBuf,
+}
+
+impl DocumentPage {
+    /// A PDF matched through its embedded JPEG images instead of renders
+    /// (feature "pdf")
+    ///
+    /// Each image counts as a page, numbered from 1 in file order, which
+    /// is usually but not always the page order.
+    pub fn pdf(document: PathBuf) -> Self {
+        Self {
+            path: document.clone(),
+            document,
+            page: 0,
+        }
+    }
+
+    fn is_pdf(&self) -> bool {
+        self.path == self.document
+    }
+}
+
+/// A photo found on a document page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentMatch {
+    pub photo: PathBuf,
+    pub document: PathBuf,
+    pub page: usize,
+    pub distance: u32,
+}
+
+/// Match `photos` against rendered document pages
+///
+/// Returns every (photo, page) pair within `threshold`, ordered by photo,
+/// then distance, then document and page, together with the photos and
+/// pages that could not be hashed. Sidecars among `photos` are ignored.
+pub fn match_documents(
+    photos: &[PathBuf],
+    pages: &[DocumentPage],
+    algorithm: HashAlgorithm,
+    threshold: u32,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DocumentMatch>, HashFailures), String> {
+    let photos: Vec<PathBuf> = photos.iter().filter(|p| !is_sidecar(p)).cloned().collect();
+    let (pdfs, rendered): (Vec<&DocumentPage>, Vec<&DocumentPage>) =
+        pages.iter().partition(|p| p.is_pdf());
+    let mut all: Vec<PathBuf> = rendered.iter().map(|p| p.path.clone()).collect();
+    all.extend(photos.iter().cloned());
+
+    let results = hash_paths(&all, algorithm, DEFAULT_HASH_SIZE, None, progress);
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    // hash_paths keeps input order, so the first rendered.len() results
+    // are pages
+    let mut index: BkTree<DocumentPage> = BkTree::new();
+    let mut photo_hashes = Vec::new();
+    let mut failures = HashFailures::new();
+    for (i, (path, result)) in results.into_iter().enumerate() {
+        match result {
+            Ok(cached) if i < rendered.len() => index.add(cached.hash, rendered[i].clone()),
+            Ok(cached) => photo_hashes.push((path, cached.hash)),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    let pdf_hashes: Vec<_> = pdfs
+        .par_iter()
+        .map(|pdf| hash_pdf_images(&pdf.document, algorithm))
+        .collect();
+    for (pdf, hashes) in pdfs.iter().zip(pdf_hashes) {
+        for (i, hash) in hashes.into_iter().enumerate() {
+            match hash {
+                Ok(hash) => index.add(
+                    hash,
+                    DocumentPage {
+                        page: i + 1,
+                        ..(*pdf).clone()
+                    },
+                ),
+                Err(e) => failures.push((pdf.document.clone(), e)),
+            }
+        }
+    }
+
+    let mut matches: Vec<DocumentMatch> = photo_hashes
+        .iter()
+        .flat_map(|(photo, hash)| {
+            index
+                .query(hash, threshold)
+                .into_iter()
+                .map(move |(page, distance)| DocumentMatch {
+                    photo: photo.clone(),
+                    document: page.document.clone(),
+                    page: page.page,
+                    distance,
+                })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        (&a.photo, a.distance, &a.document, a.page).cmp(&(
+            &b.photo,
+            b.distance,
+            &b.document,
+            b.page,
+        ))
+    });
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok((matches, failures))
+}
+
+/// Hashes of the embedded images of a PDF, in file order; a PDF that
+/// can't be read at all is one error
+fn hash_pdf_images(
+    document: &std::path::Path,
+    algorithm: HashAlgorithm,
+) -> Vec<Result<ImageHash, HashError>> {
+    let images = match pdf_images(document) {
+        Ok(images) if images.is_empty() => Err("no embedded JPEG image".to_string()),
+        other => other,
+    };
+    match images {
+        Ok(images) => images
+            .into_iter()
+            .enumerate()
+            .map(|(i, img)| {
+                img.map_err(|e| HashError::Decode(format!("image {}: {}", i + 1, e)))
+                    .and_then(|img| ImageHash::from_image(&img, algorithm, DEFAULT_HASH_SIZE))
+            })
+            .collect(),
+        Err(e) => vec![Err(HashError::Decode(e))],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unreadable_inputs_are_reported() {
+        let pages = vec![DocumentPage {
+            document: "/docs/a.pdf".into(),
+            page: 1,
+            path: "/nonexistent/a-1.png".into(),
+        }];
+        let photos = vec![
+            PathBuf::from("/nonexistent/p.jpg"),
+            PathBuf::from("/nonexistent/p.xmp"),
+        ];
+        let (matches, failures) =
+            match_documents(&photos, &pages, HashAlgorithm::PHash, 10, None).unwrap();
+
+        assert!(matches.is_empty());
+        let failed: Vec<&str> = failures.iter().filter_map(|(p, _)| p.to_str()).collect();
+        assert_eq!(failed, vec!["/nonexistent/a-1.png", "/nonexistent/p.jpg"]);
+    }
+
+    /// A photo and another that looks nothing like it, saved in `dir`
+    fn photos(dir: &std::path::Path) -> (PathBuf, PathBuf, image::DynamicImage) {
+        use image::{imageops::FilterType, DynamicImage, Rgb, RgbImage};
+        std::fs::create_dir_all(dir).unwrap();
+        // Random blocks, smoothed: plenty of structure at every frequency
+        // pHash looks at, so JPEG compression barely moves the hash
+        let mut rng = crate::rng::SeededRng::new(3);
+        let blocks = RgbImage::from_fn(12, 8, |_, _| Rgb([rng.below(256) as u8; 3]));
+        let photo = DynamicImage::ImageRgb8(blocks).resize_exact(96, 64, FilterType::Triangle);
+        let other = DynamicImage::ImageRgb8(RgbImage::from_fn(96, 64, |x, y| {
+            Rgb([if (x / 8 + y / 8) % 2 == 0 { 240 } else { 10 }; 3])
+        }));
+        let (a, b) = (dir.join("photo.png"), dir.join("other.png"));
+        photo.save(&a).unwrap();
+        other.save(&b).unwrap();
+        (a, b, photo)
+    }
+
+    #[test]
+    fn test_finds_photo_on_rendered_page() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_docs_{}", std::process::id()));
+        let (photo, other, img) = photos(&dir);
+        // Pages rendered at another resolution than the photo
+        let blank = image::DynamicImage::new_rgb8(120, 80);
+        blank.save(dir.join("page-1.png")).unwrap();
+        img.resize_exact(192, 128, image::imageops::FilterType::Triangle)
+            .save(dir.join("page-2.png"))
+            .unwrap();
+        let pages: Vec<DocumentPage> = (1..=2)
+            .map(|page| DocumentPage {
+                document: "album.pdf".into(),
+                page,
+                path: dir.join(format!("page-{}.png", page)),
+            })
+            .collect();
+
+        let (matches, failures) = match_documents(
+            &[photo.clone(), other],
+            &pages,
+            HashAlgorithm::PHash,
+            6,
+            None,
+        )
+        .unwrap();
+        assert!(failures.is_empty());
+        assert_eq!(matches.len(), 1);
+        assert_eq!((&matches[0].photo, matches[0].page), (&photo, 2));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn test_finds_photo_embedded_in_pdf() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_pdf_{}", std::process::id()));
+        let (photo, other, img) = photos(&dir);
+        let jpeg = |img: &image::DynamicImage| {
+            let mut data = std::io::Cursor::new(Vec::new());
+            img.write_to(&mut data, image::ImageFormat::Jpeg).unwrap();
+            data.into_inner()
+        };
+        // The image streams of a PDF, the logo first
+        let mut pdf = b"%PDF-1.4\n1 0 obj\n<< /Filter /DCTDecode >>\nstream\n".to_vec();
+        pdf.extend(jpeg(&image::DynamicImage::new_rgb8(16, 16)));
+        pdf.extend(b"\nendstream\n2 0 obj\n<< /Filter /DCTDecode >>\nstream\n");
+        pdf.extend(jpeg(&img));
+        pdf.extend(b"\nendstream\n%%EOF\n");
+        let document = dir.join("album.pdf");
+        std::fs::write(&document, pdf).unwrap();
+
+        let pages = [DocumentPage::pdf(document.clone())];
+        let (matches, failures) = match_documents(
+            &[photo.clone(), other],
+            &pages,
+            HashAlgorithm::PHash,
+            6,
+            None,
+        )
+        .unwrap();
+        assert!(failures.is_empty());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            (&matches[0].photo, &matches[0].document, matches[0].page),
+            (&photo, &document, 2)
+        );
+
+        let missing = [DocumentPage::pdf(dir.join("missing.pdf"))];
+        let (_, failures) =
+            match_documents(&[photo], &missing, HashAlgorithm::PHash, 6, None).unwrap();
+        assert_eq!(failures.len(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -2,178 +2,1580 @@
 //!
 //! Provides functionality to find duplicate images based on perceptual hashes.
 
-use crate::hash::{HashAlgorithm, ImageHash};
-use image::GenericImageView;
+use crate::best::BestBy;
+use crate::cache::{hash_paths_by, hash_paths_multi, hash_paths_with, AlgorithmHasher, HashCache};
+use crate::content::{classify_paths, ContentType};
+use crate::decode::{image_dimensions, open_image};
+use crate::distance::{distance_matrix, distance_sums, BitWeights};
+use crate::error::{HashError, HashFailures};
+use crate::hash::{HashAlgorithm, Hasher, ImageHash, DEFAULT_HASH_SIZE};
+use crate::index::MultiIndex;
+use crate::metadata::FileMetadata;
+use crate::paths::relative_path;
+use crate::preprocess::HashOptions;
+use crate::progress::{Progress, ProgressCounter};
+use crate::review::group_key;
+use crate::rule::MatchRule;
+use crate::sidecar::{find_sidecars, is_sidecar};
+use crate::union_find::UnionFind;
+use image::{DynamicImage, GenericImageView};
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 /// Represents a group of duplicate images
 #[derive(Debug, Clone)]
 pub struct DuplicateGroup {
     /// All file paths in this duplicate group
-    pub paths: Vec<String>,
-    /// The representative hash for this group
+    pub paths: Vec<PathBuf>,
+    /// The representative hash for this group: the medoid, the member hash
+    /// with the smallest total distance to the others (to an even sample
+    /// of 1024 of them in larger groups)
     pub hash: String,
     /// Path to the best (highest resolution) image
-    pub best_path: String,
+    pub best_path: PathBuf,
+    /// Sidecar files (XMP, AAE, THM) of members that have any, by member path
+    pub sidecars: BTreeMap<PathBuf, Vec<PathBuf>>,
+    /// Content type shared by the members, when grouped by content type
+    pub content_type: Option<ContentType>,
+    /// Hash of each member, by member path; empty for groups that were
+    /// not built from image hashes (e.g. video clips)
+    pub member_hashes: BTreeMap<PathBuf, ImageHash>,
+    /// Width and height of each member whose size is known, by member path
+    pub member_dimensions: BTreeMap<PathBuf, (u32, u32)>,
+}
+
+/// How tightly the members of a group match
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupQuality {
+    /// Distance from each member's hash to the group hash, by member path
+    pub distances: BTreeMap<PathBuf, u32>,
+    /// Largest distance between any two members
+    pub max_distance: u32,
+    /// Mean distance over all pairs of members (0 for a single member)
+    pub mean_distance: f64,
+}
+
+impl DuplicateGroup {
+    /// Stable key identifying this set of files across scans
+    pub fn key(&self) -> String {
+        group_key(&self.paths)
+    }
+
+    /// Re-pick `best_path` by another strategy than resolution
+    pub fn choose_best(&mut self, by: &BestBy) {
+        self.best_path = by.pick(&self.paths, &self.best_path).to_path_buf();
+    }
+
+    /// Size, modification time, dimensions and format of each member that
+    /// can be stat'ed, by member path
+    ///
+    /// Dimensions are those recorded while hashing; nothing is decoded.
+    pub fn metadata(&self) -> BTreeMap<PathBuf, FileMetadata> {
+        self.paths
+            .par_iter()
+            .filter_map(|path| {
+                let dimensions = self.member_dimensions.get(path).copied();
+                Some((path.clone(), FileMetadata::of(path, dimensions)?))
+            })
+            .collect()
+    }
+
+    /// Distances within the group, or `None` without member hashes
+    ///
+    /// Members are connected through chains of matches, so `max_distance`
+    /// can exceed the grouping threshold (unless split with `regroup`);
+    /// such groups deserve a look.
+    /// Distances are between unrotated hashes, also for rotated copies
+    /// matched with `check_rotations`.
+    pub fn quality(&self) -> Option<GroupQuality> {
+        let representative = ImageHash::from_hex(&self.hash).ok()?;
+        let distances = self
+            .paths
+            .iter()
+            .map(|p| {
+                Some((
+                    p.clone(),
+                    self.member_hashes.get(p)?.distance(&representative),
+                ))
+            })
+            .collect::<Option<BTreeMap<PathBuf, u32>>>()?;
+
+        let matrix = self.pairwise_distances()?;
+        let n = matrix.len();
+        let pairs = matrix
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| row[i + 1..].iter().copied());
+        let (max_distance, total) =
+            pairs.fold((0, 0u64), |(max, total), d| (max.max(d), total + d as u64));
+        let pair_count = n * n.saturating_sub(1) / 2;
+        let mean_distance = if pair_count == 0 {
+            0.0
+        } else {
+            total as f64 / pair_count as f64
+        };
+        Some(GroupQuality {
+            distances,
+            max_distance,
+            mean_distance,
+        })
+    }
+
+    /// Distance between every two members, rows and columns ordered as
+    /// `paths`, or `None` without member hashes
+    pub fn pairwise_distances(&self) -> Option<Vec<Vec<u32>>> {
+        let hashes: Vec<ImageHash> = self
+            .paths
+            .iter()
+            .map(|p| self.member_hashes.get(p).cloned())
+            .collect::<Option<_>>()?;
+        Some(distance_matrix(&hashes))
+    }
+
+    /// Same group with relative paths resolved against `root`; undoes
+    /// `relative_to`
+    pub fn resolved_under(&self, root: &Path) -> DuplicateGroup {
+        let resolve = |p: &PathBuf| root.join(p);
+        DuplicateGroup {
+            paths: self.paths.iter().map(resolve).collect(),
+            hash: self.hash.clone(),
+            best_path: resolve(&self.best_path),
+            sidecars: self
+                .sidecars
+                .iter()
+                .map(|(path, files)| (resolve(path), files.iter().map(resolve).collect()))
+                .collect(),
+            content_type: self.content_type,
+            member_hashes: self
+                .member_hashes
+                .iter()
+                .map(|(path, hash)| (resolve(path), hash.clone()))
+                .collect(),
+            member_dimensions: self
+                .member_dimensions
+                .iter()
+                .map(|(path, size)| (resolve(path), *size))
+                .collect(),
+        }
+    }
+
+    /// Same group with every path expressed relative to `root`
+    pub fn relative_to(&self, root: &Path) -> DuplicateGroup {
+        let mut paths: Vec<PathBuf> = self.paths.iter().map(|p| relative_path(p, root)).collect();
+        paths.sort();
+
+        let sidecars = self
+            .sidecars
+            .iter()
+            .map(|(path, files)| {
+                let files = files.iter().map(|f| relative_path(f, root)).collect();
+                (relative_path(path, root), files)
+            })
+            .collect();
+
+        DuplicateGroup {
+            paths,
+            hash: self.hash.clone(),
+            best_path: relative_path(&self.best_path, root),
+            sidecars,
+            content_type: self.content_type,
+            member_hashes: self
+                .member_hashes
+                .iter()
+                .map(|(path, hash)| (relative_path(path, root), hash.clone()))
+                .collect(),
+            member_dimensions: self
+                .member_dimensions
+                .iter()
+                .map(|(path, size)| (relative_path(path, root), *size))
+                .collect(),
+        }
+    }
+}
+
+/// Where one path ended up in a list of groups
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupMembership {
+    /// Position of the group in the list
+    pub group_id: usize,
+    /// Distance from the path's hash to the best copy's, `None` without
+    /// member hashes
+    pub distance_to_best: Option<u32>,
+    /// Whether the path is its group's best copy
+    pub is_best: bool,
+}
+
+/// Flat mapping from every member path to its group, e.g. for loading
+/// groups into a database table
+///
+/// Group ids are positions in `groups`, so they are only stable for the
+/// same list; use `DuplicateGroup::key` to recognize groups across scans.
+pub fn group_membership(groups: &[DuplicateGroup]) -> BTreeMap<PathBuf, GroupMembership> {
+    let mut mapping = BTreeMap::new();
+    for (group_id, group) in groups.iter().enumerate() {
+        let best_hash = group.member_hashes.get(&group.best_path);
+        for path in &group.paths {
+            let distance_to_best = best_hash
+                .zip(group.member_hashes.get(path))
+                .map(|(best, hash)| best.distance(hash));
+            let membership = GroupMembership {
+                group_id,
+                distance_to_best,
+                is_best: *path == group.best_path,
+            };
+            mapping.insert(path.clone(), membership);
+        }
+    }
+    mapping
 }
 
 /// Image info for duplicate detection
 #[derive(Debug, Clone)]
-struct ImageInfo {
-    path: String,
-    hash: ImageHash,
-    resolution: u64, // width * height
+pub(crate) struct ImageInfo {
+    pub(crate) path: PathBuf,
+    pub(crate) hash: ImageHash,
+    /// Width and height, if known
+    pub(crate) dimensions: Option<(u32, u32)>,
+}
+
+impl ImageInfo {
+    /// Width * height, 0 if unknown
+    pub(crate) fn resolution(&self) -> u64 {
+        self.dimensions.map_or(0, |(w, h)| w as u64 * h as u64)
+    }
 }
 
 /// Find duplicate images from a list of paths
+///
+/// Files that fail to hash are skipped; `find_duplicates_with` reports them.
 pub fn find_duplicates(
-    paths: &[String],
+    paths: &[PathBuf],
     algorithm: HashAlgorithm,
+    hash_size: usize,
     threshold: u32,
 ) -> Result<Vec<DuplicateGroup>, String> {
+    algorithm
+        .check_hash_size(hash_size)
+        .map_err(|e| e.to_string())?;
+
     // Compute hashes for all images
     let mut images: Vec<ImageInfo> = Vec::new();
 
-    for path in paths {
-        match ImageHash::from_path(path, algorithm, 8) {
-            Ok(hash) => {
-                let resolution = get_image_resolution(path).unwrap_or(0);
-                images.push(ImageInfo {
-                    path: path.clone(),
-                    hash,
-                    resolution,
-                });
-            }
-            Err(e) => {
-                eprintln!("Warning: Failed to hash {}: {}", path, e);
-            }
+    for path in &primaries_only(paths) {
+        if let Ok(image) = hash_image(path, algorithm, hash_size) {
+            images.push(image);
         }
     }
 
-    group_duplicates(images, threshold)
+    group_duplicates(images, threshold).map(with_sidecars)
 }
 
 /// Find duplicate images using parallel processing
 pub fn find_duplicates_parallel(
-    paths: &[String],
+    paths: &[PathBuf],
     algorithm: HashAlgorithm,
+    hash_size: usize,
     threshold: u32,
 ) -> Result<Vec<DuplicateGroup>, String> {
-    // Compute hashes in parallel
-    let images: Vec<ImageInfo> = paths
+    find_duplicates_with(
+        paths,
+        algorithm,
+        hash_size,
+        threshold,
+        false,
+        &HashOptions::default(),
+        None,
+        None,
+    )
+    .map(|(groups, _)| groups)
+}
+
+/// Find duplicate images in parallel with an optional cache and progress
+///
+/// Hashes for unchanged files are reused from `cache` and newly computed
+/// ones are added to it; saving it is up to the caller. Each file is
+/// reported to `progress` as it finishes; if `progress` cancels, this
+/// returns an error without grouping. Files that could not be hashed are
+/// returned alongside the groups.
+///
+/// With `check_rotations`, two images also match when one is a rotated or
+/// mirrored copy of the other: every image is hashed in all
+/// `DIHEDRAL_TRANSFORMS` orientations and the smallest distance counts.
+/// This costs eight hashes per decoded image.
+///
+/// Images are preprocessed with `options` before hashing, for example to
+/// trim scan borders.
+///
+/// Hashes have `hash_size` x `hash_size` bits per color channel (see
+/// `HashAlgorithm::check_hash_size`); scale `threshold` with the bit count.
+#[allow(clippy::too_many_arguments)]
+pub fn find_duplicates_with(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+    check_rotations: bool,
+    options: &HashOptions,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    options
+        .phash
+        .check_hash_size(algorithm, hash_size)
+        .map_err(|e| e.to_string())?;
+    let hasher = AlgorithmHasher {
+        algorithm,
+        hash_size,
+        rotations: check_rotations,
+        options,
+    };
+    find_duplicates_hashed(
+        paths,
+        &hasher,
+        threshold,
+        check_rotations,
+        options.apply_exif_orientation,
+        cache,
+        progress,
+    )
+}
+
+/// Find duplicate images by weighted Hamming distance
+///
+/// Like `find_duplicates_with`, but two images match when their
+/// `ImageHash::weighted_distance` under `weights` is at most `threshold`,
+/// so differences in important bits (e.g. `BitWeights::low_frequency`)
+/// count more. Candidates come from the index at the largest plain distance
+/// the weights allow, so small weights make the search slower.
+#[allow(clippy::too_many_arguments)]
+pub fn find_duplicates_weighted(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: f64,
+    weights: &BitWeights,
+    options: &HashOptions,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    options
+        .phash
+        .check_hash_size(algorithm, hash_size)
+        .map_err(|e| e.to_string())?;
+    let paths = primaries_only(paths);
+    let hashed = hash_paths_with(
+        &paths, algorithm, hash_size, false, options, cache, progress,
+    );
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    let mut images = Vec::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in hashed {
+        match result {
+            Ok(cached) => images.push(ImageInfo {
+                path,
+                hash: cached.hash,
+                dimensions: Some(cached.dimensions),
+            }),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    let groups =
+        group_duplicates_weighted(images, threshold, weights, progress).map(with_sidecars)?;
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((groups, failures))
+}
+
+/// Find duplicate images with a custom hash function
+///
+/// Like `find_duplicates_with`, but each image is decoded, preprocessed
+/// with `options` and hashed by `hasher`, then grouped with the same index
+/// and union-find. There is no cache: hashes of a custom function can't be
+/// told apart from another's. Fails if `hasher` returns hashes of different
+/// lengths.
+pub fn find_duplicates_using<H: Hasher + ?Sized>(
+    paths: &[PathBuf],
+    hasher: &H,
+    threshold: u32,
+    options: &HashOptions,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    let hasher = Preprocessed { hasher, options };
+    find_duplicates_hashed(
+        paths,
+        &hasher,
+        threshold,
+        false,
+        options.apply_exif_orientation,
+        None,
+        progress,
+    )
+}
+
+/// A custom `Hasher` run on images preprocessed with `options`
+struct Preprocessed<'a, H: ?Sized> {
+    hasher: &'a H,
+    options: &'a HashOptions,
+}
+
+impl<H: Hasher + ?Sized> Hasher for Preprocessed<'_, H> {
+    fn hash_image(&self, img: &DynamicImage) -> Result<ImageHash, HashError> {
+        self.hasher.hash_image(&self.options.apply(img))
+    }
+}
+
+/// Hash `paths` with `hasher`, cached under its `cache_tag`, and group
+/// them
+///
+/// With `rotations`, each hash packs every transform, as
+/// `ImageHash::dihedral_from_image` does, and the closest one counts.
+/// Files are decoded as `hash_paths_by` does with `apply_exif_orientation`.
+fn find_duplicates_hashed(
+    paths: &[PathBuf],
+    hasher: &dyn Hasher,
+    threshold: u32,
+    rotations: bool,
+    apply_exif_orientation: Option<bool>,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    let paths = primaries_only(paths);
+    let tag = hasher.cache_tag();
+    let algorithm = tag.as_deref().unwrap_or("custom");
+    let span = tracing::info_span!("hash_paths", files = paths.len(), algorithm);
+    let results = hash_paths_by(
+        &paths,
+        &[hasher],
+        apply_exif_orientation,
+        &span,
+        cache,
+        progress,
+    );
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    let mut images: Vec<ImageInfo> = Vec::new();
+    let mut variants: Vec<Vec<ImageHash>> = Vec::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in results {
+        match result.map(|mut hashes| hashes.swap_remove(0)) {
+            Ok(cached) => {
+                let hash = if rotations {
+                    let transforms = cached.hash.dihedral_variants();
+                    let plain = transforms[0].clone();
+                    variants.push(transforms);
+                    plain
+                } else {
+                    cached.hash
+                };
+                images.push(ImageInfo {
+                    path,
+                    hash,
+                    dimensions: Some(cached.dimensions),
+                });
+            }
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    if let Some(first) = images.first() {
+        let bits = first.hash.bit_len();
+        if images.iter().any(|image| image.hash.bit_len() != bits) {
+            return Err("Hash function returned hashes of different lengths".to_string());
+        }
+    }
+
+    let variants = rotations.then_some(variants);
+    let groups = group_duplicates_with(images, threshold, variants, progress).map(with_sidecars)?;
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((groups, failures))
+}
+
+/// Find duplicate images by a fine pHash, prefiltered by a coarse one
+///
+/// Each image is decoded and transformed once for both a 16-bit coarse
+/// hash and a fine one of `hash_size` (see
+/// `ImageHash::hierarchical_from_image`). Candidate pairs come from an
+/// index of the coarse hashes at `coarse_threshold`, and only those are
+/// compared by fine hash against `threshold`. On large corpora with few
+/// duplicates this skips almost every fine comparison. The prefilter is a
+/// heuristic: a pair within `threshold` whose coarse hashes differ in more
+/// than `coarse_threshold` bits is missed, so raise it if recall matters
+/// more than speed. There is no cache, as it holds one hash per file.
+pub fn find_duplicates_hierarchical(
+    paths: &[PathBuf],
+    hash_size: usize,
+    threshold: u32,
+    coarse_threshold: u32,
+    options: &HashOptions,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    ImageHash::check_hierarchical(hash_size, options).map_err(|e| e.to_string())?;
+    let paths = primaries_only(paths);
+    let counter = ProgressCounter::new(progress, paths.len());
+    type Hashed = Result<(ImageInfo, ImageHash), HashError>;
+    let results: Vec<(PathBuf, Hashed)> = paths
         .par_iter()
         .filter_map(|path| {
-            ImageHash::from_path(path, algorithm, 8).ok().map(|hash| {
-                let resolution = get_image_resolution(path).unwrap_or(0);
-                ImageInfo {
+            if counter.cancelled() {
+                return None;
+            }
+            let result = options.open(path).and_then(|img| {
+                let (coarse, fine) = ImageHash::hierarchical_from_image(&img, hash_size, options)?;
+                let image = ImageInfo {
                     path: path.clone(),
-                    hash,
-                    resolution,
-                }
+                    hash: fine,
+                    dimensions: Some(img.dimensions()),
+                };
+                Ok((image, coarse))
+            });
+            counter.tick(path);
+            Some((path.clone(), result))
+        })
+        .collect();
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    let mut images = Vec::new();
+    let mut coarse = Vec::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in results {
+        match result {
+            Ok((image, hash)) => {
+                images.push(image);
+                coarse.push(hash);
+            }
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    let groups = group_duplicates_coarse(images, &coarse, coarse_threshold, threshold, progress)
+        .map(with_sidecars)?;
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((groups, failures))
+}
+
+/// Find duplicate images, hashing each content type with its own preset
+///
+/// Every image is first classified as a photo, screenshot, graphic or
+/// document (see `ContentType::preset`); images are then grouped with the
+/// algorithm and threshold of their type, and only with images of the same
+/// type. Classifying decodes every file once more, so this costs roughly
+/// twice as much as `find_duplicates_with` on an empty cache. Progress is
+/// reported for each content type in turn.
+pub fn find_duplicates_by_content(
+    paths: &[PathBuf],
+    check_rotations: bool,
+    mut cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    let classified = classify_paths(&primaries_only(paths), progress);
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    let mut by_type: BTreeMap<ContentType, Vec<PathBuf>> = BTreeMap::new();
+    let mut failures = HashFailures::new();
+    for (path, content) in classified {
+        match content {
+            Ok(content) => by_type.entry(content).or_default().push(path),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (content, paths) in by_type {
+        let preset = content.preset();
+        let (found, failed) = find_duplicates_with(
+            &paths,
+            preset.algorithm,
+            DEFAULT_HASH_SIZE,
+            preset.threshold,
+            check_rotations,
+            &HashOptions::default(),
+            cache.as_deref_mut(),
+            progress,
+        )?;
+        groups.extend(found.into_iter().map(|g| DuplicateGroup {
+            content_type: Some(content),
+            ..g
+        }));
+        failures.extend(failed);
+    }
+    sort_groups(&mut groups);
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((groups, failures))
+}
+
+/// Find duplicate images matching a rule over several hash algorithms
+///
+/// Every image is decoded once and hashed with each algorithm of `rule`.
+/// A pair meeting k of n criteria meets at least one of any n - k + 1, so
+/// candidate pairs come from indexes on the first n - k + 1 criteria (one
+/// for AND rules, all for OR rules) and no pairwise pass is needed. Groups
+/// carry the hash of the first algorithm; an image that fails any
+/// algorithm is reported as failed.
+pub fn find_duplicates_by_rule(
+    paths: &[PathBuf],
+    rule: &MatchRule,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    let paths = primaries_only(paths);
+    let algorithms: Vec<HashAlgorithm> = rule.criteria().iter().map(|c| c.algorithm).collect();
+    let hashed = hash_paths_multi(&paths, &algorithms, DEFAULT_HASH_SIZE, cache, progress);
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    let mut images: Vec<ImageInfo> = Vec::new();
+    let mut hashes: Vec<Vec<ImageHash>> = Vec::new();
+    let mut failures = HashFailures::new();
+    for (path, row) in hashed {
+        match row {
+            Ok(row) => {
+                images.push(ImageInfo {
+                    path,
+                    hash: row[0].hash.clone(),
+                    dimensions: Some(row[0].dimensions),
+                });
+                hashes.push(row.into_iter().map(|c| c.hash).collect());
+            }
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    if images.is_empty() {
+        return Ok((Vec::new(), failures));
+    }
+
+    let lookups = rule.criteria().len() - rule.required() + 1;
+    let indexes: Vec<(usize, u32, MultiIndex<usize>)> = rule.criteria()[..lookups]
+        .iter()
+        .enumerate()
+        .map(|(k, c)| {
+            let mut index =
+                MultiIndex::for_corpus(hashes[0][k].bit_len(), images.len(), c.threshold);
+            for (i, h) in hashes.iter().enumerate() {
+                index.add(h[k].clone(), i);
+            }
+            (k, c.threshold, index)
+        })
+        .collect();
+
+    let groups = group_linked(
+        &images,
+        |i| {
+            indexes
+                .iter()
+                .flat_map(|(k, threshold, index)| index.query(&hashes[i][*k], *threshold))
+                .map(|(&j, _)| j)
+                .filter(|&j| j > i && rule.matches(&hashes[i], &hashes[j]))
+                .collect()
+        },
+        progress,
+    )
+    .map(with_sidecars)?;
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((groups, failures))
+}
+
+/// Group byte-identical files
+///
+/// Files are bucketed by size and only files sharing a size are read, to
+/// compare BLAKE3 digests of their contents. Returns every file in a
+/// class of its own or with its copies, classes and members in input
+/// order. Files that cannot be read stay on their own. Stops reading, with
+/// the remaining files on their own, once `progress` cancels.
+#[cfg(feature = "exact")]
+pub fn exact_duplicates(paths: &[PathBuf], progress: Option<&dyn Progress>) -> Vec<Vec<PathBuf>> {
+    use std::collections::HashMap;
+
+    let sizes: Vec<Option<u64>> = paths
+        .par_iter()
+        .map(|path| std::fs::metadata(path).ok().map(|m| m.len()))
+        .collect();
+    let mut same_size: HashMap<u64, usize> = HashMap::new();
+    for size in sizes.iter().flatten() {
+        *same_size.entry(*size).or_default() += 1;
+    }
+
+    let digests: Vec<Option<(u64, [u8; 32])>> = paths
+        .par_iter()
+        .zip(&sizes)
+        .map(|(path, size)| {
+            let size = (*size).filter(|s| same_size[s] > 1)?;
+            if progress.is_some_and(|p| p.is_cancelled()) {
+                return None;
+            }
+            content_digest(path).ok().map(|digest| (size, digest))
+        })
+        .collect();
+
+    let mut classes: Vec<Vec<PathBuf>> = Vec::new();
+    let mut class_of: HashMap<(u64, [u8; 32]), usize> = HashMap::new();
+    for (path, digest) in paths.iter().zip(digests) {
+        let class = match digest {
+            Some(digest) => *class_of.entry(digest).or_insert(classes.len()),
+            None => classes.len(),
+        };
+        if class == classes.len() {
+            classes.push(Vec::new());
+        }
+        classes[class].push(path.clone());
+    }
+    classes
+}
+
+/// BLAKE3 digest of a file's contents
+#[cfg(feature = "exact")]
+fn content_digest(path: &Path) -> std::io::Result<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(std::fs::File::open(path)?)?;
+    Ok(*hasher.finalize().as_bytes())
+}
+
+/// Run `find` on one file per class of byte-identical copies, then add the
+/// copies back into the groups
+///
+/// Perceptual hashing decodes every file; copies give the same hash
+/// anyway, so this saves a decode per copy at the cost of reading
+/// same-sized files once more. The copies join the group of their
+/// representative (the first copy in input order), and share its failure
+/// if it could not be hashed. The best path is left as `find` chose it.
+#[cfg(feature = "exact")]
+pub fn with_exact_prepass<F>(
+    paths: &[PathBuf],
+    progress: Option<&dyn Progress>,
+    find: F,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String>
+where
+    F: FnOnce(&[PathBuf]) -> Result<(Vec<DuplicateGroup>, HashFailures), String>,
+{
+    use std::collections::HashMap;
+
+    let classes = exact_duplicates(&primaries_only(paths), progress);
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+    let representatives: Vec<PathBuf> = classes.iter().map(|class| class[0].clone()).collect();
+    let copies: HashMap<&Path, &[PathBuf]> = classes
+        .iter()
+        .filter(|class| class.len() > 1)
+        .map(|class| (class[0].as_path(), &class[1..]))
+        .collect();
+
+    let (mut groups, failures) = find(&representatives)?;
+    for group in &mut groups {
+        let extra: Vec<(PathBuf, Option<ImageHash>)> = group
+            .paths
+            .iter()
+            .filter_map(|path| Some((copies.get(path.as_path())?, path)))
+            .flat_map(|(copies, path)| {
+                let hash = group.member_hashes.get(path).cloned();
+                copies.iter().map(move |c| (c.clone(), hash.clone()))
             })
+            .collect();
+        for (copy, hash) in extra {
+            if let Some(hash) = hash {
+                group.member_hashes.insert(copy.clone(), hash);
+            }
+            group.paths.push(copy);
+        }
+        group.paths.sort();
+    }
+    let mut failures: HashFailures = failures
+        .into_iter()
+        .flat_map(|(path, e)| {
+            let copies = copies.get(path.as_path()).copied().unwrap_or_default();
+            let copies: Vec<(PathBuf, HashError)> =
+                copies.iter().map(|c| (c.clone(), e.clone())).collect();
+            std::iter::once((path, e)).chain(copies)
         })
         .collect();
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((with_sidecars(groups), failures))
+}
 
-    group_duplicates(images, threshold)
+/// Run `find` on one file per class of byte-identical copies
+#[cfg(not(feature = "exact"))]
+pub fn with_exact_prepass<F>(
+    _paths: &[PathBuf],
+    _progress: Option<&dyn Progress>,
+    _find: F,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String>
+where
+    F: FnOnce(&[PathBuf]) -> Result<(Vec<DuplicateGroup>, HashFailures), String>,
+{
+    Err("The exact pre-pass requires the `exact` feature".to_string())
 }
 
 /// Group images by similarity
 fn group_duplicates(images: Vec<ImageInfo>, threshold: u32) -> Result<Vec<DuplicateGroup>, String> {
+    group_duplicates_with(images, threshold, None, None)
+}
+
+/// Group images by similarity, optionally matching any of their transforms
+///
+/// `variants[i]` holds the hashes of every transform of `images[i]`; each
+/// is looked up against the plain hashes of the other images.
+fn group_duplicates_with(
+    images: Vec<ImageInfo>,
+    threshold: u32,
+    variants: Option<Vec<Vec<ImageHash>>>,
+    progress: Option<&dyn Progress>,
+) -> Result<Vec<DuplicateGroup>, String> {
     if images.is_empty() {
         return Ok(Vec::new());
     }
-
-    // Union-Find structure for grouping
     let n = images.len();
-    let mut parent: Vec<usize> = (0..n).collect();
-    let mut rank: Vec<usize> = vec![0; n];
-
-    // Find with path compression
-    fn find(parent: &mut [usize], i: usize) -> usize {
-        if parent[i] != i {
-            parent[i] = find(parent, parent[i]);
-        }
-        parent[i]
-    }
-
-    // Union by rank
-    fn union(parent: &mut [usize], rank: &mut [usize], i: usize, j: usize) {
-        let pi = find(parent, i);
-        let pj = find(parent, j);
-        if pi != pj {
-            if rank[pi] < rank[pj] {
-                parent[pi] = pj;
-            } else if rank[pi] > rank[pj] {
-                parent[pj] = pi;
-            } else {
-                parent[pj] = pi;
-                rank[pi] += 1;
-            }
+
+    // Index all hashes so each neighbour lookup avoids a full scan
+    let mut tree = MultiIndex::for_corpus(images[0].hash.bit_len(), n, threshold);
+    for (i, image) in images.iter().enumerate() {
+        tree.add(image.hash.clone(), i);
+    }
+
+    // Transformed lookups aren't symmetric, so each image checks all
+    // others then
+    group_linked(
+        &images,
+        |i| match &variants {
+            None => tree
+                .query(&images[i].hash, threshold)
+                .into_iter()
+                .map(|(&j, _)| j)
+                .filter(|&j| j > i)
+                .collect(),
+            Some(variants) => variants[i]
+                .iter()
+                .flat_map(|v| tree.query(v, threshold))
+                .map(|(&j, _)| j)
+                .filter(|&j| j != i)
+                .collect(),
+        },
+        progress,
+    )
+}
+
+/// Group images whose hashes are within `threshold`, comparing only pairs
+/// whose `coarse[i]` hashes are within `coarse_threshold`
+fn group_duplicates_coarse(
+    images: Vec<ImageInfo>,
+    coarse: &[ImageHash],
+    coarse_threshold: u32,
+    threshold: u32,
+    progress: Option<&dyn Progress>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    if images.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut tree = MultiIndex::for_corpus(coarse[0].bit_len(), images.len(), coarse_threshold);
+    for (i, hash) in coarse.iter().enumerate() {
+        tree.add(hash.clone(), i);
+    }
+
+    group_linked(
+        &images,
+        |i| {
+            tree.query(&coarse[i], coarse_threshold)
+                .into_iter()
+                .map(|(&j, _)| j)
+                .filter(|&j| j > i && images[i].hash.distance(&images[j].hash) <= threshold)
+                .collect()
+        },
+        progress,
+    )
+}
+
+/// Group images whose weighted distance is within `threshold`
+fn group_duplicates_weighted(
+    images: Vec<ImageInfo>,
+    threshold: f64,
+    weights: &BitWeights,
+    progress: Option<&dyn Progress>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    if images.is_empty() {
+        return Ok(Vec::new());
+    }
+    let bit_len = images[0].hash.bit_len();
+    let bound = weights.hamming_bound(threshold, bit_len);
+    let mut tree = MultiIndex::for_corpus(bit_len, images.len(), bound);
+    for (i, image) in images.iter().enumerate() {
+        tree.add(image.hash.clone(), i);
+    }
+
+    group_linked(
+        &images,
+        |i| {
+            tree.query(&images[i].hash, bound)
+                .into_iter()
+                .map(|(&j, _)| j)
+                .filter(|&j| {
+                    j > i && images[i].hash.weighted_distance(&images[j].hash, weights) <= threshold
+                })
+                .collect()
+        },
+        progress,
+    )
+}
+
+/// Group images connected through `neighbours[i]` (indices into `images`)
+pub(crate) fn group_components(
+    images: &[ImageInfo],
+    neighbours: Vec<Vec<usize>>,
+) -> Vec<DuplicateGroup> {
+    let sets = UnionFind::new(images.len());
+    for (i, js) in neighbours.into_iter().enumerate() {
+        for j in js {
+            sets.union(i, j);
         }
     }
+    groups_of(images, &sets)
+}
 
-    // Compare all pairs and union similar images
-    for i in 0..n {
-        for j in (i + 1)..n {
-            let dist = images[i].hash.distance(&images[j].hash);
-            if dist <= threshold {
-                union(&mut parent, &mut rank, i, j);
-            }
+/// Group images connected through `linked(i)` (indices into `images`)
+///
+/// Lookups run in parallel and link their pairs as they find them, so
+/// neither the search nor the merging waits on one thread and the edge
+/// lists are never held all at once. Once `progress` cancels, the
+/// remaining lookups are skipped and "Cancelled" is returned.
+pub(crate) fn group_linked<F>(
+    images: &[ImageInfo],
+    linked: F,
+    progress: Option<&dyn Progress>,
+) -> Result<Vec<DuplicateGroup>, String>
+where
+    F: Fn(usize) -> Vec<usize> + Sync,
+{
+    let started = Instant::now();
+    let cancelled = || progress.is_some_and(|p| p.is_cancelled());
+    let sets = UnionFind::new(images.len());
+    (0..images.len()).into_par_iter().for_each(|i| {
+        if cancelled() {
+            return;
+        }
+        for j in linked(i) {
+            sets.union(i, j);
         }
+    });
+    if cancelled() {
+        return Err("Cancelled".to_string());
     }
+    let groups = groups_of(images, &sets);
+    let duplicates = groups.iter().filter(|g| g.paths.len() > 1);
+    tracing::info!(
+        images = images.len(),
+        groups = duplicates.clone().count(),
+        members = duplicates.clone().map(|g| g.paths.len()).sum::<usize>(),
+        largest = duplicates.map(|g| g.paths.len()).max().unwrap_or(0),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "grouped"
+    );
+    Ok(groups)
+}
+
+/// One group per set of `sets`, in output order
+fn groups_of(images: &[ImageInfo], sets: &UnionFind) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<DuplicateGroup> = sets
+        .components()
+        .into_par_iter()
+        .map(|indices| make_group(images, &indices))
+        .collect();
+    sort_groups(&mut groups);
+    groups
+}
+
+/// Order groups by best path, then members, so output doesn't depend on
+/// input order
+pub(crate) fn sort_groups(groups: &mut [DuplicateGroup]) {
+    groups.sort_by(|a, b| {
+        a.best_path
+            .cmp(&b.best_path)
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+}
+
+/// Build a `DuplicateGroup` from member indices into `images`
+pub(crate) fn make_group(images: &[ImageInfo], indices: &[usize]) -> DuplicateGroup {
+    let mut paths: Vec<PathBuf> = indices.iter().map(|&i| images[i].path.clone()).collect();
+    let member_hashes = indices
+        .iter()
+        .map(|&i| (images[i].path.clone(), images[i].hash.clone()))
+        .collect();
+    let member_dimensions = indices
+        .iter()
+        .filter_map(|&i| Some((images[i].path.clone(), images[i].dimensions?)))
+        .collect();
+
+    // Find best (highest resolution) image, the first path on ties
+    let best_idx = indices
+        .iter()
+        .max_by(|&&a, &&b| {
+            images[a]
+                .resolution()
+                .cmp(&images[b].resolution())
+                .then_with(|| images[b].path.cmp(&images[a].path))
+        })
+        .copied()
+        .unwrap_or(indices[0]);
+
+    let best_path = images[best_idx].path.clone();
+    let hash = images[medoid(images, indices)].hash.to_hex();
 
-    // Group by parent
-    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
-    for i in 0..n {
-        let root = find(&mut parent, i);
-        groups.entry(root).or_default().push(i);
+    // Sort paths for consistent output
+    paths.sort();
+
+    DuplicateGroup {
+        paths,
+        hash,
+        best_path,
+        sidecars: BTreeMap::new(),
+        content_type: None,
+        member_hashes,
+        member_dimensions,
     }
+}
 
-    // Convert to DuplicateGroup
-    let result: Vec<DuplicateGroup> = groups
-        .into_values()
-        .map(|indices| {
-            let mut paths: Vec<String> = indices.iter().map(|&i| images[i].path.clone()).collect();
+/// Members a medoid is measured against; larger groups use an even
+/// sample of this many, so picking it stays linear in the group size
+const MEDOID_SAMPLE: usize = 1024;
 
-            // Find best (highest resolution) image
-            let best_idx = indices
-                .iter()
-                .max_by_key(|&&i| images[i].resolution)
-                .copied()
-                .unwrap_or(indices[0]);
+/// The member whose hash has the smallest total distance to the others
+/// (or to a `MEDOID_SAMPLE` of them), the first path on ties
+fn medoid(images: &[ImageInfo], indices: &[usize]) -> usize {
+    let hashes: Vec<ImageHash> = indices.iter().map(|&i| images[i].hash.clone()).collect();
+    let sample: Vec<ImageHash> = hashes
+        .iter()
+        .step_by(hashes.len().div_ceil(MEDOID_SAMPLE).max(1))
+        .cloned()
+        .collect();
+    distance_sums(&hashes, &sample)
+        .into_iter()
+        .zip(indices)
+        .map(|(total, &i)| (total, &images[i].path, i))
+        .min()
+        .map_or(indices[0], |(_, _, i)| i)
+}
 
-            let best_path = images[best_idx].path.clone();
-            let hash = images[indices[0]].hash.to_hex();
+/// Drop sidecar files; they are attached to their image instead of hashed
+pub(crate) fn primaries_only(paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths.iter().filter(|p| !is_sidecar(p)).cloned().collect()
+}
 
-            // Sort paths for consistent output
-            paths.sort();
+/// Attach sidecars to the members of groups with more than one image
+pub(crate) fn with_sidecars(mut groups: Vec<DuplicateGroup>) -> Vec<DuplicateGroup> {
+    let members: Vec<PathBuf> = groups
+        .iter()
+        .filter(|g| g.paths.len() > 1)
+        .flat_map(|g| g.paths.iter().cloned())
+        .collect();
+    let mut sidecars = find_sidecars(&members);
+    for group in groups.iter_mut().filter(|g| g.paths.len() > 1) {
+        group.sidecars = group
+            .paths
+            .iter()
+            .filter_map(|p| sidecars.remove_entry(p))
+            .collect();
+    }
+    groups
+}
 
-            DuplicateGroup {
-                paths,
-                hash,
-                best_path,
-            }
+/// Group bare hashes, naming each member by its index
+///
+/// Used by the benchmarks, which have hashes but no files.
+pub(crate) fn group_hashes(hashes: &[ImageHash], threshold: u32) -> Vec<DuplicateGroup> {
+    let images = hashes
+        .iter()
+        .enumerate()
+        .map(|(i, hash)| ImageInfo {
+            path: PathBuf::from(i.to_string()),
+            hash: hash.clone(),
+            dimensions: None,
         })
         .collect();
+    group_duplicates(images, threshold).unwrap_or_default()
+}
+
+/// A hash computed earlier: path, hash and width and height, if known
+pub type HashEntry = (PathBuf, ImageHash, Option<(u32, u32)>);
+
+/// Group hashes computed earlier, without opening any file
+///
+/// `hashes` holds `(path, hash, dimensions)` entries, e.g. from a database
+/// of earlier scans. The best path is the member with the most pixels, the
+/// first path on ties, so entries without dimensions only win among
+/// themselves. Sidecars are looked up as for the other finders. Fails if
+/// the hashes differ in length or hash size, or name different algorithms.
+pub fn find_duplicates_in_hashes(
+    hashes: &[HashEntry],
+    threshold: u32,
+) -> Result<Vec<DuplicateGroup>, String> {
+    let Some((_, first, _)) = hashes.first() else {
+        return Ok(Vec::new());
+    };
+    let mut images = Vec::new();
+    for (path, hash, dimensions) in hashes {
+        first
+            .checked_distance(hash)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        if !is_sidecar(path) {
+            images.push(ImageInfo {
+                path: path.clone(),
+                hash: hash.clone(),
+                dimensions: *dimensions,
+            });
+        }
+    }
+    group_duplicates(images, threshold).map(with_sidecars)
+}
+
+/// Find duplicates of newly added images against precomputed hashes
+///
+/// Only `new_paths` are hashed. `existing` holds `(path, hash)` pairs from a
+/// previous scan; paths present in both are treated as new. Returns only
+/// groups that contain at least one new image, including any existing
+/// images transitively linked to them. Existing images are opened only to
+/// pick the best path within a returned group.
+pub fn find_duplicates_incremental(
+    new_paths: &[PathBuf],
+    existing: &[(PathBuf, ImageHash)],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+) -> Result<Vec<DuplicateGroup>, String> {
+    algorithm
+        .check_hash_size(hash_size)
+        .map_err(|e| e.to_string())?;
+    let new_paths = primaries_only(new_paths);
+    let new_images: Vec<ImageInfo> = new_paths
+        .par_iter()
+        .filter_map(|path| hash_image(path, algorithm, hash_size).ok())
+        .collect();
+    if new_images.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // New images first, so index < new_count means "new"
+    let new_count = new_images.len();
+    let new_set: HashSet<&Path> = new_paths.iter().map(|p| p.as_path()).collect();
+    let mut images = new_images;
+    images.extend(
+        existing
+            .iter()
+            .filter(|(path, _)| !new_set.contains(path.as_path()) && !is_sidecar(path))
+            .map(|(path, hash)| ImageInfo {
+                path: path.clone(),
+                hash: hash.clone(),
+                dimensions: None,
+            }),
+    );
+
+    let mut tree = MultiIndex::for_corpus(images[0].hash.bit_len(), images.len(), threshold);
+    for (i, image) in images.iter().enumerate() {
+        tree.add(image.hash.clone(), i);
+    }
+
+    // Walk each component reachable from a new image
+    let mut visited = vec![false; images.len()];
+    let mut components: Vec<Vec<usize>> = Vec::new();
+    for start in 0..new_count {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut component = vec![start];
+        let mut stack = vec![start];
+        while let Some(i) = stack.pop() {
+            for (&j, _) in tree.query(&images[i].hash, threshold) {
+                if !visited[j] {
+                    visited[j] = true;
+                    component.push(j);
+                    stack.push(j);
+                }
+            }
+        }
+        component.sort_unstable();
+        components.push(component);
+    }
+
+    // Read the size of existing members that made it into a group
+    let lookups: Vec<(usize, Option<(u32, u32)>)> = components
+        .iter()
+        .filter(|c| c.len() > 1)
+        .flatten()
+        .filter(|&&i| i >= new_count)
+        .copied()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|i| (i, image_dimensions(&images[i].path).ok()))
+        .collect();
+    for (i, dimensions) in lookups {
+        images[i].dimensions = dimensions;
+    }
 
-    Ok(result)
+    Ok(with_sidecars(
+        components
+            .iter()
+            .map(|indices| make_group(&images, indices))
+            .collect(),
+    ))
 }
 
-/// Get image resolution (width * height)
-fn get_image_resolution(path: &str) -> Result<u64, String> {
-    let img = image::open(path).map_err(|e| e.to_string())?;
-    let (w, h) = img.dimensions();
-    Ok(w as u64 * h as u64)
+/// Hash `path`, keeping the size of the image decoded for it
+fn hash_image(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+) -> Result<ImageInfo, HashError> {
+    let img = open_image(path)?;
+    Ok(ImageInfo {
+        path: path.to_path_buf(),
+        hash: ImageHash::from_image(&img, algorithm, hash_size)?,
+        dimensions: Some(img.dimensions()),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
     #[test]
     fn test_empty_input() {
-        let result = find_duplicates(&[], HashAlgorithm::PHash, 10).unwrap();
+        let result = find_duplicates(&[], HashAlgorithm::PHash, DEFAULT_HASH_SIZE, 10).unwrap();
+        assert!(find_duplicates(&[], HashAlgorithm::PHash, 64, 10).is_err());
         assert!(result.is_empty());
     }
+
+    #[test]
+    fn test_custom_hasher() {
+        let root = std::env::temp_dir().join(format!("phash_rs_hasher_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let paths: Vec<PathBuf> = [("a.png", 16), ("b.png", 20), ("c.png", 4)]
+            .iter()
+            .map(|&(name, size)| {
+                let path = root.join(name);
+                let mut png = std::io::Cursor::new(Vec::new());
+                image::DynamicImage::new_rgb8(size, size)
+                    .write_to(&mut png, image::ImageFormat::Png)
+                    .unwrap();
+                std::fs::write(&path, png.get_ref()).unwrap();
+                path
+            })
+            .chain([root.join("missing.png")])
+            .collect();
+        let options = HashOptions::default();
+
+        // One bit per image: large or small
+        let by_size = |img: &image::DynamicImage| ImageHash::from_bits(&[img.width() > 10; 8]);
+        let (groups, failures) =
+            find_duplicates_using(&paths, &by_size, 0, &options, None).unwrap();
+        let sizes: Vec<usize> = groups.iter().map(|g| g.paths.len()).collect();
+        assert_eq!(sizes.iter().sum::<usize>(), 3);
+        assert!(sizes.contains(&2));
+        assert_eq!(failures.len(), 1);
+
+        let uneven =
+            |img: &image::DynamicImage| ImageHash::from_bits(&vec![true; img.width() as usize]);
+        assert!(find_duplicates_using(&paths, &uneven, 0, &options, None).is_err());
+
+        // A built-in algorithm groups the same either way
+        let hasher = HashAlgorithm::PHash.hasher(8);
+        let (using, _) = find_duplicates_using(&paths, &hasher, 4, &options, None).unwrap();
+        let (with, _) = find_duplicates_with(
+            &paths,
+            HashAlgorithm::PHash,
+            8,
+            4,
+            false,
+            &options,
+            None,
+            None,
+        )
+        .unwrap();
+        let members = |groups: &[DuplicateGroup]| -> Vec<Vec<PathBuf>> {
+            groups.iter().map(|g| g.paths.clone()).collect()
+        };
+        assert_eq!(members(&using), members(&with));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_group_duplicates_with_variants() {
+        let hash = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let images: Vec<ImageInfo> = ["00000000000000ff", "ff00000000000000"]
+            .iter()
+            .zip(["a", "b"])
+            .map(|(hex, path)| ImageInfo {
+                path: PathBuf::from(path),
+                hash: hash(hex),
+                dimensions: None,
+            })
+            .collect();
+
+        assert_eq!(group_duplicates(images.clone(), 4).unwrap().len(), 2);
+        // Only "b" has a transform close to the other image
+        let variants = vec![
+            vec![hash("00000000000000ff")],
+            vec![hash("ff00000000000000"), hash("00000000000000fe")],
+        ];
+        let groups = group_duplicates_with(images, 4, Some(variants), None).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].paths, paths(&["a", "b"]));
+    }
+
+    #[test]
+    fn test_group_duplicates_transitive() {
+        let info = |path: &str, hex: &str| ImageInfo {
+            path: PathBuf::from(path),
+            hash: ImageHash::from_hex(hex).unwrap(),
+            dimensions: None,
+        };
+        let images = vec![
+            info("a", "0000000000000000"),
+            info("b", "000000000000000f"),
+            info("c", "00000000000000ff"),
+            info("d", "ffffffffffffffff"),
+        ];
+
+        let groups = group_duplicates(images, 4).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].paths, paths(&["a", "b", "c"]));
+        assert_eq!(groups[1].paths, paths(&["d"]));
+
+        // a-c only match through b, which is closest to both
+        assert_eq!(groups[0].hash, "000000000000000f");
+        let quality = groups[0].quality().unwrap();
+        assert_eq!(
+            quality.distances,
+            BTreeMap::from([("a".into(), 4), ("b".into(), 0), ("c".into(), 4)])
+        );
+        assert_eq!(quality.max_distance, 8);
+        assert!((quality.mean_distance - 16.0 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            groups[0].pairwise_distances().unwrap(),
+            vec![vec![0, 4, 8], vec![4, 0, 4], vec![8, 4, 0]]
+        );
+        let single = groups[1].quality().unwrap();
+        assert_eq!((single.max_distance, single.mean_distance), (0, 0.0));
+    }
+
+    #[test]
+    fn test_group_duplicates_coarse() {
+        let info = |path: &str, hex: &str| ImageInfo {
+            path: PathBuf::from(path),
+            hash: ImageHash::from_hex(hex).unwrap(),
+            dimensions: None,
+        };
+        let images = vec![
+            info("a", "0000000000000000"),
+            info("b", "000000000000000f"),
+            info("c", "00000000000000ff"),
+        ];
+        let coarse: Vec<ImageHash> = ["0000", "0001", "ff00"]
+            .iter()
+            .map(|hex| ImageHash::from_hex(hex).unwrap())
+            .collect();
+
+        // c is within the fine threshold of b, but its coarse hash is too
+        // far off to be compared
+        let groups = group_duplicates_coarse(images.clone(), &coarse, 2, 4, None).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].paths, paths(&["a", "b"]));
+        let groups = group_duplicates_coarse(images, &coarse, 16, 4, None).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(group_duplicates_coarse(Vec::new(), &[], 2, 4, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_group_linked_stops_when_cancelled() {
+        use crate::progress::CancelToken;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let images: Vec<ImageInfo> = (0..100)
+            .map(|i| ImageInfo {
+                path: PathBuf::from(i.to_string()),
+                hash: ImageHash::from_hex("0000000000000000").unwrap(),
+                dimensions: None,
+            })
+            .collect();
+        let lookups = AtomicUsize::new(0);
+        let linked = |i: usize| {
+            lookups.fetch_add(1, Ordering::Relaxed);
+            vec![(i + 1) % 100]
+        };
+        assert_eq!(group_linked(&images, linked, None).unwrap().len(), 1);
+        assert_eq!(lookups.swap(0, Ordering::Relaxed), 100);
+
+        let token = CancelToken::new();
+        token.cancel();
+        assert_eq!(
+            group_linked(&images, linked, Some(&token)).unwrap_err(),
+            "Cancelled"
+        );
+        assert_eq!(lookups.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_group_membership() {
+        let info = |path: &str, hex: &str| ImageInfo {
+            path: PathBuf::from(path),
+            hash: ImageHash::from_hex(hex).unwrap(),
+            dimensions: None,
+        };
+        let images = vec![
+            info("a", "0000000000000000"),
+            info("b", "000000000000000f"),
+            info("c", "ffffffffffffffff"),
+            info("d", "fffffffffffffff0"),
+        ];
+        let groups = group_duplicates(images, 4).unwrap();
+        let mapping = group_membership(&groups);
+
+        assert_eq!(mapping.len(), 4);
+        assert_eq!(
+            mapping[Path::new("a")],
+            GroupMembership {
+                group_id: 0,
+                distance_to_best: Some(0),
+                is_best: true,
+            }
+        );
+        assert_eq!(mapping[Path::new("b")].distance_to_best, Some(4));
+        assert!(!mapping[Path::new("b")].is_best);
+        assert_eq!(mapping[Path::new("c")].group_id, 1);
+        assert_eq!(mapping[Path::new("d")].group_id, 1);
+    }
+
+    #[test]
+    fn test_find_duplicates_in_hashes() {
+        let entry = |path: &str, hex: &str, dimensions| {
+            (
+                PathBuf::from(path),
+                ImageHash::from_hex(hex).unwrap(),
+                dimensions,
+            )
+        };
+        let hashes = vec![
+            entry("/db/a.jpg", "0000000000000000", Some((640, 480))),
+            entry("/db/b.jpg", "000000000000000f", Some((1920, 1080))),
+            entry("/db/c.jpg", "ffffffffffffffff", None),
+        ];
+        let groups = find_duplicates_in_hashes(&hashes, 4).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].paths, paths(&["/db/a.jpg", "/db/b.jpg"]));
+        assert_eq!(groups[0].best_path, Path::new("/db/b.jpg"));
+        assert_eq!(
+            groups[0].member_dimensions[Path::new("/db/a.jpg")],
+            (640, 480)
+        );
+
+        let mixed = vec![
+            hashes[0].clone(),
+            entry("/db/d.jpg", "00000000000000000000000000000000", None),
+        ];
+        assert!(find_duplicates_in_hashes(&mixed, 4).is_err());
+        assert!(find_duplicates_in_hashes(&[], 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_relative_to() {
+        let group = DuplicateGroup {
+            paths: paths(&["/mnt/b/x.jpg", "/mnt/a/y.jpg"]),
+            hash: "00".to_string(),
+            best_path: PathBuf::from("/mnt/b/x.jpg"),
+            sidecars: BTreeMap::from([(PathBuf::from("/mnt/b/x.jpg"), paths(&["/mnt/b/x.xmp"]))]),
+            content_type: None,
+            member_hashes: BTreeMap::new(),
+            member_dimensions: BTreeMap::new(),
+        };
+        let rel = group.relative_to(Path::new("/mnt"));
+        assert_eq!(rel.paths, paths(&["a/y.jpg", "b/x.jpg"]));
+        assert_eq!(rel.best_path, Path::new("b/x.jpg"));
+        assert_eq!(rel.sidecars[Path::new("b/x.jpg")], paths(&["b/x.xmp"]));
+    }
+
+    #[test]
+    fn test_incremental_without_new_images() {
+        let existing = vec![(
+            PathBuf::from("old.jpg"),
+            ImageHash::from_hex("0000000000000000").unwrap(),
+        )];
+        let groups = find_duplicates_incremental(
+            &paths(&["/nonexistent/new.jpg"]),
+            &existing,
+            HashAlgorithm::PHash,
+            DEFAULT_HASH_SIZE,
+            10,
+        )
+        .unwrap();
+        assert!(groups.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "exact")]
+    fn test_exact_prepass() {
+        let root = std::env::temp_dir().join(format!("phash_rs_exact_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = |name: &str| root.join(name);
+        std::fs::write(path("a.jpg"), b"same bytes").unwrap();
+        std::fs::write(path("b.jpg"), b"same bytes").unwrap();
+        std::fs::write(path("c.jpg"), b"other byte").unwrap();
+        std::fs::write(path("d.jpg"), b"short").unwrap();
+        std::fs::write(path("e.jpg"), b"short").unwrap();
+        let paths: Vec<PathBuf> = ["a.jpg", "c.jpg", "b.jpg", "d.jpg", "e.jpg", "x.jpg"]
+            .iter()
+            .map(|n| path(n))
+            .collect();
+
+        let classes = exact_duplicates(&paths, None);
+        assert_eq!(
+            classes,
+            vec![
+                vec![path("a.jpg"), path("b.jpg")],
+                vec![path("c.jpg")],
+                vec![path("d.jpg"), path("e.jpg")],
+                vec![path("x.jpg")],
+            ]
+        );
+
+        // a and c look alike; d fails to decode
+        let (groups, failures) = with_exact_prepass(&paths, None, |reps| {
+            assert_eq!(
+                reps,
+                [path("a.jpg"), path("c.jpg"), path("d.jpg"), path("x.jpg")]
+            );
+            let images: Vec<ImageInfo> = reps[..2]
+                .iter()
+                .map(|p| ImageInfo {
+                    path: p.clone(),
+                    hash: ImageHash::from_hex("0000000000000000").unwrap(),
+                    dimensions: None,
+                })
+                .collect();
+            let error = HashError::Decode("bad".to_string());
+            Ok((
+                vec![make_group(&images, &[0, 1])],
+                vec![(reps[2].clone(), error.clone()), (reps[3].clone(), error)],
+            ))
+        })
+        .unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].paths,
+            [path("a.jpg"), path("b.jpg"), path("c.jpg")]
+        );
+        let failed: Vec<&PathBuf> = failures.iter().map(|(p, _)| p).collect();
+        assert_eq!(failed, [&path("d.jpg"), &path("e.jpg"), &path("x.jpg")]);
+    }
 }
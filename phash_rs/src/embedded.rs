@@ -0,0 +1,173 @@
+//! Embedded Image Module
+//!
+//! Finds JPEG images stored inside other files: the previews camera RAW
+//! files carry next to the sensor data, and the DCT-encoded images of a
+//! PDF. Hashing the embedded image avoids a RAW converter or PDF renderer.
+
+use image::DynamicImage;
+use std::cmp::Reverse;
+
+/// A baseline or progressive JPEG inside a larger file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EmbeddedJpeg {
+    /// Offset of the SOI marker
+    pub start: usize,
+    /// Offset just past the EOI marker
+    pub end: usize,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// All complete JPEG images in `data`, in file order
+///
+/// Thumbnails nested inside a found image (EXIF thumbnails in a preview)
+/// are skipped with it. Lossless and arithmetic-coded JPEGs, which the
+/// `image` crate can't decode, are left out; RAW formats use lossless
+/// JPEG for the sensor data.
+pub(crate) fn embedded_jpegs(data: &[u8]) -> Vec<EmbeddedJpeg> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = data[pos..].windows(3).position(|w| w == [0xff, 0xd8, 0xff]) {
+        let start = pos + offset;
+        match parse_jpeg(data, start) {
+            Some(jpeg) => {
+                pos = jpeg.end;
+                found.push(jpeg);
+            }
+            None => pos = start + 2,
+        }
+    }
+    found
+}
+
+/// Follow the marker segments of the JPEG at `start` to its EOI
+fn parse_jpeg(data: &[u8], start: usize) -> Option<EmbeddedJpeg> {
+    let mut pos = start + 2;
+    let mut size = None;
+    loop {
+        if *data.get(pos)? != 0xff {
+            return None;
+        }
+        // Any number of fill bytes may precede a marker
+        while *data.get(pos + 1)? == 0xff {
+            pos += 1;
+        }
+        let marker = data[pos + 1];
+        pos += 2;
+        if marker == 0xd9 {
+            let (width, height) = size?;
+            return Some(EmbeddedJpeg {
+                start,
+                end: pos,
+                width,
+                height,
+            });
+        }
+
+        let len = u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        if len < 2 {
+            return None;
+        }
+        let segment = data.get(pos + 2..pos + len)?;
+        match marker {
+            // Huffman-coded baseline, extended and progressive frames
+            0xc0..=0xc2 => {
+                if segment.len() < 5 {
+                    return None;
+                }
+                let height = u16::from_be_bytes([segment[1], segment[2]]);
+                let width = u16::from_be_bytes([segment[3], segment[4]]);
+                size = Some((width, height));
+            }
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return None,
+            _ => {}
+        }
+        pos += len;
+
+        if marker == 0xda {
+            // Entropy-coded data runs up to the next marker; in it, 0xff
+            // is followed by a stuffed zero or a restart marker
+            loop {
+                if *data.get(pos)? == 0xff {
+                    let next = *data.get(pos + 1)?;
+                    if next != 0x00 && next != 0xff && !(0xd0..=0xd7).contains(&next) {
+                        break;
+                    }
+                }
+                pos += 1;
+            }
+        }
+    }
+}
+
+/// Decode the largest embedded JPEG in `data` that decodes
+pub(crate) fn decode_largest_jpeg(data: &[u8]) -> Result<DynamicImage, String> {
+    let mut jpegs = embedded_jpegs(data);
+    if jpegs.is_empty() {
+        return Err("no embedded JPEG image".to_string());
+    }
+    jpegs.sort_by_key(|j| Reverse(j.width as u64 * j.height as u64));
+    let mut errors = Vec::new();
+    for jpeg in jpegs {
+        match image::load_from_memory(&data[jpeg.start..jpeg.end]) {
+            Ok(img) => return Ok(img),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    Err(errors.join("; "))
+}
+
+/// Decode every embedded JPEG in `data`, in file order
+pub(crate) fn decode_jpegs(data: &[u8]) -> Vec<Result<DynamicImage, String>> {
+    embedded_jpegs(data)
+        .into_iter()
+        .map(|jpeg| image::load_from_memory(&data[jpeg.start..jpeg.end]).map_err(|e| e.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Marker segments of a JPEG with the given frame marker and size
+    fn jpeg(frame: u8, width: u16, height: u16, nested: &[u8]) -> Vec<u8> {
+        let mut out = vec![0xff, 0xd8];
+        // APP1 holding another image, as EXIF thumbnails do
+        out.extend([0xff, 0xe1]);
+        out.extend((nested.len() as u16 + 2).to_be_bytes());
+        out.extend(nested);
+        out.extend([0xff, frame, 0x00, 0x0b, 0x08]);
+        out.extend(height.to_be_bytes());
+        out.extend(width.to_be_bytes());
+        out.extend([0x01, 0x01, 0x11, 0x00]);
+        out.extend([0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00]);
+        // Entropy-coded bytes with a stuffed 0xff and a restart marker
+        out.extend([0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56]);
+        out.extend([0xff, 0xd9]);
+        out
+    }
+
+    #[test]
+    fn test_finds_embedded_jpegs() {
+        let thumbnail = jpeg(0xc0, 160, 120, &[]);
+        let preview = jpeg(0xc2, 1620, 1080, &thumbnail);
+        let sensor = jpeg(0xc3, 5200, 3464, &[]);
+
+        let mut container = b"II*\x00 header ".to_vec();
+        let preview_at = container.len();
+        container.extend(&preview);
+        container.extend(b" \xff\xd8\xff truncated ");
+        container.extend(&sensor);
+
+        assert_eq!(
+            embedded_jpegs(&container),
+            vec![EmbeddedJpeg {
+                start: preview_at,
+                end: preview_at + preview.len(),
+                width: 1620,
+                height: 1080,
+            }]
+        );
+        assert!(embedded_jpegs(b"%PDF-1.7 no images").is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+//! Error Module
+//!
+//! Typed errors for hashing a file, so batch callers can tell a missing or
+//! unreadable file from a corrupt image or a format nothing can decode.
+
+use std::fmt;
+use std::path::PathBuf;
+
+/// Paths that could not be hashed, with the reason
+pub type HashFailures = Vec<(PathBuf, HashError)>;
+
+/// Why an image could not be hashed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashError {
+    /// The file could not be accessed (missing, permissions, ...)
+    Io(String),
+    /// No compiled-in or configured backend handles this extension
+    UnsupportedFormat(String),
+    /// A backend accepted the file but failed to decode it
+    Decode(String),
+    /// Invalid arguments or hash strings
+    InvalidInput(String),
+    /// The file exceeds the configured decode limits (`DecodeLimits`)
+    TooLarge(String),
+}
+
+impl HashError {
+    /// Stable lowercase name used by the Python bindings
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HashError::Io(_) => "io",
+            HashError::UnsupportedFormat(_) => "unsupported_format",
+            HashError::Decode(_) => "decode",
+            HashError::InvalidInput(_) => "invalid_input",
+            HashError::TooLarge(_) => "too_large",
+        }
+    }
+
+    /// The message without the prefix `Display` adds
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            HashError::Io(msg)
+            | HashError::UnsupportedFormat(msg)
+            | HashError::Decode(msg)
+            | HashError::InvalidInput(msg)
+            | HashError::TooLarge(msg) => msg,
+        }
+    }
+
+    /// Rebuild an error from its `kind` and `message`, e.g. when read
+    /// back from a file
+    pub(crate) fn from_kind(kind: &str, message: String) -> Option<Self> {
+        Some(match kind {
+            "io" => HashError::Io(message),
+            "unsupported_format" => HashError::UnsupportedFormat(message),
+            "decode" => HashError::Decode(message),
+            "invalid_input" => HashError::InvalidInput(message),
+            "too_large" => HashError::TooLarge(message),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashError::Io(msg) => write!(f, "Failed to read image: {}", msg),
+            HashError::UnsupportedFormat(ext) => {
+                write!(f, "No decoder available for .{} files", ext)
+            }
+            HashError::Decode(msg) => write!(f, "Failed to open image: {}", msg),
+            HashError::InvalidInput(msg) => f.write_str(msg),
+            HashError::TooLarge(msg) => write!(f, "Image too large to decode: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for HashError {}
+
+impl From<HashError> for String {
+    fn from(e: HashError) -> Self {
+        e.to_string()
+    }
+}
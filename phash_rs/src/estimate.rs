@@ -0,0 +1,251 @@
+//! Scan Estimate Module
+//!
+//! Predicts the cost of a scan from file metadata alone (counts, bytes to
+//! decode, duration, peak memory) so operators can schedule large scans
+//! into maintenance windows instead of guessing.
+
+use crate::cache::{FileStamp, HashCache};
+use crate::decode::{decoder_config, normalize_ext, open_image, DecodeBackend, RAW_EXTENSIONS};
+use crate::hash::{HashAlgorithm, ImageHash, DEFAULT_HASH_SIZE};
+use crate::sidecar::is_sidecar;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Single-thread decode + hash throughput in bytes per second for a format
+/// decoded by a backend, measured on a typical desktop core
+fn default_bytes_per_sec(ext: &str, backend: DecodeBackend) -> f64 {
+    const MB: f64 = 1024.0 * 1024.0;
+    match (backend, ext) {
+        (DecodeBackend::External | DecodeBackend::Custom, _) => 5.0 * MB,
+        (DecodeBackend::TurboJpeg, _) => 110.0 * MB,
+        (DecodeBackend::LibHeif, _) => 20.0 * MB,
+        // The file is only searched; just the embedded preview is decoded
+        (DecodeBackend::Raw | DecodeBackend::Pdf, _) => 150.0 * MB,
+        (_, "jpg" | "jpeg" | "jpe" | "jfif") => 45.0 * MB,
+        (_, "png") => 35.0 * MB,
+        (_, "webp") => 25.0 * MB,
+        (_, "gif") => 40.0 * MB,
+        (_, "bmp") => 400.0 * MB,
+        (_, "tif" | "tiff") => 150.0 * MB,
+        _ => 30.0 * MB,
+    }
+}
+
+/// Decoded size relative to the file size, used for the memory estimate
+fn expansion(ext: &str) -> f64 {
+    match ext {
+        "jpg" | "jpeg" | "jpe" | "jfif" => 10.0,
+        "heic" | "heif" | "hif" | "avif" => 15.0,
+        "webp" => 12.0,
+        "png" => 3.0,
+        "gif" => 4.0,
+        "bmp" | "tif" | "tiff" => 1.2,
+        // The whole file is read, plus the decoded preview
+        "pdf" => 1.5,
+        ext if RAW_EXTENSIONS.contains(&ext) => 1.5,
+        _ => 5.0,
+    }
+}
+
+/// Per-format throughput used for duration estimates
+///
+/// Starts from built-in figures for each format and backend; `calibrate`
+/// replaces them with throughput measured on this machine.
+#[derive(Debug, Clone, Default)]
+pub struct Throughput {
+    /// Measured bytes per second by lowercase extension
+    measured: BTreeMap<String, f64>,
+}
+
+impl Throughput {
+    /// Built-in figures only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a measured throughput for an extension
+    pub fn set(&mut self, ext: &str, bytes_per_sec: f64) {
+        if bytes_per_sec > 0.0 {
+            self.measured.insert(normalize_ext(ext), bytes_per_sec);
+        }
+    }
+
+    /// Measure throughput by hashing up to `samples` files of each format
+    ///
+    /// Files are taken in input order; formats without a successfully hashed
+    /// sample keep the built-in figure.
+    pub fn calibrate(paths: &[PathBuf], samples: usize, algorithm: HashAlgorithm) -> Self {
+        let mut by_ext: BTreeMap<String, Vec<&PathBuf>> = BTreeMap::new();
+        for path in paths.iter().filter(|p| !is_sidecar(p)) {
+            let group = by_ext.entry(extension_of(path)).or_default();
+            if group.len() < samples {
+                group.push(path);
+            }
+        }
+
+        let mut throughput = Self::new();
+        for (ext, sample) in by_ext {
+            let (mut bytes, mut seconds) = (0u64, 0f64);
+            for path in sample {
+                let Ok(meta) = std::fs::metadata(path) else {
+                    continue;
+                };
+                let start = Instant::now();
+                let hashed = open_image(path)
+                    .and_then(|img| ImageHash::from_image(&img, algorithm, DEFAULT_HASH_SIZE))
+                    .is_ok();
+                if hashed {
+                    bytes += meta.len();
+                    seconds += start.elapsed().as_secs_f64();
+                }
+            }
+            if bytes > 0 && seconds > 0.0 {
+                throughput.set(&ext, bytes as f64 / seconds);
+            }
+        }
+        throughput
+    }
+
+    /// Bytes per second for `ext` decoded by `backend`
+    pub fn bytes_per_sec(&self, ext: &str, backend: DecodeBackend) -> f64 {
+        self.measured
+            .get(ext)
+            .copied()
+            .unwrap_or_else(|| default_bytes_per_sec(ext, backend))
+    }
+}
+
+/// Predicted cost of the files of one format
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FormatEstimate {
+    /// Files that need decoding
+    pub files: usize,
+    /// Bytes that need decoding
+    pub bytes: u64,
+    /// Single-thread decode + hash time in seconds
+    pub seconds: f64,
+}
+
+/// Predicted cost of a scan
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanEstimate {
+    /// Image files considered (sidecars excluded)
+    pub files: usize,
+    /// Size of all image files
+    pub total_bytes: u64,
+    /// Files whose cached hash is still valid
+    pub cached_files: usize,
+    /// Files no configured backend can decode
+    pub unsupported_files: usize,
+    /// Files that could not be stat'ed
+    pub unreadable_files: usize,
+    /// Bytes that need decoding
+    pub decode_bytes: u64,
+    /// Expected wall-clock duration in seconds
+    pub seconds: f64,
+    /// Expected peak memory for decoded images, in bytes
+    pub peak_memory_bytes: u64,
+    /// Work by lowercase extension
+    pub formats: BTreeMap<String, FormatEstimate>,
+}
+
+/// Estimate the cost of hashing `paths` on `threads` worker threads
+///
+/// Only file metadata is read. Files with a valid entry in `cache` (for
+/// `algorithm` at the batch hash size) cost nothing. Each file is assumed
+/// to be decoded by the first backend available for its format. Peak
+/// memory assumes the `threads` largest files are decoded at the same time.
+pub fn estimate_paths(
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threads: usize,
+    throughput: &Throughput,
+    cache: Option<&HashCache>,
+) -> ScanEstimate {
+    let config = decoder_config();
+    let mut estimate = ScanEstimate::default();
+    let mut decoded_sizes: Vec<u64> = Vec::new();
+
+    for path in paths.iter().filter(|p| !is_sidecar(p)) {
+        estimate.files += 1;
+        let Some(stamp) = FileStamp::of(path) else {
+            estimate.unreadable_files += 1;
+            continue;
+        };
+        let size = stamp.size();
+        estimate.total_bytes += size;
+
+        if cache.is_some_and(|c| c.get(path, algorithm, hash_size, &stamp).is_some()) {
+            estimate.cached_files += 1;
+            continue;
+        }
+        let ext = extension_of(path);
+        let Some(&backend) = config.available_backends_for(&ext).first() else {
+            estimate.unsupported_files += 1;
+            continue;
+        };
+
+        let format = estimate.formats.entry(ext.clone()).or_default();
+        format.files += 1;
+        format.bytes += size;
+        format.seconds += size as f64 / throughput.bytes_per_sec(&ext, backend);
+        estimate.decode_bytes += size;
+        decoded_sizes.push((size as f64 * expansion(&ext)) as u64);
+    }
+
+    let work: f64 = estimate.formats.values().map(|f| f.seconds).sum();
+    let workers = threads.clamp(1, decoded_sizes.len().max(1));
+    estimate.seconds = work / workers as f64;
+
+    decoded_sizes.sort_unstable_by(|a, b| b.cmp(a));
+    estimate.peak_memory_bytes = decoded_sizes.iter().take(workers).sum();
+    estimate
+}
+
+/// Lowercase extension of `path`, or "" if it has none
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|e| normalize_ext(&e.to_string_lossy()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_from_metadata() {
+        let root = std::env::temp_dir().join(format!("phash_rs_estimate_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = |name: &str| root.join(name);
+        std::fs::write(path("a.png"), vec![0u8; 1000]).unwrap();
+        std::fs::write(path("b.png"), vec![0u8; 3000]).unwrap();
+        std::fs::write(path("a.xmp"), b"").unwrap();
+
+        let mut throughput = Throughput::new();
+        throughput.set("png", 1000.0);
+        let paths: Vec<PathBuf> = ["a.png", "b.png", "a.xmp", "missing.png"]
+            .iter()
+            .map(|n| path(n))
+            .collect();
+        let estimate = estimate_paths(
+            &paths,
+            HashAlgorithm::PHash,
+            DEFAULT_HASH_SIZE,
+            2,
+            &throughput,
+            None,
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(estimate.files, 3);
+        assert_eq!(estimate.unreadable_files, 1);
+        assert_eq!(estimate.decode_bytes, 4000);
+        assert_eq!(estimate.formats["png"].files, 2);
+        // 4 s of work split over two threads
+        assert!((estimate.seconds - 2.0).abs() < 1e-9);
+        assert_eq!(estimate.peak_memory_bytes, 12000);
+    }
+}
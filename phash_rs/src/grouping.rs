@@ -0,0 +1,238 @@
+//! Grouping Strategies Module
+//!
+//! The duplicate finders link every two images within the threshold and
+//! report what stays connected, so a chain A-B-C forms one group even when
+//! A and C look nothing alike. The stricter strategies here split each
+//! connected group further so that members match each other, or a common
+//! center, directly.
+
+use crate::duplicate::{make_group, sort_groups, DuplicateGroup, ImageInfo};
+use std::fmt;
+use std::str::FromStr;
+
+/// How images within the threshold of each other are formed into groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Grouping {
+    /// Everything connected through a chain of matches (union-find); the
+    /// loosest, and what the finders return
+    #[default]
+    Connected,
+    /// Every two members within the threshold of each other
+    Clique,
+    /// Every member within the threshold of one center member
+    Medoid,
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    /// Parse a strategy as used by the Python bindings.
+    ///
+    /// Accepts `"connected"`, `"clique"` and `"medoid"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "connected" => Ok(Grouping::Connected),
+            "clique" => Ok(Grouping::Clique),
+            "medoid" => Ok(Grouping::Medoid),
+            _ => Err(format!("Unknown grouping: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Grouping {
+    /// Canonical name, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Grouping::Connected => write!(f, "connected"),
+            Grouping::Clique => write!(f, "clique"),
+            Grouping::Medoid => write!(f, "medoid"),
+        }
+    }
+}
+
+/// Split connected groups as `grouping` says
+///
+/// Every clique or star within `threshold` lies inside one connected
+/// group, so each group from a finder is split on its own, by the
+/// distances between its member hashes. Groups are taken greedily: the
+/// member matching the most others (the first path on ties) seeds the next
+/// group, until every member is placed; leftovers end up in groups of their
+/// own. Groups without member hashes, and all groups with
+/// `Grouping::Connected`, are kept as they are.
+pub fn regroup(
+    groups: Vec<DuplicateGroup>,
+    grouping: Grouping,
+    threshold: u32,
+) -> Vec<DuplicateGroup> {
+    let split: fn(&[Vec<bool>]) -> Vec<Vec<usize>> = match grouping {
+        Grouping::Connected => return groups,
+        Grouping::Clique => cliques,
+        Grouping::Medoid => stars,
+    };
+    let mut regrouped = Vec::new();
+    for group in groups {
+        if group.paths.len() < 3
+            || !group
+                .paths
+                .iter()
+                .all(|p| group.member_hashes.contains_key(p))
+        {
+            regrouped.push(group);
+            continue;
+        }
+        let images: Vec<ImageInfo> = group
+            .paths
+            .iter()
+            .map(|path| ImageInfo {
+                path: path.clone(),
+                hash: group.member_hashes[path].clone(),
+                dimensions: group.member_dimensions.get(path).copied(),
+            })
+            .collect();
+        let n = images.len();
+        let linked: Vec<Vec<bool>> = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| i != j && images[i].hash.distance(&images[j].hash) <= threshold)
+                    .collect()
+            })
+            .collect();
+        for indices in split(&linked) {
+            let mut part = make_group(&images, &indices);
+            part.content_type = group.content_type;
+            part.sidecars = group
+                .sidecars
+                .iter()
+                .filter(|(path, _)| part.paths.contains(path))
+                .map(|(path, files)| (path.clone(), files.clone()))
+                .collect();
+            regrouped.push(part);
+        }
+    }
+    sort_groups(&mut regrouped);
+    regrouped
+}
+
+/// Unplaced members, those linked to the most other unplaced members first
+fn by_degree(linked: &[Vec<bool>], placed: &[bool]) -> Vec<usize> {
+    let degree = |i: usize| {
+        (0..linked.len())
+            .filter(|&j| !placed[j] && linked[i][j])
+            .count()
+    };
+    let mut order: Vec<usize> = (0..linked.len()).filter(|&i| !placed[i]).collect();
+    order.sort_by_key(|&i| (std::cmp::Reverse(degree(i)), i));
+    order
+}
+
+/// Greedy partition into cliques of `linked`
+fn cliques(linked: &[Vec<bool>]) -> Vec<Vec<usize>> {
+    let mut placed = vec![false; linked.len()];
+    let mut parts = Vec::new();
+    while placed.iter().any(|&p| !p) {
+        let order = by_degree(linked, &placed);
+        let mut clique = vec![order[0]];
+        for &candidate in &order[1..] {
+            if clique.iter().all(|&member| linked[member][candidate]) {
+                clique.push(candidate);
+            }
+        }
+        for &i in &clique {
+            placed[i] = true;
+        }
+        clique.sort_unstable();
+        parts.push(clique);
+    }
+    parts
+}
+
+/// Greedy partition into stars of `linked`: a center and the unplaced
+/// members linked to it
+fn stars(linked: &[Vec<bool>]) -> Vec<Vec<usize>> {
+    let mut placed = vec![false; linked.len()];
+    let mut parts = Vec::new();
+    while placed.iter().any(|&p| !p) {
+        let center = by_degree(linked, &placed)[0];
+        let star: Vec<usize> = (0..linked.len())
+            .filter(|&j| !placed[j] && (j == center || linked[center][j]))
+            .collect();
+        for &i in &star {
+            placed[i] = true;
+        }
+        parts.push(star);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duplicate::group_components;
+    use crate::hash::ImageHash;
+    use std::path::PathBuf;
+
+    /// A chain of hashes 4 bits apart: a-b-c-d-e
+    fn chain() -> Vec<DuplicateGroup> {
+        let images: Vec<ImageInfo> = (0..5)
+            .map(|i| ImageInfo {
+                path: PathBuf::from(format!("{}.jpg", (b'a' + i as u8) as char)),
+                hash: ImageHash::from_u64((1u64 << (4 * i)) - 1),
+                dimensions: Some((100, 100)),
+            })
+            .collect();
+        let neighbours = (0..5).map(|i| (i + 1..5).filter(|&j| j == i + 1).collect());
+        group_components(&images, neighbours.collect())
+    }
+
+    fn members(groups: &[DuplicateGroup]) -> Vec<Vec<&str>> {
+        let mut members: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|g| g.paths.iter().filter_map(|p| p.to_str()).collect())
+            .collect();
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn test_grouping_from_str() {
+        for grouping in [Grouping::Connected, Grouping::Clique, Grouping::Medoid] {
+            assert_eq!(grouping.to_string().parse::<Grouping>(), Ok(grouping));
+        }
+        assert!("transitive".parse::<Grouping>().is_err());
+    }
+
+    #[test]
+    fn test_regroup_breaks_chains() {
+        let groups = chain();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            members(&regroup(groups.clone(), Grouping::Connected, 4)),
+            vec![vec!["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"]]
+        );
+
+        // Within 8 bits b, c and d all match each other; a and e are
+        // left on their own
+        let cliques = regroup(groups.clone(), Grouping::Clique, 8);
+        assert_eq!(
+            members(&cliques),
+            vec![
+                vec!["a.jpg"],
+                vec!["b.jpg", "c.jpg", "d.jpg"],
+                vec!["e.jpg"]
+            ]
+        );
+        for group in &cliques {
+            assert!(group.quality().unwrap().max_distance <= 8);
+        }
+
+        // c reaches everything within 8 bits
+        assert_eq!(
+            members(&regroup(groups.clone(), Grouping::Medoid, 8)),
+            vec![vec!["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"]]
+        );
+        assert_eq!(
+            members(&regroup(groups, Grouping::Medoid, 4)),
+            vec![vec!["a.jpg", "b.jpg", "c.jpg"], vec!["d.jpg", "e.jpg"]]
+        );
+    }
+}
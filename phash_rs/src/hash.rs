@@ -1,26 +1,454 @@
-//! Perceptual Hash Implementation (pHash)
+//! Perceptual Hash Implementation (pHash, wHash)
 //!
 //! Implements DCT-based perceptual hashing for robust duplicate detection
-//! that is resistant to resizing, format changes, and minor edits.
+//! that is resistant to resizing, format changes, and minor edits, plus a
+//! Haar wavelet hash compatible with `imagehash.whash` and a segment-based
+//! crop-resistant hash modelled on `imagehash.crop_resistant_hash`.
 
-use image::{imageops::FilterType, DynamicImage};
+use crate::base64;
+use crate::distance::{hamming, hamming_rows, BitWeights};
+use crate::error::HashError;
+use crate::index::{band_radii, band_range, for_each_within, probe_count};
+use crate::interop::{self, BitOrder};
+use crate::libphash::{self, MH_HASH_SIZE, RADIAL_PROJECTIONS};
+use crate::preprocess::HashOptions;
+use image::{imageops, imageops::FilterType, DynamicImage, GenericImageView};
+use rustdct::{DctPlanner, TransformType2And3};
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
 
-/// Hash algorithm type (only pHash supported)
+/// Side length of the grayscale image fed to the pHash DCT
+pub const DCT_SIZE: usize = 32;
+
+/// Largest DCT input side a `PHashConfig` accepts
+pub const MAX_DCT_SIZE: usize = 256;
+
+/// DCT-II plan for `size` points, planned once per size and shared by all
+/// threads
+fn dct_plan(size: usize) -> Arc<dyn TransformType2And3<f64>> {
+    static PLAN: OnceLock<Arc<dyn TransformType2And3<f64>>> = OnceLock::new();
+    static PLANNER: OnceLock<Mutex<DctPlanner<f64>>> = OnceLock::new();
+    if size == DCT_SIZE {
+        return PLAN
+            .get_or_init(|| DctPlanner::new().plan_dct2(DCT_SIZE))
+            .clone();
+    }
+    PLANNER
+        .get_or_init(|| Mutex::new(DctPlanner::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .plan_dct2(size)
+}
+
+/// Hash size used unless one is given: an 8x8 grid, 64-bit hashes
+pub const DEFAULT_HASH_SIZE: usize = 8;
+
+/// Hash size of the coarse level of `ImageHash::hierarchical_from_image`:
+/// a 4x4 grid, 16-bit hashes
+pub const COARSE_HASH_SIZE: usize = 4;
+
+/// Largest block grid side accepted for blockHash
+pub const BLOCKHASH_MAX_BLOCKS: usize = 64;
+
+/// Pixels per block side that blockHash averages over
+const BLOCKHASH_BLOCK_PIXELS: usize = 4;
+
+/// Largest square the wHash input is resized to
+pub const WHASH_MAX_IMAGE_SCALE: usize = u16::MAX as usize;
+
+/// Most band keys `ImageHash::index_probes` returns, summed over bands
+pub const MAX_INDEX_PROBES: usize = 1 << 20;
+
+/// Hash algorithm type
+#[allow(clippy::enum_variant_names)] // conventional algorithm names
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HashAlgorithm {
     /// Perceptual Hash - most robust, uses DCT
     PHash,
+    /// Wavelet Hash - Haar DWT low-frequency band compared to its median
+    ///
+    /// `level` is the number of Haar decomposition steps applied before the
+    /// hash is taken. `None` derives it from the image size the same way
+    /// `imagehash.whash` does.
+    WHash { level: Option<u32> },
+    /// Color Hash - pHash of the R, G and B channels, concatenated
+    ///
+    /// Three times as many bits as pHash, so recolored variants of the same
+    /// image are told apart.
+    ColorHash,
+    /// Block Mean Value Hash - mean brightness of each block of a
+    /// `hash_size` x `hash_size` grid compared to the median block
+    ///
+    /// Keeps the spatial layout of flat regions (panels, backgrounds) that
+    /// the DCT of pHash spreads over few coefficients, so it separates
+    /// screenshots of similar UIs better.
+    BlockHash,
+    /// Marr-Hildreth Hash - libphash's `ph_mh_imagehash`
+    ///
+    /// Edge response of a Mexican hat kernel over a 512x512 equalized
+    /// image, 72 bytes like libphash's; the hash size is always
+    /// `MH_HASH_SIZE`. Compared by Hamming distance like the others.
+    MarrHildreth,
+    /// Radial Variance Hash - libphash's `ph_image_digest`
+    ///
+    /// The hash size is the number of coefficients, one byte each (libphash
+    /// keeps `RADIAL_DEFAULT_COEFFS`). The bytes are not bits: compare
+    /// hashes with `ImageHash::cross_correlation`, not Hamming distance,
+    /// so the duplicate finders don't apply.
+    RadialVariance,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    /// Parse an algorithm name as used by the Python bindings.
+    ///
+    /// Accepts `"phash"`, `"whash"`, `"whash:<level>"`, `"colorhash"`,
+    /// `"blockhash"`, `"marrhildreth"` and `"radialvariance"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        match name.as_str() {
+            "phash" => Ok(HashAlgorithm::PHash),
+            "whash" => Ok(HashAlgorithm::WHash { level: None }),
+            "colorhash" => Ok(HashAlgorithm::ColorHash),
+            "blockhash" => Ok(HashAlgorithm::BlockHash),
+            "marrhildreth" => Ok(HashAlgorithm::MarrHildreth),
+            "radialvariance" => Ok(HashAlgorithm::RadialVariance),
+            _ => match name.strip_prefix("whash:") {
+                Some(level) => level
+                    .parse::<u32>()
+                    .map(|level| HashAlgorithm::WHash { level: Some(level) })
+                    .map_err(|_| format!("Invalid wHash level: {}", level)),
+                None => Err(format!("Unknown hash algorithm: {}", s)),
+            },
+        }
+    }
+}
+
+impl HashAlgorithm {
+    /// This algorithm as a `Hasher` producing hashes of `hash_size`
+    pub fn hasher(self, hash_size: usize) -> impl Hasher {
+        move |img: &DynamicImage| ImageHash::from_image(img, self, hash_size)
+    }
+
+    /// Check that this algorithm can produce hashes of `hash_size`
+    ///
+    /// pHash and colorHash keep the top-left `hash_size` x `hash_size`
+    /// coefficients of a `DCT_SIZE`-point DCT, so 2 to `DCT_SIZE` works;
+    /// wHash needs a power of two of at least 2; blockHash takes 2 to
+    /// `BLOCKHASH_MAX_BLOCKS` blocks per side. Marr-Hildreth hashes always
+    /// have size `MH_HASH_SIZE`; radial variance takes 2 to
+    /// `RADIAL_PROJECTIONS` coefficients.
+    pub fn check_hash_size(self, hash_size: usize) -> Result<(), HashError> {
+        match self {
+            HashAlgorithm::PHash | HashAlgorithm::ColorHash => {
+                if !(2..=DCT_SIZE).contains(&hash_size) {
+                    return Err(HashError::InvalidInput(format!(
+                        "{} hash size must be between 2 and {}, got {}",
+                        self, DCT_SIZE, hash_size
+                    )));
+                }
+            }
+            HashAlgorithm::WHash { .. } => {
+                if hash_size < 2 || !hash_size.is_power_of_two() {
+                    return Err(HashError::InvalidInput(format!(
+                        "wHash size must be a power of 2, got {}",
+                        hash_size
+                    )));
+                }
+            }
+            HashAlgorithm::BlockHash => {
+                if !(2..=BLOCKHASH_MAX_BLOCKS).contains(&hash_size) {
+                    return Err(HashError::InvalidInput(format!(
+                        "blockHash size must be between 2 and {}, got {}",
+                        BLOCKHASH_MAX_BLOCKS, hash_size
+                    )));
+                }
+            }
+            HashAlgorithm::MarrHildreth => {
+                if hash_size != MH_HASH_SIZE {
+                    return Err(HashError::InvalidInput(format!(
+                        "Marr-Hildreth hash size must be {}, got {}",
+                        MH_HASH_SIZE, hash_size
+                    )));
+                }
+            }
+            HashAlgorithm::RadialVariance => {
+                if !(2..=RADIAL_PROJECTIONS).contains(&hash_size) {
+                    return Err(HashError::InvalidInput(format!(
+                        "Radial variance hash size must be between 2 and {}, got {}",
+                        RADIAL_PROJECTIONS, hash_size
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Length in bytes of a hash of `hash_size` from this algorithm
+    ///
+    /// Every bit plane is padded to a whole byte: colorHash has three.
+    /// Radial variance has one byte per coefficient.
+    pub(crate) fn hash_bytes(self, hash_size: usize) -> usize {
+        let plane = (hash_size * hash_size).div_ceil(8);
+        match self {
+            HashAlgorithm::ColorHash => 3 * plane,
+            HashAlgorithm::RadialVariance => hash_size,
+            _ => plane,
+        }
+    }
 }
 
+impl fmt::Display for HashAlgorithm {
+    /// Canonical name, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::PHash => write!(f, "phash"),
+            HashAlgorithm::WHash { level: None } => write!(f, "whash"),
+            HashAlgorithm::WHash { level: Some(level) } => write!(f, "whash:{}", level),
+            HashAlgorithm::ColorHash => write!(f, "colorhash"),
+            HashAlgorithm::BlockHash => write!(f, "blockhash"),
+            HashAlgorithm::MarrHildreth => write!(f, "marrhildreth"),
+            HashAlgorithm::RadialVariance => write!(f, "radialvariance"),
+        }
+    }
+}
+
+/// How pHash and colorHash reduce an image before the DCT
+///
+/// The default, a 32x32 Lanczos3 downscale, matches `imagehash.phash`.
+/// A 16x16 Triangle downscale is several times faster; 64x64 keeps finer
+/// detail for large hash sizes. Hashes are only comparable between
+/// identical configs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PHashConfig {
+    /// Side of the grayscale square the DCT runs on (2 to `MAX_DCT_SIZE`);
+    /// the hash keeps its top-left `hash_size` x `hash_size` coefficients
+    pub dct_size: usize,
+    /// Filter used to downscale the image to `dct_size`
+    pub filter: FilterType,
+}
+
+impl Default for PHashConfig {
+    fn default() -> Self {
+        Self {
+            dct_size: DCT_SIZE,
+            filter: FilterType::Lanczos3,
+        }
+    }
+}
+
+impl PHashConfig {
+    /// Check that `dct_size` is in range
+    pub fn validate(&self) -> Result<(), HashError> {
+        if !(2..=MAX_DCT_SIZE).contains(&self.dct_size) {
+            return Err(HashError::InvalidInput(format!(
+                "dct_size must be between 2 and {}, got {}",
+                MAX_DCT_SIZE, self.dct_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// `HashAlgorithm::check_hash_size` with this config: pHash and
+    /// colorHash accept 2 to `dct_size`
+    pub fn check_hash_size(
+        &self,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<(), HashError> {
+        match algorithm {
+            HashAlgorithm::PHash | HashAlgorithm::ColorHash => {
+                self.validate()?;
+                if !(2..=self.dct_size).contains(&hash_size) {
+                    return Err(HashError::InvalidInput(format!(
+                        "{} hash size must be between 2 and dct_size {}, got {}",
+                        algorithm, self.dct_size, hash_size
+                    )));
+                }
+                Ok(())
+            }
+            _ => algorithm.check_hash_size(hash_size),
+        }
+    }
+
+    /// Suffix distinguishing cache entries hashed with this config; empty
+    /// for the default
+    pub(crate) fn tag(&self) -> String {
+        if *self == Self::default() {
+            return String::new();
+        }
+        format!("/dct{}{}", self.dct_size, filter_name(self.filter))
+    }
+}
+
+/// Canonical name of a resize filter, accepted back by `parse_filter`
+pub fn filter_name(filter: FilterType) -> &'static str {
+    match filter {
+        FilterType::Nearest => "nearest",
+        FilterType::Triangle => "triangle",
+        FilterType::CatmullRom => "catmullrom",
+        FilterType::Gaussian => "gaussian",
+        FilterType::Lanczos3 => "lanczos3",
+    }
+}
+
+/// Parse a resize filter name: `"nearest"`, `"triangle"`, `"catmullrom"`,
+/// `"gaussian"` or `"lanczos3"`
+pub fn parse_filter(name: &str) -> Result<FilterType, String> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "nearest" => Ok(FilterType::Nearest),
+        "triangle" => Ok(FilterType::Triangle),
+        "catmullrom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos3" => Ok(FilterType::Lanczos3),
+        _ => Err(format!("Unknown resize filter: {}", name)),
+    }
+}
+
+/// Which frames of an animated GIF, WebP or PNG are hashed
+///
+/// Every sampling hashes a fixed number of frames; short animations and
+/// still images repeat their last frame, so all hashes made with one
+/// sampling have the same length and compare with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameSampling {
+    /// Only the first frame, like a still image
+    #[default]
+    First,
+    /// The first `n` frames; decoding stops there, so long animations are cheap
+    Leading(usize),
+    /// `n` frames spread evenly over the whole animation, starting with the
+    /// first; every frame is decoded
+    Evenly(usize),
+}
+
+impl FrameSampling {
+    /// Number of frame hashes combined into one hash
+    pub fn frame_count(&self) -> usize {
+        match self {
+            FrameSampling::First => 1,
+            FrameSampling::Leading(n) | FrameSampling::Evenly(n) => *n,
+        }
+    }
+
+    /// Indices of the hashed frames out of `total` decoded frames
+    fn indices(&self, total: usize) -> Vec<usize> {
+        let n = self.frame_count();
+        match self {
+            FrameSampling::First => vec![0],
+            FrameSampling::Leading(_) => (0..n).map(|i| i.min(total - 1)).collect(),
+            FrameSampling::Evenly(_) => (0..n).map(|i| i * total / n).collect(),
+        }
+    }
+}
+
+impl FromStr for FrameSampling {
+    type Err = String;
+
+    /// Parse a sampling as used by the Python bindings.
+    ///
+    /// Accepts `"first"`, `"leading:<n>"` and `"evenly:<n>"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        if name == "first" {
+            return Ok(FrameSampling::First);
+        }
+        let (kind, n) = name
+            .split_once(':')
+            .ok_or_else(|| format!("Unknown frame sampling: {}", s))?;
+        let n = n
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("Invalid frame count: {}", n))?;
+        match kind {
+            "leading" => Ok(FrameSampling::Leading(n)),
+            "evenly" => Ok(FrameSampling::Evenly(n)),
+            _ => Err(format!("Unknown frame sampling: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for FrameSampling {
+    /// Canonical name, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameSampling::First => write!(f, "first"),
+            FrameSampling::Leading(n) => write!(f, "leading:{}", n),
+            FrameSampling::Evenly(n) => write!(f, "evenly:{}", n),
+        }
+    }
+}
+
+/// A hash function for the duplicate finders
+///
+/// Implemented by any `Fn(&DynamicImage) -> Result<ImageHash, HashError>`,
+/// so experimental hashes can reuse the grouping of `find_duplicates_using`;
+/// `HashAlgorithm::hasher` wraps a built-in algorithm. Images are passed
+/// decoded and upright. All hashes of one run must have the same length.
+pub trait Hasher: Sync {
+    /// Hash one image
+    fn hash_image(&self, img: &DynamicImage) -> Result<ImageHash, HashError>;
+
+    /// Tag this function's hashes are stored under in a `HashCache`;
+    /// `None`, the default, never caches them
+    fn cache_tag(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<F> Hasher for F
+where
+    F: Fn(&DynamicImage) -> Result<ImageHash, HashError> + Sync,
+{
+    fn hash_image(&self, img: &DynamicImage) -> Result<ImageHash, HashError> {
+        self(img)
+    }
+}
+
+/// Rotations and mirror images compared when matching rotation-invariantly
+pub const DIHEDRAL_TRANSFORMS: usize = 8;
+
 /// Represents a perceptual hash of an image
+///
+/// Computed with `from_path`, `from_image`, `from_bytes` or
+/// `from_raw_pixels`, or parsed from `to_hex` output with `from_hex`.
+/// `distance` is the Hamming distance between two hashes; values up to
+/// about 10 bits of a 64-bit hash mean the same picture.
+///
+/// Hashes computed from an image know their algorithm; `to_tagged` keeps
+/// it in the serialized form and `checked_distance` refuses to compare
+/// hashes of different algorithms or sizes.
 #[derive(Debug, Clone)]
 pub struct ImageHash {
-    /// The hash bits stored as bytes
-    bits: Vec<u8>,
+    /// The hash bits packed into 64-bit words, first byte in the most
+    /// significant position; bits past `len` bytes are zero
+    words: Vec<u64>,
+    /// Length of the hash in bytes
+    len: usize,
     /// Original hash size (width/height of comparison grid)
-    #[allow(dead_code)]
     size: usize,
+    /// Algorithm that produced the hash, if known
+    algorithm: Option<HashAlgorithm>,
+}
+
+impl PartialEq for ImageHash {
+    /// Same bits and hash size; the algorithm is not compared, so a hash
+    /// equals its own `to_hex` parsed back
+    fn eq(&self, other: &Self) -> bool {
+        (self.len, self.size, &self.words) == (other.len, other.size, &other.words)
+    }
+}
+
+impl Eq for ImageHash {}
+
+impl std::hash::Hash for ImageHash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.len, self.size, &self.words).hash(state);
+    }
 }
 
 impl ImageHash {
@@ -29,142 +457,950 @@ impl ImageHash {
         path: P,
         algorithm: HashAlgorithm,
         hash_size: usize,
-    ) -> Result<Self, String> {
-        let img = image::open(path.as_ref()).map_err(|e| format!("Failed to open image: {}", e))?;
+    ) -> Result<Self, HashError> {
+        let img = crate::decode::open_image(path.as_ref())?;
+
+        Self::from_image(&img, algorithm, hash_size)
+    }
+
+    /// `from_path`, computing the bits as `order` says (see
+    /// `from_image_ordered`)
+    pub fn from_path_ordered<P: AsRef<Path>>(
+        path: P,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        order: BitOrder,
+    ) -> Result<Self, HashError> {
+        let img = crate::decode::open_image(path.as_ref())?;
+
+        Self::from_image_ordered(&img, algorithm, hash_size, order)
+    }
+
+    /// `from_path`, preprocessing the image with `options` before hashing
+    pub fn from_path_with<P: AsRef<Path>>(
+        path: P,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        options: &HashOptions,
+    ) -> Result<Self, HashError> {
+        let img = options.open(path)?;
+
+        Self::from_image_with(&img, algorithm, hash_size, options)
+    }
+
+    /// Compute hash from the sampled frames of an animated image file
+    ///
+    /// The frame hashes are concatenated in frame order, so animations that
+    /// share a poster frame but differ later are told apart. The hash has
+    /// `sampling.frame_count()` times the bits of a still-image hash, and
+    /// distances grow accordingly; scale thresholds by the frame count.
+    pub fn from_path_frames<P: AsRef<Path>>(
+        path: P,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        sampling: FrameSampling,
+    ) -> Result<Self, HashError> {
+        if sampling == FrameSampling::First {
+            return Self::from_path(path, algorithm, hash_size);
+        }
+        let limit = match sampling {
+            FrameSampling::Leading(n) => Some(n),
+            _ => None,
+        };
+
+        // Hash while decoding so only one frame is in memory at a time
+        let mut frames: Vec<ImageHash> = Vec::new();
+        crate::decode::for_each_frame(path, limit, |frame| {
+            frames.push(Self::from_image(&frame, algorithm, hash_size)?);
+            Ok(())
+        })?;
+
+        let bytes: Vec<u8> = sampling
+            .indices(frames.len())
+            .into_iter()
+            .flat_map(|i| frames[i].bytes())
+            .collect();
+        Ok(Self::pack(&bytes, hash_size))
+    }
+
+    /// Compute hash from a loaded image
+    pub fn from_image(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<Self, HashError> {
+        Self::from_image_config(img, algorithm, hash_size, &PHashConfig::default())
+    }
+
+    /// `from_image`, reducing the image for pHash and colorHash as `config`
+    /// says
+    ///
+    /// Other algorithms ignore `config`. Fails if `hash_size` exceeds
+    /// `config.dct_size` (see `PHashConfig::check_hash_size`).
+    pub fn from_image_config(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        config: &PHashConfig,
+    ) -> Result<Self, HashError> {
+        config.check_hash_size(algorithm, hash_size)?;
+        let hash = match algorithm {
+            HashAlgorithm::PHash => Self::compute_phash(img, hash_size, config),
+            HashAlgorithm::WHash { level } => Self::compute_whash(img, hash_size, level)?,
+            HashAlgorithm::ColorHash => Self::compute_colorhash(img, hash_size, config),
+            HashAlgorithm::BlockHash => Self::compute_blockhash(img, hash_size),
+            HashAlgorithm::MarrHildreth => Self::pack(&libphash::marr_hildreth(img), hash_size),
+            HashAlgorithm::RadialVariance => {
+                Self::pack(&libphash::radial_variance(img, hash_size), hash_size)
+            }
+        };
+        Ok(hash.with_algorithm(algorithm))
+    }
+
+    /// `from_image`, computing the bits as `order` says
+    ///
+    /// With `BitOrder::ImageHash` the hash matches `imagehash.phash` or
+    /// `imagehash.whash` of the same image (other algorithms fail), up to
+    /// the odd bit where Lanczos resampling rounds differently from
+    /// Pillow's.
+    pub fn from_image_ordered(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        order: BitOrder,
+    ) -> Result<Self, HashError> {
+        if order == BitOrder::Native {
+            return Self::from_image(img, algorithm, hash_size);
+        }
+        algorithm.check_hash_size(hash_size)?;
+        match algorithm {
+            HashAlgorithm::PHash => {
+                let bits = interop::imagehash_phash(img, hash_size);
+                Ok(Self::pack(&Self::bools_to_bytes(&bits), hash_size).with_algorithm(algorithm))
+            }
+            HashAlgorithm::WHash { .. } => {
+                Self::from_image(&interop::pillow_luma(img), algorithm, hash_size)
+            }
+            _ => Err(HashError::InvalidInput(format!(
+                "The imagehash bit order is only available for phash and whash, not {}",
+                algorithm
+            ))),
+        }
+    }
+
+    /// `from_image`, preprocessing the image with `options` before hashing
+    ///
+    /// Fails if `options` are out of range (see `HashOptions::validate`).
+    pub fn from_image_with(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        options: &HashOptions,
+    ) -> Result<Self, HashError> {
+        options.validate()?;
+        if options.is_identity() {
+            return Self::from_image(img, algorithm, hash_size);
+        }
+        Self::from_image_config(&options.apply(img), algorithm, hash_size, &options.phash)
+    }
+
+    /// Whether `hierarchical_from_image` accepts `hash_size` and `options`
+    pub fn check_hierarchical(hash_size: usize, options: &HashOptions) -> Result<(), HashError> {
+        options.validate()?;
+        options
+            .phash
+            .check_hash_size(HashAlgorithm::PHash, hash_size)?;
+        if hash_size < COARSE_HASH_SIZE {
+            return Err(HashError::InvalidInput(format!(
+                "Hierarchical hashes need a hash size of at least {}, got {}",
+                COARSE_HASH_SIZE, hash_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// Coarse and fine pHash of `img` from one DCT
+    ///
+    /// The image is preprocessed with `options` and transformed once; the
+    /// coarse hash (`COARSE_HASH_SIZE`, 16 bits) and the fine one
+    /// (`hash_size`, e.g. 16 for 256 bits) are both read off the same
+    /// coefficients, each with its own median. Fails if `hash_size` is
+    /// below `COARSE_HASH_SIZE` or above `options.phash.dct_size`.
+    pub fn hierarchical_from_image(
+        img: &DynamicImage,
+        hash_size: usize,
+        options: &HashOptions,
+    ) -> Result<(Self, Self), HashError> {
+        Self::check_hierarchical(hash_size, options)?;
+        let dct = if options.is_identity() {
+            Self::dct_coefficients_with(img, &options.phash)
+        } else {
+            Self::dct_coefficients_with(&options.apply(img), &options.phash)
+        };
+        let dct_size = options.phash.dct_size;
+        Ok((
+            Self::phash_from_dct(&dct, dct_size, COARSE_HASH_SIZE)
+                .with_algorithm(HashAlgorithm::PHash),
+            Self::phash_from_dct(&dct, dct_size, hash_size).with_algorithm(HashAlgorithm::PHash),
+        ))
+    }
+
+    /// Hashes of all rotations and mirror images of `img`, concatenated
+    ///
+    /// Holds `DIHEDRAL_TRANSFORMS` hashes: the image rotated by 0, 90, 180
+    /// and 270 degrees clockwise, then the same for its mirror image. The
+    /// transforms are applied to the image because pHash bits don't map
+    /// onto each other under rotation. Split with `dihedral_variants`.
+    pub fn dihedral_from_image(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<Self, HashError> {
+        Self::dihedral_from_image_with(img, algorithm, hash_size, &HashOptions::default())
+    }
+
+    /// `dihedral_from_image`, preprocessing the image with `options` first
+    pub fn dihedral_from_image_with(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        options: &HashOptions,
+    ) -> Result<Self, HashError> {
+        options.validate()?;
+        let img = options.apply(img);
+        let mirrored = img.fliph();
+        let hash =
+            |img: &DynamicImage| Self::from_image_config(img, algorithm, hash_size, &options.phash);
+        let mut bytes = Vec::new();
+        for base in [&img, &mirrored] {
+            bytes.extend(hash(base)?.bytes());
+            for rotated in [base.rotate90(), base.rotate180(), base.rotate270()] {
+                bytes.extend(hash(&rotated)?.bytes());
+            }
+        }
+        Ok(Self::pack(&bytes, hash_size))
+    }
+
+    /// Split a hash from `dihedral_from_image` into its transforms
+    ///
+    /// The first variant is the plain hash of the untransformed image.
+    pub fn dihedral_variants(&self) -> Vec<ImageHash> {
+        let len = (self.len / DIHEDRAL_TRANSFORMS).max(1);
+        let bytes: Vec<u8> = self.bytes().collect();
+        bytes
+            .chunks(len)
+            .map(|bytes| Self::pack(bytes, self.size))
+            .collect()
+    }
+
+    /// Smallest distance between any transform of this image and `other`
+    ///
+    /// Both hashes come from `dihedral_from_image`; the transforms of this
+    /// hash are compared with the untransformed variant of `other`.
+    pub fn dihedral_distance(&self, other: &ImageHash) -> u32 {
+        let plain = other.dihedral_variants().swap_remove(0);
+        self.dihedral_variants()
+            .iter()
+            .map(|variant| variant.distance(&plain))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Compute hash from an encoded image held in memory
+    ///
+    /// The format is detected from the data, so any format the `image`
+    /// crate can decode is accepted.
+    pub fn from_bytes(
+        data: &[u8],
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<Self, HashError> {
+        let img = image::load_from_memory(data).map_err(|e| HashError::Decode(e.to_string()))?;
+
+        Self::from_image(&img, algorithm, hash_size)
+    }
+
+    /// Compute hash from raw 8-bit pixels in row-major order
+    ///
+    /// `channels` is 1 (grayscale), 3 (RGB) or 4 (RGBA).
+    pub fn from_raw_pixels(
+        width: u32,
+        height: u32,
+        channels: u8,
+        pixels: &[u8],
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<Self, HashError> {
+        let img = image_from_raw(width, height, channels, pixels)?;
+        Self::from_image(&img, algorithm, hash_size)
+    }
+
+    /// Parse hash from hex string
+    pub fn from_hex(hex: &str) -> Result<Self, HashError> {
+        let bits = decode_hex(hex.trim()).map_err(HashError::InvalidInput)?;
+        let size = (bits.len() * 8).isqrt();
+
+        Ok(Self::pack(&bits, size))
+    }
+
+    /// Parse hex written in `order`
+    ///
+    /// `BitOrder::ImageHash` reads the hex of `str(imagehash_hash)`; the
+    /// hash size is derived from the digit count, as imagehash does.
+    pub fn from_hex_ordered(hex: &str, order: BitOrder) -> Result<Self, HashError> {
+        match order {
+            BitOrder::Native => Self::from_hex(hex),
+            BitOrder::ImageHash => {
+                let (bits, size) = interop::imagehash_bits(hex)?;
+                Ok(Self::pack(&Self::bools_to_bytes(&bits), size))
+            }
+        }
+    }
+
+    /// Parse hash from the bytes returned by `to_bytes`
+    ///
+    /// Without `hash_size` the size is derived from the bit count, as for
+    /// `from_hex`. An explicit `hash_size` must match the byte count: one
+    /// `hash_size` x `hash_size` bit plane, padded to a whole byte, or
+    /// `hash_size` bytes for radial variance. Hashes of other shapes keep
+    /// their size through `to_tagged` and `from_tagged`.
+    pub fn from_hash_bytes(bytes: &[u8], hash_size: Option<usize>) -> Result<Self, HashError> {
+        if bytes.is_empty() {
+            return Err(HashError::InvalidInput("Empty hash".to_string()));
+        }
+        let size = match hash_size {
+            Some(size) => {
+                let plane = HashAlgorithm::PHash.hash_bytes(size);
+                if bytes.len() != plane && bytes.len() != size {
+                    return Err(HashError::InvalidInput(format!(
+                        "A hash of size {} has {} bytes, got {}",
+                        size,
+                        plane,
+                        bytes.len()
+                    )));
+                }
+                size
+            }
+            None => (bytes.len() * 8).isqrt(),
+        };
+        Ok(Self::pack(bytes, size))
+    }
+
+    /// Parse hash from the text returned by `to_base64`
+    pub fn from_base64(text: &str, hash_size: Option<usize>) -> Result<Self, HashError> {
+        let bytes = base64::decode(text).map_err(HashError::InvalidInput)?;
+        Self::from_hash_bytes(&bytes, hash_size)
+    }
+
+    /// 64-bit hash (hash size 8) from the integer returned by `to_u64`
+    pub fn from_u64(value: u64) -> Self {
+        Self {
+            words: vec![value],
+            len: 8,
+            size: DEFAULT_HASH_SIZE,
+            algorithm: None,
+        }
+    }
+
+    /// Parse a hash written by `to_tagged`
+    ///
+    /// Fails if the bit count doesn't match the algorithm and hash size.
+    pub fn from_tagged(text: &str) -> Result<Self, HashError> {
+        let invalid = || {
+            HashError::InvalidInput(format!(
+                "Expected <algorithm>:<hash size>:<hex>, got {}",
+                text
+            ))
+        };
+        // Split from the right: "whash:<level>" contains a colon itself
+        let mut parts = text.trim().rsplitn(3, ':');
+        let (Some(hex), Some(size), Some(algorithm)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let algorithm: HashAlgorithm = algorithm.parse().map_err(HashError::InvalidInput)?;
+        let size: usize = size.parse().map_err(|_| invalid())?;
+        algorithm.check_hash_size(size)?;
+
+        let mut hash = Self::from_hex(hex)?;
+        if hash.len != algorithm.hash_bytes(size) {
+            return Err(HashError::InvalidInput(format!(
+                "A {} hash of size {} has {} bits, got {}",
+                algorithm,
+                size,
+                8 * algorithm.hash_bytes(size),
+                hash.bit_len()
+            )));
+        }
+        hash.size = size;
+        Ok(hash.with_algorithm(algorithm))
+    }
+
+    /// Hash from individual bits, first bit most significant, as returned
+    /// by `to_bits`
+    ///
+    /// For hashes made outside this crate. The bits are padded with zeros
+    /// to a whole byte; the hash size is derived from the bit count, as for
+    /// `from_hex`.
+    pub fn from_bits(bits: &[bool]) -> Result<Self, HashError> {
+        if bits.is_empty() {
+            return Err(HashError::InvalidInput("Empty hash".to_string()));
+        }
+        let bytes: Vec<u8> = bits
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |byte, (i, &bit)| byte | (u8::from(bit) << (7 - i)))
+            })
+            .collect();
+        Ok(Self::pack(&bytes, bits.len().isqrt()))
+    }
+
+    /// Wrap raw hash bytes, e.g. for synthetic corpora
+    pub(crate) fn from_raw(bits: Vec<u8>, size: usize) -> Self {
+        Self::pack(&bits, size)
+    }
+
+    /// Pack hash bytes into words
+    fn pack(bytes: &[u8], size: usize) -> Self {
+        let words = bytes
+            .chunks(8)
+            .map(|chunk| {
+                let mut word = [0u8; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u64::from_be_bytes(word)
+            })
+            .collect();
+        Self {
+            words,
+            len: bytes.len(),
+            size,
+            algorithm: None,
+        }
+    }
+
+    /// The hash bytes, first byte first
+    fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(|i| (self.words[i / 8] >> (56 - 8 * (i % 8))) as u8)
+    }
+
+    /// The hash bits as packed words, for batch comparisons
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// Convert hash to hex string
+    pub fn to_hex(&self) -> String {
+        self.bytes().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Hex string in `order`
+    ///
+    /// `BitOrder::ImageHash` writes what `str()` of the same hash in
+    /// imagehash gives: the hash size squared bits as one number, so sizes
+    /// whose bit count isn't a multiple of 8 differ from `to_hex`.
+    pub fn to_hex_ordered(&self, order: BitOrder) -> String {
+        match order {
+            BitOrder::Native => self.to_hex(),
+            BitOrder::ImageHash => {
+                let mut bits = self.to_bits();
+                if self.len == (self.size * self.size).div_ceil(8) {
+                    bits.truncate(self.size * self.size);
+                }
+                interop::imagehash_hex(&bits)
+            }
+        }
+    }
+
+    /// The hash bytes, in the order of `to_hex`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes().collect()
+    }
+
+    /// The hash bytes as standard base64
+    pub fn to_base64(&self) -> String {
+        base64::encode(&self.to_bytes())
+    }
+
+    /// The hash as an integer, first bit most significant
+    ///
+    /// Returns `None` unless the hash has exactly 64 bits.
+    pub fn to_u64(&self) -> Option<u64> {
+        (self.len == 8).then(|| self.words[0])
+    }
+
+    /// The hash split into `bands` contiguous bit ranges, each as an
+    /// integer (first bit most significant), as `MultiIndex` buckets them
+    ///
+    /// Stored as indexed integer columns, the keys let a database narrow a
+    /// similarity search to rows sharing a band value with one of
+    /// `index_probes` before comparing full hashes. Bands differ in length
+    /// by at most one bit. Fails unless every band holds 1 to 64 bits.
+    pub fn index_keys(&self, bands: usize) -> Result<Vec<u64>, HashError> {
+        self.check_bands(bands)?;
+        Ok((0..bands)
+            .map(|b| {
+                let (start, len) = band_range(self.bit_len(), bands, b);
+                self.bit_range(start, len)
+            })
+            .collect())
+    }
+
+    /// Key values to look up per band so that every hash within
+    /// `threshold` of this one matches at least one of them
+    ///
+    /// Two hashes within `threshold` differ in few bits on at least one
+    /// band, so entry `b` lists the `index_keys(bands)[b]` values that
+    /// close to this hash's band `b`; bands that need no lookup get an
+    /// empty list. Matching rows are candidates only; check them with
+    /// `distance`. The lists grow quickly once `threshold` exceeds `bands`,
+    /// so use about one band per bit of threshold; fails if they would
+    /// hold more than `MAX_INDEX_PROBES` keys in total.
+    pub fn index_probes(&self, bands: usize, threshold: u32) -> Result<Vec<Vec<u64>>, HashError> {
+        let keys = self.index_keys(bands)?;
+        let bit_len = self.bit_len();
+        let count = probe_count(bit_len, bands, threshold);
+        if count > MAX_INDEX_PROBES {
+            // One-bit bands probe at most two keys each, so some count fits
+            let min = (1..=bit_len)
+                .filter(|&b| bit_len.div_ceil(b) <= 64)
+                .find(|&b| probe_count(bit_len, b, threshold) <= MAX_INDEX_PROBES)
+                .unwrap_or(bit_len);
+            return Err(HashError::InvalidInput(format!(
+                "Threshold {} over {} bands needs {} probes, more than {}; use at least {} bands",
+                threshold, bands, count, MAX_INDEX_PROBES, min
+            )));
+        }
+        Ok(keys
+            .into_iter()
+            .zip(band_radii(bands, threshold))
+            .enumerate()
+            .map(|(b, (key, radius))| {
+                let mut probes = Vec::new();
+                if let Some(radius) = radius {
+                    let (_, len) = band_range(self.bit_len(), bands, b);
+                    for_each_within(key, len, radius, &mut |probe| probes.push(probe));
+                }
+                probes
+            })
+            .collect())
+    }
+
+    fn check_bands(&self, bands: usize) -> Result<(), HashError> {
+        let bit_len = self.bit_len();
+        if bands == 0 || bands > bit_len || bit_len.div_ceil(bands) > 64 {
+            return Err(HashError::InvalidInput(format!(
+                "A {}-bit hash cannot be split into {} bands of 1 to 64 bits",
+                bit_len, bands
+            )));
+        }
+        Ok(())
+    }
+
+    /// Number of bits in the hash
+    pub fn bit_len(&self) -> usize {
+        self.len * 8
+    }
+
+    /// Hash size (width/height of the comparison grid)
+    ///
+    /// For hashes parsed with `from_hex` this is derived from the bit count.
+    pub fn hash_size(&self) -> usize {
+        self.size
+    }
+
+    /// Algorithm that produced the hash
+    ///
+    /// Known for hashes computed from an image or parsed with
+    /// `from_tagged`; `None` for bare hex, bytes and integers.
+    pub fn algorithm(&self) -> Option<HashAlgorithm> {
+        self.algorithm
+    }
+
+    /// Record the algorithm of a hash parsed from an untagged form
+    pub fn with_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    /// Self-describing text form `<algorithm>:<hash size>:<hex>`, e.g.
+    /// `phash:8:d1c4...`, parsed back by `from_tagged`
+    ///
+    /// Returns `None` if the algorithm is unknown (see `algorithm`).
+    pub fn to_tagged(&self) -> Option<String> {
+        self.algorithm
+            .map(|algorithm| format!("{}:{}:{}", algorithm, self.size, self.to_hex()))
+    }
+
+    /// Individual bits, most significant bit of the first byte first
+    pub fn to_bits(&self) -> Vec<bool> {
+        self.bytes()
+            .flat_map(|byte| (0..8).map(move |i| byte & (0x80 >> i) != 0))
+            .collect()
+    }
+
+    /// `len` (at most 64) bits starting at bit `start`, in the order of
+    /// `to_bits`, as an integer; bits past the end read as zero
+    pub(crate) fn bit_range(&self, start: usize, len: usize) -> u64 {
+        if len == 0 {
+            return 0;
+        }
+        let word = |w: usize| self.words.get(w).copied().unwrap_or(0);
+        let (w, offset) = (start / 64, start % 64);
+        let mut bits = word(w) << offset;
+        if offset > 0 {
+            bits |= word(w + 1) >> (64 - offset);
+        }
+        bits >> (64 - len)
+    }
+
+    /// Whether all bits are equal, as for a flat image
+    pub fn is_uniform(&self) -> bool {
+        self.bytes().all(|b| b == 0) || self.bytes().all(|b| b == 0xff)
+    }
+
+    /// `distance`, refusing hashes that were not made the same way
+    ///
+    /// Fails if the hashes differ in length or hash size, or if both know
+    /// their algorithm and the algorithms differ. A hash of unknown
+    /// algorithm is assumed to match.
+    pub fn checked_distance(&self, other: &ImageHash) -> Result<u32, HashError> {
+        if self.len != other.len || self.size != other.size {
+            return Err(HashError::InvalidInput(format!(
+                "Cannot compare a {}-bit hash of size {} with a {}-bit hash of size {}",
+                self.bit_len(),
+                self.size,
+                other.bit_len(),
+                other.size
+            )));
+        }
+        if let (Some(a), Some(b)) = (self.algorithm, other.algorithm) {
+            if a != b {
+                return Err(HashError::InvalidInput(format!(
+                    "Cannot compare a {} hash with a {} hash",
+                    a, b
+                )));
+            }
+        }
+        Ok(self.distance(other))
+    }
+
+    /// Peak cross-correlation of two radial variance hashes, libphash's
+    /// `ph_crosscorr`
+    ///
+    /// From 0.0 to 1.0 for identical hashes; libphash calls hashes above
+    /// `DEFAULT_RADIAL_THRESHOLD` a match. Fails if the hashes differ in
+    /// length or either is known to come from another algorithm.
+    pub fn cross_correlation(&self, other: &ImageHash) -> Result<f64, HashError> {
+        for algorithm in [self.algorithm, other.algorithm].into_iter().flatten() {
+            if algorithm != HashAlgorithm::RadialVariance {
+                return Err(HashError::InvalidInput(format!(
+                    "Cross-correlation compares radialvariance hashes, got a {} hash",
+                    algorithm
+                )));
+            }
+        }
+        if self.len != other.len || self.len == 0 {
+            return Err(HashError::InvalidInput(format!(
+                "Cannot correlate a {}-byte hash with a {}-byte hash",
+                self.len, other.len
+            )));
+        }
+        Ok(libphash::peak_cross_correlation(
+            &self.to_bytes(),
+            &other.to_bytes(),
+        ))
+    }
+
+    /// Compute Hamming distance to another hash of the same length
+    ///
+    /// Comparing hashes of different lengths is a bug, caught in debug
+    /// builds; use `checked_distance` for hashes from outside, or
+    /// `prefix_distance` to compare over the shorter one on purpose.
+    pub fn distance(&self, other: &ImageHash) -> u32 {
+        debug_assert_eq!(
+            self.len, other.len,
+            "distance between hashes of different lengths"
+        );
+        self.prefix_distance(other)
+    }
+
+    /// Hamming distance over the bits both hashes have: hashes of
+    /// different lengths are compared over the shorter one
+    pub fn prefix_distance(&self, other: &ImageHash) -> u32 {
+        let len = self.len.min(other.len);
+        let full = len / 8;
+        let mut distance = hamming(&self.words[..full], &other.words[..full]);
+        if !len.is_multiple_of(8) {
+            let mask = !0u64 << (64 - 8 * (len % 8));
+            distance += ((self.words[full] ^ other.words[full]) & mask).count_ones();
+        }
+        distance
+    }
+
+    /// Hamming distance with each differing bit counted by its weight
+    ///
+    /// Hashes of different lengths are compared over the shorter one, as
+    /// for `prefix_distance`.
+    pub fn weighted_distance(&self, other: &ImageHash, weights: &BitWeights) -> f64 {
+        let bits = self.len.min(other.len) * 8;
+        let mut total = 0.0;
+        for (w, (a, b)) in self.words.iter().zip(&other.words).enumerate() {
+            let mut diff = a ^ b;
+            while diff != 0 {
+                let lead = diff.leading_zeros() as usize;
+                let bit = w * 64 + lead;
+                if bit >= bits {
+                    break;
+                }
+                total += weights.weight(bit);
+                diff &= !(1u64 << (63 - lead));
+            }
+        }
+        total
+    }
+
+    /// Distance to each of `others`, in order
+    ///
+    /// Faster than calling `distance` per hash when there are many: the
+    /// hashes are compared in batches with SIMD where the CPU supports it.
+    pub fn distances_to_many(&self, others: &[ImageHash]) -> Vec<u32> {
+        let stride = self.words.len();
+        if stride == 0 || others.iter().any(|h| h.len != self.len) {
+            return others.iter().map(|h| self.prefix_distance(h)).collect();
+        }
+        let packed: Vec<u64> = others.iter().flat_map(|h| h.words()).copied().collect();
+        hamming_rows(&self.words, &packed)
+    }
+
+    /// Perceptual Hash (pHash)
+    ///
+    /// 1. Reduce to `dct_size` x `dct_size` grayscale (32x32 by default)
+    /// 2. Apply DCT (Discrete Cosine Transform)
+    /// 3. Keep top-left low frequencies
+    /// 4. Compare to median
+    fn compute_phash(img: &DynamicImage, hash_size: usize, config: &PHashConfig) -> Self {
+        let dct = Self::dct_coefficients_with(img, config);
+        Self::phash_from_dct(&dct, config.dct_size, hash_size)
+    }
+
+    /// pHash bits from the `dct_size` x `dct_size` coefficients of an image
+    pub(crate) fn phash_from_dct(dct: &[f64], dct_size: usize, hash_size: usize) -> Self {
+        // Top-left hash_size x hash_size coefficients, row by row. The DC
+        // term only reflects brightness: it is left out of the median and
+        // its bit is always 0.
+        let coeffs: Vec<f64> = (0..hash_size)
+            .flat_map(|y| dct[y * dct_size..y * dct_size + hash_size].iter().copied())
+            .skip(1)
+            .collect();
+
+        // Compute median
+        let mut sorted = coeffs.clone();
+        sorted.sort_by(f64::total_cmp);
+        let median = sorted[sorted.len() / 2];
 
-        Ok(Self::from_image(&img, algorithm, hash_size))
-    }
+        // Generate hash
+        let bits: Vec<bool> = std::iter::once(false)
+            .chain(coeffs.iter().map(|&v| v > median))
+            .collect();
 
-    /// Compute hash from a loaded image (uses pHash)
-    pub fn from_image(img: &DynamicImage, _algorithm: HashAlgorithm, hash_size: usize) -> Self {
-        Self::compute_phash(img, hash_size)
+        Self::pack(&Self::bools_to_bytes(&bits), hash_size)
     }
 
-    /// Parse hash from hex string
-    pub fn from_hex(hex: &str) -> Result<Self, String> {
-        let hex = hex.trim();
-        if !hex.len().is_multiple_of(2) {
-            return Err("Invalid hex string length".to_string());
-        }
+    /// Color Hash
+    ///
+    /// 1. Split into R, G and B planes
+    /// 2. pHash each plane
+    /// 3. Concatenate the bits (R, G, B)
+    fn compute_colorhash(img: &DynamicImage, hash_size: usize, config: &PHashConfig) -> Self {
+        let (width, height) = img.dimensions();
+        // 16-bit planes for deeper images, so they keep their precision
+        let planes: Vec<DynamicImage> = if is_high_depth(img) {
+            let rgb = img.to_rgb32f();
+            (0..3)
+                .map(|channel| {
+                    DynamicImage::ImageLuma16(image::ImageBuffer::from_fn(width, height, |x, y| {
+                        let v = finite_unit(rgb.get_pixel(x, y).0[channel]);
+                        image::Luma([(v * 65535.0).round() as u16])
+                    }))
+                })
+                .collect()
+        } else {
+            let rgb = img.to_rgb8();
+            (0..3)
+                .map(|channel| {
+                    DynamicImage::ImageLuma8(image::GrayImage::from_fn(width, height, |x, y| {
+                        image::Luma([rgb.get_pixel(x, y).0[channel]])
+                    }))
+                })
+                .collect()
+        };
 
-        let bits: Result<Vec<u8>, _> = (0..hex.len())
-            .step_by(2)
-            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        let bytes: Vec<u8> = planes
+            .iter()
+            .flat_map(|plane| {
+                let hash = Self::compute_phash(plane, hash_size, config);
+                hash.bytes().collect::<Vec<u8>>()
+            })
             .collect();
 
-        let bits = bits.map_err(|e| format!("Invalid hex: {}", e))?;
-        let size = (bits.len() * 8).isqrt();
-
-        Ok(Self { bits, size })
+        Self::pack(&bytes, hash_size)
     }
 
-    /// Convert hash to hex string
-    pub fn to_hex(&self) -> String {
-        self.bits.iter().map(|b| format!("{:02x}", b)).collect()
-    }
+    /// Block Mean Value Hash (bmvhash)
+    ///
+    /// 1. Reduce to a grayscale square of `BLOCKHASH_BLOCK_PIXELS` pixels
+    ///    per block side
+    /// 2. Average each of the `hash_size` x `hash_size` blocks
+    /// 3. Compare each block mean to the median, blocks row by row
+    fn compute_blockhash(img: &DynamicImage, hash_size: usize) -> Self {
+        let side = hash_size * BLOCKHASH_BLOCK_PIXELS;
+        let gray = luma_square(img, side as u32, FilterType::Triangle);
 
-    /// Compute Hamming distance to another hash
-    pub fn distance(&self, other: &ImageHash) -> u32 {
-        self.bits
-            .iter()
-            .zip(other.bits.iter())
-            .map(|(a, b)| (a ^ b).count_ones())
-            .sum()
+        let mut sums = vec![0.0f64; hash_size * hash_size];
+        for (i, value) in gray.into_iter().enumerate() {
+            let (x, y) = (i % side, i / side);
+            let block = (y / BLOCKHASH_BLOCK_PIXELS) * hash_size + x / BLOCKHASH_BLOCK_PIXELS;
+            sums[block] += value;
+        }
+
+        // Every block has the same pixel count, so sums order like means.
+        // The median of an even count is the mean of the middle two, so an
+        // image of two flat halves splits into zeros and ones.
+        let mut sorted = sums.clone();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        let twice_median = sorted[(n - 1) / 2] + sorted[n / 2];
+
+        let bits: Vec<bool> = sums.iter().map(|&s| 2.0 * s > twice_median).collect();
+        Self::pack(&Self::bools_to_bytes(&bits), hash_size)
     }
 
-    /// Perceptual Hash (pHash)
+    /// Wavelet Hash (wHash)
     ///
-    /// 1. Reduce to 32x32 grayscale
-    /// 2. Apply DCT (Discrete Cosine Transform)
-    /// 3. Keep top-left low frequencies
+    /// 1. Reduce to a power-of-two grayscale square (`hash_size << level`)
+    /// 2. Remove the lowest-frequency Haar band (the image mean)
+    /// 3. Apply `level` Haar decompositions, keeping the LL band
     /// 4. Compare to median
-    fn compute_phash(img: &DynamicImage, hash_size: usize) -> Self {
-        // Use larger size for DCT
-        let dct_size = 32;
-
-        let gray = img.grayscale();
-        let resized = gray.resize_exact(dct_size as u32, dct_size as u32, FilterType::Lanczos3);
+    fn compute_whash(
+        img: &DynamicImage,
+        hash_size: usize,
+        level: Option<u32>,
+    ) -> Result<Self, HashError> {
+        // Same default as imagehash: the largest power of two that fits the
+        // smaller image side, but never below the hash size.
+        let level = match level {
+            Some(level) => level,
+            None => {
+                let (w, h) = img.dimensions();
+                let min_side = w.min(h).max(1) as usize;
+                let natural = 1usize << min_side.ilog2();
+                natural.max(hash_size).ilog2() - hash_size.ilog2()
+            }
+        };
+        let image_scale = hash_size
+            .checked_shl(level)
+            .filter(|&s| s <= WHASH_MAX_IMAGE_SCALE)
+            .ok_or_else(|| {
+                HashError::InvalidInput(format!("wHash level {} is too large", level))
+            })?;
 
-        // Convert to f64 matrix
-        let pixels: Vec<f64> = resized.to_luma8().pixels().map(|p| p.0[0] as f64).collect();
+        let mut pixels: Vec<f64> = luma_square(img, image_scale as u32, FilterType::Lanczos3)
+            .into_iter()
+            .map(|v| v / 255.0)
+            .collect();
 
-        // Apply 2D DCT
-        let dct = Self::dct_2d(&pixels, dct_size);
+        // Zeroing the max-level Haar LL coefficient and reconstructing is
+        // equivalent to subtracting the mean.
+        let mean = pixels.iter().sum::<f64>() / pixels.len() as f64;
+        pixels.iter_mut().for_each(|p| *p -= mean);
 
-        // Extract top-left coefficients (excluding DC component)
-        let mut coeffs = Vec::with_capacity(hash_size * hash_size);
-        for y in 0..hash_size {
-            for x in 0..hash_size {
-                if x == 0 && y == 0 {
-                    continue; // Skip DC component
-                }
-                coeffs.push(dct[y * dct_size + x]);
-            }
+        let mut size = image_scale;
+        for _ in 0..level {
+            pixels = Self::haar_ll(&pixels, size);
+            size /= 2;
         }
 
-        // Compute median
-        let mut sorted = coeffs.clone();
-        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        let median = sorted[sorted.len() / 2];
+        let median = Self::median(&pixels);
+        let hash_bits: Vec<bool> = pixels.iter().map(|&v| v > median).collect();
 
-        // Generate hash
-        let hash_bits: Vec<bool> = dct[..hash_size * hash_size]
-            .iter()
-            .enumerate()
-            .filter(|(i, _)| *i != 0) // Skip DC
-            .map(|(_, &v)| v > median)
-            .collect();
+        Ok(Self::pack(&Self::bools_to_bytes(&hash_bits), hash_size))
+    }
 
-        // Pad to hash_size * hash_size bits
-        let mut padded = hash_bits;
-        padded.insert(0, false); // DC component placeholder
-        while padded.len() < hash_size * hash_size {
-            padded.push(false);
+    /// One level of the 2D Haar transform, returning only the LL band
+    fn haar_ll(pixels: &[f64], size: usize) -> Vec<f64> {
+        let half = size / 2;
+        let mut ll = vec![0.0; half * half];
+        for y in 0..half {
+            for x in 0..half {
+                let top = pixels[2 * y * size + 2 * x] + pixels[2 * y * size + 2 * x + 1];
+                let bottom =
+                    pixels[(2 * y + 1) * size + 2 * x] + pixels[(2 * y + 1) * size + 2 * x + 1];
+                ll[y * half + x] = (top + bottom) / 2.0;
+            }
         }
-        padded.truncate(hash_size * hash_size);
+        ll
+    }
 
-        let bits = Self::bools_to_bytes(&padded);
-        Self {
-            bits,
-            size: hash_size,
+    /// Median matching numpy (mean of the two middle values for even lengths)
+    fn median(values: &[f64]) -> f64 {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
         }
     }
 
-    /// 2D Discrete Cosine Transform
-    fn dct_2d(pixels: &[f64], size: usize) -> Vec<f64> {
-        // Precompute cosine table
-        let cos_table: Vec<f64> = (0..size)
-            .flat_map(|u| {
-                (0..size).map(move |x| {
-                    ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / (2.0 * size as f64))
-                        .cos()
-                })
-            })
-            .collect();
+    /// The DCT coefficients pHash thresholds, row-major
+    ///
+    /// `img` is reduced to a `DCT_SIZE` x `DCT_SIZE` grayscale image as for
+    /// pHash and transformed; the lowest frequencies are top-left. A pHash
+    /// of size n compares the top-left n x n block, minus the DC term at
+    /// index 0, with its median.
+    pub fn dct_coefficients(img: &DynamicImage) -> Vec<f64> {
+        Self::dct_coefficients_with(img, &PHashConfig::default())
+    }
+
+    /// `dct_coefficients` of the `config.dct_size` x `config.dct_size`
+    /// reduction `config` describes
+    pub fn dct_coefficients_with(img: &DynamicImage, config: &PHashConfig) -> Vec<f64> {
+        let pixels = luma_square(img, config.dct_size as u32, config.filter);
+        Self::dct_2d(&pixels, config.dct_size)
+    }
+
+    /// Orthonormal 2D DCT-II of a `size` x `size` image
+    ///
+    /// Separable: a fast DCT over every row, then every column, scaled to
+    /// the orthonormal definition.
+    pub(crate) fn dct_2d(pixels: &[f64], size: usize) -> Vec<f64> {
+        let plan = dct_plan(size);
+        let mut scratch = vec![0.0; plan.get_scratch_len()];
+        let scale = |k: usize| {
+            let ck = if k == 0 { 1.0 / 2.0_f64.sqrt() } else { 1.0 };
+            ck * (2.0 / size as f64).sqrt()
+        };
 
         // Apply 1D DCT to rows
-        let mut temp = vec![0.0; size * size];
-        for y in 0..size {
-            for u in 0..size {
-                let mut sum = 0.0;
-                for x in 0..size {
-                    sum += pixels[y * size + x] * cos_table[u * size + x];
-                }
-                let cu = if u == 0 { 1.0 / 2.0_f64.sqrt() } else { 1.0 };
-                temp[y * size + u] = sum * cu * (2.0 / size as f64).sqrt();
-            }
+        let mut result = pixels.to_vec();
+        for row in result.chunks_exact_mut(size) {
+            plan.process_dct2_with_scratch(row, &mut scratch);
+            row.iter_mut().enumerate().for_each(|(u, c)| *c *= scale(u));
         }
 
         // Apply 1D DCT to columns
-        let mut result = vec![0.0; size * size];
+        let mut column = vec![0.0; size];
         for x in 0..size {
-            for v in 0..size {
-                let mut sum = 0.0;
-                for y in 0..size {
-                    sum += temp[y * size + x] * cos_table[v * size + y];
-                }
-                let cv = if v == 0 { 1.0 / 2.0_f64.sqrt() } else { 1.0 };
-                result[v * size + x] = sum * cv * (2.0 / size as f64).sqrt();
+            for (y, c) in column.iter_mut().enumerate() {
+                *c = result[y * size + x];
+            }
+            plan.process_dct2_with_scratch(&mut column, &mut scratch);
+            for (v, c) in column.iter().enumerate() {
+                result[v * size + x] = c * scale(v);
             }
         }
 
@@ -191,6 +1427,348 @@ impl ImageHash {
     }
 }
 
+/// Whether `img` has more than 8 bits per channel (16-bit or float)
+fn is_high_depth(img: &DynamicImage) -> bool {
+    img.color().bytes_per_pixel() > img.color().channel_count()
+}
+
+/// `img` reduced to a `size` x `size` grayscale square, row by row, on the
+/// 0 to 255 scale
+///
+/// 8-bit images are reduced in 8 bits as before. Deeper images are
+/// converted to luma and resampled in floating point: cutting them to 256
+/// levels first merges the shades of dark or low-contrast 16-bit scans.
+/// Float pixels are clamped to 0 to 1, and NaN and infinite ones (as HDR,
+/// EXR and float TIFF files can hold) read as 0 so they cannot spread
+/// through the resampling or upset the median.
+pub(crate) fn luma_square(img: &DynamicImage, size: u32, filter: FilterType) -> Vec<f64> {
+    if !is_high_depth(img) {
+        let resized = img.grayscale().resize_exact(size, size, filter);
+        return resized.to_luma8().pixels().map(|p| p.0[0] as f64).collect();
+    }
+    let mut luma = img.to_luma32f();
+    for p in luma.pixels_mut() {
+        p.0[0] = finite_unit(p.0[0]);
+    }
+    imageops::resize(&luma, size, size, filter)
+        .pixels()
+        .map(|p| p.0[0] as f64 * 255.0)
+        .collect()
+}
+
+/// A float channel value clamped to 0 to 1, with NaN and infinities as 0
+fn finite_unit(v: f32) -> f32 {
+    if v.is_finite() {
+        v.clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Bytes of a hex string, two digits each
+///
+/// Works on bytes, so non-ASCII input is an invalid digit rather than a
+/// slice across a character boundary.
+pub(crate) fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("Invalid hex string length".to_string());
+    }
+    let digit = |c: u8| {
+        (c as char)
+            .to_digit(16)
+            .ok_or_else(|| format!("Invalid hex: {:?}", hex))
+    };
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
+
+/// Wrap raw 8-bit pixels in row-major order (1, 3 or 4 channels) as an image
+pub(crate) fn image_from_raw(
+    width: u32,
+    height: u32,
+    channels: u8,
+    pixels: &[u8],
+) -> Result<DynamicImage, HashError> {
+    let expected = width as usize * height as usize * channels as usize;
+    if pixels.len() != expected {
+        return Err(HashError::InvalidInput(format!(
+            "Expected {} bytes for {}x{}x{} pixels, got {}",
+            expected,
+            width,
+            height,
+            channels,
+            pixels.len()
+        )));
+    }
+
+    let raw = pixels.to_vec();
+    match channels {
+        1 => image::GrayImage::from_raw(width, height, raw).map(DynamicImage::ImageLuma8),
+        3 => image::RgbImage::from_raw(width, height, raw).map(DynamicImage::ImageRgb8),
+        4 => image::RgbaImage::from_raw(width, height, raw).map(DynamicImage::ImageRgba8),
+        _ => {
+            return Err(HashError::InvalidInput(format!(
+                "Unsupported channel count: {}",
+                channels
+            )))
+        }
+    }
+    .ok_or_else(|| HashError::InvalidInput("Pixel buffer does not match dimensions".into()))
+}
+
+/// Side length of the downscaled image used for segmentation
+pub const SEGMENTATION_IMAGE_SIZE: u32 = 300;
+/// Segments smaller than this (in segmentation pixels) are ignored
+pub const MIN_SEGMENT_SIZE: usize = 500;
+/// Luma level splitting bright and dark segments
+const SEGMENT_THRESHOLD: u8 = 128;
+/// Default fraction of differing bits for two segments to match
+pub const DEFAULT_BIT_ERROR_RATE: f64 = 0.25;
+
+/// Crop-resistant hash: one hash per bright/dark region of the image
+///
+/// Two images match if any of their segments match, so a crop that keeps
+/// at least one region intact still compares as similar.
+#[derive(Debug, Clone)]
+pub struct CropResistantHash {
+    /// Per-segment hashes, largest segment first
+    segments: Vec<ImageHash>,
+}
+
+impl CropResistantHash {
+    /// Compute crop-resistant hash from an image file path
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<Self, HashError> {
+        let img = crate::decode::open_image(path.as_ref())?;
+
+        Self::from_image(&img, algorithm, hash_size)
+    }
+
+    /// Compute crop-resistant hash from a loaded image
+    ///
+    /// 1. Reduce to a blurred 300x300 grayscale image
+    /// 2. Flood-fill connected bright and dark regions
+    /// 3. Hash the bounding box of each large region in the original image
+    pub fn from_image(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+    ) -> Result<Self, HashError> {
+        let (width, height) = img.dimensions();
+        let size = SEGMENTATION_IMAGE_SIZE;
+
+        let small = img
+            .grayscale()
+            .resize_exact(size, size, FilterType::Lanczos3)
+            .blur(2.0)
+            .to_luma8();
+        let pixels: Vec<u8> = small.pixels().map(|p| p.0[0]).collect();
+        let pixels = Self::median_filter_3x3(&pixels, size as usize);
+
+        let scale_x = width as f64 / size as f64;
+        let scale_y = height as f64 / size as f64;
+
+        let mut boxes = Self::find_segments(&pixels, size as usize);
+        if boxes.is_empty() {
+            boxes.push((usize::MAX, 0, 0, size as usize - 1, size as usize - 1));
+        }
+
+        let segments = boxes
+            .into_iter()
+            .map(|(_, min_x, min_y, max_x, max_y)| {
+                let x0 = (min_x as f64 * scale_x) as u32;
+                let y0 = (min_y as f64 * scale_y) as u32;
+                let x1 = (((max_x + 1) as f64 * scale_x) as u32).clamp(x0 + 1, width.max(1));
+                let y1 = (((max_y + 1) as f64 * scale_y) as u32).clamp(y0 + 1, height.max(1));
+                let crop = img.crop_imm(x0, y0, x1 - x0, y1 - y0);
+                ImageHash::from_image(&crop, algorithm, hash_size)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { segments })
+    }
+
+    /// Parse from comma-separated segment hex strings
+    pub fn from_hex(hex: &str) -> Result<Self, HashError> {
+        let segments = hex
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(ImageHash::from_hex)
+            .collect::<Result<Vec<_>, _>>()?;
+        if segments.is_empty() {
+            return Err(HashError::InvalidInput(
+                "Crop-resistant hash has no segments".to_string(),
+            ));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Convert to comma-separated segment hex strings
+    pub fn to_hex(&self) -> String {
+        self.segments
+            .iter()
+            .map(|h| h.to_hex())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Per-segment hashes, largest segment first
+    pub fn segments(&self) -> &[ImageHash] {
+        &self.segments
+    }
+
+    /// Count segments of `self` matching any segment of `other`
+    ///
+    /// A segment matches when its closest counterpart differs in at most
+    /// `bit_error_rate` of its bits. Returns the match count and the summed
+    /// Hamming distance of the matching segments.
+    pub fn hash_diff(&self, other: &CropResistantHash, bit_error_rate: f64) -> (usize, u32) {
+        let mut matches = 0;
+        let mut sum_distance = 0;
+        for segment in &self.segments {
+            let cutoff = (segment.bit_len() as f64 * bit_error_rate) as u32;
+            let closest = other
+                .segments
+                .iter()
+                .map(|o| segment.prefix_distance(o))
+                .min()
+                .unwrap_or(u32::MAX);
+            if closest <= cutoff {
+                matches += 1;
+                sum_distance += closest;
+            }
+        }
+        (matches, sum_distance)
+    }
+
+    /// Whether at least `region_cutoff` segments match
+    pub fn matches(
+        &self,
+        other: &CropResistantHash,
+        region_cutoff: usize,
+        bit_error_rate: f64,
+    ) -> bool {
+        self.hash_diff(other, bit_error_rate).0 >= region_cutoff.max(1)
+    }
+
+    /// `distance`, refusing hashes whose segments differ in length, e.g.
+    /// from another algorithm or hash size
+    pub fn checked_distance(&self, other: &CropResistantHash) -> Result<f64, HashError> {
+        self.check_segments(other)?;
+        Ok(self.distance(other))
+    }
+
+    /// Fail unless every segment of both hashes has the same length
+    pub fn check_segments(&self, other: &CropResistantHash) -> Result<(), HashError> {
+        let first = &self.segments[0];
+        for segment in self.segments.iter().chain(&other.segments) {
+            if segment.len != first.len {
+                return Err(HashError::InvalidInput(format!(
+                    "Cannot compare {}-bit segments with {}-bit segments",
+                    first.bit_len(),
+                    segment.bit_len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Dissimilarity score (0 = every segment matches exactly)
+    ///
+    /// Ranges up to the number of segments in `self`. Same scoring as
+    /// `imagehash.ImageMultiHash.__sub__`, so it is not symmetric.
+    pub fn distance(&self, other: &CropResistantHash) -> f64 {
+        let (matches, sum_distance) = self.hash_diff(other, DEFAULT_BIT_ERROR_RATE);
+        let max_difference = self.segments.len() as f64;
+        if matches == 0 {
+            return max_difference;
+        }
+
+        let bits = self.segments[0].bit_len() as f64;
+        let tie_breaker = -(sum_distance as f64 / (matches as f64 * bits));
+        max_difference - (matches as f64 + tie_breaker)
+    }
+
+    /// Flood-fill bright and dark regions of a thresholded image
+    ///
+    /// Returns `(pixel_count, min_x, min_y, max_x, max_y)` for each region of
+    /// at least `MIN_SEGMENT_SIZE` pixels, largest first.
+    fn find_segments(pixels: &[u8], size: usize) -> Vec<(usize, usize, usize, usize, usize)> {
+        let bright: Vec<bool> = pixels.iter().map(|&p| p > SEGMENT_THRESHOLD).collect();
+        let mut visited = vec![false; pixels.len()];
+        let mut segments = Vec::new();
+        let mut stack = Vec::new();
+
+        for start in 0..pixels.len() {
+            if visited[start] {
+                continue;
+            }
+            let class = bright[start];
+            visited[start] = true;
+            stack.push(start);
+
+            let (mut count, mut min_x, mut min_y, mut max_x, mut max_y) =
+                (0, usize::MAX, usize::MAX, 0, 0);
+            while let Some(i) = stack.pop() {
+                let (x, y) = (i % size, i / size);
+                count += 1;
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+
+                let neighbours = [
+                    (x > 0).then(|| i - 1),
+                    (x + 1 < size).then(|| i + 1),
+                    (y > 0).then(|| i - size),
+                    (y + 1 < size).then(|| i + size),
+                ];
+                for j in neighbours.into_iter().flatten() {
+                    if !visited[j] && bright[j] == class {
+                        visited[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+
+            if count >= MIN_SEGMENT_SIZE {
+                segments.push((count, min_x, min_y, max_x, max_y));
+            }
+        }
+
+        segments.sort_by_key(|s| std::cmp::Reverse(s.0));
+        segments
+    }
+
+    /// 3x3 median filter with edge clamping
+    fn median_filter_3x3(pixels: &[u8], size: usize) -> Vec<u8> {
+        let mut out = vec![0u8; pixels.len()];
+        let mut window = [0u8; 9];
+        for y in 0..size {
+            for x in 0..size {
+                let mut k = 0;
+                for dy in [-1isize, 0, 1] {
+                    for dx in [-1isize, 0, 1] {
+                        let nx = (x as isize + dx).clamp(0, size as isize - 1) as usize;
+                        let ny = (y as isize + dy).clamp(0, size as isize - 1) as usize;
+                        window[k] = pixels[ny * size + nx];
+                        k += 1;
+                    }
+                }
+                window.sort_unstable();
+                out[y * size + x] = window[4];
+            }
+        }
+        out
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -200,6 +1778,185 @@ mod tests {
         let hex = "abcdef0123456789";
         let hash = ImageHash::from_hex(hex).unwrap();
         assert_eq!(hash.to_hex(), hex);
+        for bad in ["abcdef012345678", "+bcdef0123456789", "abcdef0123456é7"] {
+            assert!(ImageHash::from_hex(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_bytes_base64_u64_roundtrip() {
+        let hash = ImageHash::from_hex("8000000000000001").unwrap();
+        assert_eq!(hash.to_u64(), Some(0x8000_0000_0000_0001));
+        assert_eq!(ImageHash::from_u64(0x8000_0000_0000_0001), hash);
+        assert_eq!(
+            ImageHash::from_hash_bytes(&hash.to_bytes(), None).unwrap(),
+            hash
+        );
+        assert_eq!(hash.to_base64(), "gAAAAAAAAAE=");
+        assert_eq!(ImageHash::from_base64("gAAAAAAAAAE=", None).unwrap(), hash);
+
+        let wide = ImageHash::from_hex(&"ab".repeat(24)).unwrap();
+        assert_eq!(wide.to_u64(), None);
+        let parsed = ImageHash::from_base64(&wide.to_base64(), None).unwrap();
+        assert_eq!(parsed.to_hex(), wide.to_hex());
+        // 192 bits are not a hash of size 8
+        assert!(ImageHash::from_base64(&wide.to_base64(), Some(8))
+            .unwrap_err()
+            .to_string()
+            .contains("has 8 bytes, got 24"));
+        let large = ImageHash::from_hash_bytes(&[0xab; 32], Some(16)).unwrap();
+        assert_eq!(large.hash_size(), 16);
+        assert!(ImageHash::from_hash_bytes(&[], None).is_err());
+    }
+
+    #[test]
+    fn test_index_probes_find_hashes_within_threshold() {
+        let hash = ImageHash::from_hex("f0f0000000000001").unwrap();
+        assert_eq!(
+            hash.index_keys(4).unwrap(),
+            vec![0xf0f0, 0x0000, 0x0000, 0x0001]
+        );
+        assert_eq!(hash.index_keys(1).unwrap(), vec![0xf0f0_0000_0000_0001]);
+        assert!(hash.index_keys(0).is_err());
+        assert!(hash.index_keys(65).is_err());
+        assert!(ImageHash::from_hex(&"00".repeat(32))
+            .unwrap()
+            .index_keys(3)
+            .is_err());
+
+        let mut rng = crate::rng::SeededRng::new(5);
+        for _ in 0..200 {
+            let bits = rng.next_u64();
+            let mut near = bits;
+            for _ in 0..rng.below(7) {
+                near ^= 1 << rng.below(64);
+            }
+            let (a, b) = (ImageHash::from_u64(bits), ImageHash::from_u64(near));
+            let probes = a.index_probes(4, 6).unwrap();
+            let keys = b.index_keys(4).unwrap();
+            assert!(probes.iter().zip(&keys).any(|(p, k)| p.contains(k)));
+        }
+
+        // One 64-bit band at threshold 12 would need billions of probes
+        let err = hash.index_probes(1, 12).unwrap_err().to_string();
+        assert!(err.contains("use at least 3 bands"), "{}", err);
+        assert!(hash.index_probes(2, 12).is_err());
+        let total: usize = hash.index_probes(3, 12).unwrap().iter().map(Vec::len).sum();
+        assert_eq!(total, 12233);
+    }
+
+    #[test]
+    fn test_tagged_roundtrip_and_checked_distance() {
+        let img = gradient_image(64, 48);
+        let phash = ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+        let tagged = phash.to_tagged().unwrap();
+        assert_eq!(tagged, format!("phash:8:{}", phash.to_hex()));
+        let parsed = ImageHash::from_tagged(&tagged).unwrap();
+        assert_eq!(parsed, phash);
+        assert_eq!(parsed.algorithm(), Some(HashAlgorithm::PHash));
+
+        let whash =
+            ImageHash::from_image(&img, HashAlgorithm::WHash { level: Some(2) }, 8).unwrap();
+        let tagged = whash.to_tagged().unwrap();
+        assert!(tagged.starts_with("whash:2:8:"));
+        assert_eq!(ImageHash::from_tagged(&tagged).unwrap(), whash);
+
+        let color = ImageHash::from_image(&img, HashAlgorithm::ColorHash, 5).unwrap();
+        assert_eq!(
+            ImageHash::from_tagged(&color.to_tagged().unwrap()).unwrap(),
+            color
+        );
+
+        // Bare hex has no algorithm; a wrong bit count or format is refused
+        assert_eq!(
+            ImageHash::from_hex(&phash.to_hex()).unwrap().to_tagged(),
+            None
+        );
+        assert!(ImageHash::from_tagged(&format!("colorhash:8:{}", phash.to_hex())).is_err());
+        assert!(ImageHash::from_tagged(&phash.to_hex()).is_err());
+        assert!(ImageHash::from_tagged("md5:8:00").is_err());
+
+        assert_eq!(phash.checked_distance(&parsed), Ok(0));
+        let untagged = ImageHash::from_hex(&whash.to_hex()).unwrap();
+        assert_eq!(
+            phash.checked_distance(&untagged),
+            Ok(phash.distance(&whash))
+        );
+        assert!(phash.checked_distance(&whash).is_err());
+        assert!(phash.checked_distance(&color).is_err());
+
+        // Untagged hashes of different sizes are refused too
+        let short = ImageHash::from_hex(&"0".repeat(16)).unwrap();
+        let long = ImageHash::from_hex(&"0".repeat(64)).unwrap();
+        assert!(short.checked_distance(&long).is_err());
+        assert_eq!(short.prefix_distance(&long), 0);
+    }
+
+    #[test]
+    fn test_to_bits() {
+        let hash = ImageHash::from_hex("a001").unwrap();
+        let bits = hash.to_bits();
+        assert_eq!(bits.len(), hash.bit_len());
+        assert_eq!(&bits[..4], &[true, false, true, false]);
+        assert!(bits[15]);
+        assert_eq!(bits.iter().filter(|&&b| b).count(), 3);
+        assert_eq!(ImageHash::from_bits(&bits).unwrap(), hash);
+        // Padded to a whole byte
+        assert_eq!(ImageHash::from_bits(&[true, true]).unwrap().to_hex(), "c0");
+        assert!(ImageHash::from_bits(&[]).is_err());
+    }
+
+    #[test]
+    fn test_frame_sampling() {
+        let evenly: FrameSampling = "evenly:4".parse().unwrap();
+        assert_eq!(evenly, FrameSampling::Evenly(4));
+        assert_eq!(evenly.to_string().parse::<FrameSampling>(), Ok(evenly));
+        assert!("leading:0".parse::<FrameSampling>().is_err());
+
+        assert_eq!(evenly.indices(20), vec![0, 5, 10, 15]);
+        // Short animations and stills repeat their last frame
+        assert_eq!(evenly.indices(1), vec![0, 0, 0, 0]);
+        assert_eq!(FrameSampling::Leading(3).indices(2), vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn test_dihedral_distance_finds_rotation() {
+        let img = gradient_image(64, 64);
+        let original = ImageHash::dihedral_from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+        let rotated =
+            ImageHash::dihedral_from_image(&img.rotate90().fliph(), HashAlgorithm::PHash, 8)
+                .unwrap();
+
+        let variants = original.dihedral_variants();
+        assert_eq!(variants.len(), DIHEDRAL_TRANSFORMS);
+        assert_eq!(
+            variants[0],
+            ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap()
+        );
+        // Resampling may round differently once the image is transposed
+        assert!(variants[0].distance(&rotated.dihedral_variants()[0]) > 8);
+        assert!(original.dihedral_distance(&rotated) <= 2);
+        assert!(rotated.dihedral_distance(&original) <= 2);
+    }
+
+    #[test]
+    fn test_fast_dct_matches_definition() {
+        let n = DCT_SIZE;
+        let pixels: Vec<f64> = (0..n * n).map(|i| ((i * 37) % 256) as f64).collect();
+        let dct = ImageHash::dct_2d(&pixels, n);
+
+        let basis = |k: usize, i: usize| {
+            let ck = if k == 0 { 1.0 / 2.0_f64.sqrt() } else { 1.0 };
+            let angle = (2 * i + 1) as f64 * k as f64 * std::f64::consts::PI / (2 * n) as f64;
+            ck * (2.0 / n as f64).sqrt() * angle.cos()
+        };
+        for (v, u) in [(0, 0), (0, 1), (1, 0), (3, 5), (7, 7), (31, 2)] {
+            let expected: f64 = (0..n)
+                .flat_map(|y| (0..n).map(move |x| (y, x)))
+                .map(|(y, x)| pixels[y * n + x] * basis(u, x) * basis(v, y))
+                .sum();
+            assert!((dct[v * n + u] - expected).abs() < 1e-6);
+        }
     }
 
     #[test]
@@ -210,5 +1967,333 @@ mod tests {
 
         let h3 = ImageHash::from_hex("ff00ff01").unwrap();
         assert_eq!(h1.distance(&h3), 1);
+
+        // Spans a word boundary and a partial last word
+        let long = ImageHash::from_hex("ff00ff00ff00ff00ff0f").unwrap();
+        assert_eq!(long.to_hex(), "ff00ff00ff00ff00ff0f");
+        assert_eq!(long.bit_range(60, 12), 0x0ff);
+        assert_eq!(long.prefix_distance(&h1), 0);
+        assert_eq!(
+            long.distances_to_many(&[long.clone(), ImageHash::from_hex(&"0".repeat(20)).unwrap()]),
+            vec![0, 44]
+        );
+    }
+
+    fn gradient_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(image::ImageBuffer::from_fn(width, height, |x, y| {
+            image::Luma([((x * 7 + y * 3) % 256) as u8])
+        }))
+    }
+
+    #[test]
+    fn test_from_raw_pixels_matches_image() {
+        let img = gradient_image(40, 30);
+        let raw = img.to_luma8().into_raw();
+        let expected = ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+        let hash = ImageHash::from_raw_pixels(40, 30, 1, &raw, HashAlgorithm::PHash, 8).unwrap();
+        assert_eq!(hash, expected);
+
+        assert!(ImageHash::from_raw_pixels(40, 30, 3, &raw, HashAlgorithm::PHash, 8).is_err());
+        assert!(ImageHash::from_raw_pixels(40, 15, 2, &raw, HashAlgorithm::PHash, 8).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_from_str() {
+        assert_eq!("phash".parse::<HashAlgorithm>(), Ok(HashAlgorithm::PHash));
+        assert_eq!(
+            "WHash".parse::<HashAlgorithm>(),
+            Ok(HashAlgorithm::WHash { level: None })
+        );
+        assert_eq!(
+            "whash:3".parse::<HashAlgorithm>(),
+            Ok(HashAlgorithm::WHash { level: Some(3) })
+        );
+        assert!("whash:x".parse::<HashAlgorithm>().is_err());
+        for name in ["phash", "whash", "whash:3", "colorhash", "blockhash"] {
+            assert_eq!(name.parse::<HashAlgorithm>().unwrap().to_string(), name);
+        }
+        assert!("nope".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_whash_size_and_level() {
+        let img = gradient_image(100, 80);
+        let auto = ImageHash::from_image(&img, HashAlgorithm::WHash { level: None }, 8).unwrap();
+        assert_eq!(auto.to_hex().len(), 16);
+
+        let fixed =
+            ImageHash::from_image(&img, HashAlgorithm::WHash { level: Some(2) }, 8).unwrap();
+        assert_eq!(fixed.to_hex().len(), 16);
+
+        assert!(ImageHash::from_image(&img, HashAlgorithm::WHash { level: None }, 6).is_err());
+    }
+
+    #[test]
+    fn test_blockhash_follows_brightness() {
+        // Dark left half, bright right half
+        let img = DynamicImage::ImageLuma8(image::ImageBuffer::from_fn(64, 48, |x, _| {
+            image::Luma([if x < 32 { 20 } else { 230 }])
+        }));
+        let hash = ImageHash::from_image(&img, HashAlgorithm::BlockHash, 8).unwrap();
+        assert_eq!(hash.to_hex(), "0f".repeat(8));
+
+        let wide = ImageHash::from_image(&img, HashAlgorithm::BlockHash, 16).unwrap();
+        assert_eq!(wide.bit_len(), 256);
+        assert!(HashAlgorithm::BlockHash.check_hash_size(1).is_err());
+    }
+
+    #[test]
+    fn test_phash_sizes() {
+        let img = gradient_image(100, 80);
+        for size in [4, 16, DCT_SIZE] {
+            let hash = ImageHash::from_image(&img, HashAlgorithm::PHash, size).unwrap();
+            assert_eq!(hash.bit_len(), size * size);
+        }
+        for size in [1, DCT_SIZE + 1] {
+            assert!(HashAlgorithm::PHash.check_hash_size(size).is_err());
+        }
+
+        // The bits follow the exposed coefficients
+        let dct = ImageHash::dct_coefficients(&img);
+        assert_eq!(dct.len(), DCT_SIZE * DCT_SIZE);
+        let hash = ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+        let coeffs: Vec<f64> = (0..8)
+            .flat_map(|y| dct[y * DCT_SIZE..y * DCT_SIZE + 8].to_vec())
+            .collect();
+        let mut sorted = coeffs[1..].to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let bits: Vec<bool> = coeffs.iter().map(|&c| c > sorted[31]).collect();
+        assert_eq!(hash.to_bits()[1..], bits[1..]);
+    }
+
+    #[test]
+    fn test_colorhash_distinguishes_recolor() {
+        let logo = |color: [u8; 3]| {
+            DynamicImage::ImageRgb8(image::ImageBuffer::from_fn(64, 64, |x, y| {
+                if (16..48).contains(&x) && (16..40).contains(&y) {
+                    image::Rgb(color)
+                } else {
+                    image::Rgb([255, 255, 255])
+                }
+            }))
+        };
+        let red = logo([255, 0, 0]);
+        let blue = logo([0, 0, 255]);
+
+        let gray_red = ImageHash::from_image(&red, HashAlgorithm::PHash, 8).unwrap();
+        let gray_blue = ImageHash::from_image(&blue, HashAlgorithm::PHash, 8).unwrap();
+        let color_red = ImageHash::from_image(&red, HashAlgorithm::ColorHash, 8).unwrap();
+        let color_blue = ImageHash::from_image(&blue, HashAlgorithm::ColorHash, 8).unwrap();
+
+        assert_eq!(color_red.bit_len(), 3 * gray_red.bit_len());
+        assert!(color_red.distance(&color_blue) > gray_red.distance(&gray_blue));
+    }
+
+    #[test]
+    fn test_sixteen_bit_keeps_precision() {
+        // A dark 16-bit scan whose shades all fall within 8-bit levels 0-3
+        let shade = |x: u32, y: u32| ((x * 7 + y * 13) % 200) as u16 + if x > 40 { 300 } else { 0 };
+        let deep = DynamicImage::ImageLuma16(image::ImageBuffer::from_fn(64, 64, |x, y| {
+            image::Luma([shade(x, y) * 2])
+        }));
+        // The same picture stretched to the full 16-bit range
+        let bright = DynamicImage::ImageLuma16(image::ImageBuffer::from_fn(64, 64, |x, y| {
+            image::Luma([shade(x, y) * 130])
+        }));
+        for algorithm in [
+            HashAlgorithm::PHash,
+            HashAlgorithm::WHash { level: None },
+            HashAlgorithm::BlockHash,
+        ] {
+            let a = ImageHash::from_image(&deep, algorithm, 8).unwrap();
+            let b = ImageHash::from_image(&bright, algorithm, 8).unwrap();
+            assert!(a.distance(&b) <= 2, "{}: {}", algorithm, a.distance(&b));
+        }
+        assert_eq!(luma_square(&bright, 2, FilterType::Nearest).len(), 4);
+        assert!(!is_high_depth(&gradient_image(4, 4)));
+        assert!(is_high_depth(&deep));
+    }
+
+    #[test]
+    fn test_non_finite_float_pixels() {
+        let gradient = |bad: f32| {
+            DynamicImage::ImageRgb32F(image::ImageBuffer::from_fn(64, 64, |x, y| {
+                let v = if (x, y) == (10, 20) {
+                    bad
+                } else {
+                    (x + y) as f32 / 128.0
+                };
+                image::Rgb([v, v, v])
+            }))
+        };
+        let clean = gradient(0.0);
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            for algorithm in [
+                HashAlgorithm::PHash,
+                HashAlgorithm::WHash { level: None },
+                HashAlgorithm::BlockHash,
+                HashAlgorithm::ColorHash,
+            ] {
+                assert_eq!(
+                    ImageHash::from_image(&gradient(bad), algorithm, 8).unwrap(),
+                    ImageHash::from_image(&clean, algorithm, 8).unwrap(),
+                    "{} with {}",
+                    algorithm,
+                    bad
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_phash_config() {
+        let img = gradient_image(100, 80);
+        let default = PHashConfig::default();
+        assert_eq!(
+            ImageHash::from_image_config(&img, HashAlgorithm::PHash, 8, &default).unwrap(),
+            ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap()
+        );
+        assert_eq!(default.tag(), "");
+
+        let fast = PHashConfig {
+            dct_size: 16,
+            filter: FilterType::Triangle,
+        };
+        assert_eq!(fast.tag(), "/dct16triangle");
+        let hash = ImageHash::from_image_config(&img, HashAlgorithm::PHash, 8, &fast).unwrap();
+        assert_eq!(hash.bit_len(), 64);
+        assert!(ImageHash::from_image_config(&img, HashAlgorithm::PHash, 17, &fast).is_err());
+        assert!(ImageHash::from_image_config(&img, HashAlgorithm::ColorHash, 16, &fast).is_ok());
+
+        let fine = PHashConfig {
+            dct_size: 64,
+            filter: FilterType::Lanczos3,
+        };
+        let hash = ImageHash::from_image_config(&img, HashAlgorithm::PHash, 48, &fine).unwrap();
+        assert_eq!(hash.hash_size(), 48);
+        assert_eq!(ImageHash::dct_coefficients_with(&img, &fine).len(), 64 * 64);
+
+        let too_large = PHashConfig {
+            dct_size: MAX_DCT_SIZE + 1,
+            ..default
+        };
+        assert!(too_large.validate().is_err());
+        for name in ["nearest", "triangle", "catmullrom", "gaussian", "lanczos3"] {
+            assert_eq!(filter_name(parse_filter(name).unwrap()), name);
+        }
+        assert!(parse_filter("bilinear").is_err());
+    }
+
+    #[test]
+    fn test_imagehash_bit_order() {
+        let img = gradient_image(100, 80);
+        let native = ImageHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+        let compat =
+            ImageHash::from_image_ordered(&img, HashAlgorithm::PHash, 8, BitOrder::ImageHash)
+                .unwrap();
+        assert_eq!(compat.bit_len(), 64);
+        assert_ne!(native, compat);
+
+        // 25 bits: imagehash right-aligns them in 7 hex digits
+        let odd = ImageHash::from_image_ordered(&img, HashAlgorithm::PHash, 5, BitOrder::ImageHash)
+            .unwrap();
+        let hex = odd.to_hex_ordered(BitOrder::ImageHash);
+        assert_eq!(hex.len(), 7);
+        assert_eq!(
+            ImageHash::from_hex_ordered(&hex, BitOrder::ImageHash).unwrap(),
+            odd
+        );
+        assert_eq!(odd.to_hex_ordered(BitOrder::Native), odd.to_hex());
+
+        assert!(ImageHash::from_image_ordered(
+            &img,
+            HashAlgorithm::WHash { level: None },
+            8,
+            BitOrder::ImageHash
+        )
+        .is_ok());
+        assert!(ImageHash::from_image_ordered(
+            &img,
+            HashAlgorithm::BlockHash,
+            8,
+            BitOrder::ImageHash
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_hierarchical_hashes() {
+        let img = gradient_image(100, 80);
+        let options = HashOptions::default();
+        let (coarse, fine) = ImageHash::hierarchical_from_image(&img, 16, &options).unwrap();
+        assert_eq!(coarse.bit_len(), 16);
+        assert_eq!(fine.bit_len(), 256);
+        assert_eq!(
+            coarse,
+            ImageHash::from_image(&img, HashAlgorithm::PHash, COARSE_HASH_SIZE).unwrap()
+        );
+        assert_eq!(
+            fine,
+            ImageHash::from_image(&img, HashAlgorithm::PHash, 16).unwrap()
+        );
+        assert!(ImageHash::hierarchical_from_image(&img, 3, &options).is_err());
+        assert!(ImageHash::hierarchical_from_image(&img, 33, &options).is_err());
+    }
+
+    #[test]
+    fn test_libphash_algorithms() {
+        let img = gradient_image(64, 48);
+        let mh = ImageHash::from_image(&img, HashAlgorithm::MarrHildreth, MH_HASH_SIZE).unwrap();
+        assert_eq!(mh.bit_len(), 576);
+        assert!(ImageHash::from_image(&img, HashAlgorithm::MarrHildreth, 8).is_err());
+
+        let radial = ImageHash::from_image(&img, HashAlgorithm::RadialVariance, 40).unwrap();
+        assert_eq!(radial.to_bytes().len(), 40);
+        let parsed = ImageHash::from_tagged(&radial.to_tagged().unwrap()).unwrap();
+        assert_eq!(parsed, radial);
+        assert_eq!(radial.cross_correlation(&parsed).unwrap(), 1.0);
+        assert!(radial.cross_correlation(&mh).is_err());
+
+        for name in ["marrhildreth", "radialvariance"] {
+            let algorithm: HashAlgorithm = name.parse().unwrap();
+            assert_eq!(algorithm.to_string(), name);
+        }
+    }
+
+    fn two_tone_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(image::ImageBuffer::from_fn(width, height, |x, y| {
+            let bright = x < width / 2 && y < height / 2;
+            image::Luma([if bright {
+                230
+            } else {
+                20 + ((x + y) % 16) as u8
+            }])
+        }))
+    }
+
+    #[test]
+    fn test_crop_resistant_roundtrip_and_self_match() {
+        let img = two_tone_image(400, 400);
+        let hash = CropResistantHash::from_image(&img, HashAlgorithm::PHash, 8).unwrap();
+        assert!(!hash.segments().is_empty());
+
+        let parsed = CropResistantHash::from_hex(&hash.to_hex()).unwrap();
+        assert_eq!(parsed.to_hex(), hash.to_hex());
+        assert!(hash.matches(&parsed, 1, DEFAULT_BIT_ERROR_RATE));
+        assert_eq!(hash.distance(&parsed), 0.0);
+    }
+
+    #[test]
+    fn test_crop_resistant_no_match_scores_segment_count() {
+        let a = CropResistantHash::from_hex("0000000000000000,00000000ffffffff").unwrap();
+        let b = CropResistantHash::from_hex("ffffffffffffffff").unwrap();
+        assert!(!a.matches(&b, 1, DEFAULT_BIT_ERROR_RATE));
+        assert_eq!(a.distance(&b), 2.0);
+        assert!(CropResistantHash::from_hex("").is_err());
+
+        assert_eq!(a.checked_distance(&b), Ok(2.0));
+        let short = CropResistantHash::from_hex("00000000").unwrap();
+        assert!(short.checked_distance(&b).is_err());
+        assert!(b.checked_distance(&short).is_err());
     }
 }
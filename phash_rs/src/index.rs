@@ -0,0 +1,722 @@
+//! Hash Index Module
+//!
+//! BK-tree and multi-index hashing over Hamming distance so that "all
+//! hashes within `threshold`" queries visit only a fraction of the stored
+//! hashes instead of all of them.
+
+use crate::distance::{hamming, hamming_rows};
+use crate::hash::ImageHash;
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// A node in the BK-tree arena
+#[derive(Debug, Clone)]
+struct Node<T> {
+    hash: ImageHash,
+    value: T,
+    /// (distance to this node, child node index)
+    children: Vec<(u32, usize)>,
+    /// Removed entries stay in place so the tree structure remains valid
+    removed: bool,
+}
+
+/// One node of a `BkTree` as `(hash, value, children, removed)`, see
+/// `BkTree::raw_nodes`
+pub(crate) type RawNode<T> = (ImageHash, T, Vec<(u32, usize)>, bool);
+
+/// BK-tree metric index keyed by `ImageHash`
+///
+/// All hashes stored in one tree must have the same bit length.
+#[derive(Debug, Clone)]
+pub struct BkTree<T> {
+    nodes: Vec<Node<T>>,
+    /// Number of nodes not marked as removed
+    live: usize,
+}
+
+impl<T> Default for BkTree<T> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            live: 0,
+        }
+    }
+}
+
+impl<T> BkTree<T> {
+    /// Create an empty tree
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of stored hashes
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    /// Whether the tree is empty
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    /// Insert a hash with an associated value
+    pub fn add(&mut self, hash: ImageHash, value: T) {
+        let new_idx = self.nodes.len();
+        self.live += 1;
+        if new_idx == 0 {
+            self.nodes.push(Node {
+                hash,
+                value,
+                children: Vec::new(),
+                removed: false,
+            });
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let dist = self.nodes[current].hash.distance(&hash);
+            let next = self.nodes[current]
+                .children
+                .iter()
+                .find(|(d, _)| *d == dist)
+                .map(|&(_, idx)| idx);
+            match next {
+                Some(idx) => current = idx,
+                None => {
+                    self.nodes[current].children.push((dist, new_idx));
+                    break;
+                }
+            }
+        }
+
+        self.nodes.push(Node {
+            hash,
+            value,
+            children: Vec::new(),
+            removed: false,
+        });
+    }
+
+    /// Remove entries with exactly this hash whose value matches `pred`
+    ///
+    /// Returns the number of entries removed.
+    pub fn remove<F: Fn(&T) -> bool>(&mut self, hash: &ImageHash, pred: F) -> usize {
+        let mut removed = 0;
+        let mut stack = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![0]
+        };
+        while let Some(idx) = stack.pop() {
+            let dist = self.nodes[idx].hash.distance(hash);
+            let node = &mut self.nodes[idx];
+            if dist == 0 && !node.removed && pred(&node.value) {
+                node.removed = true;
+                removed += 1;
+            }
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| *d == dist)
+                    .map(|&(_, child)| child),
+            );
+        }
+
+        self.live -= removed;
+        removed
+    }
+
+    /// Approximate heap memory held by the tree, in bytes
+    ///
+    /// Counts nodes, hash bits and child lists, including removed nodes;
+    /// heap data owned by the values themselves is not included.
+    pub fn heap_bytes(&self) -> usize {
+        let nodes = self.nodes.capacity() * std::mem::size_of::<Node<T>>();
+        let per_node: usize = self
+            .nodes
+            .iter()
+            .map(|n| {
+                n.hash.bit_len().div_ceil(8)
+                    + n.children.capacity() * std::mem::size_of::<(u32, usize)>()
+            })
+            .sum();
+        nodes + per_node
+    }
+
+    /// Iterate over live `(hash, value)` entries in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&ImageHash, &T)> {
+        self.nodes
+            .iter()
+            .filter(|n| !n.removed)
+            .map(|n| (&n.hash, &n.value))
+    }
+
+    /// Every node in arena order, removed ones included, as
+    /// `(hash, value, children, removed)`
+    pub(crate) fn raw_nodes(
+        &self,
+    ) -> impl Iterator<Item = (&ImageHash, &T, &[(u32, usize)], bool)> {
+        self.nodes
+            .iter()
+            .map(|n| (&n.hash, &n.value, n.children.as_slice(), n.removed))
+    }
+
+    /// Rebuild a tree from the output of `raw_nodes` without comparing any
+    /// hashes
+    ///
+    /// Fails unless every child comes after its parent, which holds for
+    /// any tree built by `add` and rules out cycles.
+    pub(crate) fn from_raw_nodes(raw: Vec<RawNode<T>>) -> Result<Self, String> {
+        let mut nodes = Vec::with_capacity(raw.len());
+        let mut live = 0;
+        for (idx, (hash, value, children, removed)) in raw.into_iter().enumerate() {
+            if children.iter().any(|&(_, child)| child <= idx) {
+                return Err(format!("Node {} links back to an earlier node", idx));
+            }
+            live += usize::from(!removed);
+            nodes.push(Node {
+                hash,
+                value,
+                children,
+                removed,
+            });
+        }
+        if nodes
+            .iter()
+            .any(|n| n.children.iter().any(|&(_, child)| child >= nodes.len()))
+        {
+            return Err("Node links past the end of the tree".to_string());
+        }
+        Ok(Self { nodes, live })
+    }
+
+    /// Find all stored entries within `threshold` of `hash`
+    ///
+    /// Returns `(value, distance)` pairs in insertion order.
+    pub fn query(&self, hash: &ImageHash, threshold: u32) -> Vec<(&T, u32)> {
+        let mut found: Vec<(usize, u32)> = Vec::new();
+        if self.nodes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            let dist = node.hash.distance(hash);
+            if dist <= threshold && !node.removed {
+                found.push((idx, dist));
+            }
+
+            // Triangle inequality: only children in [dist - t, dist + t] can match
+            let low = dist.saturating_sub(threshold);
+            let high = dist.saturating_add(threshold);
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| *d >= low && *d <= high)
+                    .map(|&(_, child)| child),
+            );
+        }
+
+        found.sort_unstable_by_key(|&(idx, _)| idx);
+        found
+            .into_iter()
+            .map(|(idx, dist)| (&self.nodes[idx].value, dist))
+            .collect()
+    }
+}
+
+/// An entry of a `MultiIndex`
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    hash: ImageHash,
+    value: T,
+    removed: bool,
+}
+
+/// Hasher for band values, which need no protection against collisions
+#[derive(Default)]
+struct BandHasher(u64);
+
+impl Hasher for BandHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.write_u64(self.0.rotate_left(8) ^ b as u64);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = n.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+/// Band value -> (entry index, first 64 hash bits) of the entries with it
+type BandTable = HashMap<u64, Vec<(usize, u64)>, BuildHasherDefault<BandHasher>>;
+
+/// Multi-index hash table over Hamming distance
+///
+/// Each hash is split into `bands` contiguous bit ranges and entries are
+/// bucketed by the value of every band. Two hashes within distance `t`
+/// differ in at most about `t / bands` bits on at least one band, so a
+/// query only probes the buckets that close to its own band values and the
+/// results are still exact. Unlike a BK-tree, the work per query stays
+/// small for thresholds up to about 10 on 64-bit hashes; when the probes
+/// would outnumber the stored hashes, `query` scans linearly instead.
+///
+/// All hashes stored in one index must have the same bit length.
+#[derive(Debug, Clone)]
+pub struct MultiIndex<T> {
+    entries: Vec<Entry<T>>,
+    bands: usize,
+    /// Bit length of the stored hashes, fixed by the first `add`
+    bit_len: usize,
+    /// Hash bits packed into `bit_len.div_ceil(64)` words per entry, so
+    /// candidates are verified without following a pointer per entry
+    words: Vec<u64>,
+    /// One table per band
+    tables: Vec<BandTable>,
+    /// Number of entries not marked as removed
+    live: usize,
+}
+
+impl<T> MultiIndex<T> {
+    /// Create an empty index splitting hashes into `bands` bands
+    ///
+    /// The count is clamped so that every band holds between 1 and 64 bits.
+    pub fn new(bands: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            bands: bands.max(1),
+            bit_len: 0,
+            words: Vec::new(),
+            tables: Vec::new(),
+            live: 0,
+        }
+    }
+
+    /// Create an empty index tuned for about `expected_len` hashes of
+    /// `bit_len` bits queried with `threshold`
+    pub fn for_corpus(bit_len: usize, expected_len: usize, threshold: u32) -> Self {
+        Self::new(Self::recommended_bands(bit_len, expected_len, threshold))
+    }
+
+    /// Band count for `expected_len` hashes of `bit_len` bits queried with
+    /// `threshold`
+    ///
+    /// More bands mean fewer buckets to probe but larger buckets to scan.
+    /// Probes are random memory accesses while bucket entries are read in
+    /// sequence, so one probe is weighted like `PROBE_COST` entries.
+    pub fn recommended_bands(bit_len: usize, expected_len: usize, threshold: u32) -> usize {
+        const PROBE_COST: f64 = 50.0;
+        let min = bit_len.div_ceil(64).max(1);
+        let max = bit_len.clamp(min, 64);
+        (min..=max)
+            .min_by(|&a, &b| {
+                let cost = |bands: usize| {
+                    band_radii(bands, threshold)
+                        .into_iter()
+                        .enumerate()
+                        .filter_map(|(b, r)| {
+                            let (_, len) = band_range(bit_len, bands, b);
+                            let probes = ball_size(len, r?) as f64;
+                            let per_bucket = expected_len as f64 / 2f64.powi(len as i32);
+                            Some(probes * (PROBE_COST + per_bucket))
+                        })
+                        .sum::<f64>()
+                };
+                cost(a).total_cmp(&cost(b))
+            })
+            .unwrap_or(min)
+    }
+
+    /// Number of bands hashes are split into
+    pub fn bands(&self) -> usize {
+        self.bands
+    }
+
+    /// Number of stored hashes
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    /// Whether the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    fn band_key(&self, hash: &ImageHash, b: usize) -> u64 {
+        let (start, len) = band_range(self.bit_len, self.bands, b);
+        hash.bit_range(start, len)
+    }
+
+    fn pack(&self, hash: &ImageHash) -> Vec<u64> {
+        let mut packed = hash.words().to_vec();
+        packed.resize(self.bit_len.div_ceil(64), 0);
+        packed
+    }
+
+    fn packed_distance(&self, idx: usize, packed: &[u64]) -> u32 {
+        let stride = packed.len();
+        hamming(&self.words[idx * stride..(idx + 1) * stride], packed)
+    }
+
+    /// Insert a hash with an associated value
+    pub fn add(&mut self, hash: ImageHash, value: T) {
+        if self.tables.is_empty() {
+            self.bit_len = hash.bit_len();
+            self.bands = self
+                .bands
+                .clamp(self.bit_len.div_ceil(64).max(1), self.bit_len.max(1));
+            self.tables = vec![BandTable::default(); self.bands];
+        }
+
+        let idx = self.entries.len();
+        let packed = self.pack(&hash);
+        for b in 0..self.bands {
+            let key = self.band_key(&hash, b);
+            self.tables[b]
+                .entry(key)
+                .or_default()
+                .push((idx, packed[0]));
+        }
+        self.words.extend(packed);
+        self.entries.push(Entry {
+            hash,
+            value,
+            removed: false,
+        });
+        self.live += 1;
+    }
+
+    /// Remove entries with exactly this hash whose value matches `pred`
+    ///
+    /// Returns the number of entries removed. Memory held by removed
+    /// entries is released once they outnumber the live ones.
+    pub fn remove<F: Fn(&T) -> bool>(&mut self, hash: &ImageHash, pred: F) -> usize {
+        let Some(bucket) = self
+            .tables
+            .first()
+            .and_then(|table| table.get(&self.band_key(hash, 0)))
+        else {
+            return 0;
+        };
+
+        let mut removed = 0;
+        for &(idx, _) in bucket {
+            let entry = &mut self.entries[idx];
+            if !entry.removed && entry.hash.distance(hash) == 0 && pred(&entry.value) {
+                entry.removed = true;
+                removed += 1;
+            }
+        }
+        self.live -= removed;
+        if self.entries.len() - self.live > self.live {
+            self.compact();
+        }
+        removed
+    }
+
+    /// Rebuild without removed entries, keeping insertion order
+    fn compact(&mut self) {
+        let entries = std::mem::take(&mut self.entries);
+        self.words = Vec::new();
+        self.tables
+            .iter_mut()
+            .for_each(|t| *t = BandTable::default());
+        self.live = 0;
+        for entry in entries.into_iter().filter(|e| !e.removed) {
+            self.add(entry.hash, entry.value);
+        }
+    }
+
+    /// Approximate heap memory held by the index, in bytes
+    ///
+    /// Counts entries, hash bits and bucket tables, including removed
+    /// entries not yet compacted away; heap data owned by the values
+    /// themselves is not included.
+    pub fn heap_bytes(&self) -> usize {
+        let entries = self.entries.capacity() * std::mem::size_of::<Entry<T>>()
+            + self.words.capacity() * std::mem::size_of::<u64>()
+            + self
+                .entries
+                .iter()
+                .map(|e| e.hash.bit_len().div_ceil(8))
+                .sum::<usize>();
+        let tables: usize = self
+            .tables
+            .iter()
+            .map(|table| {
+                table.capacity() * std::mem::size_of::<(u64, Vec<(usize, u64)>)>()
+                    + table
+                        .values()
+                        .map(|bucket| bucket.capacity() * std::mem::size_of::<(usize, u64)>())
+                        .sum::<usize>()
+            })
+            .sum();
+        entries + tables
+    }
+
+    /// Iterate over live `(hash, value)` entries in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&ImageHash, &T)> {
+        self.entries
+            .iter()
+            .filter(|e| !e.removed)
+            .map(|e| (&e.hash, &e.value))
+    }
+
+    /// Find all stored entries within `threshold` of `hash`
+    ///
+    /// Returns `(value, distance)` pairs in insertion order.
+    pub fn query(&self, hash: &ImageHash, threshold: u32) -> Vec<(&T, u32)> {
+        // No tables until the first add fixes the bit length
+        if self.live == 0 || self.tables.is_empty() {
+            return Vec::new();
+        }
+        let packed = self.pack(hash);
+        let radii = band_radii(self.tables.len(), threshold);
+        let probes = probe_count(self.bit_len, self.bands, threshold);
+
+        if probes >= self.entries.len() && !packed.is_empty() {
+            // Every entry is a candidate, so compare them all in one batch
+            return hamming_rows(&packed, &self.words)
+                .into_iter()
+                .enumerate()
+                .filter(|&(idx, dist)| dist <= threshold && !self.entries[idx].removed)
+                .map(|(idx, dist)| (&self.entries[idx].value, dist))
+                .collect();
+        }
+
+        let mut candidates: Vec<usize> = Vec::new();
+        if probes >= self.entries.len() {
+            candidates.extend(0..self.entries.len());
+        } else {
+            for (b, radius) in radii.into_iter().enumerate() {
+                let Some(radius) = radius else { continue };
+                let table = &self.tables[b];
+                let (_, len) = band_range(self.bit_len, self.bands, b);
+                for_each_within(self.band_key(hash, b), len, radius, &mut |key| {
+                    // The first word's distance is a lower bound, and exact
+                    // for hashes of up to 64 bits
+                    let near = table
+                        .get(&key)
+                        .into_iter()
+                        .flatten()
+                        .filter(|(_, first)| (first ^ packed[0]).count_ones() <= threshold);
+                    candidates.extend(near.map(|&(idx, _)| idx));
+                });
+            }
+            candidates.sort_unstable();
+            candidates.dedup();
+        }
+
+        candidates
+            .into_iter()
+            .filter(|&idx| !self.entries[idx].removed)
+            .filter_map(|idx| {
+                let dist = self.packed_distance(idx, &packed);
+                (dist <= threshold).then_some((&self.entries[idx].value, dist))
+            })
+            .collect()
+    }
+}
+
+/// First bit and length of band `b` of `bands` over `bit_len` bits
+pub(crate) fn band_range(bit_len: usize, bands: usize, b: usize) -> (usize, usize) {
+    let base = bit_len / bands;
+    let extra = bit_len % bands;
+    (b * base + b.min(extra), base + usize::from(b < extra))
+}
+
+/// Search radius for each of `bands` bands, `None` for bands that need no
+/// probing
+///
+/// If two hashes are within `threshold`, some band `b` differs by at most
+/// `radii[b]` bits as long as the `radii[b] + 1` sum to more than
+/// `threshold`, so the radii are spread as evenly as that allows.
+pub(crate) fn band_radii(bands: usize, threshold: u32) -> Vec<Option<usize>> {
+    let needed = threshold as usize + 1;
+    let (q, rem) = (needed / bands, needed % bands);
+    (0..bands)
+        .map(|b| if b < rem { Some(q) } else { q.checked_sub(1) })
+        .collect()
+}
+
+/// Total number of band keys probed to find every hash within `threshold`
+/// of a `bit_len`-bit hash split into `bands` bands, saturating
+pub(crate) fn probe_count(bit_len: usize, bands: usize, threshold: u32) -> usize {
+    band_radii(bands, threshold)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(b, r)| r.map(|r| ball_size(band_range(bit_len, bands, b).1, r)))
+        .fold(0, usize::saturating_add)
+}
+
+/// Number of `len`-bit values within `radius` bits of a given one
+fn ball_size(len: usize, radius: usize) -> usize {
+    let mut term = 1usize;
+    let mut total = 1usize;
+    for i in 0..radius.min(len) {
+        term = term.saturating_mul(len - i) / (i + 1);
+        total = total.saturating_add(term);
+    }
+    total
+}
+
+/// Call `f` with every `len`-bit value within `radius` bits of `key`
+pub(crate) fn for_each_within<F: FnMut(u64)>(key: u64, len: usize, radius: usize, f: &mut F) {
+    flip_from(key, 0, len, radius, f);
+}
+
+/// Only bits at or above `from` are flipped, so each value is produced once
+fn flip_from<F: FnMut(u64)>(key: u64, from: usize, len: usize, radius: usize, f: &mut F) {
+    f(key);
+    if radius == 0 {
+        return;
+    }
+    for bit in from..len {
+        flip_from(key ^ (1 << bit), bit + 1, len, radius - 1, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+
+    #[test]
+    fn test_query_matches_linear_scan() {
+        let hexes = [
+            "0000000000000000",
+            "0000000000000001",
+            "00000000000000ff",
+            "ffffffffffffffff",
+            "fffffffffffffff0",
+            "0f0f0f0f0f0f0f0f",
+        ];
+        let hashes: Vec<ImageHash> = hexes
+            .iter()
+            .map(|h| ImageHash::from_hex(h).unwrap())
+            .collect();
+
+        let mut tree = BkTree::new();
+        for (i, h) in hashes.iter().enumerate() {
+            tree.add(h.clone(), i);
+        }
+        assert_eq!(tree.len(), hexes.len());
+
+        for query in &hashes {
+            for threshold in [0, 1, 8, 32] {
+                let got: Vec<usize> = tree
+                    .query(query, threshold)
+                    .into_iter()
+                    .map(|(&i, _)| i)
+                    .collect();
+                let expected: Vec<usize> = (0..hashes.len())
+                    .filter(|&i| hashes[i].distance(query) <= threshold)
+                    .collect();
+                assert_eq!(got, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let h = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let mut tree = BkTree::new();
+        tree.add(h("0000000000000000"), "a");
+        tree.add(h("0000000000000001"), "b");
+        tree.add(h("0000000000000001"), "c");
+
+        assert_eq!(tree.remove(&h("0000000000000001"), |v| *v == "b"), 1);
+        assert_eq!(tree.len(), 2);
+        let got: Vec<&str> = tree
+            .query(&h("0000000000000000"), 4)
+            .into_iter()
+            .map(|(v, _)| *v)
+            .collect();
+        assert_eq!(got, vec!["a", "c"]);
+        assert_eq!(tree.remove(&h("ffffffffffffffff"), |_| true), 0);
+    }
+
+    #[test]
+    fn test_multi_index_matches_linear_scan() {
+        // Clusters of near-identical hashes, as duplicate grouping sees them
+        let mut rng = SeededRng::new(3);
+        let mut hashes = Vec::new();
+        for _ in 0..40 {
+            let base = rng.next_u64();
+            for _ in 0..5 {
+                let mut bits = base;
+                for _ in 0..rng.below(6) {
+                    bits ^= 1 << rng.below(64);
+                }
+                hashes.push(ImageHash::from_hex(&format!("{:016x}", bits)).unwrap());
+            }
+        }
+
+        for bands in [1, 3, 4, 8, 100] {
+            let mut index = MultiIndex::new(bands);
+            for (i, h) in hashes.iter().enumerate() {
+                index.add(h.clone(), i);
+            }
+            assert_eq!(index.len(), hashes.len());
+            assert!(index.bands() <= 64);
+
+            for query in hashes.iter().step_by(7) {
+                for threshold in [0, 2, 6, 10, 40] {
+                    let got: Vec<(usize, u32)> = index
+                        .query(query, threshold)
+                        .into_iter()
+                        .map(|(&i, d)| (i, d))
+                        .collect();
+                    let expected: Vec<(usize, u32)> = (0..hashes.len())
+                        .map(|i| (i, hashes[i].distance(query)))
+                        .filter(|&(_, d)| d <= threshold)
+                        .collect();
+                    assert_eq!(got, expected);
+                }
+            }
+        }
+
+        let mut index = MultiIndex::for_corpus(64, hashes.len(), 0);
+        index.add(hashes[0].clone(), "a");
+        index.add(hashes[0].clone(), "b");
+        assert_eq!(index.remove(&hashes[0], |v| *v == "a"), 1);
+        let got: Vec<&str> = index
+            .query(&hashes[0], 0)
+            .into_iter()
+            .map(|(v, _)| *v)
+            .collect();
+        assert_eq!(got, vec!["b"]);
+
+        // Empty indexes, fresh or emptied, find nothing
+        assert!(MultiIndex::<usize>::new(4).query(&hashes[0], 5).is_empty());
+        let mut emptied = MultiIndex::new(4);
+        emptied.add(hashes[0].clone(), 0);
+        emptied.remove(&hashes[0], |_| true);
+        assert!(emptied.query(&hashes[0], 5).is_empty());
+
+        // Removing most entries compacts the index without losing the rest
+        let mut index = MultiIndex::new(4);
+        for (i, h) in hashes.iter().enumerate() {
+            index.add(h.clone(), i);
+        }
+        for (i, h) in hashes.iter().enumerate().filter(|(i, _)| i % 4 != 0) {
+            index.remove(h, |&v| v == i);
+        }
+        assert!(index.entries.len() < hashes.len());
+        assert_eq!(index.len(), hashes.len() / 4);
+        let got: Vec<usize> = index
+            .query(&hashes[0], 64)
+            .into_iter()
+            .map(|(&i, _)| i)
+            .collect();
+        let expected: Vec<usize> = (0..hashes.len()).step_by(4).collect();
+        assert_eq!(got, expected);
+    }
+}
@@ -0,0 +1,359 @@
+//! Index File Module
+//!
+//! Saves a `BkTree` of paths together with the algorithm, hash size and
+//! per-file metadata in a compact binary file. The tree is stored node by
+//! node as built, so loading a million-image index only reads it back: no
+//! hash is parsed from hex and no distance is computed, unlike rebuilding
+//! the tree from a hash dump.
+//!
+//! Layout after the magic line, all integers little-endian:
+//!
+//! ```text
+//! algorithm   u8 flag, u32 length + UTF-8 name
+//! hash size   u32 (0 = derived from the bit count)
+//! list        u8 flag, u32 length + UTF-8 name (see `sync`)
+//! version     u64
+//! hash bytes  u32, the same for every node
+//! nodes       u64 count, then per node: hash bytes, u32 length + path
+//!             bytes, u8 removed, u32 child count, (u32 distance, u32 index)
+//!             per child
+//! files       u64 count, then per file: u32 length + path bytes, u8 flag +
+//!             i64 mtime seconds, u32 nanos, u64 size; u8 flag + u32 width,
+//!             u32 height
+//! ```
+//!
+//! Path bytes are the raw bytes on Unix, so names that are not valid UTF-8
+//! survive, and UTF-8 elsewhere.
+
+use crate::cache::{write_bytes, write_stamp, write_str, FileStamp, Reader};
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::index::BkTree;
+use crate::paths::path_bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// First bytes of an index file
+const INDEX_FILE_MAGIC: &[u8] = b"phash_rs index v1\n";
+
+/// What was known about a file when its hash was indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IndexedFile {
+    /// Size and modification time at hashing time
+    pub stamp: Option<FileStamp>,
+    /// Width and height of the decoded image
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl IndexedFile {
+    /// Metadata of the file at `path` as it is now, without dimensions
+    ///
+    /// The stamp is `None` if the path cannot be stat'ed, as for an
+    /// identifier that is not a file.
+    pub fn of<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            stamp: FileStamp::of(path),
+            dimensions: None,
+        }
+    }
+
+    /// Whether the file changed or disappeared since it was indexed
+    ///
+    /// Entries indexed without a stamp are never stale.
+    pub fn is_stale(&self, path: &Path) -> bool {
+        self.stamp
+            .is_some_and(|stamp| FileStamp::of(path) != Some(stamp))
+    }
+}
+
+/// A hash index as saved to and loaded from disk
+#[derive(Debug, Clone, Default)]
+pub struct IndexFile {
+    /// Algorithm the hashes were computed with, if known
+    pub algorithm: Option<HashAlgorithm>,
+    /// Hash size the hashes were computed with, if known
+    pub hash_size: Option<usize>,
+    /// Shared hash list the index is synced from, and the version applied
+    pub list: Option<String>,
+    pub version: u64,
+    pub tree: BkTree<PathBuf>,
+    /// Metadata by path, for the paths it is known for
+    pub files: HashMap<PathBuf, IndexedFile>,
+}
+
+impl IndexFile {
+    /// Write the index, replacing the file atomically
+    ///
+    /// Fails if the tree holds hashes of different lengths.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut nodes = self.tree.raw_nodes().peekable();
+        let hash_bytes = nodes.peek().map_or(0, |(hash, ..)| hash.bit_len() / 8);
+        let hash_size = self
+            .hash_size
+            .or_else(|| nodes.peek().map(|(hash, ..)| hash.hash_size()));
+
+        let mut data = INDEX_FILE_MAGIC.to_vec();
+        write_opt_str(&mut data, self.algorithm.map(|a| a.to_string()).as_deref());
+        data.extend_from_slice(&(hash_size.unwrap_or(0) as u32).to_le_bytes());
+        write_opt_str(&mut data, self.list.as_deref());
+        data.extend_from_slice(&self.version.to_le_bytes());
+        data.extend_from_slice(&(hash_bytes as u32).to_le_bytes());
+
+        let count_at = data.len();
+        data.extend_from_slice(&0u64.to_le_bytes());
+        let mut count = 0u64;
+        for (hash, value, children, removed) in nodes {
+            let bytes = hash.to_bytes();
+            if bytes.len() != hash_bytes {
+                return Err(format!(
+                    "Cannot save an index mixing {}-bit and {}-bit hashes",
+                    hash_bytes * 8,
+                    bytes.len() * 8
+                ));
+            }
+            data.extend_from_slice(&bytes);
+            write_bytes(&mut data, &path_bytes(value));
+            data.push(u8::from(removed));
+            data.extend_from_slice(&(children.len() as u32).to_le_bytes());
+            for &(distance, child) in children {
+                data.extend_from_slice(&distance.to_le_bytes());
+                data.extend_from_slice(&(child as u32).to_le_bytes());
+            }
+            count += 1;
+        }
+        data[count_at..count_at + 8].copy_from_slice(&count.to_le_bytes());
+
+        // Sorted so identical indexes produce identical files
+        let files: BTreeMap<_, _> = self.files.iter().collect();
+        data.extend_from_slice(&(files.len() as u64).to_le_bytes());
+        for (file, info) in files {
+            write_bytes(&mut data, &path_bytes(file));
+            data.push(u8::from(info.stamp.is_some()));
+            if let Some(stamp) = &info.stamp {
+                write_stamp(&mut data, stamp);
+            }
+            data.push(u8::from(info.dimensions.is_some()));
+            if let Some((width, height)) = info.dimensions {
+                data.extend_from_slice(&width.to_le_bytes());
+                data.extend_from_slice(&height.to_le_bytes());
+            }
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("Failed to write index file {}: {}", path.display(), e))
+    }
+
+    /// Load an index written by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = fs::read(path)
+            .map_err(|e| format!("Failed to read index file {}: {}", path.display(), e))?;
+        let mut reader = Reader {
+            data: data
+                .strip_prefix(INDEX_FILE_MAGIC)
+                .ok_or_else(|| format!("Not a hash index file: {}", path.display()))?,
+        };
+        let truncated = || format!("Truncated hash index file: {}", path.display());
+        Self::read(&mut reader)
+            .ok_or_else(truncated)?
+            .map_err(|e| format!("Invalid hash index file {}: {}", path.display(), e))
+    }
+
+    /// Parse the records after the magic line; `None` if they end early
+    fn read(reader: &mut Reader) -> Option<Result<Self, String>> {
+        let algorithm = match read_opt_str(reader)? {
+            Some(name) => match name.parse::<HashAlgorithm>() {
+                Ok(algorithm) => Some(algorithm),
+                Err(e) => return Some(Err(e)),
+            },
+            None => None,
+        };
+        let hash_size = match u32::from_le_bytes(reader.take()?) {
+            0 => None,
+            size => Some(size as usize),
+        };
+        let list = read_opt_str(reader)?;
+        let version = u64::from_le_bytes(reader.take()?);
+        let hash_bytes = u32::from_le_bytes(reader.take()?) as usize;
+
+        let count = u64::from_le_bytes(reader.take()?) as usize;
+        // Each node takes at least its hash and 9 more bytes
+        let mut nodes = Vec::with_capacity(count.min(reader.data.len() / (hash_bytes + 9)));
+        for _ in 0..count {
+            let (bytes, rest) = reader.data.split_at_checked(hash_bytes)?;
+            reader.data = rest;
+            // The header holds the hashes' own size, whatever their shape
+            let hash = match hash_size {
+                Some(size) if !bytes.is_empty() => Ok(ImageHash::from_raw(bytes.to_vec(), size)),
+                _ => ImageHash::from_hash_bytes(bytes, None),
+            };
+            let mut hash = match hash {
+                Ok(hash) => hash,
+                Err(e) => return Some(Err(e.to_string())),
+            };
+            if let Some(algorithm) = algorithm {
+                hash = hash.with_algorithm(algorithm);
+            }
+            let value = reader.path()?;
+            let removed = u8::from_le_bytes(reader.take()?) != 0;
+            let children = u32::from_le_bytes(reader.take()?) as usize;
+            let children = (0..children)
+                .map(|_| {
+                    let distance = u32::from_le_bytes(reader.take()?);
+                    let child = u32::from_le_bytes(reader.take()?) as usize;
+                    Some((distance, child))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            nodes.push((hash, value, children, removed));
+        }
+        let tree = match BkTree::from_raw_nodes(nodes) {
+            Ok(tree) => tree,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let count = u64::from_le_bytes(reader.take()?) as usize;
+        let mut files = HashMap::with_capacity(count.min(reader.data.len()));
+        for _ in 0..count {
+            let file = reader.path()?;
+            let stamp = match u8::from_le_bytes(reader.take()?) {
+                0 => None,
+                _ => Some(reader.stamp()?),
+            };
+            let dimensions = match u8::from_le_bytes(reader.take()?) {
+                0 => None,
+                _ => Some((
+                    u32::from_le_bytes(reader.take()?),
+                    u32::from_le_bytes(reader.take()?),
+                )),
+            };
+            files.insert(file, IndexedFile { stamp, dimensions });
+        }
+
+        Some(Ok(Self {
+            algorithm,
+            hash_size,
+            list,
+            version,
+            tree,
+            files,
+        }))
+    }
+
+    /// Indexed paths whose file changed or disappeared since it was indexed
+    ///
+    /// Sorted; paths without a recorded stamp are never listed.
+    pub fn stale_paths(&self) -> Vec<PathBuf> {
+        let mut stale: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|(path, info)| info.is_stale(path))
+            .map(|(path, _)| path.clone())
+            .collect();
+        stale.sort();
+        stale
+    }
+}
+
+fn write_opt_str(data: &mut Vec<u8>, s: Option<&str>) {
+    data.push(u8::from(s.is_some()));
+    if let Some(s) = s {
+        write_str(data, s);
+    }
+}
+
+fn read_opt_str(reader: &mut Reader) -> Option<Option<String>> {
+    match u8::from_le_bytes(reader.take()?) {
+        0 => Some(None),
+        _ => reader.string().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(hex: &str) -> ImageHash {
+        ImageHash::from_hex(hex).unwrap()
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_index_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("a.png");
+        fs::write(&image, b"not really an image").unwrap();
+
+        let mut index = IndexFile {
+            algorithm: Some(HashAlgorithm::PHash),
+            list: Some("archive".to_string()),
+            version: 3,
+            ..IndexFile::default()
+        };
+        for (i, hex) in ["00000000000000ff", "00000000000000fe", "ff00000000000000"]
+            .iter()
+            .enumerate()
+        {
+            index.tree.add(hash(hex), PathBuf::from(i.to_string()));
+        }
+        index.tree.add(hash("00000000000000ff"), image.clone());
+        index.tree.remove(&hash("ff00000000000000"), |_| true);
+        index.files.insert(
+            image.clone(),
+            IndexedFile {
+                dimensions: Some((640, 480)),
+                ..IndexedFile::of(&image)
+            },
+        );
+        index
+            .files
+            .insert(PathBuf::from("0"), IndexedFile::default());
+
+        let file = dir.join("index.bin");
+        index.save(&file).unwrap();
+        let loaded = IndexFile::load(&file).unwrap();
+        assert_eq!(loaded.algorithm, Some(HashAlgorithm::PHash));
+        assert_eq!(loaded.hash_size, Some(8));
+        assert_eq!(loaded.list.as_deref(), Some("archive"));
+        assert_eq!(loaded.version, 3);
+        assert_eq!(loaded.files, index.files);
+        assert_eq!(loaded.tree.len(), 3);
+        let query = hash("00000000000000ff");
+        assert_eq!(loaded.tree.query(&query, 2), index.tree.query(&query, 2));
+        let first = loaded.tree.iter().next().unwrap().0;
+        assert_eq!(first.algorithm(), Some(HashAlgorithm::PHash));
+        assert!(loaded.stale_paths().is_empty());
+
+        fs::write(&image, b"changed contents").unwrap();
+        assert_eq!(loaded.stale_paths(), vec![image]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_rejects_damaged_files() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_index_bad_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("index.bin");
+        let mut index = IndexFile::default();
+        index.tree.add(hash("00000000000000ff"), PathBuf::from("a"));
+        index.tree.add(hash("00000000000000fe"), PathBuf::from("b"));
+        index.save(&file).unwrap();
+        let data = fs::read(&file).unwrap();
+
+        fs::write(&file, &data[..data.len() - 12]).unwrap();
+        assert!(IndexFile::load(&file).unwrap_err().contains("Truncated"));
+        fs::write(&file, b"phash_rs cache v5\n").unwrap();
+        assert!(IndexFile::load(&file)
+            .unwrap_err()
+            .contains("Not a hash index"));
+        // Point the root's only child back at the root
+        let mut cyclic = data.clone();
+        let root_child = INDEX_FILE_MAGIC.len() + 1 + 4 + 1 + 8 + 4 + 8 + 8 + 4 + 1 + 1 + 4 + 4;
+        cyclic[root_child..root_child + 4].copy_from_slice(&0u32.to_le_bytes());
+        fs::write(&file, &cyclic).unwrap();
+        assert!(IndexFile::load(&file).unwrap_err().contains("Invalid"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+//! imagehash Interoperability Module
+//!
+//! The Python `imagehash` package computes pHash a little differently:
+//! Pillow's luma weights, a DCT input four times the hash size, an unscaled
+//! DCT, and a median that includes the DC term, whose bit is kept. It also
+//! writes hex with the bits right-aligned rather than padded at the end.
+//! Hashes of one are meaningless against the other's, so this module
+//! reproduces imagehash's computation and text form for databases built
+//! with it.
+
+use crate::error::HashError;
+use crate::hash::ImageHash;
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, Luma};
+use std::fmt;
+use std::str::FromStr;
+
+/// How hash bits are computed and written as hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    /// phash_rs's own: DC bit always 0, hex padded at the end
+    #[default]
+    Native,
+    /// As the Python `imagehash` package: hashes compare directly with
+    /// ones it computed
+    ImageHash,
+}
+
+impl FromStr for BitOrder {
+    type Err = String;
+
+    /// Parse a bit order as used by the Python bindings.
+    ///
+    /// Accepts `"native"` and `"imagehash"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "native" => Ok(BitOrder::Native),
+            "imagehash" => Ok(BitOrder::ImageHash),
+            _ => Err(format!("Unknown bit order: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for BitOrder {
+    /// Canonical name, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitOrder::Native => write!(f, "native"),
+            BitOrder::ImageHash => write!(f, "imagehash"),
+        }
+    }
+}
+
+/// Grayscale as Pillow's `convert("L")`: ITU-R 601-2 luma in 16-bit fixed
+/// point, alpha ignored
+pub(crate) fn pillow_luma(img: &DynamicImage) -> DynamicImage {
+    let rgb = img.to_rgb8();
+    DynamicImage::ImageLuma8(GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+        let [r, g, b] = rgb.get_pixel(x, y).0;
+        let luma = (r as u32 * 19595 + g as u32 * 38470 + b as u32 * 7471 + 0x8000) >> 16;
+        Luma([luma as u8])
+    }))
+}
+
+/// `imagehash.phash` bits, row by row
+///
+/// The image is reduced to `4 * hash_size` square with Lanczos3 and
+/// transformed by the DCT scipy computes without normalization: the
+/// orthonormal DCT with its first row and column scaled by sqrt(2). Every
+/// coefficient of the top-left block, the DC term included, is compared
+/// with the block's median as numpy takes it.
+pub(crate) fn imagehash_phash(img: &DynamicImage, hash_size: usize) -> Vec<bool> {
+    let size = 4 * hash_size;
+    let small = pillow_luma(img).resize_exact(size as u32, size as u32, FilterType::Lanczos3);
+    let pixels: Vec<f64> = small.to_luma8().pixels().map(|p| p.0[0] as f64).collect();
+    let dct = ImageHash::dct_2d(&pixels, size);
+
+    let low: Vec<f64> = (0..hash_size)
+        .flat_map(|y| (0..hash_size).map(move |x| (y, x)))
+        .map(|(y, x)| {
+            let scale = |i: usize| {
+                if i == 0 {
+                    std::f64::consts::SQRT_2
+                } else {
+                    1.0
+                }
+            };
+            dct[y * size + x] * scale(y) * scale(x)
+        })
+        .collect();
+    let mut sorted = low.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    };
+    low.iter().map(|&v| v > median).collect()
+}
+
+/// Hex as imagehash writes it: the bits as one number, in as many hex
+/// digits as they need
+pub(crate) fn imagehash_hex(bits: &[bool]) -> String {
+    let pad = (4 - bits.len() % 4) % 4;
+    let padded: Vec<bool> = std::iter::repeat_n(false, pad)
+        .chain(bits.iter().copied())
+        .collect();
+    padded
+        .chunks(4)
+        .map(|nibble| {
+            let value = nibble.iter().fold(0u32, |acc, &b| (acc << 1) | b as u32);
+            char::from_digit(value, 16).unwrap_or('0')
+        })
+        .collect()
+}
+
+/// Bits and hash size of hex written by imagehash
+///
+/// As `imagehash.hex_to_hash`, the hash size is the square root of the
+/// hex digit count times four, and the bits are the lowest hash size
+/// squared of the number; higher bits must be zero.
+pub(crate) fn imagehash_bits(hex: &str) -> Result<(Vec<bool>, usize), HashError> {
+    let hex = hex.trim();
+    if hex.is_empty() {
+        return Err(HashError::InvalidInput("Empty hash".to_string()));
+    }
+    let mut bits = Vec::with_capacity(hex.len() * 4);
+    for c in hex.chars() {
+        let value = c
+            .to_digit(16)
+            .ok_or_else(|| HashError::InvalidInput(format!("Invalid hex digit: {:?}", c)))?;
+        bits.extend((0..4).rev().map(|i| value >> i & 1 == 1));
+    }
+    let size = (hex.len() * 4).isqrt();
+    let extra = bits.len() - size * size;
+    if bits[..extra].iter().any(|&b| b) {
+        return Err(HashError::InvalidInput(format!(
+            "{} does not fit a hash of size {}",
+            hex, size
+        )));
+    }
+    Ok((bits.split_off(extra), size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_order_from_str() {
+        for order in [BitOrder::Native, BitOrder::ImageHash] {
+            assert_eq!(order.to_string().parse::<BitOrder>(), Ok(order));
+        }
+        assert!("lsb".parse::<BitOrder>().is_err());
+    }
+
+    #[test]
+    fn test_imagehash_hex() {
+        assert_eq!(imagehash_hex(&[true; 25]), "1ffffff");
+        let (bits, size) = imagehash_bits("1ffffff").unwrap();
+        assert_eq!((bits, size), (vec![true; 25], 5));
+
+        let mut bits = vec![false; 64];
+        bits[0] = true;
+        bits[63] = true;
+        assert_eq!(imagehash_hex(&bits), "8000000000000001");
+        assert_eq!(imagehash_bits("8000000000000001").unwrap(), (bits, 8));
+
+        assert!(imagehash_bits("3ffffff").is_err());
+        assert!(imagehash_bits("xyz").is_err());
+        assert!(imagehash_bits("").is_err());
+    }
+
+    #[test]
+    fn test_imagehash_phash_of_flat_image() {
+        // Only the DC term is non-zero, and it is above the median of
+        // zeros: imagehash's well-known 8000000000000000
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            50,
+            40,
+            image::Rgb([90, 120, 150]),
+        ));
+        let bits = imagehash_phash(&img, 8);
+        assert_eq!(imagehash_hex(&bits), "8000000000000000");
+    }
+
+    #[test]
+    fn test_pillow_luma() {
+        let img =
+            DynamicImage::ImageRgb8(image::RgbImage::from_pixel(1, 1, image::Rgb([255, 0, 0])));
+        // Pillow: (255 * 19595 + 0x8000) >> 16 = 76
+        assert_eq!(pillow_luma(&img).to_luma8().get_pixel(0, 0).0[0], 76);
+    }
+}
@@ -0,0 +1,419 @@
+//! Progress Socket Module
+//!
+//! Publishes scan progress and discovered duplicate groups on a local socket
+//! as newline-delimited JSON, so GUI frontends in any language can follow a
+//! running scan instead of polling a partially written results file.
+//!
+//! The scan listens and any number of subscribers may connect while it runs.
+//! A subscriber that connects late first receives the `start` event, the
+//! latest `progress` event and every `group` and `warning` event published
+//! so far.
+//!
+//! ```text
+//! {"event":"start","total":1200}
+//! {"event":"progress","done":17,"total":1200,"path":"/photos/a.jpg"}
+//! {"event":"group","key":"…","hash":"…","best":"…","paths":[…],"sidecars":{…}}
+//! {"event":"warning","kind":"fallback_decoder","path":"…","message":"…"}
+//! {"event":"done","groups":3}
+//! {"event":"error","message":"…"}
+//! ```
+//!
+//! `progress` events are rate-limited; the final one (`done == total`) is
+//! always sent. The address is either a loopback `host:port` (TCP) or, on
+//! Unix, a filesystem path for a Unix domain socket.
+
+use crate::duplicate::DuplicateGroup;
+use crate::paths::path_bytes;
+use crate::progress::{Progress, Throttle};
+use crate::warnings::Warning;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Minimum time between two published `progress` events
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a write may block before a slow subscriber is dropped
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often the accept loop checks for new subscribers
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+enum Client {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Client {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            Client::Tcp(s) => s.write_all(line.as_bytes()),
+            #[cfg(unix)]
+            Client::Unix(s) => s.write_all(line.as_bytes()),
+        }
+    }
+}
+
+/// Events kept for replay to late subscribers, plus the live subscribers
+#[derive(Default)]
+struct State {
+    clients: Vec<Client>,
+    start: Option<String>,
+    last_progress: Option<String>,
+    groups: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl State {
+    /// Send a line to every subscriber, dropping those that fail
+    fn broadcast(&mut self, line: &str) {
+        self.clients.retain_mut(|c| c.send(line).is_ok());
+    }
+}
+
+/// Local socket that publishes progress events of one scan
+pub struct ProgressSocket {
+    state: Arc<Mutex<State>>,
+    throttle: Throttle,
+    stop: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+    #[cfg(unix)]
+    socket_path: Option<PathBuf>,
+}
+
+impl ProgressSocket {
+    /// Start listening for subscribers on `address`
+    pub fn bind(address: &str) -> Result<Self, String> {
+        let listener = bind_listener(address)?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        #[cfg(unix)]
+        let socket_path = match &listener {
+            Listener::Unix(_) => Some(PathBuf::from(address)),
+            Listener::Tcp(_) => None,
+        };
+
+        let acceptor = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || accept_loop(listener, state, stop))
+        };
+
+        Ok(Self {
+            state,
+            throttle: Throttle::new(PROGRESS_INTERVAL),
+            stop,
+            acceptor: Some(acceptor),
+            #[cfg(unix)]
+            socket_path,
+        })
+    }
+
+    /// Publish a discovered duplicate group
+    pub fn publish_group(&self, group: &DuplicateGroup) {
+        let line = format!("{{\"event\":\"group\",{}}}\n", group_fields(group));
+
+        let mut state = self.lock();
+        state.broadcast(&line);
+        state.groups.push(line);
+    }
+
+    /// Publish the final `done` event and close the socket
+    pub fn finish(self, groups: usize) {
+        self.lock()
+            .broadcast(&format!("{{\"event\":\"done\",\"groups\":{}}}\n", groups));
+    }
+
+    /// Publish an `error` event and close the socket
+    pub fn fail(self, message: &str) {
+        self.lock().broadcast(&format!(
+            "{{\"event\":\"error\",\"message\":{}}}\n",
+            json_str(message)
+        ));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Progress for ProgressSocket {
+    fn started(&self, total: usize) {
+        let line = format!("{{\"event\":\"start\",\"total\":{}}}\n", total);
+        let mut state = self.lock();
+        state.broadcast(&line);
+        state.start = Some(line);
+    }
+
+    fn file_done(&self, done: usize, total: usize, path: &Path) {
+        if !self.throttle.due(done, total) {
+            return;
+        }
+
+        let line = format!(
+            "{{\"event\":\"progress\",\"done\":{},\"total\":{},\"path\":{}}}\n",
+            done,
+            total,
+            json_path(path)
+        );
+        let mut state = self.lock();
+        state.broadcast(&line);
+        state.last_progress = Some(line);
+    }
+
+    fn warning(&self, warning: &Warning) {
+        let line = format!(
+            "{{\"event\":\"warning\",\"kind\":{},\"path\":{},\"message\":{}}}\n",
+            json_str(warning.kind.name()),
+            json_path(&warning.path),
+            json_str(&warning.message)
+        );
+        let mut state = self.lock();
+        state.broadcast(&line);
+        state.warnings.push(line);
+    }
+}
+
+impl Drop for ProgressSocket {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+        #[cfg(unix)]
+        if let Some(path) = &self.socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn bind_listener(address: &str) -> Result<Listener, String> {
+    let bind_err = |e: io::Error| format!("Failed to bind progress socket {}: {}", address, e);
+
+    if let Ok(addr) = address.parse::<SocketAddr>() {
+        if !addr.ip().is_loopback() {
+            return Err(format!(
+                "Progress socket must use a loopback address: {}",
+                address
+            ));
+        }
+        let listener = TcpListener::bind(addr).map_err(bind_err)?;
+        listener.set_nonblocking(true).map_err(bind_err)?;
+        return Ok(Listener::Tcp(listener));
+    }
+
+    #[cfg(unix)]
+    {
+        let listener = match UnixListener::bind(address) {
+            Ok(listener) => listener,
+            // A socket file nobody is listening on is left over from a crash
+            Err(e)
+                if e.kind() == io::ErrorKind::AddrInUse
+                    && UnixStream::connect(address).is_err() =>
+            {
+                std::fs::remove_file(address).map_err(bind_err)?;
+                UnixListener::bind(address).map_err(bind_err)?
+            }
+            Err(e) => return Err(bind_err(e)),
+        };
+        listener.set_nonblocking(true).map_err(bind_err)?;
+        Ok(Listener::Unix(listener))
+    }
+
+    #[cfg(not(unix))]
+    Err(format!(
+        "Progress socket address must be a loopback host:port: {}",
+        address
+    ))
+}
+
+fn accept_loop(listener: Listener, state: Arc<Mutex<State>>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        let accepted = match &listener {
+            Listener::Tcp(l) => l.accept().and_then(|(s, _)| {
+                s.set_nonblocking(false)?;
+                s.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+                Ok(Client::Tcp(s))
+            }),
+            #[cfg(unix)]
+            Listener::Unix(l) => l.accept().and_then(|(s, _)| {
+                s.set_nonblocking(false)?;
+                s.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+                Ok(Client::Unix(s))
+            }),
+        };
+
+        match accepted {
+            Ok(mut client) => {
+                let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+                let replay = state
+                    .start
+                    .iter()
+                    .chain(state.last_progress.iter())
+                    .chain(state.groups.iter())
+                    .chain(state.warnings.iter())
+                    .try_for_each(|line| client.send(line));
+                if replay.is_ok() {
+                    state.clients.push(client);
+                }
+            }
+            Err(_) => thread::sleep(ACCEPT_POLL_INTERVAL),
+        }
+    }
+}
+
+/// JSON object members describing `group` (without the braces)
+pub(crate) fn group_fields(group: &DuplicateGroup) -> String {
+    let sidecars: Vec<String> = group
+        .sidecars
+        .iter()
+        .map(|(path, files)| format!("{}:{}", json_path(path), json_list(files)))
+        .collect();
+    let content_type = group
+        .content_type
+        .map_or("null".to_string(), |t| json_str(&t.to_string()));
+    let quality = group.quality();
+    let max_distance = quality
+        .as_ref()
+        .map_or("null".to_string(), |q| q.max_distance.to_string());
+    let mean_distance = quality
+        .as_ref()
+        .map_or("null".to_string(), |q| q.mean_distance.to_string());
+    format!(
+        "\"key\":{},\"hash\":{},\"best\":{},\"paths\":{},\"sidecars\":{{{}}},\"content_type\":{},\
+         \"max_distance\":{},\"mean_distance\":{}",
+        json_str(&group.key()),
+        json_str(&group.hash),
+        json_path(&group.best_path),
+        json_list(&group.paths),
+        sidecars.join(","),
+        content_type,
+        max_distance,
+        mean_distance
+    )
+}
+
+/// JSON string literal for `s`
+pub(crate) fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    push_escaped(&mut out, s);
+    out.push('"');
+    out
+}
+
+/// JSON string literal for `path`
+///
+/// Bytes that aren't valid UTF-8 are written as the lone surrogate escapes
+/// `\udc80` to `\udcff`, as Python's `surrogateescape` decodes them, so
+/// `json::Json::as_path` and `os.fsencode` both get the exact path back.
+pub(crate) fn json_path(path: &Path) -> String {
+    let bytes = path_bytes(path);
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for chunk in bytes.utf8_chunks() {
+        push_escaped(&mut out, chunk.valid());
+        for &b in chunk.invalid() {
+            out.push_str(&format!("\\u{:04x}", 0xdc00 + b as u32));
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn push_escaped(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+}
+
+/// JSON array of paths
+fn json_list(items: &[PathBuf]) -> String {
+    let items: Vec<String> = items.iter().map(|p| json_path(p)).collect();
+    format!("[{}]", items.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_json_str() {
+        assert_eq!(json_str("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_json_path_keeps_non_utf8_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"caf\xe9 \"1\".jpg"));
+        let text = json_path(path);
+        assert_eq!(text, "\"caf\\udce9 \\\"1\\\".jpg\"");
+        let parsed = crate::json::parse(&text).unwrap();
+        assert_eq!(parsed.as_str(), None);
+        assert_eq!(parsed.as_path().as_deref(), Some(path));
+    }
+
+    #[test]
+    fn test_rejects_non_loopback() {
+        assert!(ProgressSocket::bind("0.0.0.0:0").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_late_subscriber_gets_replay() {
+        let path = std::env::temp_dir().join(format!("phash_rs_ipc_{}.sock", std::process::id()));
+        let address = path.to_string_lossy().into_owned();
+        let socket = ProgressSocket::bind(&address).unwrap();
+        socket.started(2);
+        socket.file_done(1, 2, Path::new("a.jpg"));
+
+        let client = UnixStream::connect(&path).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut lines = BufReader::new(client).lines();
+        assert_eq!(
+            lines.next().unwrap().unwrap(),
+            "{\"event\":\"start\",\"total\":2}"
+        );
+        assert_eq!(
+            lines.next().unwrap().unwrap(),
+            "{\"event\":\"progress\",\"done\":1,\"total\":2,\"path\":\"a.jpg\"}"
+        );
+
+        // Final progress is never rate-limited
+        socket.file_done(2, 2, Path::new("b.jpg"));
+        socket.finish(0);
+        assert!(lines.next().unwrap().unwrap().contains("\"done\":2"));
+        assert_eq!(
+            lines.next().unwrap().unwrap(),
+            "{\"event\":\"done\",\"groups\":0}"
+        );
+        assert!(!path.exists());
+    }
+}
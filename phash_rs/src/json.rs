@@ -0,0 +1,346 @@
+//! JSON Reader Module
+//!
+//! A small parser for the JSON files this crate writes itself (exported
+//! scan results). Output is written directly with the helpers in `ipc`.
+//!
+//! Strings are kept as bytes: `ipc::json_path` writes path bytes that
+//! aren't valid UTF-8 as the lone surrogate escapes `\udc80` to `\udcff`,
+//! and they decode back to those bytes here.
+
+use crate::paths::path_from_bytes;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Deepest nesting of arrays and objects `parse` accepts, so hostile input
+/// cannot exhaust the stack
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON value
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    /// The number as written, so integers keep every digit
+    Number(String),
+    /// Decoded bytes, UTF-8 unless the string holds escaped path bytes
+    String(Vec<u8>),
+    Array(Vec<Json>),
+    Object(BTreeMap<Vec<u8>, Json>),
+}
+
+impl Json {
+    /// Member `key` of an object; `None` for missing keys and non-objects
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    /// String value; `None` for strings holding escaped path bytes
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => std::str::from_utf8(s).ok(),
+            _ => None,
+        }
+    }
+
+    /// String value as written by `ipc::json_path`
+    pub(crate) fn as_path(&self) -> Option<PathBuf> {
+        match self {
+            Json::String(s) => Some(path_from_bytes(s.clone())),
+            _ => None,
+        }
+    }
+
+    /// Non-negative integer value, exact over the whole `u64` range
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Members by key bytes; path keys read back with `path_from_bytes`
+    pub(crate) fn as_object(&self) -> Option<&BTreeMap<Vec<u8>, Json>> {
+        match self {
+            Json::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// Parse one JSON document
+pub(crate) fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.text.len() {
+        return Err(parser.error("Trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("Expected '{}'", c as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if !self.text[self.pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("Invalid literal"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Json, String>) -> Result<Json, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("Nesting too deep"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut members = BTreeMap::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("Expected a key"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.insert(key, self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(c))
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.pos])
+            .ok()
+            .filter(|s| s.parse::<f64>().is_ok())
+            .map(|s| Json::Number(s.to_string()))
+            .ok_or_else(|| self.error("Invalid number"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .filter(|d| d.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("Invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, String> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let Some(&c) = self.text.get(self.pos) else {
+                return Err(self.error("Unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.text.get(self.pos) else {
+                        return Err(self.error("Unterminated string"));
+                    };
+                    self.pos += 1;
+                    let decoded = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // A surrogate pair encodes one character
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("Invalid surrogate pair"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            } else if (0xdc80..0xdd00).contains(&code) {
+                                // A path byte that isn't valid UTF-8
+                                out.push((code - 0xdc00) as u8);
+                                continue;
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error("Invalid \\u escape"))?
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(decoded.encode_utf8(&mut buf).as_bytes());
+                }
+                c => out.push(c),
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::json_str;
+
+    #[test]
+    fn test_parse() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "b": {"c": "x\"é😀"}} "#).unwrap();
+        assert_eq!(
+            value.get("a").and_then(Json::as_array).unwrap(),
+            &[
+                Json::Number("1".to_string()),
+                Json::Number("-2.5e1".to_string()),
+                Json::Bool(true),
+                Json::Null
+            ]
+        );
+        assert_eq!(
+            value
+                .get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Json::as_str),
+            Some("x\"é😀")
+        );
+        let tricky = "tab\there \\ \"quoted\" \u{1}";
+        assert_eq!(parse(&json_str(tricky)).unwrap().as_str(), Some(tricky));
+        for bad in [
+            "",
+            "{",
+            "[1,]",
+            "{\"a\" 1}",
+            "\"open",
+            "1 2",
+            "tru",
+            "\"\\u+123\"",
+            "\"\\ud800\"",
+            "\"\\udc12\"",
+        ] {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+
+        // Integers past 2^53 stay exact; negatives and fractions are not u64
+        let max = parse(&u64::MAX.to_string()).unwrap();
+        assert_eq!(max.as_u64(), Some(u64::MAX));
+        assert_eq!(
+            parse("9007199254740993").unwrap().as_u64(),
+            Some((1 << 53) + 1)
+        );
+        assert_eq!(parse("-1").unwrap().as_u64(), None);
+        assert_eq!(parse("1.5").unwrap().as_u64(), None);
+
+        // Deep nesting is an error, not a stack overflow
+        let deep = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+        assert!(parse(&deep).is_ok());
+        let deeper = "[".repeat(100_000) + &"]".repeat(100_000);
+        assert!(parse(&deeper).unwrap_err().contains("Nesting too deep"));
+    }
+}
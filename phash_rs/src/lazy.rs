@@ -0,0 +1,193 @@
+//! Lazy Hashing Module
+//!
+//! `hash_paths` takes a slice, so every path is collected before the first
+//! file is read and every result is held until the last one is done.
+//! `hash_iter` pulls paths from any iterator as it goes and yields each
+//! hash as soon as it and the ones before it are ready, with a bounded
+//! number of files hashed at a time, so it composes with other iterator
+//! code (a directory walker, a database cursor) in constant memory.
+
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::preprocess::HashOptions;
+use crate::threads::{spawn, spawn_threads};
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+
+/// Hashes one file; `ImageHash::from_path_with` outside of tests
+type HashFn = fn(&Path, HashAlgorithm, usize, &HashOptions) -> Result<ImageHash, HashError>;
+
+/// Hashes of paths from an iterator, in input order; see `hash_iter`
+pub struct HashIter<I> {
+    paths: I,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    options: Arc<HashOptions>,
+    hash: HashFn,
+    in_flight: usize,
+    /// Files handed to the pool, oldest first, with where their result
+    /// arrives
+    pending: VecDeque<(PathBuf, mpsc::Receiver<Result<ImageHash, HashError>>)>,
+}
+
+/// Hash the paths of `paths` lazily, in parallel, yielding
+/// `(path, result)` in input order
+///
+/// Nothing is read until the first call to `next`. From then on up to
+/// `in_flight` files (twice the pool's threads by default) are being
+/// hashed at a time on the pool the batch functions use; the next path is
+/// pulled from `paths` only when a result is taken. Dropping the iterator
+/// lets files already handed off finish and discards their results.
+/// Waiting on results blocks, so drive it from outside that pool.
+pub fn hash_iter<I>(paths: I, algorithm: HashAlgorithm, hash_size: usize) -> HashIter<I::IntoIter>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    HashIter {
+        paths: paths.into_iter(),
+        algorithm,
+        hash_size,
+        options: Arc::new(HashOptions::default()),
+        hash: |path, algorithm, hash_size, options| {
+            ImageHash::from_path_with(path, algorithm, hash_size, options)
+        },
+        in_flight: 2 * spawn_threads(),
+        pending: VecDeque::new(),
+    }
+}
+
+impl<I> HashIter<I> {
+    /// Hash at most `files` files at a time (at least one)
+    pub fn in_flight(mut self, files: usize) -> Self {
+        self.in_flight = files.max(1);
+        self
+    }
+
+    /// Preprocess each image with `options` before hashing
+    pub fn options(mut self, options: HashOptions) -> Self {
+        self.options = Arc::new(options);
+        self
+    }
+}
+
+impl<I: Iterator<Item = PathBuf>> Iterator for HashIter<I> {
+    type Item = (PathBuf, Result<ImageHash, HashError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.len() < self.in_flight {
+            let Some(path) = self.paths.next() else {
+                break;
+            };
+            let (sender, receiver) = mpsc::sync_channel(1);
+            let (task_path, options) = (path.clone(), self.options.clone());
+            let (algorithm, hash_size, hash) = (self.algorithm, self.hash_size, self.hash);
+            spawn(move || {
+                // A panic would abort the process on the pool; report it
+                // as this file's error instead
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    hash(&task_path, algorithm, hash_size, &options)
+                }))
+                .unwrap_or_else(|panic| {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .copied()
+                        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                        .unwrap_or("unknown panic");
+                    Err(HashError::Decode(format!(
+                        "hashing {} panicked: {}",
+                        task_path.display(),
+                        message
+                    )))
+                });
+                // The receiver is gone if the iterator was dropped
+                let _ = sender.send(result);
+            });
+            self.pending.push_back((path, receiver));
+        }
+
+        let (path, receiver) = self.pending.pop_front()?;
+        let result = receiver.recv().unwrap_or_else(|_| {
+            Err(HashError::Decode(format!(
+                "hashing {} stopped without a result",
+                path.display()
+            )))
+        });
+        Some((path, result))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.paths.size_hint();
+        let pending = self.pending.len();
+        (
+            low.saturating_add(pending),
+            high.and_then(|high| high.checked_add(pending)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_hash_iter_is_lazy_and_ordered() {
+        let root = std::env::temp_dir().join(format!("phash_rs_lazy_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut paths = Vec::new();
+        for i in 0..6u32 {
+            let path = root.join(format!("{}.png", i));
+            image::GrayImage::from_fn(32, 32, |x, y| image::Luma([((x * i + y * 7) % 256) as u8]))
+                .save(&path)
+                .unwrap();
+            paths.push(path);
+        }
+        paths.insert(2, root.join("missing.png"));
+
+        let pulled = AtomicUsize::new(0);
+        let source = paths.clone().into_iter().inspect(|_| {
+            pulled.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut hashes = hash_iter(source, HashAlgorithm::PHash, 8).in_flight(3);
+        assert_eq!(pulled.load(Ordering::SeqCst), 0);
+        let first = hashes.next().unwrap();
+        assert_eq!(pulled.load(Ordering::SeqCst), 3);
+        assert_eq!(first.0, paths[0]);
+
+        let rest: Vec<_> = hashes.collect();
+        assert_eq!(rest.len(), paths.len() - 1);
+        for ((path, result), expected) in rest.iter().zip(&paths[1..]) {
+            assert_eq!(path, expected);
+            match result {
+                Ok(hash) => assert_eq!(
+                    hash,
+                    &ImageHash::from_path(path, HashAlgorithm::PHash, 8).unwrap()
+                ),
+                Err(_) => assert!(path.ends_with("missing.png")),
+            }
+        }
+        assert!(matches!(rest[1].1, Err(HashError::Io(_))));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_panic_becomes_error() {
+        let paths = ["a.png", "boom.png", "c.png"].map(PathBuf::from);
+        let mut hashes = hash_iter(paths.clone(), HashAlgorithm::PHash, 8);
+        hashes.hash = |path, _, _, _| {
+            if path.ends_with("boom.png") {
+                panic!("corrupt input");
+            }
+            Ok(ImageHash::from_u64(0))
+        };
+        let results: Vec<_> = hashes.collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].1.is_ok() && results[2].1.is_ok());
+        match &results[1].1 {
+            Err(HashError::Decode(message)) => assert!(message.contains("corrupt input")),
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+    }
+}
@@ -7,149 +7,193 @@
 //! - Minor edits or compression artifacts
 //!
 //! Uses DCT-based perceptual hashing for robustness.
-
-#![allow(clippy::useless_conversion)]
-
+//!
+//! The crate is usable from Rust directly; the Python module is only built
+//! with the `python` feature. The core API is [`ImageHash`] (compute,
+//! parse and compare hashes), [`HashAlgorithm`] and the duplicate finders:
+//! [`find_duplicates_with`] for a list of paths, [`DuplicateFinder`] for
+//! paths arriving in chunks and [`dedupe`] for a whole cleanup run.
+//!
+//! ```no_run
+//! use phash_rs::{
+//!     find_duplicates_with, HashAlgorithm, HashOptions, ImageHash, DEFAULT_HASH_SIZE,
+//! };
+//! use std::path::PathBuf;
+//!
+//! let a = ImageHash::from_path("a.jpg", HashAlgorithm::PHash, DEFAULT_HASH_SIZE)?;
+//! let b = ImageHash::from_path("b.jpg", HashAlgorithm::PHash, DEFAULT_HASH_SIZE)?;
+//! println!("{} bits differ", a.distance(&b));
+//!
+//! let paths = vec![PathBuf::from("a.jpg"), PathBuf::from("b.jpg")];
+//! let (groups, failures) = find_duplicates_with(
+//!     &paths,
+//!     HashAlgorithm::PHash,
+//!     DEFAULT_HASH_SIZE,
+//!     10,
+//!     false,
+//!     &HashOptions::default(),
+//!     None,
+//!     None,
+//! )?;
+//! for group in groups.iter().filter(|g| g.paths.len() > 1) {
+//!     println!("keep {}, duplicates: {:?}", group.best_path.display(), group.paths);
+//! }
+//! eprintln!("{} files could not be hashed", failures.len());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod actions;
+#[cfg(feature = "tokio")]
+mod async_api;
+mod backend;
+mod base64;
+mod bench;
+mod best;
+mod blocklist;
+mod burst;
+mod cache;
+mod calibrate;
+mod capabilities;
+mod checkpoint;
+mod compare;
+mod content;
+mod decode;
+mod distance;
+mod documents;
 mod duplicate;
+#[cfg(any(feature = "raw", feature = "pdf"))]
+mod embedded;
+mod error;
+mod estimate;
+mod grouping;
 mod hash;
-
-use pyo3::conversion::ToPyObject;
-use pyo3::prelude::*;
-use std::collections::HashMap;
-
-pub use duplicate::{find_duplicates, find_duplicates_parallel, DuplicateGroup};
-pub use hash::{HashAlgorithm, ImageHash};
-
-/// Compute the perceptual hash (pHash) of an image file.
-///
-/// Args:
-///     path: Path to the image file
-///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
-///
-/// Returns:
-///     Hex string representation of the hash
-#[pyfunction]
-#[pyo3(signature = (path, hash_size = 8))]
-fn compute_hash(path: &str, hash_size: usize) -> PyResult<String> {
-    let hash = ImageHash::from_path(path, HashAlgorithm::PHash, hash_size)
-        .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
-
-    Ok(hash.to_hex())
-}
-
-/// Compute the Hamming distance between two hash strings.
-///
-/// Args:
-///     hash1: First hash as hex string
-///     hash2: Second hash as hex string
-///
-/// Returns:
-///     Number of differing bits (0 = identical, higher = more different)
-#[pyfunction]
-fn hamming_distance(hash1: &str, hash2: &str) -> PyResult<u32> {
-    let h1 = ImageHash::from_hex(hash1)
-        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
-    let h2 = ImageHash::from_hex(hash2)
-        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
-
-    Ok(h1.distance(&h2))
-}
-
-/// Check if two images are perceptually similar using pHash.
-///
-/// Args:
-///     path1: Path to first image
-///     path2: Path to second image
-///     threshold: Maximum Hamming distance to consider similar (default: 10)
-///
-/// Returns:
-///     True if images are similar, False otherwise
-#[pyfunction]
-#[pyo3(signature = (path1, path2, threshold = 10))]
-fn are_similar(path1: &str, path2: &str, threshold: u32) -> PyResult<bool> {
-    let hash1 = ImageHash::from_path(path1, HashAlgorithm::PHash, 8)
-        .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
-    let hash2 = ImageHash::from_path(path2, HashAlgorithm::PHash, 8)
-        .map_err(|e| pyo3::exceptions::PyIOError::new_err(e.to_string()))?;
-
-    Ok(hash1.distance(&hash2) <= threshold)
-}
-
-/// Find duplicate images in a list of file paths using pHash.
-///
-/// Args:
-///     paths: List of image file paths to check
-///     threshold: Maximum Hamming distance for duplicates (default: 10)
-///
-/// Returns:
-///     List of duplicate groups, each containing:
-///     - "paths": List of file paths in this duplicate group
-///     - "hash": The representative hash for this group
-///     - "best": Path to the highest resolution image in the group
-#[pyfunction]
-#[pyo3(signature = (paths, threshold = 10))]
-fn find_duplicate_images(
-    paths: Vec<String>,
-    threshold: u32,
-) -> PyResult<Vec<HashMap<String, PyObject>>> {
-    let groups = find_duplicates_parallel(&paths, HashAlgorithm::PHash, threshold)
-        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))?;
-
-    Python::with_gil(|py| {
-        let result: Vec<HashMap<String, PyObject>> = groups
-            .into_iter()
-            .filter(|g| g.paths.len() > 1) // Only return actual duplicates
-            .map(|g| {
-                let mut map = HashMap::new();
-                map.insert("paths".to_string(), g.paths.clone().to_object(py));
-                map.insert("hash".to_string(), g.hash.to_object(py));
-                map.insert("best".to_string(), g.best_path.to_object(py));
-                map
-            })
-            .collect();
-        Ok(result)
-    })
-}
-
-/// Compute pHashes for multiple images in parallel.
-///
-/// Args:
-///     paths: List of image file paths
-///
-/// Returns:
-///     Dictionary mapping file paths to their hash strings.
-///     Failed images are excluded from the result.
-#[pyfunction]
-#[pyo3(signature = (paths))]
-fn compute_hashes_parallel(paths: Vec<String>) -> PyResult<HashMap<String, String>> {
-    use rayon::prelude::*;
-
-    let results: HashMap<String, String> = paths
-        .par_iter()
-        .filter_map(|path| {
-            ImageHash::from_path(path, HashAlgorithm::PHash, 8)
-                .ok()
-                .map(|h| (path.clone(), h.to_hex()))
-        })
-        .collect();
-
-    Ok(results)
-}
-
-/// Python module definition
-#[pymodule]
-fn phash_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(compute_hash, m)?)?;
-    m.add_function(wrap_pyfunction!(hamming_distance, m)?)?;
-    m.add_function(wrap_pyfunction!(are_similar, m)?)?;
-    m.add_function(wrap_pyfunction!(find_duplicate_images, m)?)?;
-    m.add_function(wrap_pyfunction!(compute_hashes_parallel, m)?)?;
-
-    // Add constants for recommended thresholds
-    m.add("THRESHOLD_IDENTICAL", 0)?;
-    m.add("THRESHOLD_VERY_SIMILAR", 5)?;
-    m.add("THRESHOLD_SIMILAR", 10)?;
-    m.add("THRESHOLD_SOMEWHAT_SIMILAR", 15)?;
-
-    Ok(())
-}
+mod index;
+mod index_file;
+mod interop;
+mod ipc;
+mod json;
+mod lazy;
+mod libphash;
+mod locality;
+mod logging;
+mod metadata;
+mod orientation;
+mod paths;
+mod pipeline;
+mod preprocess;
+mod progress;
+#[cfg(feature = "python")]
+mod python;
+mod quality;
+mod quarantine;
+mod report;
+mod results;
+mod review;
+mod rng;
+mod robustness;
+mod rule;
+mod scan;
+mod search;
+mod sidecar;
+mod similarity;
+mod stream;
+#[cfg(test)]
+mod stubs;
+mod sync;
+mod threads;
+mod tiles;
+mod union_find;
+mod video;
+mod warnings;
+mod watch;
+
+pub use actions::{apply_dedup, ActionRecord, ActionReport, DedupAction, DedupOptions};
+#[cfg(feature = "tokio")]
+pub use async_api::find_duplicates_async;
+pub use backend::{
+    active_backend, gpu_available, hash_backend, phash_batch, set_hash_backend, HashBackend,
+    GPU_BATCH_SIZE,
+};
+pub use bench::{run_benchmark, synthetic_corpus, BenchReport, CorpusSpec, Distribution};
+pub use best::{BestBy, DEFAULT_FORMAT_PRIORITY};
+pub use blocklist::{Blocklist, BlocklistEntry, BlocklistMatch, TRANSFORMS};
+pub use burst::{cluster_similar, BurstCluster, BurstShot};
+pub use cache::{hash_paths, hash_paths_multi, hash_paths_with, CachedHash, FileStamp, HashCache};
+pub use calibrate::{calibrate_threshold, Calibration, CalibrationPoint};
+pub use capabilities::{capabilities, features, Capabilities};
+pub use checkpoint::{find_duplicates_checkpointed, resume_duplicates, CheckpointSchedule};
+pub use compare::{compare_images, compare_paths, Comparison, HEATMAP_SIZE};
+pub use content::{
+    classify_image, classify_path, classify_paths, ContentFeatures, ContentType, HashPreset,
+};
+pub use decode::{
+    configure_decoder, decoder_config, image_dimensions, open_image, DecodeBackend, DecodeHook,
+    DecodeLimits, DecoderConfig, RAW_EXTENSIONS,
+};
+pub use distance::{
+    distance_matrix, hex_distances, hex_within, pairwise_distances, write_pairwise_distances,
+    BitWeights, PairDistance, PairFilter,
+};
+pub use documents::{match_documents, DocumentMatch, DocumentPage};
+#[cfg(feature = "exact")]
+pub use duplicate::exact_duplicates;
+pub use duplicate::{
+    find_duplicates, find_duplicates_by_content, find_duplicates_by_rule,
+    find_duplicates_hierarchical, find_duplicates_in_hashes, find_duplicates_incremental,
+    find_duplicates_parallel, find_duplicates_using, find_duplicates_weighted,
+    find_duplicates_with, group_membership, with_exact_prepass, DuplicateGroup, GroupMembership,
+    GroupQuality, HashEntry,
+};
+pub use error::{HashError, HashFailures};
+pub use estimate::{estimate_paths, FormatEstimate, ScanEstimate, Throughput};
+pub use grouping::{regroup, Grouping};
+pub use hash::{
+    filter_name, parse_filter, CropResistantHash, FrameSampling, HashAlgorithm, Hasher, ImageHash,
+    PHashConfig, COARSE_HASH_SIZE, DCT_SIZE, DEFAULT_BIT_ERROR_RATE, DEFAULT_HASH_SIZE,
+    DIHEDRAL_TRANSFORMS, MAX_DCT_SIZE, MAX_INDEX_PROBES,
+};
+pub use index::{BkTree, MultiIndex};
+pub use index_file::{IndexFile, IndexedFile};
+pub use interop::BitOrder;
+pub use ipc::ProgressSocket;
+pub use lazy::{hash_iter, HashIter};
+pub use libphash::{
+    DEFAULT_RADIAL_THRESHOLD, MH_HASH_SIZE, RADIAL_DEFAULT_COEFFS, RADIAL_PROJECTIONS,
+};
+pub use locality::{read_order, set_read_order, ReadOrder, DEFAULT_READAHEAD};
+pub use logging::{init_logging, LogLevel, LogRecord, LogSink};
+pub use metadata::FileMetadata;
+pub use orientation::{apply_orientation, exif_capture_time, exif_orientation};
+pub use paths::relative_path;
+pub use pipeline::{dedupe, DedupeProfile, DedupeReport, Verify, DEFAULT_QUARANTINE_DIR};
+pub use preprocess::HashOptions;
+pub use progress::{CancelToken, Fanout, Progress};
+pub use quality::{jpeg_quality, score_decoded, score_image, sharpness, QualityScore};
+pub use quarantine::{read_manifest, restore, ManifestEntry, RestoreReport, MANIFEST_NAME};
+pub use report::{
+    generate_report, render_report, thumbnail_data_uri, ReportFormat, ReportOptions,
+    DEFAULT_THUMBNAIL_SIZE, REVIEW_THUMBNAIL_SIZE,
+};
+pub use results::{
+    export_results, import_results, parse_results, render_results, ResultsFormat, ScanMetadata,
+    ScanResults,
+};
+pub use review::{group_key, Annotation, ReviewState, ReviewStore};
+pub use rng::{SeededRng, DEFAULT_SEED};
+pub use robustness::{robustness_of_image, robustness_report, Perturbation, RobustnessReport};
+pub use rule::{Combine, Criterion, MatchRule, ENSEMBLE_ALGORITHMS};
+pub use scan::{scan_directory, scan_directory_with, ScanOptions};
+pub use search::{
+    find_similar, find_similar_paths, match_collections, CollectionMatch, SimilarMatch,
+};
+pub use sidecar::{find_sidecars, is_sidecar, SIDECAR_EXTENSIONS};
+pub use similarity::{thumbnail_similarity, verify_groups, DEFAULT_MIN_SIMILARITY};
+pub use stream::DuplicateFinder;
+pub use sync::{apply_deltas, parse_public_key, pull_deltas, HashListDelta, SyncManifest};
+pub use threads::{num_threads, set_num_threads, with_threads};
+pub use tiles::{Region, RegionMatch, Tile, TileOptions, TiledHash, DEFAULT_TILE_SCALES};
+pub use video::{
+    find_video_duplicates, FfmpegFrames, FrameProvider, VideoHash, DEFAULT_VIDEO_FRAMES,
+};
+pub use warnings::{Warning, WarningCollector, WarningKind};
+pub use watch::{DuplicateWatcher, WATCH_SETTLE};
@@ -0,0 +1,343 @@
+//! Marr-Hildreth and radial variance hashes as computed by the C libphash
+//!
+//! Both follow `ph_mh_imagehash` and `ph_image_digest` step by step with
+//! libphash's default parameters, so hashes and thresholds from existing
+//! libphash databases carry over. Decoding, blurring and resampling are the
+//! `image` crate's rather than CImg's, so a hash can differ from libphash's
+//! in a few bits or coefficient steps for the same file.
+
+use image::{imageops, imageops::FilterType, DynamicImage, GrayImage};
+use std::f64::consts::{PI, SQRT_2};
+
+/// Hash size of a Marr-Hildreth hash: 576 bits, libphash's 72 bytes
+pub const MH_HASH_SIZE: usize = 24;
+
+/// Coefficients of a radial variance hash in libphash's digest
+pub const RADIAL_DEFAULT_COEFFS: usize = 40;
+
+/// Radon projection angles of a radial variance hash
+pub const RADIAL_PROJECTIONS: usize = 180;
+
+/// Peak cross-correlation above which libphash calls two radial variance
+/// hashes a match
+pub const DEFAULT_RADIAL_THRESHOLD: f64 = 0.9;
+
+/// Side of the square the Marr-Hildreth response is computed on
+const MH_IMAGE_SIZE: u32 = 512;
+
+/// Side of the pixel blocks summed from the response
+const MH_BLOCK: usize = 16;
+
+/// Blocks per side; the last 16 pixels of the image are not covered
+const MH_BLOCKS: usize = 31;
+
+/// Marr-Hildreth wavelet scale and level (`alpha` and `lvl` in libphash)
+const MH_ALPHA: f64 = 2.0;
+const MH_LEVEL: f64 = 1.0;
+
+/// Sigma of the Gaussian blur both hashes start with
+const BLUR_SIGMA: f32 = 1.0;
+
+/// Luma as CImg's `RGBtoYCbCr` computes it, blurred with sigma 1
+fn blurred_luma(img: &DynamicImage) -> GrayImage {
+    let gray = if img.color().has_color() {
+        let rgb = img.to_rgb8();
+        GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+            let [r, g, b] = rgb.get_pixel(x, y).0.map(f64::from);
+            let luma = (66.0 * r + 129.0 * g + 25.0 * b + 128.0) / 256.0 + 16.0;
+            image::Luma([luma.clamp(0.0, 255.0) as u8])
+        })
+    } else {
+        img.to_luma8()
+    };
+    imageops::blur(&gray, BLUR_SIGMA)
+}
+
+/// Marr-Hildreth hash bytes (72, `MH_HASH_SIZE` squared bits)
+///
+/// 1. Blur the luma, resize to 512x512 and equalize its histogram
+/// 2. Correlate with a Marr-Hildreth (Mexican hat) kernel
+/// 3. Sum the normalized response over 31x31 blocks of 16x16 pixels
+/// 4. For every 3x3 group of blocks, 4 blocks apart, compare each block
+///    to the group mean, groups and blocks row by row
+pub(crate) fn marr_hildreth(img: &DynamicImage) -> Vec<u8> {
+    let side = MH_IMAGE_SIZE as usize;
+    let resized = imageops::resize(
+        &blurred_luma(img),
+        MH_IMAGE_SIZE,
+        MH_IMAGE_SIZE,
+        FilterType::CatmullRom,
+    );
+    let pixels = equalize(resized.as_raw());
+
+    let (kernel, radius) = mh_kernel();
+    let width = 2 * radius + 1;
+    let clamp = |v: isize| v.clamp(0, side as isize - 1) as usize;
+    let mut response = vec![0f64; side * side];
+    for y in 0..side {
+        for x in 0..side {
+            let mut sum = 0.0;
+            for ky in 0..width {
+                let row = clamp(y as isize + ky as isize - radius as isize) * side;
+                for kx in 0..width {
+                    let col = clamp(x as isize + kx as isize - radius as isize);
+                    sum += pixels[row + col] * kernel[ky * width + kx];
+                }
+            }
+            response[y * side + x] = sum;
+        }
+    }
+
+    let (min, max) = response
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+
+    let mut blocks = vec![0f64; MH_BLOCKS * MH_BLOCKS];
+    for (i, block) in blocks.iter_mut().enumerate() {
+        let (bx, by) = (i % MH_BLOCKS * MH_BLOCK, i / MH_BLOCKS * MH_BLOCK);
+        *block = (by..by + MH_BLOCK)
+            .flat_map(|y| (bx..bx + MH_BLOCK).map(move |x| y * side + x))
+            .map(|p| (response[p] - min) * scale)
+            .sum();
+    }
+
+    let mut bits = Vec::with_capacity(MH_HASH_SIZE * MH_HASH_SIZE);
+    for row in (0..MH_BLOCKS - 2).step_by(4) {
+        for col in (0..MH_BLOCKS - 2).step_by(4) {
+            let group: Vec<f64> = (row..row + 3)
+                .flat_map(|y| (col..col + 3).map(move |x| y * MH_BLOCKS + x))
+                .map(|b| blocks[b])
+                .collect();
+            let mean = group.iter().sum::<f64>() / group.len() as f64;
+            bits.extend(group.iter().map(|&v| v > mean));
+        }
+    }
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .fold(0u8, |byte, &bit| byte << 1 | u8::from(bit))
+        })
+        .collect()
+}
+
+/// The Marr-Hildreth kernel row-major, and its radius
+fn mh_kernel() -> (Vec<f64>, usize) {
+    let radius = (4.0 * MH_ALPHA.powf(MH_LEVEL)) as usize;
+    let width = 2 * radius + 1;
+    let scale = MH_ALPHA.powf(-MH_LEVEL);
+    let kernel = (0..width * width)
+        .map(|i| {
+            let x = scale * ((i % width) as f64 - radius as f64);
+            let y = scale * ((i / width) as f64 - radius as f64);
+            let a = x * x + y * y;
+            (2.0 - a) * (-a / 2.0).exp()
+        })
+        .collect();
+    (kernel, radius)
+}
+
+/// Histogram equalization over the value range, as CImg's `equalize(256)`
+fn equalize(pixels: &[u8]) -> Vec<f64> {
+    let min = pixels.iter().copied().min().unwrap_or(0) as usize;
+    let max = pixels.iter().copied().max().unwrap_or(0) as usize;
+    if max == min {
+        return pixels.iter().map(|&p| p as f64).collect();
+    }
+    let level = |p: u8| (p as usize - min) * 255 / (max - min);
+    let mut cumulative = [0usize; 256];
+    for &p in pixels {
+        cumulative[level(p)] += 1;
+    }
+    for i in 1..256 {
+        cumulative[i] += cumulative[i - 1];
+    }
+    let total = cumulative[255];
+    pixels
+        .iter()
+        .map(|&p| (min + (max - min) * cumulative[level(p)] / total) as f64)
+        .collect()
+}
+
+/// Radial variance digest of `coeffs` bytes
+///
+/// 1. Blur the luma
+/// 2. Sample `RADIAL_PROJECTIONS` lines through the image centre
+/// 3. Take the variance of the pixels on each line, standardized over all
+///    lines
+/// 4. Keep the first `coeffs` DCT coefficients of the variances, scaled
+///    to 0-255
+pub(crate) fn radial_variance(img: &DynamicImage, coeffs: usize) -> Vec<u8> {
+    let features = radial_features(&blurred_luma(img));
+    let n = features.len() as f64;
+    let dct: Vec<f64> = (0..coeffs)
+        .map(|k| {
+            let sum: f64 = features
+                .iter()
+                .enumerate()
+                .map(|(i, &r)| r * (PI * (2 * i + 1) as f64 * k as f64 / (2.0 * n)).cos())
+                .sum();
+            let norm = if k == 0 { 1.0 } else { SQRT_2 };
+            sum * norm / n.sqrt()
+        })
+        .collect();
+
+    // libphash starts both bounds at zero
+    let (min, max) = dct
+        .iter()
+        .fold((0f64, 0f64), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    if max <= min {
+        return vec![0; coeffs];
+    }
+    dct.iter()
+        .map(|&v| (255.0 * (v - min) / (max - min)) as u8)
+        .collect()
+}
+
+/// Standardized pixel variance along each radon projection line
+///
+/// Samples the lines the way `ph_radon_projections` does, including
+/// which pixels each line picks up, so the variances match libphash's.
+fn radial_features(gray: &GrayImage) -> Vec<f64> {
+    let n = RADIAL_PROJECTIONS;
+    let (width, height) = (gray.width() as i64, gray.height() as i64);
+    let d = width.max(height) as usize;
+    let round = |v: f64| (v + if v >= 0.0 { 0.5 } else { -0.5 }).floor() as i64;
+    let x_off = round(width as f64 / 2.0);
+    let y_off = round(height as f64 / 2.0);
+    let pixel = |x: i64, y: i64| gray.get_pixel(x as u32, y as u32).0[0];
+
+    // One column of `d` samples per angle; unsampled slots stay zero
+    let mut lines = vec![0u8; n * d];
+    let mut counts = vec![0usize; n];
+    let mut sample = |line: usize, slot: usize, value: u8| {
+        lines[line * d + slot] = value;
+        counts[line] += 1;
+    };
+
+    for k in 0..n / 4 + 1 {
+        let slope = (k as f64 * PI / n as f64).tan();
+        for x in 0..d as i64 {
+            let yd = round(slope * (x - x_off) as f64);
+            if (0..height).contains(&(yd + y_off)) && x < width {
+                sample(k, x as usize, pixel(x, yd + y_off));
+            }
+            if (0..width).contains(&(yd + x_off)) && k != n / 4 && x < height {
+                sample(n / 2 - k, x as usize, pixel(yd + x_off, x));
+            }
+        }
+    }
+    for (j, k) in (3 * n / 4..n).enumerate() {
+        let slope = (k as f64 * PI / n as f64).tan();
+        for x in 0..d as i64 {
+            let yd = round(slope * (x - x_off) as f64);
+            if (0..height).contains(&(yd + y_off)) && x < width {
+                sample(k, x as usize, pixel(x, yd + y_off));
+            }
+            if (0..width).contains(&(y_off - yd))
+                && (0..height).contains(&(2 * y_off - x))
+                && k != 3 * n / 4
+            {
+                sample(k - 2 * j, x as usize, pixel(y_off - yd, 2 * y_off - x));
+            }
+        }
+    }
+
+    let variances: Vec<f64> = lines
+        .chunks(d)
+        .zip(&counts)
+        .map(|(line, &count)| {
+            let count = count.max(1) as f64;
+            let sum: f64 = line.iter().map(|&p| p as f64).sum();
+            let sum_sqd: f64 = line.iter().map(|&p| (p as f64).powi(2)).sum();
+            sum_sqd / count - sum * sum / (count * count)
+        })
+        .collect();
+    let mean = variances.iter().sum::<f64>() / n as f64;
+    let sum_sqd: f64 = variances.iter().map(|v| v * v).sum();
+    let deviation = (sum_sqd / n as f64 - mean * mean).sqrt();
+    if deviation.is_nan() || deviation <= 0.0 {
+        return vec![0.0; n];
+    }
+    variances.iter().map(|v| (v - mean) / deviation).collect()
+}
+
+/// Largest Pearson correlation of `a` with any cyclic shift of `b`
+///
+/// libphash's `ph_crosscorr`: 1.0 for identical digests, and never below
+/// 0.0. Both digests must have the same length.
+pub(crate) fn peak_cross_correlation(a: &[u8], b: &[u8]) -> f64 {
+    let n = b.len();
+    let mean = |v: &[u8]| v.iter().map(|&c| c as f64).sum::<f64>() / n as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut peak = 0.0;
+    for shift in 0..n {
+        let (mut num, mut den_a, mut den_b) = (0.0, 0.0, 0.0);
+        for i in 0..n {
+            let x = a[i] as f64 - mean_a;
+            let y = b[(n + i - shift) % n] as f64 - mean_b;
+            num += x * y;
+            den_a += x * x;
+            den_b += y * y;
+        }
+        let r = num / (den_a * den_b).sqrt();
+        if r > peak {
+            peak = r;
+        }
+    }
+    peak
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+            let ring = ((x as f64 - 60.0).hypot(y as f64 - 40.0) / 6.0) as u8;
+            image::Rgb([ring.wrapping_mul(40), (x * 2) as u8, (y * 3) as u8])
+        }))
+    }
+
+    #[test]
+    fn test_mh_kernel_is_mexican_hat() {
+        let (kernel, radius) = mh_kernel();
+        assert_eq!(radius, 8);
+        let width = 2 * radius + 1;
+        assert_eq!(kernel[radius * width + radius], 2.0);
+        assert!(kernel[radius * width + radius + 3] < 0.0);
+    }
+
+    #[test]
+    fn test_marr_hildreth_has_libphash_length() {
+        let img = pattern(120, 80);
+        let hash = marr_hildreth(&img);
+        assert_eq!(hash.len(), 72);
+        assert_eq!(hash, marr_hildreth(&img));
+        assert!(hash.iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn test_radial_variance_survives_resize() {
+        let img = pattern(120, 80);
+        let digest = radial_variance(&img, RADIAL_DEFAULT_COEFFS);
+        assert_eq!(digest.len(), RADIAL_DEFAULT_COEFFS);
+        assert_eq!(peak_cross_correlation(&digest, &digest), 1.0);
+
+        let smaller = radial_variance(&img.resize_exact(90, 60, FilterType::Triangle), 40);
+        assert!(peak_cross_correlation(&digest, &smaller) > DEFAULT_RADIAL_THRESHOLD);
+
+        let flat = DynamicImage::ImageLuma8(GrayImage::new(50, 50));
+        assert_eq!(radial_variance(&flat, 40), vec![0; 40]);
+    }
+
+    #[test]
+    fn test_cross_correlation_finds_shift() {
+        let a: Vec<u8> = (0..40).map(|i| (i * 37 % 256) as u8).collect();
+        let mut b = a.clone();
+        b.rotate_right(7);
+        assert!((peak_cross_correlation(&a, &b) - 1.0).abs() < 1e-9);
+        assert!(peak_cross_correlation(&a, &[0; 40]) == 0.0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Read Locality Module
+//!
+//! By default files are hashed in whatever order the worker threads pick
+//! them up, which on spinning disks and tape-backed mounts turns into a
+//! seek per file. The sequential read order sorts work by on-disk locality
+//! and has one thread read files in that order a few files ahead of the
+//! decoders, which then find the data in the OS page cache.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{OnceLock, RwLock};
+
+/// Files read ahead of the decoders in sequential mode by default
+pub const DEFAULT_READAHEAD: usize = 8;
+
+/// How files are read while hashing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadOrder {
+    /// Worker threads open files in any order (fastest on SSDs)
+    #[default]
+    Parallel,
+    /// One thread reads files in locality order, at most `readahead`
+    /// files ahead of the decoders
+    Sequential { readahead: usize },
+}
+
+impl FromStr for ReadOrder {
+    type Err = String;
+
+    /// Parse "parallel", "sequential" or "sequential:<readahead>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.split_once(':') {
+            None if s == "parallel" => Ok(ReadOrder::Parallel),
+            None if s == "sequential" => Ok(ReadOrder::Sequential {
+                readahead: DEFAULT_READAHEAD,
+            }),
+            Some(("sequential", n)) => match n.parse() {
+                Ok(readahead) if readahead > 0 => Ok(ReadOrder::Sequential { readahead }),
+                _ => Err(format!("Invalid readahead: {}", n)),
+            },
+            _ => Err(format!("Unknown read order: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ReadOrder {
+    /// Canonical name, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadOrder::Parallel => write!(f, "parallel"),
+            ReadOrder::Sequential { readahead } => write!(f, "sequential:{}", readahead),
+        }
+    }
+}
+
+fn global() -> &'static RwLock<ReadOrder> {
+    static ORDER: OnceLock<RwLock<ReadOrder>> = OnceLock::new();
+    ORDER.get_or_init(|| RwLock::new(ReadOrder::default()))
+}
+
+/// Process-wide read order used by the hashing functions
+pub fn read_order() -> ReadOrder {
+    *global().read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Set the process-wide read order
+pub fn set_read_order(order: ReadOrder) {
+    *global().write().unwrap_or_else(|e| e.into_inner()) = order;
+}
+
+/// Indices into `paths` in on-disk locality order
+///
+/// Files are ordered by directory, then by inode number where the platform
+/// has one (filesystems tend to allocate inodes and data in creation
+/// order), then by name. Files that cannot be stat'ed sort by name within
+/// their directory.
+pub(crate) fn locality_order(paths: &[PathBuf]) -> Vec<usize> {
+    let mut keys: Vec<(&Path, u64, &OsStr, usize)> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            (dir, inode(path), name, i)
+        })
+        .collect();
+    keys.sort_unstable();
+    keys.into_iter().map(|(_, _, _, i)| i).collect()
+}
+
+#[cfg(unix)]
+fn inode(path: &Path) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).map_or(0, |m| m.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_path: &Path) -> u64 {
+    0
+}
+
+/// Read the whole file so later opens are served from the page cache
+///
+/// Errors are ignored; the decoder reports them when it opens the file.
+pub(crate) fn prefetch(path: &Path, buf: &mut [u8]) {
+    if let Ok(mut file) = File::open(path) {
+        while matches!(file.read(buf), Ok(n) if n > 0) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_locality_order() {
+        assert_eq!("parallel".parse(), Ok(ReadOrder::Parallel));
+        assert_eq!(
+            "Sequential".parse(),
+            Ok(ReadOrder::Sequential {
+                readahead: DEFAULT_READAHEAD
+            })
+        );
+        let order: ReadOrder = "sequential:32".parse().unwrap();
+        assert_eq!(order.to_string().parse(), Ok(order));
+        assert!("sequential:0".parse::<ReadOrder>().is_err());
+        assert!("random".parse::<ReadOrder>().is_err());
+
+        let paths: Vec<PathBuf> = ["/m/b/2.jpg", "/m/a/9.jpg", "/m/b/1.jpg", "/m/a/10.jpg"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(locality_order(&paths), vec![3, 1, 2, 0]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Logging Module
+//!
+//! The library reports its work through `tracing`: a span per hashing
+//! batch, a debug event per file with its decode-and-hash time, a warning
+//! per skipped or failed file, and grouping statistics. Without a
+//! subscriber the events cost next to nothing; when none is installed they
+//! also reach a `log` logger, if the application set one up.
+//!
+//! `init_logging` (`logging` feature) installs a minimal subscriber for
+//! applications that have none, like the command line tool and the Python
+//! bindings: each event becomes one line of text, written to stderr or
+//! handed to a callback, at a level that can be changed at any time.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[cfg(feature = "logging")]
+use std::io::{self, Write};
+#[cfg(feature = "logging")]
+use std::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "logging")]
+use std::sync::{OnceLock, RwLock};
+#[cfg(feature = "logging")]
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Verbosity of the library's log records, least verbose first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    /// Nothing is logged
+    Off,
+    /// Operations that failed as a whole
+    Error,
+    /// Files that were skipped or could not be hashed
+    Warn,
+    /// Batch summaries: files hashed, time taken, groups found
+    Info,
+    /// One record per file, with its hashing time
+    Debug,
+    /// Cache hits and other per-file detail
+    Trace,
+}
+
+impl LogLevel {
+    /// All levels, least verbose first
+    pub const ALL: [LogLevel; 6] = [
+        LogLevel::Off,
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        })
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "warning" => Ok(LogLevel::Warn),
+            name => LogLevel::ALL
+                .into_iter()
+                .find(|level| level.to_string() == name)
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown log level: {} (expected off, error, warn, info, debug or trace)",
+                        s
+                    )
+                }),
+        }
+    }
+}
+
+impl From<&tracing::Level> for LogLevel {
+    fn from(level: &tracing::Level) -> Self {
+        match *level {
+            tracing::Level::ERROR => LogLevel::Error,
+            tracing::Level::WARN => LogLevel::Warn,
+            tracing::Level::INFO => LogLevel::Info,
+            tracing::Level::DEBUG => LogLevel::Debug,
+            tracing::Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+/// One log line, as passed to a `LogSink`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// Severity; never `Off`
+    pub level: LogLevel,
+    /// Module that emitted the record, e.g. `phash_rs::cache`
+    pub target: String,
+    /// The enclosing spans, the message and its fields, e.g.
+    /// `hash_paths{files=120 algorithm=phash hash_size=8}: done hashed=120
+    /// failed=0 elapsed_ms=950`
+    pub message: String,
+}
+
+/// Receiver of log records
+///
+/// Called on whichever thread emitted the record, often a rayon worker.
+pub type LogSink = Arc<dyn Fn(&LogRecord) + Send + Sync>;
+
+/// Level below which records are dropped, as `LogLevel as u8`
+#[cfg(feature = "logging")]
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Off as u8);
+/// Where records go; stderr when `None`
+#[cfg(feature = "logging")]
+static SINK: RwLock<Option<LogSink>> = RwLock::new(None);
+/// Outcome of installing the subscriber, which happens once per process
+#[cfg(feature = "logging")]
+static INSTALLED: OnceLock<Result<(), String>> = OnceLock::new();
+
+/// Send the library's log records at `level` and above to `sink`, or to
+/// stderr as `LEVEL target: message` lines when it is `None`
+///
+/// The first call installs a global `tracing` subscriber that only passes
+/// on records from this crate; later calls change the level and
+/// destination. Fails if the application installed another subscriber
+/// first; records then go to that one.
+#[cfg(feature = "logging")]
+pub fn init_logging(level: LogLevel, sink: Option<LogSink>) -> Result<(), String> {
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Layer;
+
+    INSTALLED
+        .get_or_init(|| {
+            let layer = tracing_subscriber::fmt::layer()
+                .without_time()
+                .with_ansi(false)
+                .with_level(false)
+                .with_target(false)
+                .with_writer(SinkWriter)
+                .with_filter(tracing_subscriber::filter::filter_fn(enabled));
+            tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+                .map_err(|_| "Another tracing subscriber is already installed".to_string())
+        })
+        .clone()?;
+    *SINK.write().unwrap_or_else(|e| e.into_inner()) = sink;
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Send the library's log records at `level` and above to `sink`
+///
+/// Requires the `logging` feature; without it, install a `tracing`
+/// subscriber or a `log` logger instead.
+#[cfg(not(feature = "logging"))]
+pub fn init_logging(_level: LogLevel, _sink: Option<LogSink>) -> Result<(), String> {
+    Err("init_logging requires the `logging` feature".to_string())
+}
+
+/// Whether records and spans with `meta` pass the current level
+#[cfg(feature = "logging")]
+fn enabled(meta: &tracing::Metadata<'_>) -> bool {
+    meta.target().starts_with("phash_rs")
+        && LogLevel::from(meta.level()) as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Hands each formatted event to the current sink
+#[cfg(feature = "logging")]
+struct SinkWriter;
+
+#[cfg(feature = "logging")]
+impl<'a> MakeWriter<'a> for SinkWriter {
+    type Writer = RecordWriter;
+
+    fn make_writer(&'a self) -> RecordWriter {
+        RecordWriter {
+            level: LogLevel::Info,
+            target: "phash_rs".to_string(),
+            line: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> RecordWriter {
+        RecordWriter {
+            level: LogLevel::from(meta.level()),
+            target: meta.target().to_string(),
+            line: Vec::new(),
+        }
+    }
+}
+
+/// Collects one formatted event and delivers it when dropped
+#[cfg(feature = "logging")]
+struct RecordWriter {
+    level: LogLevel,
+    target: String,
+    line: Vec<u8>,
+}
+
+#[cfg(feature = "logging")]
+impl Write for RecordWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "logging")]
+impl Drop for RecordWriter {
+    fn drop(&mut self) {
+        if self.line.is_empty() {
+            return;
+        }
+        let record = LogRecord {
+            level: self.level,
+            target: std::mem::take(&mut self.target),
+            message: String::from_utf8_lossy(&self.line).trim_end().to_string(),
+        };
+        // Cloned out so a sink may reconfigure logging
+        let sink = SINK.read().unwrap_or_else(|e| e.into_inner()).clone();
+        match sink {
+            Some(sink) => sink(&record),
+            None => eprintln!(
+                "{} {}: {}",
+                record.level.to_string().to_uppercase(),
+                record.target,
+                record.message
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_level_names() {
+        for level in LogLevel::ALL {
+            assert_eq!(level.to_string().parse::<LogLevel>(), Ok(level));
+        }
+        assert_eq!("WARNING".parse::<LogLevel>(), Ok(LogLevel::Warn));
+        assert!("verbose".parse::<LogLevel>().is_err());
+        assert!(LogLevel::Info < LogLevel::Debug);
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn test_records_reach_sink() {
+        use std::sync::Mutex;
+
+        let records: Arc<Mutex<Vec<LogRecord>>> = Arc::default();
+        let collected = records.clone();
+        let sink: LogSink = Arc::new(move |record| {
+            // Other tests log concurrently
+            if record.message.contains("logging_test") {
+                collected.lock().unwrap().push(record.clone());
+            }
+        });
+        init_logging(LogLevel::Info, Some(sink)).unwrap();
+
+        let span = tracing::info_span!("logging_test", files = 2);
+        span.in_scope(|| {
+            tracing::info!(groups = 1, "grouped");
+            tracing::debug!("too verbose");
+        });
+        init_logging(LogLevel::Off, None).unwrap();
+        tracing::error!("logging_test after off");
+
+        let records = records.lock().unwrap();
+        assert_eq!(
+            *records,
+            [LogRecord {
+                level: LogLevel::Info,
+                target: "phash_rs::logging::tests".to_string(),
+                message: "logging_test{files=2}: grouped groups=1".to_string(),
+            }]
+        );
+    }
+}
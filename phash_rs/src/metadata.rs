@@ -0,0 +1,97 @@
+//! File Metadata Module
+//!
+//! Size, modification time, dimensions and format of hashed files, for
+//! interfaces that show them next to each duplicate. Dimensions come from
+//! hashing; the rest is one stat and a read of the first bytes per file,
+//! so no image is decoded a second time.
+
+use crate::decode::normalize_ext;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Bytes read to detect the format
+const SNIFF_LEN: usize = 16;
+
+/// Facts about an image file, as shown beside a duplicate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// File size in bytes
+    pub size: u64,
+    /// Last modification time, where the platform records one
+    pub modified: Option<SystemTime>,
+    /// Width and height as decoded for hashing, if known
+    pub dimensions: Option<(u32, u32)>,
+    /// Format detected from the first bytes, named by its usual extension
+    /// ("jpg", "png", "heic", "pdf", ...), else the lowercased extension
+    pub format: Option<String>,
+}
+
+impl FileMetadata {
+    /// Metadata of the file at `path`, or `None` if it cannot be stat'ed
+    pub fn of<P: AsRef<Path>>(path: P, dimensions: Option<(u32, u32)>) -> Option<Self> {
+        let path = path.as_ref();
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            dimensions,
+            format: sniff_format(path).or_else(|| {
+                path.extension()
+                    .map(|ext| normalize_ext(&ext.to_string_lossy()))
+            }),
+        })
+    }
+}
+
+/// Format named by the magic bytes at the start of the file
+fn sniff_format(path: &Path) -> Option<String> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    if let Ok(format) = image::guess_format(&head) {
+        return format.extensions_str().first().map(|ext| ext.to_string());
+    }
+    // Formats decoded outside the image crate
+    if head.starts_with(b"%PDF") {
+        return Some("pdf".to_string());
+    }
+    let brand = head.get(4..12)?;
+    match brand {
+        b"ftypheic" | b"ftypheix" | b"ftypmif1" | b"ftypmsf1" => Some("heic".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    #[test]
+    fn test_file_metadata() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_metadata_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // A PNG under a misleading name is still reported as PNG
+        let png = dir.join("photo.jpg");
+        DynamicImage::ImageRgb8(RgbImage::new(4, 3))
+            .save_with_format(&png, image::ImageFormat::Png)
+            .unwrap();
+        let unknown = dir.join("notes.XMP");
+        fs::write(&unknown, b"<x:xmpmeta/>").unwrap();
+
+        let meta = FileMetadata::of(&png, Some((4, 3))).unwrap();
+        assert_eq!(meta.size, fs::metadata(&png).unwrap().len());
+        assert_eq!(meta.format.as_deref(), Some("png"));
+        assert_eq!(meta.dimensions, Some((4, 3)));
+        assert!(meta.modified.is_some());
+        let meta = FileMetadata::of(&unknown, None).unwrap();
+        assert_eq!(meta.format.as_deref(), Some("xmp"));
+        assert!(FileMetadata::of(dir.join("missing.png"), None).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
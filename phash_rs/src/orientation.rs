@@ -0,0 +1,353 @@
+//! EXIF Orientation Module
+//!
+//! Cameras usually store pixels in sensor order and record how the picture
+//! should be turned in the EXIF Orientation tag. Normalizing by that tag
+//! before hashing makes a photo match its physically rotated copy. The
+//! capture time is read from the same EXIF data, to order burst shots.
+
+use image::DynamicImage;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// EXIF tag number of Orientation
+const ORIENTATION_TAG: u16 = 0x0112;
+/// IFD0 tag pointing at the EXIF sub-IFD
+const EXIF_IFD_TAG: u16 = 0x8769;
+/// EXIF tag numbers of DateTimeOriginal and its fraction of a second
+const DATE_TIME_ORIGINAL_TAG: u16 = 0x9003;
+const SUB_SEC_TIME_ORIGINAL_TAG: u16 = 0x9291;
+/// IFD0 tag number of DateTime, the last modification in camera
+const DATE_TIME_TAG: u16 = 0x0132;
+
+/// Bytes read from the start of a file when looking for EXIF data
+const HEADER_LIMIT: u64 = 256 * 1024;
+
+/// EXIF orientation (1-8) of a JPEG, TIFF, PNG or WebP file
+///
+/// Returns `None` if the file has no EXIF data, no Orientation tag, or an
+/// out-of-range value.
+pub fn exif_orientation(path: &Path) -> Option<u8> {
+    orientation_from_bytes(&read_header(path)?)
+}
+
+/// Whether a JPEG, TIFF, PNG or WebP file carries EXIF data
+pub(crate) fn has_exif(path: &Path) -> bool {
+    read_header(path).is_some_and(|data| find_tiff(&data).is_some())
+}
+
+pub(crate) fn read_header(path: &Path) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(HEADER_LIMIT)
+        .read_to_end(&mut data)
+        .ok()?;
+    Some(data)
+}
+
+pub(crate) fn orientation_from_bytes(data: &[u8]) -> Option<u8> {
+    let tiff = Tiff::new(find_tiff(data)?)?;
+    let value = tiff.short(tiff.first_ifd()?, ORIENTATION_TAG)?;
+    u8::try_from(value).ok().filter(|o| (1..=8).contains(o))
+}
+
+/// EXIF capture time of a JPEG, TIFF, PNG or WebP file, as
+/// `"YYYY:MM:DD HH:MM:SS"` with the fraction of a second appended when
+/// recorded (`"2024:05:01 10:00:00.25"`)
+///
+/// Uses DateTimeOriginal, falling back to the IFD0 DateTime. Times of one
+/// camera sort chronologically as strings. Returns `None` if the file has
+/// neither.
+pub fn exif_capture_time(path: &Path) -> Option<String> {
+    capture_time_from_bytes(&read_header(path)?)
+}
+
+pub(crate) fn capture_time_from_bytes(data: &[u8]) -> Option<String> {
+    let tiff = Tiff::new(find_tiff(data)?)?;
+    let ifd0 = tiff.first_ifd()?;
+    let exif = tiff.long(ifd0, EXIF_IFD_TAG).map(|offset| offset as usize);
+    if let Some(original) = exif.and_then(|ifd| tiff.ascii(ifd, DATE_TIME_ORIGINAL_TAG)) {
+        let fraction = exif
+            .and_then(|ifd| tiff.ascii(ifd, SUB_SEC_TIME_ORIGINAL_TAG))
+            .filter(|f| !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()));
+        return Some(match fraction {
+            Some(fraction) => format!("{}.{}", original, fraction),
+            None => original,
+        });
+    }
+    tiff.ascii(ifd0, DATE_TIME_TAG)
+}
+
+/// Turn `img` upright according to EXIF `orientation`
+pub fn apply_orientation(img: DynamicImage, orientation: u8) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Locate the TIFF structure holding the EXIF tags
+fn find_tiff(data: &[u8]) -> Option<&[u8]> {
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some(data);
+    }
+    if data.starts_with(&[0xFF, 0xD8]) {
+        return find_jpeg_exif(data);
+    }
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return find_png_exif(data);
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return find_webp_exif(data);
+    }
+    None
+}
+
+fn find_jpeg_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            // Fill byte before a marker
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Start of scan or end of image: no EXIF in the header
+            0xDA | 0xD9 => return None,
+            _ => {}
+        }
+        let len = be_u16(data, pos + 2)? as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xE1 {
+            if let Some(tiff) = segment.strip_prefix(b"Exif\0\0") {
+                return Some(tiff);
+            }
+        }
+        pos += 2 + len;
+    }
+}
+
+fn find_png_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 8;
+    loop {
+        let len = be_u32(data, pos)? as usize;
+        let kind = data.get(pos + 4..pos + 8)?;
+        if kind == b"IEND" {
+            return None;
+        }
+        if kind == b"eXIf" {
+            return data.get(pos + 8..pos + 8 + len);
+        }
+        // length, type, data, CRC
+        pos += 12 + len;
+    }
+}
+
+fn find_webp_exif(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 12;
+    loop {
+        let kind = data.get(pos..pos + 4)?;
+        let len = le_u32(data, pos + 4)? as usize;
+        if kind == b"EXIF" {
+            let chunk = data.get(pos + 8..pos + 8 + len)?;
+            // Some writers keep the JPEG APP1 prefix
+            return Some(chunk.strip_prefix(b"Exif\0\0").unwrap_or(chunk));
+        }
+        // Chunks are padded to an even length
+        pos += 8 + len + (len & 1);
+    }
+}
+
+/// A TIFF structure holding EXIF tags, and its byte order
+struct Tiff<'a> {
+    data: &'a [u8],
+    little: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        Some(Self { data, little })
+    }
+
+    fn u16_at(&self, pos: usize) -> Option<u16> {
+        if self.little {
+            le_u16(self.data, pos)
+        } else {
+            be_u16(self.data, pos)
+        }
+    }
+
+    fn u32_at(&self, pos: usize) -> Option<u32> {
+        if self.little {
+            le_u32(self.data, pos)
+        } else {
+            be_u32(self.data, pos)
+        }
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.u32_at(4).map(|offset| offset as usize)
+    }
+
+    /// Position of the 12-byte entry for `tag` in the IFD at `ifd`
+    fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16_at(ifd)? as usize;
+        (0..count)
+            .map(|i| ifd + 2 + 12 * i)
+            .find(|&entry| self.u16_at(entry) == Some(tag))
+    }
+
+    /// SHORT value, which sits in the first two bytes of the value field
+    fn short(&self, ifd: usize, tag: u16) -> Option<u16> {
+        self.u16_at(self.entry(ifd, tag)? + 8)
+    }
+
+    /// LONG value, such as the offset of a sub-IFD
+    fn long(&self, ifd: usize, tag: u16) -> Option<u32> {
+        self.u32_at(self.entry(ifd, tag)? + 8)
+    }
+
+    /// ASCII value without its terminating NUL and padding; up to four
+    /// bytes are stored in the value field, longer ones at its offset
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<String> {
+        let entry = self.entry(ifd, tag)?;
+        let len = self.u32_at(entry + 4)? as usize;
+        let start = if len <= 4 {
+            entry + 8
+        } else {
+            self.u32_at(entry + 8)? as usize
+        };
+        let bytes = self.data.get(start..start.checked_add(len)?)?;
+        let text = std::str::from_utf8(bytes).ok()?;
+        let text = text.trim_end_matches(['\0', ' ']);
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+fn be_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn le_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn le_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// Big-endian TIFF header with a single Orientation entry
+#[cfg(test)]
+fn tiff(orientation: u16) -> Vec<u8> {
+    let mut t = b"MM\0*\0\0\0\x08\0\x01".to_vec();
+    t.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    t.extend_from_slice(&[0, 3, 0, 0, 0, 1]);
+    t.extend_from_slice(&orientation.to_be_bytes());
+    t.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    t
+}
+
+/// `img` encoded as a JPEG tagged with EXIF `orientation`, for tests of
+/// the code that decodes files
+#[cfg(test)]
+pub(crate) fn tagged_jpeg(img: &image::RgbImage, orientation: u16) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    img.write_to(
+        &mut std::io::Cursor::new(&mut encoded),
+        image::ImageFormat::Jpeg,
+    )
+    .unwrap();
+    let mut app1 = b"Exif\0\0".to_vec();
+    app1.extend(tiff(orientation));
+    // APP1 right after the SOI marker
+    let mut jpeg = encoded[..2].to_vec();
+    jpeg.extend_from_slice(&[0xFF, 0xE1]);
+    jpeg.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+    jpeg.extend(app1);
+    jpeg.extend_from_slice(&encoded[2..]);
+    jpeg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orientation_from_jpeg_and_tiff() {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff(6));
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+        jpeg.extend(app1);
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+
+        assert_eq!(orientation_from_bytes(&jpeg), Some(6));
+        assert_eq!(orientation_from_bytes(&tiff(8)), Some(8));
+        assert_eq!(orientation_from_bytes(&tiff(9)), None);
+        assert_eq!(orientation_from_bytes(&[0xFF, 0xD8, 0xFF, 0xDA]), None);
+    }
+
+    #[test]
+    fn test_capture_time() {
+        // Little-endian: IFD0 with DateTime and the EXIF pointer, then the
+        // EXIF IFD with DateTimeOriginal and SubSecTimeOriginal
+        let entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            let mut e = tag.to_le_bytes().to_vec();
+            e.extend_from_slice(&kind.to_le_bytes());
+            e.extend_from_slice(&count.to_le_bytes());
+            e.extend_from_slice(&value.to_le_bytes());
+            e
+        };
+        let mut t = b"II*\0\x08\0\0\0".to_vec();
+        // IFD0 at 8: 2 entries, ends at 8 + 2 + 24 + 4 = 38
+        t.extend_from_slice(&2u16.to_le_bytes());
+        t.extend(entry(DATE_TIME_TAG, 2, 20, 38));
+        t.extend(entry(EXIF_IFD_TAG, 4, 1, 58));
+        t.extend_from_slice(&[0; 4]);
+        t.extend_from_slice(b"2024:06:01 12:00:00\0");
+        // EXIF IFD at 58: 2 entries, ends at 58 + 2 + 24 + 4 = 88
+        t.extend_from_slice(&2u16.to_le_bytes());
+        t.extend(entry(DATE_TIME_ORIGINAL_TAG, 2, 20, 88));
+        t.extend(entry(
+            SUB_SEC_TIME_ORIGINAL_TAG,
+            2,
+            3,
+            u32::from_le_bytes(*b"25\0\0"),
+        ));
+        t.extend_from_slice(&[0; 4]);
+        t.extend_from_slice(b"2024:05:01 10:00:00\0");
+
+        assert_eq!(
+            capture_time_from_bytes(&t).as_deref(),
+            Some("2024:05:01 10:00:00.25")
+        );
+        assert_eq!(capture_time_from_bytes(&tiff(6)), None);
+
+        // Without the EXIF IFD, DateTime is used
+        t[22..24].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert_eq!(
+            capture_time_from_bytes(&t).as_deref(),
+            Some("2024:06:01 12:00:00")
+        );
+    }
+}
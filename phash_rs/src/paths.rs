@@ -0,0 +1,106 @@
+//! Path Reporting Module
+//!
+//! Rewrites reported paths relative to a scan root so results stay valid
+//! when the same tree is mounted at a different location on another machine.
+//!
+//! Paths are kept as `PathBuf` throughout, so file names that are not valid
+//! UTF-8 (common on Linux) survive scanning, hashing and grouping. Binary
+//! and CSV files store them as raw bytes and JSON escapes the odd bytes;
+//! only HTML reports write them lossily.
+
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+
+/// Express `path` relative to `root`, using `/` as the separator
+///
+/// The comparison is lexical: paths are not canonicalized, so `root` should
+/// be spelled the same way as the scanned paths. Paths outside `root` are
+/// returned unchanged.
+pub fn relative_path(path: &Path, root: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(rel) => {
+            let mut out = OsString::new();
+            for c in rel.components() {
+                let part = match c {
+                    Component::Normal(s) => s,
+                    Component::ParentDir => "..".as_ref(),
+                    _ => continue,
+                };
+                if !out.is_empty() {
+                    out.push("/");
+                }
+                out.push(part);
+            }
+            if out.is_empty() {
+                PathBuf::from(".")
+            } else {
+                PathBuf::from(out)
+            }
+        }
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Bytes of `path` for binary files, read back with `path_from_bytes`
+///
+/// Lossless on Unix, where paths are arbitrary bytes. Elsewhere paths are
+/// stored as UTF-8, which every path Windows tools can create is.
+pub(crate) fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        match path.to_string_lossy() {
+            Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+            Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+        }
+    }
+}
+
+/// Path written by `path_bytes`
+pub(crate) fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        PathBuf::from(OsString::from_vec(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rel(path: &str) -> PathBuf {
+        relative_path(Path::new(path), Path::new("/mnt/backup"))
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(rel("/mnt/backup/2024/a.jpg"), Path::new("2024/a.jpg"));
+        assert_eq!(rel("/mnt/backup/"), Path::new("."));
+        assert_eq!(rel("/mnt/backups/a.jpg"), Path::new("/mnt/backups/a.jpg"));
+        assert_eq!(rel("/elsewhere/a.jpg"), Path::new("/elsewhere/a.jpg"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_path_survives() {
+        use std::os::unix::ffi::OsStrExt;
+        // Latin-1 "café.jpg", as older cameras and Windows shares write it
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.jpg");
+        let path = Path::new("/mnt/backup/2024").join(name);
+        assert!(path.to_str().is_none());
+
+        let relative = relative_path(&path, Path::new("/mnt/backup"));
+        assert_eq!(relative.as_os_str().as_bytes(), b"2024/caf\xe9.jpg");
+        assert_eq!(path_from_bytes(path_bytes(&path).into_owned()), path);
+    }
+}
@@ -0,0 +1,400 @@
+//! Dedupe Pipeline Module
+//!
+//! Runs a whole cleanup in one call: scan a directory, hash through the
+//! cache, group, verify every removal against the file that is kept, and
+//! hand the verified copies to `apply_dedup`. Composing these steps by
+//! hand is where cleanups go wrong: removing a transitive match that looks
+//! nothing like the kept file, acting on a file edited since it was
+//! hashed, or leaving sidecars behind.
+
+use crate::actions::{apply_dedup, ActionReport, DedupAction, DedupOptions};
+use crate::best::BestBy;
+use crate::cache::{FileStamp, HashCache};
+use crate::duplicate::{find_duplicates_with, with_exact_prepass, DuplicateGroup};
+use crate::error::HashFailures;
+use crate::hash::{HashAlgorithm, DEFAULT_HASH_SIZE};
+use crate::preprocess::HashOptions;
+use crate::progress::Progress;
+use crate::scan::{scan_directory_with, ScanOptions};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Quarantine folder under the scan root unless a profile names another
+pub const DEFAULT_QUARANTINE_DIR: &str = "Duplicates";
+
+/// Check a duplicate must pass before it is planned for removal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verify {
+    /// Within the threshold of the kept file itself, not only through
+    /// other members of the group. Files without a cached hash (copies
+    /// skipped by the exact pre-pass) must be byte-identical instead.
+    Hash,
+    /// Byte-for-byte identical to the kept file
+    Identical,
+}
+
+/// Settings for `dedupe`
+#[derive(Debug, Clone)]
+pub struct DedupeProfile {
+    pub algorithm: HashAlgorithm,
+    pub hash_size: usize,
+    pub threshold: u32,
+    pub best_by: BestBy,
+    pub exact_prepass: bool,
+    pub verify: Verify,
+    /// What `apply_dedup` does with the verified copies; everything but
+    /// `DedupAction::Move`, which has no destination here
+    pub action: DedupAction,
+    /// Folder for the timestamped quarantine and trash run folders;
+    /// relative to the scan root unless absolute. Never scanned.
+    pub quarantine_dir: PathBuf,
+    /// Hash cache file, loaded before and saved after grouping
+    pub cache_path: Option<PathBuf>,
+}
+
+impl DedupeProfile {
+    /// Names accepted by `named`
+    pub const NAMES: [&'static str; 3] = ["identical", "conservative", "balanced"];
+
+    /// Preset by name; all quarantine rather than delete
+    ///
+    /// - identical: byte-identical copies only
+    /// - conservative: re-encodes and resizes (pHash <= 4)
+    /// - balanced: also light edits and crops (pHash <= 10), the default
+    pub fn named(name: &str) -> Result<Self, String> {
+        let (threshold, verify) = match name.trim().to_ascii_lowercase().as_str() {
+            "identical" => (0, Verify::Identical),
+            "conservative" => (4, Verify::Hash),
+            "balanced" => (10, Verify::Hash),
+            _ => {
+                return Err(format!(
+                    "Unknown profile: {} (expected one of {})",
+                    name,
+                    Self::NAMES.join(", ")
+                ))
+            }
+        };
+        Ok(Self {
+            threshold,
+            verify,
+            ..Self::default()
+        })
+    }
+}
+
+impl Default for DedupeProfile {
+    /// The "balanced" preset
+    fn default() -> Self {
+        Self {
+            algorithm: HashAlgorithm::PHash,
+            hash_size: DEFAULT_HASH_SIZE,
+            threshold: 10,
+            best_by: BestBy::Resolution,
+            exact_prepass: false,
+            verify: Verify::Hash,
+            action: DedupAction::Quarantine,
+            quarantine_dir: PathBuf::from(DEFAULT_QUARANTINE_DIR),
+            cache_path: None,
+        }
+    }
+}
+
+/// Everything `dedupe` found, verified and did
+#[derive(Debug, Clone)]
+pub struct DedupeReport {
+    /// Image files found under the root
+    pub scanned: usize,
+    /// Duplicate groups, best file chosen by the profile
+    pub groups: Vec<DuplicateGroup>,
+    /// Files that could not be hashed
+    pub failures: HashFailures,
+    /// Group members left in place because verification failed, with why
+    pub unverified: Vec<(PathBuf, String)>,
+    /// What `apply_dedup` did with the verified copies, or would do in a
+    /// dry run; quarantine and trash runs name their manifest here
+    pub actions: ActionReport,
+}
+
+impl DedupeReport {
+    /// Bytes the plan frees (or freed, once applied without errors)
+    pub fn bytes_recoverable(&self) -> u64 {
+        self.actions.bytes_freed()
+    }
+}
+
+/// Scan `root`, group duplicates, verify them and pass the verified copies
+/// to `apply_dedup`, as a dry run unless `apply` is set
+///
+/// Without `apply` nothing on disk changes except the hash cache, so the
+/// report can be reviewed first. Quarantined files keep their path below
+/// `root` in a new timestamped folder in the quarantine folder, next to
+/// the manifest `restore` reads. Progress covers scanning and hashing;
+/// cancelling returns an error before anything is planned.
+pub fn dedupe(
+    root: &Path,
+    profile: &DedupeProfile,
+    apply: bool,
+    progress: Option<&dyn Progress>,
+) -> Result<DedupeReport, String> {
+    let options = ScanOptions {
+        exclude: vec![profile.quarantine_dir.clone()],
+        ..ScanOptions::default()
+    };
+    let paths = scan_directory_with(root, &options, progress)?;
+    let mut cache = match &profile.cache_path {
+        Some(path) => HashCache::load(path)?,
+        None => HashCache::new(),
+    };
+
+    let mut find = |paths: &[PathBuf]| {
+        find_duplicates_with(
+            paths,
+            profile.algorithm,
+            profile.hash_size,
+            profile.threshold,
+            false,
+            &HashOptions::default(),
+            Some(&mut cache),
+            progress,
+        )
+    };
+    let (mut groups, failures) = if profile.exact_prepass {
+        with_exact_prepass(&paths, progress, find)?
+    } else {
+        find(&paths)?
+    };
+    if let Some(path) = &profile.cache_path {
+        cache.save(path)?;
+    }
+    groups.retain(|g| g.paths.len() > 1);
+    for g in &mut groups {
+        g.choose_best(&profile.best_by);
+    }
+
+    let verified_at = SystemTime::now();
+    let (verified, unverified) = verify_groups(&groups, &cache, profile);
+    let actions = act(root, &verified, profile, apply, verified_at)?;
+    Ok(DedupeReport {
+        scanned: paths.len(),
+        groups,
+        failures,
+        unverified,
+        actions,
+    })
+}
+
+/// Verify every non-best member of `groups`, returning the groups cut down
+/// to the members that passed and the members that failed, with why
+fn verify_groups(
+    groups: &[DuplicateGroup],
+    cache: &HashCache,
+    profile: &DedupeProfile,
+) -> (Vec<DuplicateGroup>, Vec<(PathBuf, String)>) {
+    let mut verified = Vec::new();
+    let mut unverified = Vec::new();
+    for g in groups {
+        let mut kept = g.clone();
+        kept.paths.retain(|path| {
+            *path == g.best_path
+                || verify(path, &g.best_path, cache, profile)
+                    .map_err(|reason| unverified.push((path.clone(), reason)))
+                    .is_ok()
+        });
+        // Checked just now; the verification time catches later edits
+        // without hashing every copy again
+        kept.member_hashes.clear();
+        if kept.paths.len() > 1 {
+            verified.push(kept);
+        }
+    }
+    (verified, unverified)
+}
+
+/// Run `apply_dedup` on verified groups; files modified after
+/// `verified_at` are skipped
+fn act(
+    root: &Path,
+    verified: &[DuplicateGroup],
+    profile: &DedupeProfile,
+    apply: bool,
+    verified_at: SystemTime,
+) -> Result<ActionReport, String> {
+    let options = DedupOptions {
+        action: profile.action,
+        keep: None,
+        dry_run: !apply,
+        move_to: None,
+        quarantine_dir: Some(root.join(&profile.quarantine_dir)),
+        relative_to: Some(root.to_path_buf()),
+        scanned_at: Some(verified_at),
+        algorithm: profile.algorithm,
+        hash_options: HashOptions::default(),
+    };
+    let relative: Vec<DuplicateGroup> = verified.iter().map(|g| g.relative_to(root)).collect();
+    apply_dedup(&relative, &options)
+}
+
+/// Whether `path` may be removed in favour of `keep`, and if not why
+fn verify(
+    path: &Path,
+    keep: &Path,
+    cache: &HashCache,
+    profile: &DedupeProfile,
+) -> Result<(), String> {
+    let stamp = FileStamp::of(path).ok_or("cannot be read")?;
+    let keep_stamp =
+        FileStamp::of(keep).ok_or_else(|| format!("{} cannot be read", keep.display()))?;
+    let cached = |p: &Path, s: &FileStamp| cache.get(p, profile.algorithm, profile.hash_size, s);
+    let verified = match (
+        profile.verify,
+        cached(path, &stamp),
+        cached(keep, &keep_stamp),
+    ) {
+        (Verify::Hash, Some(a), Some(b)) => a.hash.distance(&b.hash) <= profile.threshold,
+        _ => same_contents(path, keep).map_err(|e| e.to_string())?,
+    };
+    if verified {
+        Ok(())
+    } else if profile.verify == Verify::Hash {
+        Err(format!(
+            "more than {} bits from {}",
+            profile.threshold,
+            keep.display()
+        ))
+    } else {
+        Err(format!("differs from {}", keep.display()))
+    }
+}
+
+fn same_contents(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut buf_a, mut buf_b) = (vec![0u8; 1 << 16], vec![0u8; 1 << 16]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// `wanted`, or `name (2).ext`, `name (3).ext`, ... if that is taken on
+/// disk or by an earlier plan entry
+pub(crate) fn free_target(wanted: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    let is_free = |p: &Path| !p.exists() && !taken.contains(p);
+    if is_free(wanted) {
+        return wanted.to_path_buf();
+    }
+    let stem = wanted.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = wanted
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| wanted.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| is_free(p))
+        .unwrap_or_else(|| wanted.to_path_buf())
+}
+
+/// Move a file, copying across filesystems; never overwrites
+pub(crate) fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if to.exists() {
+        return Err(format!("{} already exists", to.display()));
+    }
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::rename(from, to)
+        .or_else(|_| fs::copy(from, to).and_then(|_| fs::remove_file(from)))
+        .map_err(|e| format!("{} -> {}: {}", from.display(), to.display(), e))
+}
+
+/// Name for a sidecar following its primary's (possibly renamed) target
+pub(crate) fn sidecar_name(sidecar: &Path, primary: &Path, target: &Path) -> OsString {
+    let file_name = sidecar.file_name().unwrap_or_default();
+    let name = file_name.to_string_lossy();
+    let pairs = [
+        (primary.file_name(), target.file_name()),
+        (primary.file_stem(), target.file_stem()),
+    ];
+    for (prefix, renamed) in pairs {
+        let (Some(prefix), Some(renamed)) = (prefix, renamed) else {
+            continue;
+        };
+        let prefix = format!("{}.", prefix.to_string_lossy().to_ascii_lowercase());
+        if name.to_ascii_lowercase().starts_with(&prefix) {
+            let mut renamed = renamed.to_os_string();
+            renamed.push(&name[prefix.len() - 1..]);
+            return renamed;
+        }
+    }
+    file_name.to_os_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quarantine::restore;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_verifies_then_quarantines_with_manifest() {
+        let root = std::env::temp_dir().join(format!("phash_rs_dedupe_{}", std::process::id()));
+        fs::create_dir_all(root.join("2024")).unwrap();
+        let path = |name: &str| root.join(name);
+        fs::write(path("a.jpg"), b"pixels").unwrap();
+        fs::write(path("2024/b.jpg"), b"pixels").unwrap();
+        fs::write(path("2024/b.xmp"), b"<xmp/>").unwrap();
+        fs::write(path("c.jpg"), b"edited").unwrap();
+
+        let group = DuplicateGroup {
+            paths: vec![path("a.jpg"), path("2024/b.jpg"), path("c.jpg")],
+            hash: "0000000000000000".to_string(),
+            best_path: path("a.jpg"),
+            sidecars: BTreeMap::from([(path("2024/b.jpg"), vec![path("2024/b.xmp")])]),
+            content_type: None,
+            member_hashes: BTreeMap::new(),
+            member_dimensions: BTreeMap::new(),
+        };
+        // No cached hashes, so members must be byte-identical to a.jpg
+        let profile = DedupeProfile::default();
+        let verified_at = SystemTime::now();
+        let (verified, unverified) = verify_groups(&[group], &HashCache::new(), &profile);
+        assert_eq!(unverified.len(), 1);
+        assert_eq!(unverified[0].0, path("c.jpg"));
+        assert_eq!(verified[0].paths, vec![path("a.jpg"), path("2024/b.jpg")]);
+
+        let dry_run = act(&root, &verified, &profile, false, verified_at).unwrap();
+        assert_eq!(dry_run.actions.len(), 1);
+        assert_eq!(dry_run.bytes_freed(), 12);
+        assert!(path("2024/b.jpg").exists());
+
+        // Files modified after verification are left alone
+        let stale = act(&root, &verified, &profile, true, SystemTime::UNIX_EPOCH).unwrap();
+        assert!(stale.actions.is_empty() && stale.skipped.len() == 1);
+
+        let applied = act(&root, &verified, &profile, true, verified_at).unwrap();
+        let target = applied.actions[0].target.clone().unwrap();
+        assert!(target.starts_with(root.join("Duplicates")));
+        assert!(target.ends_with("2024/b.jpg") && target.with_extension("xmp").exists());
+        assert!(!path("2024/b.jpg").exists());
+
+        let restored = restore(&applied.manifest.unwrap()).unwrap();
+        assert_eq!(restored.restored.len(), 2);
+        assert_eq!(fs::read(path("2024/b.jpg")).unwrap(), b"pixels");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(DedupeProfile::named("identical").unwrap().threshold, 0);
+        assert!(DedupeProfile::named("reckless").is_err());
+    }
+}
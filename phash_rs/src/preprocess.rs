@@ -0,0 +1,367 @@
+//! Preprocessing Module
+//!
+//! Optional steps applied to an image before it is hashed. Scanned photos
+//! with white borders and letterboxed screenshots otherwise hash mostly on
+//! the border; trimming it, cropping to the center or evening out the
+//! exposure makes the hash describe the picture itself. Masking blanks out
+//! areas known to change between otherwise identical screenshots (clocks,
+//! counters, notification badges). Compositing transparent images onto a
+//! fixed background keeps their hash from depending on whatever color a
+//! decoder or editor left under the transparent pixels.
+
+use crate::decode::{exif_orientation_enabled, open_image_oriented};
+use crate::error::HashError;
+use crate::hash::PHashConfig;
+use crate::tiles::Region;
+use image::{DynamicImage, GenericImageView, Rgb, Rgb32FImage, RgbImage, Rgba, RgbaImage};
+use std::path::Path;
+
+/// Largest luma difference from the border color still counted as border
+const BORDER_TOLERANCE: u8 = 24;
+
+/// Share of a row or column that must match the border color for it to be
+/// trimmed, so dust and scanner noise don't stop the trim
+const BORDER_MIN_SHARE: f64 = 0.98;
+
+/// Color masked regions are filled with
+const MASK_FILL: Rgba<u8> = Rgba([128, 128, 128, 255]);
+
+/// How an image is prepared before hashing; the default leaves it as is
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HashOptions {
+    /// Composite images with an alpha channel onto this color before the
+    /// other steps. Without it alpha is dropped and transparent pixels hash
+    /// as their stored color, which differs between encoders.
+    pub alpha_background: Option<Rgb<u8>>,
+    /// Rectangles, in pixels of the decoded (upright) image, filled with
+    /// flat gray before the other steps so their content doesn't affect
+    /// the hash. Parts outside the image are ignored.
+    pub mask_regions: Vec<Region>,
+    /// Trim uniform borders (scan margins, letterbox bars) whose color
+    /// matches the image corners
+    pub trim_borders: bool,
+    /// Keep only the centered part of the image, this percentage of its
+    /// width and height (1 to 100)
+    pub center_crop: Option<f32>,
+    /// Apply the gamma that brings the mean brightness to mid-gray, so
+    /// under- and overexposed copies hash alike
+    pub normalize_gamma: bool,
+    /// DCT input size and resize filter of pHash and colorHash
+    pub phash: PHashConfig,
+    /// Turn images upright by their EXIF orientation when decoding them
+    /// from a path, or not; `None` follows the decoder configuration
+    pub apply_exif_orientation: Option<bool>,
+}
+
+impl HashOptions {
+    /// Check that the options are in range
+    pub fn validate(&self) -> Result<(), HashError> {
+        if self
+            .mask_regions
+            .iter()
+            .any(|r| r.width == 0 || r.height == 0)
+        {
+            return Err(HashError::InvalidInput(
+                "mask regions must have a nonzero width and height".to_string(),
+            ));
+        }
+        if let Some(percent) = self.center_crop {
+            if !(1.0..=100.0).contains(&percent) {
+                return Err(HashError::InvalidInput(format!(
+                    "center_crop must be between 1 and 100 percent, got {}",
+                    percent
+                )));
+            }
+        }
+        self.phash.validate()
+    }
+
+    /// Whether every step is off, pHash uses its default config and EXIF
+    /// orientation follows the decoder configuration, so hashes match
+    /// those made without options
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether images decoded with these options are turned upright by
+    /// their EXIF orientation
+    pub fn applies_exif_orientation(&self) -> bool {
+        self.apply_exif_orientation
+            .unwrap_or_else(exif_orientation_enabled)
+    }
+
+    /// Decode an image file, applying EXIF orientation as these options say
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<DynamicImage, HashError> {
+        open_image_oriented(path, self.apply_exif_orientation).map(|(img, _)| img)
+    }
+
+    /// Suffix distinguishing cache entries hashed with these options
+    pub(crate) fn tag(&self) -> String {
+        let mut tag = String::new();
+        if let Some(Rgb([r, g, b])) = self.alpha_background {
+            tag.push_str(&format!("/bg{},{},{}", r, g, b));
+        }
+        for r in &self.mask_regions {
+            tag.push_str(&format!("/mask{},{},{},{}", r.x, r.y, r.width, r.height));
+        }
+        if self.trim_borders {
+            tag.push_str("/trim");
+        }
+        if let Some(percent) = self.center_crop {
+            tag.push_str(&format!("/crop{}", percent));
+        }
+        if self.normalize_gamma {
+            tag.push_str("/gamma");
+        }
+        tag.push_str(&self.phash.tag());
+        tag
+    }
+
+    /// Apply the enabled steps in order: alpha, mask, trim, crop, gamma
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let mut img = img.clone();
+        if let Some(background) = self.alpha_background {
+            img = composite_alpha(&img, background);
+        }
+        if !self.mask_regions.is_empty() {
+            img = mask_regions(&img, &self.mask_regions);
+        }
+        if self.trim_borders {
+            img = trim_borders(&img);
+        }
+        if let Some(percent) = self.center_crop {
+            img = center_crop(&img, percent);
+        }
+        if self.normalize_gamma {
+            img = normalize_gamma(&img);
+        }
+        img
+    }
+}
+
+/// `img` blended over a solid `background` by its alpha channel
+///
+/// Images without alpha are returned unchanged. 8-bit images stay 8-bit;
+/// deeper ones are blended in floating point so they keep their precision.
+pub fn composite_alpha(img: &DynamicImage, background: Rgb<u8>) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return img.clone();
+    }
+    let (width, height) = img.dimensions();
+    let Rgb(bg) = background;
+    if img.color().bytes_per_pixel() == img.color().channel_count() {
+        let rgba = img.to_rgba8();
+        return DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let Rgba(p) = *rgba.get_pixel(x, y);
+            let a = p[3] as u32;
+            Rgb(std::array::from_fn(|c| {
+                ((p[c] as u32 * a + bg[c] as u32 * (255 - a) + 127) / 255) as u8
+            }))
+        }));
+    }
+    let rgba = img.to_rgba32f();
+    DynamicImage::ImageRgb32F(Rgb32FImage::from_fn(width, height, |x, y| {
+        let Rgba(p) = *rgba.get_pixel(x, y);
+        let a = p[3].clamp(0.0, 1.0);
+        Rgb(std::array::from_fn(|c| {
+            p[c] * a + bg[c] as f32 / 255.0 * (1.0 - a)
+        }))
+    }))
+}
+
+/// `img` with each of `regions` filled with flat gray
+///
+/// Regions are clipped to the image; ones entirely outside it are ignored.
+pub fn mask_regions(img: &DynamicImage, regions: &[Region]) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    for r in regions {
+        let x1 = r.x.saturating_add(r.width).min(width);
+        let y1 = r.y.saturating_add(r.height).min(height);
+        for y in r.y.min(y1)..y1 {
+            for x in r.x.min(x1)..x1 {
+                rgba.put_pixel(x, y, MASK_FILL);
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// `img` without rows and columns at its edges that match the corner color
+///
+/// Returns the image unchanged if it is uniform throughout.
+pub fn trim_borders(img: &DynamicImage) -> DynamicImage {
+    let luma = img.to_luma8();
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return img.clone();
+    }
+    // Median of the four corners: one odd corner (a logo, a date stamp)
+    // doesn't change the border color
+    let mut corners = [
+        luma.get_pixel(0, 0).0[0],
+        luma.get_pixel(width - 1, 0).0[0],
+        luma.get_pixel(0, height - 1).0[0],
+        luma.get_pixel(width - 1, height - 1).0[0],
+    ];
+    corners.sort_unstable();
+    let border = ((corners[1] as u16 + corners[2] as u16) / 2) as u8;
+
+    let is_border = |pixels: &mut dyn Iterator<Item = u8>, count: u32| {
+        let matching = pixels
+            .filter(|&v| v.abs_diff(border) <= BORDER_TOLERANCE)
+            .count();
+        matching as f64 >= count as f64 * BORDER_MIN_SHARE
+    };
+    let row = |y: u32| is_border(&mut (0..width).map(|x| luma.get_pixel(x, y).0[0]), width);
+    let column = |x: u32| is_border(&mut (0..height).map(|y| luma.get_pixel(x, y).0[0]), height);
+
+    let top = (0..height).find(|&y| !row(y));
+    let Some(top) = top else {
+        return img.clone();
+    };
+    let bottom = (top..height).rev().find(|&y| !row(y)).unwrap_or(top);
+    let left = (0..width).find(|&x| !column(x)).unwrap_or(0);
+    let right = (left..width).rev().find(|&x| !column(x)).unwrap_or(left);
+    img.crop_imm(left, top, right - left + 1, bottom - top + 1)
+}
+
+/// The centered `percent` of `img`'s width and height
+pub fn center_crop(img: &DynamicImage, percent: f32) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let scale = (percent / 100.0).clamp(0.0, 1.0);
+    let w = ((width as f32 * scale).round() as u32).clamp(1, width.max(1));
+    let h = ((height as f32 * scale).round() as u32).clamp(1, height.max(1));
+    img.crop_imm((width - w) / 2, (height - h) / 2, w, h)
+}
+
+/// `img` with the gamma applied that maps its mean brightness to mid-gray
+///
+/// Images that are almost entirely black or white are returned unchanged.
+pub fn normalize_gamma(img: &DynamicImage) -> DynamicImage {
+    let luma = img.to_luma8();
+    let (width, height) = luma.dimensions();
+    let count = width as f64 * height as f64;
+    if count == 0.0 {
+        return img.clone();
+    }
+    let mean = luma.pixels().map(|p| p.0[0] as f64).sum::<f64>() / count / 255.0;
+    if !(0.02..=0.98).contains(&mean) {
+        return img.clone();
+    }
+    let gamma = 0.5f64.ln() / mean.ln();
+    let lut: Vec<u8> = (0..=255u32)
+        .map(|v| ((v as f64 / 255.0).powf(gamma) * 255.0).round() as u8)
+        .collect();
+
+    let rgba = img.to_rgba8();
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        Rgba([lut[r as usize], lut[g as usize], lut[b as usize], a])
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, ImageBuffer, Luma};
+
+    #[test]
+    fn test_trim_and_crop() {
+        // A 20x10 picture (gradient) inside a white margin
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(40, 30, |x, y| {
+            if (10..30).contains(&x) && (12..22).contains(&y) {
+                Luma([(x * 5) as u8])
+            } else {
+                Luma([250])
+            }
+        }));
+        assert_eq!(trim_borders(&img).dimensions(), (20, 10));
+        let flat = DynamicImage::ImageLuma8(GrayImage::from_fn(8, 8, |_, _| Luma([0])));
+        assert_eq!(trim_borders(&flat).dimensions(), (8, 8));
+
+        assert_eq!(center_crop(&img, 50.0).dimensions(), (20, 15));
+        assert_eq!(center_crop(&img, 100.0).dimensions(), (40, 30));
+        assert!(HashOptions {
+            center_crop: Some(0.0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_mask_hides_changing_area() {
+        // Two screenshots differing only in a "clock" in the top-right corner
+        let screen = |clock: u8| {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(32, 24, |x, y| {
+                if x >= 24 && y < 4 {
+                    Luma([clock])
+                } else {
+                    Luma([(x * 7 + y * 3) as u8])
+                }
+            }))
+        };
+        let options = HashOptions {
+            mask_regions: vec![Region {
+                x: 24,
+                y: 0,
+                width: 100,
+                height: 4,
+            }],
+            ..Default::default()
+        };
+        let pixels = |img: DynamicImage| img.to_rgba8().into_raw();
+        assert_ne!(pixels(screen(0)), pixels(screen(255)));
+        assert_eq!(
+            pixels(options.apply(&screen(0))),
+            pixels(options.apply(&screen(255)))
+        );
+        assert_eq!(options.tag(), "/mask24,0,100,4");
+    }
+
+    #[test]
+    fn test_gamma_evens_out_exposure() {
+        let dark = DynamicImage::ImageLuma8(GrayImage::from_fn(16, 16, |x, _| Luma([x as u8 * 4])));
+        let mean = |img: &DynamicImage| {
+            let luma = img.to_luma8();
+            luma.pixels().map(|p| p.0[0] as f64).sum::<f64>() / 256.0
+        };
+        let normalized = normalize_gamma(&dark);
+        assert!(mean(&dark) < 40.0);
+        assert!(mean(&normalized) > 90.0);
+    }
+
+    #[test]
+    fn test_alpha_composited_onto_background() {
+        // The same transparent logo, saved once with black and once with
+        // white under its transparent pixels
+        let logo = |hidden: u8| {
+            DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, _| {
+                if x < 8 {
+                    Rgba([200, 40, 40, 255])
+                } else {
+                    Rgba([hidden, hidden, hidden, 0])
+                }
+            }))
+        };
+        let options = HashOptions {
+            alpha_background: Some(Rgb([255, 255, 255])),
+            ..Default::default()
+        };
+        let (black, white) = (options.apply(&logo(0)), options.apply(&logo(255)));
+        assert_eq!(black.to_rgb8().into_raw(), white.to_rgb8().into_raw());
+        assert_eq!(black.to_rgb8().get_pixel(12, 3), &Rgb([255, 255, 255]));
+        assert_eq!(options.tag(), "/bg255,255,255");
+
+        // Half-transparent 16-bit pixels blend without losing precision
+        let deep =
+            DynamicImage::ImageRgba16(ImageBuffer::from_pixel(2, 2, Rgba([1000, 0, 0, 32768])));
+        let blended = composite_alpha(&deep, Rgb([0, 0, 0]));
+        let DynamicImage::ImageRgb32F(blended) = blended else {
+            panic!("expected a float image");
+        };
+        let red = blended.get_pixel(0, 0).0[0];
+        assert!((red * 65535.0 - 500.0).abs() < 0.1, "{}", red);
+    }
+}
@@ -0,0 +1,204 @@
+//! Progress Reporting Module
+//!
+//! Long batch operations report per-file progress through the `Progress`
+//! trait so that frontends (socket subscribers, Python callbacks) can show
+//! feedback while rayon workers are hashing, and can ask for the operation
+//! to stop early. Non-fatal warnings travel the same way.
+
+use crate::warnings::Warning;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Receiver of progress events from a batch operation
+///
+/// Methods are called from rayon worker threads, possibly concurrently.
+pub trait Progress: Sync {
+    /// A batch of `total` files is about to be processed
+    fn started(&self, _total: usize) {}
+
+    /// `path` has been processed (hashed, served from cache, or failed);
+    /// `done` counts completed files including this one
+    fn file_done(&self, done: usize, total: usize, path: &Path);
+
+    /// Whether the operation should stop; files not yet started are skipped
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+
+    /// A non-fatal condition was met
+    fn warning(&self, _warning: &Warning) {}
+}
+
+/// Forwards events to several receivers
+///
+/// Cancelled as soon as any receiver is.
+pub struct Fanout<'a>(pub Vec<&'a dyn Progress>);
+
+impl Progress for Fanout<'_> {
+    fn started(&self, total: usize) {
+        self.0.iter().for_each(|p| p.started(total));
+    }
+
+    fn file_done(&self, done: usize, total: usize, path: &Path) {
+        self.0.iter().for_each(|p| p.file_done(done, total, path));
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.iter().any(|p| p.is_cancelled())
+    }
+
+    fn warning(&self, warning: &Warning) {
+        self.0.iter().for_each(|p| p.warning(warning));
+    }
+}
+
+/// Flag for stopping a batch operation from another thread
+///
+/// Pass a clone as the operation's `progress`, alone or in a `Fanout`, and
+/// call `cancel`: files not yet started are skipped and the operation
+/// returns early, usually with a "Cancelled" error.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation holding this token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Progress for CancelToken {
+    fn file_done(&self, _done: usize, _total: usize, _path: &Path) {}
+
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Shared completion counter that forwards to an optional `Progress`
+pub(crate) struct ProgressCounter<'a> {
+    progress: Option<&'a dyn Progress>,
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl<'a> ProgressCounter<'a> {
+    /// Start counting a batch of `total` files
+    pub(crate) fn new(progress: Option<&'a dyn Progress>, total: usize) -> Self {
+        if let Some(progress) = progress {
+            progress.started(total);
+        }
+        Self {
+            progress,
+            done: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    /// Record that `path` is finished
+    pub(crate) fn tick(&self, path: &Path) {
+        if let Some(progress) = self.progress {
+            let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+            progress.file_done(done, self.total, path);
+        }
+    }
+
+    /// Log a warning and forward it to the receiver
+    pub(crate) fn warn(&self, warning: Warning) {
+        warning.log();
+        if let Some(progress) = self.progress {
+            progress.warning(&warning);
+        }
+    }
+
+    /// Whether the receiver asked to stop
+    pub(crate) fn cancelled(&self) -> bool {
+        self.progress.is_some_and(|p| p.is_cancelled())
+    }
+}
+
+/// Rate limit for per-file events
+pub(crate) struct Throttle {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Whether the event for `done` of `total` should be delivered;
+    /// the first and the final event always are
+    pub(crate) fn due(&self, done: usize, total: usize) -> bool {
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        if done < total && last.is_some_and(|at| now.duration_since(at) < self.interval) {
+            return false;
+        }
+        *last = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder(Mutex<Vec<(usize, usize)>>);
+
+    impl Progress for Recorder {
+        fn file_done(&self, done: usize, total: usize, _path: &Path) {
+            self.0.lock().unwrap().push((done, total));
+        }
+    }
+
+    #[test]
+    fn test_counter_counts_up_to_total() {
+        let recorder = Recorder(Mutex::new(Vec::new()));
+        let counter = ProgressCounter::new(Some(&recorder), 2);
+        counter.tick(Path::new("a"));
+        counter.tick(Path::new("b"));
+        assert_eq!(*recorder.0.lock().unwrap(), vec![(1, 2), (2, 2)]);
+
+        // Without a receiver ticking is a no-op
+        ProgressCounter::new(None, 1).tick(Path::new("a"));
+    }
+
+    #[test]
+    fn test_cancel_token_stops_hashing() {
+        let token = CancelToken::new();
+        let held = token.clone();
+        let fanout = Fanout(vec![&held]);
+        assert!(!fanout.is_cancelled());
+        token.cancel();
+        assert!(fanout.is_cancelled());
+
+        let paths = vec![std::path::PathBuf::from("/nonexistent/a.jpg")];
+        let results = crate::cache::hash_paths(
+            &paths,
+            crate::hash::HashAlgorithm::PHash,
+            8,
+            None,
+            Some(&token),
+        );
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_throttle_always_delivers_final() {
+        let throttle = Throttle::new(Duration::from_secs(3600));
+        assert!(throttle.due(1, 3));
+        assert!(!throttle.due(2, 3));
+        assert!(throttle.due(3, 3));
+    }
+}
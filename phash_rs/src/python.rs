@@ -0,0 +1,5731 @@
+//! Python Bindings
+//!
+//! PyO3 wrappers around the library, built with the `python` feature (on
+//! by default, and always for wheels built with maturin). Functions take
+//! and return plain Python types; errors become Python exceptions.
+
+#![allow(clippy::useless_conversion)]
+
+use crate::duplicate::sort_groups;
+use crate::hash::image_from_raw;
+use crate::*;
+use image::DynamicImage;
+use numpy::{IntoPyArray, PyArray2, PyArrayMethods};
+use pyo3::conversion::ToPyObject;
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBytes};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Parse the `algorithm` argument shared by the Python functions.
+fn parse_algorithm(algorithm: &str) -> PyResult<HashAlgorithm> {
+    algorithm
+        .parse()
+        .map_err(|e: String| pyo3::exceptions::PyValueError::new_err(e))
+}
+
+/// Parse the `bit_order` argument shared by the Python functions.
+fn parse_bit_order(bit_order: &str) -> PyResult<BitOrder> {
+    bit_order
+        .parse()
+        .map_err(|e: String| pyo3::exceptions::PyValueError::new_err(e))
+}
+
+fn parse_frame_sampling(frames: &str) -> PyResult<FrameSampling> {
+    frames
+        .parse()
+        .map_err(|e: String| pyo3::exceptions::PyValueError::new_err(e))
+}
+
+/// Run a batch function without the GIL, on a pool of `num_threads`
+/// threads or as set with set_num_threads.
+fn run_batch<R, F>(py: Python<'_>, num_threads: Option<usize>, f: F) -> PyResult<R>
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    py.allow_threads(|| with_threads(num_threads, f))
+        .map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+/// How often a cancellable batch checks for Ctrl+C
+const SIGNAL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// `run_batch` for long scans that Ctrl+C should stop
+///
+/// `f` runs on a separate thread while this one waits without the GIL,
+/// checking for signals every `SIGNAL_INTERVAL`. When a signal handler
+/// raises (KeyboardInterrupt for Ctrl+C), `cancel` is set and `f` winds
+/// down; its result is returned together with the exception, for the
+/// caller to keep what was finished before re-raising.
+fn run_cancellable<R, F>(
+    py: Python<'_>,
+    num_threads: Option<usize>,
+    cancel: &CancelToken,
+    f: F,
+) -> PyResult<(R, Option<PyErr>)>
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    let (tx, rx) = std::sync::mpsc::channel();
+    // Receivers aren't Sync, and allow_threads needs a Send closure
+    let rx = Mutex::new(rx);
+    std::thread::scope(|scope| {
+        let worker = scope.spawn(move || {
+            let _ = tx.send(with_threads(num_threads, f));
+        });
+        let mut interrupt = None;
+        loop {
+            let received = py.allow_threads(|| {
+                rx.lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .recv_timeout(SIGNAL_INTERVAL)
+            });
+            match received {
+                Ok(result) => {
+                    let result = result.map_err(pyo3::exceptions::PyValueError::new_err)?;
+                    return Ok((result, interrupt));
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                    if interrupt.is_none() {
+                        if let Err(e) = py.check_signals() {
+                            cancel.cancel();
+                            interrupt = Some(e);
+                        }
+                    }
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                    // Only a panic ends the worker without sending
+                    match worker.join() {
+                        Err(panic) => std::panic::resume_unwind(panic),
+                        Ok(()) => unreachable!("worker exited without a result"),
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Build the preprocessing and pHash options shared by the hashing
+/// functions.
+fn hash_options(
+    trim_borders: bool,
+    center_crop: Option<f32>,
+    normalize_gamma: bool,
+    mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+    alpha_background: Option<(u8, u8, u8)>,
+    dct_size: usize,
+    filter: &str,
+) -> PyResult<HashOptions> {
+    let mask_regions = mask_regions
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(x, y, width, height)| Region {
+            x,
+            y,
+            width,
+            height,
+        })
+        .collect();
+    let options = HashOptions {
+        alpha_background: alpha_background.map(|(r, g, b)| image::Rgb([r, g, b])),
+        mask_regions,
+        trim_borders,
+        center_crop,
+        normalize_gamma,
+        phash: PHashConfig {
+            dct_size,
+            filter: parse_filter(filter).map_err(pyo3::exceptions::PyValueError::new_err)?,
+        },
+        apply_exif_orientation: None,
+    };
+    options.validate().map_err(hash_err)?;
+    Ok(options)
+}
+
+/// Forwards progress to a Python callable as `callback(done, total, path)`
+///
+/// The GIL is re-acquired for each call. If the callback raises, the
+/// exception is kept, the operation is cancelled and the exception is
+/// re-raised to the caller once the work has stopped.
+struct CallbackProgress {
+    callback: PyObject,
+    throttle: progress::Throttle,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl CallbackProgress {
+    /// Minimum time between two callback invocations
+    const INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+    fn new(callback: PyObject) -> Self {
+        Self {
+            callback,
+            throttle: progress::Throttle::new(Self::INTERVAL),
+            error: Mutex::new(None),
+        }
+    }
+
+    /// The exception raised by the callback, if any
+    fn take_error(&self) -> Option<PyErr> {
+        self.error.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+impl Progress for CallbackProgress {
+    fn file_done(&self, done: usize, total: usize, path: &Path) {
+        if self.is_cancelled() || !self.throttle.due(done, total) {
+            return;
+        }
+        Python::with_gil(|py| {
+            if let Err(e) = self.callback.call1(py, (done, total, path)) {
+                let mut error = self.error.lock().unwrap_or_else(|e| e.into_inner());
+                error.get_or_insert(e);
+            }
+        });
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+}
+
+/// Frames from a Python callable `(path, count) -> [(position, image)]`
+///
+/// Images are PIL images or numpy arrays as accepted by `compute_hash_image`.
+/// Exceptions raised by the callable fail only the clip being hashed.
+struct CallbackFrames {
+    callback: PyObject,
+}
+
+impl FrameProvider for CallbackFrames {
+    fn frames(&self, path: &Path, count: usize) -> Result<Vec<(f64, DynamicImage)>, HashError> {
+        Python::with_gil(|py| {
+            let invalid = |e: PyErr| HashError::InvalidInput(e.to_string());
+            let frames: Vec<(f64, PyObject)> = self
+                .callback
+                .call1(py, (path, count))
+                .and_then(|frames| frames.extract(py))
+                .map_err(|e| HashError::Decode(e.to_string()))?;
+
+            frames
+                .into_iter()
+                .map(|(pos, image)| {
+                    let image = image.bind(py);
+                    let (width, height, channels) = pixel_layout(image).map_err(invalid)?;
+                    let data = image.call_method0("tobytes").map_err(invalid)?;
+                    let pixels = data
+                        .downcast::<PyBytes>()
+                        .map_err(|e| invalid(e.into()))?
+                        .as_bytes();
+                    Ok((pos, image_from_raw(width, height, channels, pixels)?))
+                })
+                .collect()
+        })
+    }
+}
+
+/// Compute the perceptual hash of an image file.
+///
+/// Args:
+///     path: Path to the image file
+///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash",
+///                "blockhash", "marrhildreth" (hash_size 24) or
+///                "radialvariance" (hash_size = coefficients, 40 in libphash)
+///     frames: Frames of animated GIF/WebP/PNG files to hash: "first"
+///             (default), "leading:<n>" or "evenly:<n>". Frame hashes are
+///             concatenated, so only compare hashes made with the same value.
+///     check_rotations: Hash all 8 rotations and mirror images, for
+///                      hamming_distance(..., check_rotations=True)
+///                      (default: False; not combinable with frames)
+///     trim_borders: Trim uniform borders (scan margins, letterbox bars)
+///                   before hashing (default: False)
+///     center_crop: Hash only the centered part of the image, this
+///                  percentage of its width and height, 1 to 100
+///                  (optional)
+///     normalize_gamma: Bring the mean brightness to mid-gray before
+///                      hashing, so under- and overexposed copies match
+///                      (default: False)
+///     mask_regions: (x, y, width, height) rectangles in image pixels to
+///                   fill with flat gray before hashing, for areas that
+///                   change between otherwise identical screenshots
+///                   (clocks, counters; optional)
+///     alpha_background: (r, g, b) color to composite transparent images
+///                       onto before hashing, so the color left under
+///                       transparent pixels doesn't change the hash
+///                       (optional; by default alpha is dropped)
+///     dct_size: Side of the grayscale square pHash and colorHash run their
+///               DCT on, 2 to 256 and at least hash_size (default: 32);
+///               16 is faster, 64 keeps more detail
+///     filter: Resize filter for that square: "nearest", "triangle",
+///             "catmullrom", "gaussian" or "lanczos3" (default). Hashes
+///             only compare with hashes of the same dct_size and filter.
+///     bit_order: "native" (default) or "imagehash" to compute and write
+///                the hash as the Python imagehash package does, so it
+///                compares directly with hashes stored by imagehash.phash
+///                or imagehash.whash. Not combinable with frames,
+///                check_rotations or preprocessing.
+///     apply_exif_orientation: Turn the image upright by its EXIF
+///                             orientation before hashing, or not (default:
+///                             as set by set_exif_orientation). Counts as
+///                             preprocessing.
+///
+/// Returns:
+///     Hex string representation of the hash
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    hash_size = 8,
+    algorithm = "phash",
+    frames = "first",
+    check_rotations = false,
+    trim_borders = false,
+    center_crop = None,
+    normalize_gamma = false,
+    mask_regions = None,
+    alpha_background = None,
+    dct_size = DCT_SIZE,
+    filter = "lanczos3",
+    bit_order = "native",
+    apply_exif_orientation = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn compute_hash(
+    py: Python<'_>,
+    path: PathBuf,
+    hash_size: usize,
+    algorithm: &str,
+    frames: &str,
+    check_rotations: bool,
+    trim_borders: bool,
+    center_crop: Option<f32>,
+    normalize_gamma: bool,
+    mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+    alpha_background: Option<(u8, u8, u8)>,
+    dct_size: usize,
+    filter: &str,
+    bit_order: &str,
+    apply_exif_orientation: Option<bool>,
+) -> PyResult<String> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let sampling = parse_frame_sampling(frames)?;
+    let options = HashOptions {
+        apply_exif_orientation,
+        ..hash_options(
+            trim_borders,
+            center_crop,
+            normalize_gamma,
+            mask_regions,
+            alpha_background,
+            dct_size,
+            filter,
+        )?
+    };
+    let bit_order = parse_bit_order(bit_order)?;
+    if bit_order != BitOrder::Native {
+        if check_rotations || sampling != FrameSampling::First || !options.is_identity() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "bit_order=\"imagehash\" cannot be combined with frames, check_rotations or preprocessing",
+            ));
+        }
+        let hash = py
+            .allow_threads(|| ImageHash::from_path_ordered(path, algorithm, hash_size, bit_order))
+            .map_err(hash_err)?;
+        return Ok(hash.to_hex_ordered(bit_order));
+    }
+    if check_rotations && sampling != FrameSampling::First {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "check_rotations cannot be combined with frames",
+        ));
+    }
+    if !options.is_identity() && sampling != FrameSampling::First {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "preprocessing cannot be combined with frames",
+        ));
+    }
+    let hash = py
+        .allow_threads(|| {
+            if check_rotations {
+                ImageHash::dihedral_from_image_with(
+                    &options.open(path)?,
+                    algorithm,
+                    hash_size,
+                    &options,
+                )
+            } else if !options.is_identity() {
+                ImageHash::from_path_with(path, algorithm, hash_size, &options)
+            } else {
+                ImageHash::from_path_frames(path, algorithm, hash_size, sampling)
+            }
+        })
+        .map_err(hash_err)?;
+
+    Ok(hash.to_hex())
+}
+
+/// Compute the perceptual hash of an encoded image held in memory.
+///
+/// Args:
+///     data: Encoded image file contents (JPEG, PNG, ...)
+///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash",
+///                "blockhash", "marrhildreth" (hash_size 24) or
+///                "radialvariance" (hash_size = coefficients, 40 in libphash)
+///     trim_borders, center_crop, normalize_gamma, mask_regions,
+///     alpha_background, dct_size, filter:
+///         Preprocessing, as for compute_hash
+///
+/// Returns:
+///     Hex string representation of the hash
+#[pyfunction]
+#[pyo3(signature = (
+    data,
+    hash_size = 8,
+    algorithm = "phash",
+    trim_borders = false,
+    center_crop = None,
+    normalize_gamma = false,
+    mask_regions = None,
+    alpha_background = None,
+    dct_size = DCT_SIZE,
+    filter = "lanczos3"
+))]
+#[allow(clippy::too_many_arguments)]
+fn compute_hash_bytes(
+    py: Python<'_>,
+    data: &[u8],
+    hash_size: usize,
+    algorithm: &str,
+    trim_borders: bool,
+    center_crop: Option<f32>,
+    normalize_gamma: bool,
+    mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+    alpha_background: Option<(u8, u8, u8)>,
+    dct_size: usize,
+    filter: &str,
+) -> PyResult<String> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let options = hash_options(
+        trim_borders,
+        center_crop,
+        normalize_gamma,
+        mask_regions,
+        alpha_background,
+        dct_size,
+        filter,
+    )?;
+    let hash = py
+        .allow_threads(|| {
+            let img =
+                image::load_from_memory(data).map_err(|e| HashError::Decode(e.to_string()))?;
+            ImageHash::from_image_with(&img, algorithm, hash_size, &options)
+        })
+        .map_err(hash_err)?;
+
+    Ok(hash.to_hex())
+}
+
+/// Compute the perceptual hash of a decoded PIL image or numpy array.
+///
+/// PIL images must be in mode "L", "RGB" or "RGBA". Arrays must have dtype
+/// uint8 and shape (height, width) or (height, width, channels) with 1, 3
+/// or 4 channels.
+///
+/// Args:
+///     image: PIL.Image.Image or numpy.ndarray
+///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash",
+///                "blockhash", "marrhildreth" (hash_size 24) or
+///                "radialvariance" (hash_size = coefficients, 40 in libphash)
+///     trim_borders, center_crop, normalize_gamma, mask_regions,
+///     alpha_background, dct_size, filter:
+///         Preprocessing, as for compute_hash
+///
+/// Returns:
+///     Hex string representation of the hash
+#[pyfunction]
+#[pyo3(signature = (
+    image,
+    hash_size = 8,
+    algorithm = "phash",
+    trim_borders = false,
+    center_crop = None,
+    normalize_gamma = false,
+    mask_regions = None,
+    alpha_background = None,
+    dct_size = DCT_SIZE,
+    filter = "lanczos3"
+))]
+#[allow(clippy::too_many_arguments)]
+fn compute_hash_image(
+    py: Python<'_>,
+    image: &Bound<'_, PyAny>,
+    hash_size: usize,
+    algorithm: &str,
+    trim_borders: bool,
+    center_crop: Option<f32>,
+    normalize_gamma: bool,
+    mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+    alpha_background: Option<(u8, u8, u8)>,
+    dct_size: usize,
+    filter: &str,
+) -> PyResult<String> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let options = hash_options(
+        trim_borders,
+        center_crop,
+        normalize_gamma,
+        mask_regions,
+        alpha_background,
+        dct_size,
+        filter,
+    )?;
+    let (width, height, channels) = pixel_layout(image)?;
+
+    // tobytes() yields a C-ordered copy, also for non-contiguous arrays.
+    // The buffer protocol is not part of the abi3 API before Python 3.11.
+    let data = image.call_method0("tobytes")?;
+    let pixels = data.downcast::<PyBytes>()?.as_bytes();
+    let hash = py
+        .allow_threads(|| {
+            let img = image_from_raw(width, height, channels, pixels)?;
+            ImageHash::from_image_with(&img, algorithm, hash_size, &options)
+        })
+        .map_err(hash_err)?;
+
+    Ok(hash.to_hex())
+}
+
+/// DCT coefficients of an image, as computed for its pHash.
+///
+/// The image is reduced to a 32x32 grayscale image and transformed; the
+/// lowest frequencies are top-left. A pHash of size n compares the
+/// top-left n x n block, minus the DC term at [0, 0], with its median.
+///
+/// Args:
+///     path: Path to the image file
+///
+/// Returns:
+///     numpy.ndarray of float64 with shape (32, 32), owning the Rust
+///     buffer without a copy
+#[pyfunction]
+fn dct_coefficients<'py>(py: Python<'py>, path: PathBuf) -> PyResult<Bound<'py, PyArray2<f64>>> {
+    let dct = py
+        .allow_threads(|| open_image(&path).map(|img| ImageHash::dct_coefficients(&img)))
+        .map_err(hash_err)?;
+    dct.into_pyarray_bound(py).reshape([DCT_SIZE, DCT_SIZE])
+}
+
+/// Width, height and channel count of a PIL image or numpy array
+fn pixel_layout(image: &Bound<'_, PyAny>) -> PyResult<(u32, u32, u8)> {
+    if image.hasattr("mode")? && image.hasattr("size")? {
+        let mode: String = image.getattr("mode")?.extract()?;
+        let channels = match mode.as_str() {
+            "L" => 1,
+            "RGB" => 3,
+            "RGBA" => 4,
+            _ => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Unsupported image mode {:?}; convert to \"RGB\" first",
+                    mode
+                )))
+            }
+        };
+        let (width, height): (u32, u32) = image.getattr("size")?.extract()?;
+        return Ok((width, height, channels));
+    }
+
+    if image.hasattr("shape")? && image.hasattr("dtype")? {
+        let dtype: String = image.getattr("dtype")?.getattr("name")?.extract()?;
+        if dtype != "uint8" {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Array dtype must be uint8, got {}",
+                dtype
+            )));
+        }
+        let shape: Vec<usize> = image.getattr("shape")?.extract()?;
+        let (height, width, channels) = match shape[..] {
+            [h, w] => (h, w, 1),
+            [h, w, c @ (1 | 3 | 4)] => (h, w, c),
+            _ => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Array shape must be (h, w) or (h, w, 1|3|4), got {:?}",
+                    shape
+                )))
+            }
+        };
+        let dim = |n: usize| {
+            u32::try_from(n)
+                .map_err(|_| pyo3::exceptions::PyValueError::new_err("Array is too large"))
+        };
+        return Ok((dim(width)?, dim(height)?, channels as u8));
+    }
+
+    Err(pyo3::exceptions::PyTypeError::new_err(
+        "Expected a PIL image or a numpy array",
+    ))
+}
+
+/// Parse a hash given as hex or in the tagged form of ImageHash.to_tagged
+fn parse_hash_text(text: &str) -> PyResult<ImageHash> {
+    if text.contains(':') {
+        ImageHash::from_tagged(text).map_err(hash_err)
+    } else {
+        ImageHash::from_hex(text).map_err(hash_err)
+    }
+}
+
+/// Compute the Hamming distance between two hash strings.
+///
+/// Args:
+///     hash1: First hash as hex string, or tagged as by ImageHash.to_tagged
+///     hash2: Second hash, likewise
+///     check_rotations: Both hashes come from
+///                      compute_hash(..., check_rotations=True); return the
+///                      smallest distance over all rotations and mirror
+///                      images (default: False)
+///
+/// Returns:
+///     Number of differing bits (0 = identical, higher = more different).
+///     Raises ValueError if the hashes differ in length, or if a tagged
+///     hash meets one of another algorithm or hash size.
+#[pyfunction]
+#[pyo3(signature = (hash1, hash2, check_rotations = false))]
+fn hamming_distance(hash1: &str, hash2: &str, check_rotations: bool) -> PyResult<u32> {
+    let h1 = parse_hash_text(hash1)?;
+    let h2 = parse_hash_text(hash2)?;
+
+    let distance = h1.checked_distance(&h2).map_err(hash_err)?;
+    if check_rotations {
+        Ok(h1.dihedral_distance(&h2))
+    } else {
+        Ok(distance)
+    }
+}
+
+/// Peak cross-correlation of two radial variance hashes, as libphash's
+/// ph_crosscorr.
+///
+/// Args:
+///     hash1: First hash from algorithm="radialvariance", as hex string or
+///            tagged as by ImageHash.to_tagged
+///     hash2: Second hash, likewise
+///
+/// Returns:
+///     0.0 to 1.0 for identical hashes; libphash calls values above 0.9 a
+///     match. Raises ValueError for hashes of different lengths or of
+///     another algorithm.
+#[pyfunction]
+fn cross_correlation(hash1: &str, hash2: &str) -> PyResult<f64> {
+    let h1 = parse_hash_text(hash1)?;
+    let h2 = parse_hash_text(hash2)?;
+
+    h1.cross_correlation(&h2).map_err(hash_err)
+}
+
+/// Parse a weights argument: "low_frequency", "uniform" or a list of
+/// per-bit weights
+fn parse_weights(weights: &Bound<'_, PyAny>, hash_size: usize) -> PyResult<BitWeights> {
+    if let Ok(name) = weights.extract::<String>() {
+        return match name.as_str() {
+            "low_frequency" => Ok(BitWeights::low_frequency(hash_size)),
+            "uniform" => Ok(BitWeights::uniform()),
+            _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "Unknown weights: {} (expected \"low_frequency\", \"uniform\" or a list)",
+                name
+            ))),
+        };
+    }
+    BitWeights::new(weights.extract()?).map_err(hash_err)
+}
+
+/// Hamming distance with each differing bit counted by its weight.
+///
+/// Args:
+///     hash1: First hash (hex string)
+///     hash2: Second hash (hex string)
+///     weights: "low_frequency" (default) to favour the low-frequency bits
+///              of a pHash, "uniform", or a list with one positive weight
+///              per bit in to_bits order; bits past its end weigh 1.0
+///
+/// Returns:
+///     Sum of the weights of the differing bits
+#[pyfunction]
+#[pyo3(signature = (hash1, hash2, weights = None))]
+fn weighted_hamming_distance(
+    hash1: &str,
+    hash2: &str,
+    weights: Option<&Bound<'_, PyAny>>,
+) -> PyResult<f64> {
+    let h1 = ImageHash::from_hex(hash1).map_err(hash_err)?;
+    let h2 = ImageHash::from_hex(hash2).map_err(hash_err)?;
+    let weights = match weights {
+        Some(weights) => parse_weights(weights, h1.hash_size())?,
+        None => BitWeights::low_frequency(h1.hash_size()),
+    };
+
+    Ok(h1.weighted_distance(&h2, &weights))
+}
+
+/// Compute Hamming distances between many hashes at once.
+///
+/// Much faster than calling hamming_distance in a loop: hashes are parsed
+/// once and compared in batches with SIMD where the CPU supports it.
+///
+/// Args:
+///     hashes: Hashes as hex strings
+///     query: Compare only this hash with each of `hashes` (optional)
+///
+/// Returns:
+///     Without query, the distance matrix: a list of rows, rows and columns
+///     in the order of `hashes`. With query, the distance from the query
+///     to each hash.
+#[pyfunction(name = "distance_matrix")]
+#[pyo3(signature = (hashes, query = None))]
+fn py_distance_matrix(
+    py: Python<'_>,
+    hashes: Vec<String>,
+    query: Option<&str>,
+) -> PyResult<PyObject> {
+    let hashes = hashes
+        .iter()
+        .map(|h| ImageHash::from_hex(h))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(hash_err)?;
+    match query {
+        Some(query) => {
+            let query = ImageHash::from_hex(query).map_err(hash_err)?;
+            Ok(run_batch(py, None, || query.distances_to_many(&hashes))?.to_object(py))
+        }
+        None => Ok(run_batch(py, None, || distance_matrix(&hashes))?.to_object(py)),
+    }
+}
+
+/// Compute the distance from one hash to many stored hex hashes.
+///
+/// For hashes kept as text elsewhere, such as a database column: the
+/// candidates are parsed and compared in parallel without creating an
+/// ImageHash per row, and the GIL is released meanwhile.
+///
+/// Args:
+///     query_hex: Query hash as hex string
+///     candidate_hexes: Hashes to compare with, as hex strings
+///
+/// Returns:
+///     Distance to each candidate, in order. Raises ValueError naming the
+///     first candidate that is not valid hex.
+#[pyfunction]
+fn distances(py: Python<'_>, query_hex: &str, candidate_hexes: Vec<String>) -> PyResult<Vec<u32>> {
+    let query = ImageHash::from_hex(query_hex).map_err(hash_err)?;
+    py.allow_threads(|| hex_distances(&query, &candidate_hexes))
+        .map_err(hash_err)
+}
+
+/// Find the stored hex hashes within a distance of a query hash.
+///
+/// Args:
+///     query_hex: Query hash as hex string
+///     candidates: Hashes to search, as hex strings
+///     threshold: Maximum Hamming distance to keep
+///
+/// Returns:
+///     List of (index, distance) tuples for the candidates within
+///     threshold, in candidate order; index points into candidates.
+#[pyfunction]
+fn filter_within(
+    py: Python<'_>,
+    query_hex: &str,
+    candidates: Vec<String>,
+    threshold: u32,
+) -> PyResult<Vec<(usize, u32)>> {
+    let query = ImageHash::from_hex(query_hex).map_err(hash_err)?;
+    py.allow_threads(|| hex_within(&query, &candidates, threshold))
+        .map_err(hash_err)
+}
+
+/// Compute the distance between every two hashes, tile by tile.
+///
+/// For sets whose full distance matrix would not fit in memory: the
+/// matrix is computed in tiles of at most about max_memory_mb, with the
+/// GIL released, and only the pairs passing threshold are kept. Each
+/// unordered pair is reported once, as (a, b, distance) with a < b.
+///
+/// Args:
+///     hashes: Hashes as hex strings
+///     max_memory_mb: Memory to spend per tile, in MiB (default: 256)
+///     threshold: Keep only pairs at most this far apart, or more than
+///                this far apart with above=True (default: None, every
+///                pair)
+///     above: Keep the pairs beyond threshold instead (default: False)
+///     callback: Called as callback(pairs) with the list of pairs of each
+///               tile, ordered by a then b within the tile (default: None)
+///     output_path: Write the pairs to this CSV file, with columns
+///                  a,b,distance, instead of returning them (default: None)
+///
+/// Returns:
+///     The list of pairs, or the number of pairs passed on when callback
+///     or output_path is given. Exceptions raised by the callback stop the
+///     computation and propagate.
+#[pyfunction(name = "pairwise_distances")]
+#[pyo3(signature = (hashes, max_memory_mb = 256, threshold = None, above = false, callback = None, output_path = None))]
+fn py_pairwise_distances(
+    py: Python<'_>,
+    hashes: Vec<String>,
+    max_memory_mb: usize,
+    threshold: Option<u32>,
+    above: bool,
+    callback: Option<PyObject>,
+    output_path: Option<PathBuf>,
+) -> PyResult<PyObject> {
+    let filter = match (threshold, above) {
+        (None, false) => PairFilter::All,
+        (None, true) => {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "above=True requires a threshold",
+            ))
+        }
+        (Some(t), false) => PairFilter::Within(t),
+        (Some(t), true) => PairFilter::Beyond(t),
+    };
+    if max_memory_mb == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "max_memory_mb must be at least 1",
+        ));
+    }
+    let max_memory = max_memory_mb.saturating_mul(1 << 20);
+    let hashes = hashes
+        .iter()
+        .map(|h| ImageHash::from_hex(h))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(hash_err)?;
+    let as_tuples = |pairs: &[PairDistance]| -> Vec<(usize, usize, u32)> {
+        pairs.iter().map(|p| (p.a, p.b, p.distance)).collect()
+    };
+    match (callback, output_path) {
+        (Some(_), Some(_)) => Err(pyo3::exceptions::PyValueError::new_err(
+            "Pass either callback or output_path, not both",
+        )),
+        (Some(callback), None) => {
+            let mut passed = 0u64;
+            py.allow_threads(|| {
+                pairwise_distances(&hashes, max_memory, filter, |pairs| {
+                    passed += pairs.len() as u64;
+                    Python::with_gil(|py| callback.call1(py, (as_tuples(pairs),)).map(drop))
+                })
+            })?;
+            Ok(passed.to_object(py))
+        }
+        (None, Some(path)) => Ok(py
+            .allow_threads(|| write_pairwise_distances(&hashes, max_memory, filter, &path))
+            .map_err(pyo3::exceptions::PyIOError::new_err)?
+            .to_object(py)),
+        (None, None) => {
+            let mut all = Vec::new();
+            py.allow_threads(|| {
+                pairwise_distances(&hashes, max_memory, filter, |pairs| {
+                    all.extend(as_tuples(pairs));
+                    Ok::<_, std::convert::Infallible>(())
+                })
+            })
+            .unwrap_or_else(|never| match never {});
+            Ok(all.to_object(py))
+        }
+    }
+}
+
+/// Check if two images are perceptually similar.
+///
+/// Args:
+///     path1: Path to first image
+///     path2: Path to second image
+///     threshold: Maximum Hamming distance to consider similar (default: 10)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"; or "ensemble": similar when at least two
+///                of phash, whash and blockhash, all computed from one
+///                decode per image, are within threshold (index and
+///                hash_size then don't apply)
+///     index: HashIndex whose stored hashes are used for paths it contains,
+///            so e.g. a fixed reference image is not decoded on every call;
+///            must have been built with the same algorithm (default: None)
+///     hash_size: Size of the hashes (default: 8, 64-bit hashes)
+///
+/// Returns:
+///     True if images are similar, False otherwise. Raises ValueError if
+///     the two hashes differ in length, e.g. index holds another size.
+#[pyfunction]
+#[pyo3(signature = (path1, path2, threshold = 10, algorithm = "phash", index = None, hash_size = 8))]
+fn are_similar(
+    py: Python<'_>,
+    path1: PathBuf,
+    path2: PathBuf,
+    threshold: u32,
+    algorithm: &str,
+    index: Option<PyRef<'_, HashIndex>>,
+    hash_size: usize,
+) -> PyResult<bool> {
+    if algorithm.eq_ignore_ascii_case("ensemble") {
+        if index.is_some() || hash_size != DEFAULT_HASH_SIZE {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "algorithm=\"ensemble\" cannot be combined with index or hash_size",
+            ));
+        }
+        let rule = MatchRule::ensemble(threshold);
+        let (hashes1, hashes2) =
+            py.allow_threads(|| (rule.hash_path(&path1), rule.hash_path(&path2)));
+        let hashes1 = hashes1.map_err(hash_err)?;
+        let hashes2 = hashes2.map_err(hash_err)?;
+        return Ok(rule.matches(&hashes1, &hashes2));
+    }
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let index = index.as_deref();
+    let (hash1, hash2) = py.allow_threads(|| {
+        (
+            indexed_or_computed(index, &path1, algorithm, hash_size),
+            indexed_or_computed(index, &path2, algorithm, hash_size),
+        )
+    });
+    let hash1 = hash1.map_err(hash_err)?;
+    let hash2 = hash2.map_err(hash_err)?;
+
+    Ok(hash1.checked_distance(&hash2).map_err(hash_err)? <= threshold)
+}
+
+/// Explain why two images are, or are not, similar.
+///
+/// Meant for reviewing borderline pairs: besides the distance it reports
+/// which bits agree, which DCT frequencies the differing bits come from,
+/// and which quadrant of the picture changed. DCT bits describe the whole
+/// image, so quadrants are hashed on their own to locate a change.
+///
+/// Args:
+///     path1: Path to first image
+///     path2: Path to second image
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"
+///     hash_size: Size of the hashes (default: 8, 64-bit hashes)
+///     heatmap: Also return the absolute grayscale difference of the two
+///              images reduced to 32x32 (default: False)
+///
+/// Returns:
+///     Dict with "distance", "agreement" (list of bool per hash bit, in
+///     the order of ImageHash.to_bits), "frequency_bands" (pHash and
+///     colorHash: list of (differing, total) bits per band, band k holding
+///     the coefficients whose larger of row and column is k; else None),
+///     "quadrants" (dict of "top_left", "top_right", "bottom_left" and
+///     "bottom_right" to the distance of that quadrant's hashes) and
+///     "heatmap" (numpy.ndarray of uint8 with shape (32, 32), or None)
+#[pyfunction]
+#[pyo3(signature = (path1, path2, algorithm = "phash", hash_size = 8, heatmap = false))]
+fn compare_detailed(
+    py: Python<'_>,
+    path1: PathBuf,
+    path2: PathBuf,
+    algorithm: &str,
+    hash_size: usize,
+    heatmap: bool,
+) -> PyResult<PyObject> {
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let comparison = py
+        .allow_threads(|| compare_paths(path1, path2, algorithm, hash_size, heatmap))
+        .map_err(hash_err)?;
+
+    let quadrants: HashMap<&str, u32> = ["top_left", "top_right", "bottom_left", "bottom_right"]
+        .into_iter()
+        .zip(comparison.quadrant_distances)
+        .collect();
+    let heatmap = match comparison.heatmap {
+        Some(map) => {
+            let side = map.width() as usize;
+            map.into_raw()
+                .into_pyarray_bound(py)
+                .reshape([side, side])?
+                .to_object(py)
+        }
+        None => py.None(),
+    };
+    let mut result: HashMap<&str, PyObject> = HashMap::new();
+    result.insert("distance", comparison.distance.to_object(py));
+    result.insert("agreement", comparison.agreement.to_object(py));
+    result.insert("frequency_bands", comparison.frequency_bands.to_object(py));
+    result.insert("quadrants", quadrants.to_object(py));
+    result.insert("heatmap", heatmap);
+    Ok(result.to_object(py))
+}
+
+/// Check whether an image has a near-duplicate in an index.
+///
+/// Args:
+///     path: Path to the image
+///     index: HashIndex of the library to check against
+///     threshold: Maximum Hamming distance to consider similar (default: 10)
+///     algorithm: Algorithm the index was built with; the image is only
+///                decoded if `path` is not in the index itself
+///     hash_size: Hash size the index was built with (default: 8)
+///
+/// Returns:
+///     True if an indexed entry other than `path` is within `threshold`
+#[pyfunction]
+#[pyo3(signature = (path, index, threshold = 10, algorithm = "phash", hash_size = 8))]
+fn has_duplicate(
+    py: Python<'_>,
+    path: PathBuf,
+    index: PyRef<'_, HashIndex>,
+    threshold: u32,
+    algorithm: &str,
+    hash_size: usize,
+) -> PyResult<bool> {
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let index = &*index;
+    let hash = py
+        .allow_threads(|| indexed_or_computed(Some(index), &path, algorithm, hash_size))
+        .map_err(hash_err)?;
+
+    Ok(py.allow_threads(|| {
+        index
+            .index
+            .tree
+            .query(&hash, threshold)
+            .iter()
+            .any(|(indexed, _)| **indexed != path)
+    }))
+}
+
+/// Find the images most similar to a query image.
+///
+/// The query is hashed once and compared with every candidate, so this
+/// suits looking up one photo in a library; use find_duplicate_images to
+/// compare all images with each other.
+///
+/// Args:
+///     query: Path to the query image
+///     candidates: List of image paths to hash, or a dict of precomputed
+///                 {path: hash} as returned by compute_hashes_parallel
+///     threshold: Maximum Hamming distance to report (default: 10)
+///     max_results: Return at most this many matches (optional)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"; must match precomputed hashes
+///     hash_size: Size of the hashes (default: 8, 64-bit hashes); must
+///                match precomputed hashes
+///     cache_path: Hash cache file for candidate paths (optional), as for
+///                 compute_hashes_parallel
+///     progress_callback: Called as for compute_hashes_parallel while
+///                        hashing candidate paths (optional)
+///     return_errors: Also return the candidates that could not be hashed
+///                    (default: False)
+///
+/// Returns:
+///     List of (path, distance) tuples, closest first. The query itself is
+///     never reported. With return_errors=True, a (matches, errors) tuple
+///     instead, where errors is as for compute_hashes_parallel.
+///
+/// Example:
+///     hashes = phash_rs.compute_hashes_parallel(library)
+///     phash_rs.find_similar("query.jpg", hashes, max_results=5)
+///     # [("2019/img_0042.jpg", 2), ...]
+#[pyfunction(name = "find_similar")]
+#[pyo3(signature = (
+    query,
+    candidates,
+    threshold = 10,
+    max_results = None,
+    algorithm = "phash",
+    hash_size = 8,
+    cache_path = None,
+    progress_callback = None,
+    return_errors = false
+))]
+#[allow(clippy::too_many_arguments)]
+fn py_find_similar(
+    py: Python<'_>,
+    query: PathBuf,
+    candidates: PyObject,
+    threshold: u32,
+    max_results: Option<usize>,
+    algorithm: &str,
+    hash_size: usize,
+    cache_path: Option<PathBuf>,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+) -> PyResult<PyObject> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let query_hash = py
+        .allow_threads(|| ImageHash::from_path(&query, algorithm, hash_size))
+        .map_err(hash_err)?;
+
+    let (matches, failures) = match candidates.extract::<BTreeMap<PathBuf, String>>(py) {
+        Ok(hashes) => {
+            let hashes = hashes
+                .into_iter()
+                .filter(|(path, _)| *path != query)
+                .map(|(path, hex)| Ok((path, ImageHash::from_hex(&hex)?)))
+                .collect::<Result<Vec<_>, HashError>>()
+                .map_err(hash_err)?;
+            let matches = run_batch(py, None, || {
+                find_similar(&query_hash, &hashes, threshold, max_results)
+            })?
+            .map_err(hash_err)?;
+            (matches, HashFailures::new())
+        }
+        Err(_) => {
+            let mut paths: Vec<PathBuf> = candidates.extract(py)?;
+            paths.retain(|p| *p != query);
+            let mut cache = cache_path
+                .as_ref()
+                .map(HashCache::load)
+                .transpose()
+                .map_err(pyo3::exceptions::PyIOError::new_err)?;
+            let callback = progress_callback.map(CallbackProgress::new);
+            let found = run_batch(py, None, || {
+                find_similar_paths(
+                    &query_hash,
+                    &paths,
+                    algorithm,
+                    hash_size,
+                    threshold,
+                    max_results,
+                    cache.as_mut(),
+                    callback.as_ref().map(|c| c as &dyn Progress),
+                )
+            })?;
+            let found = match found {
+                Ok(found) => found,
+                Err(e) => {
+                    return Err(match callback.and_then(|c| c.take_error()) {
+                        Some(raised) => raised,
+                        None => pyo3::exceptions::PyRuntimeError::new_err(e),
+                    })
+                }
+            };
+            if let (Some(cache), Some(cache_path)) = (&cache, &cache_path) {
+                cache
+                    .save(cache_path)
+                    .map_err(pyo3::exceptions::PyIOError::new_err)?;
+            }
+            found
+        }
+    };
+
+    let matches: Vec<(PathBuf, u32)> = matches.into_iter().map(|m| (m.path, m.distance)).collect();
+    if return_errors {
+        Ok((matches, errors_to_dict(failures, None)).to_object(py))
+    } else {
+        Ok(matches.to_object(py))
+    }
+}
+
+/// Hash stored for `path` in `index`, or computed from the file
+fn indexed_or_computed(
+    index: Option<&HashIndex>,
+    path: &Path,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+) -> Result<ImageHash, HashError> {
+    match index.and_then(|index| index.hashes.get(path)) {
+        Some(hash) => Ok(hash.clone()),
+        None => ImageHash::from_path(path, algorithm, hash_size),
+    }
+}
+
+/// A group of duplicate images, as returned by the duplicate finders.
+///
+/// Fields are attributes (`group.paths`, `group.best`). Groups used to be
+/// plain dicts, so `group["paths"]`, `get`, `keys` and `to_dict` work as
+/// well. Groups, or dicts with the same keys, can be passed back to
+/// apply_dedup, export_report and export_results.
+///
+/// Attributes:
+///     paths: Member paths
+///     hash: Representative hash (the medoid member's) as hex
+///     best: Path of the best member (see best_by)
+///     key: Stable key of the member set, for annotate_group
+///     sidecars: {member path: [sidecar paths]} for members with any
+///     content_type: Shared content type with content_aware, else None
+///     hashes: {member path: hex hash}, None if not hashed (videos)
+///     dimensions: {member path: (width, height)} for members of known
+///                 size, None if none is known
+///     distances: {member path: distance to hash} with quality=True, else
+///                None; also None without hashes
+///     max_distance: Largest distance between two members, as distances
+///     mean_distance: Mean distance over member pairs, as distances
+///     pairwise: Member-by-member distance matrix with pairwise=True,
+///               else None
+///     metadata: {member path: {"size", "mtime", "dimensions",
+///               "format"}} with metadata=True, else None
+///     review: {"state", "note"} of the group's annotation with
+///             review_path, else None
+///     new: Members that were among new_paths, for
+///          find_duplicates_incremental, else None
+#[pyclass(name = "DuplicateGroup", get_all, set_all)]
+#[derive(Clone)]
+struct PyDuplicateGroup {
+    paths: Vec<PathBuf>,
+    hash: String,
+    best: PathBuf,
+    key: String,
+    sidecars: BTreeMap<PathBuf, Vec<PathBuf>>,
+    content_type: Option<String>,
+    hashes: Option<BTreeMap<PathBuf, String>>,
+    dimensions: Option<BTreeMap<PathBuf, (u32, u32)>>,
+    distances: Option<BTreeMap<PathBuf, u32>>,
+    max_distance: Option<u32>,
+    mean_distance: Option<f64>,
+    pairwise: Option<Vec<Vec<u32>>>,
+    metadata: Option<BTreeMap<PathBuf, PyFileMetadata>>,
+    review: Option<BTreeMap<String, String>>,
+    new: Option<Vec<PathBuf>>,
+}
+
+/// Field names of `PyDuplicateGroup`, in the order `keys` lists them
+const GROUP_FIELDS: [&str; 15] = [
+    "paths",
+    "hash",
+    "best",
+    "key",
+    "sidecars",
+    "content_type",
+    "hashes",
+    "dimensions",
+    "distances",
+    "max_distance",
+    "mean_distance",
+    "pairwise",
+    "metadata",
+    "review",
+    "new",
+];
+
+impl PyDuplicateGroup {
+    /// The Python form of `g`, without the optional extras
+    fn new(g: &DuplicateGroup) -> Self {
+        PyDuplicateGroup {
+            paths: g.paths.clone(),
+            hash: g.hash.clone(),
+            best: g.best_path.clone(),
+            key: g.key(),
+            sidecars: g.sidecars.clone(),
+            content_type: g.content_type.map(|t| t.to_string()),
+            hashes: (!g.member_hashes.is_empty()).then(|| {
+                g.member_hashes
+                    .iter()
+                    .map(|(path, hash)| (path.clone(), hash.to_hex()))
+                    .collect()
+            }),
+            dimensions: (!g.member_dimensions.is_empty()).then(|| g.member_dimensions.clone()),
+            distances: None,
+            max_distance: None,
+            mean_distance: None,
+            pairwise: None,
+            metadata: None,
+            review: None,
+            new: None,
+        }
+    }
+
+    /// Fill in the distances of `GroupQuality`; they cost a distance
+    /// between every two members, so only callers asking for them pay
+    fn add_quality(&mut self, g: &DuplicateGroup) {
+        if let Some(quality) = g.quality() {
+            self.distances = Some(quality.distances);
+            self.max_distance = Some(quality.max_distance);
+            self.mean_distance = Some(quality.mean_distance);
+        }
+    }
+
+    /// Rebuild a group from a dict with the keys of `GROUP_FIELDS`; only
+    /// "paths", "hash" and "best" are required
+    fn from_dict(py: Python<'_>, dict: &HashMap<String, PyObject>) -> PyResult<Self> {
+        let required = |name: &str| {
+            dict.get(name).ok_or_else(|| {
+                pyo3::exceptions::PyKeyError::new_err(format!("Group is missing \"{}\"", name))
+            })
+        };
+        fn optional<'py, T: FromPyObject<'py> + Default>(
+            py: Python<'py>,
+            dict: &HashMap<String, PyObject>,
+            name: &str,
+        ) -> PyResult<T> {
+            match dict.get(name) {
+                Some(value) => value.bind(py).extract(),
+                None => Ok(T::default()),
+            }
+        }
+        let paths: Vec<PathBuf> = required("paths")?.extract(py)?;
+        Ok(PyDuplicateGroup {
+            key: group_key(&paths),
+            paths,
+            hash: required("hash")?.extract(py)?,
+            best: required("best")?.extract(py)?,
+            sidecars: optional(py, dict, "sidecars")?,
+            content_type: optional(py, dict, "content_type")?,
+            hashes: optional(py, dict, "hashes")?,
+            dimensions: optional(py, dict, "dimensions")?,
+            distances: None,
+            max_distance: None,
+            mean_distance: None,
+            pairwise: None,
+            metadata: optional(py, dict, "metadata")?,
+            review: None,
+            new: None,
+        })
+    }
+
+    /// The group as the library uses it
+    fn to_group(&self) -> PyResult<DuplicateGroup> {
+        let content_type = self
+            .content_type
+            .as_deref()
+            .map(str::parse::<ContentType>)
+            .transpose()
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let member_hashes = self
+            .hashes
+            .iter()
+            .flatten()
+            .map(|(path, hex)| Ok((path.clone(), ImageHash::from_hex(hex)?)))
+            .collect::<Result<_, HashError>>()
+            .map_err(hash_err)?;
+        Ok(DuplicateGroup {
+            paths: self.paths.clone(),
+            hash: self.hash.clone(),
+            best_path: self.best.clone(),
+            sidecars: self.sidecars.clone(),
+            content_type,
+            member_hashes,
+            member_dimensions: self.dimensions.clone().unwrap_or_default(),
+        })
+    }
+
+    fn field(&self, py: Python<'_>, name: &str) -> Option<PyObject> {
+        Some(match name {
+            "paths" => self.paths.to_object(py),
+            "hash" => self.hash.to_object(py),
+            "best" => self.best.to_object(py),
+            "key" => self.key.to_object(py),
+            "sidecars" => self.sidecars.to_object(py),
+            "content_type" => self.content_type.to_object(py),
+            "hashes" => self.hashes.to_object(py),
+            "dimensions" => self.dimensions.to_object(py),
+            "distances" => self.distances.to_object(py),
+            "max_distance" => self.max_distance.to_object(py),
+            "mean_distance" => self.mean_distance.to_object(py),
+            "pairwise" => self.pairwise.to_object(py),
+            "metadata" => self.metadata.to_object(py),
+            "review" => self.review.to_object(py),
+            "new" => self.new.to_object(py),
+            _ => return None,
+        })
+    }
+}
+
+#[pymethods]
+impl PyDuplicateGroup {
+    fn __getitem__(&self, py: Python<'_>, key: &str) -> PyResult<PyObject> {
+        self.field(py, key)
+            .ok_or_else(|| pyo3::exceptions::PyKeyError::new_err(key.to_string()))
+    }
+
+    fn __contains__(&self, key: &str) -> bool {
+        GROUP_FIELDS.contains(&key)
+    }
+
+    /// Field `key`, or `default` if there is no such field.
+    #[pyo3(signature = (key, default = None))]
+    fn get(&self, py: Python<'_>, key: &str, default: Option<PyObject>) -> PyObject {
+        self.field(py, key).or(default).unwrap_or_else(|| py.None())
+    }
+
+    /// Field names, as for a dict.
+    fn keys(&self) -> Vec<&'static str> {
+        GROUP_FIELDS.to_vec()
+    }
+
+    /// The group as a plain dict, e.g. for json.dumps.
+    fn to_dict(&self, py: Python<'_>) -> HashMap<&'static str, PyObject> {
+        GROUP_FIELDS
+            .iter()
+            .filter_map(|&name| Some((name, self.field(py, name)?)))
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "DuplicateGroup(best={:?}, paths={:?})",
+            self.best, self.paths
+        )
+    }
+}
+
+/// `FileMetadata` in its Python form, a dict
+#[derive(Clone)]
+struct PyFileMetadata(FileMetadata);
+
+impl ToPyObject for PyFileMetadata {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        metadata_dict(py, Some(&self.0), self.0.dimensions).to_object(py)
+    }
+}
+
+impl IntoPy<PyObject> for PyFileMetadata {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        self.to_object(py)
+    }
+}
+
+impl<'py> FromPyObject<'py> for PyFileMetadata {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let mtime: Option<f64> = ob.get_item("mtime")?.extract()?;
+        let modified = mtime
+            .map(|secs| {
+                let offset = std::time::Duration::try_from_secs_f64(secs.abs())
+                    .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+                let epoch = std::time::UNIX_EPOCH;
+                Ok::<_, PyErr>(if secs < 0.0 {
+                    epoch - offset
+                } else {
+                    epoch + offset
+                })
+            })
+            .transpose()?;
+        Ok(PyFileMetadata(FileMetadata {
+            size: ob.get_item("size")?.extract()?,
+            modified,
+            dimensions: ob.get_item("dimensions")?.extract()?,
+            format: ob.get_item("format")?.extract()?,
+        }))
+    }
+}
+
+/// {"size", "mtime", "dimensions", "format"} of a file, with mtime in
+/// seconds since the epoch; all but dimensions None without `meta`
+fn metadata_dict(
+    py: Python<'_>,
+    meta: Option<&FileMetadata>,
+    dimensions: Option<(u32, u32)>,
+) -> HashMap<&'static str, PyObject> {
+    let mtime =
+        meta.and_then(|m| m.modified)
+            .map(|t| match t.duration_since(std::time::UNIX_EPOCH) {
+                Ok(d) => d.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64(),
+            });
+    let mut map = HashMap::new();
+    map.insert("size", meta.map(|m| m.size).to_object(py));
+    map.insert("mtime", mtime.to_object(py));
+    map.insert("dimensions", dimensions.to_object(py));
+    map.insert("format", meta.and_then(|m| m.format.clone()).to_object(py));
+    map
+}
+
+/// `DuplicateGroup::metadata` for a group whose paths may have been made
+/// relative to `relative_to`
+fn member_metadata(
+    g: &DuplicateGroup,
+    relative_to: Option<&Path>,
+) -> BTreeMap<PathBuf, PyFileMetadata> {
+    g.paths
+        .par_iter()
+        .filter_map(|path| {
+            let file = match relative_to {
+                Some(root) => root.join(path),
+                None => path.clone(),
+            };
+            let meta = FileMetadata::of(file, g.member_dimensions.get(path).copied())?;
+            Some((path.clone(), PyFileMetadata(meta)))
+        })
+        .collect()
+}
+
+/// A group passed in from Python: a `DuplicateGroup` or a dict with its
+/// keys
+fn group_from_py(py: Python<'_>, group: &Bound<'_, PyAny>) -> PyResult<DuplicateGroup> {
+    match group.downcast::<PyDuplicateGroup>() {
+        Ok(group) => group.borrow().to_group(),
+        Err(_) => PyDuplicateGroup::from_dict(py, &group.extract()?)?.to_group(),
+    }
+}
+
+/// Read a {path: hash} dict or a list of (path, hash) or
+/// (path, hash, (width, height)) tuples; `None` for anything else, which
+/// is then taken as a list of paths
+fn prehashed_input(paths: &Bound<'_, PyAny>) -> PyResult<Option<Vec<HashEntry>>> {
+    if let Ok(dict) = paths.extract::<BTreeMap<PathBuf, String>>() {
+        return dict
+            .into_iter()
+            .map(|(path, hash)| Ok((path, parse_hash_text(&hash)?, None)))
+            .collect::<PyResult<_>>()
+            .map(Some);
+    }
+    let Ok(items) = paths.extract::<Vec<Bound<'_, PyAny>>>() else {
+        return Ok(None);
+    };
+    if items
+        .first()
+        .is_none_or(|item| item.extract::<PathBuf>().is_ok())
+    {
+        return Ok(None);
+    }
+    items
+        .iter()
+        .map(|item| {
+            let (path, hash, dimensions) = match item.extract() {
+                Ok(entry) => entry,
+                Err(_) => {
+                    let (path, hash): (PathBuf, String) = item.extract()?;
+                    (path, hash, None)
+                }
+            };
+            Ok((path, parse_hash_text(&hash)?, dimensions))
+        })
+        .collect::<PyResult<_>>()
+        .map(Some)
+}
+
+/// Find duplicate images in a list of file paths.
+///
+/// Args:
+///     paths: List of image file paths to check, or hashes computed
+///            earlier to group without opening any file: a
+///            {path: hex hash} dict, or a list of (path, hex hash) or
+///            (path, hex hash, (width, height)) tuples. Tagged hashes
+///            (ImageHash.to_tagged) work too. The best copy is then the
+///            member with the most pixels; algorithm, hash_size,
+///            preprocessing and cache_path don't apply, and check_rotations,
+///            content_aware, match_rule, exact_prepass, weights and a
+///            registered hash function are refused.
+///     threshold: Maximum Hamming distance for duplicates (default: 10)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash", or the name of a function registered
+///                with register_hash_function (hash_size is then ignored;
+///                files the function fails on are reported as errors).
+///                "ensemble" hashes each image with phash, whash and
+///                blockhash from one decode and matches pairs that at
+///                least two of them put within threshold; it is the
+///                match_rule "2 OF phash<=t, whash<=t, blockhash<=t" and
+///                has the same restrictions.
+///     review_path: Review file written by `annotate_group` (optional).
+///                  Decisions recorded for unchanged groups are returned
+///                  under "review".
+///     relative_to: Scan root; if given, reported paths (and group keys)
+///                  are relative to it so results survive remounting
+///     cache_path: Hash cache file (optional), as for compute_hashes_parallel
+///     progress_socket: Publish progress and groups on this local socket
+///                      (optional), as for compute_hashes_parallel
+///     progress_callback: Called as progress_callback(done, total, path)
+///                        while hashing (optional), as for
+///                        compute_hashes_parallel
+///     return_errors: Also return the files that could not be hashed
+///                    (default: False)
+///     check_rotations: Also match rotated (90/180/270 degrees) and mirrored
+///                      copies (default: False). Hashing takes about eight
+///                      times as long; cached hashes are kept separately.
+///     content_aware: Classify each image as a photo, screenshot, graphic or
+///                    document and group it only with images of the same
+///                    type, using that type's preset algorithm and threshold
+///                    instead of `algorithm` and `threshold` (default: False)
+///     match_rule: Match on several algorithms at once instead of
+///                 `algorithm` and `threshold`, e.g.
+///                 "phash<=10 AND whash<=12", "phash<=8 OR colorhash<=20"
+///                 or "2 OF phash<=10, whash<=12, blockhash<=10"
+///                 (optional). Cannot be combined with check_rotations or
+///                 content_aware.
+///     exact_prepass: Group byte-identical files first (same size, same
+///                    BLAKE3 digest) and hash only one file of each; the
+///                    copies are added to its group (default: False).
+///                    Saves a decode per copy at the cost of reading files
+///                    that share a size once more.
+///     best_by: How "best" is chosen: "resolution" (default), "filesize",
+///              "newest", "oldest", "quality" (see score_image),
+///              "format_priority" (lossless formats first) or
+///              "format_priority:png,webp,jpg" (optional). May
+///              also be a callable taking a path and returning a score;
+///              the highest score wins.
+///     pairwise: Also return each group's distance matrix under
+///               "pairwise" (default: False)
+///     hash_size: Size of the hash (default: 8, produces 64-bit hash).
+///                Cannot be changed with match_rule or content_aware,
+///                which use their own presets.
+///     trim_borders, center_crop, normalize_gamma, mask_regions,
+///     alpha_background, dct_size, filter:
+///         Preprocessing, as for compute_hash. Cannot be combined with
+///         match_rule or content_aware.
+///     verify: Check each pair of members within threshold again by
+///             structural similarity (SSIM) of small thumbnails and split
+///             groups held together only by their hashes, e.g. unrelated
+///             dark photos (default: False). Members are decoded a second
+///             time. Cannot be combined with check_rotations, match_rule or
+///             content_aware.
+///     min_similarity: Smallest thumbnail SSIM, -1 to 1, for verify to
+///                     keep a pair (default: 0.5)
+///     weights: Compare hashes by weighted distance: "low_frequency" to
+///              count the low-frequency bits of a pHash more, or a list
+///              of per-bit weights, as for weighted_hamming_distance.
+///              threshold then bounds the weighted distance. Cannot be
+///              combined with check_rotations, match_rule, content_aware,
+///              verify or a registered hash function (optional).
+///     grouping: How matches form groups: "connected" (default) joins
+///               everything linked through a chain of matches, so members
+///               can be far apart; "clique" keeps only groups whose members
+///               are all within threshold of each other; "medoid" groups
+///               members within threshold of a common center. Cannot be
+///               combined with check_rotations, match_rule, content_aware or
+///               weights.
+///     coarse_threshold: Compare fine hashes only for pairs whose 16-bit
+///                       coarse pHashes, computed from the same DCT, are
+///                       within this many bits (optional). Much faster on
+///                       large corpora with few duplicates, at the risk of
+///                       missing pairs whose coarse hashes differ more;
+///                       2 or 3 is a good start. Use with hash_size=16 for
+///                       256-bit fine hashes. pHash only, and not
+///                       combinable with cache_path, check_rotations,
+///                       match_rule, content_aware, weights or pre-computed
+///                       hashes.
+///     num_threads: Threads to hash with (default: the set_num_threads
+///                  limit, else one per core)
+///     as_mapping: Return a flat mapping of member paths instead of the
+///                 list of groups (default: False); see below
+///     metadata: Also return the size, modification time, dimensions and
+///               format of each member under "metadata" (default: False).
+///               Costs a stat and a read of the first bytes per member; no
+///               image is decoded again.
+///     quality: Also return "distances", "max_distance" and
+///              "mean_distance" (default: False). Costs a distance between
+///              every two members of each group.
+///     apply_exif_orientation: Turn images upright by their EXIF
+///                             orientation before hashing, or not (default:
+///                             as set by set_exif_orientation). Counts as
+///                             preprocessing.
+///
+/// Ctrl+C stops hashing and raises KeyboardInterrupt once the workers
+/// have stopped; hashes finished before it are still saved to cache_path,
+/// so running again only hashes the rest.
+///
+/// Returns:
+///     List of DuplicateGroup objects, each with these attributes (also
+///     readable as group["paths"] etc.):
+///     - "paths": List of file paths in this duplicate group
+///     - "hash": The representative hash for this group
+///     - "best": Path to the best image in the group (see best_by)
+///     - "key": Stable group key (changes only when members change)
+///     - "sidecars": {member path: [XMP/AAE/THM files]} for members that
+///       have sidecars; move or delete these together with the member.
+///       Sidecar files passed in `paths` are never reported as images.
+///     - "content_type": "photo", "screenshot", "graphic" or "document"
+///       with content_aware=True, else None
+///     - "hashes": {member path: hex hash}
+///     - "dimensions": {member path: (width, height)} as decoded for
+///       hashing, or None when not known (groups read from results files)
+///     - "distances": {member path: distance to "hash"} (None unless
+///       quality=True)
+///     - "max_distance": Largest distance between two members; members
+///       match through chains, so this can exceed threshold unless
+///       grouping is "clique" (None unless quality=True)
+///     - "mean_distance": Mean distance over all pairs of members (None
+///       unless quality=True)
+///     - "pairwise": Distance between every two members, rows and columns
+///       ordered as "paths" (None unless pairwise=True)
+///     - "metadata": {member path: {"size", "mtime", "dimensions",
+///       "format"}} with mtime in seconds since the epoch and format named
+///       by its usual extension ("jpg", "png", ...) as detected from the
+///       file's first bytes (None unless metadata=True)
+///     - "review": {"state", "note"} or None (always None without
+///       review_path)
+///     With as_mapping=True, {path: {"group_id", "distance_to_best",
+///     "is_best"}} for every member instead, where group_id is the group's
+///     position in the list and distance_to_best is None when hashes are
+///     not known. With return_errors=True, a (groups, errors) tuple
+///     instead, where errors is as for compute_hashes_parallel.
+#[pyfunction]
+#[pyo3(signature = (
+    paths,
+    threshold = 10,
+    algorithm = "phash",
+    review_path = None,
+    relative_to = None,
+    cache_path = None,
+    progress_socket = None,
+    progress_callback = None,
+    return_errors = false,
+    check_rotations = false,
+    content_aware = false,
+    match_rule = None,
+    exact_prepass = false,
+    best_by = None,
+    pairwise = false,
+    hash_size = 8,
+    trim_borders = false,
+    center_crop = None,
+    normalize_gamma = false,
+    mask_regions = None,
+    alpha_background = None,
+    dct_size = DCT_SIZE,
+    filter = "lanczos3",
+    verify = false,
+    min_similarity = DEFAULT_MIN_SIMILARITY,
+    weights = None,
+    grouping = "connected",
+    coarse_threshold = None,
+    num_threads = None,
+    as_mapping = false,
+    metadata = false,
+    quality = false,
+    apply_exif_orientation = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn find_duplicate_images(
+    py: Python<'_>,
+    paths: &Bound<'_, PyAny>,
+    threshold: u32,
+    algorithm: &str,
+    review_path: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    cache_path: Option<PathBuf>,
+    progress_socket: Option<&str>,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+    check_rotations: bool,
+    content_aware: bool,
+    match_rule: Option<&str>,
+    exact_prepass: bool,
+    best_by: Option<PyObject>,
+    pairwise: bool,
+    hash_size: usize,
+    trim_borders: bool,
+    center_crop: Option<f32>,
+    normalize_gamma: bool,
+    mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+    alpha_background: Option<(u8, u8, u8)>,
+    dct_size: usize,
+    filter: &str,
+    verify: bool,
+    min_similarity: f64,
+    weights: Option<&Bound<'_, PyAny>>,
+    grouping: &str,
+    coarse_threshold: Option<u32>,
+    num_threads: Option<usize>,
+    as_mapping: bool,
+    metadata: bool,
+    quality: bool,
+    apply_exif_orientation: Option<bool>,
+) -> PyResult<PyObject> {
+    let relative_to = relative_to.as_deref();
+    let prehashed = prehashed_input(paths)?;
+    let paths: Vec<PathBuf> = match prehashed {
+        Some(_) => Vec::new(),
+        None => paths.extract()?,
+    };
+    let custom = PyHasher::registered(py, algorithm);
+    let ensemble = custom.is_none() && algorithm.eq_ignore_ascii_case("ensemble");
+    // A registered hash function or the ensemble rule stands in for the
+    // built-in algorithms
+    let algorithm = match custom {
+        Some(_) => HashAlgorithm::PHash,
+        None if ensemble => HashAlgorithm::PHash,
+        None => parse_algorithm(algorithm)?,
+    };
+    if ensemble && match_rule.is_some() {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "algorithm=\"ensemble\" cannot be combined with match_rule",
+        ));
+    }
+    let options = HashOptions {
+        apply_exif_orientation,
+        ..hash_options(
+            trim_borders,
+            center_crop,
+            normalize_gamma,
+            mask_regions,
+            alpha_background,
+            dct_size,
+            filter,
+        )?
+    };
+    options
+        .phash
+        .check_hash_size(algorithm, hash_size)
+        .map_err(hash_err)?;
+    if custom.is_some()
+        && (check_rotations || match_rule.is_some() || content_aware || cache_path.is_some())
+    {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "a registered hash function cannot be combined with check_rotations, match_rule, content_aware or cache_path",
+        ));
+    }
+    let weights = weights.map(|w| parse_weights(w, hash_size)).transpose()?;
+    if weights.is_some()
+        && (check_rotations || match_rule.is_some() || content_aware || verify || custom.is_some())
+    {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "weights cannot be combined with check_rotations, match_rule, content_aware, verify or a registered hash function",
+        ));
+    }
+    let best_by = best_by.map(|b| parse_best_by(py, b)).transpose()?;
+    let match_rule = match_rule
+        .map(|r| r.parse::<MatchRule>())
+        .transpose()
+        .map_err(pyo3::exceptions::PyValueError::new_err)?
+        .or_else(|| ensemble.then(|| MatchRule::ensemble(threshold)));
+    if match_rule.is_some() && (check_rotations || content_aware) {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "match_rule cannot be combined with check_rotations or content_aware",
+        ));
+    }
+    if hash_size != DEFAULT_HASH_SIZE && (match_rule.is_some() || content_aware) {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "hash_size cannot be combined with match_rule or content_aware",
+        ));
+    }
+    if !options.is_identity() && (match_rule.is_some() || content_aware) {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "preprocessing cannot be combined with match_rule or content_aware",
+        ));
+    }
+    if verify && (check_rotations || match_rule.is_some() || content_aware) {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "verify cannot be combined with check_rotations, match_rule or content_aware",
+        ));
+    }
+    if prehashed.is_some()
+        && (check_rotations
+            || content_aware
+            || match_rule.is_some()
+            || exact_prepass
+            || weights.is_some()
+            || custom.is_some())
+    {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "hashes cannot be combined with check_rotations, content_aware, match_rule, exact_prepass, weights or a registered hash function",
+        ));
+    }
+    let grouping: Grouping = grouping
+        .parse()
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    if grouping != Grouping::Connected
+        && (check_rotations || match_rule.is_some() || content_aware || weights.is_some())
+    {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "grouping cannot be combined with check_rotations, match_rule, content_aware or weights",
+        ));
+    }
+    if coarse_threshold.is_some() {
+        if algorithm != HashAlgorithm::PHash
+            || custom.is_some()
+            || cache_path.is_some()
+            || check_rotations
+            || match_rule.is_some()
+            || content_aware
+            || weights.is_some()
+            || prehashed.is_some()
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "coarse_threshold requires algorithm=\"phash\" and cannot be combined with cache_path, check_rotations, match_rule, content_aware, weights, hashes or a registered hash function",
+            ));
+        }
+        ImageHash::check_hierarchical(hash_size, &options).map_err(hash_err)?;
+    }
+    let reviews = review_path
+        .map(ReviewStore::load)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let mut cache = cache_path
+        .as_ref()
+        .map(HashCache::load)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let socket = progress_socket
+        .map(ProgressSocket::bind)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+
+    let callback = progress_callback.map(CallbackProgress::new);
+    let warnings = WarningCollector::new();
+    let cancel = CancelToken::new();
+
+    let mut sinks: Vec<&dyn Progress> = vec![&warnings, &cancel];
+    sinks.extend(socket.as_ref().map(|s| s as &dyn Progress));
+    sinks.extend(callback.as_ref().map(|c| c as &dyn Progress));
+    let progress = Fanout(sinks);
+    let (groups, interrupt) = run_cancellable(py, num_threads, &cancel, || {
+        let mut find = |paths: &[PathBuf]| {
+            if let Some(hashes) = &prehashed {
+                find_duplicates_in_hashes(hashes, threshold)
+                    .map(|groups| (groups, HashFailures::new()))
+            } else if let Some(hasher) = &custom {
+                find_duplicates_using(paths, hasher, threshold, &options, Some(&progress))
+            } else if let Some(coarse_threshold) = coarse_threshold {
+                find_duplicates_hierarchical(
+                    paths,
+                    hash_size,
+                    threshold,
+                    coarse_threshold,
+                    &options,
+                    Some(&progress),
+                )
+            } else if let Some(weights) = &weights {
+                find_duplicates_weighted(
+                    paths,
+                    algorithm,
+                    hash_size,
+                    threshold as f64,
+                    weights,
+                    &options,
+                    cache.as_mut(),
+                    Some(&progress),
+                )
+            } else if let Some(rule) = &match_rule {
+                find_duplicates_by_rule(paths, rule, cache.as_mut(), Some(&progress))
+            } else if content_aware {
+                find_duplicates_by_content(paths, check_rotations, cache.as_mut(), Some(&progress))
+            } else {
+                find_duplicates_with(
+                    paths,
+                    algorithm,
+                    hash_size,
+                    threshold,
+                    check_rotations,
+                    &options,
+                    cache.as_mut(),
+                    Some(&progress),
+                )
+            }
+        };
+        let found = if exact_prepass {
+            with_exact_prepass(&paths, Some(&progress), find)
+        } else {
+            find(&paths)
+        }
+        .map(|(groups, failures)| (regroup(groups, grouping, threshold), failures));
+        match found {
+            Ok((groups, failures)) if verify => Ok((
+                verify_groups(groups, threshold, &options, min_similarity),
+                failures,
+            )),
+            found => found,
+        }
+    })?;
+    drop(progress);
+
+    if let Some(interrupt) = interrupt {
+        if let Some(socket) = socket {
+            socket.fail("Interrupted");
+        }
+        if let (Some(cache), Some(cache_path)) = (&cache, &cache_path) {
+            cache
+                .save(cache_path)
+                .map_err(pyo3::exceptions::PyIOError::new_err)?;
+        }
+        return Err(interrupt);
+    }
+    let (groups, failures) = match groups {
+        Ok(found) => found,
+        Err(e) => {
+            if let Some(socket) = socket {
+                socket.fail(&e);
+            }
+            return Err(match callback.and_then(|c| c.take_error()) {
+                Some(raised) => raised,
+                None => pyo3::exceptions::PyRuntimeError::new_err(e),
+            });
+        }
+    };
+    let mut groups: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|g| g.paths.len() > 1) // Only return actual duplicates
+        .collect();
+    match &best_by {
+        Some(BestChoice::Strategy(by)) => {
+            py.allow_threads(|| groups.iter_mut().for_each(|g| g.choose_best(by)))
+        }
+        Some(BestChoice::Callback(score)) => {
+            for g in &mut groups {
+                g.best_path = best_by_callback(py, score, g)?;
+            }
+        }
+        None => {}
+    }
+    let mut groups: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .map(|g| match relative_to {
+            Some(root) => g.relative_to(root),
+            None => g,
+        })
+        .collect();
+    // best_by and relative_to can change the best paths groups are ordered by
+    sort_groups(&mut groups);
+
+    if let Some(socket) = socket {
+        for g in &groups {
+            socket.publish_group(g);
+        }
+        socket.finish(groups.len());
+    }
+    if let (Some(cache), Some(cache_path)) = (&cache, &cache_path) {
+        cache
+            .save(cache_path)
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    }
+    emit_warnings(py, warnings, relative_to, return_errors)?;
+
+    if as_mapping {
+        let mapping: BTreeMap<PathBuf, HashMap<&str, PyObject>> = group_membership(&groups)
+            .into_iter()
+            .map(|(path, m)| {
+                let mut entry = HashMap::new();
+                entry.insert("group_id", m.group_id.to_object(py));
+                entry.insert("distance_to_best", m.distance_to_best.to_object(py));
+                entry.insert("is_best", m.is_best.to_object(py));
+                (path, entry)
+            })
+            .collect();
+        return if return_errors {
+            Ok((mapping, errors_to_dict(failures, relative_to)).to_object(py))
+        } else {
+            Ok(mapping.to_object(py))
+        };
+    }
+
+    let result: Vec<PyDuplicateGroup> = groups
+        .iter()
+        .map(|g| {
+            let mut group = PyDuplicateGroup::new(g);
+            if quality {
+                group.add_quality(g);
+            }
+            if pairwise {
+                group.pairwise = g.pairwise_distances();
+            }
+            if metadata {
+                group.metadata = Some(py.allow_threads(|| member_metadata(g, relative_to)));
+            }
+            if let Some(reviews) = &reviews {
+                group.review = reviews.get(&g.key()).map(|a| {
+                    BTreeMap::from([
+                        ("state".to_string(), a.state.name().to_string()),
+                        ("note".to_string(), a.note.clone()),
+                    ])
+                });
+            }
+            group
+        })
+        .collect();
+    if return_errors {
+        Ok((result, errors_to_dict(failures, relative_to)).into_py(py))
+    } else {
+        Ok(result.into_py(py))
+    }
+}
+
+/// `best_by` argument: a strategy name or a Python scoring callable
+enum BestChoice {
+    Strategy(BestBy),
+    Callback(PyObject),
+}
+
+fn parse_best_by(py: Python<'_>, best_by: PyObject) -> PyResult<BestChoice> {
+    match best_by.extract::<String>(py) {
+        Ok(name) => name
+            .parse()
+            .map(BestChoice::Strategy)
+            .map_err(pyo3::exceptions::PyValueError::new_err),
+        Err(_) => Ok(BestChoice::Callback(best_by)),
+    }
+}
+
+/// Member with the highest `score(path)`; ties keep the current best
+fn best_by_callback(py: Python<'_>, score: &PyObject, g: &DuplicateGroup) -> PyResult<PathBuf> {
+    let mut best: Option<(f64, &PathBuf)> = None;
+    for path in &g.paths {
+        let s: f64 = score.call1(py, (path,))?.extract(py)?;
+        let better = match best {
+            None => true,
+            Some((top, _)) => s > top || (s == top && *path == g.best_path),
+        };
+        if better {
+            best = Some((s, path));
+        }
+    }
+    Ok(best.map_or_else(|| g.best_path.clone(), |(_, path)| path.clone()))
+}
+
+/// Find duplicate images in chunks of paths, yielding groups as they close.
+///
+/// For libraries too large to group in one call. Only images whose group
+/// is still open are held in memory; with `window`, a group is yielded as
+/// soon as its newest member is more than `window` images behind the last
+/// one read. Images further apart than that in input order are not
+/// compared, so feed paths sorted by directory or capture time. Without
+/// `window`, all groups are yielded after the last chunk, the same as
+/// find_duplicate_images would return.
+///
+/// Args:
+///     path_chunks: Iterable of lists of image paths, consumed lazily
+///     threshold: Maximum Hamming distance for duplicates (default: 10)
+///     algorithm: Hash algorithm (default: "phash")
+///     window: Images after which a group closes (optional; see above)
+///     cache_path: Hash cache file, loaded first and saved once all chunks
+///                 are read (optional)
+///     relative_to: Report paths relative to this directory (optional)
+///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
+///
+/// Returns:
+///     Iterator over duplicate groups as returned by find_duplicate_images,
+///     without "review". Its `errors` attribute holds the files that failed
+///     to hash so far, as for compute_hashes_parallel.
+///
+/// Example:
+///     chunks = (paths[i:i + 10000] for i in range(0, len(paths), 10000))
+///     for group in phash_rs.find_duplicates_streaming(chunks, window=50000):
+///         print(group["paths"])
+#[pyfunction]
+#[pyo3(signature = (
+    path_chunks,
+    threshold = 10,
+    algorithm = "phash",
+    window = None,
+    cache_path = None,
+    relative_to = None,
+    hash_size = 8
+))]
+#[allow(clippy::too_many_arguments)]
+fn find_duplicates_streaming(
+    py: Python<'_>,
+    path_chunks: PyObject,
+    threshold: u32,
+    algorithm: &str,
+    window: Option<usize>,
+    cache_path: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    hash_size: usize,
+) -> PyResult<DuplicateStream> {
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let cache = cache_path
+        .as_deref()
+        .map(HashCache::load)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let chunks = py
+        .import_bound("builtins")?
+        .getattr("iter")?
+        .call1((path_chunks,))?
+        .unbind();
+    let mut finder = DuplicateFinder::new(algorithm, threshold).hash_size(hash_size);
+    if let Some(window) = window {
+        finder = finder.window(window);
+    }
+    Ok(DuplicateStream {
+        finder: Some(finder),
+        chunks,
+        pending: VecDeque::new(),
+        failures: HashFailures::new(),
+        cache,
+        cache_path,
+        relative_to,
+    })
+}
+
+/// Iterator returned by find_duplicates_streaming.
+#[pyclass]
+struct DuplicateStream {
+    /// None once the last chunk has been read
+    finder: Option<DuplicateFinder>,
+    chunks: PyObject,
+    pending: VecDeque<DuplicateGroup>,
+    /// Failures of a finished stream; the finder holds them before that
+    failures: HashFailures,
+    cache: Option<HashCache>,
+    cache_path: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+}
+
+#[pymethods]
+impl DuplicateStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let this = &mut *slf;
+        loop {
+            if let Some(group) = this.pending.pop_front() {
+                let group = match &this.relative_to {
+                    Some(root) => group.relative_to(root),
+                    None => group,
+                };
+                return Ok(Some(PyDuplicateGroup::new(&group).into_py(py)));
+            }
+            let Some(finder) = this.finder.as_mut() else {
+                return Ok(None);
+            };
+
+            let chunk = py
+                .import_bound("builtins")?
+                .getattr("next")?
+                .call1((this.chunks.clone_ref(py), py.None()))?;
+            if chunk.is_none() {
+                let (groups, failures) = match this.finder.take() {
+                    Some(finder) => run_batch(py, None, || finder.finish())?,
+                    None => (Vec::new(), HashFailures::new()),
+                };
+                this.pending.extend(groups);
+                this.failures = failures;
+                if let (Some(cache), Some(cache_path)) = (&this.cache, &this.cache_path) {
+                    cache
+                        .save(cache_path)
+                        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+                }
+                continue;
+            }
+
+            let paths: Vec<PathBuf> = chunk.extract()?;
+            let cache = this.cache.as_mut();
+            run_batch(py, None, || finder.add_paths(&paths, cache, None))?
+                .map_err(pyo3::exceptions::PyRuntimeError::new_err)?;
+            this.pending.extend(finder.take_finished());
+        }
+    }
+
+    /// Files that failed to hash so far, as for compute_hashes_parallel.
+    #[getter]
+    fn errors(&self) -> BTreeMap<PathBuf, HashMap<&'static str, String>> {
+        let failures = match &self.finder {
+            Some(finder) => finder.failures().clone(),
+            None => self.failures.clone(),
+        };
+        errors_to_dict(failures, self.relative_to.as_deref())
+    }
+}
+
+/// Find duplicate images, saving progress to a checkpoint file.
+///
+/// For scans long enough that a crash, kill or Ctrl+C would lose hours of
+/// hashing. Paths are hashed in chunks of checkpoint_every; after a chunk
+/// the hashes and grouping so far, and the paths still to do, are written
+/// to checkpoint_path, at most once per checkpoint_interval seconds. If the
+/// scan stops early, call resume(checkpoint_path) to carry on from the
+/// last checkpoint. The file is removed when the scan completes.
+///
+/// Args:
+///     paths: List of image file paths
+///     checkpoint_path: Checkpoint file; must not exist yet
+///     threshold: Maximum Hamming distance for duplicates (default: 10)
+///     algorithm: Hash algorithm (default: "phash")
+///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
+///     checkpoint_every: Files per chunk (default: 1000)
+///     checkpoint_interval: Minimum seconds between checkpoints
+///                          (default: 30.0)
+///     cache_path: Hash cache file, loaded first and saved at the end or
+///                 on Ctrl+C (optional)
+///     relative_to: Report paths relative to this directory (optional)
+///     progress_callback: Called as for find_duplicate_images (optional)
+///     return_errors: Also return the files that failed to hash
+///                    (default: False)
+///     num_threads: Threads to hash with (default: the set_num_threads
+///                  limit, else one per core)
+///
+/// Returns:
+///     List of duplicate groups as returned by find_duplicate_images,
+///     without "review". With return_errors=True, a (groups, errors) tuple
+///     instead, where errors is as for compute_hashes_parallel.
+///
+/// Example:
+///     try:
+///         groups = phash_rs.find_duplicates_resumable(paths, "scan.ckpt")
+///     except KeyboardInterrupt:
+///         ...  # later: groups = phash_rs.resume("scan.ckpt")
+#[pyfunction]
+#[pyo3(signature = (
+    paths,
+    checkpoint_path,
+    threshold = 10,
+    algorithm = "phash",
+    hash_size = 8,
+    checkpoint_every = 1000,
+    checkpoint_interval = 30.0,
+    cache_path = None,
+    relative_to = None,
+    progress_callback = None,
+    return_errors = false,
+    num_threads = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn find_duplicates_resumable(
+    py: Python<'_>,
+    paths: Vec<PathBuf>,
+    checkpoint_path: PathBuf,
+    threshold: u32,
+    algorithm: &str,
+    hash_size: usize,
+    checkpoint_every: usize,
+    checkpoint_interval: f64,
+    cache_path: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+    num_threads: Option<usize>,
+) -> PyResult<PyObject> {
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let schedule = CheckpointSchedule {
+        files: checkpoint_every,
+        interval: std::time::Duration::try_from_secs_f64(checkpoint_interval)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?,
+    };
+    let finder = DuplicateFinder::new(algorithm, threshold).hash_size(hash_size);
+    run_checkpointed(
+        py,
+        cache_path,
+        relative_to,
+        progress_callback,
+        return_errors,
+        num_threads,
+        |cache, progress| {
+            find_duplicates_checkpointed(
+                &paths,
+                finder,
+                &checkpoint_path,
+                schedule,
+                cache,
+                Some(progress),
+            )
+        },
+    )
+}
+
+/// Continue a scan started with find_duplicates_resumable.
+///
+/// Reads the settings and remaining paths from the checkpoint and returns
+/// the groups of the whole scan, as the uninterrupted scan would have; the
+/// checkpoint is removed once it completes. Can itself be interrupted and
+/// resumed again.
+///
+/// Args:
+///     checkpoint_path: Checkpoint file written by find_duplicates_resumable
+///     cache_path, relative_to, progress_callback, return_errors,
+///     num_threads: As for find_duplicates_resumable
+///
+/// Returns:
+///     As for find_duplicates_resumable; errors include files that failed
+///     before the interruption.
+#[pyfunction]
+#[pyo3(signature = (
+    checkpoint_path,
+    cache_path = None,
+    relative_to = None,
+    progress_callback = None,
+    return_errors = false,
+    num_threads = None
+))]
+fn resume(
+    py: Python<'_>,
+    checkpoint_path: PathBuf,
+    cache_path: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+    num_threads: Option<usize>,
+) -> PyResult<PyObject> {
+    run_checkpointed(
+        py,
+        cache_path,
+        relative_to,
+        progress_callback,
+        return_errors,
+        num_threads,
+        |cache, progress| resume_duplicates(&checkpoint_path, cache, Some(progress)),
+    )
+}
+
+/// Run a checkpointed scan under Ctrl+C handling and convert its result
+///
+/// On Ctrl+C the cache is saved and KeyboardInterrupt raised; the
+/// checkpoint stays behind for resume.
+fn run_checkpointed<F>(
+    py: Python<'_>,
+    cache_path: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+    num_threads: Option<usize>,
+    scan: F,
+) -> PyResult<PyObject>
+where
+    F: FnOnce(
+            Option<&mut HashCache>,
+            &dyn Progress,
+        ) -> Result<(Vec<DuplicateGroup>, HashFailures), String>
+        + Send,
+{
+    let relative_to = relative_to.as_deref();
+    let mut cache = cache_path
+        .as_ref()
+        .map(HashCache::load)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let callback = progress_callback.map(CallbackProgress::new);
+    let warnings = WarningCollector::new();
+    let cancel = CancelToken::new();
+
+    let mut sinks: Vec<&dyn Progress> = vec![&warnings, &cancel];
+    sinks.extend(callback.as_ref().map(|c| c as &dyn Progress));
+    let progress = Fanout(sinks);
+    let (found, interrupt) =
+        run_cancellable(py, num_threads, &cancel, || scan(cache.as_mut(), &progress))?;
+    drop(progress);
+
+    if let (Some(cache), Some(cache_path)) = (&cache, &cache_path) {
+        cache
+            .save(cache_path)
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    }
+    if let Some(interrupt) = interrupt {
+        return Err(interrupt);
+    }
+    let (groups, failures) = found.map_err(|e| match callback.and_then(|c| c.take_error()) {
+        Some(raised) => raised,
+        None => pyo3::exceptions::PyRuntimeError::new_err(e),
+    })?;
+    let mut groups: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter(|g| g.paths.len() > 1)
+        .map(|g| match relative_to {
+            Some(root) => g.relative_to(root),
+            None => g,
+        })
+        .collect();
+    sort_groups(&mut groups);
+    emit_warnings(py, warnings, relative_to, return_errors)?;
+
+    let result: Vec<PyDuplicateGroup> = groups.iter().map(PyDuplicateGroup::new).collect();
+    if return_errors {
+        Ok((result, errors_to_dict(failures, relative_to)).into_py(py))
+    } else {
+        Ok(result.into_py(py))
+    }
+}
+
+/// Label images as photo, screenshot, graphic or document.
+///
+/// Uses edge density, color distribution and the presence of EXIF data;
+/// these are the labels find_duplicate_images(content_aware=True) groups by.
+///
+/// Args:
+///     paths: List of image file paths
+///     return_errors: Also return the files that could not be decoded
+///                    (default: False)
+///
+/// Returns:
+///     Dictionary mapping file paths to "photo", "screenshot", "graphic" or
+///     "document", ordered by path. With return_errors=True, a
+///     (labels, errors) tuple instead, where errors is as for
+///     compute_hashes_parallel.
+#[pyfunction]
+#[pyo3(signature = (paths, return_errors = false))]
+fn classify_images(py: Python<'_>, paths: Vec<PathBuf>, return_errors: bool) -> PyResult<PyObject> {
+    let results = run_batch(py, None, || classify_paths(&paths, None))?;
+
+    let mut labels = BTreeMap::new();
+    let mut failures = HashFailures::new();
+    for (path, content) in results {
+        match content {
+            Ok(content) => {
+                labels.insert(path, content.to_string());
+            }
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    if return_errors {
+        Ok((labels, errors_to_dict(failures, None)).to_object(py))
+    } else {
+        Ok(labels.to_object(py))
+    }
+}
+
+/// Cluster near-identical shots, such as camera bursts, for review.
+///
+/// Unlike find_duplicate_images this is meant for series to pick from, not
+/// copies to remove: clusters are never passed to apply_dedup, and every
+/// shot is reported. Shots are ordered by EXIF capture time
+/// (DateTimeOriginal) and scored for sharpness to suggest a keeper.
+///
+/// Args:
+///     paths: List of image file paths
+///     threshold: Maximum Hamming distance linking two shots (default: 10)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"
+///     hash_size: Size of the hashes (default: 8, 64-bit hashes)
+///     progress_callback: Optional callable(done, total, path) invoked as
+///                        files finish; raising an exception cancels
+///     return_errors: Also return the files that could not be decoded
+///                    (default: False)
+///     num_threads: Threads to decode with (default: the set_num_threads
+///                  limit, else one per core)
+///
+/// Returns:
+///     List of clusters in order of their first shot, each containing:
+///     - "paths": Member paths by capture time; shots without one follow,
+///       by path
+///     - "keeper": Suggested shot to keep: the sharpest, then the largest
+///     - "shots": For each path, a dict of "path", "capture_time"
+///       ("YYYY:MM:DD HH:MM:SS[.fraction]" or None), "sharpness" (variance
+///       of the Laplacian, higher is sharper; compare within a cluster
+///       only) and "dimensions" ((width, height))
+///     Images matching no other image are left out. With
+///     return_errors=True, a (clusters, errors) tuple instead, where errors
+///     is as for compute_hashes_parallel.
+#[pyfunction(name = "cluster_similar")]
+#[pyo3(signature = (
+    paths,
+    threshold = 10,
+    algorithm = "phash",
+    hash_size = 8,
+    progress_callback = None,
+    return_errors = false,
+    num_threads = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn py_cluster_similar(
+    py: Python<'_>,
+    paths: Vec<PathBuf>,
+    threshold: u32,
+    algorithm: &str,
+    hash_size: usize,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+    num_threads: Option<usize>,
+) -> PyResult<PyObject> {
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let callback = progress_callback.map(CallbackProgress::new);
+    let found = run_batch(py, num_threads, || {
+        cluster_similar(
+            &paths,
+            algorithm,
+            hash_size,
+            threshold,
+            callback.as_ref().map(|c| c as &dyn Progress),
+        )
+    })?;
+    let (clusters, failures) = match found {
+        Ok(found) => found,
+        Err(e) => {
+            return Err(match callback.and_then(|c| c.take_error()) {
+                Some(raised) => raised,
+                None => pyo3::exceptions::PyRuntimeError::new_err(e),
+            })
+        }
+    };
+
+    let result: Vec<HashMap<&str, PyObject>> = clusters
+        .iter()
+        .map(|cluster| {
+            let paths: Vec<&Path> = cluster.shots.iter().map(|s| s.path.as_path()).collect();
+            let shots: Vec<HashMap<&str, PyObject>> = cluster
+                .shots
+                .iter()
+                .map(|shot| {
+                    let mut map = HashMap::new();
+                    map.insert("path", shot.path.to_object(py));
+                    map.insert("capture_time", shot.capture_time.to_object(py));
+                    map.insert("sharpness", shot.sharpness.to_object(py));
+                    map.insert("dimensions", shot.dimensions.to_object(py));
+                    map
+                })
+                .collect();
+            let mut map = HashMap::new();
+            map.insert("paths", paths.to_object(py));
+            map.insert("keeper", cluster.keeper.to_object(py));
+            map.insert("shots", shots.to_object(py));
+            map
+        })
+        .collect();
+    if return_errors {
+        Ok((result, errors_to_dict(failures, None)).to_object(py))
+    } else {
+        Ok(result.to_object(py))
+    }
+}
+
+/// Score how good one copy of an image looks.
+///
+/// Resolution alone prefers an upscaled, blurry copy over the original;
+/// this also weighs sharpness, detail that survives being halved, the
+/// JPEG quality setting and visible 8x8 blocks. best_by="quality" picks
+/// group members by the same score.
+///
+/// Args:
+///     path: Path to the image file
+///
+/// Returns:
+///     Dict with "score" (combined, higher is better; compare between
+///     copies of one picture only), "sharpness" (variance of the
+///     Laplacian), "upscale" (1, or 2, 4 or 8 if the image looks upscaled
+///     that many times), "jpeg_quality" (estimated 1-100 setting, None for
+///     other formats), "blockiness" (0 for no visible blocking) and
+///     "dimensions" ((width, height))
+#[pyfunction(name = "score_image")]
+fn py_score_image(py: Python<'_>, path: PathBuf) -> PyResult<PyObject> {
+    let quality = py.allow_threads(|| score_image(&path)).map_err(hash_err)?;
+    let mut map: HashMap<&str, PyObject> = HashMap::new();
+    map.insert("score", quality.score().to_object(py));
+    map.insert("sharpness", quality.sharpness.to_object(py));
+    map.insert("upscale", quality.upscale.to_object(py));
+    map.insert("jpeg_quality", quality.jpeg_quality.to_object(py));
+    map.insert("blockiness", quality.blockiness.to_object(py));
+    map.insert("dimensions", quality.dimensions.to_object(py));
+    Ok(map.to_object(py))
+}
+
+/// Measure how far a hash moves when the image is edited.
+///
+/// Hashes the image and six variants made in Rust: downscaled to half
+/// size, recompressed as JPEG at quality 50, rotated by 1 degree,
+/// brightened and darkened by 10%, and cropped by 5%. Run it over images
+/// typical of a collection to pick the algorithm that holds still for that
+/// content and a threshold that still matches such copies.
+///
+/// Args:
+///     path: Path to the image file
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash",
+///                "blockhash", "marrhildreth" or "radialvariance"
+///     hash_size: Size of the hash (default: 8, 64-bit hashes)
+///
+/// Returns:
+///     Dict with "hash" (of the unmodified image), "distances" ({"resize_50",
+///     "jpeg_50", "rotate_1", "brighten_10", "darken_10", "crop_5": distance
+///     to "hash"}, in that order) and "max_distance" (the smallest threshold
+///     matching every variant)
+#[pyfunction(name = "robustness_report")]
+#[pyo3(signature = (path, algorithm = "phash", hash_size = 8))]
+fn py_robustness_report(
+    py: Python<'_>,
+    path: PathBuf,
+    algorithm: &str,
+    hash_size: usize,
+) -> PyResult<PyObject> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let report = py
+        .allow_threads(|| robustness_report(path, algorithm, hash_size))
+        .map_err(hash_err)?;
+    let distances = report
+        .distances
+        .iter()
+        .map(|(p, d)| (p.to_string(), *d))
+        .into_py_dict_bound(py);
+    let mut map: HashMap<&str, PyObject> = HashMap::new();
+    map.insert("hash", report.hash.to_hex().to_object(py));
+    map.insert("distances", distances.to_object(py));
+    map.insert("max_distance", report.max_distance().to_object(py));
+    Ok(map.to_object(py))
+}
+
+/// Find duplicate video clips.
+///
+/// Each clip is hashed from frames sampled across its length; clips whose
+/// frames match at the same positions are grouped. Without a
+/// frame_provider, keyframes are extracted with ffmpeg/ffprobe, which
+/// requires the `video` feature and both tools on PATH.
+///
+/// Args:
+///     paths: List of video file paths
+///     threshold: Maximum mean Hamming distance per frame (default: 10.0)
+///     frames: Frames sampled per clip (default: 10)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"
+///     frame_provider: Optional callable (path, count) -> [(position, image)]
+///                     returning up to count frames as PIL images or numpy
+///                     arrays, with positions from 0.0 (start) to 1.0 (end)
+///     relative_to: Report paths relative to this root (default: absolute)
+///     progress_callback: Optional callable(done, total, path) invoked as
+///                        clips finish; raising an exception cancels
+///     return_errors: Also return the clips that could not be hashed
+///                    (default: False)
+///
+/// Returns:
+///     List of duplicate groups as for find_duplicate_images, where "hash"
+///     is the video hash and "best" is the largest file. With
+///     return_errors=True, a (groups, errors) tuple instead.
+#[pyfunction]
+#[pyo3(signature = (
+    paths,
+    threshold = 10.0,
+    frames = DEFAULT_VIDEO_FRAMES,
+    algorithm = "phash",
+    frame_provider = None,
+    relative_to = None,
+    progress_callback = None,
+    return_errors = false
+))]
+#[allow(clippy::too_many_arguments)]
+fn find_duplicate_videos(
+    py: Python<'_>,
+    paths: Vec<PathBuf>,
+    threshold: f64,
+    frames: usize,
+    algorithm: &str,
+    frame_provider: Option<PyObject>,
+    relative_to: Option<PathBuf>,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+) -> PyResult<PyObject> {
+    let relative_to = relative_to.as_deref();
+    let algorithm = parse_algorithm(algorithm)?;
+    if frames == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "frames must be at least 1",
+        ));
+    }
+    let callback_frames = frame_provider.map(|callback| CallbackFrames { callback });
+    let ffmpeg = FfmpegFrames::default();
+    let provider: &dyn FrameProvider = match &callback_frames {
+        Some(frames) => frames,
+        None => &ffmpeg,
+    };
+
+    let callback = progress_callback.map(CallbackProgress::new);
+    let found = run_batch(py, None, || {
+        find_video_duplicates(
+            &paths,
+            provider,
+            frames,
+            algorithm,
+            threshold,
+            callback.as_ref().map(|c| c as &dyn Progress),
+        )
+    })?;
+    let (groups, failures) = match found {
+        Ok(found) => found,
+        Err(e) => {
+            return Err(match callback.and_then(|c| c.take_error()) {
+                Some(raised) => raised,
+                None => pyo3::exceptions::PyRuntimeError::new_err(e),
+            })
+        }
+    };
+
+    let result: Vec<PyDuplicateGroup> = groups
+        .iter()
+        .filter(|g| g.paths.len() > 1)
+        .map(|g| match relative_to {
+            Some(root) => PyDuplicateGroup::new(&g.relative_to(root)),
+            None => PyDuplicateGroup::new(g),
+        })
+        .collect();
+    if return_errors {
+        Ok((result, errors_to_dict(failures, relative_to)).into_py(py))
+    } else {
+        Ok(result.into_py(py))
+    }
+}
+
+/// Export duplicate groups as a JSON manifest or an HTML report.
+///
+/// Args:
+///     groups: Groups as returned by find_duplicate_images
+///     path: Output file
+///     format: "json" (default) or "html"
+///     thumbnails: Embed a JPEG thumbnail of each group's best image as a
+///                 base64 data URI (default: False)
+///     thumbnail_size: Thumbnail edge length in pixels (default: 64)
+///     root: Directory that relative paths in groups are resolved against
+///           when reading images for thumbnails (optional)
+///
+/// Groups whose best image cannot be read are exported without a
+/// thumbnail.
+#[pyfunction]
+#[pyo3(signature = (
+    groups,
+    path,
+    format = "json",
+    thumbnails = false,
+    thumbnail_size = DEFAULT_THUMBNAIL_SIZE,
+    root = None
+))]
+fn export_report(
+    py: Python<'_>,
+    groups: Vec<Bound<'_, PyAny>>,
+    path: PathBuf,
+    format: &str,
+    thumbnails: bool,
+    thumbnail_size: u32,
+    root: Option<PathBuf>,
+) -> PyResult<()> {
+    let format: ReportFormat = format
+        .parse()
+        .map_err(|e: String| pyo3::exceptions::PyValueError::new_err(e))?;
+    if thumbnails && thumbnail_size == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "thumbnail_size must be at least 1",
+        ));
+    }
+    let groups = groups
+        .iter()
+        .map(|g| group_from_py(py, g))
+        .collect::<PyResult<Vec<_>>>()?;
+    let options = ReportOptions {
+        thumbnail_size: thumbnails.then_some(thumbnail_size),
+        root,
+        all_members: false,
+    };
+
+    let report = run_batch(py, None, || render_report(&groups, format, &options))?;
+    std::fs::write(&path, report).map_err(|e| {
+        pyo3::exceptions::PyIOError::new_err(format!("Failed to write {}: {}", path.display(), e))
+    })
+}
+
+/// Write an HTML page for reviewing proposed deletions.
+///
+/// Each group lists every member with an embedded thumbnail and its hash
+/// distance from the best image, which is highlighted as the copy to keep;
+/// the group's largest and mean distance head it. The page is
+/// self-contained, so it can be opened where the files aren't mounted.
+///
+/// Args:
+///     groups: Groups as returned by find_duplicate_images
+///     output_path: HTML file to write
+///     root: Directory that relative paths in groups are resolved against
+///           when reading images for thumbnails (optional)
+///
+/// Images that cannot be read are listed without a thumbnail.
+#[pyfunction(name = "generate_report")]
+#[pyo3(signature = (groups, output_path, root = None))]
+fn py_generate_report(
+    py: Python<'_>,
+    groups: Vec<Bound<'_, PyAny>>,
+    output_path: PathBuf,
+    root: Option<PathBuf>,
+) -> PyResult<()> {
+    let groups = groups
+        .iter()
+        .map(|g| group_from_py(py, g))
+        .collect::<PyResult<Vec<_>>>()?;
+    run_batch(py, None, || {
+        generate_report(&groups, output_path, root.as_deref())
+    })?
+    .map_err(pyo3::exceptions::PyIOError::new_err)
+}
+
+/// Save duplicate groups and the scan settings to a JSON or CSV file.
+///
+/// JSON holds everything in one document. CSV has one row per group
+/// member (columns key, path, best, hash, member_hash, distance,
+/// content_type, sidecars), preceded by "# name=value" lines with the
+/// settings; read it with e.g. pandas.read_csv(path, comment="#").
+///
+/// Args:
+///     groups: Groups as returned by find_duplicate_images
+///     path: Output file
+///     format: "json" (default) or "csv"
+///     algorithm: Algorithm the groups were found with (optional)
+///     threshold: Threshold the groups were found with (optional)
+///     hash_size: Hash size the groups were found with (optional)
+///     root: Directory relative group paths are relative to (optional)
+#[pyfunction(name = "export_results")]
+#[pyo3(signature = (
+    groups,
+    path,
+    format = "json",
+    algorithm = None,
+    threshold = None,
+    hash_size = None,
+    root = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn py_export_results(
+    py: Python<'_>,
+    groups: Vec<Bound<'_, PyAny>>,
+    path: PathBuf,
+    format: &str,
+    algorithm: Option<&str>,
+    threshold: Option<u32>,
+    hash_size: Option<usize>,
+    root: Option<PathBuf>,
+) -> PyResult<()> {
+    let format: ResultsFormat = format
+        .parse()
+        .map_err(|e: String| pyo3::exceptions::PyValueError::new_err(e))?;
+    let groups = groups
+        .iter()
+        .map(|g| group_from_py(py, g))
+        .collect::<PyResult<Vec<_>>>()?;
+    let created = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .ok();
+    let results = ScanResults {
+        metadata: ScanMetadata {
+            algorithm: algorithm.map(parse_algorithm).transpose()?,
+            hash_size,
+            threshold,
+            root,
+            created,
+        },
+        groups,
+    };
+    py.allow_threads(|| export_results(&results, &path, format))
+        .map_err(pyo3::exceptions::PyIOError::new_err)
+}
+
+/// Load duplicate groups saved with export_results.
+///
+/// Args:
+///     path: File written by export_results, in either format
+///
+/// Returns:
+///     Dictionary with "metadata" ({"algorithm", "threshold", "hash_size",
+///     "root", "created"}, each None if not saved; "created" is seconds
+///     since the Unix epoch) and "groups" (as from find_duplicate_images,
+///     without "review")
+#[pyfunction(name = "import_results")]
+fn py_import_results(py: Python<'_>, path: PathBuf) -> PyResult<HashMap<&'static str, PyObject>> {
+    let results = py
+        .allow_threads(|| import_results(&path))
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let meta = &results.metadata;
+    let mut metadata: HashMap<&str, PyObject> = HashMap::new();
+    metadata.insert(
+        "algorithm",
+        meta.algorithm.map(|a| a.to_string()).to_object(py),
+    );
+    metadata.insert("threshold", meta.threshold.to_object(py));
+    metadata.insert("hash_size", meta.hash_size.to_object(py));
+    metadata.insert("root", meta.root.to_object(py));
+    metadata.insert("created", meta.created.to_object(py));
+    let groups: Vec<PyDuplicateGroup> = results.groups.iter().map(PyDuplicateGroup::new).collect();
+
+    let mut map = HashMap::new();
+    map.insert("metadata", metadata.to_object(py));
+    map.insert("groups", groups.into_py(py));
+    Ok(map)
+}
+
+/// Benchmark the hash index and duplicate grouping on a synthetic corpus.
+///
+/// Generates `size` hashes, inserts them into a BK-tree, runs `queries`
+/// threshold queries in parallel and groups the whole corpus, on all
+/// threads and then on one. The corpus depends only on the arguments, so
+/// results compare across machines.
+///
+/// Args:
+///     size: Number of synthetic hashes (default: 100000)
+///     hash_size: Size of the hashes (default: 8, 64-bit hashes)
+///     distribution: "uniform", "clustered" (default, clusters of 4 within
+///                   4 bits) or "clustered:<size>:<max bit flips>"
+///     threshold: Hamming distance for queries and grouping (default: 10)
+///     queries: Number of index queries (default: 10000)
+///     seed: Seed for the corpus (default: library default seed)
+///
+/// Returns:
+///     Dictionary with corpus_size, threads, build_secs, inserts_per_sec,
+///     queries, query_secs, queries_per_sec, mean_matches, group_secs,
+///     group_secs_single (one thread), group_speedup, duplicate_groups,
+///     index_bytes and peak_rss_bytes (None off Linux)
+#[pyfunction]
+#[pyo3(signature = (
+    size = 100_000,
+    hash_size = 8,
+    distribution = "clustered",
+    threshold = 10,
+    queries = 10_000,
+    seed = None
+))]
+fn benchmark_index(
+    py: Python<'_>,
+    size: usize,
+    hash_size: usize,
+    distribution: &str,
+    threshold: u32,
+    queries: usize,
+    seed: Option<u64>,
+) -> PyResult<HashMap<&'static str, PyObject>> {
+    if hash_size == 0 {
+        return Err(pyo3::exceptions::PyValueError::new_err(
+            "hash_size must be at least 1",
+        ));
+    }
+    let spec = CorpusSpec {
+        size,
+        hash_size,
+        distribution: distribution
+            .parse()
+            .map_err(|e: String| pyo3::exceptions::PyValueError::new_err(e))?,
+        seed: seed.unwrap_or(DEFAULT_SEED),
+    };
+    let report = run_batch(py, None, || run_benchmark(&spec, threshold, queries))?;
+
+    let mut result = HashMap::new();
+    result.insert("corpus_size", report.corpus_size.to_object(py));
+    result.insert("threads", report.threads.to_object(py));
+    result.insert("build_secs", report.build_secs.to_object(py));
+    result.insert("inserts_per_sec", report.inserts_per_sec.to_object(py));
+    result.insert("queries", report.queries.to_object(py));
+    result.insert("query_secs", report.query_secs.to_object(py));
+    result.insert("queries_per_sec", report.queries_per_sec.to_object(py));
+    result.insert("mean_matches", report.mean_matches.to_object(py));
+    result.insert("group_secs", report.group_secs.to_object(py));
+    result.insert("group_secs_single", report.group_secs_single.to_object(py));
+    result.insert("group_speedup", report.group_speedup.to_object(py));
+    result.insert("duplicate_groups", report.duplicate_groups.to_object(py));
+    result.insert("index_bytes", report.index_bytes.to_object(py));
+    result.insert("peak_rss_bytes", report.peak_rss_bytes.to_object(py));
+    Ok(result)
+}
+
+/// Find photos that already appear on pages of documents.
+///
+/// Pages are matched as rendered images; render PDFs first, e.g. with
+/// `pdftoppm -r 100 -png doc.pdf pages/doc`. With the "pdf" feature, PDFs
+/// can instead be passed as a list and are matched through the JPEG
+/// images embedded in them, each image counting as a page in file order.
+///
+/// Args:
+///     photos: List of photo paths
+///     documents: {document path: [page image paths in page order]}, or a
+///                list of PDF paths
+///     threshold: Maximum Hamming distance to consider a match (default: 10)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"
+///     return_errors: Also return the photos, pages and PDFs that could not
+///                    be hashed (default: False)
+///
+/// Returns:
+///     List of matches ordered by photo and distance, each containing
+///     "photo", "document", "page" (1-based) and "distance". With
+///     return_errors=True, a (matches, errors) tuple instead, where errors
+///     is as for compute_hashes_parallel.
+#[pyfunction]
+#[pyo3(signature = (photos, documents, threshold = 10, algorithm = "phash", return_errors = false))]
+fn find_photos_in_documents(
+    py: Python<'_>,
+    photos: Vec<PathBuf>,
+    documents: PyObject,
+    threshold: u32,
+    algorithm: &str,
+    return_errors: bool,
+) -> PyResult<PyObject> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let pages: Vec<DocumentPage> = match documents.extract::<BTreeMap<PathBuf, Vec<PathBuf>>>(py) {
+        Ok(renders) => renders
+            .into_iter()
+            .flat_map(|(document, renders)| {
+                renders
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(i, path)| DocumentPage {
+                        document: document.clone(),
+                        page: i + 1,
+                        path,
+                    })
+            })
+            .collect(),
+        Err(_) => documents
+            .extract::<Vec<PathBuf>>(py)?
+            .into_iter()
+            .map(DocumentPage::pdf)
+            .collect(),
+    };
+
+    let (matches, failures) = run_batch(py, None, || {
+        match_documents(&photos, &pages, algorithm, threshold, None)
+    })?
+    .map_err(pyo3::exceptions::PyRuntimeError::new_err)?;
+
+    let result: Vec<HashMap<&str, PyObject>> = matches
+        .iter()
+        .map(|m| {
+            let mut map = HashMap::new();
+            map.insert("photo", m.photo.to_object(py));
+            map.insert("document", m.document.to_object(py));
+            map.insert("page", m.page.to_object(py));
+            map.insert("distance", m.distance.to_object(py));
+            map
+        })
+        .collect();
+    if return_errors {
+        Ok((result, errors_to_dict(failures, None)).to_object(py))
+    } else {
+        Ok(result.to_object(py))
+    }
+}
+
+/// Find which images of one collection already exist in another.
+///
+/// Only pairs across the collections are compared, e.g. new imports
+/// against an archive; two imports or two archive images are never
+/// compared with each other.
+///
+/// Args:
+///     paths_a: Image paths of the first collection (e.g. new imports)
+///     paths_b: Image paths of the second collection (e.g. the archive)
+///     threshold: Maximum Hamming distance to consider a match (default: 10)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"
+///     hash_size: Size of the hashes (default: 8, 64-bit hashes)
+///     return_errors: Also return the files that could not be hashed
+///                    (default: False)
+///
+/// Returns:
+///     List of (a_path, b_path, distance) tuples ordered by a_path, then
+///     distance. With return_errors=True, a (matches, errors) tuple
+///     instead, where errors is as for compute_hashes_parallel.
+#[pyfunction(name = "match_collections")]
+#[pyo3(signature = (
+    paths_a,
+    paths_b,
+    threshold = 10,
+    algorithm = "phash",
+    hash_size = 8,
+    return_errors = false
+))]
+fn py_match_collections(
+    py: Python<'_>,
+    paths_a: Vec<PathBuf>,
+    paths_b: Vec<PathBuf>,
+    threshold: u32,
+    algorithm: &str,
+    hash_size: usize,
+    return_errors: bool,
+) -> PyResult<PyObject> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let (matches, failures) = run_batch(py, None, || {
+        match_collections(&paths_a, &paths_b, algorithm, hash_size, threshold, None)
+    })?
+    .map_err(pyo3::exceptions::PyRuntimeError::new_err)?;
+
+    let result: Vec<(PathBuf, PathBuf, u32)> = matches
+        .into_iter()
+        .map(|m| (m.path_a, m.path_b, m.distance))
+        .collect();
+    if return_errors {
+        Ok((result, errors_to_dict(failures, None)).to_object(py))
+    } else {
+        Ok(result.to_object(py))
+    }
+}
+
+/// Find duplicates of new images against hashes from a previous scan.
+///
+/// Only the new files are hashed, so nightly re-scans of a large library
+/// cost time proportional to what changed.
+///
+/// Args:
+///     new_paths: Image files added since the previous scan
+///     existing: Dictionary mapping previously scanned paths to hex hashes
+///               (e.g. from compute_hashes_parallel)
+///     threshold: Maximum Hamming distance for duplicates (default: 10)
+///     algorithm: Must match the algorithm used for `existing`
+///     relative_to: Scan root; if given, reported paths are relative to it
+///     hash_size: Must match the hash size used for `existing` (default: 8)
+///
+/// Returns:
+///     Duplicate groups (same format as find_duplicate_images) that contain
+///     at least one new file, plus "new": the new paths in the group
+#[pyfunction(name = "find_duplicates_incremental")]
+#[pyo3(signature = (new_paths, existing, threshold = 10, algorithm = "phash", relative_to = None, hash_size = 8))]
+fn py_find_duplicates_incremental(
+    py: Python<'_>,
+    new_paths: Vec<PathBuf>,
+    existing: HashMap<PathBuf, String>,
+    threshold: u32,
+    algorithm: &str,
+    relative_to: Option<PathBuf>,
+    hash_size: usize,
+) -> PyResult<Vec<PyDuplicateGroup>> {
+    let relative_to = relative_to.as_deref();
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let existing = existing
+        .into_iter()
+        .map(|(path, hex)| ImageHash::from_hex(&hex).map(|h| (path, h)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(hash_err)?;
+    let groups = run_batch(py, None, || {
+        find_duplicates_incremental(&new_paths, &existing, algorithm, hash_size, threshold)
+    })?
+    .map_err(pyo3::exceptions::PyRuntimeError::new_err)?;
+
+    let new_set: std::collections::HashSet<&PathBuf> = new_paths.iter().collect();
+    let result: Vec<PyDuplicateGroup> = groups
+        .into_iter()
+        .filter(|g| g.paths.len() > 1)
+        .map(|g| {
+            let new: Vec<PathBuf> = g
+                .paths
+                .iter()
+                .filter(|p| new_set.contains(p))
+                .cloned()
+                .collect();
+            let (g, new) = match relative_to {
+                Some(root) => {
+                    let new = new.iter().map(|p| relative_path(p, root)).collect();
+                    (g.relative_to(root), new)
+                }
+                None => (g, new),
+            };
+            let mut group = PyDuplicateGroup::new(&g);
+            group.new = Some(new);
+            group
+        })
+        .collect();
+    Ok(result)
+}
+
+/// Record a reviewer decision for a duplicate group.
+///
+/// The review file is created if needed and rewritten atomically.
+///
+/// Args:
+///     review_path: Path of the review file (kept next to the scan output)
+///     key: Group "key" from find_duplicate_images
+///     state: "approved", "rejected" or "needs-review"
+///     note: Free-text note (default: "")
+#[pyfunction]
+#[pyo3(signature = (review_path, key, state, note = ""))]
+fn annotate_group(review_path: PathBuf, key: &str, state: &str, note: &str) -> PyResult<()> {
+    let state: ReviewState = state
+        .parse()
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let mut store =
+        ReviewStore::load(&review_path).map_err(pyo3::exceptions::PyIOError::new_err)?;
+
+    store.annotate(key, state, note);
+    store
+        .save(&review_path)
+        .map_err(pyo3::exceptions::PyIOError::new_err)
+}
+
+/// Compute perceptual hashes for multiple images in parallel.
+///
+/// Args:
+///     paths: List of image file paths
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash",
+///                "blockhash", "marrhildreth" (hash_size 24) or
+///                "radialvariance" (hash_size = coefficients, 40 in libphash)
+///     relative_to: Scan root; if given, returned paths are relative to it
+///     cache_path: Hash cache file (optional). Files whose size and mtime
+///                 are unchanged since they were cached are not re-hashed;
+///                 the file is created or updated after the run.
+///     progress_socket: Local socket to publish progress on (optional):
+///                      a Unix socket path, or a loopback "host:port".
+///                      Subscribers receive newline-delimited JSON events
+///                      ("start", "progress", "group", "warning",
+///                      "done", "error").
+///     progress_callback: Called as progress_callback(done, total, path)
+///                        from worker threads with the GIL held, at most
+///                        every 100 ms and always for the last file
+///                        (optional). Raising from it cancels the run and
+///                        the exception propagates to the caller.
+///     return_errors: Also return the files that could not be hashed
+///                    (default: False)
+///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
+///     trim_borders, center_crop, normalize_gamma, mask_regions,
+///     alpha_background, dct_size, filter:
+///         Preprocessing, as for compute_hash
+///     num_threads: Threads to hash with (default: the set_num_threads
+///                  limit, else one per core)
+///     return_partial: On Ctrl+C, return the hashes finished so far
+///                     instead of raising KeyboardInterrupt (default:
+///                     False). Either way they are saved to cache_path.
+///     metadata: Map each path to {"hash", "size", "mtime", "dimensions",
+///               "format"} instead of its hash alone (default: False), as
+///               for the "metadata" of find_duplicate_images groups
+///     apply_exif_orientation: Turn images upright by their EXIF
+///                             orientation before hashing, or not (default:
+///                             as set by set_exif_orientation). Cached
+///                             separately for each value.
+///
+/// Returns:
+///     Dictionary mapping file paths to their hash strings, ordered by path.
+///     Failed images are excluded from the result. With return_errors=True,
+///     a (hashes, errors) tuple instead, where errors maps each failed path
+///     to {"kind": ..., "message": ...} and kind is one of "io",
+///     "unsupported_format", "decode", "too_large" or "invalid_input".
+#[pyfunction]
+#[pyo3(signature = (
+    paths,
+    algorithm = "phash",
+    relative_to = None,
+    cache_path = None,
+    progress_socket = None,
+    progress_callback = None,
+    return_errors = false,
+    hash_size = 8,
+    trim_borders = false,
+    center_crop = None,
+    normalize_gamma = false,
+    mask_regions = None,
+    alpha_background = None,
+    dct_size = DCT_SIZE,
+    filter = "lanczos3",
+    num_threads = None,
+    return_partial = false,
+    metadata = false,
+    apply_exif_orientation = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn compute_hashes_parallel(
+    py: Python<'_>,
+    paths: Vec<PathBuf>,
+    algorithm: &str,
+    relative_to: Option<PathBuf>,
+    cache_path: Option<PathBuf>,
+    progress_socket: Option<&str>,
+    progress_callback: Option<PyObject>,
+    return_errors: bool,
+    hash_size: usize,
+    trim_borders: bool,
+    center_crop: Option<f32>,
+    normalize_gamma: bool,
+    mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+    alpha_background: Option<(u8, u8, u8)>,
+    dct_size: usize,
+    filter: &str,
+    num_threads: Option<usize>,
+    return_partial: bool,
+    metadata: bool,
+    apply_exif_orientation: Option<bool>,
+) -> PyResult<PyObject> {
+    let relative_to = relative_to.as_deref();
+    let algorithm = parse_algorithm(algorithm)?;
+    let options = HashOptions {
+        apply_exif_orientation,
+        ..hash_options(
+            trim_borders,
+            center_crop,
+            normalize_gamma,
+            mask_regions,
+            alpha_background,
+            dct_size,
+            filter,
+        )?
+    };
+    options
+        .phash
+        .check_hash_size(algorithm, hash_size)
+        .map_err(hash_err)?;
+    let mut cache = cache_path
+        .as_ref()
+        .map(HashCache::load)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let socket = progress_socket
+        .map(ProgressSocket::bind)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+
+    let callback = progress_callback.map(CallbackProgress::new);
+    let warnings = WarningCollector::new();
+    let cancel = CancelToken::new();
+
+    let mut sinks: Vec<&dyn Progress> = vec![&warnings, &cancel];
+    sinks.extend(socket.as_ref().map(|s| s as &dyn Progress));
+    sinks.extend(callback.as_ref().map(|c| c as &dyn Progress));
+    let progress = Fanout(sinks);
+    let (hashes, interrupt) = run_cancellable(py, num_threads, &cancel, || {
+        hash_paths_with(
+            &paths,
+            algorithm,
+            hash_size,
+            false,
+            &options,
+            cache.as_mut(),
+            Some(&progress),
+        )
+    })?;
+    drop(progress);
+
+    if let Some(raised) = callback.and_then(|c| c.take_error()) {
+        if let Some(socket) = socket {
+            socket.fail("Cancelled");
+        }
+        return Err(raised);
+    }
+    if let (Some(cache), Some(cache_path)) = (&cache, &cache_path) {
+        cache
+            .save(cache_path)
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    }
+    if let Some(interrupt) = interrupt.filter(|_| !return_partial) {
+        if let Some(socket) = socket {
+            socket.fail("Interrupted");
+        }
+        return Err(interrupt);
+    }
+    if let Some(socket) = socket {
+        socket.finish(0);
+    }
+    emit_warnings(py, warnings, relative_to, return_errors)?;
+
+    let mut hashed: Vec<(PathBuf, CachedHash)> = Vec::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in hashes {
+        match result {
+            Ok(cached) => hashed.push((path, cached)),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    let output_path = |path: &Path| match relative_to {
+        Some(root) => relative_path(path, root),
+        None => path.to_path_buf(),
+    };
+
+    let results = if metadata {
+        let files: Vec<Option<FileMetadata>> = py.allow_threads(|| {
+            hashed
+                .par_iter()
+                .map(|(path, cached)| FileMetadata::of(path, Some(cached.dimensions)))
+                .collect()
+        });
+        let results: BTreeMap<PathBuf, HashMap<&str, PyObject>> = hashed
+            .iter()
+            .zip(&files)
+            .map(|((path, cached), meta)| {
+                let mut entry = metadata_dict(py, meta.as_ref(), Some(cached.dimensions));
+                entry.insert("hash", cached.hash.to_hex().to_object(py));
+                (output_path(path), entry)
+            })
+            .collect();
+        results.to_object(py)
+    } else {
+        let results: BTreeMap<PathBuf, String> = hashed
+            .iter()
+            .map(|(path, cached)| (output_path(path), cached.hash.to_hex()))
+            .collect();
+        results.to_object(py)
+    };
+
+    if return_errors {
+        Ok((results, errors_to_dict(failures, relative_to)).to_object(py))
+    } else {
+        Ok(results)
+    }
+}
+
+/// Failed paths and their errors as returned to Python
+fn errors_to_dict(
+    failures: HashFailures,
+    relative_to: Option<&Path>,
+) -> BTreeMap<PathBuf, HashMap<&'static str, String>> {
+    failures
+        .into_iter()
+        .map(|(path, e)| {
+            let path = match relative_to {
+                Some(root) => relative_path(&path, root),
+                None => path,
+            };
+            let mut error = HashMap::new();
+            error.insert("kind", e.kind().to_string());
+            error.insert("message", e.to_string());
+            (path, error)
+        })
+        .collect()
+}
+
+/// Issue collected warnings through Python's `warnings` module
+///
+/// Each becomes a `UserWarning` with a message of the form
+/// `"[kind] path: message"`, so embedders can capture them with
+/// `warnings.catch_warnings(record=True)` or turn them into errors.
+/// Files that failed to hash are left out when `errors_returned`, as the
+/// caller gets them in the errors dictionary instead.
+fn emit_warnings(
+    py: Python<'_>,
+    warnings: WarningCollector,
+    relative_to: Option<&Path>,
+    errors_returned: bool,
+) -> PyResult<()> {
+    let mut warnings = warnings.into_sorted();
+    if errors_returned {
+        warnings.retain(|w| w.kind != WarningKind::HashFailed);
+    }
+    if warnings.is_empty() {
+        return Ok(());
+    }
+
+    let module = py.import_bound("warnings")?;
+    for mut warning in warnings {
+        if let Some(root) = relative_to {
+            warning.path = relative_path(&warning.path, root);
+        }
+        module.call_method1("warn", (warning.to_string(),))?;
+    }
+    Ok(())
+}
+
+/// Python exception for a hashing error
+fn hash_err(e: HashError) -> PyErr {
+    match e {
+        HashError::InvalidInput(_) => pyo3::exceptions::PyValueError::new_err(e.to_string()),
+        _ => pyo3::exceptions::PyIOError::new_err(e.to_string()),
+    }
+}
+
+/// Perceptual hash object, comparable like imagehash's ImageHash.
+///
+/// Keeps the hash size and parses the hex string once, so repeated
+/// comparisons do not reparse.
+///
+/// Example:
+///     a = phash_rs.ImageHash.from_path("a.jpg")
+///     b = phash_rs.ImageHash.from_hex(phash_rs.compute_hash("b.jpg"))
+///     a - b   # Hamming distance
+///     a == b  # identical bits
+#[pyclass(name = "ImageHash")]
+struct PyImageHash {
+    hash: ImageHash,
+}
+
+#[pymethods]
+impl PyImageHash {
+    /// Parse a hash from its hex string.
+    ///
+    /// Args:
+    ///     hex: Hex string
+    ///     bit_order: "native" (default) or "imagehash" for str() of a hash
+    ///                from the Python imagehash package
+    #[staticmethod]
+    #[pyo3(signature = (hex, bit_order = "native"))]
+    fn from_hex(hex: &str, bit_order: &str) -> PyResult<Self> {
+        let hash =
+            ImageHash::from_hex_ordered(hex, parse_bit_order(bit_order)?).map_err(hash_err)?;
+        Ok(Self { hash })
+    }
+
+    /// Parse a hash from the text returned by to_tagged.
+    ///
+    /// Raises ValueError if the bit count doesn't match the algorithm and
+    /// hash size named in the text.
+    #[staticmethod]
+    fn from_tagged(text: &str) -> PyResult<Self> {
+        let hash = ImageHash::from_tagged(text).map_err(hash_err)?;
+        Ok(Self { hash })
+    }
+
+    /// Parse a hash from the bytes returned by to_bytes.
+    ///
+    /// Args:
+    ///     data: Hash bytes
+    ///     hash_size: Size the hash was computed with (optional; derived
+    ///                from the bit count if omitted, as for from_hex).
+    ///                Raises ValueError unless data holds one hash_size x
+    ///                hash_size bit plane (hash_size bytes for radial
+    ///                variance); use to_tagged for other hashes
+    #[staticmethod]
+    #[pyo3(signature = (data, hash_size = None))]
+    fn from_bytes(data: &[u8], hash_size: Option<usize>) -> PyResult<Self> {
+        let hash = ImageHash::from_hash_bytes(data, hash_size).map_err(hash_err)?;
+        Ok(Self { hash })
+    }
+
+    /// Parse a hash from the text returned by to_base64.
+    ///
+    /// Args:
+    ///     text: Standard base64, padding optional
+    ///     hash_size: Size the hash was computed with, as for from_bytes
+    #[staticmethod]
+    #[pyo3(signature = (text, hash_size = None))]
+    fn from_base64(text: &str, hash_size: Option<usize>) -> PyResult<Self> {
+        let hash = ImageHash::from_base64(text, hash_size).map_err(hash_err)?;
+        Ok(Self { hash })
+    }
+
+    /// Make a 64-bit hash (hash size 8) from the integer returned by to_u64.
+    ///
+    /// Args:
+    ///     value: The hash as an integer
+    ///     signed: `value` is a signed 64-bit integer, as stored in a
+    ///             BIGINT column (default: False)
+    #[staticmethod]
+    #[pyo3(signature = (value, signed = false))]
+    fn from_u64(value: &Bound<'_, PyAny>, signed: bool) -> PyResult<Self> {
+        let value = if signed {
+            value.extract::<i64>()? as u64
+        } else {
+            value.extract::<u64>()?
+        };
+        Ok(Self {
+            hash: ImageHash::from_u64(value),
+        })
+    }
+
+    /// Compute the hash of an image file.
+    ///
+    /// Args:
+    ///     path: Path to the image file
+    ///     hash_size: Size of the hash (default: 8, produces 64-bit hash)
+    ///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash",
+    ///                "blockhash", "marrhildreth" or "radialvariance"
+    ///     frames: "first" (default), "leading:<n>" or "evenly:<n>", as for
+    ///             compute_hash
+    ///     trim_borders, center_crop, normalize_gamma, mask_regions,
+    ///     alpha_background, dct_size, filter:
+    ///         Preprocessing, as for compute_hash; not combinable with
+    ///         frames
+    ///     bit_order: "native" (default) or "imagehash", as for
+    ///                compute_hash; not combinable with frames or
+    ///                preprocessing
+    #[staticmethod]
+    #[pyo3(signature = (
+        path,
+        hash_size = 8,
+        algorithm = "phash",
+        frames = "first",
+        trim_borders = false,
+        center_crop = None,
+        normalize_gamma = false,
+        mask_regions = None,
+        alpha_background = None,
+        dct_size = DCT_SIZE,
+        filter = "lanczos3",
+        bit_order = "native"
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn from_path(
+        py: Python<'_>,
+        path: PathBuf,
+        hash_size: usize,
+        algorithm: &str,
+        frames: &str,
+        trim_borders: bool,
+        center_crop: Option<f32>,
+        normalize_gamma: bool,
+        mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+        alpha_background: Option<(u8, u8, u8)>,
+        dct_size: usize,
+        filter: &str,
+        bit_order: &str,
+    ) -> PyResult<Self> {
+        let algorithm = parse_algorithm(algorithm)?;
+        let sampling = parse_frame_sampling(frames)?;
+        let options = hash_options(
+            trim_borders,
+            center_crop,
+            normalize_gamma,
+            mask_regions,
+            alpha_background,
+            dct_size,
+            filter,
+        )?;
+        if !options.is_identity() && sampling != FrameSampling::First {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "preprocessing cannot be combined with frames",
+            ));
+        }
+        let bit_order = parse_bit_order(bit_order)?;
+        if bit_order != BitOrder::Native
+            && (!options.is_identity() || sampling != FrameSampling::First)
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "bit_order=\"imagehash\" cannot be combined with frames or preprocessing",
+            ));
+        }
+        let hash = py
+            .allow_threads(|| {
+                if bit_order != BitOrder::Native {
+                    ImageHash::from_path_ordered(path, algorithm, hash_size, bit_order)
+                } else if options.is_identity() {
+                    ImageHash::from_path_frames(path, algorithm, hash_size, sampling)
+                } else {
+                    ImageHash::from_path_with(path, algorithm, hash_size, &options)
+                }
+            })
+            .map_err(hash_err)?;
+        Ok(Self { hash })
+    }
+
+    /// Hex string representation, as returned by compute_hash.
+    ///
+    /// Args:
+    ///     bit_order: "native" (default) or "imagehash" to write it as
+    ///                str() of the same hash in the Python imagehash
+    ///                package; differs only for bit counts that are not a
+    ///                multiple of 8
+    #[pyo3(signature = (bit_order = "native"))]
+    fn to_hex(&self, bit_order: &str) -> PyResult<String> {
+        Ok(self.hash.to_hex_ordered(parse_bit_order(bit_order)?))
+    }
+
+    /// Self-describing text "<algorithm>:<hash size>:<hex>", e.g.
+    /// "phash:8:d1c4...", parsed back by from_tagged.
+    ///
+    /// Raises ValueError for hashes of unknown algorithm, i.e. parsed from
+    /// hex, bytes or integers.
+    fn to_tagged(&self) -> PyResult<String> {
+        self.hash.to_tagged().ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(
+                "The algorithm of this hash is unknown; it was not computed or parsed from a tagged hash",
+            )
+        })
+    }
+
+    /// The hash as an unsigned integer (first bit most significant).
+    fn to_int(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let int = py.import_bound("builtins")?.getattr("int")?;
+        Ok(int.call1((self.hash.to_hex(), 16))?.unbind())
+    }
+
+    /// The hash bytes, in the order of to_hex.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.hash.to_bytes())
+    }
+
+    /// The hash bytes as standard base64.
+    fn to_base64(&self) -> String {
+        self.hash.to_base64()
+    }
+
+    /// A 64-bit hash as an integer (first bit most significant).
+    ///
+    /// Raises ValueError for hashes of any other length.
+    ///
+    /// Args:
+    ///     signed: Return a signed 64-bit integer that fits a BIGINT
+    ///             column; from_u64(value, signed=True) reverses it
+    ///             (default: False)
+    #[pyo3(signature = (signed = false))]
+    fn to_u64(&self, py: Python<'_>, signed: bool) -> PyResult<PyObject> {
+        let value = self.hash.to_u64().ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(format!(
+                "Only 64-bit hashes fit an integer, this one has {} bits",
+                self.hash.bit_len()
+            ))
+        })?;
+        Ok(if signed {
+            (value as i64).to_object(py)
+        } else {
+            value.to_object(py)
+        })
+    }
+
+    /// The hash split into `bands` bit ranges, each as an integer.
+    ///
+    /// Store the keys in indexed BIGINT columns to prefilter similarity
+    /// searches in a database: rows matching one of index_probes on the
+    /// same band are the only ones worth comparing with hamming_distance.
+    /// Raises ValueError unless every band holds 1 to 64 bits.
+    ///
+    /// Args:
+    ///     bands: Number of bands; about one per bit of the threshold
+    ///            you will search with
+    ///     signed: Return signed 64-bit integers, as for to_u64
+    ///             (default: False)
+    #[pyo3(signature = (bands, signed = false))]
+    fn index_keys(&self, py: Python<'_>, bands: usize, signed: bool) -> PyResult<PyObject> {
+        let keys = self.hash.index_keys(bands).map_err(hash_err)?;
+        Ok(if signed {
+            keys.into_iter()
+                .map(|k| k as i64)
+                .collect::<Vec<_>>()
+                .to_object(py)
+        } else {
+            keys.to_object(py)
+        })
+    }
+
+    /// Band key values to look up so every hash within `threshold` matches
+    /// at least one.
+    ///
+    /// Entry b lists the values to match against band b of index_keys;
+    /// bands that need no lookup get an empty list. Matches are candidates
+    /// only; confirm them with hamming_distance. Raises ValueError, naming
+    /// the fewest bands that would do, if the lists would hold more than
+    /// 2**20 keys in total.
+    ///
+    /// Args:
+    ///     bands: Number of bands the keys were stored with
+    ///     threshold: Maximum Hamming distance searched for
+    ///     signed: Return signed 64-bit integers, as for to_u64
+    ///             (default: False)
+    ///
+    /// Example:
+    ///     probes = query.index_probes(8, threshold=8, signed=True)
+    ///     where = " OR ".join(
+    ///         f"k{b} IN ({','.join(map(str, p))})" for b, p in enumerate(probes) if p
+    ///     )
+    #[pyo3(signature = (bands, threshold, signed = false))]
+    fn index_probes(
+        &self,
+        py: Python<'_>,
+        bands: usize,
+        threshold: u32,
+        signed: bool,
+    ) -> PyResult<PyObject> {
+        let probes = self.hash.index_probes(bands, threshold).map_err(hash_err)?;
+        Ok(if signed {
+            probes
+                .into_iter()
+                .map(|p| p.into_iter().map(|k| k as i64).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+                .to_object(py)
+        } else {
+            probes.to_object(py)
+        })
+    }
+
+    /// Individual bits as booleans, first bit first.
+    fn bits(&self) -> Vec<bool> {
+        self.hash.to_bits()
+    }
+
+    /// The bits as a boolean numpy array of shape (rows, hash_size).
+    ///
+    /// Row-major like bits, padding dropped: hash_size rows for a
+    /// grayscale hash, one hash_size x hash_size block per plane stacked
+    /// vertically otherwise (3 * hash_size rows for colorhash).
+    fn bit_matrix<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<bool>>> {
+        let size = self.hash.hash_size();
+        let rows = self.hash.bit_len() / (size * size) * size;
+        let mut bits = self.hash.to_bits();
+        bits.truncate(rows * size);
+        bits.into_pyarray_bound(py).reshape([rows, size])
+    }
+
+    /// Width/height of the comparison grid.
+    #[getter]
+    fn hash_size(&self) -> usize {
+        self.hash.hash_size()
+    }
+
+    /// Algorithm name, e.g. "phash"; None if unknown (see to_tagged).
+    #[getter]
+    fn algorithm(&self) -> Option<String> {
+        self.hash.algorithm().map(|a| a.to_string())
+    }
+
+    /// Hamming distance to another hash of the same length.
+    ///
+    /// Raises ValueError if the hashes differ in length or hash size, or
+    /// come from different algorithms.
+    fn distance(&self, other: PyRef<'_, Self>) -> PyResult<u32> {
+        self.hash.checked_distance(&other.hash).map_err(hash_err)
+    }
+
+    fn __sub__(&self, other: PyRef<'_, Self>) -> PyResult<u32> {
+        self.distance(other)
+    }
+
+    fn __eq__(&self, other: PyRef<'_, Self>) -> bool {
+        self.hash == other.hash
+    }
+
+    fn __hash__(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.hash.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn __len__(&self) -> usize {
+        self.hash.bit_len()
+    }
+
+    fn __str__(&self) -> String {
+        self.hash.to_hex()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ImageHash('{}')", self.hash.to_hex())
+    }
+}
+
+/// Metric index of image hashes for fast similarity lookups.
+///
+/// Backed by a BK-tree, so queries only compare against a fraction of the
+/// stored hashes. save() and load() keep it in a binary file that loads
+/// without rebuilding the tree.
+///
+/// Args:
+///     algorithm: Algorithm the hashes are computed with, recorded in saved
+///                files (optional)
+///     hash_size: Hash size they are computed with (optional; derived from
+///                the first hash when saving)
+///
+/// Example:
+///     index = phash_rs.HashIndex()
+///     index.add("a.jpg", phash_rs.compute_hash("a.jpg"))
+///     matches = index.query(phash_rs.compute_hash("b.jpg"), threshold=10)
+#[pyclass]
+struct HashIndex {
+    /// Tree, settings, sync state and file metadata, as saved by `save`
+    index: IndexFile,
+    /// Latest hash added for each path, for lookups by path
+    hashes: HashMap<PathBuf, ImageHash>,
+}
+
+#[pymethods]
+impl HashIndex {
+    #[new]
+    #[pyo3(signature = (algorithm = None, hash_size = None))]
+    fn new(algorithm: Option<&str>, hash_size: Option<usize>) -> PyResult<Self> {
+        let index = IndexFile {
+            algorithm: algorithm.map(parse_algorithm).transpose()?,
+            hash_size,
+            ..IndexFile::default()
+        };
+        Ok(Self {
+            index,
+            hashes: HashMap::new(),
+        })
+    }
+
+    /// Version of the shared hash list applied by `sync` (0 if never synced).
+    #[getter]
+    fn version(&self) -> u64 {
+        self.index.version
+    }
+
+    /// Algorithm the index was created or saved with, if known.
+    #[getter]
+    fn algorithm(&self) -> Option<String> {
+        self.index.algorithm.map(|a| a.to_string())
+    }
+
+    /// Hash size the index was created or saved with, if known.
+    #[getter]
+    fn hash_size(&self) -> Option<usize> {
+        self.index.hash_size
+    }
+
+    /// Pull signed deltas of a shared hash list and apply them atomically.
+    ///
+    /// Requires the `sync` feature. The first sync binds the index to the
+    /// list named in the manifest; later syncs refuse any other list. If
+    /// any delta fails to download or verify, the index is left unchanged.
+    ///
+    /// Args:
+    ///     base_url: HTTPS URL of the list (manifest at <base_url>/manifest)
+    ///     public_key: Publisher's ed25519 public key as hex
+    ///
+    /// Returns:
+    ///     The list version the index is now at
+    fn sync(&mut self, py: Python<'_>, base_url: &str, public_key: &str) -> PyResult<u64> {
+        let key =
+            sync::parse_public_key(public_key).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let index = &self.index;
+        let (list, deltas) = py
+            .allow_threads(|| pull_deltas(base_url, index.list.as_deref(), index.version, &key))
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+        let (tree, version) = py
+            .allow_threads(|| apply_deltas(&index.tree, &list, index.version, &deltas))
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+
+        self.hashes = tree
+            .iter()
+            .map(|(hash, path)| (path.clone(), hash.clone()))
+            .collect();
+        self.index.tree = tree;
+        self.index.list = Some(list);
+        self.index.version = version;
+        Ok(version)
+    }
+
+    /// Add a path and its hash to the index.
+    ///
+    /// The file's size and modification time are recorded when the path
+    /// names a file, so stale() can tell which entries need rehashing.
+    ///
+    /// Args:
+    ///     path: Path (or any identifier) to return from queries
+    ///     hash: Hash as hex string, or tagged as by ImageHash.to_tagged
+    ///     dimensions: (width, height) of the image, kept with the entry
+    ///                 (optional)
+    #[pyo3(signature = (path, hash, dimensions = None))]
+    fn add(&mut self, path: PathBuf, hash: &str, dimensions: Option<(u32, u32)>) -> PyResult<()> {
+        let hash = parse_hash_text(hash)?;
+
+        let file = IndexedFile {
+            dimensions,
+            ..IndexedFile::of(&path)
+        };
+        if file != IndexedFile::default() {
+            self.index.files.insert(path.clone(), file);
+        } else {
+            self.index.files.remove(&path);
+        }
+        self.hashes.insert(path.clone(), hash.clone());
+        self.index.tree.add(hash, path);
+        Ok(())
+    }
+
+    /// Find all indexed paths within a Hamming distance of a hash.
+    ///
+    /// Args:
+    ///     hash: Hash as hex string, or tagged as by ImageHash.to_tagged
+    ///     threshold: Maximum Hamming distance (default: 10)
+    ///
+    /// Returns:
+    ///     List of (path, distance) tuples in insertion order
+    #[pyo3(signature = (hash, threshold = 10))]
+    fn query(&self, py: Python<'_>, hash: &str, threshold: u32) -> PyResult<Vec<(PathBuf, u32)>> {
+        let hash = parse_hash_text(hash)?;
+
+        Ok(py.allow_threads(|| {
+            self.index
+                .tree
+                .query(&hash, threshold)
+                .into_iter()
+                .map(|(path, dist)| (path.clone(), dist))
+                .collect()
+        }))
+    }
+
+    /// Write the index to a binary file, replacing it atomically.
+    ///
+    /// The file holds the tree as built together with the algorithm, hash
+    /// size, sync state and file metadata, so load() restores it in a
+    /// fraction of the time re-adding the hashes takes.
+    ///
+    /// Args:
+    ///     path: File to write
+    fn save(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        py.allow_threads(|| self.index.save(&path))
+            .map_err(pyo3::exceptions::PyIOError::new_err)
+    }
+
+    /// Load an index written by save().
+    ///
+    /// Args:
+    ///     path: File to read
+    ///
+    /// Returns:
+    ///     The HashIndex. Raises IOError if the file is missing, truncated
+    ///     or not an index file.
+    #[staticmethod]
+    fn load(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        let index = py
+            .allow_threads(|| IndexFile::load(&path))
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+        let hashes = index
+            .tree
+            .iter()
+            .map(|(hash, path)| (path.clone(), hash.clone()))
+            .collect();
+        Ok(Self { index, hashes })
+    }
+
+    /// Indexed files that changed or disappeared since they were added.
+    ///
+    /// Returns:
+    ///     Sorted list of paths to rehash; entries added without a file
+    ///     behind them are never listed
+    fn stale(&self, py: Python<'_>) -> Vec<PathBuf> {
+        py.allow_threads(|| self.index.stale_paths())
+    }
+
+    fn __len__(&self) -> usize {
+        self.index.tree.len()
+    }
+}
+
+/// Known-image filter: banned image hashes to check uploads against.
+///
+/// Built for moderation pipelines, where a missed match lets a banned
+/// image through: lookups are exact, images are also tried in every
+/// rotation and mirror image by default, and a hash of another algorithm
+/// or size raises ValueError instead of silently not matching.
+///
+/// Args:
+///     hashes: Banned hashes as hex or tagged strings, either a list (each
+///             hash is its own id) or a dict of {id: hash} (optional)
+///     algorithm: Algorithm the hashes are computed with; images checked
+///                against the list are hashed with it (default: "phash")
+///     hash_size: Hash size they are computed with (default: 8)
+///
+/// Example:
+///     blocklist = phash_rs.Blocklist.load("banned.txt")
+///     hit = blocklist.check("upload.jpg", threshold=8)
+///     if hit is not None:
+///         reject(hit["id"])
+#[pyclass(name = "Blocklist")]
+struct PyBlocklist {
+    blocklist: Blocklist,
+}
+
+#[pymethods]
+impl PyBlocklist {
+    #[new]
+    #[pyo3(signature = (hashes = None, algorithm = "phash", hash_size = 8))]
+    fn new(hashes: Option<&Bound<'_, PyAny>>, algorithm: &str, hash_size: usize) -> PyResult<Self> {
+        let mut blocklist =
+            Blocklist::new(parse_algorithm(algorithm)?, hash_size).map_err(hash_err)?;
+        if let Some(hashes) = hashes {
+            let entries: Vec<(String, String)> = match hashes.extract::<BTreeMap<String, String>>()
+            {
+                Ok(by_id) => by_id.into_iter().collect(),
+                Err(_) => hashes
+                    .extract::<Vec<String>>()?
+                    .into_iter()
+                    .map(|hash| (hash.clone(), hash))
+                    .collect(),
+            };
+            for (id, hash) in entries {
+                blocklist
+                    .add(id, parse_hash_text(&hash)?)
+                    .map_err(hash_err)?;
+            }
+        }
+        Ok(Self { blocklist })
+    }
+
+    /// Read a blocklist from a text file.
+    ///
+    /// One entry per line: a hash (hex or tagged), optionally followed by
+    /// whitespace and an id. Blank lines and lines starting with # are
+    /// skipped.
+    ///
+    /// Args:
+    ///     path: File to read
+    ///     algorithm, hash_size: As for Blocklist()
+    ///
+    /// Returns:
+    ///     The Blocklist. Raises IOError naming the first bad line.
+    #[staticmethod]
+    #[pyo3(signature = (path, algorithm = "phash", hash_size = 8))]
+    fn load(py: Python<'_>, path: PathBuf, algorithm: &str, hash_size: usize) -> PyResult<Self> {
+        let algorithm = parse_algorithm(algorithm)?;
+        let blocklist = py
+            .allow_threads(|| Blocklist::load(&path, algorithm, hash_size))
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+        Ok(Self { blocklist })
+    }
+
+    /// A blocklist of the hashes in a HashIndex, with their paths as ids.
+    ///
+    /// Uses the algorithm and hash size the index records, else phash
+    /// at size 8.
+    #[staticmethod]
+    fn from_index(index: PyRef<'_, HashIndex>) -> PyResult<Self> {
+        let mut blocklist = Blocklist::new(
+            index.index.algorithm.unwrap_or(HashAlgorithm::PHash),
+            index.index.hash_size.unwrap_or(8),
+        )
+        .map_err(hash_err)?;
+        for (hash, path) in index.index.tree.iter() {
+            blocklist
+                .add(path.to_string_lossy(), hash.clone())
+                .map_err(hash_err)?;
+        }
+        Ok(Self { blocklist })
+    }
+
+    /// Add a banned hash.
+    ///
+    /// Args:
+    ///     hash: Hash as hex string, tagged string or ImageHash
+    ///     id: Id reported when it matches (default: the hash as hex)
+    #[pyo3(signature = (hash, id = None))]
+    fn add(&mut self, hash: &Bound<'_, PyAny>, id: Option<String>) -> PyResult<()> {
+        let hash = match hash.downcast::<PyImageHash>() {
+            Ok(hash) => hash.borrow().hash.clone(),
+            Err(_) => parse_hash_text(&hash.extract::<String>()?)?,
+        };
+        let id = id.unwrap_or_else(|| hash.to_hex());
+        self.blocklist.add(id, hash).map_err(hash_err)
+    }
+
+    /// Find the banned entry closest to an image or hash.
+    ///
+    /// Args:
+    ///     path_or_hash: Image path (str or os.PathLike), ImageHash, or
+    ///                   hash string; a str naming an existing file is
+    ///                   taken as a path, any other str as a hash
+    ///     threshold: Maximum Hamming distance to count as a match
+    ///                (default: 10)
+    ///     check_rotations: Also try the image in all rotations and mirror
+    ///                      images, at about eight times the hashing cost;
+    ///                      has no effect on hashes (default: True)
+    ///
+    /// Returns:
+    ///     None if nothing is within threshold, else a dict with the
+    ///     entry's "id" and "hash", the "distance" and the "transform" of
+    ///     the image that matched ("none", "rotate90", ..., "mirror",
+    ///     "mirror_rotate90", ...). Raises IOError if the image cannot be
+    ///     decoded, and ValueError if a str is neither a file nor a hash or
+    ///     the hash cannot be compared with the blocklist.
+    #[pyo3(signature = (path_or_hash, threshold = 10, check_rotations = true))]
+    fn check(
+        &self,
+        py: Python<'_>,
+        path_or_hash: &Bound<'_, PyAny>,
+        threshold: u32,
+        check_rotations: bool,
+    ) -> PyResult<Option<PyObject>> {
+        let blocklist = &self.blocklist;
+        let found = if let Ok(hash) = path_or_hash.downcast::<PyImageHash>() {
+            blocklist.check_hash(&hash.borrow().hash, threshold)
+        } else {
+            let text = path_or_hash.extract::<String>().ok();
+            match text.filter(|text| !Path::new(text).is_file()) {
+                Some(text) => {
+                    let hash = parse_hash_text(&text).map_err(|_| {
+                        pyo3::exceptions::PyValueError::new_err(format!(
+                            "{} is neither an existing file nor a hash",
+                            text
+                        ))
+                    })?;
+                    blocklist.check_hash(&hash, threshold)
+                }
+                None => {
+                    let path: PathBuf = path_or_hash.extract()?;
+                    py.allow_threads(|| blocklist.check_path(&path, threshold, check_rotations))
+                }
+            }
+        }
+        .map_err(hash_err)?;
+
+        Ok(found.map(|found| {
+            let mut result: HashMap<&str, PyObject> = HashMap::new();
+            result.insert("id", found.id.to_object(py));
+            result.insert("hash", found.hash.to_hex().to_object(py));
+            result.insert("distance", found.distance.to_object(py));
+            result.insert("transform", found.transform.to_object(py));
+            result.to_object(py)
+        }))
+    }
+
+    /// Algorithm the entries are computed with.
+    #[getter]
+    fn algorithm(&self) -> String {
+        self.blocklist.algorithm().to_string()
+    }
+
+    /// Hash size the entries are computed with.
+    #[getter]
+    fn hash_size(&self) -> usize {
+        self.blocklist.hash_size()
+    }
+
+    fn __len__(&self) -> usize {
+        self.blocklist.len()
+    }
+}
+
+/// Watch a directory and report new images that match existing ones.
+///
+/// Indexes the images under `directory` on creation, then hashes each
+/// file created, written or moved into it once it has been quiet for half
+/// a second. The callback runs on a background thread whenever a new file
+/// matches indexed images; exceptions it raises are reported as
+/// unraisable and watching continues. Needs the `watch` feature.
+///
+/// Args:
+///     directory: Directory to watch
+///     callback: Called as callback(path, [(match_path, distance), ...]),
+///               matches closest first
+///     threshold: Maximum Hamming distance to report (default: 10)
+///     algorithm: "phash" (default), "whash", "whash:<level>", "colorhash"
+///                or "blockhash"
+///     hash_size: Size of the hashes (default: 8, 64-bit hashes)
+///     recursive, extensions, exclude: Which files to index and watch, as
+///         for scan_directory
+///
+/// Example:
+///     with phash_rs.DuplicateWatcher("/uploads", print) as watcher:
+///         serve_forever()
+#[pyclass(name = "DuplicateWatcher")]
+struct PyDuplicateWatcher {
+    watcher: DuplicateWatcher,
+}
+
+#[pymethods]
+impl PyDuplicateWatcher {
+    #[new]
+    #[pyo3(signature = (
+        directory,
+        callback,
+        threshold = 10,
+        algorithm = "phash",
+        hash_size = 8,
+        recursive = true,
+        extensions = None,
+        exclude = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        directory: PathBuf,
+        callback: PyObject,
+        threshold: u32,
+        algorithm: &str,
+        hash_size: usize,
+        recursive: bool,
+        extensions: Option<Vec<String>>,
+        exclude: Option<Vec<PathBuf>>,
+    ) -> PyResult<Self> {
+        let algorithm = parse_algorithm(algorithm)?;
+        let options = ScanOptions {
+            recursive,
+            extensions,
+            exclude: exclude.unwrap_or_default(),
+            ..ScanOptions::default()
+        };
+        let on_match = move |path: &Path, matches: &[SimilarMatch]| {
+            let matches: Vec<(&Path, u32)> = matches
+                .iter()
+                .map(|m| (m.path.as_path(), m.distance))
+                .collect();
+            Python::with_gil(|py| {
+                if let Err(e) = callback.call1(py, (path, matches)) {
+                    e.write_unraisable_bound(py, None);
+                }
+            });
+        };
+        let watcher = py
+            .allow_threads(|| {
+                DuplicateWatcher::start(
+                    directory, &options, algorithm, hash_size, threshold, on_match,
+                )
+            })
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+        Ok(Self { watcher })
+    }
+
+    /// Stop watching; waits for a callback in progress to return.
+    fn stop(&mut self, py: Python<'_>) {
+        py.allow_threads(|| self.watcher.stop());
+    }
+
+    /// Whether the directory is still being watched.
+    #[getter]
+    fn running(&self) -> bool {
+        self.watcher.is_running()
+    }
+
+    /// Files that failed to hash, as for compute_hashes_parallel.
+    #[getter]
+    fn errors(&self) -> BTreeMap<PathBuf, HashMap<&'static str, String>> {
+        errors_to_dict(self.watcher.failures(), None)
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (*_args))]
+    fn __exit__(&mut self, py: Python<'_>, _args: &Bound<'_, pyo3::types::PyTuple>) {
+        self.stop(py);
+    }
+}
+
+/// Discover images under a directory, optionally hashing or grouping them.
+///
+/// Args:
+///     path: Directory to scan
+///     recursive: Descend into subdirectories (default: True)
+///     extensions: Extensions to include, e.g. ["jpg", ".png"]; None includes
+///                 every format a decoder backend can open
+///     follow_symlinks: Follow symbolic links (default: False)
+///     exclude: Files or directories to skip, e.g. a quarantine folder;
+///              relative entries are relative to `path`
+///     output: "paths" (default), "hashes" or "duplicates"
+///     threshold: Maximum Hamming distance for "duplicates" (default: 10)
+///     algorithm: Hash algorithm for "hashes" and "duplicates"
+///
+/// Returns:
+///     "paths": sorted list of image paths
+///     "hashes": same as compute_hashes_parallel on those paths
+///     "duplicates": same as find_duplicate_images on those paths
+#[pyfunction(name = "scan_directory")]
+#[pyo3(signature = (
+    path,
+    recursive = true,
+    extensions = None,
+    follow_symlinks = false,
+    exclude = None,
+    output = "paths",
+    threshold = 10,
+    algorithm = "phash"
+))]
+#[allow(clippy::too_many_arguments)]
+fn py_scan_directory(
+    py: Python<'_>,
+    path: PathBuf,
+    recursive: bool,
+    extensions: Option<Vec<String>>,
+    follow_symlinks: bool,
+    exclude: Option<Vec<PathBuf>>,
+    output: &str,
+    threshold: u32,
+    algorithm: &str,
+) -> PyResult<PyObject> {
+    let options = ScanOptions {
+        recursive,
+        extensions,
+        follow_symlinks,
+        exclude: exclude.unwrap_or_default(),
+    };
+    let warnings = WarningCollector::new();
+    let paths = py
+        .allow_threads(|| scan_directory_with(path, &options, Some(&warnings)))
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    emit_warnings(py, warnings, None, false)?;
+
+    match output {
+        "paths" => Ok(paths.to_object(py)),
+        "hashes" => Ok(compute_hashes_parallel(
+            py,
+            paths,
+            algorithm,
+            None,
+            None,
+            None,
+            None,
+            false,
+            DEFAULT_HASH_SIZE,
+            false,
+            None,
+            false,
+            None,
+            None,
+            DCT_SIZE,
+            "lanczos3",
+            None,
+            false,
+            false,
+            None,
+        )?),
+        "duplicates" => Ok(find_duplicate_images(
+            py,
+            paths.to_object(py).bind(py),
+            threshold,
+            algorithm,
+            None,
+            None,
+            None,
+            None,
+            None,
+            false,
+            false,
+            false,
+            None,
+            false,
+            None,
+            false,
+            DEFAULT_HASH_SIZE,
+            false,
+            None,
+            false,
+            None,
+            None,
+            DCT_SIZE,
+            "lanczos3",
+            false,
+            DEFAULT_MIN_SIMILARITY,
+            None,
+            "connected",
+            None,
+            None,
+            false,
+            false,
+            false,
+            None,
+        )?),
+        _ => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "Unknown output: {} (expected \"paths\", \"hashes\" or \"duplicates\")",
+            output
+        ))),
+    }
+}
+
+/// Scan, group, verify, plan and optionally remove duplicates in one call.
+///
+/// Each duplicate is verified against the file that is kept (not only
+/// through other group members) and then handed to apply_dedup, which
+/// leaves it alone if it changed since. Without apply=True nothing is
+/// moved or deleted, so the plan can be reviewed first.
+///
+/// Args:
+///     root: Directory to clean up
+///     profile: "identical" (byte-identical copies only), "conservative"
+///              (pHash <= 4) or "balanced" (pHash <= 10, default)
+///     apply: Carry out the plan (default: False)
+///     threshold: Override the profile's threshold (optional)
+///     algorithm: Override the profile's algorithm (optional)
+///     best_by: Which file to keep, as for find_duplicate_images (optional;
+///              default "resolution", callables not supported)
+///     exact_prepass: Hash one file per set of byte-identical copies, as
+///                    for find_duplicate_images (default: False)
+///     action: "quarantine" (default) moves duplicates into a new
+///             timestamped folder in quarantine_dir, keeping their path
+///             below root, and logs them for restore; "trash", "delete",
+///             "hardlink" and "symlink" work as for apply_dedup
+///     quarantine_dir: Folder for quarantine and trash runs, relative to
+///                     root unless absolute (default: "Duplicates"); never
+///                     scanned
+///     cache_path: Hash cache file, loaded first and saved after hashing
+///                 (optional)
+///     progress_callback: Called as for find_duplicate_images (optional)
+///     hash_size: Hash size, overriding the profile's (optional)
+///     num_threads: Threads to hash with (default: the set_num_threads
+///                  limit, else one per core)
+///
+/// Ctrl+C during the scan raises KeyboardInterrupt before anything is
+/// moved or deleted; once actions have started they run to completion.
+///
+/// Returns:
+///     Dictionary with:
+///     - "scanned": Number of image files found
+///     - "groups": Duplicate groups as from find_duplicate_images
+///     - "plan": List of {"path", "keep", "action", "target", "sidecars",
+///       "bytes"} for each verified duplicate, as apply_dedup's "actions"
+///     - "unverified": {path: reason} for group members left in place,
+///       including those apply_dedup skipped
+///     - "applied": Whether the plan was carried out
+///     - "apply_errors": {path: reason} for planned actions that failed
+///     - "manifest": Manifest of the quarantine or trash run, for restore;
+///       None in dry runs and when nothing was taken away
+///     - "bytes_recoverable": Bytes the plan frees, sidecars included
+///     - "errors": Files that could not be hashed, as for
+///       compute_hashes_parallel
+///
+/// Example:
+///     report = phash_rs.dedupe("/photos")
+///     print(len(report["plan"]), "duplicates,", report["bytes_recoverable"], "bytes")
+///     phash_rs.dedupe("/photos", apply=True)
+#[pyfunction(name = "dedupe")]
+#[pyo3(signature = (
+    root,
+    profile = "balanced",
+    apply = false,
+    threshold = None,
+    algorithm = None,
+    best_by = None,
+    exact_prepass = false,
+    action = None,
+    quarantine_dir = None,
+    cache_path = None,
+    progress_callback = None,
+    hash_size = None,
+    num_threads = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn py_dedupe(
+    py: Python<'_>,
+    root: PathBuf,
+    profile: &str,
+    apply: bool,
+    threshold: Option<u32>,
+    algorithm: Option<&str>,
+    best_by: Option<&str>,
+    exact_prepass: bool,
+    action: Option<&str>,
+    quarantine_dir: Option<PathBuf>,
+    cache_path: Option<PathBuf>,
+    progress_callback: Option<PyObject>,
+    hash_size: Option<usize>,
+    num_threads: Option<usize>,
+) -> PyResult<HashMap<String, PyObject>> {
+    let value_err = pyo3::exceptions::PyValueError::new_err;
+    let mut profile = DedupeProfile::named(profile).map_err(value_err)?;
+    if let Some(threshold) = threshold {
+        profile.threshold = threshold;
+    }
+    if let Some(algorithm) = algorithm {
+        profile.algorithm = parse_algorithm(algorithm)?;
+    }
+    if let Some(hash_size) = hash_size {
+        profile.hash_size = hash_size;
+    }
+    profile
+        .algorithm
+        .check_hash_size(profile.hash_size)
+        .map_err(hash_err)?;
+    if let Some(best_by) = best_by {
+        profile.best_by = best_by.parse().map_err(value_err)?;
+    }
+    if let Some(action) = action {
+        profile.action = action.parse().map_err(value_err)?;
+    }
+    if let Some(dir) = quarantine_dir {
+        profile.quarantine_dir = dir;
+    }
+    profile.exact_prepass = exact_prepass;
+    profile.cache_path = cache_path;
+
+    let callback = progress_callback.map(CallbackProgress::new);
+    let cancel = CancelToken::new();
+    let mut sinks: Vec<&dyn Progress> = vec![&cancel];
+    sinks.extend(callback.as_ref().map(|c| c as &dyn Progress));
+    let progress = Fanout(sinks);
+    let (report, interrupt) = run_cancellable(py, num_threads, &cancel, || {
+        dedupe(&root, &profile, apply, Some(&progress))
+    })?;
+    // Once actions have started they run to completion, and their report
+    // matters more than the interrupt
+    if let (Some(interrupt), Err(_)) = (interrupt, &report) {
+        return Err(interrupt);
+    }
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            return Err(match callback.and_then(|c| c.take_error()) {
+                Some(raised) => raised,
+                None => pyo3::exceptions::PyRuntimeError::new_err(e),
+            })
+        }
+    };
+
+    let groups: Vec<PyDuplicateGroup> = report.groups.iter().map(PyDuplicateGroup::new).collect();
+    let unverified: BTreeMap<&PathBuf, &String> = report
+        .unverified
+        .iter()
+        .chain(&report.actions.skipped)
+        .map(|(p, r)| (p, r))
+        .collect();
+    let apply_errors: BTreeMap<&PathBuf, &String> =
+        report.actions.errors.iter().map(|(p, r)| (p, r)).collect();
+
+    let mut map = HashMap::new();
+    map.insert("scanned".to_string(), report.scanned.to_object(py));
+    map.insert("groups".to_string(), groups.into_py(py));
+    map.insert(
+        "plan".to_string(),
+        action_records(py, &report.actions.actions).to_object(py),
+    );
+    map.insert("unverified".to_string(), unverified.to_object(py));
+    map.insert("applied".to_string(), apply.to_object(py));
+    map.insert("apply_errors".to_string(), apply_errors.to_object(py));
+    map.insert(
+        "manifest".to_string(),
+        report.actions.manifest.to_object(py),
+    );
+    map.insert(
+        "bytes_recoverable".to_string(),
+        report.bytes_recoverable().to_object(py),
+    );
+    map.insert(
+        "errors".to_string(),
+        errors_to_dict(report.failures, None).to_object(py),
+    );
+    Ok(map)
+}
+
+/// Delete, link, move or quarantine the copies in duplicate groups, keeping
+/// one file each.
+///
+/// Before a copy is touched, it and the kept file are checked against the
+/// scan: files modified after scanned_at, or whose hash no longer matches
+/// the group's "hashes" entry, are skipped. Copies that can be checked
+/// neither way are skipped too. Nothing changes without dry_run=False.
+///
+/// Args:
+///     groups: Groups as returned by find_duplicate_images or
+///             import_results
+///     action: "delete" (default; sidecars too), "hardlink" or "symlink"
+///             (replace the copy with a link to the kept file; sidecars
+///             stay), "move" (into move_to; sidecars too), "quarantine"
+///             (into a timestamped folder in quarantine_dir; sidecars
+///             too) or "trash" (to the OS trash, needs the "trash"
+///             feature; sidecars too). Quarantined and trashed files can
+///             be put back with restore().
+///     keep: "best" (default, the group's "best") or a best_by strategy
+///           or callable, as for find_duplicate_images
+///     dry_run: Only report what would be done (default: True)
+///     move_to: Destination directory for action="move"
+///     quarantine_dir: Folder for the run folders of action="quarantine"
+///                     and "trash", each with a manifest for restore()
+///     relative_to: Root the group paths are relative to, as passed to
+///                  find_duplicate_images (optional). Moved files keep
+///                  their path below it, otherwise only their name.
+///     scanned_at: Unix time of the scan, e.g. import_results' "created"
+///                 (optional)
+///     algorithm: Algorithm the group hashes were computed with, for
+///                hashes not tagged with theirs (default: "phash")
+///     trim_borders, center_crop, normalize_gamma, mask_regions,
+///     alpha_background, dct_size, filter, apply_exif_orientation:
+///         Preprocessing the group hashes were computed with, as passed to
+///         find_duplicate_images
+///
+/// Returns:
+///     Dictionary with:
+///     - "dry_run": Whether nothing was changed
+///     - "actions": List of {"path", "keep", "action", "target",
+///       "sidecars", "bytes"} for each copy acted on (or that would be)
+///     - "skipped": {path: reason} for copies left alone
+///     - "errors": {path: error} for actions that failed
+///     - "bytes_freed": Bytes freed (or that would be)
+///     - "manifest": Manifest path of a quarantine or trash run, or None
+#[pyfunction(name = "apply_dedup")]
+#[pyo3(signature = (
+    groups,
+    action = "delete",
+    keep = None,
+    dry_run = true,
+    move_to = None,
+    quarantine_dir = None,
+    relative_to = None,
+    scanned_at = None,
+    algorithm = "phash",
+    trim_borders = false,
+    center_crop = None,
+    normalize_gamma = false,
+    mask_regions = None,
+    alpha_background = None,
+    dct_size = DCT_SIZE,
+    filter = "lanczos3",
+    apply_exif_orientation = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn py_apply_dedup(
+    py: Python<'_>,
+    groups: Vec<Bound<'_, PyAny>>,
+    action: &str,
+    keep: Option<PyObject>,
+    dry_run: bool,
+    move_to: Option<PathBuf>,
+    quarantine_dir: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    scanned_at: Option<f64>,
+    algorithm: &str,
+    trim_borders: bool,
+    center_crop: Option<f32>,
+    normalize_gamma: bool,
+    mask_regions: Option<Vec<(u32, u32, u32, u32)>>,
+    alpha_background: Option<(u8, u8, u8)>,
+    dct_size: usize,
+    filter: &str,
+    apply_exif_orientation: Option<bool>,
+) -> PyResult<HashMap<String, PyObject>> {
+    let value_err = pyo3::exceptions::PyValueError::new_err;
+    let hash_options = HashOptions {
+        apply_exif_orientation,
+        ..hash_options(
+            trim_borders,
+            center_crop,
+            normalize_gamma,
+            mask_regions,
+            alpha_background,
+            dct_size,
+            filter,
+        )?
+    };
+    let mut groups = groups
+        .iter()
+        .map(|g| group_from_py(py, g))
+        .collect::<PyResult<Vec<_>>>()?;
+    let keep = match keep {
+        Some(keep) if keep.extract::<String>(py).is_ok_and(|k| k == "best") => None,
+        Some(keep) => Some(parse_best_by(py, keep)?),
+        None => None,
+    };
+    // Callables are evaluated here, with the GIL
+    if let Some(BestChoice::Callback(score)) = &keep {
+        for g in &mut groups {
+            g.best_path = best_by_callback(py, score, g)?;
+        }
+    }
+    let scanned_at = scanned_at
+        .map(|secs| {
+            std::time::Duration::try_from_secs_f64(secs)
+                .map(|d| std::time::UNIX_EPOCH + d)
+                .map_err(|e| value_err(format!("Invalid scanned_at: {}", e)))
+        })
+        .transpose()?;
+    let options = DedupOptions {
+        action: action.parse().map_err(value_err)?,
+        keep: match keep {
+            Some(BestChoice::Strategy(by)) => Some(by),
+            _ => None,
+        },
+        dry_run,
+        move_to,
+        quarantine_dir,
+        relative_to,
+        scanned_at,
+        algorithm: parse_algorithm(algorithm)?,
+        hash_options,
+    };
+    let report = py
+        .allow_threads(|| apply_dedup(&groups, &options))
+        .map_err(value_err)?;
+
+    let actions = action_records(py, &report.actions);
+    let skipped: BTreeMap<&PathBuf, &String> = report.skipped.iter().map(|(p, r)| (p, r)).collect();
+    let errors: BTreeMap<&PathBuf, &String> = report.errors.iter().map(|(p, r)| (p, r)).collect();
+
+    let mut map = HashMap::new();
+    map.insert("dry_run".to_string(), report.dry_run.to_object(py));
+    map.insert("actions".to_string(), actions.to_object(py));
+    map.insert("skipped".to_string(), skipped.to_object(py));
+    map.insert("errors".to_string(), errors.to_object(py));
+    map.insert(
+        "bytes_freed".to_string(),
+        report.bytes_freed().to_object(py),
+    );
+    map.insert("manifest".to_string(), report.manifest.to_object(py));
+    Ok(map)
+}
+
+/// ActionRecord dictionaries, as apply_dedup and dedupe return them
+fn action_records(
+    py: Python<'_>,
+    records: &[ActionRecord],
+) -> Vec<HashMap<&'static str, PyObject>> {
+    records
+        .iter()
+        .map(|a| {
+            let mut map = HashMap::new();
+            map.insert("path", a.path.to_object(py));
+            map.insert("keep", a.keep.to_object(py));
+            map.insert("action", a.action.to_string().to_object(py));
+            map.insert("target", a.target.to_object(py));
+            map.insert("sidecars", a.sidecars.to_object(py));
+            map.insert("bytes", a.bytes.to_object(py));
+            map
+        })
+        .collect()
+}
+
+/// Put back the files of a quarantine or trash run of apply_dedup.
+///
+/// Files whose original path is taken again are left where they are, so
+/// restoring twice is harmless. Restoring from the OS trash works on
+/// Windows and freedesktop.org desktops (Linux, BSD).
+///
+/// Args:
+///     manifest: The run's manifest, as returned by apply_dedup, or its
+///               folder
+///
+/// Returns:
+///     Dictionary with:
+///     - "restored": List of original paths put back
+///     - "skipped": {path: reason} for entries left alone
+///     - "errors": {path: error} for entries that failed
+#[pyfunction(name = "restore")]
+fn py_restore(py: Python<'_>, manifest: PathBuf) -> PyResult<HashMap<String, PyObject>> {
+    let report = py
+        .allow_threads(|| restore(&manifest))
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let skipped: BTreeMap<&PathBuf, &String> = report.skipped.iter().map(|(p, r)| (p, r)).collect();
+    let errors: BTreeMap<&PathBuf, &String> = report.errors.iter().map(|(p, r)| (p, r)).collect();
+
+    let mut map = HashMap::new();
+    map.insert("restored".to_string(), report.restored.to_object(py));
+    map.insert("skipped".to_string(), skipped.to_object(py));
+    map.insert("errors".to_string(), errors.to_object(py));
+    Ok(map)
+}
+
+/// Predict the cost of a scan without decoding anything.
+///
+/// Reads file metadata only and applies per-format throughput figures for
+/// the decoder backend each format would use.
+///
+/// Args:
+///     root_or_paths: Directory to scan (as scan_directory with defaults)
+///                    or a list of image paths
+///     algorithm: Algorithm the scan will use (default: "phash")
+///     threads: Worker threads (default: rayon's pool size)
+///     cache_path: Hash cache the scan will use (optional); files with a
+///                 valid cached hash are not counted as work
+///     calibrate: Hash up to this many files per format first and use the
+///                measured throughput instead of built-in figures
+///                (default: 0, no calibration)
+///     hash_size: Hash size the scan will use (default: 8); only cached
+///                hashes of this size count as done
+///
+/// Returns:
+///     Dictionary with "files", "total_bytes", "cached_files",
+///     "unsupported_files", "unreadable_files", "decode_bytes",
+///     "seconds", "peak_memory_bytes" and "formats", which maps each
+///     extension to {"files", "bytes", "seconds"} (single-thread seconds)
+#[pyfunction]
+#[pyo3(signature = (root_or_paths, algorithm = "phash", threads = None, cache_path = None, calibrate = 0, hash_size = 8))]
+#[allow(clippy::too_many_arguments)]
+fn estimate_scan(
+    py: Python<'_>,
+    root_or_paths: &Bound<'_, PyAny>,
+    algorithm: &str,
+    threads: Option<usize>,
+    cache_path: Option<PathBuf>,
+    calibrate: usize,
+    hash_size: usize,
+) -> PyResult<HashMap<&'static str, PyObject>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    algorithm.check_hash_size(hash_size).map_err(hash_err)?;
+    let cache = cache_path
+        .map(HashCache::load)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let paths = match root_or_paths.extract::<PathBuf>() {
+        Ok(root) => py
+            .allow_threads(|| scan_directory(&root, &ScanOptions::default()))
+            .map_err(pyo3::exceptions::PyIOError::new_err)?,
+        Err(_) => root_or_paths.extract::<Vec<PathBuf>>()?,
+    };
+
+    let threads = threads.unwrap_or_else(rayon::current_num_threads);
+    let estimate = py.allow_threads(|| {
+        let throughput = if calibrate > 0 {
+            Throughput::calibrate(&paths, calibrate, algorithm)
+        } else {
+            Throughput::new()
+        };
+        estimate_paths(
+            &paths,
+            algorithm,
+            hash_size,
+            threads,
+            &throughput,
+            cache.as_ref(),
+        )
+    });
+
+    let formats: BTreeMap<&str, HashMap<&str, PyObject>> = estimate
+        .formats
+        .iter()
+        .map(|(ext, f)| {
+            let mut format = HashMap::new();
+            format.insert("files", f.files.to_object(py));
+            format.insert("bytes", f.bytes.to_object(py));
+            format.insert("seconds", f.seconds.to_object(py));
+            (ext.as_str(), format)
+        })
+        .collect();
+
+    let mut result = HashMap::new();
+    result.insert("files", estimate.files.to_object(py));
+    result.insert("total_bytes", estimate.total_bytes.to_object(py));
+    result.insert("cached_files", estimate.cached_files.to_object(py));
+    result.insert(
+        "unsupported_files",
+        estimate.unsupported_files.to_object(py),
+    );
+    result.insert("unreadable_files", estimate.unreadable_files.to_object(py));
+    result.insert("decode_bytes", estimate.decode_bytes.to_object(py));
+    result.insert("seconds", estimate.seconds.to_object(py));
+    result.insert(
+        "peak_memory_bytes",
+        estimate.peak_memory_bytes.to_object(py),
+    );
+    result.insert("formats", formats.to_object(py));
+    Ok(result)
+}
+
+/// Pick a duplicate threshold from labelled example pairs.
+///
+/// Every image is hashed once; each threshold from 0 to the hash's bit
+/// count is scored by precision and recall over the labelled pairs.
+///
+/// Args:
+///     duplicate_pairs: List of (path, path) pairs known to be copies
+///     distinct_pairs: List of (path, path) pairs known to be different
+///                     pictures
+///     algorithm: Algorithm to calibrate (default: "phash")
+///     hash_size: Size of the hash (default: 8)
+///     cache_path: Hash cache file (optional), as for
+///                 compute_hashes_parallel
+///
+/// Returns:
+///     Dictionary with "threshold" (best F1, the smallest on ties), its
+///     "precision", "recall" and "f1", "curve" (one such dictionary per
+///     threshold), "duplicate_distances", "distinct_distances" and
+///     "errors" for images that could not be hashed, whose pairs are left
+///     out. Raises ValueError if either list has no usable pair.
+#[pyfunction(name = "calibrate_threshold")]
+#[pyo3(signature = (duplicate_pairs, distinct_pairs, algorithm = "phash", hash_size = 8, cache_path = None))]
+fn py_calibrate_threshold(
+    py: Python<'_>,
+    duplicate_pairs: Vec<(PathBuf, PathBuf)>,
+    distinct_pairs: Vec<(PathBuf, PathBuf)>,
+    algorithm: &str,
+    hash_size: usize,
+    cache_path: Option<PathBuf>,
+) -> PyResult<HashMap<&'static str, PyObject>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let mut cache = cache_path
+        .as_ref()
+        .map(HashCache::load)
+        .transpose()
+        .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    let calibration = py
+        .allow_threads(|| {
+            calibrate_threshold(
+                &duplicate_pairs,
+                &distinct_pairs,
+                algorithm,
+                hash_size,
+                cache.as_mut(),
+                None,
+            )
+        })
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    if let (Some(cache), Some(cache_path)) = (&cache, &cache_path) {
+        cache
+            .save(cache_path)
+            .map_err(pyo3::exceptions::PyIOError::new_err)?;
+    }
+
+    let point = |p: &CalibrationPoint| {
+        let mut point = HashMap::new();
+        point.insert("threshold", p.threshold.to_object(py));
+        point.insert("precision", p.precision.to_object(py));
+        point.insert("recall", p.recall.to_object(py));
+        point.insert("f1", p.f1.to_object(py));
+        point
+    };
+    let curve: Vec<_> = calibration.curve.iter().map(point).collect();
+    let mut result = point(&calibration.best);
+    result.insert("curve", curve.to_object(py));
+    result.insert(
+        "duplicate_distances",
+        calibration.duplicate_distances.to_object(py),
+    );
+    result.insert(
+        "distinct_distances",
+        calibration.distinct_distances.to_object(py),
+    );
+    result.insert(
+        "errors",
+        errors_to_dict(calibration.failures, None).to_object(py),
+    );
+    Ok(result)
+}
+
+/// Compute a crop-resistant hash (one hash per image segment).
+///
+/// Args:
+///     path: Path to the image file
+///     hash_size: Size of each segment hash (default: 8)
+///     algorithm: Segment hash algorithm, "phash" (default), "whash",
+///                "colorhash" or "blockhash"
+///
+/// Returns:
+///     Comma-separated hex strings, one per segment
+#[pyfunction]
+#[pyo3(signature = (path, hash_size = 8, algorithm = "phash"))]
+fn compute_crop_resistant_hash(
+    py: Python<'_>,
+    path: PathBuf,
+    hash_size: usize,
+    algorithm: &str,
+) -> PyResult<String> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let hash = py
+        .allow_threads(|| CropResistantHash::from_path(path, algorithm, hash_size))
+        .map_err(hash_err)?;
+
+    Ok(hash.to_hex())
+}
+
+/// Score how different two crop-resistant hashes are.
+///
+/// Args:
+///     hash1: First crop-resistant hash string
+///     hash2: Second crop-resistant hash string
+///
+/// Returns:
+///     0.0 when every segment of hash1 matches exactly, up to the number
+///     of segments in hash1 when none match. Raises ValueError if the
+///     segments differ in length.
+#[pyfunction]
+fn crop_resistant_distance(hash1: &str, hash2: &str) -> PyResult<f64> {
+    let h1 = CropResistantHash::from_hex(hash1).map_err(hash_err)?;
+    let h2 = CropResistantHash::from_hex(hash2).map_err(hash_err)?;
+
+    h1.checked_distance(&h2).map_err(hash_err)
+}
+
+/// Check whether two crop-resistant hashes share matching segments.
+///
+/// Args:
+///     hash1: First crop-resistant hash string
+///     hash2: Second crop-resistant hash string
+///     region_cutoff: Minimum number of matching segments (default: 1)
+///     bit_error_rate: Fraction of differing bits allowed per segment
+///                     (default: 0.25)
+///
+/// Returns:
+///     True if enough segments match, False otherwise
+#[pyfunction]
+#[pyo3(signature = (hash1, hash2, region_cutoff = 1, bit_error_rate = DEFAULT_BIT_ERROR_RATE))]
+fn crop_resistant_matches(
+    hash1: &str,
+    hash2: &str,
+    region_cutoff: usize,
+    bit_error_rate: f64,
+) -> PyResult<bool> {
+    let h1 = CropResistantHash::from_hex(hash1).map_err(hash_err)?;
+    let h2 = CropResistantHash::from_hex(hash2).map_err(hash_err)?;
+    h1.check_segments(&h2).map_err(hash_err)?;
+
+    Ok(h1.matches(&h2, region_cutoff, bit_error_rate))
+}
+
+fn tile_options(scales: Option<Vec<f64>>, overlap: f64) -> TileOptions {
+    TileOptions {
+        scales: scales.unwrap_or_else(|| DEFAULT_TILE_SCALES.to_vec()),
+        overlap,
+    }
+}
+
+fn region_tuple(region: &Region) -> (u32, u32, u32, u32) {
+    (region.x, region.y, region.width, region.height)
+}
+
+/// Hash overlapping regions of an image at several scales.
+///
+/// Args:
+///     path: Path to the image file
+///     hash_size: Size of each region hash (default: 8)
+///     algorithm: Region hash algorithm (default: "phash")
+///     scales: Region sizes as fractions of the image size, each combined
+///             for width and height (default: [1.0, 0.8, 0.6, 0.5])
+///     overlap: Fraction by which neighbouring regions overlap (default: 0.5)
+///
+/// Returns:
+///     List of {"region": (x, y, width, height), "hash": hex}, largest
+///     region first; the first is the whole image
+#[pyfunction]
+#[pyo3(signature = (path, hash_size = 8, algorithm = "phash", scales = None, overlap = 0.5))]
+fn compute_tiled_hash(
+    py: Python<'_>,
+    path: PathBuf,
+    hash_size: usize,
+    algorithm: &str,
+    scales: Option<Vec<f64>>,
+    overlap: f64,
+) -> PyResult<Vec<HashMap<String, PyObject>>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let options = tile_options(scales, overlap);
+    let hash = py
+        .allow_threads(|| TiledHash::from_path(path, algorithm, hash_size, &options))
+        .map_err(hash_err)?;
+
+    Ok(hash
+        .tiles()
+        .iter()
+        .map(|tile| {
+            let mut map = HashMap::new();
+            map.insert(
+                "region".to_string(),
+                region_tuple(&tile.region).to_object(py),
+            );
+            map.insert("hash".to_string(), tile.hash.to_hex().to_object(py));
+            map
+        })
+        .collect())
+}
+
+/// Find regions of one image that match regions of another.
+///
+/// Detects an image contained in another, such as a picture with a caption
+/// bar added or one panel of a collage.
+///
+/// Args:
+///     path_a: Image whose regions are reported
+///     path_b: Image to look for
+///     threshold: Maximum Hamming distance for two regions to match
+///                (default: 10)
+///     hash_size, algorithm, scales, overlap: As for compute_tiled_hash
+///
+/// Returns:
+///     List of {"region": (x, y, width, height) in path_a, "other": the
+///     closest region of path_b, "distance": int, "contains": True when
+///     "other" is the whole of path_b}, best matches first. Regions with
+///     a flat hash (blank bars, borders) are never reported.
+#[pyfunction]
+#[pyo3(signature = (
+    path_a,
+    path_b,
+    threshold = 10,
+    hash_size = 8,
+    algorithm = "phash",
+    scales = None,
+    overlap = 0.5
+))]
+#[allow(clippy::too_many_arguments)]
+fn match_regions(
+    py: Python<'_>,
+    path_a: PathBuf,
+    path_b: PathBuf,
+    threshold: u32,
+    hash_size: usize,
+    algorithm: &str,
+    scales: Option<Vec<f64>>,
+    overlap: f64,
+) -> PyResult<Vec<HashMap<String, PyObject>>> {
+    let algorithm = parse_algorithm(algorithm)?;
+    let options = tile_options(scales, overlap);
+    let (a, b) = py
+        .allow_threads(|| {
+            let a = TiledHash::from_path(path_a, algorithm, hash_size, &options)?;
+            let b = TiledHash::from_path(path_b, algorithm, hash_size, &options)?;
+            Ok((a, b))
+        })
+        .map_err(hash_err)?;
+    let whole_b = b.tiles()[0].region;
+
+    Ok(a.match_regions(&b, threshold)
+        .iter()
+        .map(|m| {
+            let mut map = HashMap::new();
+            map.insert("region".to_string(), region_tuple(&m.region).to_object(py));
+            map.insert("other".to_string(), region_tuple(&m.other).to_object(py));
+            map.insert("distance".to_string(), m.distance.to_object(py));
+            map.insert("contains".to_string(), (m.other == whole_b).to_object(py));
+            map
+        })
+        .collect())
+}
+
+/// Choose which decoders handle a file extension, in order of preference.
+///
+/// Args:
+///     extension: File extension, e.g. "jpg" or ".heic"
+///     backends: Backend names: "image", "turbojpeg", "libheif", "raw",
+///               "pdf", "external", "custom"
+#[pyfunction]
+fn set_decode_backends(extension: &str, backends: Vec<String>) -> PyResult<()> {
+    let backends = backends
+        .iter()
+        .map(|b| b.parse::<DecodeBackend>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+
+    configure_decoder(|config| config.set_backends(extension, backends));
+    Ok(())
+}
+
+/// Configure the command used by the "external" decode backend.
+///
+/// The command must write an encoded image (e.g. PNG) to stdout.
+/// "{path}" in any argument is replaced with the input file path.
+///
+/// Args:
+///     command: Argument list, e.g. ["magick", "{path}", "png:-"],
+///              or None to disable the external backend
+#[pyfunction]
+#[pyo3(signature = (command = None))]
+fn set_external_decoder(command: Option<Vec<String>>) -> PyResult<()> {
+    configure_decoder(|config| config.set_external_command(command));
+    Ok(())
+}
+
+/// Register a Python function that decodes one file extension.
+///
+/// For formats no built-in backend reads, e.g. HEIC through pillow-heif.
+/// The function is called with the file path and returns the encoded file
+/// as bytes in a format the library reads (e.g. PNG), a PIL image, or a
+/// numpy uint8 array of shape (h, w) or (h, w, 1|3|4). It is tried before
+/// the built-in backends for the extension, unless set_decode_backends
+/// chose a chain without "custom", and directory scans include the
+/// extension. Returned images are hashed as they are, without EXIF
+/// orientation applied. Exceptions count as decode failures.
+///
+/// Args:
+///     extension: File extension, e.g. "heic"
+///     decoder: Callable taking the path, or None to remove the decoder
+#[pyfunction]
+#[pyo3(signature = (extension, decoder = None))]
+fn register_decoder(extension: &str, decoder: Option<PyObject>) -> PyResult<()> {
+    let hook = decoder.map(|decoder| -> DecodeHook {
+        Arc::new(move |path: &Path| {
+            Python::with_gil(|py| {
+                let decoded = decoder.call1(py, (path,)).map_err(|e| e.to_string())?;
+                decoded_image(decoded.bind(py)).map_err(|e| e.to_string())
+            })
+        })
+    });
+    configure_decoder(|config| config.set_custom_decoder(extension, hook));
+    Ok(())
+}
+
+/// The image returned by a registered decoder
+fn decoded_image(decoded: &Bound<'_, PyAny>) -> PyResult<DynamicImage> {
+    if let Ok(bytes) = decoded.downcast::<PyBytes>() {
+        return image::load_from_memory(bytes.as_bytes())
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()));
+    }
+    let (width, height, channels) = pixel_layout(decoded)?;
+    let data = decoded.call_method0("tobytes")?;
+    image_from_raw(
+        width,
+        height,
+        channels,
+        data.downcast::<PyBytes>()?.as_bytes(),
+    )
+    .map_err(hash_err)
+}
+
+/// Choose whether images are turned upright by their EXIF orientation.
+///
+/// Enabled by default, so a photo tagged "rotate 90" hashes the same as a
+/// physically rotated copy. Applies to every hashing function; this is the
+/// default for the apply_exif_orientation argument of compute_hash,
+/// compute_hashes_parallel and find_duplicate_images, which override it
+/// for one call.
+///
+/// Args:
+///     apply_exif_orientation: False to hash pixels in stored order
+#[pyfunction]
+#[pyo3(signature = (apply_exif_orientation = true))]
+fn set_exif_orientation(apply_exif_orientation: bool) -> PyResult<()> {
+    configure_decoder(|config| config.set_apply_exif_orientation(apply_exif_orientation));
+    Ok(())
+}
+
+/// Cap the size of the images decoded, so one huge file can't exhaust memory.
+///
+/// Files over a limit are not decoded: batch functions report them with
+/// error kind "too_large" and carry on, single-file functions raise
+/// OSError. Applies to every hashing function; call without arguments to
+/// remove the limits.
+///
+/// Args:
+///     max_pixels: Largest width x height, read from the file header for
+///                 TIFF, PNG, JPEG and the other formats the built-in
+///                 decoder knows (optional)
+///     max_file_size: Largest file size in bytes, for every format
+///                    (optional)
+#[pyfunction]
+#[pyo3(signature = (max_pixels = None, max_file_size = None))]
+fn set_decode_limits(max_pixels: Option<u64>, max_file_size: Option<u64>) -> PyResult<()> {
+    configure_decoder(|config| {
+        config.set_limits(DecodeLimits {
+            max_pixels,
+            max_file_size,
+        })
+    });
+    Ok(())
+}
+
+/// Choose the order in which files are read while hashing.
+///
+/// "parallel" (the default) lets every worker thread open files in any
+/// order. "sequential" sorts files by directory and on-disk position and
+/// reads them from one thread a few files ahead of the decoders, which
+/// avoids seeking on spinning disks and tape-backed mounts. Applies to
+/// every hashing function.
+///
+/// Args:
+///     order: "parallel", "sequential", or "sequential:N" to read up to N
+///            files ahead (default for sequential: 8)
+#[pyfunction(name = "set_read_order")]
+#[pyo3(signature = (order = "parallel"))]
+fn py_set_read_order(order: &str) -> PyResult<()> {
+    let order = order
+        .parse::<ReadOrder>()
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    set_read_order(order);
+    Ok(())
+}
+
+/// Choose where the resize and DCT stages of pHash run.
+///
+/// "auto" (the default) uses the GPU when the library was built with the
+/// `gpu` feature and finds a usable adapter, the CPU otherwise. Hashes are
+/// the same either way. Applies to the batch hashing functions; single
+/// images are always hashed on the CPU.
+///
+/// Args:
+///     backend: "auto", "cpu" or "gpu"; "gpu" raises ValueError when no
+///              GPU is usable
+#[pyfunction(name = "set_hash_backend")]
+#[pyo3(signature = (backend = "auto"))]
+fn py_set_hash_backend(backend: &str) -> PyResult<()> {
+    let backend = backend
+        .parse::<HashBackend>()
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    set_hash_backend(backend).map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+/// The backend batch hashing runs on: "cpu" or "gpu".
+#[pyfunction(name = "hash_backend")]
+fn py_hash_backend() -> String {
+    active_backend().to_string()
+}
+
+/// Log what scans do, through Python's logging module or to stderr.
+///
+/// Records cover directory scans, hashing times per batch and (at "debug")
+/// per file, files that were skipped or failed, and grouping statistics,
+/// for finding where a slow scan spends its time. Each goes to the logger
+/// of the Rust module that emitted it ("phash_rs.cache",
+/// "phash_rs.duplicate", ...), so handlers and levels set on the
+/// "phash_rs" logger apply to all of them. Nothing is logged until this is
+/// called; call it again to change the level or destination.
+///
+/// Args:
+///     level: "off", "error", "warn", "info", "debug" or "trace"
+///            (default: "info")
+///     forward: Send records to Python's logging module; False prints
+///              them to stderr (default: True)
+#[pyfunction(name = "configure_logging")]
+#[pyo3(signature = (level = "info", forward = true))]
+fn py_configure_logging(level: &str, forward: bool) -> PyResult<()> {
+    let level = level
+        .parse::<LogLevel>()
+        .map_err(pyo3::exceptions::PyValueError::new_err)?;
+    let sink = forward.then(|| Arc::new(log_to_python) as LogSink);
+    init_logging(level, sink).map_err(pyo3::exceptions::PyRuntimeError::new_err)
+}
+
+/// Pass a library log record to Python's logging module
+fn log_to_python(record: &LogRecord) {
+    let levelno = match record.level {
+        LogLevel::Error => 40,
+        LogLevel::Warn => 30,
+        LogLevel::Info => 20,
+        LogLevel::Debug => 10,
+        // Below logging.DEBUG, where other libraries put TRACE
+        LogLevel::Trace | LogLevel::Off => 5,
+    };
+    Python::with_gil(|py| {
+        let logged = py.import_bound("logging").and_then(|logging| {
+            logging
+                .call_method1("getLogger", (record.target.replace("::", "."),))?
+                .call_method1("log", (levelno, &record.message))
+        });
+        if let Err(e) = logged {
+            e.write_unraisable_bound(py, None);
+        }
+    });
+}
+
+/// Hash functions registered with register_hash_function, by name
+static HASH_FUNCTIONS: Mutex<BTreeMap<String, PyObject>> = Mutex::new(BTreeMap::new());
+
+/// A registered Python hash function
+struct PyHasher {
+    func: PyObject,
+}
+
+impl PyHasher {
+    /// The function registered as `name`, if any
+    fn registered(py: Python<'_>, name: &str) -> Option<Self> {
+        let functions = HASH_FUNCTIONS.lock().unwrap_or_else(|e| e.into_inner());
+        functions.get(name).map(|func| Self {
+            func: func.clone_ref(py),
+        })
+    }
+
+    /// Call the function on `img` as an RGB array and flatten its result
+    fn bits(&self, py: Python<'_>, img: &DynamicImage) -> PyResult<Vec<bool>> {
+        let rgb = img.to_rgb8();
+        let (width, height) = rgb.dimensions();
+        let numpy = py.import_bound("numpy")?;
+        let array = numpy
+            .call_method1(
+                "frombuffer",
+                (PyBytes::new_bound(py, &rgb.into_raw()), "uint8"),
+            )?
+            .call_method1("reshape", (height, width, 3))?;
+        let result = self.func.call1(py, (array,))?;
+        numpy
+            .call_method1("asarray", (result,))?
+            .call_method1("astype", ("bool",))?
+            .call_method0("ravel")?
+            .call_method0("tolist")?
+            .extract()
+    }
+}
+
+impl Hasher for PyHasher {
+    fn hash_image(&self, img: &DynamicImage) -> Result<ImageHash, HashError> {
+        let bits = Python::with_gil(|py| self.bits(py, img))
+            .map_err(|e| HashError::InvalidInput(format!("Hash function failed: {}", e)))?;
+        ImageHash::from_bits(&bits)
+    }
+}
+
+/// Register a Python hash function for find_duplicate_images.
+///
+/// The function is called with each image as a numpy uint8 array of shape
+/// (height, width, 3), decoded, upright and preprocessed, and returns its
+/// hash as bits: any array-like of booleans or 0/1, flattened in order.
+/// All hashes must have the same number of bits. It runs under the GIL,
+/// so only decoding is parallel.
+///
+/// Args:
+///     name: Name to pass as find_duplicate_images(algorithm=name); must
+///           not be a built-in algorithm name
+///     func: Callable mapping an image array to bits, or None to remove
+///           the function registered as name
+#[pyfunction]
+#[pyo3(signature = (name, func))]
+fn register_hash_function(name: &str, func: Option<PyObject>) -> PyResult<()> {
+    if parse_algorithm(name).is_ok() {
+        return Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "{} is a built-in algorithm",
+            name
+        )));
+    }
+    let mut functions = HASH_FUNCTIONS.lock().unwrap_or_else(|e| e.into_inner());
+    match func {
+        Some(func) => functions.insert(name.to_string(), func),
+        None => functions.remove(name),
+    };
+    Ok(())
+}
+
+/// Limit the threads the batch functions use.
+///
+/// By default they use one thread per core. With a limit they run on a
+/// dedicated pool of that size, leaving the other cores to the rest of the
+/// process; the num_threads argument overrides it per call.
+///
+/// Args:
+///     num_threads: Number of threads, or None for one per core
+#[pyfunction(name = "set_num_threads")]
+#[pyo3(signature = (num_threads = None))]
+fn py_set_num_threads(num_threads: Option<usize>) -> PyResult<()> {
+    set_num_threads(num_threads).map_err(pyo3::exceptions::PyValueError::new_err)
+}
+
+/// Report which decoders can open each known file extension.
+///
+/// Returns:
+///     Dictionary mapping extensions to the usable backend names, in the
+///     order they are tried. An empty list means the format is known but
+///     cannot be decoded with this build and configuration.
+#[pyfunction]
+fn supported_formats() -> PyResult<HashMap<String, Vec<&'static str>>> {
+    Ok(decoder_config().supported_formats().into_iter().collect())
+}
+
+/// Report which optional build features are compiled in.
+///
+/// Slim builds leave out formats and subsystems; check here before relying
+/// on them, e.g. `features()["video"]` before find_duplicate_videos.
+///
+/// Returns:
+///     Dictionary mapping each optional feature name ("gif", "webp",
+///     "tiff", "bmp", "extra-formats", "exact", "turbojpeg", "heif", "avif",
+///     "raw", "pdf", "sync", "video", "trash", "tokio", "watch", "gpu") to
+///     whether this build includes it
+#[pyfunction(name = "features")]
+fn py_features() -> HashMap<&'static str, bool> {
+    features().into_iter().collect()
+}
+
+/// Describe what this build can do on this machine.
+///
+/// Intended for support tooling, e.g. to explain why HEIC decoding works on
+/// one host but not another.
+///
+/// Returns:
+///     Dictionary with:
+///     - "version": Library version
+///     - "features": Cargo features compiled in
+///     - "algorithms": Accepted `algorithm` names
+///     - "formats": Same as `supported_formats()`
+///     - "simd": {"detected": CPU extensions, "hamming": implementation used}
+///     - "limits": Default sizes and limits
+#[pyfunction]
+fn get_capabilities() -> PyResult<HashMap<String, PyObject>> {
+    let caps = capabilities();
+
+    Python::with_gil(|py| {
+        let formats: HashMap<String, Vec<&str>> = caps.formats.into_iter().collect();
+        let limits: HashMap<&str, usize> = caps.limits.into_iter().collect();
+        let mut simd = HashMap::new();
+        simd.insert("detected", caps.simd_detected.to_object(py));
+        simd.insert("hamming", caps.hamming_path.to_object(py));
+
+        let mut map = HashMap::new();
+        map.insert("version".to_string(), caps.version.to_object(py));
+        map.insert("features".to_string(), caps.features.to_object(py));
+        map.insert("algorithms".to_string(), caps.algorithms.to_object(py));
+        map.insert("formats".to_string(), formats.to_object(py));
+        map.insert("simd".to_string(), simd.to_object(py));
+        map.insert("limits".to_string(), limits.to_object(py));
+        Ok(map)
+    })
+}
+
+/// Python module definition
+#[pymodule]
+fn phash_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compute_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_hash_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_hash_image, m)?)?;
+    m.add_function(wrap_pyfunction!(dct_coefficients, m)?)?;
+    m.add_function(wrap_pyfunction!(hamming_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(cross_correlation, m)?)?;
+    m.add_function(wrap_pyfunction!(weighted_hamming_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(py_distance_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(py_pairwise_distances, m)?)?;
+    m.add_function(wrap_pyfunction!(distances, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_within, m)?)?;
+    m.add_function(wrap_pyfunction!(are_similar, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_detailed, m)?)?;
+    m.add_function(wrap_pyfunction!(has_duplicate, m)?)?;
+    m.add_function(wrap_pyfunction!(py_find_similar, m)?)?;
+    m.add_function(wrap_pyfunction!(find_duplicate_images, m)?)?;
+    m.add_function(wrap_pyfunction!(py_dedupe, m)?)?;
+    m.add_function(wrap_pyfunction!(py_apply_dedup, m)?)?;
+    m.add_function(wrap_pyfunction!(py_restore, m)?)?;
+    m.add_function(wrap_pyfunction!(find_duplicates_streaming, m)?)?;
+    m.add_function(wrap_pyfunction!(find_duplicates_resumable, m)?)?;
+    m.add_function(wrap_pyfunction!(resume, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_hashes_parallel, m)?)?;
+    m.add_function(wrap_pyfunction!(classify_images, m)?)?;
+    m.add_function(wrap_pyfunction!(py_cluster_similar, m)?)?;
+    m.add_function(wrap_pyfunction!(py_score_image, m)?)?;
+    m.add_function(wrap_pyfunction!(py_robustness_report, m)?)?;
+    m.add_function(wrap_pyfunction!(py_scan_directory, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_scan, m)?)?;
+    m.add_function(wrap_pyfunction!(py_calibrate_threshold, m)?)?;
+    m.add_function(wrap_pyfunction!(find_photos_in_documents, m)?)?;
+    m.add_function(wrap_pyfunction!(py_match_collections, m)?)?;
+    m.add_function(wrap_pyfunction!(find_duplicate_videos, m)?)?;
+    m.add_function(wrap_pyfunction!(benchmark_index, m)?)?;
+    m.add_function(wrap_pyfunction!(export_report, m)?)?;
+    m.add_function(wrap_pyfunction!(py_generate_report, m)?)?;
+    m.add_function(wrap_pyfunction!(py_export_results, m)?)?;
+    m.add_function(wrap_pyfunction!(py_import_results, m)?)?;
+    m.add_function(wrap_pyfunction!(py_find_duplicates_incremental, m)?)?;
+    m.add_function(wrap_pyfunction!(annotate_group, m)?)?;
+    m.add_class::<HashIndex>()?;
+    m.add_class::<PyBlocklist>()?;
+    m.add_class::<DuplicateStream>()?;
+    m.add_class::<PyDuplicateWatcher>()?;
+    m.add_class::<PyImageHash>()?;
+    m.add_class::<PyDuplicateGroup>()?;
+    m.add_function(wrap_pyfunction!(compute_crop_resistant_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(crop_resistant_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(crop_resistant_matches, m)?)?;
+    m.add_function(wrap_pyfunction!(compute_tiled_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(match_regions, m)?)?;
+    m.add_function(wrap_pyfunction!(set_decode_backends, m)?)?;
+    m.add_function(wrap_pyfunction!(set_external_decoder, m)?)?;
+    m.add_function(wrap_pyfunction!(register_decoder, m)?)?;
+    m.add_function(wrap_pyfunction!(set_exif_orientation, m)?)?;
+    m.add_function(wrap_pyfunction!(set_decode_limits, m)?)?;
+    m.add_function(wrap_pyfunction!(py_set_read_order, m)?)?;
+    m.add_function(wrap_pyfunction!(py_configure_logging, m)?)?;
+    m.add_function(wrap_pyfunction!(py_set_hash_backend, m)?)?;
+    m.add_function(wrap_pyfunction!(py_hash_backend, m)?)?;
+    m.add_function(wrap_pyfunction!(py_set_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(register_hash_function, m)?)?;
+    m.add_function(wrap_pyfunction!(supported_formats, m)?)?;
+    m.add_function(wrap_pyfunction!(get_capabilities, m)?)?;
+    m.add_function(wrap_pyfunction!(py_features, m)?)?;
+
+    // Add constants for recommended thresholds
+    m.add("THRESHOLD_IDENTICAL", 0)?;
+    m.add("THRESHOLD_VERY_SIMILAR", 5)?;
+    m.add("THRESHOLD_SIMILAR", 10)?;
+    m.add("THRESHOLD_SOMEWHAT_SIMILAR", 15)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distances_refuse_hashes_of_different_lengths() {
+        let (short, long) = ("0".repeat(16), "0".repeat(64));
+        assert_eq!(hamming_distance(&short, &short, false).unwrap(), 0);
+        assert!(hamming_distance(&short, &long, false).is_err());
+        assert!(hamming_distance(&long, &short, true).is_err());
+        assert!(crop_resistant_distance(&short, "00000000").is_err());
+        assert!(crop_resistant_matches(&short, "00000000", 1, 0.25).is_err());
+    }
+
+    #[test]
+    fn test_are_similar_refuses_indexed_hash_of_another_size() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_similar_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        let img =
+            image::RgbImage::from_fn(32, 32, |x, y| image::Rgb([(x * 8) as u8, (y * 8) as u8, 0]));
+        img.save(&a).unwrap();
+        img.save(&b).unwrap();
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut index = HashIndex::new(None, None).unwrap();
+            index.add(a.clone(), &"0".repeat(64), None).unwrap();
+            let index = Py::new(py, index).unwrap();
+            let similar = |hash_size| {
+                are_similar(
+                    py,
+                    a.clone(),
+                    b.clone(),
+                    10,
+                    "phash",
+                    Some(index.borrow(py)),
+                    hash_size,
+                )
+            };
+            assert!(similar(16).is_ok());
+            assert!(similar(8).is_err());
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_batch_exif_orientation_per_call() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_oriented_py_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.jpg");
+        let img = image::RgbImage::from_fn(48, 32, |x, y| {
+            image::Rgb([(x * 5) as u8, (y * 8) as u8, if x < 16 { 255 } else { 0 }])
+        });
+        std::fs::write(&path, crate::orientation::tagged_jpeg(&img, 6)).unwrap();
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let hashes = |apply| {
+                let hashes = compute_hashes_parallel(
+                    py,
+                    vec![path.clone()],
+                    "phash",
+                    None,
+                    None,
+                    None,
+                    None,
+                    false,
+                    DEFAULT_HASH_SIZE,
+                    false,
+                    None,
+                    false,
+                    None,
+                    None,
+                    DCT_SIZE,
+                    "lanczos3",
+                    None,
+                    false,
+                    false,
+                    Some(apply),
+                )
+                .unwrap();
+                hashes.extract::<HashMap<PathBuf, String>>(py).unwrap()[&path].clone()
+            };
+            let upright = ImageHash::from_path_with(
+                &path,
+                HashAlgorithm::PHash,
+                DEFAULT_HASH_SIZE,
+                &HashOptions {
+                    apply_exif_orientation: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            let stored = ImageHash::from_image(
+                &image::open(&path).unwrap(),
+                HashAlgorithm::PHash,
+                DEFAULT_HASH_SIZE,
+            )
+            .unwrap();
+            assert_ne!(upright, stored);
+            assert_eq!(hashes(true), upright.to_hex());
+            assert_eq!(hashes(false), stored.to_hex());
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
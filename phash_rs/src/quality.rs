@@ -0,0 +1,354 @@
+//! Image Quality Module
+//!
+//! Resolution alone prefers an upscaled, blurry copy over the sharp original
+//! it was made from. The scores here look at what the pixels carry: how
+//! sharp the picture is, how much of its resolution holds real detail, how
+//! hard a JPEG was compressed and how visible its 8x8 blocks are.
+
+use crate::decode::open_image;
+use crate::error::HashError;
+use crate::orientation::read_header;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, GrayImage};
+use std::path::Path;
+
+/// Longest side images are reduced to before scoring sharpness, so copies
+/// saved at different resolutions score alike
+const SHARPNESS_SIZE: u32 = 512;
+
+/// Largest center crop examined at full resolution
+const DETAIL_CROP: u32 = 1024;
+
+/// Most halvings an image is checked for being an upscale
+const MAX_UPSCALE_STEPS: u32 = 3;
+
+/// Share of the Laplacian energy a halve-and-restore round trip may lose
+/// for the image to count as upscaled by 2; originals lose nearly all of
+/// it, since the Laplacian weighs the finest detail most
+const UPSCALE_LOSS: f64 = 0.5;
+
+/// Sharpness at which an image is taken as half sharp; the usual blur
+/// threshold for the variance of the Laplacian
+const SHARPNESS_KNEE: f64 = 100.0;
+
+/// Luminance quantization table of the JPEG standard (Annex K), which
+/// encoders scale by the quality setting
+const STANDARD_LUMA_TABLE: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// How good one copy of an image looks
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityScore {
+    /// Width and height as decoded
+    pub dimensions: (u32, u32),
+    /// Variance of the Laplacian (see `sharpness`); higher is sharper
+    pub sharpness: f64,
+    /// How many times the image appears to have been upscaled: 1 for
+    /// detail at full resolution, 2, 4 or 8 when that many times smaller
+    /// would hold the same detail
+    pub upscale: u32,
+    /// Estimated JPEG quality setting, 1 to 100, for JPEG files
+    pub jpeg_quality: Option<u8>,
+    /// How much stronger edges are on the 8x8 block grid than inside
+    /// blocks; 0 for no visible blocking
+    pub blockiness: f64,
+}
+
+impl QualityScore {
+    /// Combined score; higher is better
+    ///
+    /// Megapixels that carry detail (the decoded ones divided by
+    /// `upscale` squared), weighed by `sharpness / (sharpness + 100)`, the
+    /// JPEG quality as a fraction and `1 / (1 + blockiness)`. Only
+    /// meaningful between copies of one picture.
+    pub fn score(&self) -> f64 {
+        let (width, height) = self.dimensions;
+        let megapixels = width as f64 * height as f64 / 1e6;
+        let effective = megapixels / (self.upscale as f64).powi(2);
+        let sharpness = self.sharpness / (self.sharpness + SHARPNESS_KNEE);
+        let compression = self.jpeg_quality.map_or(1.0, |q| q as f64 / 100.0);
+        effective * sharpness * compression / (1.0 + self.blockiness)
+    }
+}
+
+/// Score the quality of an image file (see `score_decoded`)
+///
+/// JPEG files additionally get their quality setting estimated from the
+/// quantization table in their header.
+pub fn score_image<P: AsRef<Path>>(path: P) -> Result<QualityScore, HashError> {
+    let path = path.as_ref();
+    let img = open_image(path)?;
+    let quality = read_header(path).and_then(|data| jpeg_quality(&data));
+    Ok(score_decoded(&img, quality))
+}
+
+/// Score the quality of a decoded image whose JPEG quality, if any, is
+/// known
+pub fn score_decoded(img: &DynamicImage, jpeg_quality: Option<u8>) -> QualityScore {
+    let gray = img.to_luma8();
+    let crop = center_crop(&gray, DETAIL_CROP);
+    QualityScore {
+        dimensions: img.dimensions(),
+        sharpness: sharpness(img),
+        upscale: upscale_factor(&crop),
+        jpeg_quality,
+        blockiness: blockiness(&crop),
+    }
+}
+
+/// Sharpness of an image: the variance of its grayscale Laplacian
+///
+/// Motion blur and missed focus flatten edges and lower the score. The
+/// image is first reduced to at most 512 pixels on its longer side. Scores
+/// only compare between shots of the same scene.
+pub fn sharpness(img: &DynamicImage) -> f64 {
+    let gray = if img.width().max(img.height()) > SHARPNESS_SIZE {
+        img.resize(SHARPNESS_SIZE, SHARPNESS_SIZE, FilterType::Triangle)
+            .to_luma8()
+    } else {
+        img.to_luma8()
+    };
+    laplacian_variance(&luma_values(&gray), gray.width(), gray.height())
+}
+
+/// Estimated quality setting of a JPEG from its header, 1 to 100
+///
+/// Encoders scale the standard luminance table by the quality setting, so
+/// the ratio of the file's first table to the standard one gives the
+/// setting back (exactly for libjpeg-style encoders). Returns `None` for
+/// data that is not a JPEG or has no table before its first scan.
+pub fn jpeg_quality(data: &[u8]) -> Option<u8> {
+    let table = luma_table(data)?;
+    if table.iter().all(|&q| q == 1) {
+        return Some(100);
+    }
+    let sum: u32 = table.iter().map(|&q| q as u32).sum();
+    let standard: u32 = STANDARD_LUMA_TABLE.iter().map(|&q| q as u32).sum();
+    let scale = 100.0 * sum as f64 / standard as f64;
+    let quality = if scale <= 100.0 {
+        (200.0 - scale) / 2.0
+    } else {
+        5000.0 / scale
+    };
+    Some(quality.round().clamp(1.0, 100.0) as u8)
+}
+
+/// Quantization table 0 of a JPEG, from the segments before its first scan
+fn luma_table(data: &[u8]) -> Option<Vec<u16>> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            // Fill byte
+            pos += 1;
+            continue;
+        }
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + length)?;
+        if marker == 0xDB {
+            let mut tables = segment;
+            while let Some((&info, rest)) = tables.split_first() {
+                let wide = info >> 4 == 1;
+                let size = if wide { 128 } else { 64 };
+                let values = rest.get(..size)?;
+                if info & 0x0F == 0 {
+                    return Some(if wide {
+                        values
+                            .chunks(2)
+                            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                            .collect()
+                    } else {
+                        values.iter().map(|&b| b as u16).collect()
+                    });
+                }
+                tables = &rest[size..];
+            }
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+/// How many times `gray` appears upscaled, as a power of two
+///
+/// An upscaled image holds no detail a smaller copy could not: halving it
+/// and scaling back (with Lanczos3, close to an ideal filter) loses little
+/// of its Laplacian energy. Halving repeats while that holds.
+fn upscale_factor(gray: &GrayImage) -> u32 {
+    let mut current = gray.clone();
+    let mut factor = 1;
+    for _ in 0..MAX_UPSCALE_STEPS {
+        let (w, h) = current.dimensions();
+        if w < 16 || h < 16 {
+            break;
+        }
+        let half = image::imageops::resize(&current, w / 2, h / 2, FilterType::Lanczos3);
+        let restored = image::imageops::resize(&half, w, h, FilterType::Lanczos3);
+        let energy = laplacian_variance(&luma_values(&current), w, h);
+        let residual: Vec<f64> = current
+            .pixels()
+            .zip(restored.pixels())
+            .map(|(a, b)| a.0[0] as f64 - b.0[0] as f64)
+            .collect();
+        if energy == 0.0 || laplacian_variance(&residual, w, h) > UPSCALE_LOSS * energy {
+            break;
+        }
+        factor *= 2;
+        current = half;
+    }
+    factor
+}
+
+/// Blocking artifacts of `gray`: the mean step across 8-pixel block
+/// boundaries relative to the mean step inside blocks, minus one, in both
+/// directions
+///
+/// Steps are offset by one so flat images score 0.
+fn blockiness(gray: &GrayImage) -> f64 {
+    let (w, h) = gray.dimensions();
+    let at = |x: u32, y: u32| gray.get_pixel(x, y).0[0] as f64;
+    let (mut boundary, mut boundary_count) = (0.0, 0u64);
+    let (mut interior, mut interior_count) = (0.0, 0u64);
+    let mut add = |edge: bool, step: f64| {
+        if edge {
+            boundary += step;
+            boundary_count += 1;
+        } else {
+            interior += step;
+            interior_count += 1;
+        }
+    };
+    for y in 0..h {
+        for x in 0..w {
+            if x > 0 {
+                add(x.is_multiple_of(8), (at(x, y) - at(x - 1, y)).abs());
+            }
+            if y > 0 {
+                add(y.is_multiple_of(8), (at(x, y) - at(x, y - 1)).abs());
+            }
+        }
+    }
+    if boundary_count == 0 || interior_count == 0 {
+        return 0.0;
+    }
+    let boundary = boundary / boundary_count as f64;
+    let interior = interior / interior_count as f64;
+    ((boundary + 1.0) / (interior + 1.0) - 1.0).max(0.0)
+}
+
+/// Center of `gray`, at most `size` square, aligned to the 8x8 block grid
+fn center_crop(gray: &GrayImage, size: u32) -> GrayImage {
+    let (w, h) = gray.dimensions();
+    let x = (w.saturating_sub(size) / 2) & !7;
+    let y = (h.saturating_sub(size) / 2) & !7;
+    image::imageops::crop_imm(gray, x, y, w.min(size), h.min(size)).to_image()
+}
+
+fn luma_values(gray: &GrayImage) -> Vec<f64> {
+    gray.pixels().map(|p| p.0[0] as f64).collect()
+}
+
+/// Variance of the 4-neighbour Laplacian of `w` x `h` values, row by row
+fn laplacian_variance(values: &[f64], w: u32, h: u32) -> f64 {
+    let (w, h) = (w as usize, h as usize);
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let at = |x: usize, y: usize| values[y * w + x];
+    let laplacian: Vec<f64> = (1..h - 1)
+        .flat_map(|y| (1..w - 1).map(move |x| (x, y)))
+        .map(|(x, y)| at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y))
+        .collect();
+    let mean = laplacian.iter().sum::<f64>() / laplacian.len() as f64;
+    laplacian.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / laplacian.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SeededRng;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{GrayImage, Luma};
+
+    /// Detail at every scale: seeded noise over a gradient
+    fn textured(size: u32) -> DynamicImage {
+        let mut rng = SeededRng::new(7);
+        DynamicImage::ImageLuma8(GrayImage::from_fn(size, size, |x, y| {
+            Luma([((x + y) / 4 + rng.below(128) as u32) as u8])
+        }))
+    }
+
+    fn encode_jpeg(img: &DynamicImage, quality: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, quality)
+            .encode_image(img)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_sharpness() {
+        let sharp = textured(96);
+        assert!(sharpness(&sharp) > sharpness(&sharp.blur(2.0)));
+        assert_eq!(sharpness(&DynamicImage::new_luma8(64, 64)), 0.0);
+        assert_eq!(sharpness(&DynamicImage::new_luma8(2, 2)), 0.0);
+    }
+
+    #[test]
+    fn test_jpeg_quality() {
+        let img = textured(64);
+        for quality in [30, 75, 95] {
+            let estimate = jpeg_quality(&encode_jpeg(&img, quality)).unwrap();
+            assert!(
+                estimate.abs_diff(quality) <= 1,
+                "{} vs {}",
+                estimate,
+                quality
+            );
+        }
+        assert_eq!(jpeg_quality(b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(jpeg_quality(&[0xFF, 0xD8, 0xFF]), None);
+    }
+
+    #[test]
+    fn test_upscale_factor() {
+        let original = textured(128);
+        assert_eq!(upscale_factor(&original.to_luma8()), 1);
+        let upscaled = original.resize_exact(256, 256, FilterType::Triangle);
+        assert_eq!(upscale_factor(&upscaled.to_luma8()), 2);
+        assert_eq!(upscale_factor(&GrayImage::new(64, 64)), 1);
+    }
+
+    #[test]
+    fn test_blockiness() {
+        let smooth = GrayImage::from_fn(64, 64, |x, y| Luma([(x + y) as u8]));
+        assert_eq!(blockiness(&smooth), 0.0);
+        let blocky = GrayImage::from_fn(64, 64, |x, y| Luma([((x / 8 + y / 8) * 20) as u8]));
+        assert!(blockiness(&blocky) > 1.0);
+    }
+
+    #[test]
+    fn test_original_beats_upscaled_copy() {
+        let original = textured(128);
+        let upscaled = original.resize_exact(256, 256, FilterType::Triangle);
+        let original = score_decoded(&original, None);
+        let upscaled = score_decoded(&upscaled, None);
+        assert_eq!(upscaled.upscale, 2);
+        assert!(original.score() > upscaled.score());
+
+        let jpeg = image::load_from_memory(&encode_jpeg(&textured(128), 20)).unwrap();
+        assert!(original.score() > score_decoded(&jpeg, Some(20)).score());
+    }
+}
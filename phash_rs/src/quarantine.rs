@@ -0,0 +1,258 @@
+//! Quarantine Module
+//!
+//! Undo support for `apply_dedup`: the quarantine and trash actions log
+//! every file they take away in a manifest, one JSON line per file written
+//! just before the file goes, so `restore` can put them back even after an
+//! interrupted run.
+
+use crate::ipc::{json_path, json_str};
+use crate::json::{self, Json};
+use crate::pipeline::move_file;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the manifest inside a quarantine run folder
+pub const MANIFEST_NAME: &str = "manifest.jsonl";
+
+/// Where a quarantined or trashed file went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path before it was taken away
+    pub original: PathBuf,
+    /// Path inside the quarantine folder; `None` for the OS trash
+    pub stored: Option<PathBuf>,
+    /// The file kept in its place
+    pub keep: PathBuf,
+}
+
+/// Appends entries to a run's manifest, flushing each one
+pub(crate) struct ManifestWriter {
+    file: File,
+}
+
+impl ManifestWriter {
+    /// Create the manifest of a new run in `dir`
+    pub(crate) fn create(dir: &Path, mode: &str) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let path = dir.join(MANIFEST_NAME);
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut writer = Self { file };
+        writer.write_line(&format!(
+            "{{\"version\":1,\"mode\":{},\"created\":{}}}",
+            json_str(mode),
+            created
+        ))?;
+        Ok(writer)
+    }
+
+    pub(crate) fn record(&mut self, entry: &ManifestEntry) -> Result<(), String> {
+        let stored = entry
+            .stored
+            .as_deref()
+            .map_or("null".to_string(), json_path);
+        self.write_line(&format!(
+            "{{\"original\":{},\"stored\":{},\"keep\":{}}}",
+            json_path(&entry.original),
+            stored,
+            json_path(&entry.keep)
+        ))
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.file, "{}", line)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| format!("Failed to write manifest: {}", e))
+    }
+}
+
+/// Read the entries of a manifest
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let invalid =
+        |n: usize, e: &str| format!("Invalid manifest {} line {}: {}", path.display(), n, e);
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value = json::parse(&line).map_err(|e| invalid(i + 1, &e))?;
+        if i == 0 {
+            if value.get("version").and_then(Json::as_u64) != Some(1) {
+                return Err(invalid(1, "unsupported version"));
+            }
+            continue;
+        }
+        let text = |name: &str| value.get(name).and_then(Json::as_path);
+        entries.push(ManifestEntry {
+            original: text("original").ok_or_else(|| invalid(i + 1, "missing \"original\""))?,
+            stored: text("stored"),
+            keep: text("keep").unwrap_or_default(),
+        });
+    }
+    Ok(entries)
+}
+
+/// What `restore` put back
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+    /// Original paths restored
+    pub restored: Vec<PathBuf>,
+    /// Entries left alone, with why (original path taken again, ...)
+    pub skipped: Vec<(PathBuf, String)>,
+    /// Entries that failed to restore, with the error
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+/// Put the files of a quarantine or trash run back where they were
+///
+/// `manifest` is the run's manifest file or its folder. Files whose
+/// original path is taken again are left where they are, so restoring
+/// twice is harmless. Fails only if the manifest can't be read.
+pub fn restore(manifest: &Path) -> Result<RestoreReport, String> {
+    let path = if manifest.is_dir() {
+        manifest.join(MANIFEST_NAME)
+    } else {
+        manifest.to_path_buf()
+    };
+    let mut report = RestoreReport::default();
+    for entry in read_manifest(&path)? {
+        if entry.original.exists() {
+            report
+                .skipped
+                .push((entry.original, "already exists".to_string()));
+            continue;
+        }
+        let result = match &entry.stored {
+            Some(stored) => move_file(stored, &entry.original),
+            None => restore_from_trash(&entry.original),
+        };
+        match result {
+            Ok(()) => report.restored.push(entry.original),
+            Err(e) => report.errors.push((entry.original, e)),
+        }
+    }
+    Ok(report)
+}
+
+/// Move `path` to the OS trash
+#[cfg(feature = "trash")]
+pub(crate) fn move_to_trash(path: &Path) -> Result<(), String> {
+    trash::delete(path).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "trash"))]
+pub(crate) fn move_to_trash(_path: &Path) -> Result<(), String> {
+    Err("OS trash support is not compiled in (feature \"trash\")".to_string())
+}
+
+/// Restore the most recently trashed file that was at `original`
+#[cfg(all(
+    feature = "trash",
+    any(
+        target_os = "windows",
+        all(
+            unix,
+            not(target_os = "macos"),
+            not(target_os = "ios"),
+            not(target_os = "android")
+        )
+    )
+))]
+fn restore_from_trash(original: &Path) -> Result<(), String> {
+    let item = trash::os_limited::list()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|item| item.original_path() == original)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or("not in the trash")?;
+    trash::os_limited::restore_all([item]).map_err(|e| e.to_string())
+}
+
+#[cfg(not(all(
+    feature = "trash",
+    any(
+        target_os = "windows",
+        all(
+            unix,
+            not(target_os = "macos"),
+            not(target_os = "ios"),
+            not(target_os = "android")
+        )
+    )
+)))]
+fn restore_from_trash(_original: &Path) -> Result<(), String> {
+    Err("restoring from the OS trash is not supported in this build".to_string())
+}
+
+/// `time` in UTC as `YYYYMMDDTHHMMSSZ`, for run folder names
+pub(crate) fn utc_stamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_manifest_round_trip_and_restore() {
+        let root = std::env::temp_dir().join(format!("phash_rs_quarantine_{}", std::process::id()));
+        let run = root.join("run");
+        fs::create_dir_all(&root).unwrap();
+        let original = root.join("b.jpg");
+        let stored = run.join("b.jpg");
+        let entry = ManifestEntry {
+            original: original.clone(),
+            stored: Some(stored.clone()),
+            keep: PathBuf::from("a \"kept\".jpg"),
+        };
+        let mut writer = ManifestWriter::create(&run, "quarantine").unwrap();
+        writer.record(&entry).unwrap();
+        drop(writer);
+        fs::write(&stored, b"pixels").unwrap();
+        assert_eq!(
+            read_manifest(&run.join(MANIFEST_NAME)).unwrap(),
+            vec![entry]
+        );
+
+        let report = restore(&run).unwrap();
+        assert_eq!(report.restored, vec![original.clone()]);
+        assert_eq!(fs::read(&original).unwrap(), b"pixels");
+        // Restoring again leaves the restored file alone
+        assert_eq!(restore(&run).unwrap().skipped.len(), 1);
+        fs::remove_dir_all(&root).unwrap();
+
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(utc_stamp(time), "20240229T123456Z");
+    }
+}
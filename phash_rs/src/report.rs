@@ -0,0 +1,322 @@
+//! Report Export Module
+//!
+//! Writes duplicate groups as a JSON manifest or a standalone HTML report.
+//! Reports are often reviewed on machines without access to the scanned
+//! files, so each group can carry a small inline thumbnail of its best
+//! image as a base64 data URI. `generate_report` writes the HTML page
+//! meant for people reviewing proposed deletions: every member with its
+//! thumbnail and distance from the copy that is kept.
+
+use crate::base64;
+use crate::decode::open_image;
+use crate::duplicate::DuplicateGroup;
+use crate::error::HashError;
+use crate::hash::ImageHash;
+use crate::ipc::{group_fields, json_str};
+use image::{DynamicImage, ImageFormat};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Edge length used when thumbnails are requested without a size
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 64;
+
+/// Thumbnail edge length of `generate_report`, large enough to tell
+/// near-duplicates apart by eye
+pub const REVIEW_THUMBNAIL_SIZE: u32 = 160;
+
+/// Output format of an exported report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// `{"groups": [...]}` with the fields of `find_duplicate_images`
+    Json,
+    /// Self-contained HTML page, one table row per group
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(ReportFormat::Json),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(format!("Unknown report format: {}", s)),
+        }
+    }
+}
+
+/// What goes into an exported report
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    /// Embed a thumbnail of each group's best image, fitted into a square
+    /// of this many pixels (`None` = no thumbnails)
+    pub thumbnail_size: Option<u32>,
+    /// Directory that relative group paths are resolved against when
+    /// reading images for thumbnails
+    pub root: Option<PathBuf>,
+    /// Embed a thumbnail of every member, not only the best image (HTML
+    /// only)
+    pub all_members: bool,
+}
+
+/// JPEG thumbnail of the image at `path` as a `data:` URI
+///
+/// The image is scaled to fit a `size` x `size` square, keeping its aspect
+/// ratio.
+pub fn thumbnail_data_uri(path: &Path, size: u32) -> Result<String, HashError> {
+    let img = open_image(path)?;
+    // JPEG has no alpha channel
+    let thumb = DynamicImage::ImageRgb8(img.thumbnail(size, size).to_rgb8());
+
+    let mut jpeg = Cursor::new(Vec::new());
+    thumb
+        .write_to(&mut jpeg, ImageFormat::Jpeg)
+        .map_err(|e| HashError::Decode(e.to_string()))?;
+    Ok(format!(
+        "data:image/jpeg;base64,{}",
+        base64::encode(jpeg.get_ref())
+    ))
+}
+
+/// Render `groups` in `format`
+///
+/// Images that cannot be read get no thumbnail rather than failing the
+/// export.
+pub fn render_report(
+    groups: &[DuplicateGroup],
+    format: ReportFormat,
+    options: &ReportOptions,
+) -> String {
+    // Thumbnail data URIs by group and path
+    let thumbnails: Vec<BTreeMap<&Path, String>> = groups
+        .par_iter()
+        .map(|g| {
+            let Some(size) = options.thumbnail_size else {
+                return BTreeMap::new();
+            };
+            let members = if options.all_members && format == ReportFormat::Html {
+                g.paths.as_slice()
+            } else {
+                std::slice::from_ref(&g.best_path)
+            };
+            members
+                .par_iter()
+                .filter_map(|p| {
+                    let path = match &options.root {
+                        Some(root) => root.join(p),
+                        None => p.clone(),
+                    };
+                    Some((p.as_path(), thumbnail_data_uri(&path, size).ok()?))
+                })
+                .collect()
+        })
+        .collect();
+
+    match format {
+        ReportFormat::Json => render_json(groups, &thumbnails),
+        ReportFormat::Html => render_html(groups, &thumbnails),
+    }
+}
+
+/// Write the HTML review page for `groups` to `path`
+///
+/// Every member is shown with a `REVIEW_THUMBNAIL_SIZE` thumbnail and its
+/// distance from the group's best image, which is highlighted as the copy
+/// to keep. The page embeds everything it shows, so it can be mailed or
+/// opened where the files aren't mounted. Relative group paths are read
+/// from under `root`.
+pub fn generate_report<P: AsRef<Path>>(
+    groups: &[DuplicateGroup],
+    path: P,
+    root: Option<&Path>,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let options = ReportOptions {
+        thumbnail_size: Some(REVIEW_THUMBNAIL_SIZE),
+        root: root.map(Path::to_path_buf),
+        all_members: true,
+    };
+    let html = render_report(groups, ReportFormat::Html, &options);
+    fs::write(path, html).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn render_json(groups: &[DuplicateGroup], thumbnails: &[BTreeMap<&Path, String>]) -> String {
+    let groups: Vec<String> = groups
+        .iter()
+        .zip(thumbnails)
+        .map(|(g, thumbs)| {
+            let thumb = thumbs
+                .get(g.best_path.as_path())
+                .map_or("null".to_string(), |uri| json_str(uri));
+            format!("{{{},\"thumbnail\":{}}}", group_fields(g), thumb)
+        })
+        .collect();
+    format!("{{\"groups\":[\n{}\n]}}\n", groups.join(",\n"))
+}
+
+/// Hamming distance of each member's hash from the best image's, where
+/// both are known
+fn distances_to_best(group: &DuplicateGroup) -> BTreeMap<&Path, u32> {
+    let best: Option<&ImageHash> = group.member_hashes.get(&group.best_path);
+    group
+        .paths
+        .iter()
+        .filter_map(|p| Some((p.as_path(), group.member_hashes.get(p)?.distance(best?))))
+        .collect()
+}
+
+fn render_html(groups: &[DuplicateGroup], thumbnails: &[BTreeMap<&Path, String>]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Duplicate report</title>\n\
+         <style>body{font-family:sans-serif}section{border-top:1px solid #ccc}\
+         ul{list-style:none;padding:0;display:flex;flex-wrap:wrap;gap:8px}\
+         li{border:2px solid #ddd;padding:4px;max-width:200px;word-break:break-all}\
+         li img{display:block;margin-bottom:4px}.distance{display:block;color:#666}\
+         .best{font-weight:bold;border-color:#2a7}.best::before{content:\"keep \";color:#2a7}\
+         </style></head><body>\n",
+    );
+    let files: usize = groups.iter().map(|g| g.paths.len()).sum();
+    html.push_str(&format!(
+        "<h1>Duplicate report</h1>\n<p>{} groups, {} files, {} proposed for removal</p>\n",
+        groups.len(),
+        files,
+        files.saturating_sub(groups.len())
+    ));
+    for (g, thumbs) in groups.iter().zip(thumbnails) {
+        let distances = distances_to_best(g);
+        let summary = match g.quality() {
+            Some(q) => format!(
+                "{} files, largest distance {}, mean {:.1}",
+                g.paths.len(),
+                q.max_distance,
+                q.mean_distance
+            ),
+            None => format!("{} files", g.paths.len()),
+        };
+        let members: Vec<String> = g
+            .paths
+            .iter()
+            .map(|p| {
+                let is_best = *p == g.best_path;
+                let class = if is_best { " class=\"best\"" } else { "" };
+                let img = thumbs
+                    .get(p.as_path())
+                    .map(|uri| format!("<img src=\"{}\" alt=\"\">", uri))
+                    .unwrap_or_default();
+                let distance = match distances.get(p.as_path()) {
+                    Some(d) if !is_best => {
+                        format!("<span class=\"distance\">distance {}</span>", d)
+                    }
+                    _ => String::new(),
+                };
+                format!(
+                    "<li{}>{}{}{}</li>",
+                    class,
+                    img,
+                    html_escape(&p.to_string_lossy()),
+                    distance
+                )
+            })
+            .collect();
+        html.push_str(&format!(
+            "<section id=\"{}\"><p>{}</p><ul>{}</ul></section>\n",
+            html_escape(&g.key()),
+            summary,
+            members.join("")
+        ));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_unreadable_best_image_has_no_thumbnail() {
+        let group = DuplicateGroup {
+            paths: vec!["/missing/a<1>.jpg".into(), "/missing/b.jpg".into()],
+            hash: "00".into(),
+            best_path: "/missing/a<1>.jpg".into(),
+            sidecars: BTreeMap::new(),
+            content_type: None,
+            member_hashes: BTreeMap::new(),
+            member_dimensions: BTreeMap::new(),
+        };
+        let options = ReportOptions {
+            thumbnail_size: Some(DEFAULT_THUMBNAIL_SIZE),
+            root: None,
+            all_members: false,
+        };
+
+        let groups = [group];
+        let json = render_report(&groups, ReportFormat::Json, &options);
+        assert!(json.contains("\"thumbnail\":null"));
+        let html = render_report(&groups, ReportFormat::Html, &options);
+        assert!(html.contains("<li class=\"best\">/missing/a&lt;1&gt;.jpg</li>"));
+        assert!(!html.contains("<img"));
+    }
+
+    #[test]
+    fn test_review_report_shows_every_member() {
+        let dir = std::env::temp_dir().join(format!("phash_rs_review_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["keep.png", "copy.png"]
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                DynamicImage::ImageRgb8(image::RgbImage::new(300, 200))
+                    .save(&path)
+                    .unwrap();
+                path
+            })
+            .collect();
+        let hash = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let group = DuplicateGroup {
+            paths: paths.clone(),
+            hash: "0000000000000000".into(),
+            best_path: paths[0].clone(),
+            sidecars: BTreeMap::new(),
+            content_type: None,
+            member_hashes: BTreeMap::from([
+                (paths[0].clone(), hash("0000000000000000")),
+                (paths[1].clone(), hash("0000000000000007")),
+            ]),
+            member_dimensions: BTreeMap::new(),
+        };
+
+        let report = dir.join("report.html");
+        generate_report(&[group], &report, None).unwrap();
+        let html = fs::read_to_string(&report).unwrap();
+        assert_eq!(
+            html.matches("<img src=\"data:image/jpeg;base64,").count(),
+            2
+        );
+        assert!(html.contains("<p>1 groups, 2 files, 1 proposed for removal</p>"));
+        assert!(html.contains("largest distance 3, mean 3.0"));
+        assert!(html.contains("<span class=\"distance\">distance 3</span>"));
+        assert!(html.contains("<li class=\"best\"><img"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,582 @@
+//! Scan Results Module
+//!
+//! Saves duplicate groups together with the settings they were found with
+//! and reads them back, so results can be kept and diffed between runs.
+//! JSON keeps everything in one document; CSV has one row per group
+//! member for spreadsheets and dataframe tools, with the settings in
+//! leading `#` comment lines. Paths that aren't valid UTF-8 survive both:
+//! JSON escapes their odd bytes and CSV files hold the bytes as they are.
+
+use crate::content::ContentType;
+use crate::duplicate::DuplicateGroup;
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::ipc::{group_fields, json_path, json_str};
+use crate::json::{self, Json};
+use crate::paths::{path_bytes, path_from_bytes};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Version written to and accepted from results files
+const RESULTS_VERSION: u64 = 1;
+
+/// First line of a CSV results file
+const CSV_HEADER: &str = "# phash_rs results v1";
+
+/// CSV column names, in order
+const CSV_COLUMNS: [&str; 8] = [
+    "key",
+    "path",
+    "best",
+    "hash",
+    "member_hash",
+    "distance",
+    "content_type",
+    "sidecars",
+];
+
+/// File format of exported results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultsFormat {
+    /// One JSON document with the metadata and all groups
+    Json,
+    /// One row per group member
+    Csv,
+}
+
+impl FromStr for ResultsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(ResultsFormat::Json),
+            "csv" => Ok(ResultsFormat::Csv),
+            _ => Err(format!("Unknown results format: {}", s)),
+        }
+    }
+}
+
+/// Settings a scan was run with; unknown ones are `None`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanMetadata {
+    pub algorithm: Option<HashAlgorithm>,
+    pub hash_size: Option<usize>,
+    pub threshold: Option<u32>,
+    /// Directory that relative group paths are relative to
+    pub root: Option<PathBuf>,
+    /// When the results were exported, in seconds since the Unix epoch
+    pub created: Option<u64>,
+}
+
+/// Duplicate groups together with the settings that produced them
+#[derive(Debug, Clone, Default)]
+pub struct ScanResults {
+    pub metadata: ScanMetadata,
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// Render `results` in `format`
+///
+/// Member hashes and their distances to the group hash are included for
+/// groups that have them. CSV paths that aren't valid UTF-8 are lossy in
+/// the returned text; `export_results` writes their bytes.
+pub fn render_results(results: &ScanResults, format: ResultsFormat) -> String {
+    match format {
+        ResultsFormat::Json => render_json(results),
+        ResultsFormat::Csv => String::from_utf8_lossy(&render_csv(results)).into_owned(),
+    }
+}
+
+/// Read results written by `render_results` in either format
+pub fn parse_results(text: &str) -> Result<ScanResults, String> {
+    parse_bytes(text.as_bytes())
+}
+
+fn parse_bytes(data: &[u8]) -> Result<ScanResults, String> {
+    if data.trim_ascii_start().starts_with(b"{") {
+        parse_json(std::str::from_utf8(data).map_err(|_| "Invalid UTF-8 in JSON")?)
+    } else {
+        parse_csv(data)
+    }
+}
+
+/// Write `results` to `path` in `format`
+pub fn export_results(
+    results: &ScanResults,
+    path: &Path,
+    format: ResultsFormat,
+) -> Result<(), String> {
+    let data = match format {
+        ResultsFormat::Json => render_json(results).into_bytes(),
+        ResultsFormat::Csv => render_csv(results),
+    };
+    fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Read results from a file written by `export_results`
+///
+/// The format is detected from the content.
+pub fn import_results(path: &Path) -> Result<ScanResults, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_bytes(&data).map_err(|e| format!("Invalid results file {}: {}", path.display(), e))
+}
+
+/// Metadata as (name, value) pairs, unknown values left out; the root is
+/// its path bytes
+fn metadata_fields(metadata: &ScanMetadata) -> Vec<(&'static str, Vec<u8>)> {
+    let mut fields = Vec::new();
+    if let Some(algorithm) = metadata.algorithm {
+        fields.push(("algorithm", algorithm.to_string().into_bytes()));
+    }
+    if let Some(hash_size) = metadata.hash_size {
+        fields.push(("hash_size", hash_size.to_string().into_bytes()));
+    }
+    if let Some(threshold) = metadata.threshold {
+        fields.push(("threshold", threshold.to_string().into_bytes()));
+    }
+    if let Some(root) = &metadata.root {
+        fields.push(("root", path_bytes(root).into_owned()));
+    }
+    if let Some(created) = metadata.created {
+        fields.push(("created", created.to_string().into_bytes()));
+    }
+    fields
+}
+
+/// Set the metadata field `name` from its value bytes
+fn set_metadata_field(metadata: &mut ScanMetadata, name: &str, value: &[u8]) -> Result<(), String> {
+    if name == "root" {
+        metadata.root = Some(path_from_bytes(value.to_vec()));
+        return Ok(());
+    }
+    let value = std::str::from_utf8(value).map_err(|_| format!("Invalid {}", name))?;
+    let number = |value: &str| {
+        value
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid {}: {}", name, value))
+    };
+    match name {
+        "algorithm" => metadata.algorithm = Some(value.parse()?),
+        "hash_size" => metadata.hash_size = Some(number(value)? as usize),
+        "threshold" => metadata.threshold = Some(number(value)? as u32),
+        "created" => metadata.created = Some(number(value)?),
+        // Written by a newer version; keep what we understand
+        _ => {}
+    }
+    Ok(())
+}
+
+fn render_json(results: &ScanResults) -> String {
+    let metadata: Vec<String> = metadata_fields(&results.metadata)
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match name {
+                "root" => json_path(&path_from_bytes(value)),
+                "algorithm" => json_str(&String::from_utf8_lossy(&value)),
+                _ => String::from_utf8_lossy(&value).into_owned(),
+            };
+            format!("{}:{}", json_str(name), value)
+        })
+        .collect();
+    let groups: Vec<String> = results
+        .groups
+        .iter()
+        .map(|g| {
+            let hashes: Vec<String> = g
+                .member_hashes
+                .iter()
+                .map(|(path, hash)| format!("{}:{}", json_path(path), json_str(&hash.to_hex())))
+                .collect();
+            let distances = match g.quality() {
+                Some(q) => {
+                    let distances: Vec<String> = q
+                        .distances
+                        .iter()
+                        .map(|(path, d)| format!("{}:{}", json_path(path), d))
+                        .collect();
+                    format!("{{{}}}", distances.join(","))
+                }
+                None => "null".to_string(),
+            };
+            format!(
+                "{{{},\"hashes\":{{{}}},\"distances\":{}}}",
+                group_fields(g),
+                hashes.join(","),
+                distances
+            )
+        })
+        .collect();
+    format!(
+        "{{\"version\":{},\"metadata\":{{{}}},\"groups\":[\n{}\n]}}\n",
+        RESULTS_VERSION,
+        metadata.join(","),
+        groups.join(",\n")
+    )
+}
+
+fn parse_json(text: &str) -> Result<ScanResults, String> {
+    let doc = json::parse(text)?;
+    let version = doc.get("version").and_then(Json::as_u64);
+    if version != Some(RESULTS_VERSION) {
+        return Err(format!("Unsupported results version: {:?}", version));
+    }
+
+    let mut metadata = ScanMetadata::default();
+    if let Some(fields) = doc.get("metadata").and_then(Json::as_object) {
+        for (name, value) in fields {
+            let value = match value {
+                Json::String(s) => s.clone(),
+                Json::Number(_) => value
+                    .as_u64()
+                    .map(|n| n.to_string().into_bytes())
+                    .unwrap_or_default(),
+                _ => continue,
+            };
+            if let Ok(name) = std::str::from_utf8(name) {
+                set_metadata_field(&mut metadata, name, &value)?;
+            }
+        }
+    }
+
+    let groups = doc
+        .get("groups")
+        .and_then(Json::as_array)
+        .ok_or("Missing \"groups\"")?
+        .iter()
+        .map(group_from_json)
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(ScanResults { metadata, groups })
+}
+
+fn group_from_json(group: &Json) -> Result<DuplicateGroup, String> {
+    let string = |name: &str| {
+        group
+            .get(name)
+            .and_then(Json::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| format!("Group is missing \"{}\"", name))
+    };
+    let paths = |value: &Json| -> Result<Vec<PathBuf>, String> {
+        value
+            .as_array()
+            .ok_or("Expected a list of strings")?
+            .iter()
+            .map(|s| s.as_path().ok_or("Expected a string".to_string()))
+            .collect()
+    };
+
+    let members = paths(group.get("paths").ok_or("Group is missing \"paths\"")?)?;
+    let mut sidecars = BTreeMap::new();
+    for (path, files) in group
+        .get("sidecars")
+        .and_then(Json::as_object)
+        .into_iter()
+        .flatten()
+    {
+        sidecars.insert(path_from_bytes(path.clone()), paths(files)?);
+    }
+    let content_type = group
+        .get("content_type")
+        .and_then(Json::as_str)
+        .map(str::parse::<ContentType>)
+        .transpose()?;
+    let mut member_hashes = BTreeMap::new();
+    for (path, hex) in group
+        .get("hashes")
+        .and_then(Json::as_object)
+        .into_iter()
+        .flatten()
+    {
+        let hex = hex.as_str().ok_or("Expected a hex hash")?;
+        member_hashes.insert(
+            path_from_bytes(path.clone()),
+            ImageHash::from_hex(hex).map_err(|e| e.to_string())?,
+        );
+    }
+    Ok(DuplicateGroup {
+        paths: members,
+        hash: string("hash")?,
+        best_path: group
+            .get("best")
+            .and_then(Json::as_path)
+            .ok_or("Group is missing \"best\"")?,
+        sidecars,
+        content_type,
+        member_hashes,
+        member_dimensions: BTreeMap::new(),
+    })
+}
+
+fn render_csv(results: &ScanResults) -> Vec<u8> {
+    let mut out = format!("{}\n", CSV_HEADER).into_bytes();
+    for (name, value) in metadata_fields(&results.metadata) {
+        out.extend_from_slice(format!("# {}=", name).as_bytes());
+        out.extend(value.iter().map(|&b| if b == b'\n' { b' ' } else { b }));
+        out.push(b'\n');
+    }
+    out.extend_from_slice(CSV_COLUMNS.join(",").as_bytes());
+    out.push(b'\n');
+    for g in &results.groups {
+        let key = g.key();
+        let distances = g.quality().map(|q| q.distances).unwrap_or_default();
+        let content_type = g.content_type.map(|t| t.to_string()).unwrap_or_default();
+        for path in &g.paths {
+            let row = [
+                key.clone().into_bytes(),
+                path_bytes(path).into_owned(),
+                (*path == g.best_path).to_string().into_bytes(),
+                g.hash.clone().into_bytes(),
+                g.member_hashes
+                    .get(path)
+                    .map(|h| h.to_hex().into_bytes())
+                    .unwrap_or_default(),
+                distances
+                    .get(path)
+                    .map(|d| d.to_string().into_bytes())
+                    .unwrap_or_default(),
+                content_type.clone().into_bytes(),
+                g.sidecars
+                    .get(path)
+                    .map(|s| {
+                        let sidecars: Vec<_> = s.iter().map(|p| path_bytes(p)).collect();
+                        sidecars.join(&b'\n')
+                    })
+                    .unwrap_or_default(),
+            ];
+            let row: Vec<Vec<u8>> = row.iter().map(|field| csv_field(field)).collect();
+            out.extend_from_slice(&row.join(&b','));
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+fn parse_csv(data: &[u8]) -> Result<ScanResults, String> {
+    let mut metadata = ScanMetadata::default();
+    let mut lines = data.split_inclusive(|&b| b == b'\n');
+    let header = lines.next().unwrap_or_default();
+    if header.trim_ascii() != CSV_HEADER.as_bytes() {
+        return Err("Not a results file".to_string());
+    }
+    // Metadata comments, then the column header and the rows
+    let mut offset = header.len();
+    for line in lines {
+        let Some(comment) = line.strip_prefix(b"#") else {
+            break;
+        };
+        let comment = comment.trim_ascii();
+        if let Some(eq) = comment.iter().position(|&b| b == b'=') {
+            let name = String::from_utf8_lossy(&comment[..eq]);
+            set_metadata_field(&mut metadata, name.trim(), &comment[eq + 1..])?;
+        }
+        offset += line.len();
+    }
+
+    let mut records = csv_records(&data[offset..])?.into_iter();
+    if !records.next().is_some_and(|r| {
+        r.iter()
+            .map(Vec::as_slice)
+            .eq(CSV_COLUMNS.map(str::as_bytes))
+    }) {
+        return Err(format!("Expected columns {}", CSV_COLUMNS.join(",")));
+    }
+    let text = |field: Vec<u8>| {
+        String::from_utf8(field).map_err(|_| "Invalid UTF-8 outside the path columns".to_string())
+    };
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    let mut by_key: BTreeMap<String, usize> = BTreeMap::new();
+    for record in records {
+        let [key, path, best, hash, member_hash, _distance, content_type, sidecars]: [Vec<u8>; 8] =
+            record
+                .try_into()
+                .map_err(|r: Vec<Vec<u8>>| format!("Expected 8 columns, got {}", r.len()))?;
+        let (key, hash, member_hash, content_type) = (
+            text(key)?,
+            text(hash)?,
+            text(member_hash)?,
+            text(content_type)?,
+        );
+        let index = *by_key.entry(key).or_insert_with(|| {
+            groups.push(DuplicateGroup {
+                paths: Vec::new(),
+                hash: hash.clone(),
+                best_path: PathBuf::new(),
+                sidecars: BTreeMap::new(),
+                content_type: None,
+                member_hashes: BTreeMap::new(),
+                member_dimensions: BTreeMap::new(),
+            });
+            groups.len() - 1
+        });
+        let group = &mut groups[index];
+        let path = path_from_bytes(path);
+        if !content_type.is_empty() {
+            group.content_type = Some(content_type.parse()?);
+        }
+        if best == b"true" {
+            group.best_path = path.clone();
+        }
+        if !member_hash.is_empty() {
+            let member_hash = ImageHash::from_hex(&member_hash).map_err(|e| e.to_string())?;
+            group.member_hashes.insert(path.clone(), member_hash);
+        }
+        if !sidecars.is_empty() {
+            group.sidecars.insert(
+                path.clone(),
+                sidecars
+                    .split(|&b| b == b'\n')
+                    .map(|p| path_from_bytes(p.to_vec()))
+                    .collect(),
+            );
+        }
+        group.paths.push(path);
+    }
+    Ok(ScanResults { metadata, groups })
+}
+
+/// `field` quoted for CSV if it needs to be
+fn csv_field(field: &[u8]) -> Vec<u8> {
+    if field.iter().any(|b| b",\"\n\r".contains(b)) {
+        let mut quoted = vec![b'"'];
+        for &b in field {
+            quoted.extend_from_slice(if b == b'"' {
+                b"\"\""
+            } else {
+                std::slice::from_ref(&b)
+            });
+        }
+        quoted.push(b'"');
+        quoted
+    } else {
+        field.to_vec()
+    }
+}
+
+/// Split CSV data into records of fields; quoted fields may span lines
+///
+/// Works on bytes, as the delimiters are ASCII, so path fields keep bytes
+/// that aren't valid UTF-8.
+fn csv_records(data: &[u8]) -> Result<Vec<Vec<Vec<u8>>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = Vec::new();
+    let mut quoted = false;
+    let mut bytes = data.iter().copied().peekable();
+    while let Some(c) = bytes.next() {
+        match (quoted, c) {
+            (true, b'"') if bytes.peek() == Some(&b'"') => {
+                bytes.next();
+                field.push(b'"');
+            }
+            (true, b'"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, b'"') if field.is_empty() => quoted = true,
+            (false, b',') => record.push(std::mem::take(&mut field)),
+            (false, b'\r') => {}
+            (false, b'\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ScanResults {
+        let h = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let group = DuplicateGroup {
+            paths: vec!["a, \"1\".jpg".into(), "b.jpg".into()],
+            hash: "0000000000000000".into(),
+            best_path: "b.jpg".into(),
+            sidecars: BTreeMap::from([("b.jpg".into(), vec!["b.xmp".into(), "b.aae".into()])]),
+            content_type: Some(ContentType::Photo),
+            member_hashes: BTreeMap::from([
+                ("a, \"1\".jpg".into(), h("0000000000000003")),
+                ("b.jpg".into(), h("0000000000000000")),
+            ]),
+            member_dimensions: BTreeMap::new(),
+        };
+        let no_hashes = DuplicateGroup {
+            paths: vec!["c.jpg".into(), "d.jpg".into()],
+            hash: "ffffffffffffffff".into(),
+            best_path: "c.jpg".into(),
+            sidecars: BTreeMap::new(),
+            content_type: None,
+            member_hashes: BTreeMap::new(),
+            member_dimensions: BTreeMap::new(),
+        };
+        ScanResults {
+            metadata: ScanMetadata {
+                algorithm: Some(HashAlgorithm::PHash),
+                hash_size: Some(8),
+                threshold: Some(10),
+                root: Some("/photos".into()),
+                created: Some(1_700_000_000),
+            },
+            groups: vec![group, no_hashes],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_both_formats() {
+        let results = sample();
+        for format in [ResultsFormat::Json, ResultsFormat::Csv] {
+            let text = render_results(&results, format);
+            let parsed = parse_results(&text).unwrap();
+            assert_eq!(parsed.metadata, results.metadata, "{:?}", format);
+            assert_eq!(parsed.groups.len(), 2);
+            for (a, b) in parsed.groups.iter().zip(&results.groups) {
+                assert_eq!(a.paths, b.paths);
+                assert_eq!(a.hash, b.hash);
+                assert_eq!(a.best_path, b.best_path);
+                assert_eq!(a.sidecars, b.sidecars);
+                assert_eq!(a.content_type, b.content_type);
+                assert_eq!(a.member_hashes, b.member_hashes);
+            }
+            assert_eq!(parsed.groups[0].quality().unwrap().max_distance, 2);
+        }
+        assert!(parse_results("key,path\n").is_err());
+        assert!(parse_results("{\"version\":99,\"groups\":[]}").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_paths_survive_export() {
+        use std::os::unix::ffi::OsStrExt;
+        let odd = |name: &[u8]| PathBuf::from(std::ffi::OsStr::from_bytes(name));
+        let mut results = sample();
+        results.metadata.root = Some(odd(b"/ph\xf6tos"));
+        let group = &mut results.groups[0];
+        group.paths[1] = odd(b"caf\xe9,\"1\".jpg");
+        group.best_path = group.paths[1].clone();
+        group.sidecars = BTreeMap::from([(group.paths[1].clone(), vec![odd(b"caf\xe9.xmp")])]);
+        group.member_hashes = BTreeMap::new();
+
+        let dir = std::env::temp_dir().join(format!("phash_rs_results_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for format in [ResultsFormat::Json, ResultsFormat::Csv] {
+            let path = dir.join("results");
+            export_results(&results, &path, format).unwrap();
+            let parsed = import_results(&path).unwrap();
+            assert_eq!(parsed.metadata, results.metadata, "{:?}", format);
+            let (a, b) = (&parsed.groups[0], &results.groups[0]);
+            assert_eq!(a.paths, b.paths);
+            assert_eq!(a.best_path, b.best_path);
+            assert_eq!(a.sidecars, b.sidecars);
+        }
+        fs::remove_dir_all(&dir).ok();
+    }
+}
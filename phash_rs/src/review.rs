@@ -0,0 +1,226 @@
+//! Review State Module
+//!
+//! Stores reviewer decisions for duplicate groups in a small text file next
+//! to the scan output, keyed by a stable group key so that later scans can
+//! carry decisions forward for groups whose members have not changed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// First line of a review file
+const REVIEW_FILE_HEADER: &str = "# phash_rs review v1";
+
+/// Reviewer decision for a duplicate group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewState {
+    Approved,
+    Rejected,
+    NeedsReview,
+}
+
+impl ReviewState {
+    /// Stable name used in review files and the Python bindings
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReviewState::Approved => "approved",
+            ReviewState::Rejected => "rejected",
+            ReviewState::NeedsReview => "needs-review",
+        }
+    }
+}
+
+impl FromStr for ReviewState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "approved" => Ok(ReviewState::Approved),
+            "rejected" => Ok(ReviewState::Rejected),
+            "needs-review" => Ok(ReviewState::NeedsReview),
+            _ => Err(format!("Unknown review state: {}", s)),
+        }
+    }
+}
+
+/// A reviewer's decision and free-text note for one group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub state: ReviewState,
+    pub note: String,
+}
+
+/// Stable key for a group, derived from its sorted member paths
+///
+/// The key only changes when a file joins or leaves the group, which is
+/// what "unchanged group" means for carrying decisions forward.
+pub fn group_key(paths: &[PathBuf]) -> String {
+    // Raw bytes, so UTF-8 paths keep the keys they had as strings
+    let mut sorted: Vec<&[u8]> = paths
+        .iter()
+        .map(|p| p.as_os_str().as_encoded_bytes())
+        .collect();
+    sorted.sort_unstable();
+
+    // FNV-1a, 64-bit: fixed and dependency-free, so keys stay stable
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for path in sorted {
+        for &byte in path.iter().chain(std::iter::once(&0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Collection of annotations keyed by group key
+#[derive(Debug, Clone, Default)]
+pub struct ReviewStore {
+    annotations: BTreeMap<String, Annotation>,
+}
+
+impl ReviewStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a review file, or return an empty store if it does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read review file {}: {}", path.display(), e))?;
+        let mut lines = content.lines();
+        if lines.next() != Some(REVIEW_FILE_HEADER) {
+            return Err(format!("Not a review file: {}", path.display()));
+        }
+
+        let mut annotations = BTreeMap::new();
+        for (n, line) in lines.enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(3, '\t');
+            let (key, state, note) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(k), Some(s), Some(n)) => (k, s, n),
+                _ => return Err(format!("Malformed review line {}", n + 2)),
+            };
+            let annotation = Annotation {
+                state: state.parse()?,
+                note: unescape(note),
+            };
+            annotations.insert(key.to_string(), annotation);
+        }
+
+        Ok(Self { annotations })
+    }
+
+    /// Write the store, replacing the file atomically
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut content = String::from(REVIEW_FILE_HEADER);
+        content.push('\n');
+        for (key, annotation) in &self.annotations {
+            content.push_str(&format!(
+                "{}\t{}\t{}\n",
+                key,
+                annotation.state.name(),
+                escape(&annotation.note)
+            ));
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| format!("Failed to write review file {}: {}", path.display(), e))
+    }
+
+    /// Set the annotation for a group key
+    pub fn annotate(&mut self, key: &str, state: ReviewState, note: &str) {
+        self.annotations.insert(
+            key.to_string(),
+            Annotation {
+                state,
+                note: note.to_string(),
+            },
+        );
+    }
+
+    /// Annotation for a group key, if any
+    pub fn get(&self, key: &str) -> Option<&Annotation> {
+        self.annotations.get(key)
+    }
+
+    /// Remove the annotation for a group key
+    pub fn remove(&mut self, key: &str) -> Option<Annotation> {
+        self.annotations.remove(key)
+    }
+
+    /// Number of stored annotations
+    pub fn len(&self) -> usize {
+        self.annotations.len()
+    }
+
+    /// Whether the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_key_ignores_order() {
+        let a = group_key(&[PathBuf::from("x.jpg"), PathBuf::from("y.jpg")]);
+        let b = group_key(&[PathBuf::from("y.jpg"), PathBuf::from("x.jpg")]);
+        let c = group_key(&[PathBuf::from("x.jpg"), PathBuf::from("z.jpg")]);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let path = std::env::temp_dir().join(format!("phash_rs_review_{}.tsv", std::process::id()));
+        let mut store = ReviewStore::new();
+        store.annotate("abc", ReviewState::Approved, "keep\tthe\nRAW \\ one");
+        store.annotate("def", ReviewState::NeedsReview, "");
+        store.save(&path).unwrap();
+
+        let loaded = ReviewStore::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.get("abc"), store.get("abc"));
+        assert_eq!(loaded.get("def").unwrap().state, ReviewState::NeedsReview);
+    }
+}
@@ -0,0 +1,95 @@
+//! Seeded Randomness Module
+//!
+//! Every randomized step in the library (sampling, projections, partitioning)
+//! draws from `SeededRng`, so re-running with the same inputs and seed
+//! reproduces the same output bit-for-bit, independent of platform and
+//! thread count.
+//!
+//! The generator is SplitMix64 and is part of the public contract: changing
+//! it would change results for existing seeds and is treated as breaking.
+
+/// Seed used when the caller does not supply one
+pub const DEFAULT_SEED: u64 = 0x5EED_5EED_5EED_5EED;
+
+/// Small deterministic PRNG (SplitMix64)
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    /// Create a generator from an explicit seed
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in `0..bound` (bound must be non-zero)
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // Rejection sampling keeps the distribution exactly uniform
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let v = self.next_u64();
+            if v < zone {
+                return v % bound;
+            }
+        }
+    }
+
+    /// Fisher-Yates shuffle
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+
+    /// `k` distinct indices from `0..n`, returned in ascending order
+    ///
+    /// Returns all indices when `k >= n`.
+    pub fn sample_indices(&mut self, n: usize, k: usize) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..n).collect();
+        if k < n {
+            // Partial Fisher-Yates: only the first k slots are needed
+            for i in 0..k {
+                let j = i + self.below((n - i) as u64) as usize;
+                indices.swap(i, j);
+            }
+            indices.truncate(k);
+        }
+        indices.sort_unstable();
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = SeededRng::new(42);
+        let mut b = SeededRng::new(42);
+        let xs: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let ys: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs[0], SeededRng::new(43).next_u64());
+    }
+
+    #[test]
+    fn test_sample_indices() {
+        let sample = SeededRng::new(DEFAULT_SEED).sample_indices(100, 10);
+        assert_eq!(sample.len(), 10);
+        assert!(sample.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(sample, SeededRng::new(DEFAULT_SEED).sample_indices(100, 10));
+        assert_eq!(SeededRng::new(1).sample_indices(3, 10), vec![0, 1, 2]);
+    }
+}
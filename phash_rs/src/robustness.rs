@@ -0,0 +1,267 @@
+//! Robustness Module
+//!
+//! How far a hash moves under the edits copies typically go through:
+//! resizing, recompression, a slight rotation, exposure changes and a
+//! small crop. Running it over images typical of a collection shows which
+//! algorithm holds still for that content and how loose the threshold has
+//! to be to still catch such copies.
+
+use crate::decode::open_image;
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, ImageHash};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+use rayon::prelude::*;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// JPEG quality of the recompressed variant
+const JPEG_QUALITY: u8 = 50;
+/// Rotation of the rotated variant, in degrees
+const ROTATION_DEGREES: f64 = 1.0;
+/// Exposure change of the brightened and darkened variants
+const BRIGHTNESS_CHANGE: f32 = 0.1;
+/// Share of each dimension removed by the cropped variant, half from each
+/// side
+const CROP_FRACTION: f64 = 0.05;
+
+/// A standard edit applied to test a hash's stability
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Perturbation {
+    /// Downscaled to half the width and height
+    Resize,
+    /// Re-encoded as JPEG at quality 50
+    Jpeg,
+    /// Rotated by 1 degree about the center, corners filled black
+    Rotate,
+    /// Every channel scaled up by 10%
+    Brighten,
+    /// Every channel scaled down by 10%
+    Darken,
+    /// 5% of the width and height cropped away, evenly from both sides
+    Crop,
+}
+
+impl Perturbation {
+    /// All perturbations, in report order
+    pub const ALL: [Perturbation; 6] = [
+        Perturbation::Resize,
+        Perturbation::Jpeg,
+        Perturbation::Rotate,
+        Perturbation::Brighten,
+        Perturbation::Darken,
+        Perturbation::Crop,
+    ];
+
+    /// Apply the edit
+    pub fn apply(self, img: &DynamicImage) -> DynamicImage {
+        match self {
+            Perturbation::Resize => {
+                let (w, h) = img.dimensions();
+                img.resize_exact((w / 2).max(1), (h / 2).max(1), FilterType::Lanczos3)
+            }
+            Perturbation::Jpeg => recompress(img),
+            Perturbation::Rotate => rotate(img, ROTATION_DEGREES),
+            Perturbation::Brighten => scale_brightness(img, 1.0 + BRIGHTNESS_CHANGE),
+            Perturbation::Darken => scale_brightness(img, 1.0 - BRIGHTNESS_CHANGE),
+            Perturbation::Crop => {
+                let (w, h) = img.dimensions();
+                let dx = (w as f64 * CROP_FRACTION / 2.0).round() as u32;
+                let dy = (h as f64 * CROP_FRACTION / 2.0).round() as u32;
+                img.crop_imm(dx, dy, w - 2 * dx, h - 2 * dy)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Perturbation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Perturbation::Resize => "resize_50",
+            Perturbation::Jpeg => "jpeg_50",
+            Perturbation::Rotate => "rotate_1",
+            Perturbation::Brighten => "brighten_10",
+            Perturbation::Darken => "darken_10",
+            Perturbation::Crop => "crop_5",
+        })
+    }
+}
+
+impl FromStr for Perturbation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Perturbation::ALL
+            .into_iter()
+            .find(|p| p.to_string() == s.trim().to_ascii_lowercase())
+            .ok_or_else(|| {
+                let names: Vec<String> = Perturbation::ALL.iter().map(|p| p.to_string()).collect();
+                format!(
+                    "Unknown perturbation: {} (expected {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Hash distances of an image's perturbed variants
+#[derive(Debug, Clone, PartialEq)]
+pub struct RobustnessReport {
+    /// Hash of the unmodified image
+    pub hash: ImageHash,
+    /// Distance from `hash` to the hash of each variant, in the order of
+    /// `Perturbation::ALL`
+    pub distances: Vec<(Perturbation, u32)>,
+}
+
+impl RobustnessReport {
+    /// Largest distance over all variants: the smallest threshold that
+    /// still matches every one of them
+    pub fn max_distance(&self) -> u32 {
+        self.distances.iter().map(|&(_, d)| d).max().unwrap_or(0)
+    }
+}
+
+/// Hash an image file and each of its perturbed variants (see
+/// `robustness_of_image`)
+pub fn robustness_report<P: AsRef<Path>>(
+    path: P,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+) -> Result<RobustnessReport, HashError> {
+    robustness_of_image(&open_image(path.as_ref())?, algorithm, hash_size)
+}
+
+/// Hash an image and each variant in `Perturbation::ALL`, and report how
+/// far each variant's hash is from the original's
+pub fn robustness_of_image(
+    img: &DynamicImage,
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+) -> Result<RobustnessReport, HashError> {
+    let hash = ImageHash::from_image(img, algorithm, hash_size)?;
+    let distances = Perturbation::ALL
+        .par_iter()
+        .map(|&p| {
+            let variant = ImageHash::from_image(&p.apply(img), algorithm, hash_size)?;
+            Ok((p, hash.distance(&variant)))
+        })
+        .collect::<Result<Vec<_>, HashError>>()?;
+    Ok(RobustnessReport { hash, distances })
+}
+
+/// Round trip through a quality-50 JPEG
+fn recompress(img: &DynamicImage) -> DynamicImage {
+    let rgb = img.to_rgb8();
+    let mut data = Vec::new();
+    let encoded = JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY).encode_image(&rgb);
+    match encoded.and_then(|_| image::load_from_memory(&data)) {
+        Ok(decoded) => decoded,
+        // Encoding into memory only fails for images JPEG cannot hold
+        Err(_) => DynamicImage::ImageRgb8(rgb),
+    }
+}
+
+/// Rotate by `degrees` about the center on a canvas of the same size,
+/// sampling bilinearly
+fn rotate(img: &DynamicImage, degrees: f64) -> DynamicImage {
+    let src = img.to_rgb8();
+    let (w, h) = src.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = ((w as f64 - 1.0) / 2.0, (h as f64 - 1.0) / 2.0);
+    let pixel = |x: i64, y: i64| -> [f64; 3] {
+        if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+            return [0.0; 3];
+        }
+        let Rgb(p) = *src.get_pixel(x as u32, y as u32);
+        [p[0] as f64, p[1] as f64, p[2] as f64]
+    };
+    DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| {
+        // Source position of this pixel under the inverse rotation
+        let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+        let sx = cos * dx - sin * dy + cx;
+        let sy = sin * dx + cos * dy + cy;
+        let (x0, y0) = (sx.floor(), sy.floor());
+        let (fx, fy) = (sx - x0, sy - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let corners = [
+            (pixel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+            (pixel(x0 + 1, y0), fx * (1.0 - fy)),
+            (pixel(x0, y0 + 1), (1.0 - fx) * fy),
+            (pixel(x0 + 1, y0 + 1), fx * fy),
+        ];
+        let mut out = [0u8; 3];
+        for (c, value) in out.iter_mut().enumerate() {
+            let v: f64 = corners.iter().map(|(p, weight)| p[c] * weight).sum();
+            *value = v.round().clamp(0.0, 255.0) as u8;
+        }
+        Rgb(out)
+    }))
+}
+
+/// Multiply every channel by `factor`, clamped to the 8-bit range
+fn scale_brightness(img: &DynamicImage, factor: f32) -> DynamicImage {
+    let mut rgb = img.to_rgb8();
+    for value in rgb.iter_mut() {
+        *value = (*value as f32 * factor).round().clamp(0.0, 255.0) as u8;
+    }
+    DynamicImage::ImageRgb8(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smooth shading with a few large shapes, like a photo's layout
+    fn scene() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(256, 192, |x, y| {
+            let sun = (x as i32 - 180).pow(2) + (y as i32 - 60).pow(2) < 900;
+            let ground = y > 120 + x / 8;
+            match (sun, ground) {
+                (true, _) => Rgb([250, 220, 90]),
+                (_, true) => Rgb([60, 120 + (x / 4) as u8, 40]),
+                _ => Rgb([90, 140, 200 + (y / 4) as u8]),
+            }
+        }))
+    }
+
+    #[test]
+    fn test_perturbations_keep_hash_close() {
+        let report = robustness_of_image(&scene(), HashAlgorithm::PHash, 8).unwrap();
+        let names: Vec<String> = report
+            .distances
+            .iter()
+            .map(|(p, _)| p.to_string())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "resize_50",
+                "jpeg_50",
+                "rotate_1",
+                "brighten_10",
+                "darken_10",
+                "crop_5"
+            ]
+        );
+        assert!(report.max_distance() <= 12, "{:?}", report.distances);
+        // A different picture is much further away than any variant
+        let other = ImageHash::from_image(&scene().fliph(), HashAlgorithm::PHash, 8).unwrap();
+        assert!(report.hash.distance(&other) > report.max_distance());
+    }
+
+    #[test]
+    fn test_perturbation_geometry() {
+        let img = scene();
+        assert_eq!(Perturbation::Resize.apply(&img).dimensions(), (128, 96));
+        assert_eq!(Perturbation::Crop.apply(&img).dimensions(), (244, 182));
+        assert_eq!(Perturbation::Rotate.apply(&img).dimensions(), (256, 192));
+        let bright = Perturbation::Brighten.apply(&img).to_rgb8();
+        assert_eq!(bright.get_pixel(0, 0), &Rgb([99, 154, 220]));
+        assert_eq!("crop_5".parse::<Perturbation>(), Ok(Perturbation::Crop));
+        assert!("blur".parse::<Perturbation>().is_err());
+    }
+}
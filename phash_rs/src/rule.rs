@@ -0,0 +1,269 @@
+//! Match Rule Module
+//!
+//! Combines thresholds on several hash algorithms into one match decision,
+//! e.g. `phash<=10 AND whash<=12`. Requiring two algorithms to agree cuts
+//! false positives that either one produces alone; accepting either one
+//! catches edits that only one of them tolerates.
+//!
+//! Between the two, `2 OF phash<=10, whash<=10, blockhash<=10` is a
+//! majority vote: an edit one algorithm is blind to is outvoted by the
+//! other two. That rule is the ensemble behind `algorithm="ensemble"` in
+//! the Python bindings.
+
+use crate::decode::open_image;
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, ImageHash, DEFAULT_HASH_SIZE};
+use image::DynamicImage;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How the criteria of a rule are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    /// Every criterion must hold
+    All,
+    /// At least one criterion must hold
+    Any,
+    /// At least this many criteria must hold
+    AtLeast(usize),
+}
+
+/// Algorithms voting in `MatchRule::ensemble`
+pub const ENSEMBLE_ALGORITHMS: [HashAlgorithm; 3] = [
+    HashAlgorithm::PHash,
+    HashAlgorithm::WHash { level: None },
+    HashAlgorithm::BlockHash,
+];
+
+/// `algorithm <= threshold`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Criterion {
+    pub algorithm: HashAlgorithm,
+    pub threshold: u32,
+}
+
+/// Criteria on one or more hash algorithms and how they combine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchRule {
+    criteria: Vec<Criterion>,
+    combine: Combine,
+}
+
+impl MatchRule {
+    /// Build a rule; each algorithm may appear only once
+    pub fn new(criteria: Vec<Criterion>, combine: Combine) -> Result<Self, String> {
+        if criteria.is_empty() {
+            return Err("Match rule has no criteria".to_string());
+        }
+        if let Combine::AtLeast(n) = combine {
+            if !(1..=criteria.len()).contains(&n) {
+                return Err(format!(
+                    "Match rule needs 1 to {} of its criteria, got {}",
+                    criteria.len(),
+                    n
+                ));
+            }
+        }
+        for (i, c) in criteria.iter().enumerate() {
+            if criteria[..i].iter().any(|o| o.algorithm == c.algorithm) {
+                return Err(format!("{} appears twice in match rule", c.algorithm));
+            }
+        }
+        Ok(Self { criteria, combine })
+    }
+
+    pub fn criteria(&self) -> &[Criterion] {
+        &self.criteria
+    }
+
+    pub fn combine(&self) -> Combine {
+        self.combine
+    }
+
+    /// Majority vote of pHash, wHash and blockHash, each within `threshold`
+    ///
+    /// All three give 64-bit hashes at the default hash size, so one
+    /// threshold means the same to each.
+    pub fn ensemble(threshold: u32) -> Self {
+        let criteria = ENSEMBLE_ALGORITHMS
+            .iter()
+            .map(|&algorithm| Criterion {
+                algorithm,
+                threshold,
+            })
+            .collect();
+        Self {
+            criteria,
+            combine: Combine::AtLeast(2),
+        }
+    }
+
+    /// Number of criteria a matching pair must meet
+    pub fn required(&self) -> usize {
+        match self.combine {
+            Combine::All => self.criteria.len(),
+            Combine::Any => 1,
+            Combine::AtLeast(n) => n,
+        }
+    }
+
+    /// Number of criteria two images meet, given their hashes in criteria
+    /// order
+    pub fn votes(&self, a: &[ImageHash], b: &[ImageHash]) -> usize {
+        self.criteria
+            .iter()
+            .zip(a.iter().zip(b))
+            .filter(|(c, (a, b))| a.distance(b) <= c.threshold)
+            .count()
+    }
+
+    /// Whether two images match, given their hashes in criteria order
+    pub fn matches(&self, a: &[ImageHash], b: &[ImageHash]) -> bool {
+        self.votes(a, b) >= self.required()
+    }
+
+    /// Hash `img` with each algorithm of the rule, in criteria order, at
+    /// the default hash size
+    pub fn hash_image(&self, img: &DynamicImage) -> Result<Vec<ImageHash>, HashError> {
+        self.criteria
+            .iter()
+            .map(|c| ImageHash::from_image(img, c.algorithm, DEFAULT_HASH_SIZE))
+            .collect()
+    }
+
+    /// Decode the image at `path` once and run `hash_image`
+    pub fn hash_path(&self, path: &Path) -> Result<Vec<ImageHash>, HashError> {
+        self.hash_image(&open_image(path)?)
+    }
+}
+
+impl FromStr for MatchRule {
+    type Err = String;
+
+    /// Parse e.g. "phash<=10 AND whash<=12", "phash<=8 or colorhash<=20"
+    /// or "2 OF phash<=10, whash<=10, blockhash<=10"
+    ///
+    /// AND and OR cannot be mixed in one rule.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut head = s.trim_start().splitn(3, char::is_whitespace);
+        if let (Some(count), Some(of), Some(terms)) = (head.next(), head.next(), head.next()) {
+            if of.eq_ignore_ascii_case("of") {
+                let count = count
+                    .parse()
+                    .map_err(|_| format!("Invalid count in match rule: {}", s))?;
+                let criteria = terms
+                    .split(',')
+                    .map(|term| parse_criterion(&term.split_whitespace().collect::<String>()))
+                    .collect::<Result<Vec<_>, String>>()?;
+                return MatchRule::new(criteria, Combine::AtLeast(count));
+            }
+        }
+
+        let words: Vec<String> = s.split_whitespace().map(str::to_ascii_lowercase).collect();
+        let mut combine = None;
+        let mut terms = vec![String::new()];
+        for word in words {
+            let op = match word.as_str() {
+                "and" => Combine::All,
+                "or" => Combine::Any,
+                _ => {
+                    if let Some(term) = terms.last_mut() {
+                        term.push_str(&word);
+                    }
+                    continue;
+                }
+            };
+            if combine.is_some_and(|c| c != op) {
+                return Err(format!("Cannot mix AND and OR in match rule: {}", s));
+            }
+            combine = Some(op);
+            terms.push(String::new());
+        }
+
+        let criteria = terms
+            .iter()
+            .map(|term| parse_criterion(term))
+            .collect::<Result<Vec<_>, String>>()?;
+        MatchRule::new(criteria, combine.unwrap_or(Combine::All))
+    }
+}
+
+/// Parse one "algorithm<=threshold" term, whitespace removed
+fn parse_criterion(term: &str) -> Result<Criterion, String> {
+    let (algorithm, threshold) = term
+        .split_once("<=")
+        .ok_or_else(|| format!("Expected \"algorithm<=threshold\", got \"{}\"", term))?;
+    Ok(Criterion {
+        algorithm: algorithm.to_ascii_lowercase().parse()?,
+        threshold: threshold
+            .parse()
+            .map_err(|_| format!("Invalid threshold in \"{}\"", term))?,
+    })
+}
+
+impl fmt::Display for MatchRule {
+    /// Canonical form, accepted back by `FromStr`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms: Vec<String> = self
+            .criteria
+            .iter()
+            .map(|c| format!("{}<={}", c.algorithm, c.threshold))
+            .collect();
+        match self.combine {
+            Combine::All => write!(f, "{}", terms.join(" AND ")),
+            Combine::Any => write!(f, "{}", terms.join(" OR ")),
+            Combine::AtLeast(n) => write!(f, "{} OF {}", n, terms.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_match() {
+        let rule: MatchRule = "phash <= 4 AND whash<=2".parse().unwrap();
+        assert_eq!(rule.to_string(), "phash<=4 AND whash<=2");
+        assert_eq!(rule.to_string().parse::<MatchRule>(), Ok(rule.clone()));
+
+        let h = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let a = [h("0000000000000000"), h("0000000000000000")];
+        let b = [h("0000000000000003"), h("0000000000000007")];
+        assert!(!rule.matches(&a, &b));
+        let any: MatchRule = "phash<=4 or whash<=2".parse().unwrap();
+        assert!(any.matches(&a, &b));
+
+        assert!("phash<=4 and whash<=2 or colorhash<=9"
+            .parse::<MatchRule>()
+            .is_err());
+        assert!("phash<=4 and phash<=2".parse::<MatchRule>().is_err());
+        assert!("phash<4".parse::<MatchRule>().is_err());
+        assert!("".parse::<MatchRule>().is_err());
+    }
+
+    #[test]
+    fn test_ensemble_votes() {
+        let rule = MatchRule::ensemble(4);
+        assert_eq!(rule.to_string(), "2 OF phash<=4, whash<=4, blockhash<=4");
+        assert_eq!(rule.to_string().parse::<MatchRule>(), Ok(rule.clone()));
+        assert_eq!(
+            "2 of PHash <= 4,whash<=4 , blockhash<=4".parse::<MatchRule>(),
+            Ok(rule.clone())
+        );
+
+        let h = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let a = [h("00"), h("00"), h("00")];
+        // pHash and blockHash agree, wHash does not
+        let b = [h("03"), h("ff"), h("0f")];
+        assert_eq!(rule.votes(&a, &b), 2);
+        assert!(rule.matches(&a, &b));
+        let c = [h("ff"), h("ff"), h("0f")];
+        assert!(!rule.matches(&a, &c));
+
+        assert!("0 of phash<=4".parse::<MatchRule>().is_err());
+        assert!("3 of phash<=4, whash<=4".parse::<MatchRule>().is_err());
+        assert!("two of phash<=4, whash<=4".parse::<MatchRule>().is_err());
+    }
+}
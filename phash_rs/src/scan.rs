@@ -0,0 +1,177 @@
+//! Directory Scanning Module
+//!
+//! Discovers image files under a directory so callers can hand a single root
+//! to the library instead of walking the tree themselves and passing every
+//! path across the FFI boundary.
+
+use crate::decode::{decoder_config, normalize_ext};
+use crate::progress::Progress;
+use crate::warnings::{Warning, WarningKind};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use walkdir::WalkDir;
+
+/// Options controlling which files `scan_directory` returns
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Descend into subdirectories
+    pub recursive: bool,
+    /// Extensions to include (case-insensitive, leading dot optional).
+    /// `None` includes every extension some decoder backend can open.
+    pub extensions: Option<Vec<String>>,
+    /// Follow symbolic links to files and directories
+    pub follow_symlinks: bool,
+    /// Files and directories to skip, e.g. quarantine folders from an
+    /// earlier cleanup. Relative paths are taken relative to the scan root;
+    /// comparison is lexical, as for `relative_path`.
+    pub exclude: Vec<PathBuf>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            recursive: true,
+            extensions: None,
+            follow_symlinks: false,
+            exclude: Vec::new(),
+        }
+    }
+}
+
+/// Find image files under `root`, sorted by path
+///
+/// Entries that cannot be read (permission errors, symlink loops) are
+/// skipped rather than aborting the scan; use `scan_directory_with` to be
+/// told about them.
+pub fn scan_directory<P: AsRef<Path>>(
+    root: P,
+    options: &ScanOptions,
+) -> Result<Vec<PathBuf>, String> {
+    scan_directory_with(root, options, None)
+}
+
+/// Like `scan_directory`, reporting skipped entries to `progress` as
+/// warnings
+pub fn scan_directory_with<P: AsRef<Path>>(
+    root: P,
+    options: &ScanOptions,
+    progress: Option<&dyn Progress>,
+) -> Result<Vec<PathBuf>, String> {
+    let root = root.as_ref();
+    if !root.is_dir() {
+        return Err(format!("Not a directory: {}", root.display()));
+    }
+
+    let started = Instant::now();
+    let extensions = extension_set(options);
+
+    let exclude: Vec<PathBuf> = options.exclude.iter().map(|p| root.join(p)).collect();
+    let walker = WalkDir::new(root)
+        .follow_links(options.follow_symlinks)
+        .max_depth(if options.recursive { usize::MAX } else { 1 })
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| !exclude.iter().any(|x| e.path() == x));
+
+    let mut paths = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let warning = Warning::new(
+                    WarningKind::SkippedFile,
+                    e.path().unwrap_or(root),
+                    e.to_string(),
+                );
+                warning.log();
+                if let Some(progress) = progress {
+                    progress.warning(&warning);
+                }
+                continue;
+            }
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let matches = entry
+            .path()
+            .extension()
+            .is_some_and(|e| extensions.contains(&normalize_ext(&e.to_string_lossy())));
+        if matches {
+            paths.push(entry.into_path());
+        }
+    }
+
+    paths.sort();
+    tracing::info!(
+        root = %root.display(),
+        files = paths.len(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "scanned"
+    );
+    Ok(paths)
+}
+
+/// Normalized extensions `options` selects
+pub(crate) fn extension_set(options: &ScanOptions) -> HashSet<String> {
+    match &options.extensions {
+        Some(exts) => exts.iter().map(|e| normalize_ext(e)).collect(),
+        None => decoder_config()
+            .supported_formats()
+            .into_iter()
+            .filter(|(_, backends)| !backends.is_empty())
+            .map(|(ext, _)| ext)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::relative_path;
+    use std::fs;
+
+    #[test]
+    fn test_scan_recursion_and_extensions() {
+        let root = std::env::temp_dir().join(format!("phash_rs_scan_{}", std::process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join("Duplicates")).unwrap();
+        for name in [
+            "a.JPG",
+            "b.png",
+            "notes.txt",
+            "sub/c.jpg",
+            "Duplicates/d.jpg",
+        ] {
+            fs::write(root.join(name), b"").unwrap();
+        }
+
+        let name = |p: &PathBuf| relative_path(p, &root).to_string_lossy().into_owned();
+        let options = ScanOptions {
+            extensions: Some(vec![".jpg".to_string()]),
+            exclude: vec![PathBuf::from("Duplicates")],
+            ..ScanOptions::default()
+        };
+        let found: Vec<String> = scan_directory(&root, &options)
+            .unwrap()
+            .iter()
+            .map(name)
+            .collect();
+        assert_eq!(found, vec!["a.JPG", "sub/c.jpg"]);
+
+        let flat = ScanOptions {
+            recursive: false,
+            ..options
+        };
+        let found: Vec<String> = scan_directory(&root, &flat)
+            .unwrap()
+            .iter()
+            .map(name)
+            .collect();
+        assert_eq!(found, vec!["a.JPG"]);
+
+        assert!(scan_directory(root.join("notes.txt"), &flat).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Similarity Search Module
+//!
+//! Ranks a corpus by distance to one query image: the "where else is this
+//! photo in my library" lookup. Grouping compares every image with every
+//! other; a search hashes the query once and compares it with each
+//! candidate. `match_collections` searches a whole collection this way,
+//! e.g. new imports against an archive.
+
+use crate::cache::{hash_paths, HashCache};
+use crate::duplicate::primaries_only;
+use crate::error::{HashError, HashFailures};
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::index::BkTree;
+use crate::progress::Progress;
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+/// A candidate within the threshold of the query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarMatch {
+    pub path: PathBuf,
+    pub distance: u32,
+}
+
+/// An image of collection A within the threshold of one in collection B
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionMatch {
+    pub path_a: PathBuf,
+    pub path_b: PathBuf,
+    pub distance: u32,
+}
+
+/// Rank `candidates` by distance to `query`
+///
+/// Returns the candidates within `threshold`, closest first and ties by
+/// path, cut to `max_results` if given. Fails if a candidate hash has a
+/// different size than the query, which means it was computed with other
+/// settings.
+pub fn find_similar(
+    query: &ImageHash,
+    candidates: &[(PathBuf, ImageHash)],
+    threshold: u32,
+    max_results: Option<usize>,
+) -> Result<Vec<SimilarMatch>, HashError> {
+    if let Some((path, hash)) = candidates
+        .iter()
+        .find(|(_, hash)| hash.bit_len() != query.bit_len())
+    {
+        return Err(HashError::InvalidInput(format!(
+            "Hash of {} has {} bits, the query hash {}",
+            path.display(),
+            hash.bit_len(),
+            query.bit_len()
+        )));
+    }
+
+    let mut matches: Vec<SimilarMatch> = candidates
+        .par_iter()
+        .filter_map(|(path, hash)| {
+            let distance = query.distance(hash);
+            (distance <= threshold).then(|| SimilarMatch {
+                path: path.clone(),
+                distance,
+            })
+        })
+        .collect();
+    matches.sort_unstable_by(|a, b| (a.distance, &a.path).cmp(&(b.distance, &b.path)));
+    if let Some(max_results) = max_results {
+        matches.truncate(max_results);
+    }
+    Ok(matches)
+}
+
+/// Hash `paths` with `algorithm` and rank them by distance to `query`
+///
+/// Sidecar files are skipped. Fresh hashes are added to `cache`; files
+/// that fail to hash are returned with the matches, sorted by path.
+/// Returns an error if `progress` cancels.
+#[allow(clippy::too_many_arguments)]
+pub fn find_similar_paths(
+    query: &ImageHash,
+    paths: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+    max_results: Option<usize>,
+    cache: Option<&mut HashCache>,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<SimilarMatch>, HashFailures), String> {
+    algorithm
+        .check_hash_size(hash_size)
+        .map_err(|e| e.to_string())?;
+    let mut candidates = Vec::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in hash_paths(
+        &primaries_only(paths),
+        algorithm,
+        hash_size,
+        cache,
+        progress,
+    ) {
+        match result {
+            Ok(cached) => candidates.push((path, cached.hash)),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+    let matches =
+        find_similar(query, &candidates, threshold, max_results).map_err(|e| e.to_string())?;
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((matches, failures))
+}
+
+/// Match every image of `paths_a` against the images of `paths_b`
+///
+/// Only A-B pairs are compared, never two images of the same collection:
+/// B is indexed once and each A image is a query against it. Returns the
+/// pairs within `threshold`, ordered by A path, then distance, then B
+/// path, together with the files of either list that failed to hash,
+/// sorted by path. Sidecar files are skipped. Returns an error if
+/// `progress` cancels.
+pub fn match_collections(
+    paths_a: &[PathBuf],
+    paths_b: &[PathBuf],
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<CollectionMatch>, HashFailures), String> {
+    algorithm
+        .check_hash_size(hash_size)
+        .map_err(|e| e.to_string())?;
+    let paths_b = primaries_only(paths_b);
+    let mut all = paths_b.clone();
+    all.extend(primaries_only(paths_a));
+
+    let results = hash_paths(&all, algorithm, hash_size, None, progress);
+    if progress.is_some_and(|p| p.is_cancelled()) {
+        return Err("Cancelled".to_string());
+    }
+
+    // hash_paths keeps input order, so the first paths_b.len() results are B
+    let mut hashes_a = Vec::new();
+    let mut hashes_b = Vec::new();
+    let mut failures = HashFailures::new();
+    for (i, (path, result)) in results.into_iter().enumerate() {
+        match result {
+            Ok(cached) if i < paths_b.len() => hashes_b.push((path, cached.hash)),
+            Ok(cached) => hashes_a.push((path, cached.hash)),
+            Err(e) => failures.push((path, e)),
+        }
+    }
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok((match_hashes(&hashes_a, &hashes_b, threshold), failures))
+}
+
+/// The A-B pairs within `threshold`, in the order of `match_collections`
+fn match_hashes(
+    a: &[(PathBuf, ImageHash)],
+    b: &[(PathBuf, ImageHash)],
+    threshold: u32,
+) -> Vec<CollectionMatch> {
+    let mut index = BkTree::new();
+    for (path, hash) in b {
+        index.add(hash.clone(), path.as_path());
+    }
+
+    let mut matches: Vec<CollectionMatch> = a
+        .par_iter()
+        .flat_map_iter(|(path_a, hash)| {
+            index
+                .query(hash, threshold)
+                .into_iter()
+                .map(move |(path_b, distance)| CollectionMatch {
+                    path_a: path_a.clone(),
+                    path_b: path_b.to_path_buf(),
+                    distance,
+                })
+        })
+        .collect();
+    matches.sort_unstable_by(|x, y| {
+        (&x.path_a, x.distance, &x.path_b).cmp(&(&y.path_a, y.distance, &y.path_b))
+    });
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranks_by_distance() {
+        let h = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let candidates: Vec<(PathBuf, ImageHash)> = [
+            ("far", "ffffffffffffffff"),
+            ("b", "00000000000000ff"),
+            ("same", "0000000000000000"),
+            ("a", "00000000000000ff"),
+            ("near", "0000000000000001"),
+        ]
+        .iter()
+        .map(|(p, hex)| (PathBuf::from(p), h(hex)))
+        .collect();
+        let query = h("0000000000000000");
+
+        let ranked = |max| -> Vec<(PathBuf, u32)> {
+            find_similar(&query, &candidates, 10, max)
+                .unwrap()
+                .into_iter()
+                .map(|m| (m.path, m.distance))
+                .collect()
+        };
+        assert_eq!(
+            ranked(None),
+            vec![
+                (PathBuf::from("same"), 0),
+                (PathBuf::from("near"), 1),
+                (PathBuf::from("a"), 8),
+                (PathBuf::from("b"), 8),
+            ]
+        );
+        assert_eq!(ranked(Some(2)).len(), 2);
+
+        let wide = vec![(
+            PathBuf::from("x"),
+            ImageHash::from_hex(&"0".repeat(64)).unwrap(),
+        )];
+        assert!(find_similar(&query, &wide, 10, None).is_err());
+    }
+
+    #[test]
+    fn test_match_collections_compares_across_only() {
+        let h = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        let a = vec![
+            (PathBuf::from("new1"), h("0000000000000000")),
+            (PathBuf::from("new2"), h("0000000000000001")),
+            (PathBuf::from("new3"), h("ffffffffffffffff")),
+        ];
+        let b = vec![
+            (PathBuf::from("old2"), h("0000000000000003")),
+            (PathBuf::from("old1"), h("0000000000000000")),
+        ];
+
+        let pairs: Vec<(PathBuf, PathBuf, u32)> = match_hashes(&a, &b, 2)
+            .into_iter()
+            .map(|m| (m.path_a, m.path_b, m.distance))
+            .collect();
+        let pair = |a: &str, b: &str, d| (PathBuf::from(a), PathBuf::from(b), d);
+        // new1 and new2 are within 1 of each other but never paired
+        assert_eq!(
+            pairs,
+            vec![
+                pair("new1", "old1", 0),
+                pair("new1", "old2", 2),
+                pair("new2", "old1", 1),
+                pair("new2", "old2", 1),
+            ]
+        );
+
+        let (matches, failures) = match_collections(
+            &[PathBuf::from("/nonexistent/a.jpg")],
+            &[PathBuf::from("/nonexistent/b.jpg")],
+            HashAlgorithm::PHash,
+            8,
+            10,
+            None,
+        )
+        .unwrap();
+        assert!(matches.is_empty());
+        assert_eq!(failures.len(), 2);
+    }
+}
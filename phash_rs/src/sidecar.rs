@@ -0,0 +1,152 @@
+//! Sidecar Files Module
+//!
+//! Associates metadata sidecars (XMP, AAE, THM) with the image they describe,
+//! so duplicate handling can move or delete them together instead of
+//! leaving orphaned sidecars behind or reporting them as images.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions (lowercase, without the dot) treated as sidecars
+pub const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae", "thm"];
+
+/// Whether `path` has a sidecar extension
+pub fn is_sidecar<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| SIDECAR_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Find the sidecars belonging to each of `primaries`
+///
+/// A sidecar belongs to a primary if it is named after the full file name
+/// (`IMG_1.CR2.xmp`) or after the stem (`IMG_1.xmp`). A stem-named sidecar
+/// is only attached when exactly one non-sidecar file in the directory has
+/// that stem; with `IMG_1.CR2` and `IMG_1.JPG` side by side it is ambiguous
+/// and left alone. Names are compared case-insensitively. Each directory is
+/// listed once. Primaries without sidecars are omitted from the result.
+pub fn find_sidecars(primaries: &[PathBuf]) -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    let mut by_dir: BTreeMap<&Path, Vec<&PathBuf>> = BTreeMap::new();
+    for primary in primaries {
+        let dir = primary.parent().unwrap_or(Path::new(""));
+        by_dir.entry(dir).or_default().push(primary);
+    }
+
+    let mut result: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for (dir, members) in by_dir {
+        let listing = if dir.as_os_str().is_empty() {
+            fs::read_dir(".")
+        } else {
+            fs::read_dir(dir)
+        };
+        let names: Vec<(OsString, String)> = match listing {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+                .map(|e| {
+                    let name = e.file_name();
+                    let lossy = name.to_string_lossy().into_owned();
+                    (name, lossy)
+                })
+                .collect(),
+            Err(_) => continue,
+        };
+
+        // Sidecar "key" (its name minus the sidecar extension) -> file names
+        let mut sidecars: HashMap<String, Vec<&OsStr>> = HashMap::new();
+        let mut stem_counts: HashMap<String, usize> = HashMap::new();
+        for (name, lossy) in &names {
+            if is_sidecar(name) {
+                sidecars.entry(lower_stem(lossy)).or_default().push(name);
+            } else {
+                *stem_counts.entry(lower_stem(lossy)).or_default() += 1;
+            }
+        }
+
+        for primary in members {
+            let Some(file_name) = primary.file_name() else {
+                continue;
+            };
+            let file_name = file_name.to_string_lossy();
+            let stem = lower_stem(&file_name);
+
+            let mut found: Vec<&OsStr> = Vec::new();
+            if let Some(exact) = sidecars.get(&file_name.to_lowercase()) {
+                found.extend(exact);
+            }
+            if stem_counts.get(&stem) == Some(&1) {
+                if let Some(by_stem) = sidecars.get(&stem) {
+                    found.extend(by_stem);
+                }
+            }
+            if found.is_empty() {
+                continue;
+            }
+
+            let mut paths: Vec<PathBuf> = found.into_iter().map(|name| dir.join(name)).collect();
+            paths.sort();
+            paths.dedup();
+            result.insert(primary.clone(), paths);
+        }
+    }
+
+    result
+}
+
+/// Lowercased file name without its last extension
+fn lower_stem(name: &str) -> String {
+    let stem = match name.rfind('.') {
+        Some(0) | None => name,
+        Some(i) => &name[..i],
+    };
+    stem.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_sidecar() {
+        assert!(is_sidecar("/a/IMG_1.XMP"));
+        assert!(is_sidecar("IMG_1.CR2.aae"));
+        assert!(!is_sidecar("IMG_1.jpg"));
+        assert!(!is_sidecar("xmp"));
+    }
+
+    #[test]
+    fn test_find_sidecars() {
+        let root = std::env::temp_dir().join(format!("phash_rs_sidecar_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let names = [
+            "a.CR2",
+            "a.xmp",
+            "b.jpg",
+            "b.JPG.xmp",
+            "c.CR2",
+            "c.jpg",
+            "c.XMP",
+            "d.png",
+        ];
+        for name in names {
+            fs::write(root.join(name), b"").unwrap();
+        }
+        let path = |name: &str| root.join(name);
+
+        let primaries: Vec<PathBuf> = ["a.CR2", "b.jpg", "c.jpg", "d.png"]
+            .iter()
+            .map(|n| path(n))
+            .collect();
+        let found = find_sidecars(&primaries);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(found.get(&path("a.CR2")), Some(&vec![path("a.xmp")]));
+        assert_eq!(found.get(&path("b.jpg")), Some(&vec![path("b.JPG.xmp")]));
+        // c.XMP could belong to c.CR2 or c.jpg
+        assert!(!found.contains_key(&path("c.jpg")));
+        assert!(!found.contains_key(&path("d.png")));
+    }
+}
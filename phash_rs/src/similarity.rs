@@ -0,0 +1,172 @@
+//! Similarity Module
+//!
+//! Second-stage check for duplicate groups. A short hash can't tell apart
+//! unrelated images whose DCT is dominated by noise (dark, low-contrast
+//! shots), so at looser thresholds they get grouped. Each pair of members
+//! within the threshold is compared again by SSIM on contrast-stretched
+//! grayscale thumbnails, and only pairs that pass keep the group together.
+
+use crate::duplicate::{group_components, sort_groups, DuplicateGroup, ImageInfo};
+use crate::preprocess::HashOptions;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// Minimum thumbnail SSIM for two members to count as duplicates
+pub const DEFAULT_MIN_SIMILARITY: f64 = 0.5;
+/// Side length of the grayscale thumbnails compared
+const THUMBNAIL_SIZE: u32 = 64;
+/// Side length of the SSIM windows
+const WINDOW: usize = 8;
+/// SSIM stabilizing constants for 8-bit values
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Structural similarity of two images, from -1 to 1 (identical)
+///
+/// Both are reduced to 64x64 grayscale with their contrast stretched to
+/// the full range, so two dim images only score high if their structure
+/// agrees, then SSIM is averaged over 8x8 windows.
+pub fn thumbnail_similarity(a: &DynamicImage, b: &DynamicImage) -> f64 {
+    ssim(&thumbnail(a), &thumbnail(b))
+}
+
+fn thumbnail(img: &DynamicImage) -> Vec<f64> {
+    let small = img
+        .resize_exact(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle)
+        .to_luma8();
+    let pixels: Vec<f64> = small.pixels().map(|p| p.0[0] as f64).collect();
+    let min = pixels.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = pixels.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max - min < 1.0 {
+        return pixels;
+    }
+    pixels
+        .iter()
+        .map(|v| (v - min) * 255.0 / (max - min))
+        .collect()
+}
+
+fn ssim(a: &[f64], b: &[f64]) -> f64 {
+    let size = THUMBNAIL_SIZE as usize;
+    let count = (WINDOW * WINDOW) as f64;
+    let mut total = 0.0;
+    let mut windows = 0;
+    for wy in (0..size).step_by(WINDOW) {
+        for wx in (0..size).step_by(WINDOW) {
+            let at = |y: usize, x: usize| (wy + y) * size + wx + x;
+            let cells = || (0..WINDOW).flat_map(|y| (0..WINDOW).map(move |x| (y, x)));
+            let mean_a = cells().map(|(y, x)| a[at(y, x)]).sum::<f64>() / count;
+            let mean_b = cells().map(|(y, x)| b[at(y, x)]).sum::<f64>() / count;
+            let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+            for (y, x) in cells() {
+                let (da, db) = (a[at(y, x)] - mean_a, b[at(y, x)] - mean_b);
+                var_a += da * da;
+                var_b += db * db;
+                cov += da * db;
+            }
+            let (var_a, var_b, cov) = (var_a / count, var_b / count, cov / count);
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+/// Split groups whose members only match by hash
+///
+/// Within each group, members whose hashes are within `threshold` are
+/// compared by `thumbnail_similarity` after `options` preprocessing; pairs
+/// below `min_similarity`, or that can no longer be decoded, are unlinked
+/// and the group is split into what stays connected. Only the files in
+/// groups with several members are decoded. Groups without member hashes
+/// are kept as they are.
+pub fn verify_groups(
+    groups: Vec<DuplicateGroup>,
+    threshold: u32,
+    options: &HashOptions,
+    min_similarity: f64,
+) -> Vec<DuplicateGroup> {
+    let checkable = |g: &DuplicateGroup| {
+        g.paths.len() > 1 && g.paths.iter().all(|p| g.member_hashes.contains_key(p))
+    };
+    let paths: BTreeSet<&PathBuf> = groups
+        .iter()
+        .filter(|g| checkable(g))
+        .flat_map(|g| g.paths.iter())
+        .collect();
+    let thumbnails: BTreeMap<&PathBuf, Option<Vec<f64>>> = paths
+        .into_par_iter()
+        .map(|path| {
+            let thumb = options
+                .open(path)
+                .ok()
+                .map(|img| thumbnail(&options.apply(&img)));
+            (path, thumb)
+        })
+        .collect();
+
+    let mut verified = Vec::new();
+    for group in &groups {
+        if !checkable(group) {
+            verified.push(group.clone());
+            continue;
+        }
+        let images: Vec<ImageInfo> = group
+            .paths
+            .iter()
+            .map(|path| ImageInfo {
+                path: path.clone(),
+                hash: group.member_hashes[path].clone(),
+                dimensions: group.member_dimensions.get(path).copied(),
+            })
+            .collect();
+        let neighbours: Vec<Vec<usize>> = (0..images.len())
+            .map(|i| {
+                (i + 1..images.len())
+                    .filter(|&j| {
+                        images[i].hash.distance(&images[j].hash) <= threshold
+                            && match (&thumbnails[&images[i].path], &thumbnails[&images[j].path]) {
+                                (Some(a), Some(b)) => ssim(a, b) >= min_similarity,
+                                _ => false,
+                            }
+                    })
+                    .collect()
+            })
+            .collect();
+        for mut part in group_components(&images, neighbours) {
+            part.content_type = group.content_type;
+            part.sidecars = group
+                .sidecars
+                .iter()
+                .filter(|(path, _)| part.paths.contains(path))
+                .map(|(path, files)| (path.clone(), files.clone()))
+                .collect();
+            verified.push(part);
+        }
+    }
+    sort_groups(&mut verified);
+    verified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn test_similarity_separates_dim_images() {
+        let dim = |f: fn(u32, u32) -> u32| {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(64, 64, |x, y| Luma([f(x, y) as u8])))
+        };
+        let stripes = dim(|x, _| 10 + (x / 8 % 2) * 6);
+        let brighter = dim(|x, _| 30 + (x / 8 % 2) * 12);
+        let checks = dim(|x, y| 10 + ((x / 4 + y / 4) % 2) * 6);
+
+        assert!(thumbnail_similarity(&stripes, &brighter) > 0.99);
+        assert!(thumbnail_similarity(&stripes, &checks) < DEFAULT_MIN_SIMILARITY);
+    }
+}
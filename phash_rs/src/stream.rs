@@ -0,0 +1,509 @@
+//! Streaming Duplicate Module
+//!
+//! `find_duplicates_with` holds every hash and the whole union-find state
+//! until the last file is grouped. `DuplicateFinder` takes paths in chunks
+//! instead and, given a window, hands out each group as soon as no later
+//! image can join it, freeing its memory.
+
+use crate::cache::{hash_paths, write_bytes, write_str, HashCache, Reader};
+use crate::duplicate::{make_group, primaries_only, with_sidecars, DuplicateGroup, ImageInfo};
+use crate::error::{HashError, HashFailures};
+use crate::hash::{HashAlgorithm, ImageHash, DEFAULT_HASH_SIZE};
+use crate::index::MultiIndex;
+use crate::paths::path_bytes;
+use crate::progress::Progress;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+/// Images per chunk the index is sized for when no estimate is given
+const DEFAULT_EXPECTED_IMAGES: usize = 10_000;
+
+/// Images connected so far, keyed by the sequence number of its first
+/// member
+#[derive(Debug)]
+struct Component {
+    members: Vec<usize>,
+    /// Highest sequence number among the members
+    newest: usize,
+}
+
+/// Incremental duplicate grouping over paths fed in chunks
+///
+/// Images are numbered in the order they are added. Without a window all
+/// groups stay open until `finish`, which then returns the same groups as
+/// `find_duplicates_with` over all paths at once. With `window(n)`, a
+/// group is closed once its newest member is more than `n` images behind
+/// the last one added; images closer than that in input order are always
+/// compared, images further apart may be missed. Feed paths sorted (e.g.
+/// by directory or capture time) so duplicates arrive close together.
+#[derive(Debug)]
+pub struct DuplicateFinder {
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+    window: Option<usize>,
+    expected_images: usize,
+    /// Created with the first hash, once its bit length is known
+    index: Option<MultiIndex<usize>>,
+    images: HashMap<usize, ImageInfo>,
+    component_of: HashMap<usize, usize>,
+    components: HashMap<usize, Component>,
+    /// (newest member, component) for closing components oldest first
+    by_newest: BTreeSet<(usize, usize)>,
+    next_seq: usize,
+    finished: Vec<DuplicateGroup>,
+    failures: HashFailures,
+}
+
+impl DuplicateFinder {
+    /// Group with `algorithm`, matching hashes within `threshold`
+    pub fn new(algorithm: HashAlgorithm, threshold: u32) -> Self {
+        Self {
+            algorithm,
+            hash_size: DEFAULT_HASH_SIZE,
+            threshold,
+            window: None,
+            expected_images: DEFAULT_EXPECTED_IMAGES,
+            index: None,
+            images: HashMap::new(),
+            component_of: HashMap::new(),
+            components: HashMap::new(),
+            by_newest: BTreeSet::new(),
+            next_seq: 0,
+            finished: Vec::new(),
+            failures: HashFailures::new(),
+        }
+    }
+
+    /// Hash with `hash_size` x `hash_size` grids instead of the default
+    pub fn hash_size(mut self, hash_size: usize) -> Self {
+        self.hash_size = hash_size;
+        self
+    }
+
+    /// Close groups whose newest member is more than `images` behind
+    pub fn window(mut self, images: usize) -> Self {
+        self.window = Some(images);
+        self
+    }
+
+    /// Number of images held at once, used to size the hash index
+    pub fn expected_images(mut self, images: usize) -> Self {
+        self.expected_images = images;
+        self
+    }
+
+    /// Hash a chunk of paths and group it with the images still open
+    ///
+    /// Sidecar files are skipped as in `find_duplicates_with`. Fresh hashes
+    /// are added to `cache`; files that fail to hash are collected for
+    /// `finish`. Returns an error, with the chunk not added, if `progress`
+    /// cancels or the hash size is not supported.
+    pub fn add_paths(
+        &mut self,
+        paths: &[PathBuf],
+        cache: Option<&mut HashCache>,
+        progress: Option<&dyn Progress>,
+    ) -> Result<(), String> {
+        self.algorithm
+            .check_hash_size(self.hash_size)
+            .map_err(|e| e.to_string())?;
+        let mut images = Vec::new();
+        let paths = primaries_only(paths);
+        for (path, result) in hash_paths(&paths, self.algorithm, self.hash_size, cache, progress) {
+            match result {
+                Ok(cached) => images.push(ImageInfo {
+                    path,
+                    hash: cached.hash,
+                    dimensions: Some(cached.dimensions),
+                }),
+                Err(e) => self.failures.push((path, e)),
+            }
+        }
+        if progress.is_some_and(|p| p.is_cancelled()) {
+            return Err("Cancelled".to_string());
+        }
+        self.add_images(images);
+        Ok(())
+    }
+
+    /// Groups of two or more images closed since the last call
+    pub fn take_finished(&mut self) -> Vec<DuplicateGroup> {
+        with_sidecars(std::mem::take(&mut self.finished))
+    }
+
+    /// Files that failed to hash so far
+    pub fn failures(&self) -> &HashFailures {
+        &self.failures
+    }
+
+    /// Images whose group is still open
+    pub fn open_images(&self) -> usize {
+        self.images.len()
+    }
+
+    /// Close all groups
+    ///
+    /// Returns the groups not yet taken with `take_finished` and every file
+    /// that failed to hash, sorted by path.
+    pub fn finish(mut self) -> (Vec<DuplicateGroup>, HashFailures) {
+        self.close_before(usize::MAX);
+        let groups = self.take_finished();
+        self.failures.sort_by(|a, b| a.0.cmp(&b.0));
+        (groups, self.failures)
+    }
+
+    fn add_images(&mut self, images: Vec<ImageInfo>) {
+        let first = self.next_seq;
+        for image in images {
+            let seq = self.next_seq;
+            self.next_seq += 1;
+            let index = self.index.get_or_insert_with(|| {
+                MultiIndex::for_corpus(image.hash.bit_len(), self.expected_images, self.threshold)
+            });
+            index.add(image.hash.clone(), seq);
+            self.images.insert(seq, image);
+            self.component_of.insert(seq, seq);
+            self.components.insert(
+                seq,
+                Component {
+                    members: vec![seq],
+                    newest: seq,
+                },
+            );
+            self.by_newest.insert((seq, seq));
+        }
+
+        // Each new image links to earlier ones; later ones link back to it
+        let links: Vec<(usize, usize)> = match &self.index {
+            Some(index) => (first..self.next_seq)
+                .into_par_iter()
+                .flat_map_iter(|i| {
+                    index
+                        .query(&self.images[&i].hash, self.threshold)
+                        .into_iter()
+                        .map(|(&j, _)| j)
+                        .filter(move |&j| j < i)
+                        .map(move |j| (i, j))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        for (i, j) in links {
+            self.union(i, j);
+        }
+
+        if let Some(window) = self.window {
+            self.close_before(self.next_seq.saturating_sub(window));
+        }
+    }
+
+    /// Append the settings and the whole grouping state to `data`, for
+    /// `read_state`
+    ///
+    /// Images are written in sequence order and components by id, so the
+    /// same state always gives the same bytes.
+    pub(crate) fn write_state(&self, data: &mut Vec<u8>) {
+        let write_u64 =
+            |data: &mut Vec<u8>, n: usize| data.extend_from_slice(&(n as u64).to_le_bytes());
+        write_str(data, &self.algorithm.to_string());
+        write_u64(data, self.hash_size);
+        data.extend_from_slice(&self.threshold.to_le_bytes());
+        write_u64(data, self.window.map_or(0, |w| w + 1));
+        write_u64(data, self.expected_images);
+        write_u64(data, self.next_seq);
+
+        let images: BTreeMap<_, _> = self.images.iter().collect();
+        write_u64(data, images.len());
+        for (&seq, image) in images {
+            write_u64(data, seq);
+            write_image(data, image);
+        }
+        let components: BTreeMap<_, _> = self.components.iter().collect();
+        write_u64(data, components.len());
+        for (&id, component) in components {
+            write_u64(data, id);
+            write_u64(data, component.members.len());
+            for &member in &component.members {
+                write_u64(data, member);
+            }
+        }
+        write_u64(data, self.finished.len());
+        for group in &self.finished {
+            write_str(data, &group.hash);
+            write_bytes(data, &path_bytes(&group.best_path));
+            write_u64(data, group.paths.len());
+            for path in &group.paths {
+                write_image(
+                    data,
+                    &ImageInfo {
+                        path: path.clone(),
+                        hash: group.member_hashes[path].clone(),
+                        dimensions: group.member_dimensions.get(path).copied(),
+                    },
+                );
+            }
+        }
+        write_u64(data, self.failures.len());
+        for (path, error) in &self.failures {
+            write_bytes(data, &path_bytes(path));
+            write_str(data, error.kind());
+            write_str(data, error.message());
+        }
+    }
+
+    /// Read back a finder written with `write_state`, or `None` if the
+    /// data is truncated or malformed
+    pub(crate) fn read_state(reader: &mut Reader) -> Option<Self> {
+        let read_u64 = |reader: &mut Reader| Some(u64::from_le_bytes(reader.take()?) as usize);
+        let algorithm: HashAlgorithm = reader.string()?.parse().ok()?;
+        let mut finder = Self::new(algorithm, 0).hash_size(read_u64(reader)?);
+        finder.threshold = u32::from_le_bytes(reader.take()?);
+        finder.window = read_u64(reader)?.checked_sub(1);
+        finder.expected_images = read_u64(reader)?;
+        finder.next_seq = read_u64(reader)?;
+
+        for _ in 0..read_u64(reader)? {
+            let seq = read_u64(reader)?;
+            let image = read_image(reader)?;
+            let index = finder.index.get_or_insert_with(|| {
+                MultiIndex::for_corpus(
+                    image.hash.bit_len(),
+                    finder.expected_images,
+                    finder.threshold,
+                )
+            });
+            index.add(image.hash.clone(), seq);
+            finder.images.insert(seq, image);
+        }
+        for _ in 0..read_u64(reader)? {
+            let id = read_u64(reader)?;
+            let members = (0..read_u64(reader)?)
+                .map(|_| read_u64(reader))
+                .collect::<Option<Vec<usize>>>()?;
+            let newest = *members.iter().max()?;
+            for &member in &members {
+                finder.images.get(&member)?;
+                finder.component_of.insert(member, id);
+            }
+            finder.by_newest.insert((newest, id));
+            finder.components.insert(id, Component { members, newest });
+        }
+        if finder.component_of.len() != finder.images.len() {
+            return None;
+        }
+        for _ in 0..read_u64(reader)? {
+            let hash = reader.string()?;
+            let best_path = reader.path()?;
+            let members = (0..read_u64(reader)?)
+                .map(|_| read_image(reader))
+                .collect::<Option<Vec<ImageInfo>>>()?;
+            let indices: Vec<usize> = (0..members.len()).collect();
+            finder.finished.push(DuplicateGroup {
+                hash,
+                best_path,
+                ..make_group(&members, &indices)
+            });
+        }
+        for _ in 0..read_u64(reader)? {
+            let path = reader.path()?;
+            let error = HashError::from_kind(&reader.string()?, reader.string()?)?;
+            finder.failures.push((path, error));
+        }
+        Some(finder)
+    }
+
+    /// Merge the components of images `a` and `b`, relabelling the smaller
+    fn union(&mut self, a: usize, b: usize) {
+        let (ca, cb) = (self.component_of[&a], self.component_of[&b]);
+        if ca == cb {
+            return;
+        }
+        let (keep, gone) =
+            if self.components[&ca].members.len() >= self.components[&cb].members.len() {
+                (ca, cb)
+            } else {
+                (cb, ca)
+            };
+        let Some(gone_component) = self.components.remove(&gone) else {
+            return;
+        };
+        self.by_newest.remove(&(gone_component.newest, gone));
+        for &member in &gone_component.members {
+            self.component_of.insert(member, keep);
+        }
+        if let Some(kept) = self.components.get_mut(&keep) {
+            self.by_newest.remove(&(kept.newest, keep));
+            kept.members.extend(gone_component.members);
+            kept.newest = kept.newest.max(gone_component.newest);
+            self.by_newest.insert((kept.newest, keep));
+        }
+    }
+
+    /// Close components whose newest member comes before `horizon`
+    fn close_before(&mut self, horizon: usize) {
+        while let Some(&(newest, id)) = self.by_newest.first() {
+            if newest >= horizon {
+                break;
+            }
+            self.by_newest.pop_first();
+            let Some(mut component) = self.components.remove(&id) else {
+                continue;
+            };
+            // First-added member first, so the group hash matches one-shot
+            // grouping
+            component.members.sort_unstable();
+            let mut members = Vec::with_capacity(component.members.len());
+            for seq in component.members {
+                self.component_of.remove(&seq);
+                if let Some(image) = self.images.remove(&seq) {
+                    if let Some(index) = &mut self.index {
+                        index.remove(&image.hash, |&v| v == seq);
+                    }
+                    members.push(image);
+                }
+            }
+            if members.len() > 1 {
+                let indices: Vec<usize> = (0..members.len()).collect();
+                self.finished.push(make_group(&members, &indices));
+            }
+        }
+        // The index compacts itself as entries are removed; the maps keep
+        // their peak capacity unless shrunk
+        if self.images.capacity() > 4 * self.images.len() + 64 {
+            self.images.shrink_to(2 * self.images.len());
+            self.component_of.shrink_to(2 * self.component_of.len());
+            self.components.shrink_to(2 * self.components.len());
+        }
+    }
+}
+
+fn write_image(data: &mut Vec<u8>, image: &ImageInfo) {
+    write_bytes(data, &path_bytes(&image.path));
+    write_str(data, &image.hash.to_hex());
+    let (width, height) = image.dimensions.unwrap_or_default();
+    data.push(image.dimensions.is_some() as u8);
+    data.extend_from_slice(&width.to_le_bytes());
+    data.extend_from_slice(&height.to_le_bytes());
+}
+
+fn read_image(reader: &mut Reader) -> Option<ImageInfo> {
+    let path = reader.path()?;
+    let hash = ImageHash::from_hex(&reader.string()?).ok()?;
+    let known = reader.take::<1>()?[0] != 0;
+    let dimensions = (
+        u32::from_le_bytes(reader.take()?),
+        u32::from_le_bytes(reader.take()?),
+    );
+    Some(ImageInfo {
+        path,
+        hash,
+        dimensions: known.then_some(dimensions),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ImageHash;
+
+    /// Add images that were hashed elsewhere
+    fn add_hashes(finder: &mut DuplicateFinder, hashes: &[(&str, ImageHash)]) {
+        let images = hashes
+            .iter()
+            .map(|(path, hash)| ImageInfo {
+                path: PathBuf::from(path),
+                hash: hash.clone(),
+                dimensions: None,
+            })
+            .collect();
+        finder.add_images(images);
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot_grouping() {
+        let h = |hex: &str| ImageHash::from_hex(hex).unwrap();
+        // a0-a1-a2 chain through a1; b0/b1 are far from everything else
+        let hashes = [
+            ("b0", h("ffffffff00000000")),
+            ("b1", h("ffffffff00000001")),
+            ("a0", h("0000000000000000")),
+            ("c0", h("00000000ffffffff")),
+            ("a1", h("000000000000000f")),
+            ("a2", h("00000000000000ff")),
+        ];
+        let sorted = |mut groups: Vec<DuplicateGroup>| {
+            groups.sort_by(|a, b| a.paths.cmp(&b.paths));
+            groups
+                .into_iter()
+                .map(|g| (g.paths, g.hash))
+                .collect::<Vec<_>>()
+        };
+
+        let mut finder = DuplicateFinder::new(HashAlgorithm::PHash, 4);
+        for chunk in hashes.chunks(2) {
+            add_hashes(&mut finder, chunk);
+            assert!(finder.take_finished().is_empty());
+        }
+        let (groups, failures) = finder.finish();
+        assert!(failures.is_empty());
+        let expected = vec![
+            (
+                vec!["a0".into(), "a1".into(), "a2".into()],
+                "000000000000000f".to_string(),
+            ),
+            (
+                vec!["b0".into(), "b1".into()],
+                "ffffffff00000000".to_string(),
+            ),
+        ];
+        assert_eq!(sorted(groups), expected);
+
+        // A window of 2 closes the b group after the second chunk, and
+        // still links a1 to a0 two images back
+        let mut finder = DuplicateFinder::new(HashAlgorithm::PHash, 4).window(2);
+        let mut closed = Vec::new();
+        for chunk in hashes.chunks(2) {
+            add_hashes(&mut finder, chunk);
+            closed.push(sorted(finder.take_finished()));
+        }
+        assert_eq!(closed, vec![vec![], expected[1..].to_vec(), vec![]]);
+        assert_eq!(finder.open_images(), 3);
+        assert_eq!(sorted(finder.finish().0), expected[..1].to_vec());
+    }
+
+    #[test]
+    fn test_window_keeps_memory_bounded() {
+        let mut rng = crate::rng::SeededRng::new(11);
+        let mut finder = DuplicateFinder::new(HashAlgorithm::PHash, 2).window(20);
+        let mut sizes = Vec::new();
+        for chunk in 0..100 {
+            let hashes: Vec<(String, ImageHash)> = (0..10)
+                .map(|i| {
+                    let name = format!("{}-{}", chunk, i);
+                    (name, ImageHash::from_u64(rng.next_u64()))
+                })
+                .collect();
+            let named: Vec<(&str, ImageHash)> = hashes
+                .iter()
+                .map(|(name, hash)| (name.as_str(), hash.clone()))
+                .collect();
+            add_hashes(&mut finder, &named);
+            let index = finder.index.as_ref().unwrap();
+            assert_eq!(index.len(), finder.open_images());
+            sizes.push((index.heap_bytes(), finder.images.capacity()));
+        }
+        // Closed images leave the index and the maps instead of piling up
+        assert!(finder.open_images() <= 30);
+        let (early_bytes, early_capacity) = sizes[10];
+        let (late_bytes, late_capacity) = sizes[99];
+        assert!(
+            late_bytes <= 2 * early_bytes,
+            "{} vs {}",
+            late_bytes,
+            early_bytes
+        );
+        assert!(late_capacity <= 2 * early_capacity.max(64));
+    }
+}
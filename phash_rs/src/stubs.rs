@@ -0,0 +1,104 @@
+//! Python Stub Check Module
+//!
+//! `phash_rs.pyi` is written by hand next to `pyproject.toml`, which ships
+//! it with the extension module. These tests read the bindings' source and
+//! fail when a function, class or parameter registered there is missing
+//! from the stub, so the two cannot drift apart unnoticed. They run without
+//! the `python` feature.
+
+const BINDINGS: &str = include_str!("python.rs");
+const STUB: &str = include_str!("../phash_rs.pyi");
+
+/// The name Python sees for the function or struct `ident`, with its
+/// attributes from the `#[<attribute>` line on
+fn declared(attribute: &str, ident: &'static str) -> (&'static str, &'static str) {
+    let decl = match attribute {
+        "pyclass" => format!("struct {ident} "),
+        _ => format!("fn {ident}"),
+    };
+    // Skip longer names sharing the prefix
+    let at = BINDINGS
+        .match_indices(&decl)
+        .map(|(at, _)| at)
+        .find(|&at| {
+            !BINDINGS[at + decl.len()..].starts_with(|c: char| c == '_' || c.is_alphanumeric())
+        })
+        .unwrap_or_else(|| panic!("{decl} not found in python.rs"));
+    let before = &BINDINGS[..at];
+    let attrs = &before[before
+        .rfind(&format!("\n#[{attribute}"))
+        .unwrap_or_else(|| panic!("{decl} has no #[{attribute}]"))..];
+    let name = match attrs[1..].lines().next().unwrap().split_once("name = \"") {
+        Some((_, rest)) => &rest[..rest.find('"').unwrap()],
+        None => ident,
+    };
+    (name, attrs)
+}
+
+/// Parameter names of a `#[pyo3(signature = (...))]` in `attrs`
+fn signature_params(attrs: &str) -> Vec<String> {
+    let Some((_, rest)) = attrs.split_once("signature = (") else {
+        return Vec::new();
+    };
+    let rest = &rest[..rest.find("))]").unwrap()];
+    rest.split(',')
+        .map(|p| p.split('=').next().unwrap().trim().trim_start_matches('*'))
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parameter text of the first top-level `def name(` in the stub
+fn stub_def(name: &str) -> Option<&'static str> {
+    let start = STUB.find(&format!("\ndef {name}("))?;
+    let rest = &STUB[start..];
+    Some(&rest[..rest.find(") ->").unwrap()])
+}
+
+/// Registered names in the module function, from lines like
+/// `m.add_class::<PyImageHash>()?;`
+fn registered<'a>(open: &'a str, close: &'a str) -> impl Iterator<Item = &'static str> + 'a {
+    BINDINGS.lines().filter_map(move |line| {
+        let (_, rest) = line.split_once(open)?;
+        Some(&rest[..rest.find(close)?])
+    })
+}
+
+#[test]
+fn test_functions_in_stub() {
+    let mut names = Vec::new();
+    for ident in registered("wrap_pyfunction!(", ", m)") {
+        let (name, attrs) = declared("pyfunction", ident);
+        let def = stub_def(name).unwrap_or_else(|| panic!("no stub for {name}"));
+        for param in signature_params(attrs) {
+            assert!(
+                def.contains(&format!(" {param}:")) || def.contains(&format!("({param}:")),
+                "stub for {name} lacks parameter {param}"
+            );
+        }
+        names.push(name);
+    }
+    assert!(names.len() > 50);
+    // Nothing stubbed that the module no longer has
+    for line in STUB.lines() {
+        if let Some(rest) = line.strip_prefix("def ") {
+            let name = &rest[..rest.find('(').unwrap()];
+            assert!(
+                names.contains(&name),
+                "{name} is stubbed but not registered"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_classes_in_stub() {
+    for ident in registered("add_class::<", ">()") {
+        let (name, _) = declared("pyclass", ident);
+        assert!(
+            STUB.contains(&format!("\nclass {name}:"))
+                || STUB.contains(&format!("\nclass {name}(")),
+            "no stub for class {name}"
+        );
+    }
+}
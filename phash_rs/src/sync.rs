@@ -0,0 +1,378 @@
+//! Hash List Sync Module
+//!
+//! Pulls signed, versioned deltas of a shared hash list into a local
+//! `BkTree`, so sites sharing an "already archived" set only transfer what
+//! changed. Fetching over HTTPS and signature checks need the `sync` feature;
+//! parsing and applying deltas are always available.
+//!
+//! A list publishes a manifest at `<base_url>/manifest`:
+//!
+//! ```text
+//! phash-list v1
+//! list <name>
+//! latest <version>
+//! delta <from> <to> <relative path>
+//! ```
+//!
+//! and one file per delta:
+//!
+//! ```text
+//! phash-delta v1
+//! list <name>
+//! from <version>
+//! to <version>
+//! +<TAB><hex hash><TAB><path>
+//! -<TAB><hex hash><TAB><path>
+//! signature <ed25519 signature as hex>
+//! ```
+//!
+//! The signature covers every byte before the `signature` line. Version 0
+//! is the empty list, so a `0 -> N` delta doubles as a full snapshot.
+
+use crate::hash::{decode_hex, ImageHash};
+use crate::index::BkTree;
+use std::path::PathBuf;
+
+/// First line of a manifest
+const MANIFEST_HEADER: &str = "phash-list v1";
+/// First line of a delta
+const DELTA_HEADER: &str = "phash-delta v1";
+
+/// One published delta as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeltaRef {
+    pub from: u64,
+    pub to: u64,
+    pub path: String,
+}
+
+/// Parsed list manifest
+#[derive(Debug, Clone)]
+pub struct SyncManifest {
+    pub list: String,
+    pub latest: u64,
+    pub deltas: Vec<DeltaRef>,
+}
+
+impl SyncManifest {
+    /// Parse manifest text
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(MANIFEST_HEADER) {
+            return Err("Not a hash list manifest".to_string());
+        }
+
+        let mut list = None;
+        let mut latest = None;
+        let mut deltas = Vec::new();
+        for line in lines.map(str::trim).filter(|l| !l.is_empty()) {
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("list") => list = parts.next().map(str::to_string),
+                Some("latest") => latest = Some(parse_version(parts.next())?),
+                Some("delta") => deltas.push(DeltaRef {
+                    from: parse_version(parts.next())?,
+                    to: parse_version(parts.next())?,
+                    path: parts
+                        .next()
+                        .ok_or_else(|| format!("Missing delta path: {}", line))?
+                        .to_string(),
+                }),
+                _ => return Err(format!("Unexpected manifest line: {}", line)),
+            }
+        }
+
+        Ok(Self {
+            list: list.ok_or("Manifest has no list name")?,
+            latest: latest.ok_or("Manifest has no latest version")?,
+            deltas,
+        })
+    }
+
+    /// Deltas to apply, in order, to go from `current` to `latest`
+    ///
+    /// Prefers the delta that jumps furthest at each step.
+    pub fn plan(&self, current: u64) -> Result<Vec<&DeltaRef>, String> {
+        let mut plan = Vec::new();
+        let mut version = current;
+        while version < self.latest {
+            let next = self
+                .deltas
+                .iter()
+                .filter(|d| d.from == version && d.to > version && d.to <= self.latest)
+                .max_by_key(|d| d.to)
+                .ok_or_else(|| {
+                    format!(
+                        "No delta chain from version {} to {} in list {}",
+                        version, self.latest, self.list
+                    )
+                })?;
+            plan.push(next);
+            version = next.to;
+        }
+        Ok(plan)
+    }
+}
+
+/// A parsed delta of a hash list
+#[derive(Debug, Clone)]
+pub struct HashListDelta {
+    pub list: String,
+    pub from: u64,
+    pub to: u64,
+    pub adds: Vec<(ImageHash, String)>,
+    pub removes: Vec<(ImageHash, String)>,
+    /// Exact bytes covered by the signature
+    signed: String,
+    /// Raw signature bytes (empty if unsigned)
+    signature: Vec<u8>,
+}
+
+impl HashListDelta {
+    /// Parse delta text (does not verify the signature)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (signed, signature) = match text.rfind("\nsignature ") {
+            Some(pos) => {
+                let sig_hex = text[pos + "\nsignature ".len()..].trim();
+                (text[..pos + 1].to_string(), decode_hex(sig_hex)?)
+            }
+            None => (text.to_string(), Vec::new()),
+        };
+
+        let mut lines = signed.lines();
+        if lines.next().map(str::trim) != Some(DELTA_HEADER) {
+            return Err("Not a hash list delta".to_string());
+        }
+
+        let (mut list, mut from, mut to) = (None, None, None);
+        let mut adds = Vec::new();
+        let mut removes = Vec::new();
+        for line in lines.filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(op @ ("+" | "-")), Some(hex), Some(path)) => {
+                    let entry = (ImageHash::from_hex(hex)?, path.to_string());
+                    if op == "+" {
+                        adds.push(entry);
+                    } else {
+                        removes.push(entry);
+                    }
+                }
+                _ => {
+                    let mut parts = line.split_whitespace();
+                    match parts.next() {
+                        Some("list") => list = parts.next().map(str::to_string),
+                        Some("from") => from = Some(parse_version(parts.next())?),
+                        Some("to") => to = Some(parse_version(parts.next())?),
+                        _ => return Err(format!("Unexpected delta line: {}", line)),
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            list: list.ok_or("Delta has no list name")?,
+            from: from.ok_or("Delta has no from version")?,
+            to: to.ok_or("Delta has no to version")?,
+            adds,
+            removes,
+            signed,
+            signature,
+        })
+    }
+
+    /// Exact bytes covered by the signature
+    pub fn signed_bytes(&self) -> &[u8] {
+        self.signed.as_bytes()
+    }
+
+    /// Raw signature bytes (empty if the delta is unsigned)
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Check the ed25519 signature against a publisher's public key
+    #[cfg(feature = "sync")]
+    pub fn verify(&self, public_key: &[u8; 32]) -> Result<(), String> {
+        use ed25519_dalek::{Signature, VerifyingKey};
+
+        let key = VerifyingKey::from_bytes(public_key).map_err(|e| e.to_string())?;
+        let sig: [u8; 64] = self
+            .signature
+            .as_slice()
+            .try_into()
+            .map_err(|_| format!("Delta {}->{} is not signed", self.from, self.to))?;
+        key.verify_strict(self.signed_bytes(), &Signature::from_bytes(&sig))
+            .map_err(|_| format!("Bad signature on delta {}->{}", self.from, self.to))
+    }
+
+    /// Check the ed25519 signature against a publisher's public key
+    #[cfg(not(feature = "sync"))]
+    pub fn verify(&self, _public_key: &[u8; 32]) -> Result<(), String> {
+        Err("Signature verification requires the `sync` feature".to_string())
+    }
+}
+
+/// Apply a chain of deltas to a copy of `tree`
+///
+/// Either every delta applies and the new tree and version are returned,
+/// or `tree` is left untouched.
+pub fn apply_deltas(
+    tree: &BkTree<PathBuf>,
+    list: &str,
+    version: u64,
+    deltas: &[HashListDelta],
+) -> Result<(BkTree<PathBuf>, u64), String> {
+    let mut updated = tree.clone();
+    let mut version = version;
+    for delta in deltas {
+        if delta.list != list {
+            return Err(format!(
+                "Delta is for list {}, expected {}",
+                delta.list, list
+            ));
+        }
+        if delta.from != version {
+            return Err(format!(
+                "Delta {}->{} does not apply to version {}",
+                delta.from, delta.to, version
+            ));
+        }
+        for (hash, path) in &delta.removes {
+            updated.remove(hash, |p| p.as_os_str() == path.as_str());
+        }
+        for (hash, path) in &delta.adds {
+            updated.add(hash.clone(), PathBuf::from(path));
+        }
+        version = delta.to;
+    }
+    Ok((updated, version))
+}
+
+/// Fetch and verify every delta needed to bring `version` up to date
+///
+/// `list` of `None` accepts whatever list the manifest names.
+#[cfg(feature = "sync")]
+pub fn pull_deltas(
+    base_url: &str,
+    list: Option<&str>,
+    version: u64,
+    public_key: &[u8; 32],
+) -> Result<(String, Vec<HashListDelta>), String> {
+    let base = base_url.trim_end_matches('/');
+    if !base.starts_with("https://") {
+        return Err(format!("Refusing to sync over non-HTTPS URL: {}", base_url));
+    }
+
+    let manifest = SyncManifest::parse(&fetch(&format!("{}/manifest", base))?)?;
+    if let Some(list) = list {
+        if manifest.list != list {
+            return Err(format!(
+                "Manifest is for list {}, expected {}",
+                manifest.list, list
+            ));
+        }
+    }
+
+    let mut deltas = Vec::new();
+    for delta_ref in manifest.plan(version)? {
+        let delta = HashListDelta::parse(&fetch(&format!("{}/{}", base, delta_ref.path))?)?;
+        delta.verify(public_key)?;
+        if delta.from != delta_ref.from || delta.to != delta_ref.to {
+            return Err(format!(
+                "Delta {} claims {}->{}, manifest says {}->{}",
+                delta_ref.path, delta.from, delta.to, delta_ref.from, delta_ref.to
+            ));
+        }
+        deltas.push(delta);
+    }
+    Ok((manifest.list, deltas))
+}
+
+/// Fetch and verify every delta needed to bring `version` up to date
+#[cfg(not(feature = "sync"))]
+pub fn pull_deltas(
+    _base_url: &str,
+    _list: Option<&str>,
+    _version: u64,
+    _public_key: &[u8; 32],
+) -> Result<(String, Vec<HashListDelta>), String> {
+    Err("Hash list sync requires the `sync` feature".to_string())
+}
+
+#[cfg(feature = "sync")]
+fn fetch(url: &str) -> Result<String, String> {
+    ureq::get(url)
+        .call()
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?
+        .into_string()
+        .map_err(|e| format!("Failed to read {}: {}", url, e))
+}
+
+/// Parse a 32-byte public key from hex
+pub fn parse_public_key(hex: &str) -> Result<[u8; 32], String> {
+    decode_hex(hex.trim())?
+        .try_into()
+        .map_err(|_| "Public key must be 32 bytes".to_string())
+}
+
+fn parse_version(s: Option<&str>) -> Result<u64, String> {
+    let s = s.ok_or("Missing version")?;
+    s.parse().map_err(|_| format!("Invalid version: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "phash-list v1\nlist archived\nlatest 3\n\
+                            delta 0 2 d0-2\ndelta 1 2 d1-2\ndelta 2 3 d2-3\n";
+
+    #[test]
+    fn test_manifest_plan() {
+        let manifest = SyncManifest::parse(MANIFEST).unwrap();
+        let paths = |v| {
+            manifest
+                .plan(v)
+                .unwrap()
+                .into_iter()
+                .map(|d| d.path.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(0), vec!["d0-2", "d2-3"]);
+        assert_eq!(paths(1), vec!["d1-2", "d2-3"]);
+        assert!(paths(3).is_empty());
+
+        let gap = SyncManifest::parse("phash-list v1\nlist x\nlatest 5\ndelta 0 2 a\n").unwrap();
+        assert!(gap.plan(0).is_err());
+    }
+
+    #[test]
+    fn test_apply_deltas_all_or_nothing() {
+        let d1 = HashListDelta::parse(
+            "phash-delta v1\nlist archived\nfrom 0\nto 1\n\
+             +\t0000000000000000\ta.jpg\n+\tffffffffffffffff\tb.jpg\nsignature 00ff\n",
+        )
+        .unwrap();
+        let d2 = HashListDelta::parse(
+            "phash-delta v1\nlist archived\nfrom 1\nto 2\n-\tffffffffffffffff\tb.jpg\n",
+        )
+        .unwrap();
+        assert_eq!(d1.signature(), &[0x00, 0xff]);
+        assert!(d1.signed_bytes().ends_with(b"b.jpg\n"));
+
+        let tree = BkTree::new();
+        let (updated, version) =
+            apply_deltas(&tree, "archived", 0, &[d1.clone(), d2.clone()]).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(updated.len(), 1);
+
+        // Out-of-order chain fails and leaves the original untouched
+        assert!(apply_deltas(&tree, "archived", 0, &[d2, d1]).is_err());
+        assert!(tree.is_empty());
+
+        // Non-ASCII hex is rejected, not sliced mid-character
+        assert!(HashListDelta::parse("phash-delta v1\n\nsignature 0é0\n").is_err());
+        assert!(parse_public_key(&"é".repeat(32)).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! Thread Pool Module
+//!
+//! Limits how many threads the batch functions use. By default they run on
+//! rayon's global pool, one thread per core; with a limit they run on a
+//! dedicated pool of that size, so hashing leaves cores free for other
+//! work in the same process (web workers, an async executor).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+fn global() -> &'static RwLock<Option<usize>> {
+    static THREADS: OnceLock<RwLock<Option<usize>>> = OnceLock::new();
+    THREADS.get_or_init(|| RwLock::new(None))
+}
+
+/// Process-wide thread limit for the batch functions; `None` uses all cores
+pub fn num_threads() -> Option<usize> {
+    *global().read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Set the process-wide thread limit used by `with_threads`
+///
+/// `None` goes back to rayon's global pool. Fails for a limit of zero.
+pub fn set_num_threads(threads: Option<usize>) -> Result<(), String> {
+    if threads == Some(0) {
+        return Err("num_threads must be at least 1".to_string());
+    }
+    *global().write().unwrap_or_else(|e| e.into_inner()) = threads;
+    Ok(())
+}
+
+/// Run `f`, with its parallel work on a pool of `threads` threads
+///
+/// `None` falls back to the process-wide limit (`set_num_threads`), and
+/// without one `f` runs on rayon's global pool. Pools are built on first
+/// use of each size and kept for later calls. Fails for a limit of zero or
+/// if the pool's threads can't be started.
+pub fn with_threads<R, F>(threads: Option<usize>, f: F) -> Result<R, String>
+where
+    R: Send,
+    F: FnOnce() -> R + Send,
+{
+    match threads.or_else(num_threads) {
+        None => Ok(f()),
+        Some(0) => Err("num_threads must be at least 1".to_string()),
+        Some(n) => Ok(pool(n)?.install(f)),
+    }
+}
+
+/// Run `task` in the background on the pool `with_threads(None, ..)`
+/// would use, or on rayon's global pool if that one can't be started
+///
+/// The pools have no panic handler, so a panic in `task` aborts the
+/// process; catch it inside `task` and report it through its result.
+pub(crate) fn spawn<F: FnOnce() + Send + 'static>(task: F) {
+    match num_threads().map(pool) {
+        Some(Ok(pool)) => pool.spawn(task),
+        _ => rayon::spawn(task),
+    }
+}
+
+/// Threads `spawn` runs tasks on
+pub(crate) fn spawn_threads() -> usize {
+    num_threads().unwrap_or_else(rayon::current_num_threads)
+}
+
+/// The shared pool with `threads` threads
+fn pool(threads: usize) -> Result<Arc<rayon::ThreadPool>, String> {
+    static POOLS: OnceLock<Mutex<HashMap<usize, Arc<rayon::ThreadPool>>>> = OnceLock::new();
+    let mut pools = POOLS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(pool) = pools.get(&threads) {
+        return Ok(pool.clone());
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("phash-worker-{}", i))
+        .build()
+        .map_err(|e| format!("Failed to start {} threads: {}", threads, e))?;
+    let pool = Arc::new(pool);
+    pools.insert(threads, pool.clone());
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_size() {
+        let threads = with_threads(Some(2), rayon::current_num_threads).unwrap();
+        assert_eq!(threads, 2);
+        assert!(with_threads(Some(0), || ()).is_err());
+        assert!(set_num_threads(Some(0)).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+//! Tiled Hash Module
+//!
+//! Hashes overlapping regions of an image at several scales, so an image
+//! that contains another one (a meme with a caption bar, a collage, a
+//! screenshot of a photo) can be matched region by region. Whole-image
+//! hashes stop matching as soon as anything is added around the picture.
+
+use crate::decode::open_image;
+use crate::error::HashError;
+use crate::hash::{HashAlgorithm, ImageHash};
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::path::Path;
+
+/// Region sizes as fractions of the image width and height
+pub const DEFAULT_TILE_SCALES: [f64; 4] = [1.0, 0.8, 0.6, 0.5];
+/// Longer side of the downscaled image regions are cut from
+const TILE_WORK_SIZE: u32 = 256;
+
+/// How an image is split into regions
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileOptions {
+    /// Region sizes as fractions of the image size, each in (0, 1]. Every
+    /// width scale is combined with every height scale, so a picture
+    /// stretched or cut along one axis still has a region of its shape.
+    pub scales: Vec<f64>,
+    /// Fraction by which neighbouring regions of one size overlap, in [0, 1)
+    pub overlap: f64,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            scales: DEFAULT_TILE_SCALES.to_vec(),
+            overlap: 0.5,
+        }
+    }
+}
+
+impl TileOptions {
+    fn validate(&self) -> Result<(), HashError> {
+        if self.scales.is_empty() || self.scales.iter().any(|&s| !(s > 0.0 && s <= 1.0)) {
+            return Err(HashError::InvalidInput(
+                "Tile scales must be in (0, 1]".to_string(),
+            ));
+        }
+        if !(0.0..1.0).contains(&self.overlap) {
+            return Err(HashError::InvalidInput(
+                "Tile overlap must be in [0, 1)".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A rectangle of an image in its original pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// One hashed region
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tile {
+    pub region: Region,
+    pub hash: ImageHash,
+}
+
+/// A region of one image matching a region of another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionMatch {
+    /// Region of the image `match_regions` was called on
+    pub region: Region,
+    /// Closest region of the other image
+    pub other: Region,
+    /// Hamming distance between the two
+    pub distance: u32,
+}
+
+/// Hashes of overlapping regions of one image
+#[derive(Debug, Clone)]
+pub struct TiledHash {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+}
+
+impl TiledHash {
+    /// Compute tiled hashes from an image file path
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        options: &TileOptions,
+    ) -> Result<Self, HashError> {
+        let img = open_image(path.as_ref())?;
+
+        Self::from_image(&img, algorithm, hash_size, options)
+    }
+
+    /// Compute tiled hashes from a loaded image
+    ///
+    /// Regions are cut from a copy scaled down to 256 pixels on its longer
+    /// side and reported in the original image's pixels. The whole image is
+    /// always the first tile.
+    pub fn from_image(
+        img: &DynamicImage,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        options: &TileOptions,
+    ) -> Result<Self, HashError> {
+        options.validate()?;
+        algorithm.check_hash_size(hash_size)?;
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return Err(HashError::InvalidInput("Image has no pixels".to_string()));
+        }
+        let factor = (TILE_WORK_SIZE as f64 / width.max(height) as f64).min(1.0);
+        let work = if factor < 1.0 {
+            let w = ((width as f64 * factor).round() as u32).max(1);
+            let h = ((height as f64 * factor).round() as u32).max(1);
+            img.resize_exact(w, h, FilterType::Triangle)
+        } else {
+            img.clone()
+        };
+        let (work_w, work_h) = work.dimensions();
+        let to_original =
+            |v: u32, work_len: u32, len: u32| (v as u64 * len as u64 / work_len as u64) as u32;
+
+        let mut tiles = Vec::new();
+        for (x, w) in spans(work_w, options) {
+            for (y, h) in spans(work_h, options) {
+                let x0 = to_original(x, work_w, width);
+                let y0 = to_original(y, work_h, height);
+                let region = Region {
+                    x: x0,
+                    y: y0,
+                    width: (to_original(x + w, work_w, width) - x0).max(1),
+                    height: (to_original(y + h, work_h, height) - y0).max(1),
+                };
+                let crop = work.crop_imm(x, y, w, h);
+                let hash = ImageHash::from_image(&crop, algorithm, hash_size)?;
+                tiles.push(Tile { region, hash });
+            }
+        }
+        // Largest regions first, so the whole image leads
+        tiles.sort_by_key(|t| std::cmp::Reverse(t.region.width as u64 * t.region.height as u64));
+
+        Ok(Self {
+            width,
+            height,
+            tiles,
+        })
+    }
+
+    /// Size of the hashed image
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Hashed regions, largest first
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    /// Regions of `self` whose closest region in `other` is within
+    /// `threshold` bits
+    ///
+    /// Each region of `self` is reported at most once, with its closest
+    /// counterpart, best matches first. Regions with a uniform hash (flat
+    /// caption bars, borders) match anything flat and are skipped. To find
+    /// out whether `self` contains `other`, look for matches whose `other`
+    /// is the whole of `other`.
+    pub fn match_regions(&self, other: &TiledHash, threshold: u32) -> Vec<RegionMatch> {
+        let candidates: Vec<&Tile> = other
+            .tiles
+            .iter()
+            .filter(|t| !t.hash.is_uniform())
+            .collect();
+        let mut matches: Vec<RegionMatch> = self
+            .tiles
+            .iter()
+            .filter(|t| !t.hash.is_uniform())
+            .filter_map(|tile| {
+                let closest = candidates
+                    .iter()
+                    .filter(|c| c.hash.bit_len() == tile.hash.bit_len())
+                    .min_by_key(|c| tile.hash.distance(&c.hash))?;
+                let distance = tile.hash.distance(&closest.hash);
+                (distance <= threshold).then_some(RegionMatch {
+                    region: tile.region,
+                    other: closest.region,
+                    distance,
+                })
+            })
+            .collect();
+        matches.sort_by_key(|m| m.distance);
+        matches
+    }
+}
+
+/// Offsets and lengths of the regions along one axis of `len` pixels
+///
+/// For each scale, regions step by `1 - overlap` of their length and the
+/// last one ends flush with the edge.
+fn spans(len: u32, options: &TileOptions) -> Vec<(u32, u32)> {
+    let mut spans = Vec::new();
+    for &scale in &options.scales {
+        let size = ((len as f64 * scale).round() as u32).clamp(1, len);
+        let step = ((size as f64 * (1.0 - options.overlap)).round() as u32).max(1);
+        let mut offset = 0;
+        loop {
+            let offset_clamped = offset.min(len - size);
+            if !spans.contains(&(offset_clamped, size)) {
+                spans.push((offset_clamped, size));
+            }
+            if offset_clamped + size >= len {
+                break;
+            }
+            offset += step;
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_finds_picture_under_caption_bar() {
+        let picture = RgbImage::from_fn(64, 48, |x, y| {
+            let v = ((x / 8 + y / 6) % 2) as u8 * 200 + y as u8;
+            Rgb([v, v / 2, 255 - v])
+        });
+        let meme = RgbImage::from_fn(64, 60, |x, y| {
+            if y < 12 {
+                Rgb([255, 255, 255])
+            } else {
+                *picture.get_pixel(x, y - 12)
+            }
+        });
+        let options = TileOptions::default();
+        let meme = DynamicImage::ImageRgb8(meme);
+        let a = TiledHash::from_image(&meme, HashAlgorithm::PHash, 8, &options).unwrap();
+        let b = TiledHash::from_image(
+            &DynamicImage::ImageRgb8(picture),
+            HashAlgorithm::PHash,
+            8,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(a.tiles()[0].region.height, 60);
+
+        let whole_b = b.tiles()[0].region;
+        let found = a.match_regions(&b, 4);
+        assert!(found.iter().any(|m| m.other == whole_b
+            && m.region
+                == Region {
+                    x: 0,
+                    y: 12,
+                    width: 64,
+                    height: 48
+                }));
+
+        let bad = TileOptions {
+            overlap: 1.0,
+            ..Default::default()
+        };
+        assert!(TiledHash::from_image(&meme, HashAlgorithm::PHash, 8, &bad).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Concurrent Union-Find Module
+//!
+//! Grouping links every pair of images found within the threshold. Rather
+//! than collecting each image's neighbours and merging them on one thread
+//! afterwards, the worker threads that find a pair link it straight away
+//! in a lock-free disjoint-set forest.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Disjoint sets of `0..n` that any number of threads can merge at once
+///
+/// A root is always the smallest member of its set: sets are linked under
+/// the smaller root, so every parent index is at most its child's and no
+/// cycle can form. Finds halve the paths they walk.
+pub(crate) struct UnionFind {
+    parent: Vec<AtomicUsize>,
+}
+
+impl UnionFind {
+    pub(crate) fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n).map(AtomicUsize::new).collect(),
+        }
+    }
+
+    /// Root of the set holding `i`
+    pub(crate) fn find(&self, mut i: usize) -> usize {
+        loop {
+            let parent = self.parent[i].load(Ordering::Acquire);
+            if parent == i {
+                return i;
+            }
+            let grandparent = self.parent[parent].load(Ordering::Acquire);
+            // Losing this race only skips a shortcut
+            let _ = self.parent[i].compare_exchange_weak(
+                parent,
+                grandparent,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+            i = grandparent;
+        }
+    }
+
+    /// Merge the sets holding `a` and `b`
+    pub(crate) fn union(&self, a: usize, b: usize) {
+        loop {
+            let (ra, rb) = (self.find(a), self.find(b));
+            if ra == rb {
+                return;
+            }
+            let (child, root) = if ra < rb { (rb, ra) } else { (ra, rb) };
+            // Fails if another thread linked `child` first; retry from the
+            // new roots
+            if self.parent[child]
+                .compare_exchange(child, root, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Every set, members ascending, in order of their smallest member
+    ///
+    /// Call once all unions are done.
+    pub(crate) fn components(&self) -> Vec<Vec<usize>> {
+        let mut slot = vec![usize::MAX; self.parent.len()];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        for i in 0..self.parent.len() {
+            // The root is the smallest member, so it was seen first
+            let root = self.find(i);
+            if root == i {
+                slot[i] = components.len();
+                components.push(Vec::new());
+            }
+            components[slot[root]].push(i);
+        }
+        components
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn test_components() {
+        let sets = UnionFind::new(6);
+        sets.union(4, 1);
+        sets.union(5, 3);
+        sets.union(3, 4);
+        assert_eq!(sets.find(5), 1);
+        assert_eq!(sets.components(), vec![vec![0], vec![1, 3, 4, 5], vec![2]]);
+    }
+
+    #[test]
+    fn test_concurrent_unions() {
+        // Link every i to i + 1 except across multiples of 100, from many
+        // threads in scrambled order
+        let n = 10_000;
+        let sets = UnionFind::new(n);
+        (0..n - 1)
+            .into_par_iter()
+            .map(|k| (k * 7919) % (n - 1))
+            .filter(|&i| (i + 1) % 100 != 0)
+            .for_each(|i| sets.union(i + 1, i));
+        let components = sets.components();
+        assert_eq!(components.len(), 100);
+        for (k, component) in components.iter().enumerate() {
+            assert_eq!(*component, (k * 100..(k + 1) * 100).collect::<Vec<_>>());
+        }
+    }
+}
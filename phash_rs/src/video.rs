@@ -0,0 +1,384 @@
+//! Video Hash Module
+//!
+//! Perceptual hashes for short clips: frames sampled across the clip are
+//! hashed like images and compared by their position in the clip, so
+//! re-encodes, rescales and slightly trimmed copies still match. Frames
+//! come from a `FrameProvider`; `FfmpegFrames` (feature `video`) pulls
+//! keyframes with the `ffmpeg` command-line tools.
+
+use crate::duplicate::{with_sidecars, DuplicateGroup};
+use crate::error::{HashError, HashFailures};
+use crate::hash::{HashAlgorithm, ImageHash};
+use crate::progress::{Progress, ProgressCounter};
+use crate::sidecar::is_sidecar;
+use crate::union_find::UnionFind;
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+#[cfg(feature = "video")]
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "video")]
+use std::process::Command;
+
+/// Frames sampled per clip unless the caller asks for another count
+pub const DEFAULT_VIDEO_FRAMES: usize = 10;
+/// Frames of the other clip within this fraction of its length are
+/// candidates for a match, absorbing trims and keyframe jitter
+const TEMPORAL_WINDOW: f64 = 0.1;
+
+/// Source of decoded video frames
+pub trait FrameProvider: Sync {
+    /// Decode up to `count` frames spread across the clip at `path`
+    ///
+    /// Each frame comes with its position in the clip as a fraction of the
+    /// duration (0.0 = start, 1.0 = end).
+    fn frames(&self, path: &Path, count: usize) -> Result<Vec<(f64, DynamicImage)>, HashError>;
+}
+
+/// Perceptual hash of a video clip: one image hash per sampled frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoHash {
+    /// (position in the clip, frame hash), ordered by position
+    frames: Vec<(f64, ImageHash)>,
+}
+
+impl VideoHash {
+    /// Build from frame hashes and their positions in the clip
+    pub fn from_frames(mut frames: Vec<(f64, ImageHash)>) -> Result<Self, HashError> {
+        if frames.is_empty() {
+            return Err(HashError::InvalidInput(
+                "A video hash needs at least one frame".to_string(),
+            ));
+        }
+        for frame in &mut frames {
+            frame.0 = frame.0.clamp(0.0, 1.0);
+        }
+        frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self { frames })
+    }
+
+    /// Hash `count` frames of the clip at `path` supplied by `provider`
+    pub fn from_path(
+        path: &Path,
+        provider: &dyn FrameProvider,
+        count: usize,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self, HashError> {
+        let frames = provider
+            .frames(path, count)?
+            .into_iter()
+            .map(|(pos, img)| Ok((pos, ImageHash::from_image(&img, algorithm, 8)?)))
+            .collect::<Result<Vec<_>, HashError>>()?;
+        if frames.is_empty() {
+            return Err(HashError::Decode(format!(
+                "No frames decoded from {}",
+                path.display()
+            )));
+        }
+        Self::from_frames(frames)
+    }
+
+    /// Parse from the format written by `to_hex`
+    pub fn from_hex(s: &str) -> Result<Self, HashError> {
+        let frames = s
+            .trim()
+            .split(',')
+            .map(|frame| {
+                let (pos, hex) = frame.split_once(':').ok_or_else(|| {
+                    HashError::InvalidInput(format!("Invalid video frame: {}", frame))
+                })?;
+                let pos = pos.parse::<f64>().map_err(|_| {
+                    HashError::InvalidInput(format!("Invalid frame position: {}", pos))
+                })?;
+                Ok((pos, ImageHash::from_hex(hex)?))
+            })
+            .collect::<Result<Vec<_>, HashError>>()?;
+        Self::from_frames(frames)
+    }
+
+    /// Comma-separated `position:hash` pairs
+    pub fn to_hex(&self) -> String {
+        self.frames
+            .iter()
+            .map(|(pos, hash)| format!("{:.4}:{}", pos, hash.to_hex()))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Frame hashes with their positions, ordered by position
+    pub fn frames(&self) -> &[(f64, ImageHash)] {
+        &self.frames
+    }
+
+    /// Mean Hamming distance between frames at matching positions
+    ///
+    /// Each frame is compared with the frames of the other clip within
+    /// `TEMPORAL_WINDOW` of its position (or the nearest one if there are
+    /// none) and its best match counts. Both directions are averaged, so
+    /// the distance is symmetric; 0.0 means every frame found an exact
+    /// match.
+    pub fn distance(&self, other: &VideoHash) -> f64 {
+        (directed_distance(&self.frames, &other.frames)
+            + directed_distance(&other.frames, &self.frames))
+            / 2.0
+    }
+}
+
+/// Mean best-match distance from each frame of `a` to nearby frames of `b`
+fn directed_distance(a: &[(f64, ImageHash)], b: &[(f64, ImageHash)]) -> f64 {
+    let total: u32 = a
+        .iter()
+        .map(|(pos, hash)| {
+            let nearby = b
+                .iter()
+                .filter(|(p, _)| (p - pos).abs() <= TEMPORAL_WINDOW)
+                .map(|(_, h)| hash.distance(h))
+                .min();
+            nearby.unwrap_or_else(|| {
+                let (_, nearest) = b
+                    .iter()
+                    .min_by(|x, y| (x.0 - pos).abs().total_cmp(&(y.0 - pos).abs()))
+                    .expect("video hashes have at least one frame");
+                hash.distance(nearest)
+            })
+        })
+        .sum();
+    total as f64 / a.len() as f64
+}
+
+/// Keyframes extracted with the `ffmpeg` and `ffprobe` command-line tools
+#[derive(Debug, Clone)]
+pub struct FfmpegFrames {
+    /// ffmpeg executable
+    pub ffmpeg: String,
+    /// ffprobe executable
+    pub ffprobe: String,
+}
+
+impl Default for FfmpegFrames {
+    fn default() -> Self {
+        Self {
+            ffmpeg: "ffmpeg".to_string(),
+            ffprobe: "ffprobe".to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "video")]
+impl FfmpegFrames {
+    /// Clip duration in seconds
+    fn duration(&self, path: &Path) -> Result<f64, HashError> {
+        let args = [
+            "-v".as_ref(),
+            "error".as_ref(),
+            "-show_entries".as_ref(),
+            "format=duration".as_ref(),
+            "-of".as_ref(),
+            "default=noprint_wrappers=1:nokey=1".as_ref(),
+            path.as_os_str(),
+        ];
+        let stdout = run(&self.ffprobe, &args)?;
+        let text = String::from_utf8_lossy(&stdout);
+        text.trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|d| d.is_finite() && *d > 0.0)
+            .ok_or_else(|| HashError::Decode(format!("Unknown duration: {}", text.trim())))
+    }
+
+    /// The keyframe at or before `seconds`, decoded through a PNG pipe
+    fn keyframe_at(&self, path: &Path, seconds: f64) -> Result<DynamicImage, HashError> {
+        let at = format!("{:.3}", seconds);
+        let args: [&OsStr; 16] = [
+            "-v".as_ref(),
+            "error".as_ref(),
+            "-skip_frame".as_ref(),
+            "nokey".as_ref(),
+            "-noaccurate_seek".as_ref(),
+            "-ss".as_ref(),
+            at.as_ref(),
+            "-i".as_ref(),
+            path.as_os_str(),
+            "-frames:v".as_ref(),
+            "1".as_ref(),
+            "-f".as_ref(),
+            "image2pipe".as_ref(),
+            "-c:v".as_ref(),
+            "png".as_ref(),
+            "-".as_ref(),
+        ];
+        let stdout = run(&self.ffmpeg, &args)?;
+        image::load_from_memory(&stdout).map_err(|e| HashError::Decode(e.to_string()))
+    }
+}
+
+#[cfg(feature = "video")]
+impl FrameProvider for FfmpegFrames {
+    fn frames(&self, path: &Path, count: usize) -> Result<Vec<(f64, DynamicImage)>, HashError> {
+        std::fs::metadata(path).map_err(|e| HashError::Io(e.to_string()))?;
+        let duration = self.duration(path)?;
+
+        // Sample the middle of `count` equal slices, skipping the very first
+        // and last frames, which are often black
+        (0..count)
+            .map(|i| {
+                let pos = (i as f64 + 0.5) / count as f64;
+                Ok((pos, self.keyframe_at(path, pos * duration)?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(feature = "video"))]
+impl FrameProvider for FfmpegFrames {
+    fn frames(&self, path: &Path, _count: usize) -> Result<Vec<(f64, DynamicImage)>, HashError> {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        Err(HashError::UnsupportedFormat(ext))
+    }
+}
+
+/// Run a tool and return its stdout, failing on a non-zero exit
+#[cfg(feature = "video")]
+fn run(program: &str, args: &[&OsStr]) -> Result<Vec<u8>, HashError> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| HashError::Decode(format!("failed to run {}: {}", program, e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(HashError::Decode(format!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            stderr.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Find groups of duplicate clips
+///
+/// Clips are hashed in parallel from `frames` sampled frames each and
+/// grouped transitively when their `VideoHash::distance` is at most
+/// `threshold`. The largest file of a group is its best copy. Clips that
+/// could not be hashed are returned alongside the groups; if `progress`
+/// cancels, this returns an error without grouping.
+pub fn find_video_duplicates(
+    paths: &[PathBuf],
+    provider: &dyn FrameProvider,
+    frames: usize,
+    algorithm: HashAlgorithm,
+    threshold: f64,
+    progress: Option<&dyn Progress>,
+) -> Result<(Vec<DuplicateGroup>, HashFailures), String> {
+    let paths: Vec<&PathBuf> = paths.iter().filter(|p| !is_sidecar(p)).collect();
+    let counter = ProgressCounter::new(progress, paths.len());
+    let results: Vec<(&PathBuf, Result<VideoHash, HashError>)> = paths
+        .par_iter()
+        .filter_map(|&path| {
+            if counter.cancelled() {
+                return None;
+            }
+            let hash = VideoHash::from_path(path, provider, frames, algorithm);
+            counter.tick(path);
+            Some((path, hash))
+        })
+        .collect();
+    if counter.cancelled() {
+        return Err("Cancelled".to_string());
+    }
+
+    let mut clips: Vec<(&PathBuf, VideoHash)> = Vec::new();
+    let mut failures = HashFailures::new();
+    for (path, result) in results {
+        match result {
+            Ok(hash) => clips.push((path, hash)),
+            Err(e) => failures.push((path.clone(), e)),
+        }
+    }
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // Video distances are not a metric on single hashes, so compare all
+    // pairs, linking each match as it is found
+    let sets = UnionFind::new(clips.len());
+    (0..clips.len()).into_par_iter().for_each(|i| {
+        for j in i + 1..clips.len() {
+            if clips[i].1.distance(&clips[j].1) <= threshold {
+                sets.union(i, j);
+            }
+        }
+    });
+
+    let groups = sets
+        .components()
+        .into_iter()
+        .map(|indices| {
+            let mut paths: Vec<PathBuf> = indices.iter().map(|&i| clips[i].0.clone()).collect();
+            paths.sort();
+            let best = indices
+                .iter()
+                .copied()
+                .max_by_key(|&i| std::fs::metadata(clips[i].0).map(|m| m.len()).unwrap_or(0))
+                .unwrap_or(indices[0]);
+            DuplicateGroup {
+                paths,
+                hash: clips[indices[0]].1.to_hex(),
+                best_path: clips[best].0.clone(),
+                sidecars: BTreeMap::new(),
+                content_type: None,
+                member_hashes: BTreeMap::new(),
+                member_dimensions: BTreeMap::new(),
+            }
+        })
+        .collect();
+
+    Ok((with_sidecars(groups), failures))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pos: f64, hex: &str) -> (f64, ImageHash) {
+        (pos, ImageHash::from_hex(hex).unwrap())
+    }
+
+    #[test]
+    fn test_distance_follows_position() {
+        let a = VideoHash::from_frames(vec![frame(0.25, "00"), frame(0.75, "ff")]).unwrap();
+        // Same content, sampled slightly later
+        let b = VideoHash::from_frames(vec![frame(0.3, "00"), frame(0.8, "ff")]).unwrap();
+        // Same frames in reverse order
+        let c = VideoHash::from_frames(vec![frame(0.25, "ff"), frame(0.75, "00")]).unwrap();
+
+        assert_eq!(a.distance(&b), 0.0);
+        assert_eq!(a.distance(&c), 8.0);
+        assert_eq!(a.distance(&c), c.distance(&a));
+        assert_eq!(VideoHash::from_hex(&a.to_hex()).unwrap(), a);
+    }
+
+    #[test]
+    fn test_video_failures_are_reported() {
+        struct NoFrames;
+        impl FrameProvider for NoFrames {
+            fn frames(&self, _: &Path, _: usize) -> Result<Vec<(f64, DynamicImage)>, HashError> {
+                Ok(Vec::new())
+            }
+        }
+
+        let paths = vec![
+            PathBuf::from("b.mp4"),
+            PathBuf::from("a.mp4"),
+            PathBuf::from("a.thm"),
+        ];
+        let (groups, failures) =
+            find_video_duplicates(&paths, &NoFrames, 4, HashAlgorithm::PHash, 5.0, None).unwrap();
+        assert!(groups.is_empty());
+        let failed: Vec<&str> = failures.iter().filter_map(|(p, _)| p.to_str()).collect();
+        assert_eq!(failed, vec!["a.mp4", "b.mp4"]);
+    }
+}
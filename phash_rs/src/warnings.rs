@@ -0,0 +1,138 @@
+//! Warnings Module
+//!
+//! Non-fatal conditions met while scanning or hashing (skipped entries,
+//! files that failed to hash, fallback decoders, hashes that carry little
+//! information). They are
+//! reported through `Progress::warning` so embedders can show them in their
+//! own UI instead of losing them on stderr.
+
+use crate::progress::Progress;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Category of a non-fatal condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WarningKind {
+    /// A directory entry could not be read and was skipped
+    SkippedFile,
+    /// A file could not be hashed and was left out of the results
+    HashFailed,
+    /// The preferred decoder failed and a later backend decoded the file
+    FallbackDecoder,
+    /// The hash is unlikely to discriminate (tiny or flat image)
+    LowConfidence,
+}
+
+impl WarningKind {
+    /// Stable lowercase name used by the Python bindings
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::SkippedFile => "skipped_file",
+            WarningKind::HashFailed => "hash_failed",
+            WarningKind::FallbackDecoder => "fallback_decoder",
+            WarningKind::LowConfidence => "low_confidence",
+        }
+    }
+}
+
+/// A non-fatal condition concerning one path
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Warning {
+    pub path: PathBuf,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl Warning {
+    /// Create a warning about `path`
+    pub fn new(kind: WarningKind, path: &Path, message: String) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            kind,
+            message,
+        }
+    }
+
+    /// Record the warning in the library's log
+    pub(crate) fn log(&self) {
+        tracing::warn!(
+            kind = self.kind.name(),
+            path = %self.path.display(),
+            "{}",
+            self.message
+        );
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}: {}",
+            self.kind.name(),
+            self.path.display(),
+            self.message
+        )
+    }
+}
+
+/// Collects the warnings of one operation
+#[derive(Default)]
+pub struct WarningCollector {
+    warnings: Mutex<Vec<Warning>>,
+}
+
+impl WarningCollector {
+    /// Create an empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collected warnings, ordered by path
+    pub fn into_sorted(self) -> Vec<Warning> {
+        let mut warnings = self
+            .warnings
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        warnings.sort();
+        warnings
+    }
+}
+
+impl Progress for WarningCollector {
+    fn file_done(&self, _done: usize, _total: usize, _path: &Path) {}
+
+    fn warning(&self, warning: &Warning) {
+        self.warnings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(warning.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Fanout;
+
+    #[test]
+    fn test_collector_through_fanout() {
+        let collector = WarningCollector::new();
+        let fanout = Fanout(vec![&collector]);
+        fanout.warning(&Warning::new(
+            WarningKind::LowConfidence,
+            Path::new("b.png"),
+            "flat".into(),
+        ));
+        fanout.warning(&Warning::new(
+            WarningKind::SkippedFile,
+            Path::new("a"),
+            "denied".into(),
+        ));
+
+        let warnings = collector.into_sorted();
+        assert_eq!(warnings[0].to_string(), "[skipped_file] a: denied");
+        assert_eq!(warnings[1].kind, WarningKind::LowConfidence);
+    }
+}
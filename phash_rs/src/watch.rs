@@ -0,0 +1,359 @@
+//! Watch Module
+//!
+//! Keeps an index of the images under a directory and checks files as they
+//! arrive (`watch` feature), e.g. to flag near-duplicate uploads. Events
+//! come from the OS through the notify crate; a file is hashed once it has
+//! been quiet for `WATCH_SETTLE`, so copies still being written are not
+//! read half-way.
+
+use crate::error::HashFailures;
+use crate::hash::HashAlgorithm;
+use crate::scan::ScanOptions;
+use crate::search::SimilarMatch;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+#[cfg(feature = "watch")]
+use crate::{
+    cache::hash_paths,
+    decode::{normalize_ext, open_image},
+    hash::ImageHash,
+    index::BkTree,
+    scan::{extension_set, scan_directory},
+    sidecar::is_sidecar,
+};
+#[cfg(feature = "watch")]
+use notify::event::{EventKind, ModifyKind, RenameMode};
+#[cfg(feature = "watch")]
+use notify::{RecursiveMode, Watcher};
+#[cfg(feature = "watch")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "watch")]
+use std::path::PathBuf;
+#[cfg(feature = "watch")]
+use std::sync::mpsc::{self, RecvTimeoutError};
+#[cfg(feature = "watch")]
+use std::time::Instant;
+
+/// How long a file must go without events before it is hashed
+pub const WATCH_SETTLE: Duration = Duration::from_millis(500);
+
+/// Watches a directory and reports new images matching indexed ones
+///
+/// Starting indexes every image `scan_directory` finds; afterwards each
+/// file created, written or moved into the directory is hashed, compared
+/// with the index and added to it. Deleted and moved-away files leave the
+/// index. Watching stops with `stop` or when the watcher is dropped.
+pub struct DuplicateWatcher {
+    #[cfg(feature = "watch")]
+    watcher: Option<notify::RecommendedWatcher>,
+    worker: Option<JoinHandle<()>>,
+    failures: Arc<Mutex<HashFailures>>,
+}
+
+impl DuplicateWatcher {
+    /// Index `root` and watch it for new images
+    ///
+    /// `on_match(path, matches)` runs on the watcher's thread for every
+    /// new file with indexed images within `threshold`, matches closest
+    /// first. Files that fail to hash are kept in `failures`; a file that
+    /// hashes on a later event is removed from them again.
+    #[cfg(feature = "watch")]
+    pub fn start<P, F>(
+        root: P,
+        options: &ScanOptions,
+        algorithm: HashAlgorithm,
+        hash_size: usize,
+        threshold: u32,
+        on_match: F,
+    ) -> Result<Self, String>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Path, &[SimilarMatch]) + Send + 'static,
+    {
+        let root = root.as_ref();
+        algorithm
+            .check_hash_size(hash_size)
+            .map_err(|e| e.to_string())?;
+
+        // Watch before scanning, so files arriving during the scan are
+        // queued rather than missed
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|e| e.to_string())?;
+        let mode = if options.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher.watch(root, mode).map_err(|e| e.to_string())?;
+
+        let mut index = WatchIndex {
+            algorithm,
+            hash_size,
+            threshold,
+            extensions: extension_set(options),
+            exclude: options.exclude.iter().map(|p| root.join(p)).collect(),
+            tree: BkTree::new(),
+            hashes: HashMap::new(),
+            failures: Arc::new(Mutex::new(HashFailures::new())),
+        };
+        let paths = scan_directory(root, options)?;
+        for (path, result) in hash_paths(&paths, algorithm, hash_size, None, None) {
+            match result {
+                Ok(cached) => index.insert(path, cached.hash),
+                Err(e) => index.fail(path, e),
+            }
+        }
+
+        let failures = Arc::clone(&index.failures);
+        let worker = std::thread::Builder::new()
+            .name("phash-watch".to_string())
+            .spawn(move || index.run(rx, on_match))
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            watcher: Some(watcher),
+            worker: Some(worker),
+            failures,
+        })
+    }
+
+    /// Index `root` and watch it for new images
+    #[cfg(not(feature = "watch"))]
+    pub fn start<P, F>(
+        _root: P,
+        _options: &ScanOptions,
+        _algorithm: HashAlgorithm,
+        _hash_size: usize,
+        _threshold: u32,
+        _on_match: F,
+    ) -> Result<Self, String>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Path, &[SimilarMatch]) + Send + 'static,
+    {
+        Err("Watching directories requires the `watch` feature".to_string())
+    }
+
+    /// Files that could not be hashed, sorted by path
+    pub fn failures(&self) -> HashFailures {
+        let mut failures = self
+            .failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        failures.sort_by(|a, b| a.0.cmp(&b.0));
+        failures
+    }
+
+    /// Stop watching and wait for the callback in progress, if any
+    ///
+    /// Files still settling are not checked.
+    pub fn stop(&mut self) {
+        #[cfg(feature = "watch")]
+        drop(self.watcher.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+
+    /// Whether the watcher is still running
+    pub fn is_running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+}
+
+impl Drop for DuplicateWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The watcher thread's state
+#[cfg(feature = "watch")]
+struct WatchIndex {
+    algorithm: HashAlgorithm,
+    hash_size: usize,
+    threshold: u32,
+    extensions: HashSet<String>,
+    exclude: Vec<PathBuf>,
+    tree: BkTree<PathBuf>,
+    /// Current hash of every indexed path, to replace it when rewritten
+    hashes: HashMap<PathBuf, ImageHash>,
+    failures: Arc<Mutex<HashFailures>>,
+}
+
+#[cfg(feature = "watch")]
+impl WatchIndex {
+    /// Handle events until the watcher is dropped
+    fn run<F>(mut self, rx: mpsc::Receiver<notify::Result<notify::Event>>, mut on_match: F)
+    where
+        F: FnMut(&Path, &[SimilarMatch]),
+    {
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        loop {
+            let wait = pending
+                .values()
+                .min()
+                .map_or(Duration::from_secs(3600), |&t| {
+                    (t + WATCH_SETTLE).saturating_duration_since(Instant::now())
+                });
+            match rx.recv_timeout(wait) {
+                // Errors are transient (e.g. a directory removed mid-walk)
+                Ok(Err(_)) | Err(RecvTimeoutError::Timeout) => {}
+                Ok(Ok(event)) => self.note(event, &mut pending),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            let now = Instant::now();
+            let mut settled: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, &t)| now.duration_since(t) >= WATCH_SETTLE)
+                .map(|(path, _)| path.clone())
+                .collect();
+            settled.sort();
+            for path in settled {
+                pending.remove(&path);
+                self.check(&path, &mut on_match);
+            }
+        }
+    }
+
+    /// Queue or forget the paths of `event`
+    fn note(&mut self, event: notify::Event, pending: &mut HashMap<PathBuf, Instant>) {
+        let now = Instant::now();
+        match event.kind {
+            EventKind::Create(_)
+            | EventKind::Modify(ModifyKind::Data(_))
+            | EventKind::Modify(ModifyKind::Any)
+            | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                for path in event.paths {
+                    pending.insert(path, now);
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let [from, to] = &event.paths[..] {
+                    pending.remove(from);
+                    self.remove(from);
+                    pending.insert(to.clone(), now);
+                }
+            }
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                for path in &event.paths {
+                    pending.remove(path);
+                    self.remove(path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Hash a settled file, report its matches and index it
+    fn check<F>(&mut self, path: &Path, on_match: &mut F)
+    where
+        F: FnMut(&Path, &[SimilarMatch]),
+    {
+        let wanted = path.is_file()
+            && path.extension().is_some_and(|e| {
+                self.extensions
+                    .contains(&normalize_ext(&e.to_string_lossy()))
+            })
+            && !self.exclude.iter().any(|x| path.starts_with(x));
+        if !wanted || is_sidecar(path) {
+            return;
+        }
+
+        let hash = match open_image(path)
+            .and_then(|img| ImageHash::from_image(&img, self.algorithm, self.hash_size))
+        {
+            Ok(hash) => hash,
+            Err(e) => return self.fail(path.to_path_buf(), e),
+        };
+        if self.hashes.get(path) == Some(&hash) {
+            return;
+        }
+
+        self.remove(path);
+        let mut matches: Vec<SimilarMatch> = self
+            .tree
+            .query(&hash, self.threshold)
+            .into_iter()
+            .map(|(path, distance)| SimilarMatch {
+                path: path.clone(),
+                distance,
+            })
+            .collect();
+        matches.sort_unstable_by(|a, b| (a.distance, &a.path).cmp(&(b.distance, &b.path)));
+        if !matches.is_empty() {
+            on_match(path, &matches);
+        }
+        self.insert(path.to_path_buf(), hash);
+    }
+
+    fn insert(&mut self, path: PathBuf, hash: ImageHash) {
+        self.failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(p, _)| *p != path);
+        self.tree.add(hash.clone(), path.clone());
+        self.hashes.insert(path, hash);
+    }
+
+    fn fail(&mut self, path: PathBuf, error: crate::error::HashError) {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        failures.retain(|(p, _)| *p != path);
+        failures.push((path, error));
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(hash) = self.hashes.remove(path) {
+            self.tree.remove(&hash, |p| p == path);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "watch"))]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageBuffer, Luma};
+    use std::fs;
+    use std::sync::mpsc;
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([(x * 160 / width + y * 90 / height) as u8])
+        }))
+    }
+
+    #[test]
+    fn test_reports_new_copies() {
+        let root = std::env::temp_dir().join(format!("phash_rs_watch_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        gradient(64, 48).save(root.join("a.png")).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = DuplicateWatcher::start(
+            &root,
+            &ScanOptions::default(),
+            HashAlgorithm::PHash,
+            8,
+            10,
+            move |path: &Path, matches: &[SimilarMatch]| {
+                let _ = tx.send((path.to_path_buf(), matches.to_vec()));
+            },
+        )
+        .unwrap();
+
+        fs::copy(root.join("a.png"), root.join("b.png")).unwrap();
+        let (path, matches) = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(path.ends_with("b.png"));
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].path.ends_with("a.png"));
+        assert_eq!(matches[0].distance, 0);
+
+        watcher.stop();
+        assert!(!watcher.is_running());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -24,6 +24,9 @@ from src.phash import (
 from src.logger import logger
 from src.undo_manager import undo_manager
 
+# Default quarantine folder for moved duplicates, created inside the source
+DUPLICATES_DIRNAME = "Duplicates"
+
 
 @dataclass
 class DuplicateReport:
@@ -37,6 +40,23 @@ class DuplicateReport:
     groups: List[DuplicateGroup]
 
 
+def _set_aside_filter(source_path: Path) -> Callable[[Path], bool]:
+    """Predicate for files an earlier cleanup already set aside.
+
+    Covers the default duplicates folder and every file quarantined in an
+    undo journal that has not been reverted, so rescans neither rediscover
+    them as duplicates nor count them as library images.
+    """
+    quarantined = undo_manager.quarantined_paths()
+    quarantine_dir = (source_path / DUPLICATES_DIRNAME).absolute()
+
+    def is_set_aside(file_path: Path) -> bool:
+        absolute = file_path.absolute()
+        return str(absolute) in quarantined or quarantine_dir in absolute.parents
+
+    return is_set_aside
+
+
 def scan_for_duplicates_with_progress(
     source_dir: str,
     threshold: int = THRESHOLD_SIMILAR,
@@ -63,9 +83,10 @@ def scan_for_duplicates_with_progress(
     collect_started = time.perf_counter()
     image_paths: List[str] = []
     files_seen = 0
+    is_set_aside = _set_aside_filter(source_path)
 
     for file_path in source_path.rglob("*"):
-        if not file_path.is_file():
+        if not file_path.is_file() or is_set_aside(file_path):
             continue
 
         files_seen += 1
@@ -214,7 +235,9 @@ def scan_for_duplicates(
     logger.info(f"Scanning for duplicates using {get_backend()}")
     logger.info(f"Threshold: {threshold}")
     
-    duplicates = find_duplicates(source_dir, threshold)
+    duplicates = find_duplicates(
+        source_dir, threshold, exclude=_set_aside_filter(Path(source_dir))
+    )
     
     if duplicates:
         logger.info(f"Found {len(duplicates)} duplicate groups")
@@ -258,18 +281,24 @@ def handle_duplicates(
     groups = scan_for_duplicates(source_dir, threshold)
     
     # Calculate statistics
-    total_scanned = sum(len(list(source_path.rglob(f'*{ext}'))) 
-                       for ext in ['.jpg', '.jpeg', '.png', '.heic', '.bmp', '.tiff', '.gif'])
+    is_set_aside = _set_aside_filter(source_path)
+    total_scanned = sum(
+        1
+        for ext in ['.jpg', '.jpeg', '.png', '.heic', '.bmp', '.tiff', '.gif']
+        for path in source_path.rglob(f'*{ext}')
+        if not is_set_aside(path)
+    )
     total_duplicates = sum(len(g.paths) - 1 for g in groups)  # -1 for keeping best
     
-    # Calculate recoverable space
+    # Calculate recoverable space (sidecars go with their image)
     space_recoverable = 0
     for group in groups:
-        for path in group.duplicates:  # Excludes best
-            try:
-                space_recoverable += Path(path).stat().st_size
-            except OSError:
-                pass
+        for dup in group.duplicates:  # Excludes best
+            for path in [dup, *group.sidecars_of(dup)]:
+                try:
+                    space_recoverable += Path(path).stat().st_size
+                except OSError:
+                    pass
     
     report = DuplicateReport(
         total_scanned=total_scanned,
@@ -287,7 +316,7 @@ def handle_duplicates(
     # Handle duplicates
     if action in ("move", "copy"):
         if not duplicates_dir:
-            duplicates_dir = str(source_path / "Duplicates")
+            duplicates_dir = str(source_path / DUPLICATES_DIRNAME)
         dup_path = Path(duplicates_dir)
         dup_path.mkdir(parents=True, exist_ok=True)
         
@@ -306,12 +335,23 @@ def handle_duplicates(
                     if action == "move":
                         shutil.move(dup, str(target))
                         logger.info(f"Moved duplicate: {dup} -> {target}")
-                        undo_manager.log_action('move', dup, target)
+                        undo_manager.log_action('quarantine', dup, target)
                     else:  # copy
                         shutil.copy2(dup, str(target))
                         logger.info(f"Copied duplicate: {dup} -> {target}")
                         undo_manager.log_action('copy', dup, target)
                     report.duplicates_moved += 1
+
+                    # Keep sidecars next to their image, renamed to match it
+                    for sidecar in group.sidecars_of(dup):
+                        sidecar_target = dup_path / _sidecar_name(sidecar, dup, target)
+                        if action == "move":
+                            shutil.move(sidecar, str(sidecar_target))
+                            undo_manager.log_action('quarantine', sidecar, sidecar_target)
+                        else:
+                            shutil.copy2(sidecar, str(sidecar_target))
+                            undo_manager.log_action('copy', sidecar, sidecar_target)
+                        logger.info(f"{action.capitalize()}d sidecar: {sidecar} -> {sidecar_target}")
                 except Exception as e:
                     logger.error(f"Error {action}ing {dup}: {e}")
                     report.errors += 1
@@ -323,6 +363,9 @@ def handle_duplicates(
                     Path(dup).unlink()
                     logger.info(f"Deleted duplicate: {dup}")
                     report.duplicates_moved += 1
+                    for sidecar in group.sidecars_of(dup):
+                        Path(sidecar).unlink(missing_ok=True)
+                        logger.info(f"Deleted sidecar: {sidecar}")
                 except Exception as e:
                     logger.error(f"Error deleting {dup}: {e}")
                     report.errors += 1
@@ -331,6 +374,16 @@ def handle_duplicates(
     return report
 
 
+def _sidecar_name(sidecar: str, primary: str, target: Path) -> str:
+    """Name for a sidecar following its primary's (possibly renamed) target."""
+    name = Path(sidecar).name
+    primary_path = Path(primary)
+    for prefix, renamed in ((primary_path.name, target.name), (primary_path.stem, target.stem)):
+        if name.lower().startswith(prefix.lower() + "."):
+            return renamed + name[len(prefix):]
+    return name
+
+
 def print_duplicate_report(report: DuplicateReport):
     """Print a formatted duplicate detection report."""
     print("\n" + "=" * 50)
@@ -17,8 +17,8 @@ Usage:
 """
 
 from pathlib import Path
-from typing import List, Dict, Optional
-from dataclasses import dataclass
+from typing import Callable, List, Dict, Optional
+from dataclasses import dataclass, field
 from src.logger import logger
 from src import constants
 
@@ -45,6 +45,7 @@ class DuplicateGroup:
     paths: List[str]
     hash: str
     best: str  # Path to highest resolution image
+    sidecars: Dict[str, List[str]] = field(default_factory=dict)  # XMP/AAE/THM per path
     
     def __len__(self) -> int:
         return len(self.paths)
@@ -54,6 +55,10 @@ class DuplicateGroup:
         """Return paths of duplicates (excluding the best one)."""
         return [p for p in self.paths if p != self.best]
 
+    def sidecars_of(self, path: str) -> List[str]:
+        """Return sidecar files that must move/delete together with path."""
+        return self.sidecars.get(path, [])
+
 
 def compute_hash(
     path: str,
@@ -128,7 +133,8 @@ def are_similar(
 def find_duplicates(
     source: str,
     threshold: int = THRESHOLD_SIMILAR,
-    extensions: Optional[set] = None
+    extensions: Optional[set] = None,
+    exclude: Optional[Callable[[Path], bool]] = None
 ) -> List[DuplicateGroup]:
     """
     Find duplicate images in a directory using pHash.
@@ -137,6 +143,7 @@ def find_duplicates(
         source: Directory path to scan
         threshold: Maximum Hamming distance for duplicates
         extensions: File extensions to include (default: IMAGE_EXTENSIONS)
+        exclude: Predicate for files to skip (e.g. quarantined duplicates)
     
     Returns:
         List of DuplicateGroup objects for each set of duplicates
@@ -149,6 +156,8 @@ def find_duplicates(
     image_paths = []
     
     for file_path in source_path.rglob('*'):
+        if exclude is not None and exclude(file_path):
+            continue
         if file_path.is_file() and file_path.suffix.lower() in extensions:
             image_paths.append(str(file_path.absolute()))
     
@@ -185,7 +194,8 @@ def find_duplicates_from_paths(
                 DuplicateGroup(
                     paths=g["paths"],
                     hash=g["hash"],
-                    best=g["best"]
+                    best=g["best"],
+                    sidecars=g.get("sidecars", {})
                 )
                 for g in raw_groups
             ]
@@ -25,7 +25,8 @@ class UndoManager:
         """Log a file operation.
         
         Args:
-            action_type (str): 'move' or 'copy'
+            action_type (str): 'move', 'copy' or 'quarantine' (a move into
+                a duplicates/trash area that later scans should ignore)
             src (str): Source path
             dst (str): Destination path
         """
@@ -116,7 +117,7 @@ class UndoManager:
             dst = entry.get("dst") # This was the destination
 
             try:
-                if action == "move":
+                if action in ("move", "quarantine"):
                     # Undo move: Move dst back to src
                     if os.path.exists(dst):
                         # Ensure src directory exists
@@ -165,4 +166,23 @@ class UndoManager:
             
         return True
 
+    def quarantined_paths(self):
+        """Return current locations of files set aside by cleanup actions.
+
+        Collects the destinations of 'quarantine' actions from journals that
+        have not been reverted, so scans can skip files that were already
+        handled instead of reporting them again.
+        """
+        paths = set()
+        for p in JOURNAL_DIR.glob("journal_*.json"):
+            try:
+                with open(p, 'r') as f:
+                    actions = json.load(f)
+            except Exception:
+                continue
+            for entry in actions:
+                if entry.get("action") == "quarantine" and entry.get("dst"):
+                    paths.add(str(Path(entry["dst"]).absolute()))
+        return paths
+
 undo_manager = UndoManager()
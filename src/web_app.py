@@ -827,7 +827,7 @@ def api_delete_selected():
                     target = _trash_target_for(file_path)
                     target.parent.mkdir(parents=True, exist_ok=True)
                     file_path.rename(target)
-                    undo_manager.log_action("move", str(file_path), str(target))
+                    undo_manager.log_action("quarantine", str(file_path), str(target))
                 else:
                     file_path.unlink()
 